@@ -0,0 +1,298 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use super::AITask;
+
+/// AI任务输出中建议执行的动作字段，格式 {"action": "refund", "params": {...}, "reason": "..."}
+pub const SUGGESTED_ACTION_KEY: &str = "suggested_action";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ActionStatus {
+    /// 敏感动作等待客服/主管确认，仅展示不执行
+    PendingApproval,
+    Executed,
+    Rejected,
+    Failed,
+}
+
+/// AI建议执行的动作及其确认、执行记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestedAction {
+    pub id: String,
+    pub action: String,
+    pub customer_id: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+    pub reason: Option<String>,
+    /// 产生建议的AI任务
+    pub source_task_id: Option<String>,
+    pub requires_approval: bool,
+    pub status: ActionStatus,
+    pub created_at: DateTime<Utc>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+impl SuggestedAction {
+    pub fn new(action: &str, customer_id: &str, params: serde_json::Value) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            action: action.to_string(),
+            customer_id: customer_id.to_string(),
+            params,
+            reason: None,
+            source_task_id: None,
+            requires_approval: false,
+            status: ActionStatus::PendingApproval,
+            created_at: Utc::now(),
+            decided_by: None,
+            decided_at: None,
+            result: None,
+            error: None,
+        }
+    }
+
+    /// 从任务输出中取出建议动作，没有或格式不对时返回 None
+    pub fn from_task_output(task: &AITask, output: &serde_json::Value) -> Option<Self> {
+        let suggestion = output.get(SUGGESTED_ACTION_KEY)?;
+        let action = suggestion.get("action")?.as_str()?.trim();
+        if action.is_empty() {
+            return None;
+        }
+        let mut suggested = Self::new(action, &task.user_id, suggestion.get("params").cloned().unwrap_or_default());
+        suggested.reason = suggestion.get("reason").and_then(|r| r.as_str()).map(str::to_string);
+        suggested.source_task_id = Some(task.id.clone());
+        // 模型自己标记需要确认的也走确认流程
+        suggested.requires_approval = suggestion
+            .get("requires_approval")
+            .and_then(|r| r.as_bool())
+            .unwrap_or(false);
+        Some(suggested)
+    }
+}
+
+/// 真正执行动作（如调用订单系统退款）的接口
+#[async_trait::async_trait]
+pub trait ActionExecutor: Send + Sync {
+    async fn execute(&self, action: &SuggestedAction) -> Result<serde_json::Value>;
+}
+
+/// 未接入业务系统时的执行器，只记录日志
+pub struct LoggingActionExecutor;
+
+#[async_trait::async_trait]
+impl ActionExecutor for LoggingActionExecutor {
+    async fn execute(&self, action: &SuggestedAction) -> Result<serde_json::Value> {
+        tracing::info!(
+            "🤖 执行AI建议动作: id={}, action={}, customer={}, params={}",
+            action.id,
+            action.action,
+            action.customer_id,
+            action.params
+        );
+        Ok(serde_json::json!({ "dispatched": true }))
+    }
+}
+
+/// 敏感动作的人工确认闸门：敏感建议只保存待确认，确认后才交给执行器
+pub struct ActionGate {
+    actions: RwLock<HashMap<String, SuggestedAction>>,
+    executor: Arc<dyn ActionExecutor>,
+}
+
+impl Default for ActionGate {
+    fn default() -> Self {
+        Self::new(Arc::new(LoggingActionExecutor))
+    }
+}
+
+impl ActionGate {
+    pub fn new(executor: Arc<dyn ActionExecutor>) -> Self {
+        Self {
+            actions: RwLock::new(HashMap::new()),
+            executor,
+        }
+    }
+
+    /// 登记AI建议：只有 auto_execute_actions 白名单内且未标记 requires_approval 的动作直接执行，
+    /// 其余（包括模型自造的未知动作）等待确认
+    pub async fn suggest(&self, mut action: SuggestedAction, auto_execute_actions: &[String]) -> SuggestedAction {
+        action.requires_approval |= !auto_execute_actions
+            .iter()
+            .any(|name| name.trim().eq_ignore_ascii_case(&action.action));
+        action.status = ActionStatus::PendingApproval;
+        if action.requires_approval {
+            tracing::info!(
+                "🤖 AI建议敏感动作，等待人工确认: id={}, action={}, customer={}",
+                action.id,
+                action.action,
+                action.customer_id
+            );
+        } else {
+            self.execute(&mut action).await;
+        }
+        self.actions.write().await.insert(action.id.clone(), action.clone());
+        action
+    }
+
+    /// 客服/主管确认后执行，只能确认待确认的动作
+    pub async fn approve(&self, id: &str, approver: &str) -> Result<SuggestedAction> {
+        let mut action = self.take_pending(id).await?;
+        action.decided_by = Some(approver.to_string());
+        action.decided_at = Some(Utc::now());
+        self.execute(&mut action).await;
+        tracing::info!("🤖 AI建议动作已确认: id={}, 确认人={}, 状态={:?}", id, approver, action.status);
+        self.actions.write().await.insert(action.id.clone(), action.clone());
+        Ok(action)
+    }
+
+    pub async fn reject(&self, id: &str, approver: &str, reason: Option<String>) -> Result<SuggestedAction> {
+        let mut action = self.take_pending(id).await?;
+        action.status = ActionStatus::Rejected;
+        action.decided_by = Some(approver.to_string());
+        action.decided_at = Some(Utc::now());
+        action.error = reason;
+        tracing::info!("🤖 AI建议动作已驳回: id={}, 操作人={}", id, approver);
+        self.actions.write().await.insert(action.id.clone(), action.clone());
+        Ok(action)
+    }
+
+    /// 待确认的动作，可按客户过滤，最早的在前
+    pub async fn pending(&self, customer_id: Option<&str>) -> Vec<SuggestedAction> {
+        let mut pending: Vec<SuggestedAction> = self
+            .actions
+            .read()
+            .await
+            .values()
+            .filter(|action| action.status == ActionStatus::PendingApproval)
+            .filter(|action| customer_id.is_none_or(|id| action.customer_id == id))
+            .cloned()
+            .collect();
+        pending.sort_by_key(|action| action.created_at);
+        pending
+    }
+
+    // 先在锁内改为非待确认状态，避免并发确认重复执行
+    async fn take_pending(&self, id: &str) -> Result<SuggestedAction> {
+        let mut actions = self.actions.write().await;
+        let action = actions
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("AI建议动作不存在: {}", id))?;
+        if action.status != ActionStatus::PendingApproval {
+            return Err(anyhow::anyhow!("AI建议动作已处理: {} ({:?})", id, action.status));
+        }
+        let pending = action.clone();
+        action.status = ActionStatus::Executed;
+        Ok(pending)
+    }
+
+    async fn execute(&self, action: &mut SuggestedAction) {
+        match self.executor.execute(action).await {
+            Ok(result) => {
+                action.status = ActionStatus::Executed;
+                action.result = Some(result);
+            }
+            Err(e) => {
+                tracing::error!("🤖 AI建议动作执行失败: id={}, 错误={}", action.id, e);
+                action.status = ActionStatus::Failed;
+                action.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::AITaskType;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingExecutor {
+        executed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ActionExecutor for RecordingExecutor {
+        async fn execute(&self, action: &SuggestedAction) -> Result<serde_json::Value> {
+            self.executed.lock().unwrap().push(action.action.clone());
+            Ok(serde_json::json!({ "ok": true }))
+        }
+    }
+
+    fn refund_suggestion() -> SuggestedAction {
+        let task = AITask::new(
+            AITaskType::AutoReply,
+            "kehu_1".to_string(),
+            "msg_1".to_string(),
+            serde_json::json!({ "text": "我要退款" }),
+            5,
+        );
+        let output = serde_json::json!({
+            "reply": "已为您提交退款申请",
+            "suggested_action": { "action": "refund", "params": { "order_id": "A1", "amount": 99 }, "reason": "客户要求退款" }
+        });
+        SuggestedAction::from_task_output(&task, &output).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_action_executed_only_after_approval() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let gate = ActionGate::new(executor.clone());
+        let auto_execute = vec!["send_coupon_info".to_string()];
+
+        // 未确认的敏感建议只展示，不执行
+        let suggested = gate.suggest(refund_suggestion(), &auto_execute).await;
+        assert!(suggested.requires_approval);
+        assert_eq!(suggested.status, ActionStatus::PendingApproval);
+        assert_eq!(suggested.params["order_id"], "A1");
+        assert!(executor.executed.lock().unwrap().is_empty());
+        assert_eq!(gate.pending(Some("kehu_1")).await.len(), 1);
+        assert!(gate.pending(Some("kehu_2")).await.is_empty());
+
+        // 确认后执行，且不能重复确认
+        let approved = gate.approve(&suggested.id, "kefu_1").await.unwrap();
+        assert_eq!(approved.status, ActionStatus::Executed);
+        assert_eq!(approved.decided_by.as_deref(), Some("kefu_1"));
+        assert_eq!(*executor.executed.lock().unwrap(), ["refund"]);
+        assert!(gate.approve(&suggested.id, "kefu_1").await.is_err());
+        assert!(gate.pending(None).await.is_empty());
+
+        // 驳回的建议不会执行
+        let rejected = gate.suggest(refund_suggestion(), &auto_execute).await;
+        gate.reject(&rejected.id, "kefu_1", Some("订单已发货".to_string())).await.unwrap();
+        assert!(gate.approve(&rejected.id, "kefu_1").await.is_err());
+        assert_eq!(executor.executed.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_only_allowlisted_action_executed_directly() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let gate = ActionGate::new(executor.clone());
+        let auto_execute = vec!["send_coupon_info".to_string()];
+
+        let mut suggestion = refund_suggestion();
+        suggestion.action = "Send_Coupon_Info".to_string();
+        let executed = gate.suggest(suggestion, &auto_execute).await;
+        assert!(!executed.requires_approval);
+        assert_eq!(executed.status, ActionStatus::Executed);
+        assert_eq!(*executor.executed.lock().unwrap(), ["Send_Coupon_Info"]);
+
+        // 大小写变体、白名单外的动作和空白名单都要确认
+        for (action, allowlist) in [("REFUND", &auto_execute), ("delete_account", &auto_execute), ("send_coupon_info", &vec![])] {
+            let mut suggestion = refund_suggestion();
+            suggestion.action = action.to_string();
+            let pending = gate.suggest(suggestion, allowlist).await;
+            assert!(pending.requires_approval, "{}", action);
+            assert_eq!(pending.status, ActionStatus::PendingApproval);
+        }
+        assert_eq!(executor.executed.lock().unwrap().len(), 1);
+    }
+}
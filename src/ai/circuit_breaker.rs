@@ -0,0 +1,357 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::config::AIConfig;
+
+/// 单次重试等待的上限
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// 连续失败达到该次数后熔断，0 表示不熔断
+    pub failure_threshold: u32,
+    /// 熔断后多久放行一次探测请求
+    pub cooldown_seconds: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_seconds: 30,
+        }
+    }
+}
+
+/// 网络错误和超时的重试次数与退避间隔
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// 首次请求之外最多重试的次数，0 表示不重试
+    pub max_retries: u32,
+    /// 首次重试前的基础等待时间，之后每次翻倍
+    pub base_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay_ms: 200,
+        }
+    }
+}
+
+/// 外部服务熔断中，请求未发出
+#[derive(Debug, thiserror::Error)]
+#[error("外部AI服务熔断中，{}秒后重试: {service}", .retry_after.as_secs())]
+pub struct CircuitOpenError {
+    pub service: String,
+    pub retry_after: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// 冷却结束后放行的探测请求尚未返回；探测请求被取消时超过冷却时长再放行下一个
+    HalfOpen { since: Instant },
+}
+
+impl CircuitState {
+    /// 是否放行本次请求，拒绝时返回距离下次探测的时长
+    fn acquire(&mut self, now: Instant, cooldown: Duration) -> Result<(), Duration> {
+        match *self {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } if now < until => Err(until - now),
+            CircuitState::HalfOpen { since } if now < since + cooldown => Err(since + cooldown - now),
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => {
+                *self = CircuitState::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    /// 记录一次失败，返回本次是否（重新）进入熔断
+    fn on_failure(&mut self, now: Instant, config: &CircuitBreakerConfig) -> bool {
+        let failures = match *self {
+            CircuitState::Closed { failures } => failures + 1,
+            // 探测失败直接重新熔断
+            _ => config.failure_threshold,
+        };
+        if failures >= config.failure_threshold {
+            *self = CircuitState::Open {
+                until: now + Duration::from_secs(config.cooldown_seconds),
+            };
+            true
+        } else {
+            *self = CircuitState::Closed { failures };
+            false
+        }
+    }
+}
+
+/// 按服务地址（不含查询参数）独立熔断的外部AI服务调用。
+/// 网络错误、超时、5xx 和 429 计为失败；其余响应说明服务可用，交给调用方判断
+#[derive(Debug, Default)]
+pub struct CircuitBreakers {
+    states: Mutex<HashMap<String, CircuitState>>,
+}
+
+fn service_key(endpoint: &str) -> &str {
+    endpoint.split('?').next().unwrap_or(endpoint)
+}
+
+fn is_service_failure(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// 只有连接阶段的失败（含连接超时）能确定请求没有发出，可以重试；
+/// 请求发出后的超时或连接中断时服务可能已经处理（并计费），不能重试
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_connect()
+}
+
+/// 第 attempt 次重试前的等待时间：指数退避，并在后一半区间内随机抖动
+fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    let backoff = base
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY);
+    let half = backoff / 2;
+    half + half.mul_f64(rand::random::<f64>())
+}
+
+/// 只重试连接失败；服务已经返回的响应（包括 5xx）和发出后的失败原样交给调用方，
+/// 避免非幂等的请求被重复执行
+async fn send_with_retry(config: &RetryConfig, request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    let base_delay = Duration::from_millis(config.base_delay_ms);
+    let mut attempt = 0;
+    loop {
+        let retryable = if attempt < config.max_retries { request.try_clone() } else { None };
+        let Some(retryable) = retryable else {
+            return request.send().await;
+        };
+        match retryable.send().await {
+            Err(e) if is_transient(&e) => {
+                attempt += 1;
+                tracing::warn!("外部AI服务请求失败，第{}次重试: {}", attempt, e);
+                tokio::time::sleep(retry_delay(base_delay, attempt)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl CircuitBreakers {
+    /// 发送请求：熔断中直接拒绝，连接失败按 config.retry 重试，重试全部失败才计一次熔断失败。
+    /// 请求和重试可能持续较久，调用方应传入配置的副本，不要持有配置读锁，以免阻塞热更新
+    pub async fn send(
+        &self,
+        config: &AIConfig,
+        endpoint: &str,
+        request: reqwest::RequestBuilder,
+    ) -> anyhow::Result<reqwest::Response> {
+        let breaker = &config.circuit_breaker;
+        if breaker.failure_threshold == 0 {
+            return Ok(send_with_retry(&config.retry, request).await?);
+        }
+        let service = service_key(endpoint);
+        self.acquire(service, breaker)?;
+
+        let result = send_with_retry(&config.retry, request).await;
+        let failed = match &result {
+            Ok(response) => is_service_failure(response.status()),
+            Err(_) => true,
+        };
+        self.record(service, breaker, !failed);
+        Ok(result?)
+    }
+
+    fn acquire(&self, service: &str, config: &CircuitBreakerConfig) -> Result<(), CircuitOpenError> {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        let state = states
+            .entry(service.to_string())
+            .or_insert(CircuitState::Closed { failures: 0 });
+        state
+            .acquire(Instant::now(), Duration::from_secs(config.cooldown_seconds))
+            .map_err(|retry_after| CircuitOpenError {
+                service: service.to_string(),
+                retry_after,
+            })
+    }
+
+    fn record(&self, service: &str, config: &CircuitBreakerConfig, success: bool) {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(state) = states.get_mut(service) else {
+            return;
+        };
+        if success {
+            if !matches!(state, CircuitState::Closed { .. }) {
+                tracing::info!("🔌 外部AI服务恢复，解除熔断: {}", service);
+            }
+            *state = CircuitState::Closed { failures: 0 };
+        } else if state.on_failure(Instant::now(), config) {
+            tracing::warn!(
+                "🔌 外部AI服务连续失败，熔断{}秒: {}",
+                config.cooldown_seconds,
+                service
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_circuit_opens_fast_fails_and_probes_after_cooldown() {
+        let config = CircuitBreakerConfig {
+            failure_threshold: 3,
+            cooldown_seconds: 30,
+        };
+        let cooldown = Duration::from_secs(config.cooldown_seconds);
+        let start = Instant::now();
+        let mut state = CircuitState::Closed { failures: 0 };
+
+        assert!(!state.on_failure(start, &config));
+        assert!(!state.on_failure(start, &config));
+        assert!(state.acquire(start, cooldown).is_ok());
+        assert!(state.on_failure(start, &config));
+
+        // 熔断期间直接拒绝
+        let retry_after = state.acquire(start + Duration::from_secs(10), cooldown).unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(20));
+
+        // 冷却结束只放行一个探测请求
+        let probe_at = start + cooldown;
+        assert!(state.acquire(probe_at, cooldown).is_ok());
+        assert!(state.acquire(probe_at, cooldown).is_err());
+
+        // 探测失败重新熔断一个冷却周期
+        assert!(state.on_failure(probe_at, &config));
+        assert!(state.acquire(probe_at + Duration::from_secs(29), cooldown).is_err());
+        assert!(state.acquire(probe_at + cooldown, cooldown).is_ok());
+
+        assert_eq!(service_key("https://api.example.com/v1/chat?key=secret"), "https://api.example.com/v1/chat");
+        assert!(is_service_failure(reqwest::StatusCode::BAD_GATEWAY));
+        assert!(is_service_failure(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_service_failure(reqwest::StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn test_retry_delay_grows_with_jitter() {
+        let base = Duration::from_millis(200);
+        for attempt in 1..=3 {
+            let full = base * 2u32.pow(attempt - 1);
+            let delay = retry_delay(base, attempt);
+            assert!(delay >= full / 2 && delay <= full);
+        }
+        assert!(retry_delay(base, 30) <= MAX_RETRY_DELAY);
+    }
+
+    /// 统计连接次数的DNS解析器：前 failures 次解析失败（连接阶段出错，请求未发出），之后解析到模拟服务
+    struct FlakyResolver {
+        addr: std::net::SocketAddr,
+        failures: usize,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    impl reqwest::dns::Resolve for FlakyResolver {
+        fn resolve(&self, _name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            let addr = self.addr;
+            let failed = attempt <= self.failures;
+            Box::pin(async move {
+                if failed {
+                    return Err("模拟服务暂不可用".into());
+                }
+                Ok(Box::new(std::iter::once(addr)) as reqwest::dns::Addrs)
+            })
+        }
+    }
+
+    fn flaky_client(addr: std::net::SocketAddr, failures: usize) -> (reqwest::Client, Arc<AtomicUsize>) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let resolver = FlakyResolver { addr, failures, attempts: attempts.clone() };
+        let client = reqwest::Client::builder().dns_resolver(Arc::new(resolver)).build().unwrap();
+        (client, attempts)
+    }
+
+    #[tokio::test]
+    async fn test_only_connect_errors_are_retried() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/v1/chat", listener.local_addr().unwrap());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        {
+            let attempts = attempts.clone();
+            tokio::spawn(async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    // 读完请求后直接断开，模拟服务已收到请求但响应中断
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                }
+            });
+        }
+
+        let config = AIConfig {
+            retry: RetryConfig {
+                max_retries: 2,
+                base_delay_ms: 10,
+            },
+            ..AIConfig::default()
+        };
+        let client = reqwest::Client::new();
+        let breakers = CircuitBreakers::default();
+
+        // 请求已经发出，不能重复执行
+        let error = breakers.send(&config, &endpoint, client.post(&endpoint).body("{}")).await.unwrap_err();
+        assert!(!error.downcast_ref::<reqwest::Error>().unwrap().is_connect());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // 服务一直不可用时首次请求加两次重试后放弃
+        let unused = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let (client, connects) = flaky_client(unused, usize::MAX);
+        let endpoint = format!("http://mock-ai.test:{}/v1/chat", unused.port());
+        let error = breakers.send(&config, &endpoint, client.post(&endpoint).body("{}")).await.unwrap_err();
+        assert!(error.downcast_ref::<reqwest::Error>().unwrap().is_connect());
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_connect_failures_retried_until_success() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n{}")
+                    .await;
+            }
+        });
+
+        let config = AIConfig {
+            retry: RetryConfig {
+                max_retries: 2,
+                base_delay_ms: 10,
+            },
+            ..AIConfig::default()
+        };
+        // 前两次连接失败，第三次连上模拟服务并收到 200
+        let (client, connects) = flaky_client(addr, 2);
+        let endpoint = format!("http://mock-ai.test:{}/v1/chat", addr.port());
+        let response = CircuitBreakers::default()
+            .send(&config, &endpoint, client.post(&endpoint).body("{}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+    }
+}
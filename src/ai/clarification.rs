@@ -0,0 +1,321 @@
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use super::fallback::FallbackEngine;
+
+/// 自动回复输出中的澄清状态字段
+pub const CLARIFICATION_KEY: &str = "clarification";
+
+/// 按用户灰度开启多轮澄清的功能开关名，配置中 enabled 为 true 时对所有用户开启
+pub const CLARIFICATION_FLAG: &str = "ai_clarification";
+
+/// 已编译的信息匹配规则，按规则文本缓存；规则来自配置，数量有限
+static SLOT_PATTERNS: OnceLock<Mutex<HashMap<String, Option<Regex>>>> = OnceLock::new();
+
+/// 取出规则的编译结果，首次使用时编译；无效规则只告警一次
+fn slot_pattern(slot: &RequiredSlot) -> Option<Regex> {
+    let mut patterns = SLOT_PATTERNS.get_or_init(Default::default).lock().ok()?;
+    patterns
+        .entry(slot.pattern.clone())
+        .or_insert_with(|| match Regex::new(&slot.pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                tracing::warn!("澄清信息 {} 的匹配规则无效: {}", slot.name, e);
+                None
+            }
+        })
+        .clone()
+}
+
+/// 多轮澄清：意图不明或缺少关键信息时先向用户追问，收集到补充信息后再回复
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClarificationConfig {
+    pub enabled: bool,
+    /// 外部模型给出的意图置信度低于该值时追问；兜底引擎的置信度只是来源标记，不参与比较
+    pub min_confidence: f32,
+    /// 同一个问题最多追问几轮，用完后按已有信息直接回复
+    pub max_rounds: u32,
+    /// 追问后等待用户补充的时间（秒），超时后的消息按新问题处理
+    pub pending_ttl_seconds: u64,
+    /// 意图不明时的追问
+    pub intent_question: String,
+    /// 各意图回复前必须具备的信息
+    #[serde(default)]
+    pub required_slots: Vec<RequiredSlot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequiredSlot {
+    pub intent: String,
+    pub name: String,
+    /// 判断文本中已包含该信息的正则
+    pub pattern: String,
+    pub question: String,
+}
+
+impl Default for ClarificationConfig {
+    fn default() -> Self {
+        let order_id = |intent: &str, question: &str| RequiredSlot {
+            intent: intent.to_string(),
+            name: "order_id".to_string(),
+            pattern: r"\d{6,}".to_string(),
+            question: question.to_string(),
+        };
+        Self {
+            enabled: false,
+            min_confidence: 0.5,
+            max_rounds: 2,
+            pending_ttl_seconds: 600,
+            intent_question: "请问您具体想咨询哪方面的问题？例如订单查询、退款退货或物流进度。".to_string(),
+            required_slots: vec![
+                order_id("refund", "请问需要退款的是哪个订单？麻烦提供一下订单号。"),
+                order_id("order", "请提供您的订单号，方便为您查询。"),
+            ],
+        }
+    }
+}
+
+impl ClarificationConfig {
+    /// 判断回复前是否需要追问，需要时返回缺少的信息名和追问内容
+    pub fn missing_info(&self, text: &str, output: &serde_json::Value) -> Option<(String, String)> {
+        let intent = output["intent"].as_str().unwrap_or_default();
+        let low_confidence = !FallbackEngine::is_fallback(output)
+            && output["confidence"]
+                .as_f64()
+                .is_some_and(|confidence| (confidence as f32) < self.min_confidence);
+        if intent.is_empty() || intent == "unknown" || low_confidence {
+            return Some(("intent".to_string(), self.intent_question.clone()));
+        }
+
+        self.required_slots
+            .iter()
+            .filter(|slot| slot.intent == intent)
+            .find(|slot| slot_pattern(slot).is_some_and(|pattern| !pattern.is_match(text)))
+            .map(|slot| (slot.name.clone(), slot.question.clone()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClarificationStatus {
+    /// 已追问，等待用户补充
+    Pending,
+    /// 补充信息后已正常回复
+    Resolved,
+    /// 追问轮数用完，按已有信息回复
+    Exhausted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClarificationState {
+    pub status: ClarificationStatus,
+    /// 已追问的轮数
+    pub rounds: u32,
+    pub missing: Option<String>,
+    pub question: Option<String>,
+}
+
+#[derive(Debug)]
+struct PendingClarification {
+    /// 原问题加上历次补充内容
+    text: String,
+    rounds: u32,
+    asked_at: Instant,
+}
+
+/// 按用户保存待澄清的问题，用户下一条消息与原问题合并后重新判断
+#[derive(Debug, Default)]
+pub struct ClarificationTracker {
+    pending: Mutex<HashMap<String, PendingClarification>>,
+}
+
+impl ClarificationTracker {
+    /// 取出用户待澄清的问题，返回合并补充内容后的文本和已追问轮数；没有或已超时时原样返回
+    pub fn merge_pending(&self, user_id: &str, text: &str, ttl_seconds: u64) -> (String, u32) {
+        let pending = self.pending.lock().ok().and_then(|mut pending| pending.remove(user_id));
+        match pending {
+            Some(pending) if pending.asked_at.elapsed() <= Duration::from_secs(ttl_seconds) => {
+                (format!("{}\n{}", pending.text, text), pending.rounds)
+            }
+            _ => (text.to_string(), 0),
+        }
+    }
+
+    /// 根据回复结果决定追问还是直接回复，追问时把回复替换为追问内容并记住本轮问题
+    pub fn apply(
+        &self,
+        config: &ClarificationConfig,
+        user_id: &str,
+        text: &str,
+        rounds: u32,
+        output: &mut serde_json::Value,
+    ) {
+        let state = match config.missing_info(text, output) {
+            Some((missing, question)) if rounds < config.max_rounds => {
+                let rounds = rounds + 1;
+                tracing::info!("🤔 用户 {} 的问题信息不足（缺少 {}），第 {} 轮追问", user_id, missing, rounds);
+                if let Ok(mut pending) = self.pending.lock() {
+                    // 顺带清掉超时未补充的问题，避免只追问不回复的用户一直占用内存
+                    let ttl = Duration::from_secs(config.pending_ttl_seconds);
+                    pending.retain(|_, pending| pending.asked_at.elapsed() <= ttl);
+                    pending.insert(
+                        user_id.to_string(),
+                        PendingClarification { text: text.to_string(), rounds, asked_at: Instant::now() },
+                    );
+                }
+                output["reply"] = serde_json::json!(question);
+                ClarificationState {
+                    status: ClarificationStatus::Pending,
+                    rounds,
+                    missing: Some(missing),
+                    question: Some(question),
+                }
+            }
+            Some((missing, _)) => ClarificationState {
+                status: ClarificationStatus::Exhausted,
+                rounds,
+                missing: Some(missing),
+                question: None,
+            },
+            None if rounds > 0 => ClarificationState {
+                status: ClarificationStatus::Resolved,
+                rounds,
+                missing: None,
+                question: None,
+            },
+            None => return,
+        };
+        output[CLARIFICATION_KEY] = serde_json::to_value(&state).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{AIManager, AITask, AITaskType};
+
+    async fn auto_reply(manager: &AIManager, text: &str) -> serde_json::Value {
+        auto_reply_as(manager, "kehu_1", text).await
+    }
+
+    async fn auto_reply_as(manager: &AIManager, user_id: &str, text: &str) -> serde_json::Value {
+        let task = AITask::new(
+            AITaskType::AutoReply,
+            user_id.to_string(),
+            "msg_1".to_string(),
+            serde_json::json!({ "text": text }),
+            5,
+        );
+        let task_id = manager.submit_task(task).await.unwrap();
+        manager
+            .wait_for_result(&task_id, std::time::Duration::from_secs(5))
+            .await
+            .unwrap()
+            .expect("自动回复应返回结果")
+            .result
+    }
+
+    #[tokio::test]
+    async fn test_vague_question_clarified_before_answer() {
+        let manager = AIManager::new();
+        let mut config = manager.get_config().await;
+        assert!(!config.clarification.enabled, "多轮澄清默认关闭");
+        config.clarification.enabled = true;
+        manager.update_config(config).await.unwrap();
+        manager.start_processing().await.unwrap();
+
+        // 意图不明：先追问而不是猜测回复
+        let first = auto_reply(&manager, "帮我看看这个").await;
+        assert_eq!(first[CLARIFICATION_KEY]["status"], "pending");
+        assert_eq!(first[CLARIFICATION_KEY]["missing"], "intent");
+        assert_eq!(first["reply"], ClarificationConfig::default().intent_question.as_str());
+
+        // 补充了意图但缺订单号：继续追问
+        let second = auto_reply(&manager, "我想退款").await;
+        assert_eq!(second["intent"], "refund");
+        assert_eq!(second[CLARIFICATION_KEY]["status"], "pending");
+        assert_eq!(second[CLARIFICATION_KEY]["missing"], "order_id");
+        assert_eq!(second[CLARIFICATION_KEY]["rounds"], 2);
+
+        // 信息齐全后正常回复，并清掉待澄清状态
+        let third = auto_reply(&manager, "订单号 20241016001").await;
+        assert_eq!(third["intent"], "refund");
+        assert_eq!(third[CLARIFICATION_KEY]["status"], "resolved");
+        assert_ne!(third["reply"], second["reply"]);
+        let next = auto_reply(&manager, "你好").await;
+        assert!(next.get(CLARIFICATION_KEY).is_none());
+    }
+
+    #[test]
+    fn test_rounds_capped_and_low_confidence() {
+        let config = ClarificationConfig { max_rounds: 1, ..ClarificationConfig::default() };
+        let tracker = ClarificationTracker::default();
+
+        let mut output = serde_json::json!({ "reply": "猜测的回复", "intent": "unknown", "confidence": 0.9 });
+        tracker.apply(&config, "kehu_1", "嗯", 0, &mut output);
+        assert_eq!(output[CLARIFICATION_KEY]["status"], "pending");
+
+        // 追问轮数用完后不再追问，按已有信息回复
+        let (text, rounds) = tracker.merge_pending("kehu_1", "还是那个", config.pending_ttl_seconds);
+        assert_eq!((text.as_str(), rounds), ("嗯\n还是那个", 1));
+        let mut output = serde_json::json!({ "reply": "猜测的回复", "intent": "unknown", "confidence": 0.9 });
+        tracker.apply(&config, "kehu_1", &text, rounds, &mut output);
+        assert_eq!(output[CLARIFICATION_KEY]["status"], "exhausted");
+        assert_eq!(output["reply"], "猜测的回复");
+
+        // 外部模型置信度低时追问，置信度足够时直接回复
+        let low = serde_json::json!({ "intent": "inquiry", "confidence": 0.3 });
+        assert_eq!(config.missing_info("怎么用", &low).unwrap().0, "intent");
+        let high = serde_json::json!({ "intent": "inquiry", "confidence": 0.8 });
+        assert!(config.missing_info("怎么用", &high).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_feature_flag_enables_clarification_per_user() {
+        use crate::feature_flags::{FeatureFlag, FeatureFlags, FeatureFlagStore, MemoryFeatureFlagStore};
+        use std::sync::Arc;
+
+        let store: Arc<dyn FeatureFlagStore> = Arc::new(MemoryFeatureFlagStore::default());
+        let flags = Arc::new(FeatureFlags::new(store));
+        flags
+            .set_flag(
+                FeatureFlag::new(CLARIFICATION_FLAG)
+                    .with_percentage(0)
+                    .with_allow_users(vec!["kehu_beta".to_string()]),
+            )
+            .await
+            .unwrap();
+        let manager = AIManager::new().with_feature_flags(flags);
+        manager.start_processing().await.unwrap();
+
+        // 配置关闭时只有开关覆盖的用户会被追问
+        let beta = auto_reply_as(&manager, "kehu_beta", "帮我看看这个").await;
+        assert_eq!(beta[CLARIFICATION_KEY]["status"], "pending");
+        let other = auto_reply_as(&manager, "kehu_other", "帮我看看这个").await;
+        assert!(other.get(CLARIFICATION_KEY).is_none());
+    }
+
+    #[test]
+    fn test_expired_pending_questions_evicted() {
+        let config = ClarificationConfig { pending_ttl_seconds: 60, ..ClarificationConfig::default() };
+        let tracker = ClarificationTracker::default();
+        tracker.pending.lock().unwrap().insert(
+            "kehu_stale".to_string(),
+            PendingClarification {
+                text: "嗯".to_string(),
+                rounds: 1,
+                asked_at: Instant::now() - Duration::from_secs(120),
+            },
+        );
+
+        let mut output = serde_json::json!({ "reply": "猜测的回复", "intent": "unknown" });
+        tracker.apply(&config, "kehu_new", "嗯", 0, &mut output);
+
+        let pending = tracker.pending.lock().unwrap();
+        assert!(pending.contains_key("kehu_new"));
+        assert!(!pending.contains_key("kehu_stale"));
+    }
+}
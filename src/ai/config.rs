@@ -1,5 +1,13 @@
+use super::circuit_breaker::{CircuitBreakerConfig, RetryConfig};
+use super::clarification::ClarificationConfig;
+use super::experiment::{ExperimentConfig, ExperimentGroup};
+use super::AITask;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+/// 任务 metadata 中标识对话场景的字段
+pub const SCENE_METADATA_KEY: &str = "scene";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AIConfig {
@@ -11,6 +19,112 @@ pub struct AIConfig {
     pub speech_recognition: SpeechRecognitionConfig,
     pub sentiment_analysis: SentimentAnalysisConfig,
     pub auto_reply: AutoReplyConfig,
+    /// 任务结果指纹缓存的有效期（秒），0 表示不复用结果
+    #[serde(default = "default_result_cache_ttl_seconds")]
+    pub result_cache_ttl_seconds: u64,
+    /// 按对话场景选择模型，如闲聊走低成本模型、专业咨询走高能力模型
+    #[serde(default)]
+    pub model_routing: ModelRoutingConfig,
+    /// 任务结束回调（metadata 带 callback_url 的任务）
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+    /// 会话摘要，复用 intent_recognition 的对话补全接口
+    #[serde(default)]
+    pub summarization: SummarizationConfig,
+    /// AI建议动作的人工确认
+    #[serde(default)]
+    pub approval: ApprovalConfig,
+    /// 模型/prompt 的 A/B 实验
+    #[serde(default)]
+    pub experiment: ExperimentConfig,
+    /// 自动回复前的多轮澄清
+    #[serde(default)]
+    pub clarification: ClarificationConfig,
+    /// 外部AI服务连续失败时按服务地址熔断
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// 外部AI服务网络错误和超时的重试
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+fn default_result_cache_ttl_seconds() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRoutingConfig {
+    /// 任务没有 scene 或 scene 未配置时使用的模型
+    pub default_model: String,
+    /// scene -> 模型名
+    #[serde(default)]
+    pub scene_models: HashMap<String, String>,
+}
+
+impl Default for ModelRoutingConfig {
+    fn default() -> Self {
+        Self {
+            default_model: "gpt-3.5-turbo".to_string(),
+            scene_models: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// 回调请求体的 HMAC-SHA256 签名密钥，为空时不签名
+    #[serde(default)]
+    pub signing_secret: String,
+    /// 最多尝试次数（含首次）
+    pub max_attempts: u32,
+    /// 首次重试前的等待时间，之后每次翻倍
+    pub initial_backoff_ms: u64,
+    pub timeout_seconds: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            signing_secret: String::new(),
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummarizationConfig {
+    /// 会话结束时自动提交摘要任务
+    pub auto_summarize: bool,
+    /// 消息数达到该值的会话才生成摘要
+    pub min_messages: usize,
+    pub max_key_points: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApprovalConfig {
+    /// 无需确认即可自动执行的动作（不区分大小写），其余动作一律等待客服/主管确认
+    #[serde(default)]
+    pub auto_execute_actions: Vec<String>,
+}
+
+impl Default for SummarizationConfig {
+    fn default() -> Self {
+        Self {
+            auto_summarize: true,
+            min_messages: 10,
+            max_key_points: 5,
+        }
+    }
+}
+
+impl ModelRoutingConfig {
+    pub fn model_for_scene(&self, scene: Option<&str>) -> &str {
+        scene
+            .and_then(|scene| self.scene_models.get(scene))
+            .unwrap_or(&self.default_model)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +139,13 @@ pub struct IntentRecognitionConfig {
     pub supported_languages: Vec<String>,
     pub custom_intents: Vec<CustomIntent>,
     pub preprocessing: PreprocessingConfig,
+    /// 主意图之外的其他意图达到该置信度时一并返回
+    #[serde(default = "default_multi_intent_threshold")]
+    pub multi_intent_threshold: f32,
+}
+
+fn default_multi_intent_threshold() -> f32 {
+    0.35
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,12 +168,26 @@ pub struct TranslationConfig {
     pub default_target_language: String,
     pub supported_languages: Vec<LanguageMapping>,
     pub auto_detect_language: bool,
+    /// 语言检测置信度低于该值时改用 fallback_source_language
+    #[serde(default = "default_detection_confidence_threshold")]
+    pub detection_confidence_threshold: f32,
+    /// 语言检测不可靠时使用的源语言
+    #[serde(default = "default_fallback_source_language")]
+    pub fallback_source_language: String,
     pub confidence_threshold: f32,
     pub max_text_length: usize,
     pub cache_translations: bool,
     pub cache_ttl_seconds: u64,
 }
 
+fn default_detection_confidence_threshold() -> f32 {
+    0.6
+}
+
+fn default_fallback_source_language() -> String {
+    "zh".to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LanguageMapping {
     pub code: String,
@@ -77,6 +212,23 @@ pub struct SpeechRecognitionConfig {
     pub enable_word_timestamps: bool,
     pub enable_speaker_diarization: bool,
     pub custom_vocabulary: Vec<String>,
+    /// 语音消息未附带转写文本时自动提交语音识别任务
+    #[serde(default)]
+    pub auto_transcribe_voice: bool,
+    /// 分段识别时每段音频的时长（秒），超过该时长的语音消息按段识别并推送中间结果
+    #[serde(default = "default_streaming_chunk_seconds")]
+    pub streaming_chunk_seconds: u64,
+    /// 语音文件存储目录，识别任务只读取该目录下的音频文件
+    #[serde(default = "default_audio_dir")]
+    pub audio_dir: String,
+}
+
+fn default_streaming_chunk_seconds() -> u64 {
+    15
+}
+
+fn default_audio_dir() -> String {
+    "data/voice".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,6 +241,23 @@ pub struct SentimentAnalysisConfig {
     pub confidence_threshold: f32,
     pub sentiment_categories: Vec<String>,
     pub custom_keywords: HashMap<String, f32>, // keyword -> sentiment_score
+    /// 客户消息情绪分低于该值视为负面（取值 -1.0 ~ 1.0）
+    #[serde(default = "default_escalation_threshold")]
+    pub escalation_threshold: f32,
+    /// 连续多少条负面消息后升级给主管，0 表示关闭
+    #[serde(default = "default_escalation_consecutive")]
+    pub escalation_consecutive: u32,
+    /// 接收升级告警的主管ID，为空时通知所有在线客服
+    #[serde(default)]
+    pub supervisor_ids: Vec<String>,
+}
+
+fn default_escalation_threshold() -> f32 {
+    -0.5
+}
+
+fn default_escalation_consecutive() -> u32 {
+    3
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -146,6 +315,15 @@ impl Default for AIConfig {
             speech_recognition: SpeechRecognitionConfig::default(),
             sentiment_analysis: SentimentAnalysisConfig::default(),
             auto_reply: AutoReplyConfig::default(),
+            result_cache_ttl_seconds: default_result_cache_ttl_seconds(),
+            model_routing: ModelRoutingConfig::default(),
+            webhook: WebhookConfig::default(),
+            summarization: SummarizationConfig::default(),
+            approval: ApprovalConfig::default(),
+            experiment: ExperimentConfig::default(),
+            clarification: ClarificationConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            retry: RetryConfig::default(),
         }
     }
 }
@@ -169,6 +347,13 @@ impl Default for IntentRecognitionConfig {
                     patterns: vec!["我要投诉".to_string(), "这个有问题".to_string()],
                     confidence_boost: 0.1,
                 },
+                CustomIntent {
+                    name: "refund".to_string(),
+                    description: "退款退货".to_string(),
+                    keywords: vec!["退款".to_string(), "退货".to_string(), "退钱".to_string()],
+                    patterns: vec!["我要退款".to_string(), "申请退款".to_string()],
+                    confidence_boost: 0.1,
+                },
                 CustomIntent {
                     name: "inquiry".to_string(),
                     description: "咨询问询".to_string(),
@@ -185,6 +370,7 @@ impl Default for IntentRecognitionConfig {
                 },
             ],
             preprocessing: PreprocessingConfig::default(),
+            multi_intent_threshold: default_multi_intent_threshold(),
         }
     }
 }
@@ -217,6 +403,8 @@ impl Default for TranslationConfig {
                 },
             ],
             auto_detect_language: true,
+            detection_confidence_threshold: default_detection_confidence_threshold(),
+            fallback_source_language: default_fallback_source_language(),
             confidence_threshold: 0.8,
             max_text_length: 5000,
             cache_translations: true,
@@ -252,6 +440,9 @@ impl Default for SpeechRecognitionConfig {
             enable_word_timestamps: false,
             enable_speaker_diarization: false,
             custom_vocabulary: vec![],
+            auto_transcribe_voice: false,
+            streaming_chunk_seconds: default_streaming_chunk_seconds(),
+            audio_dir: default_audio_dir(),
         }
     }
 }
@@ -271,6 +462,9 @@ impl Default for SentimentAnalysisConfig {
                 "neutral".to_string(),
             ],
             custom_keywords: HashMap::new(),
+            escalation_threshold: default_escalation_threshold(),
+            escalation_consecutive: default_escalation_consecutive(),
+            supervisor_ids: Vec::new(),
         }
     }
 }
@@ -365,9 +559,44 @@ impl AIConfig {
             return Err("auto_reply.api_key is required when enabled".to_string());
         }
 
+        if self.experiment.treatment_percent > 100 {
+            return Err("experiment.treatment_percent must be between 0 and 100".to_string());
+        }
+
+        if self.experiment.enabled && self.experiment.task_types.is_empty() {
+            return Err("experiment.task_types is required when enabled".to_string());
+        }
+
         Ok(())
     }
 
+    /// 根据任务 metadata 中的 scene 选择模型，A/B 实验组的用户改用实验模型
+    pub fn model_for_task(&self, task: &AITask) -> &str {
+        if self.experiment_group(task) == Some(ExperimentGroup::Treatment) {
+            return &self.experiment.treatment.model;
+        }
+        self.model_routing
+            .model_for_scene(task.metadata.get(SCENE_METADATA_KEY).map(String::as_str))
+    }
+
+    /// 任务所属的实验分组，实验未启用或任务不在实验范围内时为 None
+    pub fn experiment_group(&self, task: &AITask) -> Option<ExperimentGroup> {
+        self.experiment.group_for(&task.task_type, &task.user_id)
+    }
+
+    /// 处理器的系统 prompt：实验组为该任务类型配置了 prompt 时替换默认值
+    pub fn system_prompt_for_task<'a>(&'a self, task: &AITask, default: &'a str) -> &'a str {
+        match self.experiment_group(task) {
+            Some(ExperimentGroup::Treatment) => self
+                .experiment
+                .treatment
+                .system_prompts
+                .get(&task.task_type)
+                .map_or(default, String::as_str),
+            _ => default,
+        }
+    }
+
     pub fn get_enabled_features(&self) -> Vec<String> {
         let mut features = Vec::new();
         
@@ -386,7 +615,183 @@ impl AIConfig {
         if self.auto_reply.enabled {
             features.push("auto_reply".to_string());
         }
+        if self.summarization.auto_summarize {
+            features.push("summarization".to_string());
+        }
         
         features
     }
-} 
\ No newline at end of file
+} 
+
+/// 对比两份配置，返回形如 "intent_recognition.confidence_threshold: 0.7 -> 0.8" 的变更列表
+pub fn diff_config(old: &AIConfig, new: &AIConfig) -> serde_json::Result<Vec<String>> {
+    let mut changes = Vec::new();
+    diff_values("", &serde_json::to_value(old)?, &serde_json::to_value(new)?, &mut changes);
+    Ok(changes)
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            let keys: BTreeSet<&String> = old_map.keys().chain(new_map.keys()).collect();
+            for key in keys {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_values(
+                    &child,
+                    old_map.get(key).unwrap_or(&Value::Null),
+                    new_map.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ if old != new => {
+            // 密钥类字段只记录发生了变化，不输出内容
+            if path.ends_with("api_key") || path.ends_with("secret") {
+                changes.push(format!("{}: *** -> ***", path));
+            } else {
+                changes.push(format!("{}: {} -> {}", path, old, new));
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{AIManager, AITaskType};
+
+    #[test]
+    fn test_diff_config() {
+        let old = AIConfig::default();
+        let mut new = old.clone();
+        new.intent_recognition.confidence_threshold = 0.5;
+        new.translation.api_key = "secret-key".to_string();
+
+        let changes = diff_config(&old, &new).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().any(|c| c.starts_with("intent_recognition.confidence_threshold:")));
+        assert!(changes.contains(&"translation.api_key: *** -> ***".to_string()));
+        assert!(diff_config(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_model_routing_by_scene() {
+        let mut config = AIConfig::default();
+        config.model_routing.default_model = "default-model".to_string();
+        config.model_routing.scene_models.insert("chitchat".to_string(), "small-model".to_string());
+        config.model_routing.scene_models.insert("consulting".to_string(), "large-model".to_string());
+
+        let task_in_scene = |scene: Option<&str>| {
+            let mut task = AITask::new(
+                crate::ai::AITaskType::IntentRecognition,
+                "user1".to_string(),
+                "msg1".to_string(),
+                serde_json::json!({ "text": "你好" }),
+                5,
+            );
+            if let Some(scene) = scene {
+                task.metadata.insert(SCENE_METADATA_KEY.to_string(), scene.to_string());
+            }
+            task
+        };
+
+        assert_eq!(config.model_for_task(&task_in_scene(Some("chitchat"))), "small-model");
+        assert_eq!(config.model_for_task(&task_in_scene(Some("consulting"))), "large-model");
+        // 未配置的场景和没有场景的任务都走默认模型
+        assert_eq!(config.model_for_task(&task_in_scene(Some("unknown"))), "default-model");
+        assert_eq!(config.model_for_task(&task_in_scene(None)), "default-model");
+    }
+
+    #[test]
+    fn test_treatment_prompt_keyed_by_task_type() {
+        let mut config = AIConfig::default();
+        config.experiment.enabled = true;
+        config.experiment.treatment_percent = 100;
+        config.experiment.task_types = vec![AITaskType::IntentRecognition, AITaskType::Summarization];
+        config
+            .experiment
+            .treatment
+            .system_prompts
+            .insert(AITaskType::IntentRecognition, "intent-v2".to_string());
+
+        let task = |task_type| {
+            AITask::new(task_type, "user1".to_string(), "msg1".to_string(), serde_json::json!({}), 5)
+        };
+        assert_eq!(config.system_prompt_for_task(&task(AITaskType::IntentRecognition), "intent"), "intent-v2");
+        // 实验内但没有单独配置 prompt 的任务沿用默认，不会套用意图识别的 prompt
+        assert_eq!(config.system_prompt_for_task(&task(AITaskType::Summarization), "summary"), "summary");
+        // 不在实验范围内的任务既不分组也不换模型
+        let translation = task(AITaskType::Translation);
+        assert_eq!(config.experiment_group(&translation), None);
+        assert_ne!(config.model_for_task(&translation), config.experiment.treatment.model);
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_from_file() {
+        let path = std::env::temp_dir().join(format!("ai_config_{}.json", uuid::Uuid::new_v4()));
+        let mut config = AIConfig::default();
+        config.intent_recognition.api_key = "sk-intent".to_string();
+        config.translation.api_key = "sk-translation".to_string();
+        config.speech_recognition.api_key = "sk-speech".to_string();
+        config.auto_reply.api_key = "sk-reply".to_string();
+        std::fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+
+        let manager = AIManager::new();
+        manager.reload_config(&path).await.unwrap();
+        config.max_concurrent_tasks = 42;
+        std::fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+        let changes = manager.reload_config(&path).await.unwrap();
+        assert_eq!(changes, vec!["max_concurrent_tasks: 10 -> 42".to_string()]);
+        assert_eq!(manager.get_config().await.max_concurrent_tasks, 42);
+
+        // 无效文件不覆盖当前配置
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(manager.reload_config(&path).await.is_err());
+        assert_eq!(manager.get_config().await.max_concurrent_tasks, 42);
+
+        // 能解析但校验不通过的配置同样被拒绝
+        let invalid = AIConfig { max_concurrent_tasks: 0, ..config.clone() };
+        std::fs::write(&path, serde_json::to_string_pretty(&invalid).unwrap()).unwrap();
+        let error = manager.reload_config(&path).await.unwrap_err().to_string();
+        assert!(error.contains("max_concurrent_tasks"));
+        assert_eq!(manager.get_config().await.max_concurrent_tasks, 42);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watch_config_file_reloads_on_change() {
+        let dir = std::env::temp_dir().join(format!("ai_config_watch_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ai_config.json");
+        let config: AIConfig = serde_json::from_str(include_str!("../../config/ai_config.json")).unwrap();
+        std::fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+
+        let manager = std::sync::Arc::new(AIManager::new());
+        let watcher = tokio::spawn(manager.clone().watch_config_file(path.clone()));
+        // 启动时加载随仓库提供的配置文件
+        let loaded = async {
+            while manager.get_config().await.intent_recognition.enabled {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), loaded).await.expect("未加载配置文件");
+
+        let updated = AIConfig { task_timeout_seconds: 45, ..config };
+        std::fs::write(&path, serde_json::to_string_pretty(&updated).unwrap()).unwrap();
+        let reloaded = async {
+            while manager.get_config().await.task_timeout_seconds != 45 {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(10), reloaded).await.expect("文件修改后未重新加载");
+
+        watcher.abort();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,205 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+#[cfg(test)]
+use std::collections::HashMap;
+#[cfg(test)]
+use std::time::{Duration, Instant};
+#[cfg(test)]
+use tokio::sync::RwLock;
+
+use super::config::AIConfig;
+use super::AITask;
+use crate::redis_client::RedisManager;
+
+const FINGERPRINT_KEY_PREFIX: &str = "ai:fingerprint:";
+
+/// 计算任务指纹：相同类型、相同输入且路由到同一模型、同一实验分组的任务得到相同指纹，
+/// 不同场景路由到不同模型时结果不共享，实验对照组和实验组的结果也不互用
+pub fn task_fingerprint(task: &AITask, config: &AIConfig) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}", task.task_type).as_bytes());
+    hasher.update(b"|");
+    hasher.update(config.model_for_task(task).as_bytes());
+    if let Some(group) = config.experiment_group(task) {
+        hasher.update(b"|");
+        hasher.update(format!("{}:{:?}", config.experiment.name, group).as_bytes());
+    }
+    hasher.update(b"|");
+    hasher.update(task.input_data.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// 指纹 -> 结果 的存储接口
+#[async_trait::async_trait]
+pub trait ResultStore: Send + Sync {
+    async fn get(&self, fingerprint: &str) -> Result<Option<serde_json::Value>>;
+    async fn put(&self, fingerprint: &str, result: &serde_json::Value, ttl_seconds: u64) -> Result<()>;
+}
+
+/// 基于Redis的结果存储，多实例共享
+pub struct RedisResultStore {
+    redis: RedisManager,
+}
+
+impl RedisResultStore {
+    pub fn new(redis: RedisManager) -> Self {
+        Self { redis }
+    }
+
+    fn key(fingerprint: &str) -> String {
+        format!("{}{}", FINGERPRINT_KEY_PREFIX, fingerprint)
+    }
+}
+
+#[async_trait::async_trait]
+impl ResultStore for RedisResultStore {
+    async fn get(&self, fingerprint: &str) -> Result<Option<serde_json::Value>> {
+        let key = Self::key(fingerprint);
+        let mut conn = self.redis.get_async_connection().await?;
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        let raw = conn.get(&key).await?;
+        Ok(Some(serde_json::from_str(&raw)?))
+    }
+
+    async fn put(&self, fingerprint: &str, result: &serde_json::Value, ttl_seconds: u64) -> Result<()> {
+        let mut conn = self.redis.get_async_connection().await?;
+        conn.set_ex(Self::key(fingerprint), result.to_string(), ttl_seconds as i64).await
+    }
+}
+
+/// 进程内结果存储，用于测试
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryResultStore {
+    entries: RwLock<HashMap<String, (serde_json::Value, Instant)>>,
+}
+
+#[cfg(test)]
+impl MemoryResultStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl ResultStore for MemoryResultStore {
+    async fn get(&self, fingerprint: &str) -> Result<Option<serde_json::Value>> {
+        let entries = self.entries.read().await;
+        Ok(entries
+            .get(fingerprint)
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(value, _)| value.clone()))
+    }
+
+    async fn put(&self, fingerprint: &str, result: &serde_json::Value, ttl_seconds: u64) -> Result<()> {
+        let mut entries = self.entries.write().await;
+        entries.retain(|_, (_, expires_at)| *expires_at > Instant::now());
+        entries.insert(
+            fingerprint.to_string(),
+            (result.clone(), Instant::now() + Duration::from_secs(ttl_seconds)),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::experiment::ExperimentGroup;
+    use crate::ai::{AIManager, AITaskType};
+    use std::sync::Arc;
+
+    fn intent_task(text: &str) -> AITask {
+        AITask::new(
+            AITaskType::IntentRecognition,
+            "user1".to_string(),
+            "msg1".to_string(),
+            serde_json::json!({ "text": text }),
+            5,
+        )
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_task_identity() {
+        let a = intent_task("我要投诉");
+        let b = intent_task("我要投诉");
+        let c = intent_task("我想了解一下");
+        assert_ne!(a.id, b.id);
+        let config = AIConfig::default();
+        assert_eq!(task_fingerprint(&a, &config), task_fingerprint(&b, &config));
+        assert_ne!(task_fingerprint(&a, &config), task_fingerprint(&c, &config));
+    }
+
+    #[test]
+    fn test_fingerprint_separates_scene_models() {
+        let mut config = AIConfig::default();
+        config.model_routing.scene_models.insert("chitchat".to_string(), "small-model".to_string());
+        config.model_routing.scene_models.insert("consulting".to_string(), "large-model".to_string());
+        let in_scene = |scene: &str| {
+            let mut task = intent_task("这个套餐怎么收费");
+            task.metadata.insert(crate::ai::config::SCENE_METADATA_KEY.to_string(), scene.to_string());
+            task
+        };
+
+        // 同一输入在不同场景由不同模型处理，不能共用缓存结果
+        assert_ne!(task_fingerprint(&in_scene("chitchat"), &config), task_fingerprint(&in_scene("consulting"), &config));
+        assert_eq!(task_fingerprint(&in_scene("chitchat"), &config), task_fingerprint(&in_scene("chitchat"), &config));
+    }
+
+    #[test]
+    fn test_fingerprint_separates_experiment_groups() {
+        let mut config = AIConfig::default();
+        config.experiment.enabled = true;
+        config.experiment.task_types = vec![AITaskType::IntentRecognition];
+        config.experiment.treatment_percent = 50;
+        // 实验组只改 prompt 不换模型时，也不能与对照组共用结果
+        config.experiment.treatment.model = config.model_routing.default_model.clone();
+
+        let task_for = |user_id: &str| {
+            let mut task = intent_task("这个套餐怎么收费");
+            task.user_id = user_id.to_string();
+            task
+        };
+        let user_in = |group| {
+            (0..100)
+                .map(|i| format!("kehu_{}", i))
+                .find(|user| config.experiment_group(&task_for(user)) == Some(group))
+                .unwrap()
+        };
+        let control = task_for(&user_in(ExperimentGroup::Control));
+        let treatment = task_for(&user_in(ExperimentGroup::Treatment));
+        assert_ne!(task_fingerprint(&control, &config), task_fingerprint(&treatment, &config));
+    }
+
+    #[tokio::test]
+    async fn test_result_shared_across_instances() {
+        // 两个实例共享同一存储，模拟共享Redis
+        let store: Arc<dyn ResultStore> = Arc::new(MemoryResultStore::new());
+        let instance_a = AIManager::new().with_result_store(store.clone());
+        let instance_b = AIManager::new().with_result_store(store.clone());
+        instance_a.start_processing().await.unwrap();
+        instance_b.start_processing().await.unwrap();
+
+        let task_a = intent_task("我要投诉这个产品质量有问题");
+        let id_a = instance_a.submit_task(task_a).await.unwrap();
+        let result_a = instance_a.wait_for_result(&id_a, Duration::from_secs(5)).await.unwrap().unwrap();
+
+        let task_b = intent_task("我要投诉这个产品质量有问题");
+        let id_b = instance_b.submit_task(task_b).await.unwrap();
+        let result_b = instance_b.wait_for_result(&id_b, Duration::from_secs(5)).await.unwrap().unwrap();
+
+        assert_eq!(result_a.result, result_b.result);
+        assert_eq!(instance_a.result_cache_hits(), 0);
+        assert_eq!(instance_b.result_cache_hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_expires() {
+        let store = MemoryResultStore::new();
+        store.put("fp", &serde_json::json!({"ok": true}), 0).await.unwrap();
+        assert!(store.get("fp").await.unwrap().is_none());
+    }
+}
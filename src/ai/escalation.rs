@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// 内置情绪词，custom_keywords 中的同名词会覆盖这里的分值
+const POSITIVE_WORDS: &[&str] = &["好", "棒", "赞", "喜欢", "满意", "谢谢", "excellent", "good", "great", "thanks"];
+const NEGATIVE_WORDS: &[&str] = &[
+    "不好", "差", "烂", "讨厌", "不满意", "生气", "失望", "垃圾", "投诉", "骗子",
+    "bad", "terrible", "awful", "angry",
+];
+
+/// 基于关键词估算文本情绪分，范围 -1.0（极负面）~ 1.0（极正面），未命中任何词时为 0
+pub fn score_sentiment(text: &str, custom_keywords: &HashMap<String, f32>) -> f32 {
+    let mut lexicon: HashMap<String, f32> = POSITIVE_WORDS
+        .iter()
+        .map(|word| (word.to_string(), 1.0))
+        .chain(NEGATIVE_WORDS.iter().map(|word| (word.to_string(), -1.0)))
+        .collect();
+    for (word, score) in custom_keywords {
+        lexicon.insert(word.to_lowercase(), *score);
+    }
+
+    // 长词优先匹配并从文本中移除，避免“不满意”同时命中“满意”
+    let mut words: Vec<(String, f32)> = lexicon.into_iter().filter(|(word, _)| !word.is_empty()).collect();
+    words.sort_by(|a, b| b.0.chars().count().cmp(&a.0.chars().count()).then_with(|| a.0.cmp(&b.0)));
+
+    let mut remaining = text.to_lowercase();
+    let mut total = 0.0;
+    let mut hits = 0;
+    for (word, score) in words {
+        let count = remaining.matches(word.as_str()).count();
+        if count > 0 {
+            total += score * count as f32;
+            hits += count;
+            remaining = remaining.replace(word.as_str(), " ");
+        }
+    }
+
+    if hits == 0 {
+        0.0
+    } else {
+        (total / hits as f32).clamp(-1.0, 1.0)
+    }
+}
+
+/// 按客户统计连续负面消息数，达到阈值时触发一次主管升级
+#[derive(Default)]
+pub struct SentimentEscalationTracker {
+    streaks: Mutex<HashMap<String, u32>>,
+}
+
+impl SentimentEscalationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一条消息的情绪分，连续负面次数恰好达到 consecutive 时返回 true（同一轮只触发一次）
+    pub fn observe(&self, customer_id: &str, score: f32, threshold: f32, consecutive: u32) -> bool {
+        if consecutive == 0 {
+            return false;
+        }
+        let mut streaks = match self.streaks.lock() {
+            Ok(streaks) => streaks,
+            Err(_) => return false,
+        };
+
+        if score < threshold {
+            let streak = streaks.entry(customer_id.to_string()).or_insert(0);
+            *streak = streak.saturating_add(1);
+            *streak == consecutive
+        } else {
+            streaks.remove(customer_id);
+            false
+        }
+    }
+
+    pub fn reset(&self, customer_id: &str) {
+        if let Ok(mut streaks) = self.streaks.lock() {
+            streaks.remove(customer_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_sentiment() {
+        let custom = HashMap::new();
+        assert!(score_sentiment("服务很好，非常满意", &custom) > 0.0);
+        assert!(score_sentiment("太差了，我很不满意", &custom) < 0.0);
+        assert_eq!(score_sentiment("请问几点发货", &custom), 0.0);
+
+        let custom = HashMap::from([("发货慢".to_string(), -0.8)]);
+        assert_eq!(score_sentiment("又是发货慢", &custom), -0.8);
+    }
+
+    #[test]
+    fn test_escalates_after_consecutive_negative_messages() {
+        let tracker = SentimentEscalationTracker::new();
+        assert!(!tracker.observe("kehu_1", -1.0, -0.5, 3));
+        assert!(!tracker.observe("kehu_1", -1.0, -0.5, 3));
+        // 中间出现一条正常消息，计数重新开始
+        assert!(!tracker.observe("kehu_1", 0.0, -0.5, 3));
+        assert!(!tracker.observe("kehu_1", -1.0, -0.5, 3));
+        assert!(!tracker.observe("kehu_1", -0.8, -0.5, 3));
+        assert!(tracker.observe("kehu_1", -0.9, -0.5, 3));
+        // 同一轮不重复告警
+        assert!(!tracker.observe("kehu_1", -1.0, -0.5, 3));
+        // 其他客户互不影响
+        assert!(!tracker.observe("kehu_2", -1.0, -0.5, 3));
+
+        tracker.reset("kehu_1");
+        assert!(!tracker.observe("kehu_1", -1.0, -0.5, 0));
+    }
+}
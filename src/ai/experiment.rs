@@ -0,0 +1,268 @@
+use super::AITaskType;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A/B 实验配置：按用户哈希把一定比例的流量分到实验组，实验组使用另一套模型/prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentConfig {
+    pub enabled: bool,
+    /// 实验名参与用户哈希，换实验名即重新分组并重新统计
+    pub name: String,
+    /// 分到实验组的流量百分比（0-100）
+    pub treatment_percent: u32,
+    /// 参与实验的任务类型，其它任务不分组、不计入实验统计
+    #[serde(default)]
+    pub task_types: Vec<AITaskType>,
+    /// 实验组使用的模型和 prompt，对照组沿用 model_routing
+    pub treatment: ExperimentVariant,
+    /// 对照组每次调用的估算成本
+    pub control_cost_per_request: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentVariant {
+    pub model: String,
+    /// 按任务类型替换处理器默认的系统 prompt，未配置的任务沿用默认
+    #[serde(default)]
+    pub system_prompts: HashMap<AITaskType, String>,
+    /// 每次调用的估算成本
+    pub cost_per_request: f64,
+}
+
+impl Default for ExperimentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: "default".to_string(),
+            treatment_percent: 0,
+            task_types: Vec::new(),
+            treatment: ExperimentVariant {
+                model: "gpt-4o-mini".to_string(),
+                system_prompts: HashMap::new(),
+                cost_per_request: 0.0,
+            },
+            control_cost_per_request: 0.0,
+        }
+    }
+}
+
+impl ExperimentConfig {
+    /// 实验未启用或任务类型不在实验范围内时返回 None
+    pub fn group_for(&self, task_type: &AITaskType, user_id: &str) -> Option<ExperimentGroup> {
+        (self.enabled && self.task_types.contains(task_type))
+            .then(|| assign_group(&self.name, user_id, self.treatment_percent))
+    }
+
+    pub fn cost_per_request(&self, group: ExperimentGroup) -> f64 {
+        match group {
+            ExperimentGroup::Control => self.control_cost_per_request,
+            ExperimentGroup::Treatment => self.treatment.cost_per_request,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ExperimentGroup {
+    Control,
+    Treatment,
+}
+
+/// 稳定分流：同一实验中同一用户总是分到同一组，与进程和实例无关
+pub fn assign_group(experiment: &str, user_id: &str, treatment_percent: u32) -> ExperimentGroup {
+    let mut hasher = Sha256::new();
+    hasher.update(experiment.as_bytes());
+    hasher.update(b":");
+    hasher.update(user_id.as_bytes());
+    let digest = hasher.finalize();
+    let bucket = u64::from_be_bytes(digest[..8].try_into().unwrap_or_default()) % 100;
+    if bucket < treatment_percent.min(100) as u64 {
+        ExperimentGroup::Treatment
+    } else {
+        ExperimentGroup::Control
+    }
+}
+
+/// 单组的对比指标
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ExperimentGroupStats {
+    pub group: ExperimentGroup,
+    /// 含命中结果缓存的请求
+    pub requests: u64,
+    /// 命中结果缓存、未调用外部模型的请求
+    pub cache_hits: u64,
+    pub failures: u64,
+    /// 外部模型调用的平均耗时，不含缓存命中
+    pub avg_latency_ms: f64,
+    pub total_cost: f64,
+    pub feedback_count: u64,
+    /// 客服评价为有用的比例，没有评价时为 None
+    pub satisfaction_rate: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct GroupAccumulator {
+    requests: u64,
+    cache_hits: u64,
+    failures: u64,
+    total_latency_ms: u64,
+    total_cost: f64,
+    feedback_count: u64,
+    helpful_count: u64,
+}
+
+/// 按实验组累计调用耗时、成本和客服评价，实验名变化时清空重新统计
+#[derive(Debug, Default)]
+pub struct ExperimentTracker {
+    state: Mutex<(String, HashMap<ExperimentGroup, GroupAccumulator>)>,
+}
+
+impl ExperimentTracker {
+    /// 记录一次外部模型调用
+    pub fn record_call(&self, experiment: &str, group: ExperimentGroup, latency_ms: u64, cost: f64, success: bool) {
+        self.update(experiment, group, |acc| {
+            acc.requests += 1;
+            acc.total_latency_ms += latency_ms;
+            acc.total_cost += cost;
+            if !success {
+                acc.failures += 1;
+            }
+        });
+    }
+
+    /// 命中结果缓存的请求只计入请求数，不计耗时和成本
+    pub fn record_cache_hit(&self, experiment: &str, group: ExperimentGroup) {
+        self.update(experiment, group, |acc| {
+            acc.requests += 1;
+            acc.cache_hits += 1;
+        });
+    }
+
+    pub fn record_feedback(&self, experiment: &str, group: ExperimentGroup, helpful: bool) {
+        self.update(experiment, group, |acc| {
+            acc.feedback_count += 1;
+            if helpful {
+                acc.helpful_count += 1;
+            }
+        });
+    }
+
+    /// 当前实验的各组指标，两组都会列出
+    pub fn report(&self, experiment: &str) -> Vec<ExperimentGroupStats> {
+        let state = self.state.lock().unwrap();
+        let empty = HashMap::new();
+        let groups = if state.0 == experiment { &state.1 } else { &empty };
+        [ExperimentGroup::Control, ExperimentGroup::Treatment]
+            .into_iter()
+            .map(|group| {
+                let acc = groups.get(&group);
+                let requests = acc.map_or(0, |acc| acc.requests);
+                let cache_hits = acc.map_or(0, |acc| acc.cache_hits);
+                let calls = requests - cache_hits;
+                let feedback_count = acc.map_or(0, |acc| acc.feedback_count);
+                ExperimentGroupStats {
+                    group,
+                    requests,
+                    cache_hits,
+                    failures: acc.map_or(0, |acc| acc.failures),
+                    avg_latency_ms: if calls == 0 {
+                        0.0
+                    } else {
+                        acc.map_or(0, |acc| acc.total_latency_ms) as f64 / calls as f64
+                    },
+                    total_cost: acc.map_or(0.0, |acc| acc.total_cost),
+                    feedback_count,
+                    satisfaction_rate: (feedback_count > 0)
+                        .then(|| acc.map_or(0, |acc| acc.helpful_count) as f64 / feedback_count as f64),
+                }
+            })
+            .collect()
+    }
+
+    fn update(&self, experiment: &str, group: ExperimentGroup, apply: impl FnOnce(&mut GroupAccumulator)) {
+        let mut state = self.state.lock().unwrap();
+        if state.0 != experiment {
+            *state = (experiment.to_string(), HashMap::new());
+        }
+        apply(state.1.entry(group).or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_split_by_ratio_and_stable() {
+        let users: Vec<String> = (0..10_000).map(|i| format!("kehu_{}", i)).collect();
+        let treatment = users
+            .iter()
+            .filter(|user| assign_group("prompt-v2", user, 20) == ExperimentGroup::Treatment)
+            .count();
+        // 20% 流量进入实验组，允许小幅误差
+        assert!((1_700..=2_300).contains(&treatment), "实验组人数: {}", treatment);
+
+        // 同一用户多次分流结果一致
+        assert!(users
+            .iter()
+            .all(|user| assign_group("prompt-v2", user, 20) == assign_group("prompt-v2", user, 20)));
+        assert!(users.iter().all(|user| assign_group("prompt-v2", user, 0) == ExperimentGroup::Control));
+        assert!(users.iter().all(|user| assign_group("prompt-v2", user, 100) == ExperimentGroup::Treatment));
+
+        let config = ExperimentConfig::default();
+        assert_eq!(config.group_for(&AITaskType::IntentRecognition, "kehu_1"), None);
+    }
+
+    #[test]
+    fn test_only_experiment_tasks_are_grouped() {
+        let config = ExperimentConfig {
+            enabled: true,
+            treatment_percent: 100,
+            task_types: vec![AITaskType::IntentRecognition],
+            ..ExperimentConfig::default()
+        };
+        assert_eq!(
+            config.group_for(&AITaskType::IntentRecognition, "kehu_1"),
+            Some(ExperimentGroup::Treatment)
+        );
+        assert_eq!(config.group_for(&AITaskType::Summarization, "kehu_1"), None);
+        assert_eq!(config.group_for(&AITaskType::Translation, "kehu_1"), None);
+    }
+
+    #[test]
+    fn test_metrics_tracked_per_group() {
+        let tracker = ExperimentTracker::default();
+        tracker.record_call("exp", ExperimentGroup::Control, 100, 0.01, true);
+        tracker.record_call("exp", ExperimentGroup::Control, 300, 0.01, false);
+        tracker.record_call("exp", ExperimentGroup::Treatment, 50, 0.002, true);
+        tracker.record_cache_hit("exp", ExperimentGroup::Treatment);
+        tracker.record_feedback("exp", ExperimentGroup::Control, true);
+        tracker.record_feedback("exp", ExperimentGroup::Treatment, true);
+        tracker.record_feedback("exp", ExperimentGroup::Treatment, false);
+
+        let report = tracker.report("exp");
+        assert_eq!(report.len(), 2);
+        let control = &report[0];
+        assert_eq!(control.group, ExperimentGroup::Control);
+        assert_eq!((control.requests, control.failures), (2, 1));
+        assert_eq!(control.avg_latency_ms, 200.0);
+        assert!((control.total_cost - 0.02).abs() < 1e-9);
+        assert_eq!(control.satisfaction_rate, Some(1.0));
+
+        let treatment = &report[1];
+        // 缓存命中计入请求数，不拉低平均耗时
+        assert_eq!((treatment.requests, treatment.cache_hits), (2, 1));
+        assert_eq!(treatment.avg_latency_ms, 50.0);
+        assert!((treatment.total_cost - 0.002).abs() < 1e-9);
+        assert_eq!(treatment.feedback_count, 2);
+        assert_eq!(treatment.satisfaction_rate, Some(0.5));
+
+        // 换实验后重新统计
+        tracker.record_call("exp-2", ExperimentGroup::Treatment, 10, 0.0, true);
+        assert_eq!(tracker.report("exp-2")[0].requests, 0);
+        assert_eq!(tracker.report("exp-2")[1].requests, 1);
+        assert!(tracker.report("exp").iter().all(|stats| stats.requests == 0));
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::Result;
+use super::{AITask, AITaskType};
+use super::intent_recognition::{IntentResult, IntentScore};
+
+/// 兜底结果的来源标记
+pub const FALLBACK_SOURCE: &str = "fallback";
+
+/// 兜底结果的置信度，调用方据此区分真实AI结果
+pub const FALLBACK_CONFIDENCE: f32 = 0.2;
+
+const DEFAULT_REPLY: &str = "您好，您的消息已收到，客服会尽快为您处理。";
+
+struct FallbackRule {
+    intent: &'static str,
+    keywords: &'static [&'static str],
+    reply: &'static str,
+}
+
+// 按优先级排列，意图识别返回全部命中的规则，自动回复取第一条
+const RULES: &[FallbackRule] = &[
+    FallbackRule {
+        intent: "complaint",
+        keywords: &["投诉", "不满", "差评", "问题"],
+        reply: "非常抱歉给您带来不便，您的问题已记录，客服会尽快跟进处理。",
+    },
+    FallbackRule {
+        intent: "refund",
+        keywords: &["退款", "退货", "退钱", "refund"],
+        reply: "退款/退货请提供订单号，客服会尽快为您核实办理。",
+    },
+    FallbackRule {
+        intent: "order",
+        keywords: &["订单", "下单", "购买", "发货", "物流"],
+        reply: "请提供您的订单号，我们会帮您查询订单状态。",
+    },
+    FallbackRule {
+        intent: "inquiry",
+        keywords: &["请问", "咨询", "了解", "怎么"],
+        reply: "您好，您的咨询已收到，客服稍后会为您详细解答。",
+    },
+    FallbackRule {
+        intent: "greeting",
+        keywords: &["你好", "您好", "在吗", "hello"],
+        reply: "您好，请问有什么可以帮您？",
+    },
+];
+
+/// 纯本地关键词规则引擎，外部AI服务全部不可用时作为最终兜底
+pub struct FallbackEngine;
+
+impl FallbackEngine {
+    /// 是否可以为该类型的任务兜底
+    pub fn supports(task_type: &AITaskType) -> bool {
+        matches!(task_type, AITaskType::IntentRecognition | AITaskType::AutoReply)
+    }
+
+    pub fn process(task: &AITask) -> Result<serde_json::Value> {
+        let text = task.input_data["text"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("缺少文本输入"))?;
+
+        let rules = Self::match_rules(text);
+        let rule = rules.first().copied();
+        let intent = rule.map(|r| r.intent).unwrap_or("unknown");
+
+        let mut output = match task.task_type {
+            AITaskType::IntentRecognition => serde_json::to_value(IntentResult {
+                intent: intent.to_string(),
+                confidence: FALLBACK_CONFIDENCE,
+                intents: rules
+                    .iter()
+                    .map(|r| IntentScore {
+                        intent: r.intent.to_string(),
+                        confidence: FALLBACK_CONFIDENCE,
+                    })
+                    .collect(),
+                entities: vec![],
+                sentiment: None,
+                language: "zh".to_string(),
+                original_text: text.to_string(),
+            })?,
+            AITaskType::AutoReply => serde_json::json!({
+                "reply": rule.map(|r| r.reply).unwrap_or(DEFAULT_REPLY),
+                "intent": intent,
+                "confidence": FALLBACK_CONFIDENCE,
+            }),
+            _ => return Err(anyhow::anyhow!("规则引擎不支持的任务类型: {:?}", task.task_type)),
+        };
+        output["source"] = serde_json::json!(FALLBACK_SOURCE);
+        Ok(output)
+    }
+
+    /// 判断处理结果是否来自兜底引擎
+    pub fn is_fallback(output: &serde_json::Value) -> bool {
+        output["source"] == FALLBACK_SOURCE
+    }
+
+    // 按优先级返回所有命中的规则
+    fn match_rules(text: &str) -> Vec<&'static FallbackRule> {
+        let text = text.to_lowercase();
+        RULES
+            .iter()
+            .filter(|rule| rule.keywords.iter().any(|keyword| text.contains(keyword)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::AIManager;
+    use std::time::Duration;
+
+    fn task(task_type: AITaskType, text: &str) -> AITask {
+        AITask::new(
+            task_type,
+            "user1".to_string(),
+            "msg1".to_string(),
+            serde_json::json!({ "text": text }),
+            5,
+        )
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let output = FallbackEngine::process(&task(AITaskType::AutoReply, "我要退款")).unwrap();
+        assert_eq!(output["intent"], "refund");
+        assert_eq!(output["source"], FALLBACK_SOURCE);
+
+        let output = FallbackEngine::process(&task(AITaskType::AutoReply, "abc")).unwrap();
+        assert_eq!(output["intent"], "unknown");
+        assert_eq!(output["reply"], DEFAULT_REPLY);
+        assert!(FallbackEngine::process(&task(AITaskType::Translation, "你好")).is_err());
+
+        let output = FallbackEngine::process(&task(AITaskType::IntentRecognition, "我要退款，太不满了")).unwrap();
+        assert_eq!(output["intent"], "complaint");
+        assert_eq!(output["intents"][1]["intent"], "refund");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_when_all_providers_unavailable() {
+        let manager = AIManager::new();
+        let mut config = manager.get_config().await;
+        // 指向不可达的外部服务
+        config.intent_recognition.model_type = "openai".to_string();
+        config.intent_recognition.api_key = "test-key".to_string();
+        config.intent_recognition.api_endpoint = "http://127.0.0.1:1/v1/chat/completions".to_string();
+        config.intent_recognition.timeout_seconds = 1;
+        manager.update_config(config).await.unwrap();
+        manager.start_processing().await.unwrap();
+
+        let intent_id = manager
+            .submit_task(task(AITaskType::IntentRecognition, "我要投诉这个产品"))
+            .await
+            .unwrap();
+        let intent = manager
+            .wait_for_result(&intent_id, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .expect("兜底引擎应返回结果");
+        assert_eq!(intent.result["source"], FALLBACK_SOURCE);
+        assert_eq!(intent.result["intent"], "complaint");
+        assert!(intent.confidence <= FALLBACK_CONFIDENCE);
+
+        let reply_id = manager
+            .submit_task(task(AITaskType::AutoReply, "请问什么时候发货"))
+            .await
+            .unwrap();
+        let reply = manager
+            .wait_for_result(&reply_id, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .expect("兜底引擎应返回结果");
+        assert_eq!(reply.result["source"], FALLBACK_SOURCE);
+        assert!(reply.result["reply"].as_str().is_some());
+        assert!(reply.confidence <= FALLBACK_CONFIDENCE);
+    }
+}
@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+use super::{AIResult, AITaskType};
+
+/// 最多保留的反馈条数，超出后丢弃最早的反馈
+pub const MAX_FEEDBACK_HISTORY: usize = 10_000;
+
+/// 客服对一次AI处理结果的评价，保存结果快照以便后续复盘
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIFeedback {
+    pub id: String,
+    pub result_id: String,
+    pub task_type: AITaskType,
+    pub user_id: String,
+    pub message_id: String,
+    pub helpful: bool,
+    pub comment: Option<String>,
+    pub result: serde_json::Value,
+    pub confidence: f32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 按任务类型汇总的满意度
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeedbackStats {
+    pub task_type: AITaskType,
+    pub total: usize,
+    pub helpful: usize,
+    pub helpful_rate: f32,
+}
+
+/// 内存中的反馈记录，同一结果重复评价时以最后一次为准
+pub struct FeedbackStore {
+    entries: VecDeque<AIFeedback>,
+    capacity: usize,
+}
+
+impl Default for FeedbackStore {
+    fn default() -> Self {
+        Self::new(MAX_FEEDBACK_HISTORY)
+    }
+}
+
+impl FeedbackStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn record(&mut self, result: &AIResult, helpful: bool, comment: Option<String>) -> AIFeedback {
+        self.entries.retain(|entry| entry.result_id != result.task_id);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+
+        let feedback = AIFeedback {
+            id: Uuid::new_v4().to_string(),
+            result_id: result.task_id.clone(),
+            task_type: result.task_type.clone(),
+            user_id: result.user_id.clone(),
+            message_id: result.message_id.clone(),
+            helpful,
+            comment,
+            result: result.result.clone(),
+            confidence: result.confidence,
+            created_at: Utc::now(),
+        };
+        self.entries.push_back(feedback.clone());
+        feedback
+    }
+
+    /// 各任务类型的好评率，按首次出现顺序返回
+    pub fn stats(&self) -> Vec<FeedbackStats> {
+        let mut stats: Vec<FeedbackStats> = Vec::new();
+        for entry in &self.entries {
+            let index = match stats.iter().position(|s| s.task_type == entry.task_type) {
+                Some(index) => index,
+                None => {
+                    stats.push(FeedbackStats {
+                        task_type: entry.task_type.clone(),
+                        total: 0,
+                        helpful: 0,
+                        helpful_rate: 0.0,
+                    });
+                    stats.len() - 1
+                }
+            };
+            stats[index].total += 1;
+            if entry.helpful {
+                stats[index].helpful += 1;
+            }
+        }
+        for s in &mut stats {
+            s.helpful_rate = s.helpful as f32 / s.total as f32;
+        }
+        stats
+    }
+
+    /// 被评为"没用"的案例，最新的在前
+    pub fn low_quality_cases(&self, task_type: Option<&AITaskType>, limit: usize) -> Vec<AIFeedback> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| !entry.helpful)
+            .filter(|entry| task_type.is_none_or(|t| &entry.task_type == t))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{AIManager, AITask};
+
+    async fn completed_task(manager: &AIManager, task_type: AITaskType) -> String {
+        let task = AITask::new(task_type, "kehu_001".to_string(), "msg_001".to_string(), serde_json::json!({}), 5);
+        let task_id = manager.submit_task(task).await.unwrap();
+        let mut queue = manager.queue.write().await;
+        queue.dequeue().await.unwrap();
+        queue
+            .complete_task(&task_id, serde_json::json!({ "reply": "您好", "confidence": 0.9 }))
+            .await
+            .unwrap();
+        task_id
+    }
+
+    #[tokio::test]
+    async fn test_helpful_rate_by_task_type() {
+        let manager = AIManager::new();
+        for helpful in [true, true, false, true] {
+            let result_id = completed_task(&manager, AITaskType::AutoReply).await;
+            manager.submit_ai_feedback(&result_id, helpful, None).await.unwrap();
+        }
+        let translation = completed_task(&manager, AITaskType::Translation).await;
+        manager
+            .submit_ai_feedback(&translation, true, Some("翻译准确".to_string()))
+            .await
+            .unwrap();
+        // 同一结果改评价，以最后一次为准
+        manager
+            .submit_ai_feedback(&translation, false, Some("术语翻错".to_string()))
+            .await
+            .unwrap();
+
+        let stats = manager.get_feedback_stats().await;
+        let auto_reply = stats.iter().find(|s| s.task_type == AITaskType::AutoReply).unwrap();
+        assert_eq!((auto_reply.total, auto_reply.helpful), (4, 3));
+        assert_eq!(auto_reply.helpful_rate, 0.75);
+        let translation_stats = stats.iter().find(|s| s.task_type == AITaskType::Translation).unwrap();
+        assert_eq!((translation_stats.total, translation_stats.helpful_rate), (1, 0.0));
+
+        let cases = manager
+            .get_low_quality_cases(Some(&AITaskType::Translation), 10)
+            .await;
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].comment.as_deref(), Some("术语翻错"));
+        assert_eq!(cases[0].result["reply"], "您好");
+        assert_eq!(manager.get_low_quality_cases(None, 10).await.len(), 2);
+
+        assert!(manager.submit_ai_feedback("missing", true, None).await.is_err());
+    }
+}
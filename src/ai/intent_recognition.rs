@@ -3,17 +3,30 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use super::{AIProcessor, AITask, AITaskType, config::AIConfig};
+use super::circuit_breaker::CircuitBreakers;
+
+const INTENT_SYSTEM_PROMPT: &str = "你是一个专业的意图识别助手，请准确分析用户的意图。";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IntentResult {
+    /// 置信度最高的主意图，兼容只处理单一意图的调用方
     pub intent: String,
     pub confidence: f32,
+    /// 识别出的全部意图，按置信度降序，第一项即主意图
+    #[serde(default)]
+    pub intents: Vec<IntentScore>,
     pub entities: Vec<Entity>,
     pub sentiment: Option<String>,
     pub language: String,
     pub original_text: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IntentScore {
+    pub intent: String,
+    pub confidence: f32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entity {
     pub name: String,
@@ -26,6 +39,7 @@ pub struct Entity {
 pub struct IntentProcessor {
     config: Arc<RwLock<AIConfig>>,
     http_client: reqwest::Client,
+    circuit_breakers: Arc<CircuitBreakers>,
 }
 
 // 确保IntentProcessor可以在线程间安全传递
@@ -37,9 +51,16 @@ impl IntentProcessor {
         Self {
             config,
             http_client: reqwest::Client::new(),
+            circuit_breakers: Arc::new(CircuitBreakers::default()),
         }
     }
 
+    /// 与其他处理器共用按服务地址的熔断状态
+    pub fn with_circuit_breakers(mut self, circuit_breakers: Arc<CircuitBreakers>) -> Self {
+        self.circuit_breakers = circuit_breakers;
+        self
+    }
+
     async fn preprocess_text(&self, text: &str) -> Result<String> {
         let config = self.config.read().await;
         let preprocessing = &config.intent_recognition.preprocessing;
@@ -68,17 +89,18 @@ impl IntentProcessor {
         Ok(processed)
     }
 
-    async fn detect_intent_openai(&self, text: &str) -> Result<IntentResult> {
-        let config = self.config.read().await;
+    async fn detect_intent_openai(&self, text: &str, model: &str, system_prompt: &str) -> Result<IntentResult> {
+        let config = self.config.read().await.clone();
         let intent_config = &config.intent_recognition;
         
         let prompt = format!(
-            "请分析以下文本的意图，并返回JSON格式的结果：\
+            "请分析以下文本的意图，一句话可能包含多个意图，并返回JSON格式的结果：\
             \n文本：{}\
             \n支持的意图类型：{}\
             \n返回格式：{{\
-            \n  \"intent\": \"意图名称\",\
+            \n  \"intent\": \"置信度最高的意图名称\",\
             \n  \"confidence\": 0.95,\
+            \n  \"intents\": [{{\"intent\": \"意图名称\", \"confidence\": 0.95}}],\
             \n  \"entities\": [],\
             \n  \"sentiment\": \"positive/negative/neutral\",\
             \n  \"language\": \"zh\"\
@@ -91,11 +113,11 @@ impl IntentProcessor {
         );
 
         let request_body = serde_json::json!({
-            "model": "gpt-3.5-turbo",
+            "model": model,
             "messages": [
                 {
                     "role": "system",
-                    "content": "你是一个专业的意图识别助手，请准确分析用户的意图。"
+                    "content": system_prompt
                 },
                 {
                     "role": "user",
@@ -106,13 +128,14 @@ impl IntentProcessor {
             "max_tokens": 500
         });
 
-        let response = self.http_client
+        let request = self.http_client
             .post(&intent_config.api_endpoint)
             .header("Authorization", format!("Bearer {}", intent_config.api_key))
             .header("Content-Type", "application/json")
             .json(&request_body)
-            .timeout(std::time::Duration::from_secs(intent_config.timeout_seconds))
-            .send()
+            .timeout(std::time::Duration::from_secs(intent_config.timeout_seconds));
+        let response = self.circuit_breakers
+            .send(&config, &intent_config.api_endpoint, request)
             .await?;
 
         if !response.status().is_success() {
@@ -126,9 +149,20 @@ impl IntentProcessor {
             .ok_or_else(|| anyhow::anyhow!("无法解析OpenAI响应"))?;
 
         // 尝试解析JSON响应
-        let intent_result: IntentResult = serde_json::from_str(content)
+        let mut intent_result: IntentResult = serde_json::from_str(content)
             .map_err(|e| anyhow::anyhow!("解析意图结果失败: {}", e))?;
 
+        // 模型只返回单一意图时补齐意图列表
+        if intent_result.intents.is_empty() && intent_result.intent != "unknown" {
+            intent_result.intents.push(IntentScore {
+                intent: intent_result.intent.clone(),
+                confidence: intent_result.confidence,
+            });
+        }
+        intent_result
+            .intents
+            .sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal));
+
         Ok(IntentResult {
             original_text: text.to_string(),
             ..intent_result
@@ -140,10 +174,9 @@ impl IntentProcessor {
         let intent_config = &config.intent_recognition;
         
         let processed_text = self.preprocess_text(text).await?;
-        let mut best_intent = "unknown".to_string();
-        let mut best_confidence = 0.0f32;
+        let mut scores: Vec<IntentScore> = Vec::new();
         
-        // 基于规则的意图识别
+        // 基于规则的意图识别，每个意图独立打分
         for custom_intent in &intent_config.custom_intents {
             let mut confidence = 0.0f32;
             
@@ -161,24 +194,36 @@ impl IntentProcessor {
                 }
             }
             
+            if confidence == 0.0 {
+                continue;
+            }
+
             // 应用置信度提升
             confidence += custom_intent.confidence_boost;
-            
-            if confidence > best_confidence {
-                best_confidence = confidence;
-                best_intent = custom_intent.name.clone();
-            }
-        }
-        
-        // 如果置信度低于阈值，标记为未知
-        if best_confidence < intent_config.confidence_threshold {
-            best_intent = "unknown".to_string();
-            best_confidence = 0.1;
+            scores.push(IntentScore {
+                intent: custom_intent.name.clone(),
+                confidence,
+            });
         }
+        scores.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal));
+
+        // 主意图须达到识别阈值，否则标记为未知；其余意图达到多意图阈值即保留
+        let intents: Vec<IntentScore> = match scores.first() {
+            Some(top) if top.confidence >= intent_config.confidence_threshold => scores
+                .into_iter()
+                .filter(|score| score.confidence >= intent_config.multi_intent_threshold)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let (best_intent, best_confidence) = match intents.first() {
+            Some(top) => (top.intent.clone(), top.confidence),
+            None => ("unknown".to_string(), 0.1),
+        };
         
         Ok(IntentResult {
             intent: best_intent,
             confidence: best_confidence,
+            intents,
             entities: vec![], // 简单规则不提取实体
             sentiment: None,
             language: "zh".to_string(),
@@ -294,7 +339,10 @@ impl AIProcessor for IntentProcessor {
         let intent_config = &config.intent_recognition;
         
         let mut result = if intent_config.model_type == "openai" && !intent_config.api_key.is_empty() {
-            self.detect_intent_openai(text).await
+            let model = config.model_for_task(task);
+            let system_prompt = config.system_prompt_for_task(task, INTENT_SYSTEM_PROMPT);
+            tracing::debug!("任务 {} 使用模型 {}", task.id, model);
+            self.detect_intent_openai(text, model, system_prompt).await
         } else {
             self.detect_intent_rule_based(text).await
         }?;
@@ -350,6 +398,39 @@ mod tests {
         assert_eq!(intent_result.language, "zh");
     }
 
+    #[tokio::test]
+    async fn test_sentence_with_two_intents() {
+        let config = Arc::new(RwLock::new(AIConfig::default()));
+        let processor = IntentProcessor::new(config);
+
+        let task = AITask::new(
+            AITaskType::IntentRecognition,
+            "user1".to_string(),
+            "msg1".to_string(),
+            serde_json::json!({ "text": "我要退款并投诉客服" }),
+            5,
+        );
+        let result: IntentResult = serde_json::from_value(processor.process(&task).await.unwrap()).unwrap();
+
+        let names: Vec<&str> = result.intents.iter().map(|i| i.intent.as_str()).collect();
+        assert_eq!(names, ["refund", "complaint"]);
+        assert!(result.intents[0].confidence > result.intents[1].confidence);
+        assert_eq!(result.intent, "refund");
+        assert_eq!(result.confidence, result.intents[0].confidence);
+
+        // 主意图不达阈值时不返回任何意图
+        let task = AITask::new(
+            AITaskType::IntentRecognition,
+            "user1".to_string(),
+            "msg2".to_string(),
+            serde_json::json!({ "text": "今天天气不错" }),
+            5,
+        );
+        let result: IntentResult = serde_json::from_value(processor.process(&task).await.unwrap()).unwrap();
+        assert_eq!(result.intent, "unknown");
+        assert!(result.intents.is_empty());
+    }
+
     #[tokio::test]
     async fn test_entity_extraction() {
         let config = Arc::new(RwLock::new(AIConfig::default()));
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// 中间结果通道容量，订阅方处理过慢时丢弃最早的中间结果，最终结果仍以任务结果为准
+const INTERIM_CHANNEL_CAPACITY: usize = 256;
+
+/// 流式任务处理过程中产生的中间结果，如分段语音识别每完成一段的转写
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InterimResult {
+    pub task_id: String,
+    /// 已完成的分段数（从1开始）
+    pub sequence: usize,
+    pub total: usize,
+    pub output: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 流式任务的中间结果：逐段发布给订阅方（按任务ID过滤），并保留每个任务最新的一条供状态查询；
+/// 任务结束后清除
+#[derive(Clone)]
+pub struct InterimResults {
+    sender: broadcast::Sender<InterimResult>,
+    latest: Arc<Mutex<HashMap<String, InterimResult>>>,
+}
+
+impl Default for InterimResults {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(INTERIM_CHANNEL_CAPACITY);
+        Self {
+            sender,
+            latest: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl InterimResults {
+    pub fn publish(&self, result: InterimResult) {
+        self.latest.lock().unwrap().insert(result.task_id.clone(), result.clone());
+        // 没有订阅方时发送失败，忽略即可
+        let _ = self.sender.send(result);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<InterimResult> {
+        self.sender.subscribe()
+    }
+
+    pub fn latest(&self, task_id: &str) -> Option<InterimResult> {
+        self.latest.lock().unwrap().get(task_id).cloned()
+    }
+
+    pub fn clear(&self, task_id: &str) {
+        self.latest.lock().unwrap().remove(task_id);
+    }
+}
@@ -1,24 +1,50 @@
 pub mod config;
+pub mod circuit_breaker;
 pub mod intent_recognition;
 pub mod translation;
 pub mod speech_recognition;
 pub mod queue;
+pub mod dedup;
+pub mod fallback;
+pub mod escalation;
+pub mod feedback;
+pub mod webhook;
+pub mod summarization;
+pub mod approval;
+pub mod experiment;
+pub mod clarification;
+pub mod interim;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use crate::feature_flags::{FeatureFlags, FlagContext};
+use crate::middleware::metrics::request_timer;
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode, DebounceEventResult};
+use crate::monitoring::metrics::{Histograms, AI_TASK_PROCESSING_MS};
+
+/// AI配置文件路径，启动时加载，修改后由后台任务自动重新加载
+pub const AI_CONFIG_PATH: &str = "config/ai_config.json";
+
+/// 配置文件防抖时间：编辑器保存时常连续写入多次，等文件稳定后再加载
+const CONFIG_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
 
 // AI处理任务类型
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum AITaskType {
     IntentRecognition,
     Translation,
     SpeechRecognition,
+    /// 分段语音识别，逐段推送中间结果
+    StreamingSpeechRecognition,
     SentimentAnalysis,
     AutoReply,
+    Summarization,
 }
 
 // AI处理任务状态
@@ -48,6 +74,9 @@ pub struct AITask {
     pub priority: u8,
     pub retry_count: u32,
     pub max_retries: u32,
+    /// 被高优任务抢占的次数
+    #[serde(default)]
+    pub preemptions: u32,
     pub metadata: std::collections::HashMap<String, String>,
 }
 
@@ -74,6 +103,7 @@ impl AITask {
             priority,
             retry_count: 0,
             max_retries: 3,
+            preemptions: 0,
             metadata: std::collections::HashMap::new(),
         }
     }
@@ -99,6 +129,13 @@ impl AITask {
         self.retry_count < self.max_retries
     }
 
+    /// 被抢占后回到待处理状态，输入和重试次数保持不变，稍后从头恢复处理
+    pub fn preempt(&mut self) {
+        self.preemptions += 1;
+        self.status = AITaskStatus::Pending;
+        self.started_at = None;
+    }
+
     pub fn retry(&mut self) {
         self.retry_count += 1;
         self.status = AITaskStatus::Pending;
@@ -137,22 +174,104 @@ pub struct AIManager {
     pub intent_processor: Arc<intent_recognition::IntentProcessor>,
     pub translation_processor: Arc<translation::TranslationProcessor>,
     pub speech_processor: Arc<speech_recognition::SpeechProcessor>,
+    pub streaming_speech_processor: Arc<speech_recognition::StreamingSpeechProcessor>,
+    pub summary_processor: Arc<summarization::SummaryProcessor>,
     pub config: Arc<RwLock<config::AIConfig>>,
+    /// 流式任务的中间结果
+    interim: interim::InterimResults,
+    /// 任务指纹结果存储（多实例共享时使用Redis）
+    result_store: Option<Arc<dyn dedup::ResultStore>>,
+    result_cache_hits: Arc<AtomicU64>,
+    /// 客服对AI结果的有用/没用评价
+    feedback: Arc<RwLock<feedback::FeedbackStore>>,
+    webhook: webhook::WebhookNotifier,
+    /// AI建议动作的人工确认闸门
+    action_gate: Arc<approval::ActionGate>,
+    /// A/B 实验各组的对比指标
+    experiments: Arc<experiment::ExperimentTracker>,
+    /// 各用户待澄清的自动回复问题
+    clarifications: Arc<clarification::ClarificationTracker>,
+    /// 功能开关，用于按用户灰度开启新能力
+    feature_flags: Option<Arc<FeatureFlags>>,
+    /// 任务处理耗时分布，由 /metrics 导出
+    latency_histograms: Arc<Histograms>,
 }
 
 impl AIManager {
     pub fn new() -> Self {
-        let config = Arc::new(RwLock::new(config::AIConfig::default()));
+        let default_config = config::AIConfig::default();
+        let queue = queue::AIQueue::new().with_max_concurrent_tasks(default_config.max_concurrent_tasks);
+        let config = Arc::new(RwLock::new(default_config));
+        // 各处理器共用熔断状态，摘要与意图识别调用同一服务时一并熔断
+        let circuit_breakers = Arc::new(circuit_breaker::CircuitBreakers::default());
+        let speech_processor = Arc::new(
+            speech_recognition::SpeechProcessor::new(config.clone()).with_circuit_breakers(circuit_breakers.clone()),
+        );
+        let interim = interim::InterimResults::default();
         
         Self {
-            queue: Arc::new(RwLock::new(queue::AIQueue::new())),
-            intent_processor: Arc::new(intent_recognition::IntentProcessor::new(config.clone())),
-            translation_processor: Arc::new(translation::TranslationProcessor::new(config.clone())),
-            speech_processor: Arc::new(speech_recognition::SpeechProcessor::new(config.clone())),
+            queue: Arc::new(RwLock::new(queue)),
+            intent_processor: Arc::new(
+                intent_recognition::IntentProcessor::new(config.clone()).with_circuit_breakers(circuit_breakers.clone()),
+            ),
+            translation_processor: Arc::new(
+                translation::TranslationProcessor::new(config.clone()).with_circuit_breakers(circuit_breakers.clone()),
+            ),
+            streaming_speech_processor: Arc::new(speech_recognition::StreamingSpeechProcessor::new(
+                speech_processor.clone(),
+                interim.clone(),
+            )),
+            speech_processor,
+            summary_processor: Arc::new(
+                summarization::SummaryProcessor::new(config.clone()).with_circuit_breakers(circuit_breakers),
+            ),
             config,
+            interim,
+            result_store: None,
+            result_cache_hits: Arc::new(AtomicU64::new(0)),
+            feedback: Arc::new(RwLock::new(feedback::FeedbackStore::default())),
+            webhook: webhook::WebhookNotifier::default(),
+            action_gate: Arc::new(approval::ActionGate::default()),
+            experiments: Arc::new(experiment::ExperimentTracker::default()),
+            clarifications: Arc::new(clarification::ClarificationTracker::default()),
+            feature_flags: None,
+            latency_histograms: Arc::new(Histograms::default()),
         }
     }
 
+    /// 接入执行AI建议动作的业务系统
+    #[allow(dead_code)]
+    pub fn with_action_executor(mut self, executor: Arc<dyn approval::ActionExecutor>) -> Self {
+        self.action_gate = Arc::new(approval::ActionGate::new(executor));
+        self
+    }
+
+    pub fn action_gate(&self) -> &Arc<approval::ActionGate> {
+        &self.action_gate
+    }
+
+    /// 启用任务结果复用：相同指纹的任务在有效期内直接返回已有结果
+    pub fn with_result_store(mut self, store: Arc<dyn dedup::ResultStore>) -> Self {
+        self.result_store = Some(store);
+        self
+    }
+
+    /// 接入功能开关：配置未开启多轮澄清时，可按开关对部分用户灰度开启
+    pub fn with_feature_flags(mut self, feature_flags: Arc<FeatureFlags>) -> Self {
+        self.feature_flags = Some(feature_flags);
+        self
+    }
+
+    /// 与WebSocket管理器、指标注册中心共用耗时直方图
+    pub fn with_latency_histograms(mut self, histograms: Arc<Histograms>) -> Self {
+        self.latency_histograms = histograms;
+        self
+    }
+
+    pub fn result_cache_hits(&self) -> u64 {
+        self.result_cache_hits.load(Ordering::Relaxed)
+    }
+
     pub async fn submit_task(&self, task: AITask) -> Result<String> {
         let task_id = task.id.clone();
         let mut queue = self.queue.write().await;
@@ -170,59 +289,100 @@ impl AIManager {
         queue.get_task_result(task_id).await
     }
 
-    pub async fn start_processing(&self) -> Result<()> {
-        let queue = self.queue.clone();
-        let intent_processor = self.intent_processor.clone();
-        let translation_processor = self.translation_processor.clone();
-        let speech_processor = self.speech_processor.clone();
-
-        tokio::spawn(async move {
-            loop {
-                let task = {
-                    let mut queue_lock = queue.write().await;
-                    match queue_lock.dequeue().await {
-                        Ok(Some(task)) => task,
-                        Ok(None) => {
-                            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                            continue;
-                        }
-                        Err(e) => {
-                            tracing::error!("队列处理错误: {}", e);
-                            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                            continue;
-                        }
-                    }
-                };
+    /// 订阅流式任务的中间结果，订阅方按任务ID过滤
+    pub fn subscribe_interim(&self) -> tokio::sync::broadcast::Receiver<interim::InterimResult> {
+        self.interim.subscribe()
+    }
 
-                let processor: Arc<dyn AIProcessor> = match task.task_type {
-                    AITaskType::IntentRecognition => intent_processor.clone(),
-                    AITaskType::Translation => translation_processor.clone(),
-                    AITaskType::SpeechRecognition => speech_processor.clone(),
-                    _ => {
-                        tracing::warn!("未支持的AI任务类型: {:?}", task.task_type);
-                        continue;
-                    }
-                };
+    /// 处理中的流式任务最新的中间结果
+    pub fn interim_result(&self, task_id: &str) -> Option<interim::InterimResult> {
+        self.interim.latest(task_id)
+    }
 
-                let task_id = task.id.clone();
-                let result = processor.process(&task).await;
+    /// 取消仍在排队的任务（如客户已断开，慢速翻译不必再做），已开始处理或已结束时返回 false
+    pub async fn cancel_task(&self, task_id: &str) -> Result<bool> {
+        let cancelled = self.queue.write().await.cancel_task(task_id).await?;
+        if cancelled {
+            tracing::info!("🤖 AI任务已取消: {}", task_id);
+        }
+        Ok(cancelled)
+    }
 
-                let mut queue_lock = queue.write().await;
-                match result {
-                    Ok(output) => {
-                        if let Err(e) = queue_lock.complete_task(&task_id, output).await {
-                            tracing::error!("完成任务失败: {}", e);
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("处理任务失败: {}", e);
-                        if let Err(e) = queue_lock.fail_task(&task_id, e.to_string()).await {
-                            tracing::error!("标记任务失败: {}", e);
+    /// 等待任务结束，任务失败、被取消或不存在时返回None
+    pub async fn wait_for_result(
+        &self,
+        task_id: &str,
+        timeout: std::time::Duration,
+    ) -> Result<Option<AIResult>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match self.get_task_status(task_id).await? {
+                Some(AITaskStatus::Completed) => return self.get_task_result(task_id).await,
+                Some(AITaskStatus::Pending) | Some(AITaskStatus::Processing) => {}
+                _ => return Ok(None),
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!("等待AI任务超时: {}", task_id));
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+    }
+
+    pub async fn start_processing(&self) -> Result<()> {
+        let queue = self.queue.clone();
+        let runner = TaskRunner {
+            queue: self.queue.clone(),
+            intent_processor: self.intent_processor.clone(),
+            translation_processor: self.translation_processor.clone(),
+            speech_processor: self.speech_processor.clone(),
+            streaming_speech_processor: self.streaming_speech_processor.clone(),
+            summary_processor: self.summary_processor.clone(),
+            config: self.config.clone(),
+            interim: self.interim.clone(),
+            result_store: self.result_store.clone(),
+            result_cache_hits: self.result_cache_hits.clone(),
+            webhook: self.webhook.clone(),
+            action_gate: self.action_gate.clone(),
+            experiments: self.experiments.clone(),
+            clarifications: self.clarifications.clone(),
+            feature_flags: self.feature_flags.clone(),
+            latency_histograms: self.latency_histograms.clone(),
+        };
+
+        // 固定数量的 worker 从队列取任务并就地执行，不再为每个任务单独 spawn；
+        // 队列同样限制并发数，高优任务可以抢占低优任务
+        let workers = self.config.read().await.max_concurrent_tasks.max(1);
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let runner = runner.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (task, preempt) = {
+                        let mut queue_lock = queue.write().await;
+                        match queue_lock.dequeue().await {
+                            Ok(Some(task)) => {
+                                let preempt = queue_lock.preemption_signal(&task.id).unwrap_or_default();
+                                (task, preempt)
+                            }
+                            Ok(None) => {
+                                drop(queue_lock);
+                                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                                continue;
+                            }
+                            Err(e) => {
+                                drop(queue_lock);
+                                tracing::error!("队列处理错误: {}", e);
+                                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                                continue;
+                            }
                         }
-                    }
+                    };
+
+                    runner.clone().run(task, preempt).await;
                 }
-            }
-        });
+            });
+        }
+        tracing::info!("🤖 AI任务处理已启动，worker 数: {}", workers);
 
         Ok(())
     }
@@ -238,9 +398,329 @@ impl AIManager {
         config_lock.clone()
     }
 
-    pub async fn get_statistics(&self) -> Result<serde_json::Value> {
+    /// 从文件重新加载配置，返回变更项；文件无法解析或校验不通过时保留当前配置
+    pub async fn reload_config(&self, path: &Path) -> Result<Vec<String>> {
+        let content = tokio::fs::read_to_string(path).await?;
+        let new_config: config::AIConfig = serde_json::from_str(&content)?;
+        new_config
+            .validate()
+            .map_err(|e| anyhow::anyhow!("AI配置校验失败: {}", e))?;
+
+        let mut config_lock = self.config.write().await;
+        let changes = config::diff_config(&config_lock, &new_config)?;
+        *config_lock = new_config;
+        Ok(changes)
+    }
+
+    /// 加载配置文件，随后用 notify 监听其所在目录，文件变化经防抖后自动重新加载
+    ///
+    /// 监听目录而不是文件本身：编辑器常以"写临时文件再改名"的方式保存，替换后原文件的监听会失效。
+    /// max_concurrent_tasks 只在启动时用于创建任务队列，热更新后需重启才能调整并发上限
+    pub async fn watch_config_file(self: Arc<Self>, path: PathBuf) {
+        if path.exists() {
+            self.apply_config_file(&path).await;
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut debouncer = match new_debouncer(CONFIG_DEBOUNCE, move |result: DebounceEventResult| {
+            let _ = tx.send(result);
+        }) {
+            Ok(debouncer) => debouncer,
+            Err(e) => {
+                tracing::error!("🤖 AI配置文件监听启动失败: {}", e);
+                return;
+            }
+        };
+        let watch_dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if let Err(e) = debouncer.watcher().watch(watch_dir, RecursiveMode::NonRecursive) {
+            tracing::error!("🤖 无法监听AI配置目录 {}: {}", watch_dir.display(), e);
+            return;
+        }
+
+        while let Some(result) = rx.recv().await {
+            match result {
+                Ok(events) if events.iter().any(|event| event.path.file_name() == path.file_name()) => {
+                    self.apply_config_file(&path).await;
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("🤖 AI配置文件监听出错: {}", e),
+            }
+        }
+    }
+
+    async fn apply_config_file(&self, path: &Path) {
+        let previous_max_tasks = self.config.read().await.max_concurrent_tasks;
+        match self.reload_config(path).await {
+            Ok(changes) if changes.is_empty() => {
+                tracing::info!("🤖 AI配置已重新加载，无变更: {}", path.display());
+            }
+            Ok(changes) => {
+                tracing::info!("🤖 AI配置已重新加载: {} 项变更", changes.len());
+                for change in changes {
+                    tracing::info!("🤖   {}", change);
+                }
+                if self.config.read().await.max_concurrent_tasks != previous_max_tasks {
+                    tracing::warn!("🤖 max_concurrent_tasks 的修改需重启后生效，运行中的任务队列不会调整并发上限");
+                }
+            }
+            Err(e) => tracing::error!("🤖 AI配置重新加载失败，保留当前配置: {}", e),
+        }
+    }
+
+    pub async fn get_statistics(&self) -> Result<queue::AIStatistics> {
         let queue = self.queue.read().await;
-        Ok(queue.get_statistics().await)
+        let mut stats = queue.get_statistics().await;
+        stats.result_cache_hits = self.result_cache_hits();
+        Ok(stats)
+    }
+
+    /// 记录客服对AI结果（result_id 即任务ID）的评价
+    pub async fn submit_ai_feedback(
+        &self,
+        result_id: &str,
+        helpful: bool,
+        comment: Option<String>,
+    ) -> Result<feedback::AIFeedback> {
+        let result = self
+            .get_task_result(result_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("AI结果不存在: {}", result_id))?;
+        let feedback = self.feedback.write().await.record(&result, helpful, comment);
+        // 实验任务的评价计入结果所属用户的实验分组
+        let config = self.config.read().await;
+        if let Some(group) = config.experiment.group_for(&result.task_type, &result.user_id) {
+            self.experiments.record_feedback(&config.experiment.name, group, helpful);
+        }
+        drop(config);
+        tracing::info!(
+            "🤖 收到AI结果反馈: result_id={}, type={:?}, helpful={}",
+            result_id,
+            feedback.task_type,
+            helpful
+        );
+        Ok(feedback)
+    }
+
+    pub async fn get_feedback_stats(&self) -> Vec<feedback::FeedbackStats> {
+        self.feedback.read().await.stats()
+    }
+
+    /// 当前 A/B 实验的配置与各组满意度、耗时、成本对比
+    pub async fn get_experiment_report(&self) -> serde_json::Value {
+        let experiment = self.config.read().await.experiment.clone();
+        serde_json::json!({
+            "enabled": experiment.enabled,
+            "name": experiment.name,
+            "treatment_percent": experiment.treatment_percent,
+            "task_types": experiment.task_types,
+            "treatment_model": experiment.treatment.model,
+            "groups": self.experiments.report(&experiment.name),
+        })
+    }
+
+    pub async fn get_low_quality_cases(
+        &self,
+        task_type: Option<&AITaskType>,
+        limit: usize,
+    ) -> Vec<feedback::AIFeedback> {
+        self.feedback.read().await.low_quality_cases(task_type, limit)
+    }
+}
+
+/// 执行单个AI任务的 worker
+#[derive(Clone)]
+struct TaskRunner {
+    queue: Arc<RwLock<queue::AIQueue>>,
+    intent_processor: Arc<intent_recognition::IntentProcessor>,
+    translation_processor: Arc<translation::TranslationProcessor>,
+    speech_processor: Arc<speech_recognition::SpeechProcessor>,
+    streaming_speech_processor: Arc<speech_recognition::StreamingSpeechProcessor>,
+    summary_processor: Arc<summarization::SummaryProcessor>,
+    config: Arc<RwLock<config::AIConfig>>,
+    interim: interim::InterimResults,
+    result_store: Option<Arc<dyn dedup::ResultStore>>,
+    result_cache_hits: Arc<AtomicU64>,
+    webhook: webhook::WebhookNotifier,
+    action_gate: Arc<approval::ActionGate>,
+    experiments: Arc<experiment::ExperimentTracker>,
+    clarifications: Arc<clarification::ClarificationTracker>,
+    feature_flags: Option<Arc<FeatureFlags>>,
+    latency_histograms: Arc<Histograms>,
+}
+
+impl TaskRunner {
+    async fn run(self, mut task: AITask, preempt: Arc<tokio::sync::Notify>) {
+        let processor: Option<Arc<dyn AIProcessor>> = match task.task_type {
+            AITaskType::IntentRecognition => Some(self.intent_processor.clone()),
+            AITaskType::Translation => Some(self.translation_processor.clone()),
+            AITaskType::SpeechRecognition => Some(self.speech_processor.clone()),
+            AITaskType::StreamingSpeechRecognition => Some(self.streaming_speech_processor.clone()),
+            AITaskType::Summarization => Some(self.summary_processor.clone()),
+            // 没有外部处理器的类型直接交给规则引擎
+            _ if fallback::FallbackEngine::supports(&task.task_type) => None,
+            _ => {
+                tracing::warn!("未支持的AI任务类型: {:?}", task.task_type);
+                return;
+            }
+        };
+
+        // 用户正在回答追问时，把补充内容与原问题合并后再处理
+        let clarification = match (&task.task_type, task.input_data["text"].as_str().map(str::to_string)) {
+            (AITaskType::AutoReply, Some(text)) => {
+                let config = self.config.read().await.clarification.clone();
+                if config.enabled || self.clarification_flag_enabled(&task.user_id) {
+                    let ttl = config.pending_ttl_seconds;
+                    let (text, rounds) = self.clarifications.merge_pending(&task.user_id, &text, ttl);
+                    task.input_data["text"] = serde_json::json!(text);
+                    Some((config, rounds))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        let task_id = task.id.clone();
+        let (ttl_seconds, fingerprint) = {
+            let config = self.config.read().await;
+            let ttl_seconds = config.result_cache_ttl_seconds;
+            let fingerprint = match &self.result_store {
+                Some(_) if ttl_seconds > 0 => Some(dedup::task_fingerprint(&task, &config)),
+                _ => None,
+            };
+            (ttl_seconds, fingerprint)
+        };
+
+        let cached = match (&self.result_store, &fingerprint) {
+            (Some(store), Some(fp)) => match store.get(fp).await {
+                Ok(cached) => cached,
+                Err(e) => {
+                    tracing::warn!("读取任务指纹缓存失败: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let result = match cached {
+            Some(output) => {
+                tracing::debug!("任务 {} 命中指纹缓存", task_id);
+                self.result_cache_hits.fetch_add(1, Ordering::Relaxed);
+                self.record_experiment_cache_hit(&task).await;
+                Ok(output)
+            }
+            None => {
+                let timer = request_timer(&self.latency_histograms, AI_TASK_PROCESSING_MS);
+                let result = match &processor {
+                    Some(processor) => tokio::select! {
+                        result = processor.process(&task) => result,
+                        _ = preempt.notified() => {
+                            // 外部调用没有中间状态，任务保留输入重新入队，恢复时从头处理
+                            if let Err(e) = self.queue.write().await.requeue_preempted(&task_id).await {
+                                tracing::error!("被抢占任务重新入队失败: {}", e);
+                            }
+                            return;
+                        }
+                    },
+                    None => Err(anyhow::anyhow!("没有可用的外部AI服务: {:?}", task.task_type)),
+                };
+                let elapsed = timer.finish();
+                if processor.is_some() {
+                    self.record_experiment_call(&task, elapsed, result.is_ok()).await;
+                }
+                let result = match result {
+                    Err(e) if fallback::FallbackEngine::supports(&task.task_type) => {
+                        tracing::warn!("外部AI服务不可用，任务 {} 使用规则引擎兜底: {}", task_id, e);
+                        fallback::FallbackEngine::process(&task)
+                    }
+                    other => other,
+                };
+                // 兜底结果不写入缓存，服务恢复后应重新调用外部AI
+                let cacheable = matches!(&result, Ok(output) if !fallback::FallbackEngine::is_fallback(output));
+                if let (true, Ok(output), Some(store), Some(fp)) = (cacheable, &result, &self.result_store, &fingerprint) {
+                    if let Err(e) = store.put(fp, output, ttl_seconds).await {
+                        tracing::warn!("写入任务指纹缓存失败: {}", e);
+                    }
+                }
+                result
+            }
+        };
+
+        // 信息不足时回复改为追问；建议动作经确认闸门登记，输出中替换为带确认状态的记录供前端展示
+        let result = match result {
+            Ok(mut output) => {
+                if let (Some((config, rounds)), Some(text)) = (&clarification, task.input_data["text"].as_str()) {
+                    self.clarifications.apply(config, &task.user_id, text, *rounds, &mut output);
+                }
+                if let Some(action) = approval::SuggestedAction::from_task_output(&task, &output) {
+                    let auto_execute_actions = self.config.read().await.approval.auto_execute_actions.clone();
+                    let action = self.action_gate.suggest(action, &auto_execute_actions).await;
+                    output[approval::SUGGESTED_ACTION_KEY] = serde_json::to_value(&action).unwrap_or_default();
+                }
+                Ok(output)
+            }
+            Err(e) => Err(e),
+        };
+
+        // 任务已有最终结果（或进入重试从头处理），不再保留中间结果
+        self.interim.clear(&task_id);
+        let mut queue_lock = self.queue.write().await;
+        let callback = match result {
+            Ok(output) => {
+                if let Err(e) = queue_lock.complete_task(&task_id, output).await {
+                    tracing::error!("完成任务失败: {}", e);
+                }
+                match queue_lock.get_task_result(&task_id).await {
+                    Ok(Some(result)) => Some(webhook::TaskCallback::completed(&task, result)),
+                    _ => None,
+                }
+            }
+            Err(e) => {
+                tracing::error!("处理任务失败: {}", e);
+                let error = e.to_string();
+                if let Err(e) = queue_lock.fail_task(&task_id, error.clone()).await {
+                    tracing::error!("标记任务失败: {}", e);
+                }
+                // 进入重试队列的任务尚未结束，只在最终失败时回调
+                match queue_lock.get_task_status(&task_id).await {
+                    Some(AITaskStatus::Failed) => Some(webhook::TaskCallback::failed(&task, error)),
+                    _ => None,
+                }
+            }
+        };
+        drop(queue_lock);
+
+        if let (Some(callback), Some(url)) = (callback, task.metadata.get(webhook::CALLBACK_URL_METADATA_KEY)) {
+            let config = self.config.read().await.webhook.clone();
+            if let Err(e) = self.webhook.deliver(url, &callback, &config).await {
+                tracing::error!("AI任务回调失败: {}", e);
+            }
+        }
+    }
+
+    // 配置未开启多轮澄清时，看功能开关是否对该用户开启
+    fn clarification_flag_enabled(&self, user_id: &str) -> bool {
+        self.feature_flags.as_ref().is_some_and(|flags| {
+            flags.is_enabled(clarification::CLARIFICATION_FLAG, &FlagContext::user(user_id))
+        })
+    }
+
+    // 实验启用时按任务用户的分组记录外部模型调用的耗时与成本，不在实验范围内的任务不计入
+    async fn record_experiment_call(&self, task: &AITask, latency: std::time::Duration, success: bool) {
+        let config = self.config.read().await;
+        if let Some(group) = config.experiment_group(task) {
+            let cost = config.experiment.cost_per_request(group);
+            self.experiments
+                .record_call(&config.experiment.name, group, latency.as_millis() as u64, cost, success);
+        }
+    }
+
+    // 指纹含实验分组，命中的一定是本组的结果；计入本组请求数，不计耗时和成本
+    async fn record_experiment_cache_hit(&self, task: &AITask) {
+        let config = self.config.read().await;
+        if let Some(group) = config.experiment_group(task) {
+            self.experiments.record_cache_hit(&config.experiment.name, group);
+        }
     }
 }
 
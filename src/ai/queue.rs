@@ -1,10 +1,18 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque, BinaryHeap};
+use std::collections::{HashMap, HashSet, VecDeque, BinaryHeap};
 use std::cmp::Ordering;
+use std::sync::Arc;
 use chrono::{DateTime, Utc};
+use tokio::sync::Notify;
 use super::{AITask, AITaskStatus, AIResult, AITaskType};
 
+/// 达到该优先级的任务视为高优任务，并发已满时可以抢占低优任务
+pub const PREEMPTIVE_PRIORITY: u8 = 8;
+
+/// 单个任务最多被抢占的次数，超过后不再被抢占，避免低优任务饿死
+pub const MAX_PREEMPTIONS_PER_TASK: u32 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueueMetrics {
     pub total_tasks: u64,
@@ -14,6 +22,33 @@ pub struct QueueMetrics {
     pub failed_tasks: u64,
     pub average_processing_time_ms: f64,
     pub tasks_per_type: HashMap<String, u64>,
+    #[serde(default)]
+    pub preempted_tasks: u64,
+    #[serde(default)]
+    pub cancelled_tasks: u64,
+    /// 各类型已完成的任务数
+    #[serde(default)]
+    pub completed_per_type: HashMap<String, u64>,
+    /// 各类型已完成任务的累计处理耗时
+    #[serde(default)]
+    pub processing_time_ms_per_type: HashMap<String, u64>,
+}
+
+/// AI 任务统计，供看板按能力绘制吞吐量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIStatistics {
+    pub total_tasks: u64,
+    /// 等待处理的任务数（含重试队列）
+    pub queue_depth: usize,
+    pub tasks_by_type: HashMap<String, u64>,
+    pub tasks_by_status: HashMap<String, u64>,
+    pub average_processing_time_ms: f64,
+    pub average_processing_time_ms_by_type: HashMap<String, f64>,
+    pub retry_queue_size: usize,
+    pub preempted_tasks: u64,
+    pub max_concurrent_tasks: usize,
+    pub utilization_rate: f64,
+    pub result_cache_hits: u64,
 }
 
 // 优先级任务包装器
@@ -26,7 +61,7 @@ struct PriorityTask {
 
 impl PartialEq for PriorityTask {
     fn eq(&self, other: &Self) -> bool {
-        self.priority == other.priority
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -40,9 +75,10 @@ impl PartialOrd for PriorityTask {
 
 impl Ord for PriorityTask {
     fn cmp(&self, other: &Self) -> Ordering {
-        // 高优先级数字表示高优先级，所以reverse
-        other.priority.cmp(&self.priority)
-            .then_with(|| self.created_at.cmp(&other.created_at))
+        // BinaryHeap为最大堆，最大的先出队：优先级数字大的排在前面，
+        // 同优先级时创建时间早的视为更大（Reverse），保证先进先出
+        (self.priority, std::cmp::Reverse(self.created_at))
+            .cmp(&(other.priority, std::cmp::Reverse(other.created_at)))
     }
 }
 
@@ -53,6 +89,10 @@ pub struct AIQueue {
     completed_tasks: HashMap<String, AIResult>,
     failed_tasks: HashMap<String, AITask>,
     retry_queue: VecDeque<AITask>,
+    /// 处理中任务的抢占信号，worker 收到通知后暂停任务并调用 requeue_preempted
+    preempt_signals: HashMap<String, Arc<Notify>>,
+    /// 已发出抢占通知、尚未让出的任务
+    preempting: HashSet<String>,
     metrics: QueueMetrics,
     max_concurrent_tasks: usize,
     max_completed_history: usize,
@@ -66,6 +106,8 @@ impl AIQueue {
             completed_tasks: HashMap::new(),
             failed_tasks: HashMap::new(),
             retry_queue: VecDeque::new(),
+            preempt_signals: HashMap::new(),
+            preempting: HashSet::new(),
             metrics: QueueMetrics {
                 total_tasks: 0,
                 pending_tasks: 0,
@@ -74,15 +116,25 @@ impl AIQueue {
                 failed_tasks: 0,
                 average_processing_time_ms: 0.0,
                 tasks_per_type: HashMap::new(),
+                preempted_tasks: 0,
+                cancelled_tasks: 0,
+                completed_per_type: HashMap::new(),
+                processing_time_ms_per_type: HashMap::new(),
             },
             max_concurrent_tasks: 10,
             max_completed_history: 1000,
         }
     }
 
+    pub fn with_max_concurrent_tasks(mut self, max_concurrent_tasks: usize) -> Self {
+        self.max_concurrent_tasks = max_concurrent_tasks.max(1);
+        self
+    }
+
     pub async fn enqueue(&mut self, task: AITask) -> Result<()> {
         let task_type = format!("{:?}", task.task_type);
-        
+        let priority = task.priority;
+
         self.pending_queue.push(PriorityTask {
             priority: task.priority,
             created_at: task.created_at,
@@ -94,9 +146,66 @@ impl AIQueue {
         *self.metrics.tasks_per_type.entry(task_type).or_insert(0) += 1;
 
         tracing::debug!("任务已入队: {}", self.pending_queue.len());
+        self.preempt_for(priority);
         Ok(())
     }
 
+    /// 高优任务到来且并发已满时，通知一个低优任务让出位置。
+    /// 优先选择优先级最低、最近开始（已完成工作最少）的任务；被抢占次数已达上限的任务不再参与
+    fn preempt_for(&mut self, priority: u8) {
+        if priority < PREEMPTIVE_PRIORITY || self.processing_tasks.len() < self.max_concurrent_tasks {
+            return;
+        }
+
+        let victim = self
+            .processing_tasks
+            .values()
+            .filter(|task| {
+                task.priority < PREEMPTIVE_PRIORITY
+                    && task.preemptions < MAX_PREEMPTIONS_PER_TASK
+                    && !self.preempting.contains(&task.id)
+            })
+            .min_by(|a, b| a.priority.cmp(&b.priority).then_with(|| b.started_at.cmp(&a.started_at)))
+            .map(|task| task.id.clone());
+
+        if let Some(victim) = victim {
+            if let Some(signal) = self.preempt_signals.get(&victim) {
+                signal.notify_one();
+            }
+            tracing::info!("🤖 高优任务(优先级 {}) 抢占低优任务: {}", priority, victim);
+            self.preempting.insert(victim);
+        }
+    }
+
+    /// 处理中任务的抢占信号
+    pub fn preemption_signal(&self, task_id: &str) -> Option<Arc<Notify>> {
+        self.preempt_signals.get(task_id).cloned()
+    }
+
+    /// 被抢占的任务保留原始创建时间重新入队，在同优先级任务中仍排在前面
+    pub async fn requeue_preempted(&mut self, task_id: &str) -> Result<()> {
+        self.preempting.remove(task_id);
+        self.preempt_signals.remove(task_id);
+        if let Some(mut task) = self.processing_tasks.remove(task_id) {
+            task.preempt();
+            self.pending_queue.push(PriorityTask {
+                priority: task.priority,
+                created_at: task.created_at,
+                task,
+            });
+            self.metrics.processing_tasks = self.metrics.processing_tasks.saturating_sub(1);
+            self.metrics.pending_tasks += 1;
+            self.metrics.preempted_tasks += 1;
+            tracing::debug!("任务被抢占，重新入队: {}", task_id);
+        }
+        Ok(())
+    }
+
+    fn release_slot(&mut self, task_id: &str) {
+        self.preempting.remove(task_id);
+        self.preempt_signals.remove(task_id);
+    }
+
     pub async fn dequeue(&mut self) -> Result<Option<AITask>> {
         // 检查并发限制
         if self.processing_tasks.len() >= self.max_concurrent_tasks {
@@ -107,6 +216,7 @@ impl AIQueue {
         if let Some(mut task) = self.retry_queue.pop_front() {
             task.start_processing();
             let task_id = task.id.clone();
+            self.preempt_signals.insert(task_id.clone(), Arc::new(Notify::new()));
             self.processing_tasks.insert(task_id, task.clone());
             self.metrics.processing_tasks += 1;
             return Ok(Some(task));
@@ -117,6 +227,7 @@ impl AIQueue {
             let mut task = priority_task.task;
             task.start_processing();
             let task_id = task.id.clone();
+            self.preempt_signals.insert(task_id.clone(), Arc::new(Notify::new()));
             self.processing_tasks.insert(task_id, task.clone());
             
             self.metrics.pending_tasks = self.metrics.pending_tasks.saturating_sub(1);
@@ -129,6 +240,7 @@ impl AIQueue {
     }
 
     pub async fn complete_task(&mut self, task_id: &str, output: serde_json::Value) -> Result<()> {
+        self.release_slot(task_id);
         if let Some(mut task) = self.processing_tasks.remove(task_id) {
             task.complete(output.clone());
             
@@ -136,13 +248,16 @@ impl AIQueue {
                 .signed_duration_since(task.started_at.unwrap_or_else(Utc::now))
                 .num_milliseconds() as u64;
 
+            // 处理结果带有置信度时使用结果值，否则使用默认置信度
+            let confidence = output["confidence"].as_f64().map(|c| c as f32).unwrap_or(0.8);
+
             let result = AIResult {
                 task_id: task_id.to_string(),
                 task_type: task.task_type.clone(),
                 user_id: task.user_id.clone(),
                 message_id: task.message_id.clone(),
                 result: output,
-                confidence: 0.8, // 默认置信度，实际应该从处理结果获取
+                confidence,
                 processing_time_ms: processing_time,
                 created_at: Utc::now(),
             };
@@ -165,6 +280,9 @@ impl AIQueue {
             
             // 更新平均处理时间
             self.update_average_processing_time(processing_time);
+            let task_type = format!("{:?}", task.task_type);
+            *self.metrics.completed_per_type.entry(task_type.clone()).or_insert(0) += 1;
+            *self.metrics.processing_time_ms_per_type.entry(task_type).or_insert(0) += processing_time;
             
             tracing::debug!("任务完成: {}", task_id);
         }
@@ -173,6 +291,7 @@ impl AIQueue {
     }
 
     pub async fn fail_task(&mut self, task_id: &str, error: String) -> Result<()> {
+        self.release_slot(task_id);
         if let Some(mut task) = self.processing_tasks.remove(task_id) {
             if task.can_retry() {
                 task.retry();
@@ -197,8 +316,9 @@ impl AIQueue {
             Some(AITaskStatus::Processing)
         } else if self.completed_tasks.contains_key(task_id) {
             Some(AITaskStatus::Completed)
-        } else if self.failed_tasks.contains_key(task_id) {
-            Some(AITaskStatus::Failed)
+        } else if let Some(task) = self.failed_tasks.get(task_id) {
+            // 取消的任务与失败任务存放在一起，按任务自身状态区分
+            Some(task.status.clone())
         } else if self.retry_queue.iter().any(|t| t.id == task_id) {
             Some(AITaskStatus::Pending)
         } else if self.pending_queue.iter().any(|pt| pt.task.id == task_id) {
@@ -212,42 +332,59 @@ impl AIQueue {
         Ok(self.completed_tasks.get(task_id).cloned())
     }
 
-    pub async fn get_statistics(&self) -> serde_json::Value {
-        serde_json::json!({
-            "total_tasks": self.metrics.total_tasks,
-            "pending_tasks": self.metrics.pending_tasks,
-            "processing_tasks": self.metrics.processing_tasks,
-            "completed_tasks": self.metrics.completed_tasks,
-            "failed_tasks": self.metrics.failed_tasks,
-            "retry_queue_size": self.retry_queue.len(),
-            "average_processing_time_ms": self.metrics.average_processing_time_ms,
-            "tasks_per_type": self.metrics.tasks_per_type,
-            "queue_health": {
-                "max_concurrent_tasks": self.max_concurrent_tasks,
-                "current_concurrent_tasks": self.processing_tasks.len(),
-                "utilization_rate": self.processing_tasks.len() as f64 / self.max_concurrent_tasks as f64,
-            }
-        })
+    pub async fn get_statistics(&self) -> AIStatistics {
+        let tasks_by_status = [
+            (AITaskStatus::Pending, (self.pending_queue.len() + self.retry_queue.len()) as u64),
+            (AITaskStatus::Processing, self.processing_tasks.len() as u64),
+            (AITaskStatus::Completed, self.metrics.completed_tasks),
+            (AITaskStatus::Failed, self.metrics.failed_tasks),
+            (AITaskStatus::Cancelled, self.metrics.cancelled_tasks),
+        ]
+        .into_iter()
+        .map(|(status, count)| (format!("{:?}", status), count))
+        .collect();
+
+        let average_processing_time_ms_by_type = self
+            .metrics
+            .completed_per_type
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(task_type, count)| {
+                let total = self.metrics.processing_time_ms_per_type.get(task_type).copied().unwrap_or(0);
+                (task_type.clone(), total as f64 / *count as f64)
+            })
+            .collect();
+
+        AIStatistics {
+            total_tasks: self.metrics.total_tasks,
+            queue_depth: self.pending_queue.len() + self.retry_queue.len(),
+            tasks_by_type: self.metrics.tasks_per_type.clone(),
+            tasks_by_status,
+            average_processing_time_ms: self.metrics.average_processing_time_ms,
+            average_processing_time_ms_by_type,
+            retry_queue_size: self.retry_queue.len(),
+            preempted_tasks: self.metrics.preempted_tasks,
+            max_concurrent_tasks: self.max_concurrent_tasks,
+            utilization_rate: self.processing_tasks.len() as f64 / self.max_concurrent_tasks as f64,
+            result_cache_hits: 0,
+        }
     }
 
+    /// 取消尚未开始处理的任务；处理中、已结束或不存在的任务返回 false
     pub async fn cancel_task(&mut self, task_id: &str) -> Result<bool> {
-        // 从处理中任务取消
-        if let Some(mut task) = self.processing_tasks.remove(task_id) {
-            task.status = AITaskStatus::Cancelled;
-            self.failed_tasks.insert(task_id.to_string(), task);
-            self.metrics.processing_tasks = self.metrics.processing_tasks.saturating_sub(1);
-            return Ok(true);
-        }
-
         // 从重试队列取消
         if let Some(pos) = self.retry_queue.iter().position(|t| t.id == task_id) {
             let mut task = self.retry_queue.remove(pos).unwrap();
             task.status = AITaskStatus::Cancelled;
             self.failed_tasks.insert(task_id.to_string(), task);
+            self.metrics.cancelled_tasks += 1;
             return Ok(true);
         }
 
         // 从待处理队列取消（需要重建堆）
+        if !self.pending_queue.iter().any(|pt| pt.task.id == task_id) {
+            return Ok(false);
+        }
         let tasks: Vec<PriorityTask> = self.pending_queue.drain().collect();
         let mut found = false;
         
@@ -257,6 +394,7 @@ impl AIQueue {
                 task.status = AITaskStatus::Cancelled;
                 self.failed_tasks.insert(task_id.to_string(), task);
                 self.metrics.pending_tasks = self.metrics.pending_tasks.saturating_sub(1);
+                self.metrics.cancelled_tasks += 1;
                 found = true;
             } else {
                 self.pending_queue.push(pt);
@@ -349,4 +487,174 @@ impl Default for AIQueue {
     fn default() -> Self {
         Self::new()
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, priority: u8) -> AITask {
+        let mut task = AITask::new(
+            AITaskType::IntentRecognition,
+            "kehu_001".to_string(),
+            format!("msg_{}", id),
+            serde_json::json!({ "text": id }),
+            priority,
+        );
+        task.id = id.to_string();
+        task
+    }
+
+    fn task_at(id: &str, priority: u8, offset_ms: i64) -> AITask {
+        let mut task = task(id, priority);
+        task.created_at += chrono::Duration::milliseconds(offset_ms);
+        task
+    }
+
+    async fn assert_notified(signal: &Notify) {
+        tokio::time::timeout(std::time::Duration::from_millis(100), signal.notified())
+            .await
+            .expect("低优任务应收到抢占通知");
+    }
+
+    #[tokio::test]
+    async fn test_dequeue_order_by_priority_then_fifo() {
+        let mut queue = AIQueue::new().with_max_concurrent_tasks(10);
+        // 入队顺序与创建时间刻意打乱
+        queue.enqueue(task_at("p5_late", 5, 30)).await.unwrap();
+        queue.enqueue(task_at("p1", 1, 0)).await.unwrap();
+        queue.enqueue(task_at("p9", 9, 40)).await.unwrap();
+        queue.enqueue(task_at("p5_early", 5, 10)).await.unwrap();
+        queue.enqueue(task_at("p5_mid", 5, 20)).await.unwrap();
+
+        let mut order = Vec::new();
+        while let Some(task) = queue.dequeue().await.unwrap() {
+            order.push(task.id);
+        }
+        assert_eq!(order, vec!["p9", "p5_early", "p5_mid", "p5_late", "p1"]);
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_preempts_low_and_low_resumes() {
+        let mut queue = AIQueue::new().with_max_concurrent_tasks(1);
+        queue.enqueue(task("low", 3)).await.unwrap();
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "low");
+        let signal = queue.preemption_signal("low").unwrap();
+
+        // 普通任务不抢占，只排队等待
+        queue.enqueue(task("normal", 5)).await.unwrap();
+        assert!(queue.preempting.is_empty());
+
+        queue.enqueue(task("vip", 9)).await.unwrap();
+        assert_notified(&signal).await;
+        queue.requeue_preempted("low").await.unwrap();
+        assert_eq!(queue.get_task_status("low").await, Some(AITaskStatus::Pending));
+
+        let vip = queue.dequeue().await.unwrap().unwrap();
+        assert_eq!(vip.id, "vip");
+        queue.complete_task("vip", serde_json::json!({})).await.unwrap();
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "normal");
+        queue.complete_task("normal", serde_json::json!({})).await.unwrap();
+
+        // 被抢占的任务稍后恢复处理并正常完成
+        let resumed = queue.dequeue().await.unwrap().unwrap();
+        assert_eq!(resumed.id, "low");
+        assert_eq!(resumed.preemptions, 1);
+        assert_eq!(resumed.input_data, serde_json::json!({ "text": "low" }));
+        queue.complete_task("low", serde_json::json!({ "intent": "inquiry" })).await.unwrap();
+        assert_eq!(queue.get_task_status("low").await, Some(AITaskStatus::Completed));
+        assert_eq!(queue.metrics.preempted_tasks, 1);
+    }
+
+    #[tokio::test]
+    async fn test_preemption_limit_prevents_starvation() {
+        let mut queue = AIQueue::new().with_max_concurrent_tasks(1);
+        queue.enqueue(task("low", 1)).await.unwrap();
+
+        for round in 0..MAX_PREEMPTIONS_PER_TASK {
+            assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "low");
+            let signal = queue.preemption_signal("low").unwrap();
+            let vip = format!("vip_{}", round);
+            queue.enqueue(task(&vip, 9)).await.unwrap();
+            assert_notified(&signal).await;
+            queue.requeue_preempted("low").await.unwrap();
+            assert_eq!(queue.dequeue().await.unwrap().unwrap().id, vip);
+            queue.complete_task(&vip, serde_json::json!({})).await.unwrap();
+        }
+
+        // 达到抢占上限后，新的高优任务只能排队等待
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "low");
+        queue.enqueue(task("vip_last", 9)).await.unwrap();
+        assert!(queue.preempting.is_empty());
+        assert!(queue.dequeue().await.unwrap().is_none());
+
+        queue.complete_task("low", serde_json::json!({})).await.unwrap();
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "vip_last");
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_does_not_preempt_when_slots_free() {
+        let mut queue = AIQueue::new().with_max_concurrent_tasks(2);
+        queue.enqueue(task("low", 1)).await.unwrap();
+        queue.dequeue().await.unwrap();
+        queue.enqueue(task("vip", 9)).await.unwrap();
+        assert!(queue.preempting.is_empty());
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "vip");
+    }
+
+    #[tokio::test]
+    async fn test_statistics_break_down_by_type_and_status() {
+        let mut queue = AIQueue::new().with_max_concurrent_tasks(1);
+        for (id, elapsed_ms) in [("a", 100), ("b", 300)] {
+            queue.enqueue(task(id, 5)).await.unwrap();
+            queue.dequeue().await.unwrap();
+            queue.processing_tasks.get_mut(id).unwrap().started_at =
+                Some(Utc::now() - chrono::Duration::milliseconds(elapsed_ms));
+            queue.complete_task(id, serde_json::json!({})).await.unwrap();
+        }
+
+        let mut translation = task("t", 5);
+        translation.task_type = AITaskType::Translation;
+        queue.enqueue(translation).await.unwrap();
+        queue.enqueue(task("c", 5)).await.unwrap();
+        queue.cancel_task("c").await.unwrap();
+
+        let stats = queue.get_statistics().await;
+        assert_eq!(stats.total_tasks, 4);
+        assert_eq!(stats.queue_depth, 1);
+        assert_eq!(stats.tasks_by_type["IntentRecognition"], 3);
+        assert_eq!(stats.tasks_by_type["Translation"], 1);
+        assert_eq!(stats.tasks_by_status["Pending"], 1);
+        assert_eq!(stats.tasks_by_status["Completed"], 2);
+        assert_eq!(stats.tasks_by_status["Cancelled"], 1);
+        assert_eq!(stats.tasks_by_status["Failed"], 0);
+
+        let intent_avg = stats.average_processing_time_ms_by_type["IntentRecognition"];
+        assert!((200.0..250.0).contains(&intent_avg), "平均耗时: {}", intent_avg);
+        assert!(!stats.average_processing_time_ms_by_type.contains_key("Translation"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_only_pending_tasks() {
+        let mut queue = AIQueue::new().with_max_concurrent_tasks(1);
+        queue.enqueue(task("running", 5)).await.unwrap();
+        queue.enqueue(task("waiting", 5)).await.unwrap();
+        queue.enqueue(task("other", 5)).await.unwrap();
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "running");
+
+        // 排队中的任务可以取消，取消后不会再被取出处理
+        assert!(queue.cancel_task("waiting").await.unwrap());
+        assert_eq!(queue.get_task_status("waiting").await, Some(AITaskStatus::Cancelled));
+        assert!(!queue.cancel_task("waiting").await.unwrap());
+
+        // 处理中、已完成和不存在的任务不能取消
+        assert!(!queue.cancel_task("running").await.unwrap());
+        assert_eq!(queue.get_task_status("running").await, Some(AITaskStatus::Processing));
+        queue.complete_task("running", serde_json::json!({})).await.unwrap();
+        assert!(!queue.cancel_task("running").await.unwrap());
+        assert!(!queue.cancel_task("missing").await.unwrap());
+
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().id, "other");
+        assert_eq!(queue.metrics.cancelled_tasks, 1);
+        assert_eq!(queue.metrics.pending_tasks, 0);
+    }
+}
@@ -3,7 +3,15 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::Utc;
 use super::{AIProcessor, AITask, AITaskType, config::AIConfig};
+use super::circuit_breaker::CircuitBreakers;
+use super::interim::{InterimResult, InterimResults};
+
+/// 标准 PCM WAV 文件头长度（RIFF + fmt + data 头），切分后的每段都使用该文件头
+const WAV_HEADER_LEN: usize = 44;
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpeechRecognitionResult {
@@ -38,6 +46,7 @@ pub struct SpeakerSegment {
 pub struct SpeechProcessor {
     config: Arc<RwLock<AIConfig>>,
     http_client: reqwest::Client,
+    circuit_breakers: Arc<CircuitBreakers>,
 }
 
 // 确保SpeechProcessor可以在线程间安全传递
@@ -49,21 +58,29 @@ impl SpeechProcessor {
         Self {
             config,
             http_client: reqwest::Client::new(),
+            circuit_breakers: Arc::new(CircuitBreakers::default()),
         }
     }
 
+    /// 与其他处理器共用按服务地址的熔断状态
+    pub fn with_circuit_breakers(mut self, circuit_breakers: Arc<CircuitBreakers>) -> Self {
+        self.circuit_breakers = circuit_breakers;
+        self
+    }
+
     async fn validate_audio_file(&self, file_path: &str) -> Result<AudioMetadata> {
         let config = self.config.read().await;
         let speech_config = &config.speech_recognition;
+        let path = resolve_audio_path(&speech_config.audio_dir, file_path)?;
         
-        let metadata = std::fs::metadata(file_path)?;
+        let metadata = std::fs::metadata(&path)?;
         let file_size = metadata.len();
         
         if file_size > speech_config.max_file_size_bytes {
             return Err(anyhow::anyhow!("音频文件大小超过限制"));
         }
         
-        let extension = std::path::Path::new(file_path)
+        let extension = path
             .extension()
             .and_then(|ext| ext.to_str())
             .unwrap_or("");
@@ -73,7 +90,7 @@ impl SpeechProcessor {
         }
         
         // 读取音频文件基本信息
-        let audio_data = std::fs::read(file_path)?;
+        let audio_data = std::fs::read(&path)?;
         let duration_ms = self.estimate_audio_duration(&audio_data, extension).await?;
         
         if duration_ms > speech_config.max_audio_duration_seconds * 1000 {
@@ -85,6 +102,7 @@ impl SpeechProcessor {
             duration_ms,
             format: extension.to_string(),
             sample_rate: 16000, // 默认采样率
+            path,
         })
     }
 
@@ -94,7 +112,7 @@ impl SpeechProcessor {
     }
 
     async fn recognize_speech_azure(&self, audio_data: &[u8], language: &str, format: &str) -> Result<SpeechRecognitionResult> {
-        let config = self.config.read().await;
+        let config = self.config.read().await.clone();
         let speech_config = &config.speech_recognition;
         
         let url = format!("{}/speech/recognition/conversation/cognitiveservices/v1?language={}&format=detailed", 
@@ -107,12 +125,13 @@ impl SpeechProcessor {
             _ => "audio/wav",
         };
         
-        let response = self.http_client
+        let request = self.http_client
             .post(&url)
             .header("Ocp-Apim-Subscription-Key", &speech_config.api_key)
             .header("Content-Type", content_type)
-            .body(audio_data.to_vec())
-            .send()
+            .body(audio_data.to_vec());
+        let response = self.circuit_breakers
+            .send(&config, &speech_config.api_endpoint, request)
             .await?;
         
         if !response.status().is_success() {
@@ -166,7 +185,7 @@ impl SpeechProcessor {
     }
 
     async fn recognize_speech_google(&self, audio_data: &[u8], language: &str, format: &str) -> Result<SpeechRecognitionResult> {
-        let config = self.config.read().await;
+        let config = self.config.read().await.clone();
         let speech_config = &config.speech_recognition;
         
         let audio_base64 = STANDARD.encode(audio_data);
@@ -193,12 +212,13 @@ impl SpeechProcessor {
             }
         });
         
-        let response = self.http_client
+        let request = self.http_client
             .post(&speech_config.api_endpoint)
             .header("Authorization", format!("Bearer {}", speech_config.api_key))
             .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
+            .json(&request_body);
+        let response = self.circuit_breakers
+            .send(&config, &speech_config.api_endpoint, request)
             .await?;
         
         if !response.status().is_success() {
@@ -252,7 +272,11 @@ impl SpeechProcessor {
             language: language.to_string(),
             duration_ms: word_timestamps.last().map(|w| w.end_time_ms).unwrap_or(0),
             word_timestamps,
-            speaker_segments: vec![], // 需要额外解析
+            speaker_segments: if speech_config.enable_speaker_diarization {
+                Self::parse_google_speaker_segments(&response_body, language, confidence)
+            } else {
+                vec![]
+            },
             provider: "google".to_string(),
             audio_format: format.to_string(),
             sample_rate: 16000,
@@ -260,7 +284,7 @@ impl SpeechProcessor {
     }
 
     async fn recognize_speech_baidu(&self, audio_data: &[u8], language: &str, format: &str) -> Result<SpeechRecognitionResult> {
-        let config = self.config.read().await;
+        let config = self.config.read().await.clone();
         let speech_config = &config.speech_recognition;
         
         let audio_base64 = STANDARD.encode(audio_data);
@@ -275,11 +299,12 @@ impl SpeechProcessor {
             "len": audio_data.len()
         });
         
-        let response = self.http_client
+        let request = self.http_client
             .post(&speech_config.api_endpoint)
             .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
+            .json(&request_body);
+        let response = self.circuit_breakers
+            .send(&config, &speech_config.api_endpoint, request)
             .await?;
         
         if !response.status().is_success() {
@@ -350,13 +375,136 @@ impl SpeechProcessor {
         0
     }
 
+    /// 按speakerTag切分Google识别结果；开启diarization时最后一个result包含全部带说话人标签的词
+    fn parse_google_speaker_segments(
+        response_body: &serde_json::Value,
+        language: &str,
+        confidence: f32,
+    ) -> Vec<SpeakerSegment> {
+        let words = match response_body["results"]
+            .as_array()
+            .and_then(|results| results.last())
+            .and_then(|result| result["alternatives"][0]["words"].as_array())
+        {
+            Some(words) => words,
+            None => return vec![],
+        };
+        let separator = text_separator(language);
+
+        let mut segments: Vec<SpeakerSegment> = Vec::new();
+        for word in words {
+            let (text, speaker_tag) = match (word["word"].as_str(), word["speakerTag"].as_u64()) {
+                (Some(text), Some(tag)) if tag > 0 => (text, tag),
+                _ => continue,
+            };
+            let speaker_id = format!("speaker_{}", speaker_tag);
+            let start_time_ms = Self::parse_google_timestamp(word["startTime"].as_str().unwrap_or(""));
+            let end_time_ms = Self::parse_google_timestamp(word["endTime"].as_str().unwrap_or(""));
+
+            match segments.last_mut() {
+                Some(segment) if segment.speaker_id == speaker_id => {
+                    segment.end_time_ms = end_time_ms;
+                    segment.text.push_str(separator);
+                    segment.text.push_str(text);
+                }
+                _ => segments.push(SpeakerSegment {
+                    speaker_id,
+                    start_time_ms,
+                    end_time_ms,
+                    text: text.to_string(),
+                    confidence,
+                }),
+            }
+        }
+        segments
+    }
+
+    /// 按配置的服务商识别一段音频，不做后处理
+    async fn recognize(&self, audio_data: &[u8], language: &str, format: &str) -> Result<SpeechRecognitionResult> {
+        let provider = self.config.read().await.speech_recognition.service_provider.clone();
+        match provider.as_str() {
+            "azure" => self.recognize_speech_azure(audio_data, language, format).await,
+            "google" => self.recognize_speech_google(audio_data, language, format).await,
+            "baidu" => self.recognize_speech_baidu(audio_data, language, format).await,
+            _ => self.recognize_speech_local(audio_data, language, format).await,
+        }
+    }
+
+    /// 分段识别：输入为客户端分段上传的 chunk_paths，或 audio_file_path（WAV 按配置时长切分，其他格式整段识别）。
+    /// 每识别完一段调用 on_chunk(已完成段数, 总段数, 本段结果, 目前为止的转写)，最后合并为完整结果
+    pub async fn process_streaming<F>(&self, task: &AITask, mut on_chunk: F) -> Result<serde_json::Value>
+    where
+        F: FnMut(usize, usize, &SpeechRecognitionResult, &str),
+    {
+        let (language, chunk_ms) = {
+            let config = self.config.read().await;
+            let speech_config = &config.speech_recognition;
+            let language = task.input_data["language"]
+                .as_str()
+                .unwrap_or(&speech_config.default_language)
+                .to_string();
+            (language, speech_config.streaming_chunk_seconds.max(1) * 1000)
+        };
+
+        let mut chunks = Vec::new();
+        if let Some(paths) = task.input_data["chunk_paths"].as_array() {
+            for path in paths {
+                let path = path.as_str().ok_or_else(|| anyhow::anyhow!("音频分段路径必须是字符串"))?;
+                let metadata = self.validate_audio_file(path).await?;
+                chunks.push((std::fs::read(&metadata.path)?, metadata.format));
+            }
+        } else {
+            let audio_file_path = task.input_data["audio_file_path"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("缺少音频文件路径"))?;
+            let metadata = self.validate_audio_file(audio_file_path).await?;
+            let audio_data = std::fs::read(&metadata.path)?;
+            let split = match metadata.format.to_lowercase().as_str() {
+                "wav" => split_wav(&audio_data, chunk_ms),
+                _ => None,
+            };
+            match split {
+                Some(parts) => chunks.extend(parts.into_iter().map(|part| (part, metadata.format.clone()))),
+                None => chunks.push((audio_data, metadata.format)),
+            }
+        }
+        if chunks.is_empty() {
+            return Err(anyhow::anyhow!("没有可识别的音频分段"));
+        }
+
+        let total = chunks.len();
+        let separator = text_separator(&language);
+        let mut results: Vec<SpeechRecognitionResult> = Vec::with_capacity(total);
+        let mut partial_text = String::new();
+        for (index, (audio_data, format)) in chunks.iter().enumerate() {
+            let mut result = self.recognize(audio_data, &language, format).await?;
+            // 部分服务商不返回时长，WAV 分段按数据长度计算，保证后续分段的时间戳偏移正确
+            if result.duration_ms == 0 {
+                result.duration_ms = wav_duration_ms(audio_data).unwrap_or(0);
+            }
+            if !result.text.is_empty() {
+                if !partial_text.is_empty() {
+                    partial_text.push_str(separator);
+                }
+                partial_text.push_str(&result.text);
+            }
+            on_chunk(index + 1, total, &result, &partial_text);
+            results.push(result);
+        }
+
+        let consolidated = self.post_process_result(consolidate(results, &language)?).await?;
+        let mut output = serde_json::to_value(consolidated)?;
+        output["chunks"] = serde_json::json!(total);
+        Ok(output)
+    }
+
     async fn post_process_result(&self, mut result: SpeechRecognitionResult) -> Result<SpeechRecognitionResult> {
         let config = self.config.read().await;
         let speech_config = &config.speech_recognition;
         
         // 应用置信度阈值过滤
         if result.confidence < speech_config.confidence_threshold {
-            result.text = "[低置信度] ".to_string() + &result.text;
+            result.text = format!("[低置信度] {}", result.text);
         }
         
         // 过滤低置信度的单词
@@ -366,6 +514,164 @@ impl SpeechProcessor {
     }
 }
 
+// 中日文分段之间不加空格
+fn text_separator(language: &str) -> &'static str {
+    if language.starts_with("zh") || language.starts_with("ja") { "" } else { " " }
+}
+
+// 音频路径必须位于语音存储目录内，解析符号链接和 .. 之后再比较，避免任务参数读取任意文件
+fn resolve_audio_path(audio_dir: &str, file_path: &str) -> Result<std::path::PathBuf> {
+    let audio_dir = std::fs::canonicalize(audio_dir)
+        .map_err(|e| anyhow::anyhow!("语音存储目录不可用: {}", e))?;
+    let path = std::fs::canonicalize(file_path).map_err(|_| anyhow::anyhow!("音频文件不存在"))?;
+    if !path.starts_with(&audio_dir) {
+        return Err(anyhow::anyhow!("音频文件不在语音存储目录内"));
+    }
+    Ok(path)
+}
+
+/// 按时长切分 PCM WAV，每段使用标准44字节文件头，可单独识别；不是 PCM WAV 时返回 None
+pub fn split_wav(data: &[u8], chunk_ms: u64) -> Option<Vec<Vec<u8>>> {
+    let wav = parse_wav(data)?;
+    let byte_rate = wav.byte_rate() as u64;
+    let block_align = wav.block_align();
+    let chunk_len = ((byte_rate * chunk_ms / 1000) as usize / block_align).max(1) * block_align;
+
+    let chunks = wav
+        .audio
+        .chunks(chunk_len)
+        .map(|samples| {
+            let mut chunk = wav.header(samples.len());
+            chunk.extend_from_slice(samples);
+            chunk
+        })
+        .collect();
+    Some(chunks)
+}
+
+fn wav_duration_ms(data: &[u8]) -> Option<u64> {
+    let wav = parse_wav(data)?;
+    Some(wav.audio.len() as u64 * 1000 / wav.byte_rate() as u64)
+}
+
+// WAV 的 fmt 块（前16字节的 PCM 参数）和 data 块内容
+struct Wav<'a> {
+    format: [u8; 16],
+    audio: &'a [u8],
+}
+
+impl Wav<'_> {
+    fn byte_rate(&self) -> u32 {
+        u32::from_le_bytes([self.format[8], self.format[9], self.format[10], self.format[11]])
+    }
+
+    fn block_align(&self) -> usize {
+        u16::from_le_bytes([self.format[12], self.format[13]]) as usize
+    }
+
+    // 标准44字节文件头，格式标记统一为 PCM
+    fn header(&self, audio_len: usize) -> Vec<u8> {
+        let mut header = Vec::with_capacity(WAV_HEADER_LEN + audio_len);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&((WAV_HEADER_LEN - 8 + audio_len) as u32).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        header.extend_from_slice(&self.format[2..]);
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&(audio_len as u32).to_le_bytes());
+        header
+    }
+}
+
+// 按 RIFF 块遍历查找 fmt 和 data，跳过 LIST/fact 等附加块；只接受 PCM（含 WAVE_FORMAT_EXTENSIBLE 的 PCM 子格式）
+fn parse_wav(data: &[u8]) -> Option<Wav<'_>> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return None;
+    }
+    let mut format: Option<[u8; 16]> = None;
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().ok()?) as usize;
+        let body_start = offset + 8;
+        match id {
+            b"fmt " => {
+                let body = data.get(body_start..body_start.checked_add(size)?)?;
+                if body.len() < 16 {
+                    return None;
+                }
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                // 扩展格式的子格式GUID前两字节为实际格式标记
+                let pcm = tag == WAVE_FORMAT_PCM
+                    || (tag == WAVE_FORMAT_EXTENSIBLE && body.len() >= 26 && u16::from_le_bytes([body[24], body[25]]) == WAVE_FORMAT_PCM);
+                if !pcm {
+                    return None;
+                }
+                format = Some(body[..16].try_into().ok()?);
+            }
+            b"data" => {
+                // 流式录音常把 data 长度写成0或最大值，按文件实际剩余长度截断
+                let end = match body_start.checked_add(size) {
+                    Some(end) if size > 0 && end <= data.len() => end,
+                    _ => data.len(),
+                };
+                let wav = Wav { format: format?, audio: &data[body_start..end] };
+                if wav.byte_rate() == 0 || wav.block_align() == 0 {
+                    return None;
+                }
+                return Some(wav);
+            }
+            _ => {}
+        }
+        // 块按偶数字节对齐
+        offset = body_start.checked_add(size)?.checked_add(size % 2)?;
+    }
+    None
+}
+
+/// 合并各段识别结果：文本按语言拼接，词级时间戳和说话人分段按前面分段的时长偏移，置信度取平均
+pub fn consolidate(chunks: Vec<SpeechRecognitionResult>, language: &str) -> Result<SpeechRecognitionResult> {
+    let first = chunks.first().ok_or_else(|| anyhow::anyhow!("没有可合并的识别结果"))?;
+    let mut consolidated = SpeechRecognitionResult {
+        text: String::new(),
+        confidence: 0.0,
+        language: language.to_string(),
+        duration_ms: 0,
+        word_timestamps: vec![],
+        speaker_segments: vec![],
+        provider: first.provider.clone(),
+        audio_format: first.audio_format.clone(),
+        sample_rate: first.sample_rate,
+    };
+    let separator = text_separator(language);
+    let count = chunks.len();
+
+    for chunk in chunks {
+        let offset = consolidated.duration_ms;
+        if !chunk.text.is_empty() {
+            if !consolidated.text.is_empty() {
+                consolidated.text.push_str(separator);
+            }
+            consolidated.text.push_str(&chunk.text);
+        }
+        consolidated.word_timestamps.extend(chunk.word_timestamps.into_iter().map(|mut word| {
+            word.start_time_ms += offset;
+            word.end_time_ms += offset;
+            word
+        }));
+        consolidated.speaker_segments.extend(chunk.speaker_segments.into_iter().map(|mut segment| {
+            segment.start_time_ms += offset;
+            segment.end_time_ms += offset;
+            segment
+        }));
+        consolidated.confidence += chunk.confidence;
+        consolidated.duration_ms += chunk.duration_ms;
+    }
+    consolidated.confidence /= count as f32;
+    Ok(consolidated)
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 struct AudioMetadata {
@@ -373,6 +679,8 @@ struct AudioMetadata {
     duration_ms: u64,
     format: String,
     sample_rate: u32,
+    /// 解析符号链接后的实际路径，后续读取都使用该路径
+    path: std::path::PathBuf,
 }
 
 #[async_trait::async_trait]
@@ -394,16 +702,18 @@ impl AIProcessor for SpeechProcessor {
         let audio_metadata = self.validate_audio_file(audio_file_path).await?;
         
         // 读取音频文件
-        let audio_data = std::fs::read(audio_file_path)?;
+        let audio_data = std::fs::read(&audio_metadata.path)?;
         
+        let enable_speaker_diarization = speech_config.enable_speaker_diarization;
+        drop(config);
+
         // 执行语音识别
-        let result = match speech_config.service_provider.as_str() {
-            "azure" => self.recognize_speech_azure(&audio_data, &language, &audio_metadata.format).await?,
-            "google" => self.recognize_speech_google(&audio_data, &language, &audio_metadata.format).await?,
-            "baidu" => self.recognize_speech_baidu(&audio_data, &language, &audio_metadata.format).await?,
-            _ => self.recognize_speech_local(&audio_data, &language, &audio_metadata.format).await?,
-        };
+        let result = self.recognize(&audio_data, &language, &audio_metadata.format).await?;
         
+        if enable_speaker_diarization && result.speaker_segments.is_empty() {
+            tracing::debug!("语音服务 {} 未返回说话人分段，使用整段转录", result.provider);
+        }
+
         // 后处理结果
         let processed_result = self.post_process_result(result).await?;
         
@@ -419,6 +729,48 @@ impl AIProcessor for SpeechProcessor {
     }
 }
 
+/// 分段语音识别处理器：每完成一段发布一次中间结果，任务结果为合并后的完整转写
+pub struct StreamingSpeechProcessor {
+    speech: Arc<SpeechProcessor>,
+    interim: InterimResults,
+}
+
+impl StreamingSpeechProcessor {
+    pub fn new(speech: Arc<SpeechProcessor>, interim: InterimResults) -> Self {
+        Self { speech, interim }
+    }
+}
+
+#[async_trait::async_trait]
+impl AIProcessor for StreamingSpeechProcessor {
+    async fn process(&self, task: &AITask) -> Result<serde_json::Value> {
+        self.speech
+            .process_streaming(task, |sequence, total, chunk, partial_text| {
+                tracing::debug!("🎤 任务 {} 分段识别完成 ({}/{})", task.id, sequence, total);
+                self.interim.publish(InterimResult {
+                    task_id: task.id.clone(),
+                    sequence,
+                    total,
+                    output: serde_json::json!({
+                        "text": chunk.text,
+                        "confidence": chunk.confidence,
+                        "partial_text": partial_text,
+                    }),
+                    created_at: Utc::now(),
+                });
+            })
+            .await
+    }
+
+    fn get_task_type(&self) -> AITaskType {
+        AITaskType::StreamingSpeechRecognition
+    }
+
+    fn get_name(&self) -> &'static str {
+        "分段语音识别处理器"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -431,6 +783,150 @@ mod tests {
         assert_eq!(SpeechProcessor::parse_google_timestamp("invalid"), 0);
     }
 
+    #[test]
+    fn test_google_diarization_two_speakers() {
+        // 模拟开启 enableSpeakerDiarization 后的Google响应
+        let word = |text: &str, start: &str, end: &str, tag: u64| {
+            serde_json::json!({"word": text, "startTime": start, "endTime": end, "speakerTag": tag})
+        };
+        let response = serde_json::json!({
+            "results": [
+                {"alternatives": [{"transcript": "hello how can I help", "confidence": 0.9}]},
+                {"alternatives": [{
+                    "transcript": "hello how can I help",
+                    "words": [
+                        word("hello", "0s", "0.500s", 1),
+                        word("how", "0.600s", "0.800s", 2),
+                        word("can", "0.800s", "1s", 2),
+                        word("I", "1s", "1.100s", 2),
+                        word("help", "1.100s", "1.500s", 2)
+                    ]
+                }]}
+            ]
+        });
+
+        let segments = SpeechProcessor::parse_google_speaker_segments(&response, "en-US", 0.9);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].speaker_id, "speaker_1");
+        assert_eq!(segments[0].text, "hello");
+        assert_eq!(segments[1].speaker_id, "speaker_2");
+        assert_eq!(segments[1].text, "how can I help");
+        assert_eq!((segments[1].start_time_ms, segments[1].end_time_ms), (600, 1500));
+
+        // 未返回说话人标签时退回整段转录
+        let plain = serde_json::json!({"results": [{"alternatives": [{"transcript": "hello"}]}]});
+        assert!(SpeechProcessor::parse_google_speaker_segments(&plain, "en-US", 0.9).is_empty());
+    }
+
+    // 16kHz 16bit 单声道 PCM，每秒 32000 字节
+    fn pcm_wav(audio_len: usize) -> Vec<u8> {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&((36 + audio_len) as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&32000u32.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(audio_len as u32).to_le_bytes());
+        wav.extend(std::iter::repeat_n(1u8, audio_len));
+        wav
+    }
+
+    #[test]
+    fn test_split_wav_into_standalone_chunks() {
+        // 2.5秒音频按1秒切分为3段，每段都是可单独识别的WAV
+        let chunks = split_wav(&pcm_wav(80_000), 1000).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].len(), WAV_HEADER_LEN + 32_000);
+        assert_eq!(chunks[2].len(), WAV_HEADER_LEN + 16_000);
+        assert_eq!(wav_duration_ms(&chunks[0]), Some(1000));
+        assert_eq!(wav_duration_ms(&chunks[2]), Some(500));
+        assert_eq!(chunks[2][40..44], 16_000u32.to_le_bytes());
+        assert_eq!(chunks[2][4..8], (36 + 16_000u32).to_le_bytes());
+
+        assert!(split_wav(b"ID3 not a wav file", 1000).is_none());
+    }
+
+    #[test]
+    fn test_split_wav_skips_extra_chunks() {
+        // 录音软件常在 fmt 和 data 之间写入 LIST 元数据块（奇数长度，带填充字节）
+        let wav = pcm_wav(48_000);
+        let mut with_list = wav[..36].to_vec();
+        with_list.extend_from_slice(b"LIST");
+        with_list.extend_from_slice(&5u32.to_le_bytes());
+        with_list.extend_from_slice(b"INFO\0\0");
+        with_list.extend_from_slice(&wav[36..]);
+
+        let chunks = split_wav(&with_list, 1000).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks, split_wav(&wav, 1000).unwrap());
+        assert_eq!(wav_duration_ms(&with_list), Some(1500));
+        assert_eq!(wav_duration_ms(&chunks[1]), Some(500));
+
+        // 非 PCM 编码（如 IEEE float）不切分
+        let mut float_wav = wav.clone();
+        float_wav[20..22].copy_from_slice(&3u16.to_le_bytes());
+        assert!(split_wav(&float_wav, 1000).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_audio_path_must_stay_in_voice_dir() {
+        let base = std::env::temp_dir().join(format!("speech_dir_{}", uuid::Uuid::new_v4()));
+        let voice_dir = base.join("voice");
+        std::fs::create_dir_all(&voice_dir).unwrap();
+        std::fs::write(voice_dir.join("ok.wav"), pcm_wav(3_200)).unwrap();
+        std::fs::write(base.join("secret.wav"), pcm_wav(3_200)).unwrap();
+
+        let mut config = AIConfig::default();
+        config.speech_recognition.audio_dir = voice_dir.to_string_lossy().to_string();
+        let processor = SpeechProcessor::new(Arc::new(RwLock::new(config)));
+
+        let ok = voice_dir.join("ok.wav");
+        assert!(processor.validate_audio_file(ok.to_str().unwrap()).await.is_ok());
+        for path in [base.join("secret.wav"), voice_dir.join("../secret.wav"), "/etc/passwd".into()] {
+            let err = processor.validate_audio_file(path.to_str().unwrap()).await.unwrap_err();
+            assert!(!err.to_string().contains("secret"), "{}", err);
+        }
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_consolidate_offsets_chunk_timestamps() {
+        let chunk = |text: &str, duration_ms: u64, confidence: f32| SpeechRecognitionResult {
+            text: text.to_string(),
+            confidence,
+            language: "zh-CN".to_string(),
+            duration_ms,
+            word_timestamps: vec![WordTimestamp {
+                word: text.to_string(),
+                start_time_ms: 100,
+                end_time_ms: 400,
+                confidence,
+            }],
+            speaker_segments: vec![],
+            provider: "local".to_string(),
+            audio_format: "wav".to_string(),
+            sample_rate: 16000,
+        };
+
+        let result = consolidate(vec![chunk("你好", 1000, 0.9), chunk("", 1000, 0.5), chunk("我要退款", 800, 0.7)], "zh-CN").unwrap();
+        assert_eq!(result.text, "你好我要退款");
+        assert_eq!(result.duration_ms, 2800);
+        assert!((result.confidence - 0.7).abs() < 1e-6);
+        let starts: Vec<u64> = result.word_timestamps.iter().map(|word| word.start_time_ms).collect();
+        assert_eq!(starts, vec![100, 1100, 2100]);
+
+        let english = consolidate(vec![chunk("hello", 1000, 0.9), chunk("world", 1000, 0.9)], "en-US").unwrap();
+        assert_eq!(english.text, "hello world");
+        assert!(consolidate(vec![], "zh-CN").is_err());
+    }
+
     #[tokio::test]
     async fn test_local_speech_recognition() {
         let config = Arc::new(RwLock::new(AIConfig::default()));
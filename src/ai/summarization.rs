@@ -0,0 +1,260 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use super::circuit_breaker::CircuitBreakers;
+use super::{config::AIConfig, AIProcessor, AITask, AITaskType};
+use crate::message::ChatMessage;
+
+const SUMMARY_SYSTEM_PROMPT: &str = "你是客服质检助手，请客观、简洁地总结会话，保留订单号等关键信息。";
+
+/// 会话中的一条发言，role 为 "customer" 或 "agent"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationLine {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummaryResult {
+    /// 一句话摘要
+    pub summary: String,
+    pub key_points: Vec<String>,
+    pub message_count: usize,
+}
+
+/// 摘要任务的输入：按时间顺序的会话全文，客户的发言标记为 customer，其余为 agent
+pub fn summary_input(messages: &[ChatMessage], customer_id: &str) -> serde_json::Value {
+    let lines: Vec<ConversationLine> = messages
+        .iter()
+        .map(|message| ConversationLine {
+            role: if message.from == customer_id { "customer" } else { "agent" }.to_string(),
+            content: message.content.clone(),
+        })
+        .collect();
+    serde_json::json!({ "messages": lines })
+}
+
+fn transcript(lines: &[ConversationLine]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            let speaker = if line.role == "customer" { "客户" } else { "客服" };
+            format!("{}：{}", speaker, line.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// 模型可能用 ```json 包裹输出；不是JSON时把整段回复作为摘要
+fn parse_summary(content: &str, message_count: usize, max_key_points: usize) -> Result<SummaryResult> {
+    #[derive(Deserialize)]
+    struct ModelOutput {
+        summary: String,
+        #[serde(default)]
+        key_points: Vec<String>,
+    }
+
+    let body = content
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let (summary, key_points) = match serde_json::from_str::<ModelOutput>(body) {
+        Ok(output) => (output.summary, output.key_points),
+        Err(_) => (body.to_string(), Vec::new()),
+    };
+
+    let summary = summary.trim().to_string();
+    if summary.is_empty() {
+        return Err(anyhow::anyhow!("模型返回的摘要为空"));
+    }
+    let key_points = key_points
+        .into_iter()
+        .map(|point| point.trim().to_string())
+        .filter(|point| !point.is_empty())
+        .take(max_key_points)
+        .collect();
+    Ok(SummaryResult { summary, key_points, message_count })
+}
+
+pub struct SummaryProcessor {
+    config: Arc<RwLock<AIConfig>>,
+    http_client: reqwest::Client,
+    circuit_breakers: Arc<CircuitBreakers>,
+}
+
+impl SummaryProcessor {
+    pub fn new(config: Arc<RwLock<AIConfig>>) -> Self {
+        Self {
+            config,
+            http_client: reqwest::Client::new(),
+            circuit_breakers: Arc::new(CircuitBreakers::default()),
+        }
+    }
+
+    /// 与其他处理器共用按服务地址的熔断状态
+    pub fn with_circuit_breakers(mut self, circuit_breakers: Arc<CircuitBreakers>) -> Self {
+        self.circuit_breakers = circuit_breakers;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl AIProcessor for SummaryProcessor {
+    async fn process(&self, task: &AITask) -> Result<serde_json::Value> {
+        let lines: Vec<ConversationLine> = serde_json::from_value(task.input_data["messages"].clone())
+            .map_err(|_| anyhow::anyhow!("缺少会话内容"))?;
+        if lines.is_empty() {
+            return Err(anyhow::anyhow!("会话内容为空"));
+        }
+
+        let config = self.config.read().await.clone();
+        let llm = &config.intent_recognition;
+        if llm.api_key.is_empty() {
+            return Err(anyhow::anyhow!("未配置对话补全接口的 API Key"));
+        }
+        let max_key_points = config.summarization.max_key_points;
+
+        let prompt = format!(
+            "请为以下客服会话生成存档摘要，返回JSON格式的结果：\
+            \n{}\
+            \n返回格式：{{\"summary\": \"一句话概括客户诉求与处理结果\", \"key_points\": [\"订单号、金额、承诺时间等关键信息\"]}}\
+            \n关键要点不超过{}条",
+            transcript(&lines),
+            max_key_points
+        );
+        let request_body = serde_json::json!({
+            "model": config.model_for_task(task),
+            "messages": [
+                {
+                    "role": "system",
+                    "content": config.system_prompt_for_task(task, SUMMARY_SYSTEM_PROMPT)
+                },
+                {
+                    "role": "user",
+                    "content": prompt
+                }
+            ],
+            "temperature": 0.2,
+            "max_tokens": 500
+        });
+
+        let request = self
+            .http_client
+            .post(&llm.api_endpoint)
+            .header("Authorization", format!("Bearer {}", llm.api_key))
+            .header("Content-Type", "application/json")
+            .json(&request_body)
+            .timeout(std::time::Duration::from_secs(llm.timeout_seconds));
+        let response = self
+            .circuit_breakers
+            .send(&config, &llm.api_endpoint, request)
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("摘要接口请求失败: {}", response.status()));
+        }
+
+        let response_body: serde_json::Value = response.json().await?;
+        let content = response_body["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("无法解析摘要接口响应"))?;
+
+        Ok(serde_json::to_value(parse_summary(content, lines.len(), max_key_points)?)?)
+    }
+
+    fn get_task_type(&self) -> AITaskType {
+        AITaskType::Summarization
+    }
+
+    fn get_name(&self) -> &'static str {
+        "会话摘要处理器"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::sync::Mutex;
+    use warp::Filter;
+
+    fn chat(from: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            id: None,
+            from: from.to_string(),
+            to: None,
+            content: content.to_string(),
+            content_type: None,
+            filename: None,
+            timestamp: Utc::now(),
+            url: None,
+            thumbnail_url: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_model_output() {
+        let fenced = "```json\n{\"summary\": \"客户咨询退款\", \"key_points\": [\"订单 A1\", \" \", \"三天内到账\"]}\n```";
+        let result = parse_summary(fenced, 4, 5).unwrap();
+        assert_eq!(result.summary, "客户咨询退款");
+        assert_eq!(result.key_points, ["订单 A1", "三天内到账"]);
+
+        // 非JSON回复整体作为摘要
+        assert_eq!(parse_summary("客户咨询物流", 2, 5).unwrap().summary, "客户咨询物流");
+        assert!(parse_summary("{\"summary\": \"  \"}", 2, 5).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_summary_keeps_key_information() {
+        let received = Arc::new(Mutex::new(String::new()));
+        let route = {
+            let received = received.clone();
+            warp::post().and(warp::body::json()).map(move |body: serde_json::Value| {
+                *received.lock().unwrap() = body["messages"][1]["content"].as_str().unwrap_or_default().to_string();
+                let content = serde_json::json!({
+                    "summary": "客户反馈订单20240601未发货，客服承诺明天发出",
+                    "key_points": ["订单号20240601", "承诺明天发货"]
+                });
+                warp::reply::json(&serde_json::json!({
+                    "choices": [{ "message": { "content": content.to_string() } }]
+                }))
+            })
+        };
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let mut config = AIConfig::default();
+        config.intent_recognition.api_endpoint = format!("http://{}/v1/chat/completions", addr);
+        config.intent_recognition.api_key = "test-key".to_string();
+        let processor = SummaryProcessor::new(Arc::new(RwLock::new(config)));
+
+        let messages = [
+            chat("kehu_1", "你好，我的订单20240601一周了还没发货"),
+            chat("kefu_1", "您好，我帮您查一下"),
+            chat("kefu_1", "仓库缺货，明天一定发出"),
+            chat("kehu_1", "好的，谢谢"),
+        ];
+        let task = AITask::new(
+            AITaskType::Summarization,
+            "kehu_1".to_string(),
+            "kehu_1:kefu_1".to_string(),
+            summary_input(&messages, "kehu_1"),
+            3,
+        );
+
+        let output: SummaryResult = serde_json::from_value(processor.process(&task).await.unwrap()).unwrap();
+        assert!(!output.summary.is_empty());
+        assert!(output.summary.contains("20240601"));
+        assert!(output.key_points.iter().any(|point| point.contains("20240601")));
+        assert_eq!(output.message_count, 4);
+
+        // 会话全文以客户/客服区分发言后发送给模型
+        let prompt = received.lock().unwrap().clone();
+        assert!(prompt.contains("客户：你好，我的订单20240601一周了还没发货"));
+        assert!(prompt.contains("客服：仓库缺货，明天一定发出"));
+    }
+}
@@ -5,6 +5,7 @@ use tokio::sync::RwLock;
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use super::{AIProcessor, AITask, AITaskType, config::AIConfig};
+use super::circuit_breaker::CircuitBreakers;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TranslationResult {
@@ -15,6 +16,18 @@ pub struct TranslationResult {
     pub confidence: f32,
     pub provider: String,
     pub cached: bool,
+    /// 客户端未指定源语言时的自动检测结果
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language_detection: Option<LanguageDetection>,
+}
+
+/// 源语言检测结果
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LanguageDetection {
+    pub language: String,
+    pub confidence: f32,
+    /// 置信度不足，改用配置的默认源语言
+    pub fallback: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +42,7 @@ pub struct TranslationProcessor {
     config: Arc<RwLock<AIConfig>>,
     http_client: reqwest::Client,
     translation_cache: Arc<RwLock<HashMap<String, CachedTranslation>>>,
+    circuit_breakers: Arc<CircuitBreakers>,
 }
 
 // 确保TranslationProcessor可以在线程间安全传递
@@ -41,9 +55,16 @@ impl TranslationProcessor {
             config,
             http_client: reqwest::Client::new(),
             translation_cache: Arc::new(RwLock::new(HashMap::new())),
+            circuit_breakers: Arc::new(CircuitBreakers::default()),
         }
     }
 
+    /// 与其他处理器共用按服务地址的熔断状态
+    pub fn with_circuit_breakers(mut self, circuit_breakers: Arc<CircuitBreakers>) -> Self {
+        self.circuit_breakers = circuit_breakers;
+        self
+    }
+
     fn generate_cache_key(&self, text: &str, source_lang: &str, target_lang: &str) -> String {
         format!("{}:{}:{}", source_lang, target_lang, text)
     }
@@ -86,36 +107,46 @@ impl TranslationProcessor {
         });
     }
 
-    async fn detect_language(&self, text: &str) -> Result<String> {
-        // 简单的语言检测逻辑
-        let chinese_chars = text.chars().filter(|c| {
-            (*c as u32) >= 0x4e00 && (*c as u32) <= 0x9fff
-        }).count();
-        
-        let english_chars = text.chars().filter(|c| {
-            c.is_ascii_alphabetic()
-        }).count();
-        
-        let japanese_chars = text.chars().filter(|c| {
-            (*c as u32) >= 0x3040 && (*c as u32) <= 0x309f ||  // Hiragana
-            (*c as u32) >= 0x30a0 && (*c as u32) <= 0x30ff     // Katakana
-        }).count();
-        
-        let total_chars = text.chars().count();
-        
-        if chinese_chars > total_chars / 3 {
-            Ok("zh".to_string())
-        } else if japanese_chars > total_chars / 3 {
-            Ok("ja".to_string())
-        } else if english_chars > total_chars / 2 {
-            Ok("en".to_string())
-        } else {
-            Ok("auto".to_string())
+    /// 按文字占比检测源语言，置信度为主要文字在全部字母类字符中的占比；
+    /// 置信度低于配置阈值时使用 fallback_source_language
+    async fn detect_language(&self, text: &str) -> Result<LanguageDetection> {
+        let (mut han, mut kana, mut latin, mut total) = (0usize, 0usize, 0usize, 0usize);
+        for c in text.chars().filter(|c| c.is_alphabetic()) {
+            total += 1;
+            match c as u32 {
+                0x3040..=0x30ff => kana += 1, // 平假名、片假名
+                0x4e00..=0x9fff => han += 1,
+                _ if c.is_ascii_alphabetic() => latin += 1,
+                _ => {}
+            }
+        }
+
+        // 日文混用汉字，出现假名时汉字计入日文
+        let candidates = [("ja", if kana > 0 { kana + han } else { 0 }), ("zh", han), ("en", latin)];
+        let (language, count) = candidates
+            .iter()
+            .fold(("auto", 0), |best, &candidate| if candidate.1 > best.1 { candidate } else { best });
+        let confidence = if total == 0 { 0.0 } else { count as f32 / total as f32 };
+
+        let config = self.config.read().await;
+        let translation_config = &config.translation;
+        if confidence < translation_config.detection_confidence_threshold {
+            return Ok(LanguageDetection {
+                language: translation_config.fallback_source_language.clone(),
+                confidence,
+                fallback: true,
+            });
         }
+
+        Ok(LanguageDetection {
+            language: language.to_string(),
+            confidence,
+            fallback: false,
+        })
     }
 
     async fn translate_google(&self, text: &str, source_lang: &str, target_lang: &str) -> Result<TranslationResult> {
-        let config = self.config.read().await;
+        let config = self.config.read().await.clone();
         let translation_config = &config.translation;
         
         let mut params = vec![
@@ -128,10 +159,11 @@ impl TranslationProcessor {
             params.push(("source", source_lang));
         }
         
-        let response = self.http_client
+        let request = self.http_client
             .post(&translation_config.api_endpoint)
-            .form(&params)
-            .send()
+            .form(&params);
+        let response = self.circuit_breakers
+            .send(&config, &translation_config.api_endpoint, request)
             .await?;
         
         if !response.status().is_success() {
@@ -161,11 +193,12 @@ impl TranslationProcessor {
             confidence: 0.9,
             provider: "google".to_string(),
             cached: false,
+            language_detection: None,
         })
     }
 
     async fn translate_baidu(&self, text: &str, source_lang: &str, target_lang: &str) -> Result<TranslationResult> {
-        let config = self.config.read().await;
+        let config = self.config.read().await.clone();
         let translation_config = &config.translation;
         
         let app_id = &translation_config.api_key;
@@ -189,10 +222,11 @@ impl TranslationProcessor {
             ("sign", &sign),
         ];
         
-        let response = self.http_client
+        let request = self.http_client
             .post(&translation_config.api_endpoint)
-            .form(&params)
-            .send()
+            .form(&params);
+        let response = self.circuit_breakers
+            .send(&config, &translation_config.api_endpoint, request)
             .await?;
         
         if !response.status().is_success() {
@@ -222,11 +256,12 @@ impl TranslationProcessor {
             confidence: 0.85,
             provider: "baidu".to_string(),
             cached: false,
+            language_detection: None,
         })
     }
 
     async fn translate_azure(&self, text: &str, source_lang: &str, target_lang: &str) -> Result<TranslationResult> {
-        let config = self.config.read().await;
+        let config = self.config.read().await.clone();
         let translation_config = &config.translation;
         
         let mut url = format!("{}/translate?api-version=3.0&to={}", 
@@ -240,12 +275,13 @@ impl TranslationProcessor {
             "Text": text
         }]);
         
-        let response = self.http_client
+        let request = self.http_client
             .post(&url)
             .header("Ocp-Apim-Subscription-Key", &translation_config.api_key)
             .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
+            .json(&body);
+        let response = self.circuit_breakers
+            .send(&config, &translation_config.api_endpoint, request)
             .await?;
         
         if !response.status().is_success() {
@@ -275,6 +311,7 @@ impl TranslationProcessor {
             confidence: 0.92,
             provider: "azure".to_string(),
             cached: false,
+            language_detection: None,
         })
     }
 
@@ -302,6 +339,7 @@ impl TranslationProcessor {
             confidence: 0.3,
             provider: "local".to_string(),
             cached: false,
+            language_detection: None,
         })
     }
 
@@ -336,20 +374,23 @@ impl AIProcessor for TranslationProcessor {
             return Err(anyhow::anyhow!("文本长度超过限制"));
         }
         
-        let source_lang = task.input_data["source_language"]
-            .as_str()
-            .unwrap_or(&translation_config.default_source_language)
-            .to_string();
-        
         let target_lang = task.input_data["target_language"]
             .as_str()
             .unwrap_or(&translation_config.default_target_language)
             .to_string();
         
-        let detected_source_lang = if source_lang == "auto" && translation_config.auto_detect_language {
-            self.detect_language(text).await?
-        } else {
-            source_lang
+        // 客户端未指定源语言时先检测，再调用翻译服务
+        let requested_source_lang = task.input_data["source_language"]
+            .as_str()
+            .filter(|lang| !lang.is_empty() && *lang != "auto");
+        let language_detection = match requested_source_lang {
+            None if translation_config.auto_detect_language => Some(self.detect_language(text).await?),
+            _ => None,
+        };
+        let detected_source_lang = match (&language_detection, requested_source_lang) {
+            (Some(detection), _) => detection.language.clone(),
+            (None, Some(lang)) => lang.to_string(),
+            (None, None) => translation_config.default_source_language.clone(),
         };
         
         // 检查是否支持这种语言组合
@@ -367,11 +408,12 @@ impl AIProcessor for TranslationProcessor {
                 confidence: 0.9,
                 provider: translation_config.service_provider.clone(),
                 cached: true,
+                language_detection,
             })?);
         }
         
         // 执行翻译
-        let result = match translation_config.service_provider.as_str() {
+        let mut result = match translation_config.service_provider.as_str() {
             "google" => self.translate_google(text, &detected_source_lang, &target_lang).await?,
             "baidu" => self.translate_baidu(text, &detected_source_lang, &target_lang).await?,
             "azure" => self.translate_azure(text, &detected_source_lang, &target_lang).await?,
@@ -383,6 +425,7 @@ impl AIProcessor for TranslationProcessor {
             self.save_to_cache(text, &result.source_language, &result.target_language, &result.translated_text).await;
         }
         
+        result.language_detection = language_detection;
         Ok(serde_json::to_value(result)?)
     }
 
@@ -405,9 +448,61 @@ mod tests {
         let config = Arc::new(RwLock::new(AIConfig::default()));
         let processor = TranslationProcessor::new(config);
         
-        assert_eq!(processor.detect_language("你好世界").await.unwrap(), "zh");
-        assert_eq!(processor.detect_language("Hello World").await.unwrap(), "en");
-        assert_eq!(processor.detect_language("こんにちは").await.unwrap(), "ja");
+        assert_eq!(processor.detect_language("你好世界").await.unwrap().language, "zh");
+        assert_eq!(processor.detect_language("Hello World").await.unwrap().language, "en");
+        assert_eq!(processor.detect_language("こんにちは").await.unwrap().language, "ja");
+    }
+
+    #[tokio::test]
+    async fn test_low_confidence_detection_falls_back_to_default_language() {
+        let mut config = AIConfig::default();
+        config.translation.fallback_source_language = "en".to_string();
+        let processor = TranslationProcessor::new(Arc::new(RwLock::new(config)));
+
+        let detected = processor.detect_language("今日は良い天気ですね").await.unwrap();
+        assert_eq!(detected.language, "ja");
+        assert!(!detected.fallback);
+
+        // 中英文各占一半，无法可靠判断
+        let mixed = processor.detect_language("ok 好的 ab 是的").await.unwrap();
+        assert!(mixed.confidence < 0.6);
+        assert!(mixed.fallback);
+        assert_eq!(mixed.language, "en");
+
+        // 没有任何文字时同样回退
+        let empty = processor.detect_language("12345 !!!").await.unwrap();
+        assert_eq!(empty.confidence, 0.0);
+        assert!(empty.fallback);
+    }
+
+    #[tokio::test]
+    async fn test_detected_language_is_reported_in_result() {
+        let mut config = AIConfig::default();
+        config.translation.service_provider = "local".to_string();
+        config.translation.default_target_language = "zh".to_string();
+        let manager = crate::ai::AIManager::new();
+        manager.update_config(config).await.unwrap();
+        manager.start_processing().await.unwrap();
+
+        let task = AITask::new(
+            AITaskType::Translation,
+            "kehu_001".to_string(),
+            "msg_001".to_string(),
+            serde_json::json!({ "text": "hello" }),
+            5,
+        );
+        let task_id = manager.submit_task(task).await.unwrap();
+        let result = manager
+            .wait_for_result(&task_id, std::time::Duration::from_secs(5))
+            .await
+            .unwrap()
+            .expect("翻译任务应完成");
+
+        assert_eq!(result.result["translated_text"], "你好");
+        assert_eq!(result.result["source_language"], "en");
+        assert_eq!(result.result["language_detection"]["language"], "en");
+        assert_eq!(result.result["language_detection"]["confidence"], 1.0);
+        assert_eq!(result.result["language_detection"]["fallback"], false);
     }
 
     #[tokio::test]
@@ -419,4 +514,4 @@ mod tests {
         assert_eq!(result.translated_text, "你好");
         assert_eq!(result.provider, "local");
     }
-} 
\ No newline at end of file
+} 
\ No newline at end of file
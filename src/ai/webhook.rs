@@ -0,0 +1,195 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::config::WebhookConfig;
+use super::{AIResult, AITask, AITaskStatus, AITaskType};
+use crate::auth::jwt_auth::hmac_sha256;
+
+/// 任务 metadata 中的回调地址，任务结束后把结果 POST 到该地址
+pub const CALLBACK_URL_METADATA_KEY: &str = "callback_url";
+
+/// 回调请求体的 HMAC-SHA256 签名，格式为 "sha256=<hex>"
+pub const SIGNATURE_HEADER: &str = "X-AI-Signature";
+
+/// 重试间隔上限
+const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 任务结束回调的请求体：完成时带 AIResult，最终失败时带错误信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskCallback {
+    pub task_id: String,
+    pub task_type: AITaskType,
+    pub user_id: String,
+    pub message_id: String,
+    pub status: AITaskStatus,
+    pub result: Option<AIResult>,
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl TaskCallback {
+    pub fn completed(task: &AITask, result: AIResult) -> Self {
+        Self::new(task, AITaskStatus::Completed, Some(result), None)
+    }
+
+    pub fn failed(task: &AITask, error: String) -> Self {
+        Self::new(task, AITaskStatus::Failed, None, Some(error))
+    }
+
+    fn new(task: &AITask, status: AITaskStatus, result: Option<AIResult>, error: Option<String>) -> Self {
+        Self {
+            task_id: task.id.clone(),
+            task_type: task.task_type.clone(),
+            user_id: task.user_id.clone(),
+            message_id: task.message_id.clone(),
+            status,
+            result,
+            error,
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// 请求体签名，接收方用同一密钥对原始请求体计算后比对
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mac = hmac_sha256(secret.as_bytes(), body);
+    let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+/// 第 attempt 次失败后的等待时间：从初始间隔开始指数退避
+fn backoff_delay(initial: std::time::Duration, attempt: u32) -> std::time::Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// 网络错误、5xx 和 429 可以重试，其他 4xx 说明请求本身不被接受，重试无意义
+fn should_retry(status: Option<reqwest::StatusCode>) -> bool {
+    match status {
+        None => true,
+        Some(status) => status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+    }
+}
+
+/// 任务结束回调的投递器
+#[derive(Clone, Default)]
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    /// 投递回调，失败时按退避策略重试，超过最大次数后返回错误
+    pub async fn deliver(&self, url: &str, callback: &TaskCallback, config: &WebhookConfig) -> Result<()> {
+        let body = serde_json::to_vec(callback)?;
+        let signature = (!config.signing_secret.is_empty()).then(|| sign(&config.signing_secret, &body));
+        let max_attempts = config.max_attempts.max(1);
+        let initial_backoff = std::time::Duration::from_millis(config.initial_backoff_ms);
+
+        for attempt in 1..=max_attempts {
+            let mut request = self
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .timeout(std::time::Duration::from_secs(config.timeout_seconds))
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+
+            let status = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => Some(response.status()),
+                Err(e) => {
+                    tracing::warn!("任务 {} 回调请求失败（第{}次）: {}", callback.task_id, attempt, e);
+                    None
+                }
+            };
+            if !should_retry(status) || attempt == max_attempts {
+                return Err(anyhow::anyhow!(
+                    "任务 {} 回调失败: {} 次尝试后放弃, 最后状态 {:?}",
+                    callback.task_id,
+                    attempt,
+                    status
+                ));
+            }
+            tokio::time::sleep(backoff_delay(initial_backoff, attempt)).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    fn test_task() -> AITask {
+        let mut task = AITask::new(
+            AITaskType::IntentRecognition,
+            "user1".to_string(),
+            "msg1".to_string(),
+            serde_json::json!({ "text": "你好" }),
+            5,
+        );
+        task.metadata.insert(CALLBACK_URL_METADATA_KEY.to_string(), "http://example.com".to_string());
+        task
+    }
+
+    #[test]
+    fn test_backoff_and_retry_policy() {
+        let initial = std::time::Duration::from_millis(500);
+        assert_eq!(backoff_delay(initial, 1), initial);
+        assert_eq!(backoff_delay(initial, 3), initial * 4);
+        assert_eq!(backoff_delay(initial, 30), MAX_BACKOFF);
+
+        assert!(should_retry(None));
+        assert!(should_retry(Some(reqwest::StatusCode::BAD_GATEWAY)));
+        assert!(should_retry(Some(reqwest::StatusCode::TOO_MANY_REQUESTS)));
+        assert!(!should_retry(Some(reqwest::StatusCode::BAD_REQUEST)));
+    }
+
+    #[tokio::test]
+    async fn test_callback_retried_and_signed() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let received = Arc::new(Mutex::new(None));
+        let route = {
+            let attempts = attempts.clone();
+            let received = received.clone();
+            warp::post()
+                .and(warp::header::<String>(SIGNATURE_HEADER))
+                .and(warp::body::bytes())
+                .map(move |signature: String, body: bytes::Bytes| {
+                    // 第一次返回 503，验证会退避重试
+                    if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return warp::http::StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    *received.lock().unwrap() = Some((signature, body.to_vec()));
+                    warp::http::StatusCode::OK
+                })
+        };
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let config = WebhookConfig {
+            signing_secret: "webhook-secret".to_string(),
+            initial_backoff_ms: 10,
+            ..WebhookConfig::default()
+        };
+        let callback = TaskCallback::failed(&test_task(), "外部服务超时".to_string());
+        WebhookNotifier::default()
+            .deliver(&format!("http://{}/callback", addr), &callback, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        let (signature, body) = received.lock().unwrap().take().unwrap();
+        assert_eq!(signature, sign("webhook-secret", &body));
+        let delivered: TaskCallback = serde_json::from_slice(&body).unwrap();
+        assert_eq!(delivered.status, AITaskStatus::Failed);
+        assert_eq!(delivered.error.as_deref(), Some("外部服务超时"));
+    }
+}
@@ -0,0 +1,373 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+#[cfg(test)]
+use std::collections::HashMap;
+use std::sync::Arc;
+#[cfg(test)]
+use tokio::sync::RwLock;
+
+use crate::redis_client::RedisManager;
+
+const BAN_KEY_PREFIX: &str = "customer_ban:";
+const BAN_INDEX_KEY: &str = "customer_bans";
+const BAN_AUDIT_KEY: &str = "customer_ban_audit";
+
+/// 封禁对象：按客户ID或按IP
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum BanKind {
+    Customer,
+    Ip,
+}
+
+impl BanKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BanKind::Customer => "customer",
+            BanKind::Ip => "ip",
+        }
+    }
+}
+
+impl std::str::FromStr for BanKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "customer" => Ok(BanKind::Customer),
+            "ip" => Ok(BanKind::Ip),
+            other => Err(anyhow::anyhow!("未知的封禁类型: {}", other)),
+        }
+    }
+}
+
+/// 一条封禁记录，expires_at 为空表示永久封禁
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CustomerBan {
+    pub kind: BanKind,
+    pub value: String,
+    pub reason: Option<String>,
+    pub operator: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl CustomerBan {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BanAction {
+    Ban,
+    Unban,
+}
+
+/// 封禁操作审计记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanAuditEntry {
+    pub action: BanAction,
+    pub kind: BanKind,
+    pub value: String,
+    pub operator: String,
+    pub reason: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// 封禁记录与审计日志的存储接口
+#[async_trait::async_trait]
+pub trait BanStore: Send + Sync {
+    async fn put_ban(&self, ban: &CustomerBan) -> Result<()>;
+    async fn get_ban(&self, kind: BanKind, value: &str) -> Result<Option<CustomerBan>>;
+    async fn remove_ban(&self, kind: BanKind, value: &str) -> Result<bool>;
+    async fn list_bans(&self) -> Result<Vec<CustomerBan>>;
+    async fn append_audit(&self, entry: &BanAuditEntry) -> Result<()>;
+    /// 最新的在前
+    async fn list_audit(&self, limit: usize) -> Result<Vec<BanAuditEntry>>;
+}
+
+/// 基于Redis的封禁存储，临时封禁依赖键过期自动解除
+pub struct RedisBanStore {
+    redis: RedisManager,
+}
+
+impl RedisBanStore {
+    pub fn new(redis: RedisManager) -> Self {
+        Self { redis }
+    }
+
+    fn key(kind: BanKind, value: &str) -> String {
+        format!("{}{}:{}", BAN_KEY_PREFIX, kind.as_str(), value)
+    }
+}
+
+#[async_trait::async_trait]
+impl BanStore for RedisBanStore {
+    async fn put_ban(&self, ban: &CustomerBan) -> Result<()> {
+        let key = Self::key(ban.kind, &ban.value);
+        let payload = serde_json::to_string(ban)?;
+        let mut conn = self.redis.get_async_connection().await?;
+        match ban.expires_at {
+            Some(expires_at) => {
+                let ttl = (expires_at - Utc::now()).num_seconds().max(1);
+                conn.set_ex(key.clone(), payload, ttl).await?;
+            }
+            None => conn.set(&key, &payload).await?,
+        }
+        conn.sadd(BAN_INDEX_KEY, &key).await
+    }
+
+    async fn get_ban(&self, kind: BanKind, value: &str) -> Result<Option<CustomerBan>> {
+        let key = Self::key(kind, value);
+        let mut conn = self.redis.get_async_connection().await?;
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        let raw = conn.get(&key).await?;
+        Ok(Some(serde_json::from_str(&raw)?))
+    }
+
+    async fn remove_ban(&self, kind: BanKind, value: &str) -> Result<bool> {
+        let key = Self::key(kind, value);
+        let mut conn = self.redis.get_async_connection().await?;
+        let existed = conn.exists(&key).await?;
+        conn.del(&key).await?;
+        conn.srem(BAN_INDEX_KEY, &key).await?;
+        Ok(existed)
+    }
+
+    async fn list_bans(&self) -> Result<Vec<CustomerBan>> {
+        let mut conn = self.redis.get_async_connection().await?;
+        let mut bans = Vec::new();
+        for key in conn.smembers(BAN_INDEX_KEY).await? {
+            // 临时封禁过期后键已被Redis删除，顺带清理索引
+            if !conn.exists(&key).await? {
+                conn.srem(BAN_INDEX_KEY, &key).await?;
+                continue;
+            }
+            let raw = conn.get(&key).await?;
+            if let Ok(ban) = serde_json::from_str(&raw) {
+                bans.push(ban);
+            }
+        }
+        Ok(bans)
+    }
+
+    async fn append_audit(&self, entry: &BanAuditEntry) -> Result<()> {
+        let mut conn = self.redis.get_async_connection().await?;
+        conn.lpush(BAN_AUDIT_KEY, &serde_json::to_string(entry)?).await
+    }
+
+    async fn list_audit(&self, limit: usize) -> Result<Vec<BanAuditEntry>> {
+        let mut conn = self.redis.get_async_connection().await?;
+        let raw = conn.lrange(BAN_AUDIT_KEY, 0, limit.max(1) as i64 - 1).await?;
+        Ok(raw.iter().filter_map(|entry| serde_json::from_str(entry).ok()).collect())
+    }
+}
+
+/// 进程内封禁存储，用于测试
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryBanStore {
+    bans: RwLock<HashMap<(BanKind, String), CustomerBan>>,
+    audit: RwLock<Vec<BanAuditEntry>>,
+}
+
+#[cfg(test)]
+impl MemoryBanStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl BanStore for MemoryBanStore {
+    async fn put_ban(&self, ban: &CustomerBan) -> Result<()> {
+        self.bans.write().await.insert((ban.kind, ban.value.clone()), ban.clone());
+        Ok(())
+    }
+
+    async fn get_ban(&self, kind: BanKind, value: &str) -> Result<Option<CustomerBan>> {
+        Ok(self.bans.read().await.get(&(kind, value.to_string())).cloned())
+    }
+
+    async fn remove_ban(&self, kind: BanKind, value: &str) -> Result<bool> {
+        Ok(self.bans.write().await.remove(&(kind, value.to_string())).is_some())
+    }
+
+    async fn list_bans(&self) -> Result<Vec<CustomerBan>> {
+        Ok(self.bans.read().await.values().cloned().collect())
+    }
+
+    async fn append_audit(&self, entry: &BanAuditEntry) -> Result<()> {
+        self.audit.write().await.push(entry.clone());
+        Ok(())
+    }
+
+    async fn list_audit(&self, limit: usize) -> Result<Vec<BanAuditEntry>> {
+        Ok(self.audit.read().await.iter().rev().take(limit).cloned().collect())
+    }
+}
+
+/// 客户黑名单管理：封禁/解封客户ID或IP，每次操作写入审计日志
+pub struct CustomerManager {
+    store: Arc<dyn BanStore>,
+}
+
+impl CustomerManager {
+    pub fn new(store: Arc<dyn BanStore>) -> Self {
+        Self { store }
+    }
+
+    /// 封禁客户ID或IP，duration_secs 为空时永久封禁；重复封禁会覆盖原记录
+    pub async fn ban(
+        &self,
+        kind: BanKind,
+        value: &str,
+        reason: Option<String>,
+        operator: &str,
+        duration_secs: Option<u64>,
+    ) -> Result<CustomerBan> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(anyhow::anyhow!("封禁对象不能为空"));
+        }
+
+        let now = Utc::now();
+        let ban = CustomerBan {
+            kind,
+            value: value.to_string(),
+            reason,
+            operator: operator.to_string(),
+            created_at: now,
+            expires_at: duration_secs.map(|secs| now + chrono::Duration::seconds(secs as i64)),
+        };
+        self.store.put_ban(&ban).await?;
+        self.audit(BanAction::Ban, &ban).await;
+        tracing::warn!("🚫 {} 封禁{} {}: {:?}, 到期={:?}", operator, kind.as_str(), ban.value, ban.reason, ban.expires_at);
+        Ok(ban)
+    }
+
+    /// 解除封禁，返回原本是否处于封禁状态
+    pub async fn unban(&self, kind: BanKind, value: &str, operator: &str) -> Result<bool> {
+        let existing = self.store.get_ban(kind, value).await?;
+        let removed = self.store.remove_ban(kind, value).await?;
+        if removed {
+            let ban = existing.unwrap_or_else(|| CustomerBan {
+                kind,
+                value: value.to_string(),
+                reason: None,
+                operator: operator.to_string(),
+                created_at: Utc::now(),
+                expires_at: None,
+            });
+            self.audit(BanAction::Unban, &CustomerBan { operator: operator.to_string(), ..ban }).await;
+            tracing::info!("✅ {} 解除封禁{} {}", operator, kind.as_str(), value);
+        }
+        Ok(removed)
+    }
+
+    /// 当前生效的封禁
+    pub async fn list_bans(&self) -> Result<Vec<CustomerBan>> {
+        let now = Utc::now();
+        let mut bans: Vec<CustomerBan> = self.store.list_bans().await?.into_iter().filter(|ban| ban.is_active(now)).collect();
+        bans.sort_by_key(|ban| std::cmp::Reverse(ban.created_at));
+        Ok(bans)
+    }
+
+    pub async fn audit_log(&self, limit: usize) -> Result<Vec<BanAuditEntry>> {
+        self.store.list_audit(limit).await
+    }
+
+    /// 检查客户ID或来源IP是否处于封禁中，客户ID优先
+    pub async fn find_active_ban(&self, customer_id: &str, ip: Option<&str>) -> Result<Option<CustomerBan>> {
+        let now = Utc::now();
+        let mut targets = vec![(BanKind::Customer, customer_id)];
+        targets.extend(ip.map(|ip| (BanKind::Ip, ip)));
+        for (kind, value) in targets {
+            if let Some(ban) = self.store.get_ban(kind, value).await? {
+                if ban.is_active(now) {
+                    return Ok(Some(ban));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    async fn audit(&self, action: BanAction, ban: &CustomerBan) {
+        let entry = BanAuditEntry {
+            action,
+            kind: ban.kind,
+            value: ban.value.clone(),
+            operator: ban.operator.clone(),
+            reason: ban.reason.clone(),
+            expires_at: ban.expires_at,
+            timestamp: Utc::now(),
+        };
+        if let Err(e) = self.store.append_audit(&entry).await {
+            tracing::error!("❌ 写入封禁审计日志失败: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> CustomerManager {
+        CustomerManager::new(Arc::new(MemoryBanStore::new()))
+    }
+
+    #[tokio::test]
+    async fn test_ban_by_customer_id_or_ip() {
+        let manager = manager();
+        manager
+            .ban(BanKind::Customer, "kehu_001", Some("恶意骚扰".to_string()), "admin", None)
+            .await
+            .unwrap();
+        manager.ban(BanKind::Ip, "203.0.113.9", None, "admin", Some(3600)).await.unwrap();
+
+        let ban = manager.find_active_ban("kehu_001", Some("198.51.100.1")).await.unwrap().unwrap();
+        assert_eq!(ban.kind, BanKind::Customer);
+        let ban = manager.find_active_ban("kehu_002", Some("203.0.113.9")).await.unwrap().unwrap();
+        assert_eq!(ban.kind, BanKind::Ip);
+        assert!(ban.expires_at.is_some());
+        assert!(manager.find_active_ban("kehu_002", None).await.unwrap().is_none());
+        assert_eq!(manager.list_bans().await.unwrap().len(), 2);
+
+        assert!(manager.unban(BanKind::Customer, "kehu_001", "kefu_001").await.unwrap());
+        assert!(!manager.unban(BanKind::Customer, "kehu_001", "kefu_001").await.unwrap());
+        assert!(manager.find_active_ban("kehu_001", None).await.unwrap().is_none());
+
+        // 每次封禁/解封都有审计记录，最新的在前
+        let audit = manager.audit_log(10).await.unwrap();
+        assert_eq!(audit.len(), 3);
+        assert_eq!(audit[0].action, BanAction::Unban);
+        assert_eq!(audit[0].operator, "kefu_001");
+        assert_eq!(audit[0].reason.as_deref(), Some("恶意骚扰"));
+    }
+
+    #[tokio::test]
+    async fn test_expired_ban_no_longer_applies() {
+        let store = Arc::new(MemoryBanStore::new());
+        let manager = CustomerManager::new(store.clone());
+        let expired = CustomerBan {
+            kind: BanKind::Customer,
+            value: "kehu_001".to_string(),
+            reason: None,
+            operator: "admin".to_string(),
+            created_at: Utc::now() - chrono::Duration::hours(2),
+            expires_at: Some(Utc::now() - chrono::Duration::hours(1)),
+        };
+        store.put_ban(&expired).await.unwrap();
+
+        assert!(manager.find_active_ban("kehu_001", None).await.unwrap().is_none());
+        assert!(manager.list_bans().await.unwrap().is_empty());
+        assert!(manager.ban(BanKind::Ip, "  ", None, "admin", None).await.is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::config::GeoRiskConfig;
+
+/// 每个用户保留的最近可信登录位置数
+pub const MAX_KNOWN_LOCATIONS: usize = 10;
+
+/// IP解析出的地理位置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GeoLocation {
+    pub country: String,
+    pub region: String,
+    pub city: String,
+}
+
+impl std::fmt::Display for GeoLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}/{}", self.country, self.region, self.city)
+    }
+}
+
+/// IP地理位置库，无法解析（如内网地址）时返回 None
+pub trait GeoLocator: Send + Sync {
+    fn locate(&self, ip: &str) -> Option<GeoLocation>;
+}
+
+/// 使用系统内置的IP地理位置库，内网地址和库中没有的IP不参与风控，
+/// 避免未知IP都被当成同一个默认国家而掩盖位置突变
+pub struct BuiltinGeoLocator;
+
+impl GeoLocator for BuiltinGeoLocator {
+    fn locate(&self, ip: &str) -> Option<GeoLocation> {
+        if ip.parse::<std::net::IpAddr>().is_err() {
+            return None;
+        }
+        let location = crate::handlers::client::lookup_known_ip_location(ip)?;
+        Some(GeoLocation {
+            country: location.country,
+            region: location.region,
+            city: location.city,
+        })
+    }
+}
+
+/// 基于 MaxMind City 数据库（GeoIP2 / GeoLite2）的地理位置库，地名优先取中文
+pub struct MaxMindGeoLocator {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+impl MaxMindGeoLocator {
+    /// 打开数据库文件；文件不存在或无法解析时返回 None，调用方据此跳过地理位置补充
+    pub fn open(path: impl AsRef<Path>) -> Option<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            tracing::info!("🌍 未找到GeoIP数据库 {}，跳过地理位置补充", path.display());
+            return None;
+        }
+        match maxminddb::Reader::open_readfile(path) {
+            Ok(reader) => {
+                tracing::info!("🌍 已加载GeoIP数据库: {}", path.display());
+                Some(Self { reader })
+            }
+            Err(e) => {
+                tracing::warn!("⚠️ GeoIP数据库加载失败，跳过地理位置补充: {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+fn localized_name(names: Option<&BTreeMap<&str, &str>>) -> Option<String> {
+    let names = names?;
+    names.get("zh-CN").or_else(|| names.get("en")).map(|name| name.to_string())
+}
+
+impl GeoLocator for MaxMindGeoLocator {
+    fn locate(&self, ip: &str) -> Option<GeoLocation> {
+        let addr: IpAddr = ip.parse().ok()?;
+        if crate::handlers::client::is_private_ip(ip) {
+            return None;
+        }
+        let city: maxminddb::geoip2::City = self.reader.lookup(addr).ok()?;
+        let country = localized_name(city.country.as_ref()?.names.as_ref())?;
+        let region = city
+            .subdivisions
+            .as_ref()
+            .and_then(|subdivisions| subdivisions.first())
+            .and_then(|subdivision| localized_name(subdivision.names.as_ref()));
+        let city_name = city.city.as_ref().and_then(|city| localized_name(city.names.as_ref()));
+        Some(GeoLocation {
+            country,
+            region: region.unwrap_or_else(|| "未知".to_string()),
+            city: city_name.unwrap_or_else(|| "未知".to_string()),
+        })
+    }
+}
+
+/// 风控处置结果
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub enum GeoRiskAction {
+    /// 位置正常，或异常但已完成二次验证
+    Trusted,
+    /// 位置异常，允许连接但标记为可疑
+    Flagged,
+    /// 位置异常且未完成二次验证，拒绝连接
+    ReverificationRequired,
+}
+
+/// 一次握手的地理位置评估
+#[derive(Debug, Clone, Serialize)]
+pub struct GeoRiskAssessment {
+    pub ip: String,
+    pub location: GeoLocation,
+    /// 最近一次可信登录位置
+    pub previous: Option<GeoLocation>,
+    /// 是否出现从未登录过的国家
+    pub suspicious: bool,
+    pub action: GeoRiskAction,
+}
+
+impl GeoRiskAssessment {
+    pub fn reason(&self) -> String {
+        match &self.previous {
+            Some(previous) => format!("登录位置突变: {} -> {} ({})", previous, self.location, self.ip),
+            None => format!("登录位置异常: {} ({})", self.location, self.ip),
+        }
+    }
+}
+
+/// 按用户记录可信登录位置，新连接来自从未出现过的国家时判定为异常
+pub struct GeoRiskTracker {
+    locator: Arc<dyn GeoLocator>,
+    config: GeoRiskConfig,
+    history: Mutex<HashMap<String, VecDeque<GeoLocation>>>,
+}
+
+impl GeoRiskTracker {
+    pub fn new(locator: Arc<dyn GeoLocator>, config: GeoRiskConfig) -> Self {
+        Self {
+            locator,
+            config,
+            history: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 评估一次握手。issued_at 为令牌签发时间，签发不久的令牌视为刚完成二次验证。
+    /// 被拒绝的位置不会进入历史，其余位置记为可信
+    pub fn evaluate(&self, user_id: &str, ip: &str, issued_at: Option<i64>) -> Option<GeoRiskAssessment> {
+        if !self.config.enabled {
+            return None;
+        }
+        let location = self.locator.locate(ip)?;
+        let mut history = self.history.lock().ok()?;
+        let known = history.entry(user_id.to_string()).or_default();
+
+        let previous = known.back().cloned();
+        let suspicious = !known.is_empty() && !known.iter().any(|l| l.country == location.country);
+        let reverified = issued_at.is_some_and(|issued_at| {
+            chrono::Utc::now().timestamp() - issued_at <= self.config.reverification_window as i64
+        });
+        let action = if !suspicious {
+            GeoRiskAction::Trusted
+        } else if !self.config.require_reverification {
+            GeoRiskAction::Flagged
+        } else if reverified {
+            GeoRiskAction::Trusted
+        } else {
+            GeoRiskAction::ReverificationRequired
+        };
+
+        if action != GeoRiskAction::ReverificationRequired {
+            known.retain(|l| l != &location);
+            if known.len() >= MAX_KNOWN_LOCATIONS {
+                known.pop_front();
+            }
+            known.push_back(location.clone());
+        }
+
+        Some(GeoRiskAssessment {
+            ip: ip.to_string(),
+            location,
+            previous,
+            suspicious,
+            action,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockLocator(HashMap<&'static str, GeoLocation>);
+
+    impl GeoLocator for MockLocator {
+        fn locate(&self, ip: &str) -> Option<GeoLocation> {
+            self.0.get(ip).cloned()
+        }
+    }
+
+    fn location(country: &str, region: &str) -> GeoLocation {
+        GeoLocation {
+            country: country.to_string(),
+            region: region.to_string(),
+            city: region.to_string(),
+        }
+    }
+
+    fn tracker(require_reverification: bool) -> GeoRiskTracker {
+        let locator = MockLocator(HashMap::from([
+            ("1.1.1.1", location("中国", "北京")),
+            ("2.2.2.2", location("中国", "上海")),
+            ("3.3.3.3", location("美国", "加利福尼亚")),
+        ]));
+        let config = GeoRiskConfig {
+            require_reverification,
+            ..GeoRiskConfig::default()
+        };
+        GeoRiskTracker::new(Arc::new(locator), config)
+    }
+
+    #[test]
+    fn test_sudden_country_change_is_flagged() {
+        let tracker = tracker(false);
+        // 首次登录没有历史，不判定异常
+        assert!(!tracker.evaluate("kefu_001", "1.1.1.1", None).unwrap().suspicious);
+        // 国内换城市属于正常出行
+        assert!(!tracker.evaluate("kefu_001", "2.2.2.2", None).unwrap().suspicious);
+
+        let abroad = tracker.evaluate("kefu_001", "3.3.3.3", None).unwrap();
+        assert!(abroad.suspicious);
+        assert_eq!(abroad.action, GeoRiskAction::Flagged);
+        assert_eq!(abroad.previous, Some(location("中国", "上海")));
+        assert!(abroad.reason().contains("美国"));
+
+        // 其他用户互不影响，无法解析的IP不参与评估
+        assert!(!tracker.evaluate("kefu_002", "3.3.3.3", None).unwrap().suspicious);
+        assert!(tracker.evaluate("kefu_001", "10.0.0.1", None).is_none());
+    }
+
+    #[test]
+    fn test_reverification_required_until_fresh_login() {
+        let tracker = tracker(true);
+        tracker.evaluate("kefu_001", "1.1.1.1", None);
+
+        let stale_token = chrono::Utc::now().timestamp() - 3600;
+        let rejected = tracker.evaluate("kefu_001", "3.3.3.3", Some(stale_token)).unwrap();
+        assert_eq!(rejected.action, GeoRiskAction::ReverificationRequired);
+        // 被拒绝的位置不记入历史，再次连接仍需验证
+        assert_eq!(
+            tracker.evaluate("kefu_001", "3.3.3.3", None).unwrap().action,
+            GeoRiskAction::ReverificationRequired
+        );
+
+        let fresh_token = chrono::Utc::now().timestamp();
+        let verified = tracker.evaluate("kefu_001", "3.3.3.3", Some(fresh_token)).unwrap();
+        assert!(verified.suspicious);
+        assert_eq!(verified.action, GeoRiskAction::Trusted);
+        // 验证通过后该国家成为可信位置
+        assert!(!tracker.evaluate("kefu_001", "3.3.3.3", None).unwrap().suspicious);
+    }
+
+    #[test]
+    fn test_builtin_locator_skips_unknown_ips() {
+        assert!(BuiltinGeoLocator.locate("8.8.8.8").is_some());
+        // 库中没有的IP不回落到默认国家
+        assert!(BuiltinGeoLocator.locate("203.0.113.7").is_none());
+        assert!(BuiltinGeoLocator.locate("192.168.1.10").is_none());
+        assert!(BuiltinGeoLocator.locate("not-an-ip").is_none());
+    }
+
+    #[test]
+    fn test_missing_maxmind_database_is_skipped() {
+        assert!(MaxMindGeoLocator::open("data/does-not-exist/GeoLite2-City.mmdb").is_none());
+
+        let names: BTreeMap<&str, &str> = [("en", "Germany"), ("zh-CN", "德国")].into_iter().collect();
+        assert_eq!(localized_name(Some(&names)).as_deref(), Some("德国"));
+        let english: BTreeMap<&str, &str> = [("en", "Berlin")].into_iter().collect();
+        assert_eq!(localized_name(Some(&english)).as_deref(), Some("Berlin"));
+        assert_eq!(localized_name(None), None);
+    }
+}
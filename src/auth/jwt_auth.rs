@@ -0,0 +1,545 @@
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+#[cfg(test)]
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+#[cfg(test)]
+use tokio::sync::RwLock;
+
+use crate::config::AppConfig;
+use crate::message::UserType;
+use crate::redis_client::RedisManager;
+
+const REFRESH_TOKEN_KEY_PREFIX: &str = "jwt:refresh:";
+const REFRESH_USED_KEY_PREFIX: &str = "jwt:refresh:used:";
+const REFRESH_FAMILY_REVOKED_KEY_PREFIX: &str = "jwt:refresh:revoked:";
+const REFRESH_USER_REVOKED_KEY_PREFIX: &str = "jwt:refresh:logout:";
+
+/// HS256 签名密钥的最小长度（字节）
+pub const MIN_SECRET_LEN: usize = 32;
+
+/// 配置文件中附带的示例密钥，生产环境不能使用
+const PLACEHOLDER_SECRETS: &[&str] = &["your-secret-key-here", "development-secret-key-not-for-production"];
+
+/// JWT 载荷
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JwtClaims {
+    /// 用户ID
+    pub sub: String,
+    /// 显示名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// 用户类型，以签发方为准
+    pub user_type: UserType,
+    /// 签发时间（Unix秒）
+    pub iat: i64,
+    /// 过期时间（Unix秒）
+    pub exp: i64,
+}
+
+/// HS256 JWT 签发与校验
+#[derive(Clone)]
+pub struct JwtAuth {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    expiry_seconds: i64,
+}
+
+impl JwtAuth {
+    pub fn new(secret: &str, expiry_seconds: u64) -> Self {
+        Self {
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            expiry_seconds: expiry_seconds as i64,
+        }
+    }
+
+    /// 启动时检查 security.jwtSecret：客服身份只凭该密钥签发的令牌认定，
+    /// 生产环境拒绝空密钥、示例密钥和短于 MIN_SECRET_LEN 的密钥，其他环境只告警
+    pub fn check_secret(secret: &str, environment: &str) -> Result<()> {
+        let problem = if secret.is_empty() {
+            "未配置"
+        } else if PLACEHOLDER_SECRETS.contains(&secret) {
+            "仍是配置文件中的示例值"
+        } else if secret.len() < MIN_SECRET_LEN {
+            "长度不足32字节"
+        } else {
+            return Ok(());
+        };
+        if environment.eq_ignore_ascii_case("production") {
+            return Err(anyhow!("JWT密钥{}，生产环境拒绝启动，请通过 JWT_SECRET 设置随机密钥", problem));
+        }
+        tracing::warn!("⚠️ JWT密钥{}，仅可用于 {} 环境", problem, environment);
+        Ok(())
+    }
+
+    /// 使用 security.jwtSecret / security.jwtExpiry 创建
+    pub fn from_config() -> Self {
+        let security = &AppConfig::get().security;
+        Self::new(&security.jwt_secret, security.jwt_expiry)
+    }
+
+    pub fn expiry_seconds(&self) -> i64 {
+        self.expiry_seconds
+    }
+
+    /// 为用户签发令牌
+    pub fn issue(&self, user_id: &str, user_name: Option<&str>, user_type: UserType) -> Result<String> {
+        let now = chrono::Utc::now().timestamp();
+        self.encode(&JwtClaims {
+            sub: user_id.to_string(),
+            name: user_name.map(str::to_string),
+            user_type,
+            iat: now,
+            exp: now + self.expiry_seconds,
+        })
+    }
+
+    pub fn encode(&self, claims: &JwtClaims) -> Result<String> {
+        Ok(jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &self.encoding_key)?)
+    }
+
+    /// 校验签名与有效期，返回载荷；只接受 HS256
+    pub fn verify(&self, token: &str) -> Result<JwtClaims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        let claims = jsonwebtoken::decode::<JwtClaims>(token, &self.decoding_key, &validation)
+            .map_err(|e| anyhow!("令牌无效: {}", e))?
+            .claims;
+        // exp 恰好等于当前时间也视为过期
+        if claims.exp <= chrono::Utc::now().timestamp() {
+            return Err(anyhow!("令牌已过期"));
+        }
+        Ok(claims)
+    }
+}
+
+/// 访问令牌和刷新令牌
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    /// 访问令牌有效期（秒）
+    pub expires_in: i64,
+    /// 刷新令牌有效期（秒）
+    pub refresh_expires_in: i64,
+}
+
+/// 刷新令牌对应的登录信息，按令牌哈希保存，不保存令牌原文
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RefreshTokenRecord {
+    pub user_id: String,
+    pub user_name: Option<String>,
+    pub user_type: UserType,
+    /// 同一次登录轮换出的刷新令牌属于同一族，重复使用时整族吊销
+    pub family: String,
+    pub expires_at: i64,
+    /// 签发时间（毫秒），早于用户登出时间的令牌失效
+    #[serde(default)]
+    pub issued_at: i64,
+    /// 取出时该令牌已被使用过
+    #[serde(default)]
+    pub used: bool,
+}
+
+/// 刷新令牌存储
+#[async_trait::async_trait]
+pub trait RefreshTokenStore: Send + Sync {
+    async fn save(&self, token_hash: &str, record: &RefreshTokenRecord, ttl_seconds: i64) -> Result<()>;
+    /// 取出记录并原子地标记为已使用，之前已使用过的记录 used 为 true
+    async fn consume(&self, token_hash: &str) -> Result<Option<RefreshTokenRecord>>;
+    async fn revoke_family(&self, family: &str, ttl_seconds: i64) -> Result<()>;
+    async fn is_family_revoked(&self, family: &str) -> Result<bool>;
+    /// 记录用户登出时间（毫秒），此前签发的刷新令牌全部失效
+    async fn revoke_user(&self, user_id: &str, revoked_at: i64, ttl_seconds: i64) -> Result<()>;
+    async fn user_revoked_at(&self, user_id: &str) -> Result<Option<i64>>;
+}
+
+/// 刷新时复查账号状态，账号被禁用后不再换发令牌
+#[async_trait::async_trait]
+pub trait AccountStatus: Send + Sync {
+    async fn is_disabled(&self, user_id: &str, user_type: &UserType) -> Result<bool>;
+}
+
+/// 基于Redis的刷新令牌存储，多实例共享，过期由Redis TTL清理
+pub struct RedisRefreshTokenStore {
+    redis: RedisManager,
+}
+
+impl RedisRefreshTokenStore {
+    pub fn new(redis: RedisManager) -> Self {
+        Self { redis }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    async fn save(&self, token_hash: &str, record: &RefreshTokenRecord, ttl_seconds: i64) -> Result<()> {
+        let mut conn = self.redis.get_async_connection().await?;
+        conn.set_ex(
+            format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, token_hash),
+            serde_json::to_string(record)?,
+            ttl_seconds,
+        )
+        .await
+    }
+
+    async fn consume(&self, token_hash: &str) -> Result<Option<RefreshTokenRecord>> {
+        let key = format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, token_hash);
+        let mut conn = self.redis.get_async_connection().await?;
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        let mut record: RefreshTokenRecord = serde_json::from_str(&conn.get(&key).await?)?;
+
+        // SET NX 保证并发刷新时只有一个请求能使用该令牌
+        let ttl = (record.expires_at - chrono::Utc::now().timestamp()).max(1);
+        let mut pipe = redis::pipe();
+        pipe.cmd("SET")
+            .arg(format!("{}{}", REFRESH_USED_KEY_PREFIX, token_hash))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl);
+        let (first_use,): (Option<String>,) = conn.query_pipeline(&pipe).await?;
+        record.used = first_use.is_none();
+        Ok(Some(record))
+    }
+
+    async fn revoke_family(&self, family: &str, ttl_seconds: i64) -> Result<()> {
+        let mut conn = self.redis.get_async_connection().await?;
+        conn.set_ex(
+            format!("{}{}", REFRESH_FAMILY_REVOKED_KEY_PREFIX, family),
+            "1".to_string(),
+            ttl_seconds,
+        )
+        .await
+    }
+
+    async fn is_family_revoked(&self, family: &str) -> Result<bool> {
+        let mut conn = self.redis.get_async_connection().await?;
+        conn.exists(&format!("{}{}", REFRESH_FAMILY_REVOKED_KEY_PREFIX, family)).await
+    }
+
+    async fn revoke_user(&self, user_id: &str, revoked_at: i64, ttl_seconds: i64) -> Result<()> {
+        let mut conn = self.redis.get_async_connection().await?;
+        conn.set_ex(
+            format!("{}{}", REFRESH_USER_REVOKED_KEY_PREFIX, user_id),
+            revoked_at.to_string(),
+            ttl_seconds,
+        )
+        .await
+    }
+
+    async fn user_revoked_at(&self, user_id: &str) -> Result<Option<i64>> {
+        let key = format!("{}{}", REFRESH_USER_REVOKED_KEY_PREFIX, user_id);
+        let mut conn = self.redis.get_async_connection().await?;
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        Ok(conn.get(&key).await?.parse().ok())
+    }
+}
+
+/// 进程内刷新令牌存储，用于测试
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryRefreshTokenStore {
+    tokens: RwLock<HashMap<String, RefreshTokenRecord>>,
+    revoked_families: RwLock<HashSet<String>>,
+    revoked_users: RwLock<HashMap<String, i64>>,
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl RefreshTokenStore for MemoryRefreshTokenStore {
+    async fn save(&self, token_hash: &str, record: &RefreshTokenRecord, _ttl_seconds: i64) -> Result<()> {
+        self.tokens.write().await.insert(token_hash.to_string(), record.clone());
+        Ok(())
+    }
+
+    async fn consume(&self, token_hash: &str) -> Result<Option<RefreshTokenRecord>> {
+        let mut tokens = self.tokens.write().await;
+        Ok(tokens.get_mut(token_hash).map(|record| {
+            let found = record.clone();
+            record.used = true;
+            found
+        }))
+    }
+
+    async fn revoke_family(&self, family: &str, _ttl_seconds: i64) -> Result<()> {
+        self.revoked_families.write().await.insert(family.to_string());
+        Ok(())
+    }
+
+    async fn is_family_revoked(&self, family: &str) -> Result<bool> {
+        Ok(self.revoked_families.read().await.contains(family))
+    }
+
+    async fn revoke_user(&self, user_id: &str, revoked_at: i64, _ttl_seconds: i64) -> Result<()> {
+        self.revoked_users.write().await.insert(user_id.to_string(), revoked_at);
+        Ok(())
+    }
+
+    async fn user_revoked_at(&self, user_id: &str) -> Result<Option<i64>> {
+        Ok(self.revoked_users.read().await.get(user_id).copied())
+    }
+}
+
+/// 刷新令牌轮换：每次刷新签发新的令牌对并作废旧刷新令牌，旧令牌被再次使用时视为泄露，吊销整个登录
+pub struct RefreshTokenManager {
+    jwt: JwtAuth,
+    store: Arc<dyn RefreshTokenStore>,
+    refresh_expiry_seconds: i64,
+    account_status: Option<Arc<dyn AccountStatus>>,
+}
+
+impl RefreshTokenManager {
+    pub fn new(jwt: JwtAuth, store: Arc<dyn RefreshTokenStore>, refresh_expiry_seconds: u64) -> Self {
+        Self {
+            jwt,
+            store,
+            refresh_expiry_seconds: refresh_expiry_seconds as i64,
+            account_status: None,
+        }
+    }
+
+    /// 刷新时复查账号是否被禁用
+    pub fn with_account_status(mut self, account_status: Arc<dyn AccountStatus>) -> Self {
+        self.account_status = Some(account_status);
+        self
+    }
+
+    /// 登出：吊销该用户此前签发的全部刷新令牌（含轮换出的整族），已签发的访问令牌到期前仍有效
+    pub async fn revoke_user(&self, user_id: &str) -> Result<()> {
+        self.store
+            .revoke_user(user_id, chrono::Utc::now().timestamp_millis(), self.refresh_expiry_seconds)
+            .await?;
+        tracing::info!("🔑 已吊销用户的刷新令牌: user_id={}", user_id);
+        Ok(())
+    }
+
+    /// 登录成功后签发令牌对，开始新的令牌族
+    pub async fn issue_pair(&self, user_id: &str, user_name: Option<&str>, user_type: UserType) -> Result<TokenPair> {
+        let family = uuid::Uuid::new_v4().simple().to_string();
+        self.issue_in_family(user_id, user_name, user_type, &family).await
+    }
+
+    /// 用刷新令牌换取新的令牌对，旧刷新令牌随即失效
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair> {
+        let record = self
+            .store
+            .consume(&hash_refresh_token(refresh_token))
+            .await?
+            .ok_or_else(|| anyhow!("刷新令牌无效或已过期"))?;
+        if record.expires_at <= chrono::Utc::now().timestamp() {
+            return Err(anyhow!("刷新令牌无效或已过期"));
+        }
+        let logged_out = matches!(
+            self.store.user_revoked_at(&record.user_id).await?,
+            Some(revoked_at) if record.issued_at <= revoked_at
+        );
+        if logged_out || self.store.is_family_revoked(&record.family).await? {
+            return Err(anyhow!("刷新令牌已被吊销，请重新登录"));
+        }
+        if record.used {
+            self.store.revoke_family(&record.family, self.refresh_expiry_seconds).await?;
+            tracing::warn!(
+                "🚨 检测到刷新令牌重复使用，可能已泄露，已吊销该登录的全部刷新令牌: user_id={}",
+                record.user_id
+            );
+            return Err(anyhow!("刷新令牌已被使用，请重新登录"));
+        }
+        if let Some(account_status) = &self.account_status {
+            if account_status.is_disabled(&record.user_id, &record.user_type).await? {
+                tracing::warn!("🔑 账号已被禁用，拒绝刷新令牌: user_id={}", record.user_id);
+                return Err(anyhow!("账号已被禁用，请联系管理员"));
+            }
+        }
+
+        tracing::info!("🔑 刷新令牌轮换: user_id={}", record.user_id);
+        self.issue_in_family(&record.user_id, record.user_name.as_deref(), record.user_type, &record.family)
+            .await
+    }
+
+    async fn issue_in_family(
+        &self,
+        user_id: &str,
+        user_name: Option<&str>,
+        user_type: UserType,
+        family: &str,
+    ) -> Result<TokenPair> {
+        let access_token = self.jwt.issue(user_id, user_name, user_type.clone())?;
+        let refresh_token = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let record = RefreshTokenRecord {
+            user_id: user_id.to_string(),
+            user_name: user_name.map(str::to_string),
+            user_type,
+            family: family.to_string(),
+            expires_at: chrono::Utc::now().timestamp() + self.refresh_expiry_seconds,
+            issued_at: chrono::Utc::now().timestamp_millis(),
+            used: false,
+        };
+        self.store
+            .save(&hash_refresh_token(&refresh_token), &record, self.refresh_expiry_seconds)
+            .await?;
+        Ok(TokenPair {
+            access_token,
+            refresh_token,
+            token_type: "Bearer".to_string(),
+            expires_in: self.jwt.expiry_seconds(),
+            refresh_expires_in: self.refresh_expiry_seconds,
+        })
+    }
+}
+
+fn hash_refresh_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 定长比较，避免通过响应时间猜测密钥
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// HMAC-SHA256 (RFC 2104)
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC接受任意长度的密钥");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+    #[test]
+    fn test_hmac_sha256_rfc4231_vector() {
+        // RFC 4231 Test Case 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn test_check_secret_rejects_weak_secrets_in_production() {
+        let strong = "0123456789abcdef0123456789abcdef";
+        assert!(JwtAuth::check_secret(strong, "production").is_ok());
+        for weak in ["", "your-secret-key-here", "development-secret-key-not-for-production", "short-secret"] {
+            assert!(JwtAuth::check_secret(weak, "production").is_err(), "{:?}", weak);
+            assert!(JwtAuth::check_secret(weak, "development").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_issue_and_verify() {
+        let auth = JwtAuth::new("test-secret", 3600);
+        let token = auth.issue("kefu_001", Some("客服小王"), UserType::Kefu).unwrap();
+        let claims = auth.verify(&token).unwrap();
+        assert_eq!(claims.sub, "kefu_001");
+        assert_eq!(claims.name.as_deref(), Some("客服小王"));
+        assert_eq!(claims.user_type, UserType::Kefu);
+    }
+
+    #[test]
+    fn test_reject_tampered_and_expired() {
+        let auth = JwtAuth::new("test-secret", 3600);
+        let token = auth.issue("kehu_001", None, UserType::Kehu).unwrap();
+        assert!(JwtAuth::new("other-secret", 3600).verify(&token).is_err());
+
+        // 篡改载荷中的用户类型
+        let forged = JwtClaims {
+            sub: "kehu_001".to_string(),
+            name: None,
+            user_type: UserType::Kefu,
+            iat: 0,
+            exp: i64::MAX,
+        };
+        let forged_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        let mut parts: Vec<&str> = token.split('.').collect();
+        parts[1] = &forged_payload;
+        assert!(auth.verify(&parts.join(".")).is_err());
+
+        let expired = JwtAuth::new("test-secret", 0).issue("kehu_001", None, UserType::Kehu).unwrap();
+        assert!(auth.verify(&expired).is_err());
+        assert!(auth.verify("not-a-token").is_err());
+    }
+
+    fn refresh_manager() -> RefreshTokenManager {
+        RefreshTokenManager::new(
+            JwtAuth::new("test-secret", 3600),
+            Arc::new(MemoryRefreshTokenStore::default()),
+            86400,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotates_token_pair() {
+        let manager = refresh_manager();
+        let first = manager.issue_pair("kefu_001", Some("客服小王"), UserType::Kefu).await.unwrap();
+        assert_eq!(first.expires_in, 3600);
+
+        let second = manager.refresh(&first.refresh_token).await.unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        let claims = JwtAuth::new("test-secret", 3600).verify(&second.access_token).unwrap();
+        assert_eq!(claims.sub, "kefu_001");
+        assert_eq!(claims.name.as_deref(), Some("客服小王"));
+        assert_eq!(claims.user_type, UserType::Kefu);
+
+        // 新令牌可以继续轮换，未知令牌被拒绝
+        assert!(manager.refresh(&second.refresh_token).await.is_ok());
+        assert!(manager.refresh("unknown").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reused_refresh_token_revokes_family() {
+        let manager = refresh_manager();
+        let first = manager.issue_pair("kehu_001", None, UserType::Kehu).await.unwrap();
+        let second = manager.refresh(&first.refresh_token).await.unwrap();
+
+        // 旧令牌被重放，视为泄露：本次失败，同一登录轮换出的令牌全部失效
+        assert!(manager.refresh(&first.refresh_token).await.is_err());
+        assert!(manager.refresh(&second.refresh_token).await.is_err());
+
+        // 其他登录不受影响
+        let other = manager.issue_pair("kehu_001", None, UserType::Kehu).await.unwrap();
+        let rotated = manager.refresh(&other.refresh_token).await.unwrap();
+        let claims = JwtAuth::new("test-secret", 3600).verify(&rotated.access_token).unwrap();
+        assert_eq!(claims.user_type, UserType::Kehu);
+    }
+
+    struct DisabledAccounts(Vec<&'static str>);
+
+    #[async_trait::async_trait]
+    impl AccountStatus for DisabledAccounts {
+        async fn is_disabled(&self, user_id: &str, _user_type: &UserType) -> Result<bool> {
+            Ok(self.0.contains(&user_id))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logout_and_disabled_account_stop_refresh() {
+        let manager = refresh_manager().with_account_status(Arc::new(DisabledAccounts(vec!["kefu_002"])));
+        let first = manager.issue_pair("kefu_001", None, UserType::Kefu).await.unwrap();
+        let rotated = manager.refresh(&first.refresh_token).await.unwrap();
+
+        // 登出后轮换出的令牌同样失效，重新登录后恢复
+        manager.revoke_user("kefu_001").await.unwrap();
+        assert!(manager.refresh(&rotated.refresh_token).await.is_err());
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let relogin = manager.issue_pair("kefu_001", None, UserType::Kefu).await.unwrap();
+        assert!(manager.refresh(&relogin.refresh_token).await.is_ok());
+
+        let disabled = manager.issue_pair("kefu_002", None, UserType::Kefu).await.unwrap();
+        assert!(manager.refresh(&disabled.refresh_token).await.is_err());
+    }
+}
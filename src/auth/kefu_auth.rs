@@ -6,6 +6,8 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use tracing::{info, warn, error};
 
+use crate::auth::jwt_auth::AccountStatus;
+use crate::message::UserType;
 use crate::redis_pool::RedisPoolManager;
 
 /// 客服认证信息
@@ -145,11 +147,11 @@ impl KefuAuthManager {
         // 保存到Redis
         let key = format!("kefu:online:{}", kefu_auth.kefu_id);
         let status_json = serde_json::to_string(&online_status)?;
-        conn.set_ex(&key, status_json, 3600).await?; // 1小时过期
+        let _: () = conn.set_ex(&key, status_json, 3600).await?; // 1小时过期
         
         // 添加到在线列表
         let online_list_key = "kefu:online:list";
-        conn.sadd(&online_list_key, &kefu_auth.kefu_id).await?;
+        let _: () = conn.sadd(online_list_key, &kefu_auth.kefu_id).await?;
         
         info!("✅ 客服上线成功: {}", kefu_auth.kefu_id);
         Ok(true)
@@ -163,11 +165,11 @@ impl KefuAuthManager {
         
         // 删除在线状态
         let key = format!("kefu:online:{}", kefu_id);
-        conn.del(&key).await?;
+        let _: () = conn.del(&key).await?;
         
         // 从在线列表移除
         let online_list_key = "kefu:online:list";
-        conn.srem(&online_list_key, kefu_id).await?;
+        let _: () = conn.srem(online_list_key, kefu_id).await?;
         
         info!("✅ 客服下线完成: {}", kefu_id);
         Ok(())
@@ -188,7 +190,7 @@ impl KefuAuthManager {
             if let Ok(mut status) = serde_json::from_str::<KefuOnlineStatus>(&json) {
                 status.last_heartbeat = chrono::Utc::now();
                 let updated_json = serde_json::to_string(&status)?;
-                conn.set_ex(&key, updated_json, 3600).await?;
+                let _: () = conn.set_ex(&key, updated_json, 3600).await?;
             }
         }
         
@@ -200,7 +202,7 @@ impl KefuAuthManager {
         let mut conn = self.redis_pool.get_connection().await?;
         let online_list_key = "kefu:online:list";
         
-        let kefu_ids: Vec<String> = conn.smembers(&online_list_key).await?;
+        let kefu_ids: Vec<String> = conn.smembers(online_list_key).await?;
         let mut online_kefu = Vec::new();
         
         for kefu_id in kefu_ids {
@@ -239,7 +241,7 @@ impl KefuAuthManager {
             // 记录客户-客服关系
             let mut conn = self.redis_pool.get_connection().await?;
             let customer_key = format!("customer:kefu:{}", customer_id);
-            conn.set_ex(&customer_key, &kefu.kefu_id, 3600).await?;
+            let _: () = conn.set_ex(&customer_key, &kefu.kefu_id, 3600).await?;
             
             return Ok(Some(kefu.kefu_id.clone()));
         }
@@ -262,7 +264,7 @@ impl KefuAuthManager {
                 }
                 
                 let updated_json = serde_json::to_string(&status)?;
-                conn.set_ex(&key, updated_json, 3600).await?;
+                let _: () = conn.set_ex(&key, updated_json, 3600).await?;
             }
         }
         
@@ -276,7 +278,7 @@ impl KefuAuthManager {
         
         if let Ok(Some(kefu_id)) = conn.get::<_, Option<String>>(&customer_key).await {
             self.increment_kefu_customers(&kefu_id, -1).await?;
-            conn.del(&customer_key).await?;
+            let _: () = conn.del(&customer_key).await?;
             info!("✅ 为客户 {} 释放客服: {}", customer_id, kefu_id);
         }
         
@@ -309,7 +311,7 @@ impl KefuAuthManager {
         let mut conn = self.redis_pool.get_connection().await?;
         let online_list_key = "kefu:online:list";
         
-        let kefu_ids: Vec<String> = conn.smembers(&online_list_key).await?;
+        let kefu_ids: Vec<String> = conn.smembers(online_list_key).await?;
         let now = chrono::Utc::now();
         
         for kefu_id in kefu_ids {
@@ -328,4 +330,20 @@ impl KefuAuthManager {
         
         Ok(())
     }
+}
+
+#[async_trait::async_trait]
+impl AccountStatus for KefuAuthManager {
+    // 账号按 kefu_id 和用户名两个键缓存；不在本地账号表中的客服（如单点登录创建的）不在此限制
+    async fn is_disabled(&self, user_id: &str, user_type: &UserType) -> Result<bool> {
+        if *user_type != UserType::Kefu {
+            return Ok(false);
+        }
+        Ok(self
+            .kefu_accounts
+            .read()
+            .await
+            .values()
+            .any(|account| account.kefu_id == user_id && !account.is_active))
+    }
 }
\ No newline at end of file
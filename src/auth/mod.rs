@@ -1,5 +1,11 @@
 pub mod middleware;
 pub mod websocket;
 pub mod kefu_auth;
+pub mod jwt_auth;
+pub mod geo_risk;
+pub mod customer_manager;
+pub mod sso;
+pub mod operator;
+pub mod session_crypto;
 
 // pub use middleware::extract_user_info; // 暂时注释，如果需要可以取消注释 
\ No newline at end of file
@@ -0,0 +1,77 @@
+use crate::auth::jwt_auth::{JwtAuth, JwtClaims};
+use crate::handlers::system_extended::verify_admin_token;
+use crate::message::UserType;
+
+/// 取 Authorization: Bearer 中的JWT并校验，客服和客户的令牌都接受
+pub fn bearer_claims(authorization: Option<&str>) -> Option<JwtClaims> {
+    verify_bearer(&JwtAuth::from_config(), authorization)
+}
+
+fn verify_bearer(jwt: &JwtAuth, authorization: Option<&str>) -> Option<JwtClaims> {
+    let token = authorization?.strip_prefix("Bearer ")?;
+    jwt.verify(token.trim()).ok()
+}
+
+/// 管理类HTTP接口的调用方：持管理令牌的管理员，或持JWT登录的客服
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operator {
+    Admin,
+    Kefu(String),
+}
+
+impl Operator {
+    /// 管理令牌优先；客户令牌、无效或过期的令牌返回 None
+    pub fn resolve(authorization: Option<&str>, admin_token: Option<&str>) -> Option<Self> {
+        if verify_admin_token(admin_token) {
+            return Some(Operator::Admin);
+        }
+        bearer_claims(authorization).and_then(Self::from_claims)
+    }
+
+    fn from_claims(claims: JwtClaims) -> Option<Self> {
+        (claims.user_type == UserType::Kefu).then_some(Operator::Kefu(claims.sub))
+    }
+
+    /// 审计、限额中记录的调用方名称
+    pub fn name(&self) -> &str {
+        match self {
+            Operator::Admin => "admin",
+            Operator::Kefu(kefu_id) => kefu_id,
+        }
+    }
+
+    /// 客服只能访问本人参与的会话，管理员为 None 表示不限
+    pub fn kefu_id(&self) -> Option<&str> {
+        match self {
+            Operator::Admin => None,
+            Operator::Kefu(kefu_id) => Some(kefu_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_kefu_tokens_resolve_to_operator() {
+        let jwt = JwtAuth::new("test-secret", 3600);
+        let kefu = format!("Bearer {}", jwt.issue("kefu_001", None, UserType::Kefu).unwrap());
+        let kehu = format!("Bearer {}", jwt.issue("kehu_001", None, UserType::Kehu).unwrap());
+
+        let operator = verify_bearer(&jwt, Some(&kefu)).and_then(Operator::from_claims).unwrap();
+        assert_eq!(operator, Operator::Kefu("kefu_001".to_string()));
+        assert_eq!(operator.kefu_id(), Some("kefu_001"));
+        assert_eq!(operator.name(), "kefu_001");
+
+        // 客户令牌能通过校验，但不是管理接口的调用方
+        let claims = verify_bearer(&jwt, Some(&kehu)).unwrap();
+        assert_eq!(claims.sub, "kehu_001");
+        assert!(Operator::from_claims(claims).is_none());
+
+        assert!(verify_bearer(&jwt, Some("Bearer invalid")).is_none());
+        assert!(verify_bearer(&JwtAuth::new("other-secret", 3600), Some(&kefu)).is_none());
+        assert!(verify_bearer(&jwt, kefu.strip_prefix("Bearer ")).is_none());
+        assert_eq!(Operator::Admin.kefu_id(), None);
+    }
+}
@@ -0,0 +1,320 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::{Duration, Instant};
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// 客户端通过 encryption=aes-256-gcm 并附带 client_key=<Base64 X25519 公钥> 请求会话加密
+pub const SESSION_ENCRYPTION: &str = "aes-256-gcm";
+
+/// 单个密钥最多加密的消息数，超过后轮换
+pub const DEFAULT_ROTATE_AFTER_MESSAGES: u64 = 1000;
+
+/// 单个密钥的最长使用时长，超过后轮换
+pub const DEFAULT_ROTATE_AFTER: Duration = Duration::from_secs(30 * 60);
+
+/// 轮换后旧密钥的保留时长，用于解密轮换前已发出的在途消息
+pub const DEFAULT_KEY_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+const NONCE_LEN: usize = 12;
+
+const KEY_EXCHANGE_SALT: &[u8] = b"kefu-session-x25519";
+
+/// 密钥轮换策略，消息数或时长任一达到阈值即轮换
+#[derive(Debug, Clone)]
+pub struct KeyRotationPolicy {
+    pub max_messages: u64,
+    pub max_age: Duration,
+    pub grace_period: Duration,
+}
+
+impl Default for KeyRotationPolicy {
+    fn default() -> Self {
+        Self {
+            max_messages: DEFAULT_ROTATE_AFTER_MESSAGES,
+            max_age: DEFAULT_ROTATE_AFTER,
+            grace_period: DEFAULT_KEY_GRACE_PERIOD,
+        }
+    }
+}
+
+/// 加密后的消息帧，以 {"type":"Encrypted", ...} 的文本帧收发；key_id 告知对端使用哪一代密钥
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename = "Encrypted")]
+pub struct EncryptedFrame {
+    pub key_id: u32,
+    /// Base64 编码的 12 字节随机数
+    pub nonce: String,
+    /// Base64 编码的密文（含 GCM 认证标签）
+    pub ciphertext: String,
+}
+
+struct SessionKey {
+    id: u32,
+    cipher: Aes256Gcm,
+    created_at: Instant,
+    messages: u64,
+}
+
+impl SessionKey {
+    /// 各代密钥由协商出的会话密钥材料经 HKDF 按 key_id 派生，双方独立计算，轮换时无需传输新密钥
+    fn derive(session_secret: &[u8; 32], id: u32) -> Self {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::from_prk(session_secret)
+            .expect("32字节的PRK满足HKDF-SHA256长度要求")
+            .expand(format!("kefu-session-key:{}", id).as_bytes(), &mut key)
+            .expect("32字节输出不超过HKDF上限");
+        Self {
+            id,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+            created_at: Instant::now(),
+            messages: 0,
+        }
+    }
+}
+
+/// 解析 Base64 编码的 32 字节 X25519 公钥
+pub fn parse_public_key(value: &str) -> Result<[u8; 32]> {
+    STANDARD
+        .decode(value)?
+        .try_into()
+        .map_err(|_| anyhow!("X25519公钥长度错误"))
+}
+
+/// 一次 X25519 临时密钥协商：双方各生成临时密钥对并交换公钥，会话密钥材料不经网络传输
+pub struct KeyExchange {
+    secret: EphemeralSecret,
+    public: PublicKey,
+}
+
+impl KeyExchange {
+    pub fn new() -> Self {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    /// 本端临时公钥（Base64），服务端随 Welcome 消息下发
+    pub fn public_key(&self) -> String {
+        STANDARD.encode(self.public.as_bytes())
+    }
+
+    /// 用对端公钥完成协商，经 HKDF 提取会话密钥材料；双方公钥按字节序拼接为盐，与角色无关
+    pub fn complete(self, peer_public: [u8; 32], policy: KeyRotationPolicy) -> Result<SessionCipher> {
+        let own_public = *self.public.as_bytes();
+        let shared = self.secret.diffie_hellman(&PublicKey::from(peer_public));
+        // 低阶点会得到全零共享密钥，攻击者可借此预知会话密钥
+        if !shared.was_contributory() {
+            return Err(anyhow!("对端X25519公钥无效"));
+        }
+
+        let (low, high) = if own_public <= peer_public { (own_public, peer_public) } else { (peer_public, own_public) };
+        let salt = [KEY_EXCHANGE_SALT, &low, &high].concat();
+        let (prk, _) = Hkdf::<Sha256>::extract(Some(&salt), shared.as_bytes());
+        Ok(SessionCipher::new(prk.into(), policy))
+    }
+}
+
+impl Default for KeyExchange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 单个WebSocket连接的消息加解密：按策略轮换密钥，上一代密钥在宽限期内仍可解密
+pub struct SessionCipher {
+    session_secret: [u8; 32],
+    policy: KeyRotationPolicy,
+    current: SessionKey,
+    /// 上一代密钥及其失效时间
+    previous: Option<(SessionKey, Instant)>,
+}
+
+impl SessionCipher {
+    pub fn new(session_secret: [u8; 32], policy: KeyRotationPolicy) -> Self {
+        Self {
+            current: SessionKey::derive(&session_secret, 0),
+            session_secret,
+            policy,
+            previous: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn current_key_id(&self) -> u32 {
+        self.current.id
+    }
+
+    fn needs_rotation(&self) -> bool {
+        self.current.messages >= self.policy.max_messages || self.current.created_at.elapsed() >= self.policy.max_age
+    }
+
+    /// 切换到下一代密钥，返回新的 key_id；当前密钥降为上一代，宽限期内仍可解密
+    pub fn rotate(&mut self) -> u32 {
+        let next = SessionKey::derive(&self.session_secret, self.current.id.wrapping_add(1));
+        let previous = std::mem::replace(&mut self.current, next);
+        self.previous = Some((previous, Instant::now() + self.policy.grace_period));
+        tracing::info!("🔑 会话密钥已轮换: key_id={}", self.current.id);
+        self.current.id
+    }
+
+    pub fn encrypt(&mut self, plaintext: &str) -> Result<EncryptedFrame> {
+        if self.needs_rotation() {
+            self.rotate();
+        }
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = self.current.id.to_be_bytes();
+        let ciphertext = self
+            .current
+            .cipher
+            .encrypt(&nonce, Payload { msg: plaintext.as_bytes(), aad: &aad })
+            .map_err(|_| anyhow!("会话消息加密失败"))?;
+        self.current.messages += 1;
+
+        Ok(EncryptedFrame {
+            key_id: self.current.id,
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    /// 解密对端发来的帧。对端先行轮换时（key_id 为下一代）校验通过后本端随之轮换
+    pub fn decrypt(&mut self, frame: &EncryptedFrame) -> Result<String> {
+        if self.previous.as_ref().is_some_and(|(_, expires_at)| *expires_at <= Instant::now()) {
+            self.previous = None;
+        }
+
+        if frame.key_id == self.current.id {
+            return open_frame(&self.current, frame);
+        }
+        if let Some((previous, _)) = self.previous.as_ref().filter(|(previous, _)| previous.id == frame.key_id) {
+            return open_frame(previous, frame);
+        }
+        if frame.key_id == self.current.id.wrapping_add(1) {
+            let plaintext = open_frame(&SessionKey::derive(&self.session_secret, frame.key_id), frame)?;
+            self.rotate();
+            return Ok(plaintext);
+        }
+        Err(anyhow!("会话密钥不可用或已过期: key_id={}", frame.key_id))
+    }
+}
+
+fn open_frame(key: &SessionKey, frame: &EncryptedFrame) -> Result<String> {
+    let nonce = STANDARD.decode(&frame.nonce)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(anyhow!("加密帧随机数长度错误"));
+    }
+    let ciphertext = STANDARD.decode(&frame.ciphertext)?;
+    let plaintext = key
+        .cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &key.id.to_be_bytes() })
+        .map_err(|_| anyhow!("加密帧校验失败"))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_messages: u64, grace_period: Duration) -> KeyRotationPolicy {
+        KeyRotationPolicy {
+            max_messages,
+            max_age: Duration::from_secs(3600),
+            grace_period,
+        }
+    }
+
+    #[test]
+    fn test_message_encrypted_before_rotation_decrypts_after() {
+        let secret = rand::random();
+        let mut sender = SessionCipher::new(secret, policy(2, Duration::from_secs(60)));
+        let mut receiver = SessionCipher::new(secret, policy(2, Duration::from_secs(60)));
+
+        let first = sender.encrypt("第一条").unwrap();
+        let second = sender.encrypt("second").unwrap();
+        // 达到消息数阈值，第三条使用新密钥
+        let third = sender.encrypt("third").unwrap();
+        assert_eq!((first.key_id, second.key_id, third.key_id), (0, 0, 1));
+
+        // 新消息先到达，接收方随之轮换；轮换前加密的在途消息仍可用上一代密钥解密
+        assert_eq!(receiver.decrypt(&third).unwrap(), "third");
+        assert_eq!(receiver.current_key_id(), 1);
+        assert_eq!(receiver.decrypt(&first).unwrap(), "第一条");
+        assert_eq!(receiver.decrypt(&second).unwrap(), "second");
+
+        // 发送方自身轮换后同样能解开轮换前的帧
+        assert_eq!(sender.decrypt(&first).unwrap(), "第一条");
+    }
+
+    #[test]
+    fn test_previous_key_expires_after_grace_period() {
+        let mut cipher = SessionCipher::new(rand::random(), policy(100, Duration::ZERO));
+        let old = cipher.encrypt("old").unwrap();
+        cipher.rotate();
+        let new = cipher.encrypt("new").unwrap();
+        assert_eq!(new.key_id, 1);
+        assert_eq!(cipher.decrypt(&new).unwrap(), "new");
+        assert!(cipher.decrypt(&old).is_err());
+
+        // 只保留上一代密钥，连续轮换两次后更早的帧不可解
+        let mut cipher = SessionCipher::new(rand::random(), policy(100, Duration::from_secs(60)));
+        let oldest = cipher.encrypt("oldest").unwrap();
+        cipher.rotate();
+        cipher.rotate();
+        assert!(cipher.decrypt(&oldest).is_err());
+    }
+
+    #[test]
+    fn test_key_exchange_derives_same_keys_on_both_sides() {
+        let client = KeyExchange::new();
+        let server = KeyExchange::new();
+        let client_public = parse_public_key(&client.public_key()).unwrap();
+        let server_public = parse_public_key(&server.public_key()).unwrap();
+
+        let mut server = server.complete(client_public, KeyRotationPolicy::default()).unwrap();
+        let mut client = client.complete(server_public, KeyRotationPolicy::default()).unwrap();
+        let frame = server.encrypt("欢迎").unwrap();
+        assert_eq!(client.decrypt(&frame).unwrap(), "欢迎");
+        let frame = client.encrypt("hello").unwrap();
+        assert_eq!(server.decrypt(&frame).unwrap(), "hello");
+
+        // 只看到双方公钥的第三方无法解密
+        let mut eavesdropper = KeyExchange::new().complete(client_public, KeyRotationPolicy::default()).unwrap();
+        assert!(eavesdropper.decrypt(&frame).is_err());
+
+        // 低阶点公钥和长度错误的公钥都被拒绝
+        assert!(KeyExchange::new().complete([0u8; 32], KeyRotationPolicy::default()).is_err());
+        assert!(parse_public_key(&STANDARD.encode([1u8; 16])).is_err());
+    }
+
+    #[test]
+    fn test_tampered_or_foreign_frames_rejected() {
+        let mut sender = SessionCipher::new(rand::random(), KeyRotationPolicy::default());
+        let frame = sender.encrypt("hello").unwrap();
+        assert_ne!(frame.ciphertext, STANDARD.encode("hello"));
+        assert_eq!(
+            serde_json::to_value(&frame).unwrap()["type"],
+            serde_json::Value::String("Encrypted".to_string())
+        );
+
+        let mut other = SessionCipher::new(rand::random(), KeyRotationPolicy::default());
+        assert!(other.decrypt(&frame).is_err());
+
+        // 改动 key_id 会使附加认证数据不匹配，伪造的下一代 key_id 也不会触发轮换
+        let mut relabeled = frame.clone();
+        relabeled.key_id = 1;
+        assert!(sender.decrypt(&relabeled).is_err());
+        assert_eq!(sender.current_key_id(), 0);
+
+        let mut tampered = frame;
+        let mut bytes = STANDARD.decode(&tampered.ciphertext).unwrap();
+        bytes[0] ^= 1;
+        tampered.ciphertext = STANDARD.encode(bytes);
+        assert!(sender.decrypt(&tampered).is_err());
+    }
+}
@@ -0,0 +1,631 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::auth::jwt_auth::{JwtAuth, RefreshTokenManager};
+use crate::config::{SsoConfig, SsoProviderConfig};
+use crate::message::UserType;
+use crate::redis_client::RedisManager;
+
+const SSO_USER_KEY_PREFIX: &str = "sso:user:";
+
+/// 发起登录时写入浏览器的 state Cookie，回调时须与 state 参数一致
+pub const SSO_STATE_COOKIE: &str = "kefu_sso_state";
+
+/// 同时等待回调的登录上限，超出时拒绝发起新的登录
+const MAX_PENDING_LOGINS: usize = 10_000;
+
+/// 外部身份关联的本系统用户
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SsoUser {
+    pub provider: String,
+    /// IdP 中的用户标识（sub）
+    pub subject: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub email: Option<String>,
+    pub user_type: UserType,
+    pub created_at: DateTime<Utc>,
+    pub last_login: DateTime<Utc>,
+}
+
+/// IdP userinfo 接口返回中用到的字段
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdpUserInfo {
+    pub sub: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub preferred_username: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// 其余字段，用户组按 groupsClaim 从中读取
+    #[serde(flatten)]
+    pub claims: HashMap<String, serde_json::Value>,
+}
+
+impl IdpUserInfo {
+    /// 用户组字段，支持字符串数组或空格分隔的字符串
+    fn groups(&self, claim: &str) -> Vec<String> {
+        match self.claims.get(claim) {
+            Some(serde_json::Value::Array(groups)) => groups
+                .iter()
+                .filter_map(|group| group.as_str().map(str::to_string))
+                .collect(),
+            Some(serde_json::Value::String(groups)) => groups.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .or_else(|| self.preferred_username.clone())
+            .or_else(|| self.email.clone())
+            .unwrap_or_else(|| self.sub.clone())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    id_token: Option<String>,
+}
+
+/// id_token 中校验的字段
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    #[serde(default)]
+    nonce: Option<String>,
+}
+
+/// 发起登录的结果：跳转地址和需要写入浏览器 Cookie 的 state
+#[derive(Debug, Clone)]
+pub struct SsoAuthorization {
+    pub url: String,
+    pub state: String,
+}
+
+/// 登录页展示的身份提供方
+#[derive(Debug, Clone, Serialize)]
+pub struct SsoProviderInfo {
+    pub id: String,
+    pub name: String,
+}
+
+/// 单点登录成功后返回给前端的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SsoLoginResult {
+    /// 本系统签发的JWT
+    pub access_token: String,
+    /// 用于 /auth/refresh 换取新令牌，未启用刷新令牌时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub user_id: String,
+    pub user_name: String,
+    pub user_type: UserType,
+    /// 首次登录时新建了本系统用户
+    pub created: bool,
+}
+
+/// 外部身份 -> 本系统用户 的映射存储
+#[async_trait::async_trait]
+pub trait SsoUserStore: Send + Sync {
+    async fn find(&self, provider: &str, subject: &str) -> Result<Option<SsoUser>>;
+    async fn save(&self, user: &SsoUser) -> Result<()>;
+}
+
+/// 基于Redis的映射存储，多实例共享
+pub struct RedisSsoUserStore {
+    redis: RedisManager,
+}
+
+impl RedisSsoUserStore {
+    pub fn new(redis: RedisManager) -> Self {
+        Self { redis }
+    }
+
+    fn key(provider: &str, subject: &str) -> String {
+        format!("{}{}:{}", SSO_USER_KEY_PREFIX, provider, subject)
+    }
+}
+
+#[async_trait::async_trait]
+impl SsoUserStore for RedisSsoUserStore {
+    async fn find(&self, provider: &str, subject: &str) -> Result<Option<SsoUser>> {
+        let key = Self::key(provider, subject);
+        let mut conn = self.redis.get_async_connection().await?;
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        let raw = conn.get(&key).await?;
+        Ok(Some(serde_json::from_str(&raw)?))
+    }
+
+    async fn save(&self, user: &SsoUser) -> Result<()> {
+        let key = Self::key(&user.provider, &user.subject);
+        let mut conn = self.redis.get_async_connection().await?;
+        conn.set(&key, &serde_json::to_string(user)?).await
+    }
+}
+
+/// 进程内映射存储，用于测试
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemorySsoUserStore {
+    users: tokio::sync::RwLock<HashMap<(String, String), SsoUser>>,
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl SsoUserStore for MemorySsoUserStore {
+    async fn find(&self, provider: &str, subject: &str) -> Result<Option<SsoUser>> {
+        let users = self.users.read().await;
+        Ok(users.get(&(provider.to_string(), subject.to_string())).cloned())
+    }
+
+    async fn save(&self, user: &SsoUser) -> Result<()> {
+        let mut users = self.users.write().await;
+        users.insert((user.provider.clone(), user.subject.clone()), user.clone());
+        Ok(())
+    }
+}
+
+/// 已发起、等待IdP回调的登录
+struct PendingLogin {
+    provider: String,
+    created_at: DateTime<Utc>,
+    /// PKCE 校验码，换取令牌时提交
+    code_verifier: String,
+    /// OIDC nonce，须与 id_token 中的一致
+    nonce: String,
+}
+
+fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// PKCE S256 质询：校验码 SHA-256 后 base64url 编码
+fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// 读取 id_token 载荷。id_token 由令牌接口经 TLS 直接返回，按 OIDC 规范以 TLS 校验代替签名校验
+fn decode_id_token(id_token: &str) -> Result<IdTokenClaims> {
+    let payload = id_token.split('.').nth(1).ok_or_else(|| anyhow!("id_token 格式错误"))?;
+    Ok(serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload)?)?)
+}
+
+/// 用户类型：属于 kefuGroups 中任一组的用户为客服，否则为提供方的默认类型
+fn resolve_user_type(provider: &SsoProviderConfig, info: &IdpUserInfo) -> UserType {
+    let groups = info.groups(&provider.groups_claim);
+    if provider.kefu_groups.iter().any(|group| groups.contains(group)) {
+        UserType::Kefu
+    } else {
+        provider.user_type.clone()
+    }
+}
+
+/// 本系统用户ID：同一IdP的同一 sub 总是得到同一个ID
+fn local_user_id(provider: &str, subject: &str, user_type: &UserType) -> String {
+    let digest = Sha256::digest(format!("{}:{}", provider, subject).as_bytes());
+    let hex: String = digest.iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    let prefix = match user_type {
+        UserType::Kefu => "kefu",
+        UserType::Kehu => "kehu",
+    };
+    format!("{}_sso_{}", prefix, hex)
+}
+
+/// OAuth2 授权码流程：重定向到IdP、回调换取用户信息、关联本系统用户并签发JWT
+pub struct SsoManager {
+    providers: HashMap<String, SsoProviderConfig>,
+    state_ttl: Duration,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    store: Arc<dyn SsoUserStore>,
+    jwt: JwtAuth,
+    refresh_tokens: Option<Arc<RefreshTokenManager>>,
+    http_client: reqwest::Client,
+}
+
+impl SsoManager {
+    pub fn new(config: &SsoConfig, store: Arc<dyn SsoUserStore>, jwt: JwtAuth) -> Self {
+        Self {
+            providers: config
+                .providers
+                .iter()
+                .map(|provider| (provider.id.clone(), provider.clone()))
+                .collect(),
+            state_ttl: Duration::seconds(config.state_ttl_seconds as i64),
+            pending: Mutex::new(HashMap::new()),
+            store,
+            jwt,
+            refresh_tokens: None,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// 登录成功时同时签发刷新令牌
+    pub fn with_refresh_tokens(mut self, refresh_tokens: Arc<RefreshTokenManager>) -> Self {
+        self.refresh_tokens = Some(refresh_tokens);
+        self
+    }
+
+    pub fn providers(&self) -> Vec<SsoProviderInfo> {
+        let mut providers: Vec<SsoProviderInfo> = self
+            .providers
+            .values()
+            .map(|provider| SsoProviderInfo {
+                id: provider.id.clone(),
+                name: provider.name.clone(),
+            })
+            .collect();
+        providers.sort_by(|a, b| a.id.cmp(&b.id));
+        providers
+    }
+
+    fn provider(&self, provider_id: &str) -> Result<&SsoProviderConfig> {
+        self.providers
+            .get(provider_id)
+            .ok_or_else(|| anyhow!("未配置的身份提供方: {}", provider_id))
+    }
+
+    /// state Cookie 的有效期（秒），与登录状态有效期一致
+    pub fn state_ttl_seconds(&self) -> i64 {
+        self.state_ttl.num_seconds()
+    }
+
+    /// 生成IdP授权地址，附带一次性 state、PKCE 质询和 nonce。
+    /// 返回的 state 须写入浏览器 Cookie，回调时校验，防止他人诱导浏览器完成登录
+    pub fn authorize_url(&self, provider_id: &str) -> Result<SsoAuthorization> {
+        let provider = self.provider(provider_id)?;
+        let state = random_token();
+        let code_verifier = random_token();
+        let nonce = random_token();
+        let now = Utc::now();
+        {
+            let mut pending = self.pending.lock().map_err(|_| anyhow!("登录状态不可用"))?;
+            pending.retain(|_, login| now - login.created_at <= self.state_ttl);
+            if pending.len() >= MAX_PENDING_LOGINS {
+                return Err(anyhow!("等待回调的登录过多，请稍后重试"));
+            }
+            pending.insert(
+                state.clone(),
+                PendingLogin {
+                    provider: provider_id.to_string(),
+                    created_at: now,
+                    code_verifier: code_verifier.clone(),
+                    nonce: nonce.clone(),
+                },
+            );
+        }
+
+        let mut url = url::Url::parse(&provider.authorize_url)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", &provider.redirect_uri)
+            .append_pair("scope", &provider.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("code_challenge", &pkce_challenge(&code_verifier))
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("nonce", &nonce);
+        Ok(SsoAuthorization { url: url.to_string(), state })
+    }
+
+    /// 处理IdP回调：校验 state 与浏览器 Cookie 一致、用授权码和 PKCE 校验码换取令牌，
+    /// 校验 id_token 的 nonce，关联或创建本系统用户并签发JWT
+    pub async fn handle_callback(
+        &self,
+        provider_id: &str,
+        code: &str,
+        state: &str,
+        cookie_state: Option<&str>,
+    ) -> Result<SsoLoginResult> {
+        let provider = self.provider(provider_id)?;
+        if cookie_state != Some(state) {
+            return Err(anyhow!("登录状态与当前浏览器不符，请重新发起登录"));
+        }
+        let login = self.take_state(provider_id, state)?;
+
+        let tokens = self.exchange_code(provider, code, &login.code_verifier).await?;
+        let info = self.fetch_user_info(provider, &tokens.access_token).await?;
+        match &tokens.id_token {
+            Some(id_token) => {
+                let claims = decode_id_token(id_token)?;
+                if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+                    return Err(anyhow!("id_token 的 nonce 不符"));
+                }
+                if claims.sub != info.sub {
+                    return Err(anyhow!("id_token 与用户信息不属于同一用户"));
+                }
+            }
+            None if provider.scopes.iter().any(|scope| scope == "openid") => {
+                return Err(anyhow!("身份提供方未返回 id_token"));
+            }
+            None => {}
+        }
+        let (user, created) = self.link_user(provider, &info).await?;
+
+        let (access_token, refresh_token) = match &self.refresh_tokens {
+            Some(refresh_tokens) => {
+                let pair = refresh_tokens
+                    .issue_pair(&user.user_id, Some(&user.user_name), user.user_type.clone())
+                    .await?;
+                (pair.access_token, Some(pair.refresh_token))
+            }
+            None => (self.jwt.issue(&user.user_id, Some(&user.user_name), user.user_type.clone())?, None),
+        };
+        tracing::info!(
+            "🔑 单点登录成功: provider={}, user_id={}, 新用户={}",
+            provider_id,
+            user.user_id,
+            created
+        );
+        Ok(SsoLoginResult {
+            access_token,
+            refresh_token,
+            user_id: user.user_id,
+            user_name: user.user_name,
+            user_type: user.user_type,
+            created,
+        })
+    }
+
+    // state 只能使用一次，且须由同一提供方在有效期内回调
+    fn take_state(&self, provider_id: &str, state: &str) -> Result<PendingLogin> {
+        let login = self
+            .pending
+            .lock()
+            .map_err(|_| anyhow!("登录状态不可用"))?
+            .remove(state)
+            .ok_or_else(|| anyhow!("登录状态无效或已使用"))?;
+        if login.provider != provider_id {
+            return Err(anyhow!("登录状态与身份提供方不符"));
+        }
+        if Utc::now() - login.created_at > self.state_ttl {
+            return Err(anyhow!("登录已超时，请重新发起"));
+        }
+        Ok(login)
+    }
+
+    async fn exchange_code(&self, provider: &SsoProviderConfig, code: &str, code_verifier: &str) -> Result<TokenResponse> {
+        let response = self
+            .http_client
+            .post(&provider.token_url)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", provider.redirect_uri.as_str()),
+                ("client_id", provider.client_id.as_str()),
+                ("client_secret", provider.client_secret.as_str()),
+                ("code_verifier", code_verifier),
+            ])
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("授权码换取令牌失败: {}", response.status()));
+        }
+        Ok(response.json::<TokenResponse>().await?)
+    }
+
+    async fn fetch_user_info(&self, provider: &SsoProviderConfig, access_token: &str) -> Result<IdpUserInfo> {
+        let response = self
+            .http_client
+            .get(&provider.userinfo_url)
+            .bearer_auth(access_token)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("获取IdP用户信息失败: {}", response.status()));
+        }
+        let info: IdpUserInfo = response.json().await?;
+        if info.sub.is_empty() {
+            return Err(anyhow!("IdP用户信息缺少 sub"));
+        }
+        Ok(info)
+    }
+
+    // 已关联的用户更新资料、用户类型和登录时间，首次登录时创建；用户类型按当前所属组重新判断
+    async fn link_user(&self, provider: &SsoProviderConfig, info: &IdpUserInfo) -> Result<(SsoUser, bool)> {
+        let now = Utc::now();
+        let user_type = resolve_user_type(provider, info);
+        let (user, created) = match self.store.find(&provider.id, &info.sub).await? {
+            Some(mut user) => {
+                user.user_name = info.display_name();
+                user.email = info.email.clone().or(user.email);
+                // 类型变化时换用对应前缀的用户ID，客服身份与客户身份不共用会话记录
+                if user.user_type != user_type {
+                    user.user_id = local_user_id(&provider.id, &info.sub, &user_type);
+                    user.user_type = user_type;
+                }
+                user.last_login = now;
+                (user, false)
+            }
+            None => (
+                SsoUser {
+                    provider: provider.id.clone(),
+                    subject: info.sub.clone(),
+                    user_id: local_user_id(&provider.id, &info.sub, &user_type),
+                    user_name: info.display_name(),
+                    email: info.email.clone(),
+                    user_type,
+                    created_at: now,
+                    last_login: now,
+                },
+                true,
+            ),
+        };
+        self.store.save(&user).await?;
+        Ok((user, created))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    /// 模拟IdP记录的授权请求参数，令牌接口据此校验 PKCE 并在 id_token 中返回 nonce
+    #[derive(Default)]
+    struct AuthorizeRequest {
+        code_challenge: String,
+        nonce: String,
+    }
+
+    type SharedRequest = Arc<Mutex<AuthorizeRequest>>;
+
+    fn id_token(sub: &str, nonce: &str) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::json!({ "sub": sub, "nonce": nonce }).to_string());
+        format!("eyJhbGciOiJSUzI1NiJ9.{}.signature", payload)
+    }
+
+    async fn mock_idp(request: SharedRequest) -> std::net::SocketAddr {
+        let token = warp::path!("token")
+            .and(warp::post())
+            .and(warp::body::form())
+            .map(move |form: HashMap<String, String>| {
+                let request = request.lock().unwrap();
+                let verifier_ok = form
+                    .get("code_verifier")
+                    .is_some_and(|verifier| pkce_challenge(verifier) == request.code_challenge);
+                if form.get("code").map(String::as_str) == Some("good-code")
+                    && form.get("client_secret").map(String::as_str) == Some("secret")
+                    && verifier_ok
+                {
+                    warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({
+                            "access_token": "idp-token",
+                            "token_type": "Bearer",
+                            "id_token": id_token("u-1001", &request.nonce),
+                        })),
+                        warp::http::StatusCode::OK,
+                    )
+                } else {
+                    warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({ "error": "invalid_grant" })),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    )
+                }
+            });
+        let userinfo = warp::path!("userinfo")
+            .and(warp::header::exact("authorization", "Bearer idp-token"))
+            .map(|| {
+                warp::reply::json(&serde_json::json!({
+                    "sub": "u-1001",
+                    "name": "张三",
+                    "email": "zhangsan@example.com",
+                    "groups": ["employees", "customer-service"]
+                }))
+            });
+        let (addr, server) = warp::serve(token.or(userinfo)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    fn manager(addr: std::net::SocketAddr, kefu_groups: &[&str]) -> SsoManager {
+        let config = SsoConfig {
+            providers: vec![SsoProviderConfig {
+                id: "corp".to_string(),
+                name: "企业统一登录".to_string(),
+                authorize_url: format!("http://{}/authorize", addr),
+                token_url: format!("http://{}/token", addr),
+                userinfo_url: format!("http://{}/userinfo", addr),
+                client_id: "kefu-system".to_string(),
+                client_secret: "secret".to_string(),
+                redirect_uri: "http://localhost:6006/api/auth/sso/corp/callback".to_string(),
+                scopes: vec!["openid".to_string(), "email".to_string()],
+                user_type: UserType::Kehu,
+                kefu_groups: kefu_groups.iter().map(|group| group.to_string()).collect(),
+                groups_claim: "groups".to_string(),
+            }],
+            state_ttl_seconds: 600,
+        };
+        SsoManager::new(&config, Arc::new(MemorySsoUserStore::default()), JwtAuth::new("test-secret", 3600))
+    }
+
+    /// 发起登录，并把授权地址中的 PKCE 质询和 nonce 交给模拟IdP
+    fn start_login(sso: &SsoManager, request: &SharedRequest) -> String {
+        let authorization = sso.authorize_url("corp").unwrap();
+        let url = url::Url::parse(&authorization.url).unwrap();
+        let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).unwrap().1.to_string();
+        assert_eq!(param("state"), authorization.state);
+        assert_eq!(param("code_challenge_method"), "S256");
+        *request.lock().unwrap() = AuthorizeRequest {
+            code_challenge: param("code_challenge"),
+            nonce: param("nonce"),
+        };
+        authorization.state
+    }
+
+    #[tokio::test]
+    async fn test_callback_creates_then_links_user_and_issues_token() {
+        let request = SharedRequest::default();
+        let addr = mock_idp(request.clone()).await;
+        let sso = manager(addr, &["customer-service"]);
+
+        let authorize_url = sso.authorize_url("corp").unwrap().url;
+        assert!(authorize_url.contains("client_id=kefu-system"));
+        assert!(authorize_url.contains("scope=openid+email"));
+
+        let state = start_login(&sso, &request);
+        let first = sso.handle_callback("corp", "good-code", &state, Some(&state)).await.unwrap();
+        assert!(first.created);
+        assert!(first.user_id.starts_with("kefu_sso_"));
+        assert_eq!(first.user_name, "张三");
+        let claims = JwtAuth::new("test-secret", 3600).verify(&first.access_token).unwrap();
+        assert_eq!(claims.sub, first.user_id);
+        assert_eq!(claims.user_type, UserType::Kefu);
+
+        // 再次登录关联到同一用户
+        let state = start_login(&sso, &request);
+        let second = sso.handle_callback("corp", "good-code", &state, Some(&state)).await.unwrap();
+        assert!(!second.created);
+        assert_eq!(second.user_id, first.user_id);
+    }
+
+    #[tokio::test]
+    async fn test_users_outside_kefu_groups_login_as_kehu() {
+        let request = SharedRequest::default();
+        let addr = mock_idp(request.clone()).await;
+        let sso = manager(addr, &["admins"]);
+
+        let state = start_login(&sso, &request);
+        let result = sso.handle_callback("corp", "good-code", &state, Some(&state)).await.unwrap();
+        assert_eq!(result.user_type, UserType::Kehu);
+        assert!(result.user_id.starts_with("kehu_sso_"));
+    }
+
+    #[tokio::test]
+    async fn test_callback_rejects_bad_state_cookie_nonce_and_code() {
+        let request = SharedRequest::default();
+        let addr = mock_idp(request.clone()).await;
+        let sso = manager(addr, &[]);
+
+        assert!(sso.authorize_url("unknown").is_err());
+        assert!(sso.handle_callback("corp", "good-code", "forged", Some("forged")).await.is_err());
+
+        // 回调的 state 与浏览器 Cookie 不一致（他人发起的登录）
+        let state = start_login(&sso, &request);
+        assert!(sso.handle_callback("corp", "good-code", &state, None).await.is_err());
+        assert!(sso.handle_callback("corp", "good-code", &state, Some("other")).await.is_err());
+
+        // id_token 的 nonce 不是本次登录的
+        let state = start_login(&sso, &request);
+        request.lock().unwrap().nonce = "replayed".to_string();
+        assert!(sso.handle_callback("corp", "good-code", &state, Some(&state)).await.is_err());
+
+        // state 只能使用一次
+        let state = start_login(&sso, &request);
+        assert!(sso.handle_callback("corp", "bad-code", &state, Some(&state)).await.is_err());
+        assert!(sso.handle_callback("corp", "good-code", &state, Some(&state)).await.is_err());
+    }
+}
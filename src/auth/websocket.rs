@@ -2,15 +2,39 @@ use crate::types::websocket::WebSocketParams;
 use crate::message::UserType;
 use crate::errors::log_websocket_param_error;
 use crate::auth::kefu_auth::KefuAuthManager;
+use crate::auth::jwt_auth::JwtAuth;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
+/// 可能携带凭据的连接参数，记录日志前打码
+const SENSITIVE_PARAMS: &[&str] = &["token", "session_token", "access_token", "refresh_token", "password"];
+
 /// WebSocket连接信息
 pub struct WebSocketConnectionInfo {
     pub user_id: String,
     pub user_name: String,
     pub user_type: UserType,
     pub zhanghao: Option<String>,
+    #[allow(dead_code)]
     pub session_token: Option<String>,
+    /// JWT签发时间，访客连接为 None
+    pub issued_at: Option<i64>,
+}
+
+impl WebSocketConnectionInfo {
+    /// 未携带令牌的访客改用服务端分配的ID，未单独指定的用户名随之更新
+    pub fn with_guest_id(mut self, guest_id: String) -> Self {
+        if self.user_name == self.user_id {
+            self.user_name = guest_id.clone();
+        }
+        self.user_id = guest_id;
+        self
+    }
+}
+
+/// 生成访客客户ID，欢迎消息会把该ID回传给客户端
+pub fn generate_guest_customer_id() -> String {
+    format!("kehu_{}", uuid::Uuid::new_v4().simple())
 }
 
 /// 验证WebSocket连接参数 - 修复版本
@@ -68,41 +92,120 @@ pub fn parse_websocket_connection(query: &WebSocketParams) -> Result<WebSocketCo
         user_type,
         zhanghao,
         session_token,
+        issued_at: None,
     })
 }
 
-/// 验证客服WebSocket连接的认证
-pub async fn validate_kefu_websocket_auth(
-    connection_info: &WebSocketConnectionInfo,
+/// 用于日志输出的连接参数：令牌等凭据替换为 `***`，按参数名排序
+pub fn redacted_params(query: &WebSocketParams) -> BTreeMap<&str, &str> {
+    query
+        .iter()
+        .map(|(key, value)| {
+            let sensitive = SENSITIVE_PARAMS.iter().any(|name| key.eq_ignore_ascii_case(name));
+            (key.as_str(), if sensitive { "***" } else { value.as_str() })
+        })
+        .collect()
+}
+
+/// 从查询参数 `token` 或 `Sec-WebSocket-Protocol` 头中提取JWT
+///
+/// 子协议形式为 `bearer, <token>` 或直接携带令牌；返回令牌及需要回显的子协议
+pub fn extract_websocket_token(
+    query: &WebSocketParams,
+    protocol_header: Option<&str>,
+) -> Option<(String, Option<String>)> {
+    if let Some(token) = query.get("token").filter(|token| !token.is_empty()) {
+        return Some((token.clone(), None));
+    }
+
+    let protocols: Vec<&str> = protocol_header?
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect();
+    let token = match protocols.iter().position(|p| p.eq_ignore_ascii_case("bearer")) {
+        Some(index) => protocols.get(index + 1).copied(),
+        None => protocols.iter().copied().find(|p| p.split('.').count() == 3),
+    }?;
+    Some((token.to_string(), protocols.first().map(|p| p.to_string())))
+}
+
+/// 使用JWT建立WebSocket连接身份，令牌中的用户身份覆盖客户端传入的参数
+pub async fn authenticate_websocket_token(
+    token: &str,
+    query: &WebSocketParams,
+    jwt_auth: &JwtAuth,
     kefu_auth_manager: &Arc<KefuAuthManager>,
-) -> Result<bool, String> {
-    // 只对客服类型进行认证验证
-    if let UserType::Kefu = connection_info.user_type {
-        // 检查是否提供了session_token
-        if let Some(session_token) = &connection_info.session_token {
-            // 验证客服是否在线且token有效
-            match kefu_auth_manager.is_kefu_online(&connection_info.user_id).await {
-                Ok(true) => {
-                    // 这里应该验证session_token的有效性
-                    // 暂时简化验证，只要有token且客服在线就认为有效
-                    if session_token.starts_with("kefu_session_") {
-                        return Ok(true);
-                    } else {
-                        return Err("无效的session_token".to_string());
-                    }
-                }
-                Ok(false) => {
-                    return Err("客服未登录或已下线".to_string());
-                }
-                Err(e) => {
-                    return Err(format!("验证客服状态失败: {}", e));
-                }
-            }
-        } else {
-            return Err("客服类型连接缺少session_token".to_string());
+) -> Result<WebSocketConnectionInfo, String> {
+    let claims = jwt_auth.verify(token).map_err(|e| e.to_string())?;
+
+    if let Some(user_type) = query.get("user_type") {
+        let requested_kefu = matches!(user_type.to_lowercase().as_str(), "kefu" | "support" | "agent");
+        if requested_kefu != (claims.user_type == UserType::Kefu) {
+            tracing::warn!("⚠️ 客户端声明的用户类型 {} 与令牌不符，以令牌为准: {}", user_type, claims.sub);
         }
     }
-    
-    // 客户类型不需要认证
-    Ok(true)
-} 
\ No newline at end of file
+
+    // 客服令牌仍要求客服处于登录状态，下线后旧令牌失效
+    if claims.user_type == UserType::Kefu {
+        match kefu_auth_manager.is_kefu_online(&claims.sub).await {
+            Ok(true) => {}
+            Ok(false) => return Err("客服未登录或已下线".to_string()),
+            Err(e) => return Err(format!("验证客服状态失败: {}", e)),
+        }
+    }
+
+    Ok(WebSocketConnectionInfo {
+        user_name: claims
+            .name
+            .clone()
+            .or_else(|| query.get("user_name").cloned())
+            .unwrap_or_else(|| claims.sub.clone()),
+        user_id: claims.sub,
+        user_type: claims.user_type,
+        zhanghao: query.get("zhanghao").cloned(),
+        session_token: None,
+        issued_at: Some(claims.iat),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted_params_hide_credentials() {
+        let query: WebSocketParams = [
+            ("user_id", "kehu_001"),
+            ("token", "eyJhbGciOiJIUzI1NiJ9.payload.sig"),
+            ("Session_Token", "abc"),
+            ("version", "2"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        let logged = format!("{:?}", redacted_params(&query));
+        assert!(!logged.contains("eyJ"));
+        assert!(!logged.contains("abc"));
+        assert!(logged.contains("kehu_001"));
+        assert_eq!(redacted_params(&query)["token"], "***");
+        assert_eq!(redacted_params(&query)["version"], "2");
+    }
+
+    #[test]
+    fn test_guest_id_replaces_requested_customer_id() {
+        let query: WebSocketParams = [("user_id", "kehu_001"), ("user_type", "kehu")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let guest_id = generate_guest_customer_id();
+        let info = parse_websocket_connection(&query).unwrap().with_guest_id(guest_id.clone());
+
+        assert!(guest_id.starts_with("kehu_"));
+        assert_ne!(guest_id, generate_guest_customer_id());
+        assert_eq!(info.user_id, guest_id);
+        assert_eq!(info.user_name, guest_id);
+        assert_eq!(info.user_type, UserType::Kehu);
+    }
+}
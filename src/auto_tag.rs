@@ -0,0 +1,138 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// 关键词打标规则：消息内容包含任一关键词即打上对应标签
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TagRule {
+    pub tag: String,
+    pub keywords: Vec<String>,
+}
+
+/// 消息自动打标器，规则可运行时替换
+#[derive(Clone)]
+pub struct AutoTagger {
+    rules: Arc<RwLock<Vec<TagRule>>>,
+    source: Option<PathBuf>,
+    source_modified: Arc<RwLock<Option<SystemTime>>>,
+}
+
+impl Default for AutoTagger {
+    fn default() -> Self {
+        Self::new(Self::default_rules())
+    }
+}
+
+impl AutoTagger {
+    pub fn new(rules: Vec<TagRule>) -> Self {
+        Self {
+            rules: Arc::new(RwLock::new(rules)),
+            source: None,
+            source_modified: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// 从规则文件加载，文件不存在时使用默认规则；之后可通过 reload_if_changed 热更新
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let tagger = Self {
+            source: Some(path.as_ref().to_path_buf()),
+            ..Self::default()
+        };
+        tagger.reload_if_changed()?;
+        Ok(tagger)
+    }
+
+    pub fn default_rules() -> Vec<TagRule> {
+        let rule = |tag: &str, keywords: &[&str]| TagRule {
+            tag: tag.to_string(),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+        };
+        vec![
+            rule("complaint", &["投诉", "不满", "差评", "举报"]),
+            rule("refund", &["退款", "退钱", "退货", "refund"]),
+            rule("urgent", &["紧急", "尽快", "马上", "urgent"]),
+        ]
+    }
+
+    /// 计算消息内容的标签（去重、按规则顺序）
+    pub fn tag(&self, content: &str) -> Vec<String> {
+        let content = content.to_lowercase();
+        let rules = match self.rules.read() {
+            Ok(rules) => rules,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut tags: Vec<String> = Vec::new();
+        for rule in rules.iter() {
+            let matched = rule
+                .keywords
+                .iter()
+                .any(|keyword| !keyword.is_empty() && content.contains(&keyword.to_lowercase()));
+            if matched && !tags.contains(&rule.tag) {
+                tags.push(rule.tag.clone());
+            }
+        }
+        tags
+    }
+
+    pub fn update_rules(&self, rules: Vec<TagRule>) {
+        if let Ok(mut current) = self.rules.write() {
+            *current = rules;
+        }
+    }
+
+    /// 规则文件有变化时重新加载，返回是否发生了更新
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let path = match &self.source {
+            Some(path) if path.exists() => path,
+            _ => return Ok(false),
+        };
+
+        let modified = std::fs::metadata(path)?.modified().ok();
+        {
+            let last = self.source_modified.read().map_err(|_| anyhow::anyhow!("规则状态锁异常"))?;
+            if modified.is_some() && *last == modified {
+                return Ok(false);
+            }
+        }
+
+        let rules: Vec<TagRule> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        tracing::info!("🏷️ 自动打标规则已加载: {} 条 ({})", rules.len(), path.display());
+        self.update_rules(rules);
+        if let Ok(mut last) = self.source_modified.write() {
+            *last = modified;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules() {
+        let tagger = AutoTagger::default();
+        assert_eq!(tagger.tag("我要投诉，并且申请退款"), vec!["complaint", "refund"]);
+        assert_eq!(tagger.tag("Please REFUND my order"), vec!["refund"]);
+        assert!(tagger.tag("你好，请问营业时间").is_empty());
+    }
+
+    #[test]
+    fn test_rules_hot_reload_from_file() {
+        let path = std::env::temp_dir().join(format!("auto_tag_rules_{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"[{"tag": "invoice", "keywords": ["发票"]}]"#).unwrap();
+
+        let tagger = AutoTagger::from_file(&path).unwrap();
+        assert_eq!(tagger.tag("帮我开发票"), vec!["invoice"]);
+        assert!(tagger.tag("我要退款").is_empty());
+        assert!(!tagger.reload_if_changed().unwrap());
+
+        tagger.update_rules(AutoTagger::default_rules());
+        assert_eq!(tagger.tag("我要退款"), vec!["refund"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::cache::manager::{CacheConfig, CacheManager};
+
+/// 请求头 Cache-Control 为 no-cache 时跳过缓存重新计算，并用新结果刷新缓存
+pub fn is_cache_bypass(cache_control: Option<&str>) -> bool {
+    cache_control.is_some_and(|value| {
+        value
+            .split(',')
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache"))
+    })
+}
+
+/// 统计分析接口缓存的命中、未命中和跳过次数，由 /metrics 导出
+#[derive(Debug, Default)]
+pub struct AnalyticsCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bypasses: AtomicU64,
+}
+
+impl AnalyticsCacheCounters {
+    /// (结果, 次数)，结果为 hit / miss / bypass
+    pub fn snapshot(&self) -> [(&'static str, u64); 3] {
+        [
+            ("hit", self.hits.load(Ordering::Relaxed)),
+            ("miss", self.misses.load(Ordering::Relaxed)),
+            ("bypass", self.bypasses.load(Ordering::Relaxed)),
+        ]
+    }
+}
+
+/// 仪表盘统计数据的短时缓存：相同接口、相同查询参数在有效期内直接返回上次结果。
+/// 只缓存在本实例内存中，失败的结果不缓存
+pub struct AnalyticsCache {
+    cache: Option<CacheManager>,
+    ttl: Duration,
+    counters: Arc<AnalyticsCacheCounters>,
+}
+
+impl Default for AnalyticsCache {
+    fn default() -> Self {
+        Self {
+            cache: None,
+            ttl: Duration::ZERO,
+            counters: Arc::new(AnalyticsCacheCounters::default()),
+        }
+    }
+}
+
+impl AnalyticsCache {
+    pub fn new(config: &crate::config::CacheConfig) -> Self {
+        let cache = (config.enabled && config.ttl > 0).then(|| {
+            CacheManager::new(
+                CacheConfig {
+                    memory_max_size: config.max_size.max(1),
+                    default_ttl: config.ttl,
+                    enable_redis: false,
+                    redis_prefix: String::new(),
+                },
+                None,
+            )
+        });
+        Self {
+            cache,
+            ttl: Duration::from_secs(config.ttl),
+            counters: Arc::new(AnalyticsCacheCounters::default()),
+        }
+    }
+
+    pub fn counters(&self) -> Arc<AnalyticsCacheCounters> {
+        self.counters.clone()
+    }
+
+    /// 返回缓存的结果，没有或 bypass 时调用 compute 计算并写入缓存
+    pub async fn get_or_compute<F, Fut>(
+        &self,
+        endpoint: &str,
+        request: &impl Serialize,
+        bypass: bool,
+        compute: F,
+    ) -> Result<serde_json::Value>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<serde_json::Value>>,
+    {
+        let Some(cache) = &self.cache else {
+            return compute().await;
+        };
+        let key = format!("analytics:{}:{}", endpoint, serde_json::to_string(request)?);
+
+        if bypass {
+            self.counters.bypasses.fetch_add(1, Ordering::Relaxed);
+        } else if let Some(cached) = cache.get::<serde_json::Value>(&key).await? {
+            self.counters.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(cached);
+        } else {
+            self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let value = compute().await?;
+        cache.set(key, value.clone(), Some(self.ttl)).await?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn test_repeated_queries_served_from_cache() {
+        let cache = AnalyticsCache::new(&crate::config::CacheConfig {
+            enabled: true,
+            max_size: 10,
+            ttl: 30,
+        });
+        let computed = AtomicUsize::new(0);
+        let counter = &computed;
+        let compute = move || async move {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, anyhow::Error>(serde_json::json!({ "n": n }))
+        };
+
+        let query = serde_json::json!({ "days": 7 });
+        let first = cache.get_or_compute("sla", &query, false, compute).await.unwrap();
+        let second = cache.get_or_compute("sla", &query, false, compute).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+
+        // 参数不同或要求跳过缓存时重新计算，跳过后的结果刷新缓存
+        cache.get_or_compute("sla", &serde_json::json!({ "days": 30 }), false, compute).await.unwrap();
+        let fresh = cache.get_or_compute("sla", &query, true, compute).await.unwrap();
+        assert_eq!(fresh["n"], 2);
+        assert_eq!(cache.get_or_compute("sla", &query, false, compute).await.unwrap(), fresh);
+        assert_eq!(computed.load(Ordering::SeqCst), 3);
+
+        assert_eq!(cache.counters().snapshot(), [("hit", 2), ("miss", 2), ("bypass", 1)]);
+
+        assert!(is_cache_bypass(Some("no-cache")));
+        assert!(is_cache_bypass(Some("max-age=0, No-Cache")));
+        assert!(!is_cache_bypass(Some("max-age=60")));
+        assert!(!is_cache_bypass(None));
+    }
+}
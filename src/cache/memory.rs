@@ -1,3 +1,4 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
@@ -9,12 +10,14 @@ use serde::{Serialize, Deserialize};
 struct CacheItem<T> {
     value: T,
     expires_at: Option<Instant>,
+    /// 最近一次访问的序号，缓存满时淘汰序号最小的项
+    last_access: u64,
 }
 
 impl<T> CacheItem<T> {
-    fn new(value: T, ttl: Option<Duration>) -> Self {
+    fn new(value: T, ttl: Option<Duration>, last_access: u64) -> Self {
         let expires_at = ttl.map(|duration| Instant::now() + duration);
-        Self { value, expires_at }
+        Self { value, expires_at, last_access }
     }
     
     fn is_expired(&self) -> bool {
@@ -28,6 +31,7 @@ impl<T> CacheItem<T> {
 pub struct MemoryCache<T: Clone> {
     cache: Arc<RwLock<HashMap<String, CacheItem<T>>>>,
     max_size: usize,
+    access_counter: AtomicU64,
 }
 
 impl<T: Clone + Send + Sync> MemoryCache<T> {
@@ -35,15 +39,21 @@ impl<T: Clone + Send + Sync> MemoryCache<T> {
         Self {
             cache: Arc::new(RwLock::new(HashMap::new())),
             max_size,
+            access_counter: AtomicU64::new(0),
         }
     }
+
+    fn next_access(&self) -> u64 {
+        self.access_counter.fetch_add(1, Ordering::Relaxed)
+    }
     
-    /// 获取缓存值
+    /// 获取缓存值，命中时刷新访问顺序
     pub async fn get(&self, key: &str) -> Option<T> {
-        let cache = self.cache.read().await;
+        let mut cache = self.cache.write().await;
         
-        if let Some(item) = cache.get(key) {
+        if let Some(item) = cache.get_mut(key) {
             if !item.is_expired() {
+                item.last_access = self.next_access();
                 return Some(item.value.clone());
             }
         }
@@ -55,15 +65,21 @@ impl<T: Clone + Send + Sync> MemoryCache<T> {
     pub async fn set(&self, key: String, value: T, ttl: Option<Duration>) {
         let mut cache = self.cache.write().await;
         
-        // 如果缓存已满，移除最早的项
+        // 如果缓存已满，先清理过期项，仍然满则移除最久未访问的项（LRU）
         if cache.len() >= self.max_size && !cache.contains_key(&key) {
-            // 简单的FIFO策略
-            if let Some(first_key) = cache.keys().next().cloned() {
-                cache.remove(&first_key);
+            cache.retain(|_, item| !item.is_expired());
+            if cache.len() >= self.max_size {
+                let lru_key = cache
+                    .iter()
+                    .min_by_key(|(_, item)| item.last_access)
+                    .map(|(key, _)| key.clone());
+                if let Some(lru_key) = lru_key {
+                    cache.remove(&lru_key);
+                }
             }
         }
         
-        cache.insert(key, CacheItem::new(value, ttl));
+        cache.insert(key, CacheItem::new(value, ttl, self.next_access()));
     }
     
     /// 删除缓存值
@@ -72,6 +88,14 @@ impl<T: Clone + Send + Sync> MemoryCache<T> {
         cache.remove(key).is_some()
     }
     
+    /// 删除键以 prefix 开头的所有缓存项，返回删除的数量
+    pub async fn delete_prefix(&self, prefix: &str) -> usize {
+        let mut cache = self.cache.write().await;
+        let before = cache.len();
+        cache.retain(|key, _| !key.starts_with(prefix));
+        before - cache.len()
+    }
+    
     /// 清空缓存
     pub async fn clear(&self) {
         let mut cache = self.cache.write().await;
@@ -116,4 +140,25 @@ impl<T> CachedValue<T> {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lru_eviction_and_prefix_delete() {
+        let cache = MemoryCache::new(2);
+        cache.set("a:1".to_string(), 1, None).await;
+        cache.set("b:1".to_string(), 2, None).await;
+
+        // 访问 a:1 后，b:1 成为最久未访问的项
+        assert_eq!(cache.get("a:1").await, Some(1));
+        cache.set("a:2".to_string(), 3, None).await;
+        assert_eq!(cache.get("b:1").await, None);
+        assert_eq!(cache.get("a:1").await, Some(1));
+
+        assert_eq!(cache.delete_prefix("a:").await, 2);
+        assert_eq!(cache.size().await, 0);
+    }
+}
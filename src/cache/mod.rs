@@ -3,6 +3,7 @@
 /// 提供多级缓存支持，包括内存缓存和Redis缓存
 pub mod memory;
 pub mod manager;
+pub mod analytics;
 
 pub use memory::MemoryCache;
 pub use manager::{CacheManager, CacheConfig};
\ No newline at end of file
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 快捷回复快捷码的最大长度（字符）
+pub const MAX_SHORTCUT_LEN: usize = 32;
+/// 快捷回复内容的最大长度（字符）
+pub const MAX_CANNED_CONTENT_LEN: usize = 2000;
+/// 按前缀匹配时默认返回的条数
+pub const DEFAULT_MATCH_LIMIT: usize = 20;
+
+/// 客服快捷回复。owner_id 为空时是所有客服可用的全局快捷回复，否则只属于该客服
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct CannedResponse {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<String>,
+    /// 快捷码，如 "/refund"，客户端输入前缀时据此匹配
+    pub shortcut: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CannedResponse {
+    pub fn is_global(&self) -> bool {
+        self.owner_id.is_none()
+    }
+
+    /// 全局快捷回复所有客服可用，个人快捷回复只有本人可用
+    pub fn is_visible_to(&self, kefu_id: &str) -> bool {
+        self.owner_id.as_deref().is_none_or(|owner| owner == kefu_id)
+    }
+}
+
+/// 校验快捷码和内容，返回错误描述
+pub fn validate(shortcut: &str, content: &str) -> Result<(), String> {
+    let shortcut = shortcut.trim();
+    if shortcut.is_empty() {
+        return Err("快捷码不能为空".to_string());
+    }
+    if shortcut.chars().count() > MAX_SHORTCUT_LEN {
+        return Err(format!("快捷码不能超过{}个字符", MAX_SHORTCUT_LEN));
+    }
+    if shortcut.chars().any(char::is_whitespace) {
+        return Err("快捷码不能包含空白字符".to_string());
+    }
+    if content.trim().is_empty() {
+        return Err("快捷回复内容不能为空".to_string());
+    }
+    if content.chars().count() > MAX_CANNED_CONTENT_LEN {
+        return Err(format!("快捷回复内容不能超过{}个字符", MAX_CANNED_CONTENT_LEN));
+    }
+    Ok(())
+}
+
+/// 客服可用的快捷回复中快捷码以 prefix 开头（不区分大小写）的条目；
+/// 个人快捷回复排在全局之前，同类按快捷码排序
+pub fn matching<'a>(
+    responses: &'a [CannedResponse],
+    kefu_id: &str,
+    prefix: &str,
+    limit: usize,
+) -> Vec<&'a CannedResponse> {
+    let prefix = prefix.trim().to_lowercase();
+    let mut matched: Vec<&CannedResponse> = responses
+        .iter()
+        .filter(|response| response.is_visible_to(kefu_id))
+        .filter(|response| response.shortcut.to_lowercase().starts_with(&prefix))
+        .collect();
+    matched.sort_by(|a, b| {
+        a.is_global()
+            .cmp(&b.is_global())
+            .then_with(|| a.shortcut.cmp(&b.shortcut))
+    });
+    matched.truncate(limit);
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(id: &str, owner_id: Option<&str>, shortcut: &str) -> CannedResponse {
+        CannedResponse {
+            id: id.to_string(),
+            owner_id: owner_id.map(str::to_string),
+            shortcut: shortcut.to_string(),
+            content: format!("{}的内容", shortcut),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_prefix_match_prefers_own_responses() {
+        let responses = vec![
+            response("1", None, "/refund"),
+            response("2", Some("kefu_1"), "/Refund-vip"),
+            response("3", Some("kefu_2"), "/refund-other"),
+            response("4", None, "/hello"),
+        ];
+
+        let ids: Vec<&str> = matching(&responses, "kefu_1", "/re", 10)
+            .iter()
+            .map(|response| response.id.as_str())
+            .collect();
+        // 其他客服的个人快捷回复不可见，匹配不区分大小写
+        assert_eq!(ids, vec!["2", "1"]);
+
+        assert_eq!(matching(&responses, "kefu_2", "", 10).len(), 3);
+        assert_eq!(matching(&responses, "kefu_2", "", 1)[0].id, "3");
+        assert!(matching(&responses, "kefu_1", "/x", 10).is_empty());
+    }
+
+    #[test]
+    fn test_validate_shortcut_and_content() {
+        assert!(validate("/refund", "退款将在3个工作日内到账").is_ok());
+        assert!(validate(" ", "内容").is_err());
+        assert!(validate("/re fund", "内容").is_err());
+        assert!(validate(&"a".repeat(MAX_SHORTCUT_LEN + 1), "内容").is_err());
+        assert!(validate("/refund", "  ").is_err());
+        assert!(validate("/refund", &"字".repeat(MAX_CANNED_CONTENT_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use warp::ws::Message as WsMessage;
+
+/// 服务端主动关闭WebSocket连接的原因，关闭帧带对应的状态码和原因字符串，
+/// 客户端据此决定自动重连、提示重新登录还是展示封禁信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// 连接数已达上限，稍后重连
+    ServerBusy,
+    /// 同一用户建立了新连接，旧连接不应重连
+    Replaced,
+    /// 同一用户已有连接，按策略拒绝新连接
+    DuplicateConnection,
+    /// 连续未响应协议层ping，网络恢复后可重连
+    HeartbeatTimeout,
+    /// 管理员强制断开
+    Kicked,
+    /// 账号或IP已被封禁，不应重连
+    Banned,
+    /// 异地登录需要二次验证
+    ReverificationRequired,
+}
+
+impl CloseReason {
+    /// 关闭帧状态码：1013 沿用标准的 Try Again Later，其余为应用自定义的 4000-4999
+    pub fn code(self) -> u16 {
+        match self {
+            CloseReason::ServerBusy => 1013,
+            CloseReason::Replaced => 4000,
+            CloseReason::DuplicateConnection => 4001,
+            CloseReason::HeartbeatTimeout => 4002,
+            CloseReason::Kicked => 4003,
+            CloseReason::Banned => 4004,
+            CloseReason::ReverificationRequired => 4006,
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            CloseReason::ServerBusy => "server busy",
+            CloseReason::Replaced => "replaced by new connection",
+            CloseReason::DuplicateConnection => "duplicate connection",
+            CloseReason::HeartbeatTimeout => "heartbeat timeout",
+            CloseReason::Kicked => "disconnected by admin",
+            CloseReason::Banned => "banned",
+            CloseReason::ReverificationRequired => "reverification required",
+        }
+    }
+
+    pub fn frame(self) -> WsMessage {
+        WsMessage::close_with(self.code(), self.reason())
+    }
+}
+
+/// 连接的关闭信号：通知该连接的发送任务以指定原因发送关闭帧后结束
+#[derive(Debug, Default)]
+pub struct CloseSignal {
+    notify: Notify,
+    reason: Mutex<Option<CloseReason>>,
+}
+
+impl CloseSignal {
+    pub fn close(&self, reason: CloseReason) {
+        *self.reason.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason);
+        self.notify.notify_one();
+    }
+
+    /// 等待关闭信号，返回关闭原因
+    pub async fn closed(&self) -> CloseReason {
+        self.notify.notified().await;
+        self.reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .unwrap_or(CloseReason::Replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_close_codes_distinct_and_signal_carries_reason() {
+        let reasons = [
+            CloseReason::ServerBusy,
+            CloseReason::Replaced,
+            CloseReason::DuplicateConnection,
+            CloseReason::HeartbeatTimeout,
+            CloseReason::Kicked,
+            CloseReason::Banned,
+            CloseReason::ReverificationRequired,
+        ];
+        let mut codes: Vec<u16> = reasons.iter().map(|reason| reason.code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), reasons.len());
+        // 关闭帧的原因字符串不能超过123字节
+        assert!(reasons.iter().all(|reason| reason.reason().len() <= 123));
+        assert_eq!(CloseReason::Banned.frame().close_frame(), Some((4004, "banned")));
+
+        // 信号先于等待方发出时也不会丢失
+        let signal = CloseSignal::default();
+        signal.close(CloseReason::Kicked);
+        assert_eq!(signal.closed().await, CloseReason::Kicked);
+    }
+}
@@ -10,6 +10,7 @@ pub struct CompressionConfig {
     pub min_size: usize,        // 最小压缩大小（字节）
     pub compression_level: u32, // 压缩级别 0-9
     pub use_base64: bool,       // 是否使用base64编码
+    pub max_decompressed_size: usize, // 解压后大小上限（字节），超出即拒绝，防止压缩炸弹
 }
 
 impl Default for CompressionConfig {
@@ -19,10 +20,21 @@ impl Default for CompressionConfig {
             min_size: 1024,       // 1KB以上才压缩
             compression_level: 6, // 中等压缩级别，平衡速度和压缩率
             use_base64: true,
+            max_decompressed_size: 1024 * 1024, // 与 websocket.maxMessageSize 默认值一致
         }
     }
 }
 
+// 最多读取 limit + 1 字节，超过上限时报错而不是把整个解压结果读进内存
+fn read_limited(reader: impl Read, limit: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(limit as u64 + 1).read_to_end(&mut data)?;
+    if data.len() > limit {
+        return Err(anyhow::anyhow!("解压后的消息超过大小上限 {} 字节", limit));
+    }
+    Ok(data)
+}
+
 // 压缩结果 - 企业级性能分析
 #[derive(Debug, Clone)]
 pub struct CompressionResult {
@@ -64,10 +76,7 @@ impl MessageCompressor {
         }
 
         // 执行压缩
-        let mut encoder =
-            GzEncoder::new(Vec::new(), Compression::new(self.config.compression_level));
-        encoder.write_all(data.as_bytes())?;
-        let compressed_bytes = encoder.finish()?;
+        let compressed_bytes = self.gzip(data.as_bytes())?;
 
         let compressed_size = compressed_bytes.len();
 
@@ -108,6 +117,42 @@ impl MessageCompressor {
         Ok((final_data, result))
     }
 
+    // 压缩为二进制帧：未达到阈值或压缩无收益时返回None，调用方按文本发送
+    pub fn compress_binary(&self, data: &str) -> Result<(Option<Vec<u8>>, CompressionResult)> {
+        let start_time = std::time::Instant::now();
+        let original_size = data.len();
+
+        let compressed_bytes = if self.config.enabled && original_size >= self.config.min_size {
+            Some(self.gzip(data.as_bytes())?).filter(|bytes| bytes.len() < original_size)
+        } else {
+            None
+        };
+
+        let compressed_size = compressed_bytes.as_ref().map_or(original_size, |bytes| bytes.len());
+        let result = CompressionResult {
+            original_size,
+            compressed_size,
+            compression_ratio: compressed_size as f64 / original_size.max(1) as f64,
+            compressed: compressed_bytes.is_some(),
+            processing_time_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+        };
+
+        Ok((compressed_bytes, result))
+    }
+
+    // 解压二进制帧，解压后超过 max_decompressed_size 的帧直接拒绝
+    pub fn decompress_binary(&self, data: &[u8]) -> Result<String> {
+        let decompressed_data = read_limited(GzDecoder::new(data), self.config.max_decompressed_size)?;
+        Ok(String::from_utf8(decompressed_data)?)
+    }
+
+    fn gzip(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder =
+            GzEncoder::new(Vec::new(), Compression::new(self.config.compression_level));
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    }
+
     // 解压缩数据
     #[allow(dead_code)] // 企业级功能：用于数据解压缩和历史消息处理
     pub fn decompress(&self, compressed_data: &str) -> Result<(String, CompressionResult)> {
@@ -131,9 +176,7 @@ impl MessageCompressor {
 
         // 使用std::io::Cursor包装Vec<u8>以实现Read trait
         let cursor = std::io::Cursor::new(compressed_bytes);
-        let mut decoder = GzDecoder::new(cursor);
-        let mut decompressed_data = Vec::new();
-        decoder.read_to_end(&mut decompressed_data)?;
+        let decompressed_data = read_limited(GzDecoder::new(cursor), self.config.max_decompressed_size)?;
 
         let decompressed_string = String::from_utf8(decompressed_data)?;
         let process_time = start_time.elapsed();
@@ -387,6 +430,19 @@ impl AdaptiveCompressor {
         }
     }
 
+    // 压缩WebSocket二进制帧并记录统计
+    pub fn compress_frame(&mut self, data: &str) -> Result<Option<Vec<u8>>> {
+        let (compressed, result) = self.compressor.compress_binary(data)?;
+        self.stats.record(&result);
+
+        if self.stats.total_messages - self.last_adjustment >= self.adjustment_interval {
+            self.adjust_config();
+            self.last_adjustment = self.stats.total_messages;
+        }
+
+        Ok(compressed)
+    }
+
     // 企业级统计报告功能
     #[allow(dead_code)] // 企业级功能：用于性能监控和运营分析
     pub fn get_stats(&self) -> CompressionStatsReport {
@@ -431,6 +487,38 @@ mod tests {
         assert_eq!(compressed, small_data);
     }
 
+    #[test]
+    fn test_binary_frame_roundtrip() {
+        let mut compressor = AdaptiveCompressor::new(CompressionConfig::default());
+
+        let large = format!(r#"{{"type":"History","messages":"{}"}}"#, "消息".repeat(500));
+        let frame = compressor.compress_frame(&large).unwrap().expect("超过阈值应压缩");
+        assert!(frame.len() < large.len());
+        assert_eq!(compressor.compressor.decompress_binary(&frame).unwrap(), large);
+
+        // 低于阈值保持文本
+        assert!(compressor.compress_frame(r#"{"type":"Heartbeat"}"#).unwrap().is_none());
+        assert_eq!(compressor.get_stats().compressed_messages, 1);
+    }
+
+    #[test]
+    fn test_decompression_bomb_rejected() {
+        let compressor = MessageCompressor::new(CompressionConfig {
+            max_decompressed_size: 4096,
+            ..CompressionConfig::default()
+        });
+
+        let at_limit = compressor.gzip("a".repeat(4096).as_bytes()).unwrap();
+        assert_eq!(compressor.decompress_binary(&at_limit).unwrap().len(), 4096);
+
+        // 很小的压缩帧解压后远超上限
+        let bomb = compressor.gzip(&vec![b'a'; 10 * 1024 * 1024]).unwrap();
+        assert!(bomb.len() < 64 * 1024);
+        assert!(compressor.decompress_binary(&bomb).is_err());
+        let text_bomb = format!("GZIP:{}", general_purpose::STANDARD.encode(&bomb));
+        assert!(compressor.decompress(&text_bomb).is_err());
+    }
+
     #[test]
     fn test_smart_compression() {
         let config = CompressionConfig::default();
@@ -38,6 +38,35 @@ pub struct CorsConfig {
     pub origins: Vec<String>,
     pub methods: Vec<String>,
     pub headers: Vec<String>,
+    /// 是否允许跨域请求携带Cookie等凭据
+    #[serde(default)]
+    pub credentials: bool,
+    /// 按运行环境（app.environment）覆盖的设置，未覆盖的项沿用上面的值
+    #[serde(default)]
+    pub environments: std::collections::HashMap<String, CorsOverride>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorsOverride {
+    pub origins: Option<Vec<String>>,
+    pub methods: Option<Vec<String>>,
+    pub headers: Option<Vec<String>>,
+    pub credentials: Option<bool>,
+}
+
+impl CorsConfig {
+    /// 指定运行环境下生效的跨域设置
+    pub fn for_environment(&self, environment: &str) -> CorsConfig {
+        let overrides = self.environments.get(environment).cloned().unwrap_or_default();
+        CorsConfig {
+            enabled: self.enabled,
+            origins: overrides.origins.unwrap_or_else(|| self.origins.clone()),
+            methods: overrides.methods.unwrap_or_else(|| self.methods.clone()),
+            headers: overrides.headers.unwrap_or_else(|| self.headers.clone()),
+            credentials: overrides.credentials.unwrap_or(self.credentials),
+            environments: Default::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +79,23 @@ pub struct FrontendConfig {
     pub ws_url: String,
     pub features: FrontendFeatures,
     pub upload: UploadConfig,
+    /// 按客户端类型（web、mobile、desktop 等）定制下发的配置，按顺序套用满足版本要求的条目
+    #[serde(rename = "clientProfiles", default)]
+    pub client_profiles: std::collections::HashMap<String, Vec<ClientProfile>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ClientProfile {
+    /// 适用的最低客户端版本，未配置时对所有版本生效
+    #[serde(rename = "minVersion", default)]
+    pub min_version: Option<String>,
+    #[serde(rename = "wsUrl", default)]
+    pub ws_url: Option<String>,
+    /// 覆盖的功能开关，键与 features 相同（如 imageUpload）
+    #[serde(default)]
+    pub features: std::collections::HashMap<String, bool>,
+    #[serde(rename = "maxFileSize", default)]
+    pub max_file_size: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,6 +126,21 @@ pub struct UploadConfig {
     pub max_width: u32,
     #[serde(rename = "maxHeight")]
     pub max_height: u32,
+    /// 按类型的大小上限，键为完整MIME（如 application/pdf）或主类型（如 audio）
+    #[serde(rename = "maxSizeByType", default)]
+    pub max_size_by_type: std::collections::HashMap<String, u64>,
+}
+
+impl UploadConfig {
+    /// 某MIME类型的大小上限：完整类型优先，其次主类型，未配置时使用 maxFileSize
+    pub fn max_size_for(&self, mime: &str) -> u64 {
+        let main_type = mime.split('/').next().unwrap_or_default();
+        self.max_size_by_type
+            .get(mime)
+            .or_else(|| self.max_size_by_type.get(main_type))
+            .copied()
+            .unwrap_or(self.max_file_size)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,6 +155,164 @@ pub struct WebSocketConfig {
     pub message_timeout: u64,
     #[serde(rename = "maxMessageSize")]
     pub max_message_size: usize,
+    /// 服务端消息重排序窗口（毫秒），0 表示不重排
+    #[serde(rename = "reorderWindow", default = "default_reorder_window")]
+    pub reorder_window: u64,
+    /// 客户分配方式：auto 自动分配客服，manual 由客服主动接入
+    #[serde(rename = "assignmentMode", default)]
+    pub assignment_mode: AssignmentMode,
+    /// 同一用户再次连接时替换旧连接（replace）还是拒绝新连接（reject）
+    #[serde(rename = "duplicateConnectionPolicy", default)]
+    pub duplicate_connection_policy: DuplicateConnectionPolicy,
+    /// manual 模式下新客户提醒推送给负载最低的客服数量
+    #[serde(rename = "newCustomerAlertCount", default = "default_new_customer_alert_count")]
+    pub new_customer_alert_count: usize,
+    /// 同时在线连接数上限，超过后新连接收到 server busy 关闭帧；0 表示不限制
+    #[serde(rename = "maxConnections", default = "default_max_connections")]
+    pub max_connections: usize,
+    /// 每个连接待发送消息的队列长度，消费过慢时新消息转入离线队列
+    #[serde(rename = "sendQueueSize", default = "default_send_queue_size")]
+    pub send_queue_size: usize,
+    /// 客户在等待队列中超过该时长（秒）仍无客服接入时转为留言；0 表示一直等待
+    #[serde(rename = "queueTimeoutSeconds", default = "default_queue_timeout_seconds")]
+    pub queue_timeout_seconds: u64,
+    /// 会话双方都没有消息超过该时长（秒）时自动结束会话；0 表示不自动结束
+    #[serde(rename = "sessionIdleTimeoutSeconds", default = "default_session_idle_timeout_seconds")]
+    pub session_idle_timeout_seconds: u64,
+    /// MaxMind City 数据库（.mmdb）路径，用于为连接事件补充地理位置；未配置或文件不存在时不补充
+    #[serde(rename = "geoipDatabasePath", default)]
+    pub geoip_database_path: Option<String>,
+    /// 单个连接收发速率超过该值（字节/秒）时告警；0 表示不检查
+    #[serde(rename = "bandwidthAlertBytesPerSec", default = "default_bandwidth_alert_bytes_per_sec")]
+    pub bandwidth_alert_bytes_per_sec: u64,
+    /// 需确认消息推送后超过该时长（秒）仍未送达确认的计为未确认
+    #[serde(rename = "ackTimeoutSeconds", default = "default_ack_timeout_seconds")]
+    pub ack_timeout_seconds: u64,
+    /// 每分钟检查一次，未确认率超过该值（0-1）时告警；0 表示不告警
+    #[serde(rename = "unackedAlertRate", default = "default_unacked_alert_rate")]
+    pub unacked_alert_rate: f64,
+    /// 离线消息队列的长度上限与保留时长
+    #[serde(rename = "messageQueue", default)]
+    pub message_queue: MessageQueueConfig,
+    /// 客户分配到客服后自动发送的问候语，默认关闭
+    #[serde(default)]
+    pub greeting: GreetingConfig,
+}
+
+/// 自动问候语模板，支持 {customer_name} 和 {kefu_name} 变量
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GreetingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 全局问候语，为空时只给配置了个人问候语的客服发送
+    #[serde(default = "default_greeting_message")]
+    pub message: String,
+    /// 客服个人问候语，键为客服ID，优先于全局问候语
+    #[serde(rename = "perKefu", default)]
+    pub per_kefu: std::collections::HashMap<String, String>,
+}
+
+impl Default for GreetingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: default_greeting_message(),
+            per_kefu: std::collections::HashMap::new(),
+        }
+    }
+}
+
+fn default_greeting_message() -> String {
+    "您好{customer_name}，我是客服{kefu_name}，很高兴为您服务，请问有什么可以帮您？".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MessageQueueConfig {
+    /// 每个用户的离线队列最多保留的消息数，超出时丢弃最早的消息；0 表示不限制
+    #[serde(rename = "maxLength")]
+    pub max_length: usize,
+    /// 离线消息的保留时长（秒），过期的消息不再投递，长期无人读取的队列整体过期
+    #[serde(rename = "messageTtlSeconds")]
+    pub message_ttl_seconds: u64,
+}
+
+impl Default for MessageQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_length: 1000,
+            message_ttl_seconds: 7 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AssignmentMode {
+    #[default]
+    Auto,
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateConnectionPolicy {
+    /// 关闭旧连接，由新连接接管（如用户刷新页面或换设备）
+    #[default]
+    Replace,
+    /// 保留旧连接，拒绝新连接
+    Reject,
+}
+
+fn default_template_render_cache() -> CacheConfig {
+    CacheConfig {
+        enabled: true,
+        max_size: 500,
+        ttl: 600,
+    }
+}
+
+fn default_analytics_cache() -> CacheConfig {
+    CacheConfig {
+        enabled: true,
+        max_size: 200,
+        ttl: 30,
+    }
+}
+
+fn default_reorder_window() -> u64 {
+    200
+}
+
+fn default_new_customer_alert_count() -> usize {
+    3
+}
+
+fn default_max_connections() -> usize {
+    10000
+}
+
+fn default_send_queue_size() -> usize {
+    256
+}
+
+fn default_queue_timeout_seconds() -> u64 {
+    300
+}
+
+fn default_session_idle_timeout_seconds() -> u64 {
+    1800
+}
+
+fn default_bandwidth_alert_bytes_per_sec() -> u64 {
+    1048576
+}
+
+fn default_ack_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_unacked_alert_rate() -> f64 {
+    0.2
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,6 +322,54 @@ pub struct RedisConfig {
     pub password: String,
     pub database: u8,
     pub pool: RedisPoolConfig,
+    /// 部署方式：single、sentinel 或 cluster
+    #[serde(default)]
+    pub mode: RedisMode,
+    #[serde(default)]
+    pub sentinel: RedisSentinelConfig,
+    #[serde(default)]
+    pub cluster: RedisClusterConfig,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisMode {
+    #[default]
+    Single,
+    Sentinel,
+    Cluster,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RedisSentinelConfig {
+    /// 哨兵地址，如 10.0.0.1:26379
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    #[serde(rename = "masterName", default)]
+    pub master_name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RedisClusterConfig {
+    /// 集群种子节点地址，连接后自动发现其余节点
+    #[serde(default)]
+    pub nodes: Vec<String>,
+}
+
+impl RedisConfig {
+    /// 按部署方式生成连接池使用的拓扑，地址等校验在创建连接池时进行
+    pub fn topology(&self) -> crate::redis_pool::RedisTopology {
+        match self.mode {
+            RedisMode::Single => crate::redis_pool::RedisTopology::Single,
+            RedisMode::Sentinel => crate::redis_pool::RedisTopology::Sentinel {
+                addresses: self.sentinel.addresses.clone(),
+                master_name: self.sentinel.master_name.clone(),
+            },
+            RedisMode::Cluster => crate::redis_pool::RedisTopology::Cluster {
+                nodes: self.cluster.nodes.clone(),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -127,6 +394,83 @@ pub struct StorageConfig {
     pub snapshot_interval: u64,
     #[serde(rename = "maxSnapshotSize")]
     pub max_snapshot_size: u64,
+    /// 消息导出限额，每次导出（含被拒绝的）都记审计
+    #[serde(default)]
+    pub export: ExportLimitConfig,
+    /// 消息静态加密，默认关闭
+    #[serde(default)]
+    pub encryption: StorageEncryptionConfig,
+    /// 消息和二进制对象的存储后端：local 或 s3
+    #[serde(default)]
+    pub backend: StorageBackend,
+    #[serde(default)]
+    pub s3: S3StorageConfig,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Local,
+    S3,
+}
+
+/// S3 兼容对象存储；访问密钥为空时从 AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY 环境变量读取
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct S3StorageConfig {
+    #[serde(default)]
+    pub bucket: String,
+    #[serde(default)]
+    pub region: String,
+    /// 自定义服务地址（如 MinIO），为空时使用 AWS 官方地址
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(rename = "accessKeyId", default)]
+    pub access_key_id: Option<String>,
+    #[serde(rename = "secretAccessKey", default)]
+    pub secret_access_key: Option<String>,
+    /// 对象键前缀，多个环境共用一个桶时区分
+    #[serde(default)]
+    pub prefix: String,
+    /// 使用路径风格地址（endpoint/bucket/key），MinIO 等通常需要开启
+    #[serde(rename = "pathStyle", default)]
+    pub path_style: bool,
+}
+
+/// 消息落盘前用 AES-256-GCM 加密 content 和 filename，ID、时间戳等索引字段保持明文
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageEncryptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Base64 编码的 32 字节密钥，建议通过环境变量 STORAGE_ENCRYPTION_KEY 提供
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// 按导出人每天（UTC）累计的导出限额，0 表示不限制
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ExportLimitConfig {
+    #[serde(rename = "maxExportsPerDay", default = "default_max_exports_per_day")]
+    pub max_exports_per_day: u32,
+    #[serde(rename = "maxMessagesPerDay", default = "default_max_exported_messages_per_day")]
+    pub max_messages_per_day: u64,
+}
+
+impl Default for ExportLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_exports_per_day: default_max_exports_per_day(),
+            max_messages_per_day: default_max_exported_messages_per_day(),
+        }
+    }
+}
+
+fn default_max_exports_per_day() -> u32 {
+    10
+}
+
+fn default_max_exported_messages_per_day() -> u64 {
+    10000
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -135,10 +479,178 @@ pub struct SecurityConfig {
     pub jwt_secret: String,
     #[serde(rename = "jwtExpiry")]
     pub jwt_expiry: u64,
+    /// 刷新令牌有效期（秒），每次刷新轮换出新的刷新令牌
+    #[serde(rename = "refreshTokenExpiry", default = "default_refresh_token_expiry")]
+    pub refresh_token_expiry: u64,
     #[serde(rename = "bcryptRounds")]
     pub bcrypt_rounds: u32,
     #[serde(rename = "rateLimiting")]
     pub rate_limiting: RateLimitConfig,
+    /// 管理端点（/admin/*）访问令牌，未配置时管理端点不可用
+    #[serde(rename = "adminToken", default)]
+    pub admin_token: Option<String>,
+    /// 可信反向代理的地址或网段，只有来自这些地址的请求才读取 X-Forwarded-For
+    #[serde(rename = "trustedProxies", default)]
+    pub trusted_proxies: Vec<String>,
+    /// WebSocket握手时的异地登录风控
+    #[serde(rename = "geoRisk", default)]
+    pub geo_risk: GeoRiskConfig,
+    /// 企业单点登录（OAuth2 / OIDC）
+    #[serde(default)]
+    pub sso: SsoConfig,
+    /// 聊天内容长度限制与敏感词过滤
+    #[serde(rename = "contentFilter", default)]
+    pub content_filter: ContentFilterConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentFilterConfig {
+    #[serde(default = "default_content_filter_enabled")]
+    pub enabled: bool,
+    /// 单条消息最大字符数，0 表示不限制
+    #[serde(rename = "maxLength", default = "default_content_max_length")]
+    pub max_length: usize,
+    /// 超长消息的处理方式：拦截或截断
+    #[serde(rename = "overLengthAction", default)]
+    pub over_length_action: OverLengthAction,
+    /// 是否把敏感词替换为 *
+    #[serde(rename = "profanityEnabled", default)]
+    pub profanity_enabled: bool,
+    /// 敏感词表（JSON 字符串数组），修改后自动重新加载
+    #[serde(rename = "wordListPath", default = "default_word_list_path")]
+    pub word_list_path: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverLengthAction {
+    #[default]
+    Block,
+    Truncate,
+}
+
+impl Default for ContentFilterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_content_filter_enabled(),
+            max_length: default_content_max_length(),
+            over_length_action: OverLengthAction::default(),
+            profanity_enabled: false,
+            word_list_path: default_word_list_path(),
+        }
+    }
+}
+
+fn default_content_filter_enabled() -> bool {
+    false
+}
+
+fn default_content_max_length() -> usize {
+    5000
+}
+
+fn default_word_list_path() -> String {
+    "config/profanity_words.json".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SsoConfig {
+    /// 可用的身份提供方，按 id 区分
+    #[serde(default)]
+    pub providers: Vec<SsoProviderConfig>,
+    /// 发起登录到IdP回调之间允许的最长时间（秒）
+    #[serde(rename = "stateTtlSeconds", default = "default_sso_state_ttl")]
+    pub state_ttl_seconds: u64,
+}
+
+impl Default for SsoConfig {
+    fn default() -> Self {
+        Self {
+            providers: Vec::new(),
+            state_ttl_seconds: default_sso_state_ttl(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SsoProviderConfig {
+    pub id: String,
+    /// 登录页展示的名称
+    pub name: String,
+    #[serde(rename = "authorizeUrl")]
+    pub authorize_url: String,
+    #[serde(rename = "tokenUrl")]
+    pub token_url: String,
+    #[serde(rename = "userinfoUrl")]
+    pub userinfo_url: String,
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    #[serde(rename = "clientSecret")]
+    pub client_secret: String,
+    /// 在IdP登记的回调地址，即本系统的 /api/auth/sso/{id}/callback
+    #[serde(rename = "redirectUri")]
+    pub redirect_uri: String,
+    #[serde(default = "default_sso_scopes")]
+    pub scopes: Vec<String>,
+    /// 通过该IdP登录的用户在本系统中的默认类型，默认为客户
+    #[serde(rename = "userType", default = "default_sso_user_type")]
+    pub user_type: crate::message::UserType,
+    /// 属于其中任一组的用户登录为客服；为空时所有用户都使用 userType
+    #[serde(rename = "kefuGroups", default)]
+    pub kefu_groups: Vec<String>,
+    /// userinfo 中列出用户所属组的字段
+    #[serde(rename = "groupsClaim", default = "default_sso_groups_claim")]
+    pub groups_claim: String,
+}
+
+fn default_refresh_token_expiry() -> u64 {
+    604800
+}
+
+fn default_sso_state_ttl() -> u64 {
+    600
+}
+
+fn default_sso_scopes() -> Vec<String> {
+    vec!["openid".to_string(), "profile".to_string(), "email".to_string()]
+}
+
+fn default_sso_user_type() -> crate::message::UserType {
+    crate::message::UserType::Kehu
+}
+
+fn default_sso_groups_claim() -> String {
+    "groups".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoRiskConfig {
+    #[serde(default = "default_geo_risk_enabled")]
+    pub enabled: bool,
+    /// 位置异常时要求二次验证（重新登录获取新令牌），关闭时仅标记并告警
+    #[serde(rename = "requireReverification", default)]
+    pub require_reverification: bool,
+    /// 令牌签发后多少秒内视为已完成二次验证
+    #[serde(rename = "reverificationWindow", default = "default_reverification_window")]
+    pub reverification_window: u64,
+}
+
+impl Default for GeoRiskConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_geo_risk_enabled(),
+            require_reverification: false,
+            reverification_window: default_reverification_window(),
+        }
+    }
+}
+
+fn default_geo_risk_enabled() -> bool {
+    true
+}
+
+fn default_reverification_window() -> u64 {
+    300
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -172,6 +684,34 @@ pub struct PerformanceConfig {
     #[serde(rename = "messageCache")]
     pub message_cache: CacheConfig,
     pub compression: CompressionConfig,
+    /// HTML模板渲染结果缓存
+    #[serde(rename = "templateRenderCache", default = "default_template_render_cache")]
+    pub template_render_cache: CacheConfig,
+    /// 仪表盘统计接口的结果缓存
+    #[serde(rename = "analyticsCache", default = "default_analytics_cache")]
+    pub analytics_cache: CacheConfig,
+    /// 服务水平目标，用于计算每日 SLA 达成率
+    #[serde(default)]
+    pub sla: SlaConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlaConfig {
+    /// 首响时限（秒）：客户发出第一条消息到客服第一次回复
+    #[serde(rename = "firstResponseSeconds")]
+    pub first_response_seconds: u64,
+    /// 首响在时限内的会话占比目标
+    #[serde(rename = "targetRate")]
+    pub target_rate: f64,
+}
+
+impl Default for SlaConfig {
+    fn default() -> Self {
+        Self {
+            first_response_seconds: 30,
+            target_rate: 0.9,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -230,6 +770,11 @@ impl AppConfig {
         if let Ok(secret) = std::env::var("JWT_SECRET") {
             self.security.jwt_secret = secret;
         }
+
+        // 消息加密密钥
+        if let Ok(key) = std::env::var("STORAGE_ENCRYPTION_KEY") {
+            self.storage.encryption.key = Some(key);
+        }
     }
 
     /// 获取全局配置实例
@@ -243,6 +788,146 @@ impl AppConfig {
     }
 }
 
+/// 配置项说明：(路径, 类型, 默认值JSON, 说明)。默认值为出厂配置或缺省时的取值，null 表示必须自行配置
+const CONFIG_SCHEMA: &[(&str, &str, &str, &str)] = &[
+    ("app.name", "string", r#""企业级客服系统""#, "应用名称"),
+    ("app.version", "string", r#""2.1.0""#, "应用版本"),
+    ("app.environment", "string", r#""production""#, "运行环境，可由环境变量 APP_ENV 覆盖"),
+    ("server.host", "string", r#""0.0.0.0""#, "服务监听地址，可由环境变量 SERVER_HOST 覆盖"),
+    ("server.port", "integer", "6006", "服务监听端口，可由环境变量 SERVER_PORT 覆盖"),
+    ("server.cors.enabled", "boolean", "true", "是否启用跨域"),
+    ("server.cors.origins", "array<string>", r#"["http://localhost:6006"]"#, "允许的跨域来源"),
+    ("server.cors.methods", "array<string>", r#"["GET","POST","PUT","DELETE","OPTIONS"]"#, "允许的HTTP方法"),
+    ("server.cors.headers", "array<string>", r#"["Content-Type","Authorization"]"#, "允许的请求头"),
+    ("server.cors.credentials", "boolean", "false", "是否允许跨域请求携带Cookie等凭据"),
+    (
+        "server.cors.environments.development.origins",
+        "array<string>",
+        "null",
+        "development 环境允许的跨域来源，覆盖 server.cors.origins；其他环境同理按 environments.<环境名> 配置",
+    ),
+    ("frontend.host", "string", r#""localhost""#, "前端主机名"),
+    ("frontend.port", "integer", "6006", "前端端口"),
+    ("frontend.apiUrl", "string", r#""http://localhost:6006/api""#, "前端访问的API地址"),
+    ("frontend.wsUrl", "string", r#""ws://localhost:6006/ws""#, "前端访问的WebSocket地址"),
+    ("frontend.features.imageUpload", "boolean", "true", "启用图片上传"),
+    ("frontend.features.audioNotifications", "boolean", "true", "启用声音提醒"),
+    ("frontend.features.messageCompression", "boolean", "true", "启用消息压缩"),
+    ("frontend.features.virtualScrolling", "boolean", "true", "启用虚拟滚动"),
+    ("frontend.features.offlineSupport", "boolean", "true", "启用离线支持"),
+    ("frontend.upload.maxFileSize", "integer", "10485760", "上传文件大小上限（字节）"),
+    ("frontend.upload.allowedTypes", "array<string>", r#"["image/jpeg","image/png","image/gif","image/webp"]"#, "允许上传的MIME类型"),
+    ("frontend.upload.compressionEnabled", "boolean", "true", "上传图片是否压缩"),
+    ("frontend.upload.compressionQuality", "number", "0.8", "图片压缩质量（0~1）"),
+    ("frontend.upload.maxWidth", "integer", "1920", "图片最大宽度（像素）"),
+    ("frontend.upload.maxHeight", "integer", "1080", "图片最大高度（像素）"),
+    ("frontend.upload.maxSizeByType", "map<string, integer>", "{}", "按MIME类型或主类型的大小上限（字节），未配置时使用 maxFileSize"),
+    ("frontend.clientProfiles", "map<string, array<object>>", "{}", "按客户端类型定制下发的配置，条目含 minVersion、wsUrl、features、maxFileSize"),
+    ("websocket.heartbeatInterval", "integer", "30000", "心跳及协议层ping间隔（毫秒），0 表示关闭ping"),
+    ("websocket.reconnectInterval", "integer", "5000", "客户端重连间隔（毫秒）"),
+    ("websocket.maxReconnectAttempts", "integer", "5", "客户端最大重连次数"),
+    ("websocket.messageTimeout", "integer", "10000", "消息超时时间（毫秒）"),
+    ("websocket.maxMessageSize", "integer", "1048576", "单条消息大小上限（字节）"),
+    ("websocket.reorderWindow", "integer", "200", "消息重排序窗口（毫秒），0 表示不重排"),
+    ("websocket.assignmentMode", "string", r#""auto""#, "客户分配方式：auto 或 manual"),
+    ("websocket.duplicateConnectionPolicy", "string", r#""replace""#, "同一用户重复连接的处理：replace 或 reject"),
+    ("websocket.newCustomerAlertCount", "integer", "3", "manual 模式下新客户提醒的客服人数"),
+    ("websocket.maxConnections", "integer", "10000", "同时在线连接数上限，0 表示不限制"),
+    ("websocket.sendQueueSize", "integer", "256", "每个连接的待发送消息队列长度"),
+    ("websocket.queueTimeoutSeconds", "integer", "300", "客户排队超时转留言的时长（秒），0表示不超时"),
+    ("websocket.sessionIdleTimeoutSeconds", "integer", "1800", "会话空闲自动结束的时长（秒），0表示不自动结束"),
+    ("websocket.bandwidthAlertBytesPerSec", "integer", "1048576", "单连接收发速率告警阈值（字节/秒），0表示不检查"),
+    ("websocket.geoipDatabasePath", "string", "null", "MaxMind City 数据库路径，用于连接事件的地理位置，文件不存在时跳过"),
+    ("websocket.ackTimeoutSeconds", "integer", "30", "需确认消息的确认超时（秒），超时计为未确认"),
+    ("websocket.unackedAlertRate", "number", "0.2", "消息未确认率告警阈值（0-1），0表示不告警"),
+    ("websocket.messageQueue.maxLength", "integer", "1000", "每个用户离线队列的消息数上限，超出丢弃最早的消息，0表示不限制"),
+    ("websocket.messageQueue.messageTtlSeconds", "integer", "604800", "离线消息保留时长（秒）"),
+    ("websocket.greeting.enabled", "boolean", "false", "客户分配到客服后是否自动发送问候语"),
+    ("websocket.greeting.message", "string", r#""您好{customer_name}，我是客服{kefu_name}，很高兴为您服务，请问有什么可以帮您？""#, "全局问候语，支持 {customer_name} 和 {kefu_name} 变量，为空时不发送"),
+    ("websocket.greeting.perKefu", "map<string, string>", "{}", "客服个人问候语，键为客服ID，优先于全局问候语"),
+    ("redis.host", "string", r#""127.0.0.1""#, "Redis地址，可由环境变量 REDIS_HOST 覆盖"),
+    ("redis.port", "integer", "6379", "Redis端口，可由环境变量 REDIS_PORT 覆盖"),
+    ("redis.password", "string", r#""""#, "Redis密码，可由环境变量 REDIS_PASSWORD 覆盖"),
+    ("redis.database", "integer", "0", "Redis数据库编号"),
+    ("redis.pool.maxSize", "integer", "20", "连接池最大连接数"),
+    ("redis.pool.minIdle", "integer", "5", "连接池最小空闲连接数"),
+    ("redis.pool.maxLifetime", "integer", "3600", "连接最长存活时间（秒）"),
+    ("redis.pool.idleTimeout", "integer", "300", "空闲连接超时（秒）"),
+    ("redis.mode", "string", r#""single""#, "部署方式：single、sentinel 或 cluster"),
+    ("redis.sentinel.addresses", "array<string>", "[]", "哨兵地址列表，sentinel 模式必填"),
+    ("redis.sentinel.masterName", "string", r#""""#, "哨兵监控的主节点名称，sentinel 模式必填"),
+    ("redis.cluster.nodes", "array<string>", "[]", "集群种子节点地址列表，cluster 模式必填"),
+    ("storage.dataDir", "string", r#""./data""#, "数据目录"),
+    ("storage.blobsDir", "string", r#""./data/blobs""#, "文件存储目录"),
+    ("storage.snapshotInterval", "integer", "300", "快照间隔（秒）"),
+    ("storage.maxSnapshotSize", "integer", "104857600", "快照大小上限（字节）"),
+    ("storage.export.maxExportsPerDay", "integer", "10", "每人每天最多导出次数，0 表示不限制"),
+    ("storage.export.maxMessagesPerDay", "integer", "10000", "每人每天最多导出消息条数，0 表示不限制"),
+    ("storage.encryption.enabled", "boolean", "false", "是否加密落盘消息的 content 和 filename（AES-256-GCM）"),
+    ("storage.encryption.key", "string", "null", "Base64 编码的 32 字节加密密钥，可由环境变量 STORAGE_ENCRYPTION_KEY 覆盖"),
+    ("storage.backend", "string", r#""local""#, "消息和二进制对象的存储后端：local 或 s3"),
+    ("storage.s3.bucket", "string", r#""""#, "S3 桶名，s3 后端必填"),
+    ("storage.s3.region", "string", r#""""#, "S3 区域"),
+    ("storage.s3.endpoint", "string", "null", "自定义 S3 兼容服务地址，为空时使用 AWS 官方地址"),
+    ("storage.s3.accessKeyId", "string", "null", "访问密钥ID，为空时读取环境变量 AWS_ACCESS_KEY_ID"),
+    ("storage.s3.secretAccessKey", "string", "null", "访问密钥，为空时读取环境变量 AWS_SECRET_ACCESS_KEY"),
+    ("storage.s3.prefix", "string", r#""""#, "对象键前缀"),
+    ("storage.s3.pathStyle", "boolean", "false", "是否使用路径风格地址，MinIO 等通常需要开启"),
+    ("security.jwtSecret", "string", "null", "JWT签名密钥，必须修改，生产环境至少32字节，可由环境变量 JWT_SECRET 覆盖"),
+    ("security.jwtExpiry", "integer", "86400", "JWT有效期（秒）"),
+    ("security.refreshTokenExpiry", "integer", "604800", "刷新令牌有效期（秒）"),
+    ("security.bcryptRounds", "integer", "10", "密码哈希轮数"),
+    ("security.rateLimiting.enabled", "boolean", "true", "是否启用限流"),
+    ("security.rateLimiting.windowMs", "integer", "60000", "限流窗口（毫秒）"),
+    ("security.rateLimiting.maxRequests", "integer", "100", "窗口内最大请求数"),
+    ("security.adminToken", "string", "null", "管理端点访问令牌，未配置时管理端点不可用"),
+    ("security.geoRisk.enabled", "boolean", "true", "是否启用异地登录风控"),
+    ("security.geoRisk.requireReverification", "boolean", "false", "位置异常时是否要求二次验证"),
+    ("security.geoRisk.reverificationWindow", "integer", "300", "令牌签发后视为已二次验证的时长（秒）"),
+    ("security.sso.providers", "array<object>", "[]", "单点登录身份提供方，条目含 id、name、authorizeUrl、tokenUrl、userinfoUrl、clientId、clientSecret、redirectUri、scopes、userType（默认 Kehu）、kefuGroups、groupsClaim"),
+    ("security.sso.stateTtlSeconds", "integer", "600", "单点登录发起到回调的最长时间（秒）"),
+    ("security.contentFilter.enabled", "boolean", "true", "是否启用聊天内容过滤"),
+    ("security.contentFilter.maxLength", "integer", "5000", "单条消息最大字符数，0 表示不限制"),
+    ("security.contentFilter.overLengthAction", "string", r#""block""#, "超长消息处理方式：block 拦截 / truncate 截断"),
+    ("security.contentFilter.profanityEnabled", "boolean", "false", "是否替换敏感词"),
+    ("security.contentFilter.wordListPath", "string", r#""config/profanity_words.json""#, "敏感词表文件（JSON 字符串数组），支持热更新"),
+    ("logging.level", "string", r#""info""#, "日志级别"),
+    ("logging.format", "string", r#""json""#, "日志格式"),
+    ("logging.file.enabled", "boolean", "true", "是否写入日志文件"),
+    ("logging.file.path", "string", r#""./logs/app.log""#, "日志文件路径"),
+    ("logging.file.maxSize", "integer", "10485760", "单个日志文件大小上限（字节）"),
+    ("logging.file.maxFiles", "integer", "5", "保留的日志文件数"),
+    ("performance.messageCache.enabled", "boolean", "true", "是否启用消息缓存"),
+    ("performance.messageCache.maxSize", "integer", "1000", "消息缓存条数上限"),
+    ("performance.messageCache.ttl", "integer", "3600", "消息缓存过期时间（秒）"),
+    ("performance.compression.enabled", "boolean", "true", "是否启用WebSocket帧压缩"),
+    ("performance.compression.threshold", "integer", "1024", "超过该大小（字节）的消息才压缩"),
+    ("performance.templateRenderCache.enabled", "boolean", "true", "是否缓存HTML模板渲染结果"),
+    ("performance.templateRenderCache.maxSize", "integer", "500", "模板渲染缓存条数上限"),
+    ("performance.templateRenderCache.ttl", "integer", "600", "模板渲染缓存过期时间（秒）"),
+    ("performance.analyticsCache.enabled", "boolean", "true", "是否缓存仪表盘统计接口的结果"),
+    ("performance.analyticsCache.maxSize", "integer", "200", "统计结果缓存条数上限"),
+    ("performance.analyticsCache.ttl", "integer", "30", "统计结果缓存有效期（秒）"),
+    ("performance.sla.firstResponseSeconds", "integer", "30", "首响SLA时限（秒）"),
+    ("performance.sla.targetRate", "number", "0.9", "首响SLA达成率目标"),
+];
+
+/// 导出所有配置项的名称、类型、默认值和说明，供前端和运维查阅
+pub fn export_config_schema() -> serde_json::Value {
+    let fields: Vec<serde_json::Value> = CONFIG_SCHEMA
+        .iter()
+        .map(|(name, field_type, default, description)| {
+            serde_json::json!({
+                "name": name,
+                "type": field_type,
+                "default": serde_json::from_str::<serde_json::Value>(default).unwrap_or_default(),
+                "description": description,
+            })
+        })
+        .collect();
+    serde_json::json!({ "fields": fields })
+}
+
 /// 加载并初始化配置
 pub fn init_config() -> Result<(), Box<dyn std::error::Error>> {
     // 尝试多个可能的配置文件路径
@@ -280,3 +965,67 @@ pub fn init_config() -> Result<(), Box<dyn std::error::Error>> {
     AppConfig::init(config).map_err(|_| "配置已初始化")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_type<'a>(schema: &'a serde_json::Value, name: &str) -> Option<&'a str> {
+        schema["fields"]
+            .as_array()?
+            .iter()
+            .find(|field| field["name"] == name)?["type"]
+            .as_str()
+    }
+
+    fn collect_leaves(prefix: &str, value: &serde_json::Value, leaves: &mut Vec<(String, serde_json::Value)>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, child) in map {
+                    let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                    collect_leaves(&path, child, leaves);
+                }
+            }
+            _ => leaves.push((prefix.to_string(), value.clone())),
+        }
+    }
+
+    #[test]
+    fn test_schema_lists_key_fields_with_types() {
+        let schema = export_config_schema();
+        assert_eq!(schema_type(&schema, "server.port"), Some("integer"));
+        assert_eq!(schema_type(&schema, "redis.host"), Some("string"));
+        assert_eq!(schema_type(&schema, "websocket.heartbeatInterval"), Some("integer"));
+        assert_eq!(schema_type(&schema, "security.geoRisk.enabled"), Some("boolean"));
+        assert_eq!(schema_type(&schema, "frontend.upload.compressionQuality"), Some("number"));
+
+        let port = schema["fields"].as_array().unwrap().iter().find(|f| f["name"] == "server.port").unwrap();
+        assert_eq!(port["default"], 6006);
+        assert!(!port["description"].as_str().unwrap().is_empty());
+        // 密钥不提供默认值
+        let secret = schema["fields"].as_array().unwrap().iter().find(|f| f["name"] == "security.jwtSecret").unwrap();
+        assert!(secret["default"].is_null());
+    }
+
+    #[test]
+    fn test_schema_covers_shipped_config() {
+        let shipped: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("config/app-config.json").unwrap()).unwrap();
+        let mut leaves = Vec::new();
+        collect_leaves("", &shipped, &mut leaves);
+
+        let schema = export_config_schema();
+        for (path, value) in leaves {
+            let field_type = schema_type(&schema, &path).unwrap_or_else(|| panic!("配置项缺少schema: {}", path));
+            let expected = match value {
+                serde_json::Value::Bool(_) => "boolean",
+                serde_json::Value::Number(n) if n.is_f64() => "number",
+                serde_json::Value::Number(_) => "integer",
+                serde_json::Value::String(_) => "string",
+                serde_json::Value::Array(_) => "array<string>",
+                _ => "unknown",
+            };
+            assert_eq!(field_type, expected, "配置项类型不一致: {}", path);
+        }
+    }
+}
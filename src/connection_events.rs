@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::geo_risk::GeoLocation;
+use crate::message::UserConnection;
+
+/// 每个用户保留的最近连接事件条数
+pub const MAX_CONNECTION_EVENTS: isize = 200;
+
+/// 连接事件保留时长（30天），从最近一次事件起算
+pub const CONNECTION_EVENTS_TTL_SECS: usize = 30 * 24 * 3600;
+
+/// 查询连接事件时默认返回的条数
+pub const DEFAULT_EVENT_LIMIT: usize = 50;
+
+/// User-Agent 最多保存的字符数
+const MAX_USER_AGENT_CHARS: usize = 256;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionEventKind {
+    Connect,
+    Disconnect,
+}
+
+/// 用户连接/断开的审计事件，按时间存在 Redis 有序集合 events:{user_id} 中
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConnectionEvent {
+    /// 事件ID，保证同一毫秒内的相同事件也是不同成员
+    pub id: String,
+    pub user_id: String,
+    pub kind: ConnectionEventKind,
+    pub timestamp: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// 断开事件记录本次连接持续的秒数
+    pub duration_secs: Option<i64>,
+    /// 按IP解析出的地理位置，未配置GeoIP数据库或无法解析时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<GeoLocation>,
+}
+
+impl ConnectionEvent {
+    pub fn connect(connection: &UserConnection) -> Self {
+        Self::new(connection, ConnectionEventKind::Connect, connection.connected_at, None)
+    }
+
+    pub fn disconnect(connection: &UserConnection, at: DateTime<Utc>) -> Self {
+        let duration = (at - connection.connected_at).num_seconds().max(0);
+        Self::new(connection, ConnectionEventKind::Disconnect, at, Some(duration))
+    }
+
+    fn new(
+        connection: &UserConnection,
+        kind: ConnectionEventKind,
+        timestamp: DateTime<Utc>,
+        duration_secs: Option<i64>,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: connection.user_id.clone(),
+            kind,
+            timestamp,
+            ip: connection.client_ip.clone(),
+            user_agent: connection
+                .user_agent
+                .as_deref()
+                .map(|agent| agent.chars().take(MAX_USER_AGENT_CHARS).collect()),
+            duration_secs,
+            location: None,
+        }
+    }
+}
+
+pub fn connection_events_key(user_id: &str) -> String {
+    format!("events:{}", user_id)
+}
+
+/// 解析有序集合中的事件记录，跳过无法解析的
+pub fn parse_events(entries: &[String]) -> Vec<ConnectionEvent> {
+    entries.iter().filter_map(|entry| serde_json::from_str(entry).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{OnlineStatus, UserType};
+
+    #[test]
+    fn test_events_capture_ip_agent_and_duration() {
+        let connected_at = Utc::now() - chrono::Duration::seconds(90);
+        let connection = UserConnection {
+            user_id: "kehu_1".to_string(),
+            user_name: "客户1".to_string(),
+            user_type: UserType::Kehu,
+            zhanghao: None,
+            connected_at,
+            last_heartbeat: connected_at,
+            status: OnlineStatus::Online,
+            suspicious_reason: None,
+            client_ip: Some("203.0.113.7".to_string()),
+            user_agent: Some("x".repeat(1000)),
+        };
+
+        let connect = ConnectionEvent::connect(&connection);
+        assert_eq!(connect.kind, ConnectionEventKind::Connect);
+        assert_eq!(connect.timestamp, connected_at);
+        assert_eq!(connect.ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(connect.user_agent.as_ref().unwrap().len(), MAX_USER_AGENT_CHARS);
+        assert_eq!(connect.duration_secs, None);
+        assert_eq!(connect.location, None);
+
+        let disconnect = ConnectionEvent::disconnect(&connection, connected_at + chrono::Duration::seconds(90));
+        assert_eq!(disconnect.kind, ConnectionEventKind::Disconnect);
+        assert_eq!(disconnect.duration_secs, Some(90));
+        assert_ne!(connect.id, disconnect.id);
+
+        // 有序集合中的记录原样解析，损坏的记录跳过
+        let entries = vec![
+            serde_json::to_string(&disconnect).unwrap(),
+            "not json".to_string(),
+            serde_json::to_string(&connect).unwrap(),
+        ];
+        assert_eq!(parse_events(&entries), vec![disconnect, connect]);
+        assert_eq!(connection_events_key("kehu_1"), "events:kehu_1");
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use crate::config::{ContentFilterConfig, OverLengthAction};
+
+/// 过滤结果：放行、已替换（截断或敏感词打码）、拦截
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterDecision {
+    Allowed,
+    Masked,
+    Blocked,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterOutcome {
+    pub decision: FilterDecision,
+    /// 过滤后的内容，拦截时为原内容
+    pub content: String,
+    /// 被替换或拦截的原因
+    pub reason: Option<String>,
+}
+
+/// 聊天内容过滤器：超长消息拦截或截断，敏感词替换为 *；词表文件可热更新
+#[derive(Clone)]
+pub struct ContentFilter {
+    config: ContentFilterConfig,
+    words: Arc<RwLock<Vec<String>>>,
+    source: Option<PathBuf>,
+    source_modified: Arc<RwLock<Option<SystemTime>>>,
+}
+
+impl Default for ContentFilter {
+    fn default() -> Self {
+        Self::new(ContentFilterConfig::default(), Vec::new())
+    }
+}
+
+impl ContentFilter {
+    pub fn new(config: ContentFilterConfig, words: Vec<String>) -> Self {
+        Self {
+            config,
+            words: Arc::new(RwLock::new(words)),
+            source: None,
+            source_modified: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// 按配置创建，词表从 wordListPath 加载，文件不存在时词表为空；之后可通过 reload_if_changed 热更新
+    pub fn from_config(config: &ContentFilterConfig) -> Result<Self> {
+        let mut filter = Self::new(config.clone(), Vec::new());
+        if !config.word_list_path.is_empty() {
+            filter.source = Some(PathBuf::from(&config.word_list_path));
+            filter.reload_if_changed()?;
+        }
+        Ok(filter)
+    }
+
+    pub fn update_words(&self, words: Vec<String>) {
+        if let Ok(mut current) = self.words.write() {
+            *current = words;
+        }
+    }
+
+    /// 词表文件有变化时重新加载，返回是否发生了更新
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let path = match &self.source {
+            Some(path) if path.exists() => path,
+            _ => return Ok(false),
+        };
+
+        let modified = std::fs::metadata(path)?.modified().ok();
+        {
+            let last = self.source_modified.read().map_err(|_| anyhow::anyhow!("词表状态锁异常"))?;
+            if modified.is_some() && *last == modified {
+                return Ok(false);
+            }
+        }
+
+        let words: Vec<String> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        tracing::info!("🧹 敏感词表已加载: {} 个 ({})", words.len(), path.display());
+        self.update_words(words);
+        if let Ok(mut last) = self.source_modified.write() {
+            *last = modified;
+        }
+        Ok(true)
+    }
+
+    /// 检查一条聊天内容，先处理长度再替换敏感词
+    pub fn check(&self, content: &str) -> FilterOutcome {
+        if !self.config.enabled {
+            return FilterOutcome { decision: FilterDecision::Allowed, content: content.to_string(), reason: None };
+        }
+
+        let mut reasons = Vec::new();
+        let mut filtered = content.to_string();
+        let max_length = self.config.max_length;
+        if max_length > 0 && content.chars().count() > max_length {
+            let reason = format!("消息长度超过上限{}字", max_length);
+            if self.config.over_length_action == OverLengthAction::Block {
+                return FilterOutcome {
+                    decision: FilterDecision::Blocked,
+                    content: content.to_string(),
+                    reason: Some(reason),
+                };
+            }
+            filtered = content.chars().take(max_length).collect();
+            reasons.push(reason);
+        }
+
+        if self.config.profanity_enabled {
+            let words = self.words.read().map(|words| words.clone()).unwrap_or_default();
+            if let Some(masked) = mask_words(&filtered, &words) {
+                filtered = masked;
+                reasons.push("包含敏感词".to_string());
+            }
+        }
+
+        FilterOutcome {
+            decision: if reasons.is_empty() { FilterDecision::Allowed } else { FilterDecision::Masked },
+            content: filtered,
+            reason: (!reasons.is_empty()).then(|| reasons.join("，")),
+        }
+    }
+}
+
+// 按字符匹配，英文不区分大小写；命中的每个字符替换为 *，没有命中时返回 None
+fn mask_words(content: &str, words: &[String]) -> Option<String> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let mut chars: Vec<char> = content.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+    let mut masked = false;
+
+    for word in words {
+        let word: Vec<char> = word.trim().chars().map(fold).collect();
+        if word.is_empty() || word.len() > folded.len() {
+            continue;
+        }
+        let mut start = 0;
+        while start + word.len() <= folded.len() {
+            if folded[start..start + word.len()] == word[..] {
+                chars[start..start + word.len()].fill('*');
+                masked = true;
+                start += word.len();
+            } else {
+                start += 1;
+            }
+        }
+    }
+
+    masked.then(|| chars.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(max_length: usize, action: OverLengthAction) -> ContentFilter {
+        let config = ContentFilterConfig {
+            enabled: true,
+            max_length,
+            over_length_action: action,
+            profanity_enabled: true,
+            word_list_path: String::new(),
+        };
+        ContentFilter::new(config, vec!["傻瓜".to_string(), "Damn".to_string()])
+    }
+
+    #[test]
+    fn test_length_limit_blocks_or_truncates() {
+        let blocked = filter(5, OverLengthAction::Block).check("这条消息有点太长了");
+        assert_eq!(blocked.decision, FilterDecision::Blocked);
+        assert!(blocked.reason.unwrap().contains("5"));
+
+        let truncated = filter(5, OverLengthAction::Truncate).check("这条消息有点太长了");
+        assert_eq!(truncated.decision, FilterDecision::Masked);
+        assert_eq!(truncated.content, "这条消息有");
+
+        let allowed = filter(5, OverLengthAction::Block).check("你好");
+        assert_eq!(allowed.decision, FilterDecision::Allowed);
+        assert_eq!(allowed.content, "你好");
+        assert!(allowed.reason.is_none());
+    }
+
+    #[test]
+    fn test_profanity_masked_before_forwarding() {
+        let outcome = filter(0, OverLengthAction::Block).check("你这个傻瓜，damn it, DAMN!");
+        assert_eq!(outcome.decision, FilterDecision::Masked);
+        assert_eq!(outcome.content, "你这个**，**** it, ****!");
+
+        let disabled = ContentFilter::new(ContentFilterConfig::default(), vec!["傻瓜".to_string()]);
+        assert_eq!(disabled.check("傻瓜").decision, FilterDecision::Allowed);
+    }
+
+    #[test]
+    fn test_word_list_hot_reload() {
+        let path = std::env::temp_dir().join(format!("profanity_words_{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"["笨蛋"]"#).unwrap();
+        let config = ContentFilterConfig {
+            enabled: true,
+            profanity_enabled: true,
+            word_list_path: path.to_string_lossy().to_string(),
+            ..ContentFilterConfig::default()
+        };
+
+        let filter = ContentFilter::from_config(&config).unwrap();
+        assert_eq!(filter.check("你是笨蛋").content, "你是**");
+        assert!(!filter.reload_if_changed().unwrap());
+
+        // 改写词表文件；文件系统的修改时间精度有限，显式把修改时间往后调，确保能看出变化
+        std::fs::write(&path, r#"["讨厌"]"#).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(5))
+            .unwrap();
+        assert!(filter.reload_if_changed().unwrap());
+        assert_eq!(filter.check("你是笨蛋").decision, FilterDecision::Allowed);
+        assert_eq!(filter.check("真讨厌").content, "真**");
+        // 文件未再变化时不重复加载
+        assert!(!filter.reload_if_changed().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_filter_disabled_by_default() {
+        let config = ContentFilterConfig::default();
+        assert!(!config.enabled);
+        let outcome = ContentFilter::new(config, vec!["傻瓜".to_string()]).check(&"傻瓜".repeat(10_000));
+        assert_eq!(outcome.decision, FilterDecision::Allowed);
+    }
+}
@@ -1,10 +1,16 @@
 
+pub mod websocket;
+
+pub use websocket::WebSocketError;
+
 use std::sync::atomic::AtomicU64;
 use warp::reject::Rejection;
 use warp::reply::Reply;
 
 use serde::{Deserialize, Serialize};
 
+use crate::middleware::request_id::current_request_id;
+
 /// 全局错误计数器 - 用于限制重复错误日志
 /// WebSocket参数错误计数器，用于监控和调试
 #[allow(dead_code)] // 用于错误统计和监控
@@ -18,35 +24,74 @@ pub struct InvalidParams {
 
 impl warp::reject::Reject for InvalidParams {}
 
+/// 认证失败错误
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Unauthorized {
+    pub message: String,
+}
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// 已认证但无权访问（如被封禁）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Forbidden {
+    pub message: String,
+}
+
+impl warp::reject::Reject for Forbidden {}
+
+/// 上传文件未通过类型/大小校验
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadRejected {
+    pub message: String,
+}
+
+impl warp::reject::Reject for UploadRejected {}
+
 /// 统一错误处理函数
 /// 
 /// 将各种类型的错误转换为统一的JSON响应格式
 pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, std::convert::Infallible> {
     let code;
-    let message;
+    let message: String;
 
     if err.is_not_found() {
         code = warp::http::StatusCode::NOT_FOUND;
-        message = "路径不存在";
+        message = "路径不存在".to_string();
     } else if err.find::<InvalidParams>().is_some() {
         code = warp::http::StatusCode::BAD_REQUEST;
-        message = "参数错误";
+        message = "参数错误".to_string();
+    } else if let Some(rejected) = err.find::<UploadRejected>() {
+        code = warp::http::StatusCode::BAD_REQUEST;
+        message = rejected.message.clone();
+    } else if err.find::<Unauthorized>().is_some() {
+        code = warp::http::StatusCode::UNAUTHORIZED;
+        message = "认证失败".to_string();
+    } else if let Some(forbidden) = err.find::<Forbidden>() {
+        code = warp::http::StatusCode::FORBIDDEN;
+        message = forbidden.message.clone();
+    } else if err.find::<warp::cors::CorsForbidden>().is_some() {
+        code = warp::http::StatusCode::FORBIDDEN;
+        message = "跨域请求来源不被允许".to_string();
     } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
         code = warp::http::StatusCode::METHOD_NOT_ALLOWED;
-        message = "方法不允许";
+        message = "方法不允许".to_string();
     } else {
         tracing::error!("未处理的错误: {:?}", err);
         code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
-        message = "内部服务器错误";
+        message = "内部服务器错误".to_string();
     }
 
-    let json = warp::reply::json(&serde_json::json!({
+    let mut body = serde_json::json!({
         "success": false,
         "message": message,
         "code": code.as_u16()
-    }));
+    });
+    if let Some(request_id) = current_request_id() {
+        body["request_id"] = serde_json::Value::String(request_id);
+    }
 
-    Ok(warp::reply::with_status(json, code))
+    Ok(warp::reply::with_status(warp::reply::json(&body), code))
 }
 
 /// 记录WebSocket参数错误
@@ -101,4 +146,42 @@ mod tests {
         
         assert!(result.is_ok(), "handle_rejection应该能处理InvalidParams错误");
     }
+
+    #[tokio::test]
+    async fn test_handle_rejection_unauthorized() {
+        use warp::reject;
+
+        let rejection = reject::custom(Unauthorized {
+            message: "令牌无效".to_string(),
+        });
+        let response = handle_rejection(rejection).await.unwrap().into_response();
+
+        assert_eq!(response.status(), warp::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_handle_rejection_forbidden() {
+        use warp::reject;
+
+        let rejection = reject::custom(Forbidden {
+            message: "账号已被封禁".to_string(),
+        });
+        let response = handle_rejection(rejection).await.unwrap().into_response();
+        assert_eq!(response.status(), warp::http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_handle_rejection_upload_rejected() {
+        use warp::reject;
+
+        let rejection = reject::custom(UploadRejected {
+            message: "不支持的文件类型: application/x-msdownload".to_string(),
+        });
+        let response = handle_rejection(rejection).await.unwrap().into_response();
+        assert_eq!(response.status(), warp::http::StatusCode::BAD_REQUEST);
+
+        let body = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["message"], "不支持的文件类型: application/x-msdownload");
+    }
 } 
\ No newline at end of file
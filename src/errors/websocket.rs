@@ -0,0 +1,61 @@
+use thiserror::Error;
+
+/// WebSocket 消息处理的错误类型，调用方据此区分稍后重试可能成功的临时故障和重试无用的错误
+#[derive(Debug, Error)]
+pub enum WebSocketError {
+    /// 聊天消息既没有指定接收方，发送方也没有会话伙伴；分配客服后可重新发送
+    #[error("用户{0}的消息没有接收方")]
+    NoRecipient(String),
+    /// 接收方不在线，消息已保存并进入离线队列，接收方上线后补发
+    #[error("接收方{0}不在线，消息已转入离线队列")]
+    RecipientOffline(String),
+    #[error("消息序列化失败: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Redis操作失败: {0}")]
+    Redis(#[source] anyhow::Error),
+    #[error("本地存储失败: {0}")]
+    Storage(#[source] anyhow::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl WebSocketError {
+    /// 没有接收方、接收方离线和Redis连接类故障是临时的；序列化、存储等错误重试也不会成功
+    pub fn is_transient(&self) -> bool {
+        match self {
+            WebSocketError::NoRecipient(_) | WebSocketError::RecipientOffline(_) => true,
+            WebSocketError::Redis(e) => crate::redis_client::is_retryable(e),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_and_permanent_failures() {
+        assert!(WebSocketError::NoRecipient("kehu_001".to_string()).is_transient());
+        assert!(WebSocketError::RecipientOffline("kefu_001".to_string()).is_transient());
+
+        let connection_refused = redis::RedisError::from(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            "connection refused",
+        ));
+        assert!(WebSocketError::Redis(connection_refused.into()).is_transient());
+        let type_error = redis::RedisError::from((redis::ErrorKind::TypeError, "wrong type"));
+        assert!(!WebSocketError::Redis(type_error.into()).is_transient());
+
+        let serialization = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert!(!WebSocketError::from(serialization).is_transient());
+        assert!(!WebSocketError::Storage(anyhow::anyhow!("disk full")).is_transient());
+
+        // 经 anyhow 传到边界后仍可还原出具体类型
+        let boundary: anyhow::Error = WebSocketError::NoRecipient("kehu_001".to_string()).into();
+        assert!(matches!(
+            boundary.downcast_ref::<WebSocketError>(),
+            Some(WebSocketError::NoRecipient(_))
+        ));
+    }
+}
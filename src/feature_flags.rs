@@ -0,0 +1,404 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::redis_client::RedisManager;
+
+/// 功能开关存放的Redis哈希，字段为开关名，值为开关JSON
+const FEATURE_FLAGS_KEY: &str = "feature_flags";
+
+/// 各实例从Redis同步开关状态的间隔
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 功能开关：总开关关闭时对所有人关闭；开启时依次看用户黑白名单、租户、灰度百分比
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeatureFlag {
+    /// 通过管理接口修改时以路径中的开关名为准
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// 总开关，关闭时覆盖其余规则
+    #[serde(default)]
+    pub enabled: bool,
+    /// 按用户哈希灰度的百分比（0-100），100 即全量开启
+    #[serde(default = "default_percentage")]
+    pub percentage: u32,
+    /// 强制开启的用户，不受灰度比例限制
+    #[serde(default)]
+    pub allow_users: Vec<String>,
+    /// 强制关闭的用户，优先于其余规则
+    #[serde(default)]
+    pub deny_users: Vec<String>,
+    /// 整体开启的租户
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+fn default_percentage() -> u32 {
+    100
+}
+
+#[cfg(test)]
+impl FeatureFlag {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            enabled: true,
+            percentage: default_percentage(),
+            allow_users: Vec::new(),
+            deny_users: Vec::new(),
+            tenants: Vec::new(),
+            updated_at: None,
+        }
+    }
+
+    pub fn with_percentage(mut self, percentage: u32) -> Self {
+        self.percentage = percentage;
+        self
+    }
+
+    pub fn with_allow_users(mut self, users: Vec<String>) -> Self {
+        self.allow_users = users;
+        self
+    }
+
+    pub fn with_deny_users(mut self, users: Vec<String>) -> Self {
+        self.deny_users = users;
+        self
+    }
+
+    pub fn with_tenants(mut self, tenants: Vec<String>) -> Self {
+        self.tenants = tenants;
+        self
+    }
+}
+
+impl FeatureFlag {
+    /// 判断开关对给定上下文是否开启；没有用户时只有全量开启才算开启
+    pub fn evaluate(&self, ctx: &FlagContext) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if let Some(user_id) = ctx.user_id.as_deref() {
+            if self.deny_users.iter().any(|user| user == user_id) {
+                return false;
+            }
+            if self.allow_users.iter().any(|user| user == user_id) {
+                return true;
+            }
+        }
+        if ctx
+            .tenant_id
+            .as_deref()
+            .is_some_and(|tenant_id| self.tenants.iter().any(|tenant| tenant == tenant_id))
+        {
+            return true;
+        }
+        match ctx.user_id.as_deref() {
+            Some(user_id) => rollout_bucket(&self.name, user_id) < self.percentage.min(100),
+            None => self.percentage >= 100,
+        }
+    }
+}
+
+/// 稳定分桶（0-99）：同一开关下同一用户总是落在同一个桶，与进程和实例无关；
+/// 调大百分比时已开启的用户保持开启
+pub fn rollout_bucket(flag: &str, user_id: &str) -> u32 {
+    let mut hasher = Sha256::new();
+    hasher.update(flag.as_bytes());
+    hasher.update(b":");
+    hasher.update(user_id.as_bytes());
+    let digest = hasher.finalize();
+    (u64::from_be_bytes(digest[..8].try_into().unwrap_or_default()) % 100) as u32
+}
+
+/// 判断开关时的上下文
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FlagContext {
+    pub user_id: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+impl FlagContext {
+    pub fn user(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: Some(user_id.into()),
+            tenant_id: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_tenant(mut self, tenant_id: impl Into<String>) -> Self {
+        self.tenant_id = Some(tenant_id.into());
+        self
+    }
+}
+
+/// 功能开关的存储接口
+#[async_trait::async_trait]
+pub trait FeatureFlagStore: Send + Sync {
+    async fn load_all(&self) -> Result<Vec<FeatureFlag>>;
+    async fn save(&self, flag: &FeatureFlag) -> Result<()>;
+    async fn remove(&self, name: &str) -> Result<bool>;
+}
+
+/// 基于Redis的开关存储，多实例共享
+pub struct RedisFeatureFlagStore {
+    redis: RedisManager,
+}
+
+impl RedisFeatureFlagStore {
+    pub fn new(redis: RedisManager) -> Self {
+        Self { redis }
+    }
+}
+
+#[async_trait::async_trait]
+impl FeatureFlagStore for RedisFeatureFlagStore {
+    async fn load_all(&self) -> Result<Vec<FeatureFlag>> {
+        let mut conn = self.redis.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hgetall(FEATURE_FLAGS_KEY);
+        let (entries,): (HashMap<String, String>,) = conn.query_pipeline(&pipe).await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|(name, raw)| match serde_json::from_str(&raw) {
+                Ok(flag) => Some(flag),
+                Err(e) => {
+                    tracing::warn!("🚩 功能开关 {} 解析失败，已跳过: {}", name, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    async fn save(&self, flag: &FeatureFlag) -> Result<()> {
+        let mut conn = self.redis.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hset(FEATURE_FLAGS_KEY, &flag.name, serde_json::to_string(flag)?).ignore();
+        conn.query_pipeline::<()>(&pipe).await
+    }
+
+    async fn remove(&self, name: &str) -> Result<bool> {
+        let mut conn = self.redis.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hdel(FEATURE_FLAGS_KEY, name);
+        let (removed,): (u32,) = conn.query_pipeline(&pipe).await?;
+        Ok(removed > 0)
+    }
+}
+
+/// 进程内开关存储，用于测试
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryFeatureFlagStore {
+    flags: tokio::sync::RwLock<HashMap<String, FeatureFlag>>,
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl FeatureFlagStore for MemoryFeatureFlagStore {
+    async fn load_all(&self) -> Result<Vec<FeatureFlag>> {
+        Ok(self.flags.read().await.values().cloned().collect())
+    }
+
+    async fn save(&self, flag: &FeatureFlag) -> Result<()> {
+        self.flags.write().await.insert(flag.name.clone(), flag.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, name: &str) -> Result<bool> {
+        Ok(self.flags.write().await.remove(name).is_some())
+    }
+}
+
+/// 功能开关管理器：判断走本地缓存，修改先写存储再更新缓存，其他实例通过定期 refresh 同步
+pub struct FeatureFlags {
+    store: Arc<dyn FeatureFlagStore>,
+    flags: RwLock<HashMap<String, FeatureFlag>>,
+}
+
+impl FeatureFlags {
+    pub fn new(store: Arc<dyn FeatureFlagStore>) -> Self {
+        Self {
+            store,
+            flags: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 代码中守卫新功能用；未定义的开关视为关闭
+    pub fn is_enabled(&self, flag: &str, ctx: &FlagContext) -> bool {
+        self.flags
+            .read()
+            .ok()
+            .and_then(|flags| flags.get(flag).map(|flag| flag.evaluate(ctx)))
+            .unwrap_or(false)
+    }
+
+    pub fn get(&self, name: &str) -> Option<FeatureFlag> {
+        self.flags.read().ok().and_then(|flags| flags.get(name).cloned())
+    }
+
+    /// 全部开关，按名称排序
+    pub fn list(&self) -> Vec<FeatureFlag> {
+        let mut flags: Vec<FeatureFlag> = self
+            .flags
+            .read()
+            .map(|flags| flags.values().cloned().collect())
+            .unwrap_or_default();
+        flags.sort_by(|a, b| a.name.cmp(&b.name));
+        flags
+    }
+
+    /// 新增或修改开关，立即在本实例生效
+    pub async fn set_flag(&self, mut flag: FeatureFlag) -> Result<FeatureFlag> {
+        flag.name = flag.name.trim().to_string();
+        if flag.name.is_empty() {
+            return Err(anyhow::anyhow!("开关名不能为空"));
+        }
+        if flag.percentage > 100 {
+            return Err(anyhow::anyhow!("灰度百分比必须在 0-100 之间: {}", flag.percentage));
+        }
+        flag.updated_at = Some(Utc::now());
+
+        self.store.save(&flag).await?;
+        if let Ok(mut flags) = self.flags.write() {
+            flags.insert(flag.name.clone(), flag.clone());
+        }
+        tracing::info!(
+            "🚩 功能开关已更新: {} enabled={} percentage={}",
+            flag.name,
+            flag.enabled,
+            flag.percentage
+        );
+        Ok(flag)
+    }
+
+    pub async fn remove_flag(&self, name: &str) -> Result<bool> {
+        let removed = self.store.remove(name).await?;
+        if let Ok(mut flags) = self.flags.write() {
+            flags.remove(name);
+        }
+        if removed {
+            tracing::info!("🚩 功能开关已删除: {}", name);
+        }
+        Ok(removed)
+    }
+
+    /// 从存储重新加载全部开关，返回开关数量
+    pub async fn refresh(&self) -> Result<usize> {
+        let loaded: HashMap<String, FeatureFlag> = self
+            .store
+            .load_all()
+            .await?
+            .into_iter()
+            .map(|flag| (flag.name.clone(), flag))
+            .collect();
+        let count = loaded.len();
+        if let Ok(mut flags) = self.flags.write() {
+            *flags = loaded;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users() -> Vec<String> {
+        (0..10_000).map(|i| format!("kehu_{}", i)).collect()
+    }
+
+    #[test]
+    fn test_percentage_rollout_stable_and_monotonic() {
+        let users = users();
+        let rollout = FeatureFlag::new("new_queue").with_percentage(30);
+        let enabled: Vec<&String> = users
+            .iter()
+            .filter(|user| rollout.evaluate(&FlagContext::user(user.as_str())))
+            .collect();
+        // 30% 用户开启，允许小幅误差
+        assert!((2_700..=3_300).contains(&enabled.len()), "开启人数: {}", enabled.len());
+
+        // 调大比例后原来开启的用户仍然开启
+        let wider = rollout.clone().with_percentage(60);
+        assert!(enabled.iter().all(|user| wider.evaluate(&FlagContext::user(user.as_str()))));
+
+        let none = rollout.clone().with_percentage(0);
+        assert!(users.iter().all(|user| !none.evaluate(&FlagContext::user(user.as_str()))));
+        let all = rollout.clone().with_percentage(100);
+        assert!(users.iter().all(|user| all.evaluate(&FlagContext::user(user.as_str()))));
+
+        // 没有用户时只有全量才开启；总开关关闭时全部关闭
+        assert!(!rollout.evaluate(&FlagContext::default()));
+        assert!(all.evaluate(&FlagContext::default()));
+        let killed = FeatureFlag { enabled: false, ..all };
+        assert!(users.iter().all(|user| !killed.evaluate(&FlagContext::user(user.as_str()))));
+    }
+
+    #[test]
+    fn test_user_and_tenant_overrides() {
+        let users = users();
+        let excluded = users
+            .iter()
+            .find(|user| rollout_bucket("new_queue", user) < 50)
+            .unwrap()
+            .clone();
+        let flag = FeatureFlag::new("new_queue")
+            .with_percentage(50)
+            .with_allow_users(vec!["kefu_beta".to_string()])
+            .with_deny_users(vec![excluded.clone()])
+            .with_tenants(vec!["tenant_a".to_string()]);
+
+        // 白名单用户不受比例限制，黑名单用户即使落在灰度范围内也关闭
+        assert!(flag.evaluate(&FlagContext::user("kefu_beta")));
+        assert!(!flag.evaluate(&FlagContext::user(excluded.as_str())));
+        assert!(!flag.evaluate(&FlagContext::user(excluded.as_str()).with_tenant("tenant_a")));
+
+        // 租户整体开启
+        let outside = users
+            .iter()
+            .find(|user| rollout_bucket("new_queue", user) >= 50)
+            .unwrap();
+        assert!(!flag.evaluate(&FlagContext::user(outside.as_str())));
+        assert!(flag.evaluate(&FlagContext::user(outside.as_str()).with_tenant("tenant_a")));
+        assert!(!flag.evaluate(&FlagContext::user(outside.as_str()).with_tenant("tenant_b")));
+    }
+
+    #[tokio::test]
+    async fn test_runtime_changes_sync_between_instances() {
+        let store: Arc<dyn FeatureFlagStore> = Arc::new(MemoryFeatureFlagStore::default());
+        let first = FeatureFlags::new(store.clone());
+        let second = FeatureFlags::new(store);
+        let ctx = FlagContext::user("kehu_1");
+        assert!(!first.is_enabled("new_queue", &ctx));
+
+        first.set_flag(FeatureFlag::new("new_queue")).await.unwrap();
+        assert!(first.is_enabled("new_queue", &ctx));
+        // 其他实例同步前沿用旧状态
+        assert!(!second.is_enabled("new_queue", &ctx));
+        assert_eq!(second.refresh().await.unwrap(), 1);
+        assert!(second.is_enabled("new_queue", &ctx));
+
+        first
+            .set_flag(FeatureFlag::new("new_queue").with_deny_users(vec!["kehu_1".to_string()]))
+            .await
+            .unwrap();
+        second.refresh().await.unwrap();
+        assert!(!second.is_enabled("new_queue", &ctx));
+
+        assert!(first.set_flag(FeatureFlag::new("bad").with_percentage(101)).await.is_err());
+        assert!(first.remove_flag("new_queue").await.unwrap());
+        assert_eq!(second.refresh().await.unwrap(), 0);
+        assert!(second.list().is_empty());
+    }
+}
@@ -9,6 +9,18 @@ use tracing::{error, info, warn};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+/// 缩略图最长边（像素）
+pub const THUMBNAIL_MAX_DIMENSION: u32 = 256;
+
+/// 缩略图访问地址前缀，后接文件ID
+pub const THUMBNAIL_URL_PREFIX: &str = "/api/file/thumbnail/";
+
+/// 生成缩略图时允许解码的原图最长边（像素），防止小文件声明超大尺寸耗尽内存
+const THUMBNAIL_SOURCE_MAX_DIMENSION: u32 = 8192;
+
+/// 生成缩略图时解码器最多分配的内存
+const THUMBNAIL_DECODE_MAX_ALLOC: u64 = 128 * 1024 * 1024;
+
 /// 企业级文件管理器
 pub struct FileManager {
     #[allow(dead_code)] // 企业级字段：config用于未来配置扩展和企业级功能
@@ -17,6 +29,33 @@ pub struct FileManager {
     base_path: PathBuf,
 }
 
+/// 上传内容校验失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadValidationError {
+    Empty,
+    TooLarge { size: u64, max_size: u64 },
+    UnsupportedType { mime: String },
+    /// 文件内容的魔数与声明的类型不符
+    MimeMismatch { declared: String, detected: String },
+}
+
+impl std::fmt::Display for UploadValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadValidationError::Empty => write!(f, "上传文件为空"),
+            UploadValidationError::TooLarge { size, max_size } => {
+                write!(f, "文件大小 {} 字节超过该类型上限 {} 字节", size, max_size)
+            }
+            UploadValidationError::UnsupportedType { mime } => write!(f, "不支持的文件类型: {}", mime),
+            UploadValidationError::MimeMismatch { declared, detected } => {
+                write!(f, "文件内容({})与声明的类型({})不符", detected, declared)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UploadValidationError {}
+
 /// 文件信息结构
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct FileInfo {
@@ -34,6 +73,11 @@ pub struct FileInfo {
     pub is_public: bool,
     pub download_count: u64,
     pub expires_at: Option<DateTime<Utc>>,
+    /// 图片缩略图的相对存储路径，非图片或生成失败时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
 }
 
 /// 文件分类目录结构
@@ -199,6 +243,39 @@ impl FileManager {
         ]
     }
 
+    /// 校验上传内容：声明的类型须在白名单内、文件头魔数须与之相符、大小不超过 max_size。
+    /// 返回规范化后的MIME类型
+    pub fn validate_upload(
+        data: &[u8],
+        declared_mime: &str,
+        max_size: u64,
+    ) -> std::result::Result<String, UploadValidationError> {
+        if data.is_empty() {
+            return Err(UploadValidationError::Empty);
+        }
+        if data.len() as u64 > max_size {
+            return Err(UploadValidationError::TooLarge {
+                size: data.len() as u64,
+                max_size,
+            });
+        }
+
+        let mime = normalize_mime(declared_mime);
+        let expected = expected_signature(&mime)
+            .ok_or_else(|| UploadValidationError::UnsupportedType { mime: mime.clone() })?;
+        let detected = sniff_signature(data);
+        // 纯文本没有魔数，"BM"等开头的文本会被识别成其他类型，单独判断
+        let matched = if expected == "text/plain" { is_plain_text(data) } else { detected == Some(expected) };
+        if !matched {
+            warn!("⚠️ 上传文件类型不符: 声明={}, 检测={:?}", mime, detected);
+            return Err(UploadValidationError::MimeMismatch {
+                declared: mime,
+                detected: detected.unwrap_or("unknown").to_string(),
+            });
+        }
+        Ok(mime)
+    }
+
     /// 上传文件 (基础版本)
     #[allow(dead_code)]
     pub async fn upload_file(&self, request: FileUploadRequest) -> Result<FileUploadResponse> {
@@ -253,6 +330,11 @@ impl FileManager {
         // 保存文件
         tokio::fs::write(&file_path, &request.content).await?;
 
+        // 图片生成缩略图，失败不影响上传
+        let thumbnail_path = self
+            .save_thumbnail(&file_id, &date_path, &request.content, &request.mime_type)
+            .await;
+
         // 创建文件信息
         let expires_at = request
             .expires_days
@@ -273,6 +355,10 @@ impl FileManager {
             is_public: request.is_public,
             download_count: 0,
             expires_at,
+            thumbnail_url: thumbnail_path
+                .as_ref()
+                .map(|_| format!("{}{}", THUMBNAIL_URL_PREFIX, file_id)),
+            thumbnail_path,
         };
 
         // 保存文件元数据
@@ -339,6 +425,16 @@ impl FileManager {
         Ok(content)
     }
 
+    /// 读取图片缩略图，没有缩略图时返回错误
+    pub async fn read_thumbnail(&self, file_id: &str) -> Result<Vec<u8>> {
+        let thumbnail_path = self
+            .get_file_info(file_id)
+            .await?
+            .and_then(|file_info| file_info.thumbnail_path)
+            .ok_or_else(|| anyhow!("缩略图不存在: {}", file_id))?;
+        Ok(tokio::fs::read(self.base_path.join(thumbnail_path)).await?)
+    }
+
     /// 删除文件
     #[allow(dead_code)]
     pub async fn delete_file(&self, file_id: &str, user_id: &str) -> Result<bool> {
@@ -357,6 +453,9 @@ impl FileManager {
         if file_path.exists() {
             tokio::fs::remove_file(&file_path).await?;
         }
+        if let Some(thumbnail_path) = &file_info.thumbnail_path {
+            let _ = tokio::fs::remove_file(self.base_path.join(thumbnail_path)).await;
+        }
 
         // 删除元数据
         let metadata_path = self.get_metadata_path(file_id);
@@ -422,6 +521,36 @@ impl FileManager {
         format!("{:08x}", sum)
     }
 
+    /// 生成并保存缩略图，返回相对路径；非图片或解码失败时返回 None
+    async fn save_thumbnail(&self, file_id: &str, date_path: &str, content: &[u8], mime: &str) -> Option<String> {
+        if !supports_thumbnail(mime) {
+            return None;
+        }
+        let content = content.to_vec();
+        let mime = mime.to_string();
+        // 解码和缩放比较耗CPU，放到阻塞线程池
+        let thumbnail = tokio::task::spawn_blocking(move || generate_thumbnail(&content, &mime))
+            .await
+            .ok()
+            .flatten()?;
+
+        let relative_path = format!("thumbnails/{}/{}.png", date_path, file_id);
+        let thumbnail_path = self.base_path.join(&relative_path);
+        if let Some(parent) = thumbnail_path.parent() {
+            if let Err(e) = tokio::fs::create_dir_all(parent).await {
+                warn!("⚠️ 创建缩略图目录失败: {:?} - {}", parent, e);
+                return None;
+            }
+        }
+        match tokio::fs::write(&thumbnail_path, thumbnail).await {
+            Ok(()) => Some(relative_path),
+            Err(e) => {
+                warn!("⚠️ 保存缩略图失败: {:?} - {}", thumbnail_path, e);
+                None
+            }
+        }
+    }
+
     #[allow(dead_code)]
     fn get_metadata_path(&self, file_id: &str) -> PathBuf {
         self.base_path
@@ -445,6 +574,137 @@ impl FileManager {
     }
 }
 
+/// 可以生成缩略图的图片类型，SVG、ICO 等直接使用原图
+fn supports_thumbnail(mime: &str) -> bool {
+    matches!(
+        normalize_mime(mime).as_str(),
+        "image/jpeg" | "image/jpg" | "image/png" | "image/gif" | "image/webp" | "image/bmp"
+    )
+}
+
+/// 按比例缩小到最长边不超过 THUMBNAIL_MAX_DIMENSION 并编码为PNG，小图不放大；
+/// 不支持的类型或无法解码时返回 None
+pub fn generate_thumbnail(content: &[u8], mime: &str) -> Option<Vec<u8>> {
+    if !supports_thumbnail(mime) {
+        return None;
+    }
+    let image = match decode_with_limits(content) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("⚠️ 图片解码失败，跳过缩略图: {}", e);
+            return None;
+        }
+    };
+    let thumbnail = if image.width() > THUMBNAIL_MAX_DIMENSION || image.height() > THUMBNAIL_MAX_DIMENSION {
+        image.thumbnail(THUMBNAIL_MAX_DIMENSION, THUMBNAIL_MAX_DIMENSION)
+    } else {
+        image
+    };
+
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    match thumbnail.write_to(&mut encoded, image::ImageOutputFormat::Png) {
+        Ok(()) => Some(encoded.into_inner()),
+        Err(e) => {
+            warn!("⚠️ 缩略图编码失败: {}", e);
+            None
+        }
+    }
+}
+
+// 按文件内容识别格式并在解码器上设置尺寸和内存上限
+fn decode_with_limits(content: &[u8]) -> image::ImageResult<image::DynamicImage> {
+    let mut reader = image::io::Reader::new(std::io::Cursor::new(content)).with_guessed_format()?;
+    let mut limits = image::io::Limits::default();
+    limits.max_image_width = Some(THUMBNAIL_SOURCE_MAX_DIMENSION);
+    limits.max_image_height = Some(THUMBNAIL_SOURCE_MAX_DIMENSION);
+    limits.max_alloc = Some(THUMBNAIL_DECODE_MAX_ALLOC);
+    reader.limits(limits);
+    reader.decode()
+}
+
+/// 是否为本服务生成的缩略图地址：前缀后只能是单个文件ID，不含路径分隔符或 ..
+pub fn is_thumbnail_url(url: &str) -> bool {
+    url.strip_prefix(THUMBNAIL_URL_PREFIX).is_some_and(|file_id| {
+        !file_id.is_empty() && !file_id.contains(['/', '\\', '%', '?', '#']) && !file_id.contains("..")
+    })
+}
+
+// 去掉参数（如 audio/webm;codecs=opus）并统一小写
+fn normalize_mime(mime: &str) -> String {
+    mime.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// 上传白名单：声明的MIME类型 -> 文件头应匹配的签名
+fn expected_signature(mime: &str) -> Option<&'static str> {
+    let signature = match mime {
+        "image/jpeg" | "image/jpg" => "image/jpeg",
+        "image/png" => "image/png",
+        "image/gif" => "image/gif",
+        "image/webp" => "image/webp",
+        "image/bmp" => "image/bmp",
+        "application/pdf" => "application/pdf",
+        // OOXML 文档本质是 zip 包
+        "application/zip"
+        | "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+        | "application/vnd.openxmlformats-officedocument.presentationml.presentation" => "application/zip",
+        // 旧版 Office 文档使用 OLE 复合文档格式
+        "application/msword" | "application/vnd.ms-excel" | "application/vnd.ms-powerpoint" => "application/x-ole-storage",
+        "text/plain" | "text/csv" => "text/plain",
+        // mp3 与 ADTS 封装的 aac 都以 MPEG 帧同步字开头
+        "audio/mpeg" | "audio/mp3" | "audio/aac" => "audio/mpeg",
+        "audio/wav" | "audio/wave" | "audio/x-wav" => "audio/wav",
+        "audio/ogg" => "audio/ogg",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "audio/mp4",
+        "audio/flac" => "audio/flac",
+        "audio/webm" => "audio/webm",
+        _ => return None,
+    };
+    Some(signature)
+}
+
+/// 按文件头魔数识别文件类型，纯文本按"合法UTF-8且不含NUL"判断
+fn sniff_signature(data: &[u8]) -> Option<&'static str> {
+    let riff_kind = |kind: &[u8]| data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == kind;
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        Some("image/png")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if riff_kind(b"WEBP") {
+        Some("image/webp")
+    } else if data.starts_with(b"BM") {
+        Some("image/bmp")
+    } else if data.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if data.starts_with(b"PK\x03\x04") {
+        Some("application/zip")
+    } else if data.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+        Some("application/x-ole-storage")
+    } else if data.starts_with(b"ID3") || (data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0) {
+        Some("audio/mpeg")
+    } else if riff_kind(b"WAVE") {
+        Some("audio/wav")
+    } else if data.starts_with(b"OggS") {
+        Some("audio/ogg")
+    } else if data.len() >= 8 && &data[4..8] == b"ftyp" {
+        Some("audio/mp4")
+    } else if data.starts_with(b"fLaC") {
+        Some("audio/flac")
+    } else if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some("audio/webm")
+    } else if is_plain_text(data) {
+        Some("text/plain")
+    } else {
+        None
+    }
+}
+
+fn is_plain_text(data: &[u8]) -> bool {
+    !data.contains(&0) && std::str::from_utf8(data).is_ok()
+}
+
 // 为ContentType添加Display实现
 impl std::fmt::Display for ContentType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -458,3 +718,150 @@ impl std::fmt::Display for ContentType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 13];
+
+    #[test]
+    fn test_validate_upload_checks_magic_bytes() {
+        assert_eq!(FileManager::validate_upload(PNG, "image/png", 1024).unwrap(), "image/png");
+        assert_eq!(
+            FileManager::validate_upload(b"%PDF-1.7\n", "Application/PDF", 1024).unwrap(),
+            "application/pdf"
+        );
+        assert!(FileManager::validate_upload(b"OggS\0\x02", "audio/ogg; codecs=opus", 1024).is_ok());
+        assert!(FileManager::validate_upload("订单号,金额\n1,2".as_bytes(), "text/csv", 1024).is_ok());
+        assert!(FileManager::validate_upload(b"BMW,3\n", "text/csv", 1024).is_ok());
+
+        // 改了扩展名的可执行文件
+        assert_eq!(
+            FileManager::validate_upload(b"MZ\x90\0\x03\0", "image/png", 1024),
+            Err(UploadValidationError::MimeMismatch {
+                declared: "image/png".to_string(),
+                detected: "unknown".to_string(),
+            })
+        );
+        assert!(matches!(
+            FileManager::validate_upload(PNG, "application/pdf", 1024),
+            Err(UploadValidationError::MimeMismatch { .. })
+        ));
+        assert!(matches!(
+            FileManager::validate_upload(b"MZ\x90\0", "application/x-msdownload", 1024),
+            Err(UploadValidationError::UnsupportedType { .. })
+        ));
+        assert_eq!(FileManager::validate_upload(b"", "text/plain", 1024), Err(UploadValidationError::Empty));
+    }
+
+    #[test]
+    fn test_validate_upload_enforces_per_type_size() {
+        let mut upload: crate::config::UploadConfig = serde_json::from_value(serde_json::json!({
+            "maxFileSize": 1024,
+            "allowedTypes": [],
+            "compressionEnabled": false,
+            "compressionQuality": 0.8,
+            "maxWidth": 1920,
+            "maxHeight": 1080,
+            "maxSizeByType": { "image": 8 }
+        }))
+        .unwrap();
+        upload.max_size_by_type.insert("image/png".to_string(), 16);
+
+        assert_eq!(upload.max_size_for("image/png"), 16);
+        assert_eq!(upload.max_size_for("image/gif"), 8);
+        assert_eq!(upload.max_size_for("application/pdf"), 1024);
+
+        assert!(FileManager::validate_upload(PNG, "image/png", upload.max_size_for("image/png")).is_ok());
+        let err = FileManager::validate_upload(PNG, "image/png", 8).unwrap_err();
+        assert_eq!(err, UploadValidationError::TooLarge { size: 12, max_size: 8 });
+        assert!(err.to_string().contains("超过"));
+    }
+
+    fn encode_png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::DynamicImage::new_rgb8(width, height);
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut encoded, image::ImageOutputFormat::Png).unwrap();
+        encoded.into_inner()
+    }
+
+    #[test]
+    fn test_thumbnail_generated_for_images_only() {
+        let thumbnail = generate_thumbnail(&encode_png(1024, 512), "image/png").unwrap();
+        let decoded = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (256, 128));
+
+        // 小图不放大
+        let small = image::load_from_memory(&generate_thumbnail(&encode_png(40, 30), "image/png").unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (40, 30));
+
+        assert!(generate_thumbnail(b"%PDF-1.7\n", "application/pdf").is_none());
+        assert!(generate_thumbnail(PNG, "image/png").is_none());
+    }
+
+    #[test]
+    fn test_thumbnail_rejects_oversized_source() {
+        // 文件很小，但尺寸超过解码上限
+        let wide = encode_png(THUMBNAIL_SOURCE_MAX_DIMENSION + 1, 1);
+        assert!(wide.len() < 64 * 1024);
+        assert!(generate_thumbnail(&wide, "image/png").is_none());
+        assert!(generate_thumbnail(&encode_png(THUMBNAIL_SOURCE_MAX_DIMENSION, 1), "image/png").is_some());
+    }
+
+    #[test]
+    fn test_thumbnail_url_is_single_file_id() {
+        assert!(is_thumbnail_url(&format!("{}{}", THUMBNAIL_URL_PREFIX, Uuid::new_v4())));
+        for url in [
+            "/api/file/thumbnail/",
+            "/api/file/thumbnail/..",
+            "/api/file/thumbnail/../../admin",
+            "/api/file/thumbnail/a/b",
+            "/api/file/thumbnail/a\\b",
+            "/api/file/thumbnail/%2e%2e",
+            "https://evil.example/api/file/thumbnail/a",
+        ] {
+            assert!(!is_thumbnail_url(url), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_stores_thumbnail_under_blobs() {
+        let dir = std::env::temp_dir().join(format!("file_thumbnail_{}", Uuid::new_v4()));
+        let manager = FileManager::new(StorageConfig {
+            data_dir: dir.to_string_lossy().to_string(),
+            blobs_dir: dir.join("blobs").to_string_lossy().to_string(),
+            snapshot_interval: 300,
+            max_snapshot_size: 1024,
+            export: Default::default(),
+            encryption: Default::default(),
+            backend: Default::default(),
+            s3: Default::default(),
+        })
+        .unwrap();
+        let upload = |name: &str, content: Vec<u8>, mime: &str| FileUploadRequest {
+            original_name: name.to_string(),
+            content,
+            mime_type: mime.to_string(),
+            uploaded_by: "kehu_1".to_string(),
+            is_public: true,
+            expires_days: None,
+        };
+
+        let image = manager.upload_file(upload("photo.png", encode_png(800, 600), "image/png")).await.unwrap();
+        let thumbnail_url = image.file_info.thumbnail_url.clone().unwrap();
+        assert_eq!(thumbnail_url, format!("{}{}", THUMBNAIL_URL_PREFIX, image.file_info.id));
+        assert!(image.file_info.thumbnail_path.as_deref().unwrap().starts_with("thumbnails/"));
+        let thumbnail = manager.read_thumbnail(&image.file_info.id).await.unwrap();
+        assert_eq!(image::load_from_memory(&thumbnail).unwrap().width(), 256);
+
+        let document = manager
+            .upload_file(upload("report.pdf", b"%PDF-1.7\n".to_vec(), "application/pdf"))
+            .await
+            .unwrap();
+        assert!(document.file_info.thumbnail_url.is_none());
+        assert!(manager.read_thumbnail(&document.file_info.id).await.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -101,6 +101,7 @@ impl FileManagerExt for FileManager {
             "upload_time": response.file_info.uploaded_at.to_rfc3339(),
             "uploaded_by": user_id,
             "access_url": response.file_info.access_url,
+            "thumbnail_url": response.file_info.thumbnail_url,
         }))
     }
 
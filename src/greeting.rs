@@ -0,0 +1,48 @@
+use crate::config::GreetingConfig;
+
+/// 会话建立后发给客户的问候语：客服个人问候语优先于全局问候语，
+/// 替换 {customer_name} 和 {kefu_name}；未开启或问候语为空时返回 None
+pub fn render_greeting(config: &GreetingConfig, kefu_id: &str, kefu_name: &str, customer_name: &str) -> Option<String> {
+    if !config.enabled {
+        return None;
+    }
+    let template = config.per_kefu.get(kefu_id).unwrap_or(&config.message);
+    if template.trim().is_empty() {
+        return None;
+    }
+    Some(
+        template
+            .replace("{customer_name}", customer_name)
+            .replace("{kefu_name}", kefu_name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_kefu_greeting_overrides_global() {
+        let mut config = GreetingConfig {
+            enabled: true,
+            message: "您好{customer_name}，我是{kefu_name}".to_string(),
+            per_kefu: [("kefu_002".to_string(), "{customer_name}，欢迎回来".to_string())].into(),
+        };
+        assert_eq!(
+            render_greeting(&config, "kefu_001", "小王", "张三").as_deref(),
+            Some("您好张三，我是小王")
+        );
+        assert_eq!(
+            render_greeting(&config, "kefu_002", "小李", "张三").as_deref(),
+            Some("张三，欢迎回来")
+        );
+
+        // 全局问候语为空时只有配置了个人问候语的客服发送
+        config.message = String::new();
+        assert!(render_greeting(&config, "kefu_001", "小王", "张三").is_none());
+        assert!(render_greeting(&config, "kefu_002", "小李", "张三").is_some());
+
+        config.enabled = false;
+        assert!(render_greeting(&config, "kefu_002", "小李", "张三").is_none());
+    }
+}
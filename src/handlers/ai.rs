@@ -2,6 +2,9 @@ use std::sync::Arc;
 use warp::{Filter, Reply};
 use serde::{Deserialize, Serialize};
 use crate::ai::{AIManager, AITask, AITaskType, config::AIConfig};
+use crate::auth::jwt_auth::JwtAuth;
+use crate::auth::operator::Operator;
+use crate::message::UserType;
 use anyhow::Result;
 
 // API请求结构
@@ -34,6 +37,31 @@ pub struct ConfigUpdateRequest {
     pub config: AIConfig,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRequest {
+    /// AI结果ID（即任务ID）
+    pub result_id: String,
+    pub helpful: bool,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LowQualityQuery {
+    pub task_type: Option<AITaskType>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingActionsQuery {
+    pub customer_id: Option<String>,
+}
+
+/// 确认或驳回AI建议动作，操作人取自客服JWT
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActionDecisionRequest {
+    pub reason: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigResponse {
     pub config: AIConfig,
@@ -58,6 +86,8 @@ impl AIHandler {
                 warp::path("tasks")
                     .and(warp::post())
                     .and(warp::body::json())
+                    .and(warp::header::optional::<String>("authorization"))
+                    .and(warp::header::optional::<String>("x-admin-token"))
                     .and(with_ai_manager(ai_manager.clone()))
                     .and_then(submit_task)
                     .or(
@@ -107,11 +137,71 @@ impl AIHandler {
                             .and(with_ai_manager(ai_manager.clone()))
                             .and_then(get_statistics)
                     )
+                    .or(
+                        // 提交AI结果反馈
+                        warp::path("feedback")
+                            .and(warp::path::end())
+                            .and(warp::post())
+                            .and(warp::body::json())
+                            .and(with_ai_manager(ai_manager.clone()))
+                            .and_then(submit_feedback)
+                    )
+                    .or(
+                        // 各任务类型的好评率
+                        warp::path!("feedback" / "stats")
+                            .and(warp::get())
+                            .and(with_ai_manager(ai_manager.clone()))
+                            .and_then(get_feedback_stats)
+                    )
+                    .or(
+                        // A/B 实验各组对比
+                        warp::path!("experiment" / "stats")
+                            .and(warp::get())
+                            .and(with_ai_manager(ai_manager.clone()))
+                            .and_then(get_experiment_stats)
+                    )
+                    .or(
+                        // 低质量案例
+                        warp::path!("feedback" / "low-quality")
+                            .and(warp::get())
+                            .and(warp::query::<LowQualityQuery>())
+                            .and(with_ai_manager(ai_manager.clone()))
+                            .and_then(get_low_quality_cases)
+                    )
+                    .or(
+                        // 待人工确认的AI建议动作
+                        warp::path!("actions" / "pending")
+                            .and(warp::get())
+                            .and(warp::query::<PendingActionsQuery>())
+                            .and(warp::header::optional::<String>("authorization"))
+                            .and(warp::header::optional::<String>("x-admin-token"))
+                            .and(with_ai_manager(ai_manager.clone()))
+                            .and_then(get_pending_actions)
+                    )
+                    .or(
+                        // 确认执行AI建议动作
+                        warp::path!("actions" / String / "approve")
+                            .and(warp::post())
+                            .and(warp::header::optional::<String>("authorization"))
+                            .and(with_ai_manager(ai_manager.clone()))
+                            .and_then(approve_action)
+                    )
+                    .or(
+                        // 驳回AI建议动作
+                        warp::path!("actions" / String / "reject")
+                            .and(warp::post())
+                            .and(warp::header::optional::<String>("authorization"))
+                            .and(warp::body::json())
+                            .and(with_ai_manager(ai_manager.clone()))
+                            .and_then(reject_action)
+                    )
                     .or(
                         // 批量处理消息
                         warp::path("batch")
                             .and(warp::post())
                             .and(warp::body::json())
+                            .and(warp::header::optional::<String>("authorization"))
+                            .and(warp::header::optional::<String>("x-admin-token"))
                             .and(with_ai_manager(ai_manager.clone()))
                             .and_then(batch_process)
                     )
@@ -123,16 +213,36 @@ fn with_ai_manager(ai_manager: Arc<AIManager>) -> impl Filter<Extract = (Arc<AIM
     warp::any().map(move || ai_manager.clone())
 }
 
+/// 未指定优先级时使用的默认值
+const DEFAULT_TASK_PRIORITY: u8 = 5;
+
+/// 优先级决定出队顺序和能否抢占，只有客服或管理员可以指定；
+/// 其他调用方传入的优先级被忽略，统一按默认优先级排队
+fn task_priority(requested: Option<u8>, operator: Option<&Operator>) -> u8 {
+    match operator {
+        Some(_) => requested.unwrap_or(DEFAULT_TASK_PRIORITY),
+        None => {
+            if requested.is_some_and(|priority| priority != DEFAULT_TASK_PRIORITY) {
+                tracing::debug!("未认证调用方指定的AI任务优先级已忽略: {:?}", requested);
+            }
+            DEFAULT_TASK_PRIORITY
+        }
+    }
+}
+
 async fn submit_task(
     request: SubmitTaskRequest,
+    authorization: Option<String>,
+    admin_token: Option<String>,
     ai_manager: Arc<AIManager>,
 ) -> Result<impl Reply, warp::Rejection> {
+    let operator = Operator::resolve(authorization.as_deref(), admin_token.as_deref());
     let task = AITask::new(
         request.task_type,
         request.user_id,
         request.message_id,
         request.input_data,
-        request.priority.unwrap_or(5),
+        task_priority(request.priority, operator.as_ref()),
     );
 
     match ai_manager.submit_task(task).await {
@@ -161,10 +271,12 @@ async fn get_task_status(
 ) -> Result<impl Reply, warp::Rejection> {
     match ai_manager.get_task_status(&task_id).await {
         Ok(Some(status)) => {
+            // 处理中的分段任务返回最新的中间结果
+            let interim = ai_manager.interim_result(&task_id);
             let response = TaskStatusResponse {
-                task_id,
                 status: format!("{:?}", status),
-                result: None,
+                result: interim.and_then(|interim| serde_json::to_value(interim).ok()),
+                task_id,
                 error: None,
             };
             Ok(warp::reply::json(&response))
@@ -229,12 +341,25 @@ async fn cancel_task(
     task_id: String,
     ai_manager: Arc<AIManager>,
 ) -> Result<impl Reply, warp::Rejection> {
-    match ai_manager.queue.write().await.cancel_task(&task_id).await {
-        Ok(cancelled) => {
+    match ai_manager.cancel_task(&task_id).await {
+        Ok(true) => {
+            let response = TaskResponse {
+                task_id,
+                status: "cancelled".to_string(),
+                message: "任务已取消".to_string(),
+            };
+            Ok(warp::reply::json(&response))
+        }
+        Ok(false) => {
+            // 只有排队中的任务可以取消，返回任务当前状态便于调用方判断
+            let (status, message) = match ai_manager.get_task_status(&task_id).await.ok().flatten() {
+                Some(status) => (format!("{:?}", status), "任务已开始处理或已结束，无法取消"),
+                None => ("not_found".to_string(), "任务不存在"),
+            };
             let response = TaskResponse {
                 task_id,
-                status: if cancelled { "cancelled" } else { "not_found" }.to_string(),
-                message: if cancelled { "任务已取消" } else { "任务不存在" }.to_string(),
+                status,
+                message: message.to_string(),
             };
             Ok(warp::reply::json(&response))
         }
@@ -311,6 +436,101 @@ async fn get_statistics(
     }
 }
 
+async fn submit_feedback(
+    request: FeedbackRequest,
+    ai_manager: Arc<AIManager>,
+) -> Result<impl Reply, warp::Rejection> {
+    match ai_manager
+        .submit_ai_feedback(&request.result_id, request.helpful, request.comment)
+        .await
+    {
+        Ok(feedback) => Ok(warp::reply::json(&feedback)),
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": e.to_string(),
+                "status": "not_found"
+            });
+            Ok(warp::reply::json(&error_response))
+        }
+    }
+}
+
+async fn get_feedback_stats(
+    ai_manager: Arc<AIManager>,
+) -> Result<impl Reply, warp::Rejection> {
+    Ok(warp::reply::json(&ai_manager.get_feedback_stats().await))
+}
+
+async fn get_experiment_stats(
+    ai_manager: Arc<AIManager>,
+) -> Result<impl Reply, warp::Rejection> {
+    Ok(warp::reply::json(&ai_manager.get_experiment_report().await))
+}
+
+async fn get_low_quality_cases(
+    query: LowQualityQuery,
+    ai_manager: Arc<AIManager>,
+) -> Result<impl Reply, warp::Rejection> {
+    let cases = ai_manager
+        .get_low_quality_cases(query.task_type.as_ref(), query.limit.unwrap_or(50))
+        .await;
+    Ok(warp::reply::json(&cases))
+}
+
+// 只有客服（含主管）可以确认动作，返回JWT中的客服ID
+fn kefu_operator(authorization: Option<&str>) -> Option<String> {
+    let token = authorization?.strip_prefix("Bearer ")?;
+    let claims = JwtAuth::from_config().verify(token.trim()).ok()?;
+    (claims.user_type == UserType::Kefu).then_some(claims.sub)
+}
+
+fn action_error(message: String, status: warp::http::StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&serde_json::json!({ "error": message })), status)
+}
+
+// 待确认动作包含退款金额等业务参数，只对客服和管理员开放
+async fn get_pending_actions(
+    query: PendingActionsQuery,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    ai_manager: Arc<AIManager>,
+) -> Result<impl Reply, warp::Rejection> {
+    if Operator::resolve(authorization.as_deref(), admin_token.as_deref()).is_none() {
+        return Ok(action_error("需要客服登录或管理令牌".to_string(), warp::http::StatusCode::UNAUTHORIZED));
+    }
+    let actions = ai_manager.action_gate().pending(query.customer_id.as_deref()).await;
+    Ok(warp::reply::with_status(warp::reply::json(&actions), warp::http::StatusCode::OK))
+}
+
+async fn approve_action(
+    action_id: String,
+    authorization: Option<String>,
+    ai_manager: Arc<AIManager>,
+) -> Result<impl Reply, warp::Rejection> {
+    let Some(operator) = kefu_operator(authorization.as_deref()) else {
+        return Ok(action_error("需要客服登录后确认".to_string(), warp::http::StatusCode::UNAUTHORIZED));
+    };
+    Ok(match ai_manager.action_gate().approve(&action_id, &operator).await {
+        Ok(action) => warp::reply::with_status(warp::reply::json(&action), warp::http::StatusCode::OK),
+        Err(e) => action_error(e.to_string(), warp::http::StatusCode::CONFLICT),
+    })
+}
+
+async fn reject_action(
+    action_id: String,
+    authorization: Option<String>,
+    request: ActionDecisionRequest,
+    ai_manager: Arc<AIManager>,
+) -> Result<impl Reply, warp::Rejection> {
+    let Some(operator) = kefu_operator(authorization.as_deref()) else {
+        return Ok(action_error("需要客服登录后操作".to_string(), warp::http::StatusCode::UNAUTHORIZED));
+    };
+    Ok(match ai_manager.action_gate().reject(&action_id, &operator, request.reason).await {
+        Ok(action) => warp::reply::with_status(warp::reply::json(&action), warp::http::StatusCode::OK),
+        Err(e) => action_error(e.to_string(), warp::http::StatusCode::CONFLICT),
+    })
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchProcessRequest {
     pub messages: Vec<BatchMessage>,
@@ -336,11 +556,14 @@ pub struct BatchProcessResponse {
 
 async fn batch_process(
     request: BatchProcessRequest,
+    authorization: Option<String>,
+    admin_token: Option<String>,
     ai_manager: Arc<AIManager>,
 ) -> Result<impl Reply, warp::Rejection> {
     let mut submitted_tasks = Vec::new();
     let mut failed_tasks = Vec::new();
-    let priority = request.priority.unwrap_or(5);
+    let operator = Operator::resolve(authorization.as_deref(), admin_token.as_deref());
+    let priority = task_priority(request.priority, operator.as_ref());
 
     let total_messages = request.messages.len();
     
@@ -461,4 +684,16 @@ mod tests {
         assert_eq!(determine_ai_tasks("voice"), vec![AITaskType::SpeechRecognition, AITaskType::IntentRecognition]);
         assert_eq!(determine_ai_tasks("unknown"), vec![AITaskType::IntentRecognition]);
     }
+
+    #[test]
+    fn test_task_priority_only_honoured_for_operators() {
+        // 未认证调用方不能借高优先级插队或抢占
+        assert_eq!(task_priority(Some(10), None), DEFAULT_TASK_PRIORITY);
+        assert_eq!(task_priority(Some(1), None), DEFAULT_TASK_PRIORITY);
+        assert_eq!(task_priority(None, None), DEFAULT_TASK_PRIORITY);
+
+        let kefu = Operator::Kefu("kefu_001".to_string());
+        assert_eq!(task_priority(Some(9), Some(&kefu)), 9);
+        assert_eq!(task_priority(None, Some(&Operator::Admin)), DEFAULT_TASK_PRIORITY);
+    }
 } 
\ No newline at end of file
@@ -5,6 +5,7 @@ use crate::websocket::WebSocketManager;
 use crate::storage::LocalStorage;
 use crate::user_manager::UserManager;
 use crate::types::api::ApiResponse;
+use crate::cache::analytics::is_cache_bypass;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
@@ -16,6 +17,122 @@ pub struct AnalyticsDateRange {
     pub group_by: Option<String>, // hour, day, week, month
 }
 
+// 统计结果响应：成功时返回数据，失败时返回错误信息
+fn analytics_response(result: anyhow::Result<serde_json::Value>, success: &str, failure: &str) -> ApiResponse<serde_json::Value> {
+    match result {
+        Ok(data) => ApiResponse {
+            success: true,
+            message: success.to_string(),
+            data: Some(data),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            message: format!("{}: {}", failure, e),
+            data: None,
+        },
+    }
+}
+
+// 连接数历史曲线，默认最近24小时
+pub async fn handle_analytics_connections(
+    query: AnalyticsDateRange,
+    cache_control: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let bypass = is_cache_bypass(cache_control.as_deref());
+    let result = ws_manager
+        .analytics_cache
+        .get_or_compute("connections", &query, bypass, || async {
+            let to = query.end_date.unwrap_or_else(Utc::now);
+            let from = query.start_date.unwrap_or(to - chrono::Duration::hours(24));
+            let series = ws_manager.get_connection_history(from, to);
+            Ok::<_, anyhow::Error>(serde_json::json!({
+                "from": from,
+                "to": to,
+                "count": series.len(),
+                "series": series
+            }))
+        })
+        .await;
+
+    Ok(warp::reply::json(&analytics_response(result, "获取连接历史成功", "获取连接历史失败")))
+}
+
+// 各客服满意度（CSAT）：会话结束评价的滚动平均
+pub async fn handle_analytics_satisfaction(
+    cache_control: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let bypass = is_cache_bypass(cache_control.as_deref());
+    let result = ws_manager
+        .analytics_cache
+        .get_or_compute("satisfaction", &(), bypass, || async {
+            let scores = ws_manager.get_kefu_satisfaction_scores().await?;
+            let rated: Vec<_> = scores.iter().filter(|s| s.rating_count > 0).collect();
+            let overall = if rated.is_empty() {
+                None
+            } else {
+                Some(rated.iter().map(|s| s.satisfaction_score).sum::<f64>() / rated.len() as f64)
+            };
+            Ok::<_, anyhow::Error>(serde_json::json!({
+                "overall_satisfaction_score": overall,
+                "window": crate::satisfaction::SATISFACTION_WINDOW,
+                "kefu": scores,
+            }))
+        })
+        .await;
+
+    Ok(warp::reply::json(&analytics_response(result, "获取客服满意度成功", "获取客服满意度失败")))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SlaQuery {
+    pub days: Option<usize>,
+}
+
+// 每日 SLA 指标：首响分位与达成率、会话解决率、排队超时率，默认最近7天
+pub async fn handle_analytics_sla(
+    query: SlaQuery,
+    cache_control: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let bypass = is_cache_bypass(cache_control.as_deref());
+    let result = ws_manager
+        .analytics_cache
+        .get_or_compute("sla", &query, bypass, || async {
+            let reports = ws_manager.get_sla_reports(query.days.unwrap_or(7));
+            Ok::<_, anyhow::Error>(serde_json::json!({
+                "retention_days": crate::monitoring::sla::SLA_RETENTION_DAYS,
+                "days": reports
+            }))
+        })
+        .await;
+
+    Ok(warp::reply::json(&analytics_response(result, "获取SLA指标成功", "获取SLA指标失败")))
+}
+
+// 在线客服实时工作负载：每位客服的接待数、利用率、是否满负载，以及整体容量汇总
+pub async fn handle_kefu_workload(
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let workloads = ws_manager.get_kefu_workloads().await;
+    let active_sessions: Vec<usize> = workloads
+        .iter()
+        .map(|workload| workload["active_sessions"].as_u64().unwrap_or(0) as usize)
+        .collect();
+
+    let response = ApiResponse {
+        success: true,
+        message: format!("获取客服工作负载成功，在线客服{}位", workloads.len()),
+        data: Some(serde_json::json!({
+            "summary": crate::kefu_alert::summarize_workloads(&active_sessions),
+            "kefu": workloads,
+        })),
+    };
+
+    Ok(warp::reply::json(&response))
+}
+
 // 系统概览统计
 pub async fn handle_analytics_overview(
     ws_manager: Arc<WebSocketManager>,
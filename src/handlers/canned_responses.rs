@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+use crate::auth::operator::Operator;
+use crate::canned_response::{self, CannedResponse, DEFAULT_MATCH_LIMIT};
+use crate::types::api::ApiResponse;
+use crate::websocket::WebSocketManager;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CannedResponseRequest {
+    pub shortcut: String,
+    pub content: String,
+    /// 创建全局快捷回复（需要管理令牌），默认创建当前客服的个人快捷回复
+    #[serde(default)]
+    pub global: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CannedResponseQuery {
+    pub prefix: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// 管理员可以管理所有快捷回复，客服只能管理自己的个人快捷回复
+fn can_manage(operator: &Operator, response: &CannedResponse) -> bool {
+    match operator {
+        Operator::Admin => true,
+        Operator::Kefu(kefu_id) => response.owner_id.as_deref() == Some(kefu_id.as_str()),
+    }
+}
+
+fn reply<T: Serialize>(response: ApiResponse<T>, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
+fn error_reply(message: impl Into<String>, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    let response: ApiResponse<()> = ApiResponse {
+        success: false,
+        message: message.into(),
+        data: None,
+    };
+    reply(response, status)
+}
+
+fn unauthorized() -> warp::reply::WithStatus<warp::reply::Json> {
+    error_reply("需要客服登录或管理令牌", StatusCode::UNAUTHORIZED)
+}
+
+fn storage_error(action: &str, e: anyhow::Error) -> warp::reply::WithStatus<warp::reply::Json> {
+    tracing::error!("❌ {}失败: {:?}", action, e);
+    error_reply(format!("{}失败: {}", action, e), StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// 快捷回复列表：客服获取自己可用的（可按快捷码前缀过滤），管理员获取全部
+pub async fn handle_list_canned_responses(
+    query: CannedResponseQuery,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized());
+    };
+
+    let prefix = query.prefix.unwrap_or_default().trim().to_lowercase();
+    let result = match &operator {
+        Operator::Kefu(kefu_id) => {
+            let limit = query.limit.unwrap_or(DEFAULT_MATCH_LIMIT);
+            ws_manager.find_canned_responses(kefu_id, &prefix, limit).await
+        }
+        Operator::Admin => ws_manager.redis.read().await.list_canned_responses().await.map(|mut responses| {
+            responses.retain(|response| response.shortcut.to_lowercase().starts_with(&prefix));
+            responses.sort_by(|a, b| a.shortcut.cmp(&b.shortcut));
+            responses
+        }),
+    };
+
+    Ok(match result {
+        Ok(responses) => reply(
+            ApiResponse {
+                success: true,
+                message: "获取快捷回复成功".to_string(),
+                data: Some(serde_json::json!({
+                    "total": responses.len(),
+                    "responses": responses,
+                })),
+            },
+            StatusCode::OK,
+        ),
+        Err(e) => storage_error("获取快捷回复", e),
+    })
+}
+
+// 创建快捷回复
+pub async fn handle_create_canned_response(
+    request: CannedResponseRequest,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized());
+    };
+    if let Err(message) = canned_response::validate(&request.shortcut, &request.content) {
+        return Ok(error_reply(message, StatusCode::BAD_REQUEST));
+    }
+
+    let owner_id = match (&operator, request.global) {
+        (Operator::Admin, true) => None,
+        (Operator::Kefu(kefu_id), false) => Some(kefu_id.clone()),
+        (Operator::Kefu(_), true) => {
+            return Ok(error_reply("创建全局快捷回复需要管理令牌", StatusCode::FORBIDDEN));
+        }
+        (Operator::Admin, false) => {
+            return Ok(error_reply("管理员只能创建全局快捷回复", StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let now = Utc::now();
+    let response = CannedResponse {
+        id: Uuid::new_v4().to_string(),
+        owner_id,
+        shortcut: request.shortcut.trim().to_string(),
+        content: request.content,
+        created_at: now,
+        updated_at: now,
+    };
+    Ok(match ws_manager.redis.read().await.save_canned_response(&response).await {
+        Ok(()) => {
+            tracing::info!("💬 新建快捷回复: {} ({})", response.shortcut, response.id);
+            reply(
+                ApiResponse {
+                    success: true,
+                    message: "快捷回复已创建".to_string(),
+                    data: Some(response),
+                },
+                StatusCode::CREATED,
+            )
+        }
+        Err(e) => storage_error("保存快捷回复", e),
+    })
+}
+
+// 修改快捷回复的快捷码和内容，归属不变
+pub async fn handle_update_canned_response(
+    id: String,
+    request: CannedResponseRequest,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized());
+    };
+    if let Err(message) = canned_response::validate(&request.shortcut, &request.content) {
+        return Ok(error_reply(message, StatusCode::BAD_REQUEST));
+    }
+
+    let redis = ws_manager.redis.read().await;
+    let mut response = match redis.get_canned_response(&id).await {
+        Ok(Some(response)) => response,
+        Ok(None) => return Ok(error_reply("快捷回复不存在", StatusCode::NOT_FOUND)),
+        Err(e) => return Ok(storage_error("获取快捷回复", e)),
+    };
+    if !can_manage(&operator, &response) {
+        return Ok(error_reply("无权修改该快捷回复", StatusCode::FORBIDDEN));
+    }
+
+    response.shortcut = request.shortcut.trim().to_string();
+    response.content = request.content;
+    response.updated_at = Utc::now();
+    Ok(match redis.save_canned_response(&response).await {
+        Ok(()) => reply(
+            ApiResponse {
+                success: true,
+                message: "快捷回复已更新".to_string(),
+                data: Some(response),
+            },
+            StatusCode::OK,
+        ),
+        Err(e) => storage_error("保存快捷回复", e),
+    })
+}
+
+// 删除快捷回复
+pub async fn handle_delete_canned_response(
+    id: String,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized());
+    };
+
+    let redis = ws_manager.redis.read().await;
+    match redis.get_canned_response(&id).await {
+        Ok(Some(response)) if can_manage(&operator, &response) => {}
+        Ok(Some(_)) => return Ok(error_reply("无权删除该快捷回复", StatusCode::FORBIDDEN)),
+        Ok(None) => return Ok(error_reply("快捷回复不存在", StatusCode::NOT_FOUND)),
+        Err(e) => return Ok(storage_error("获取快捷回复", e)),
+    }
+    Ok(match redis.delete_canned_response(&id).await {
+        Ok(_) => {
+            tracing::info!("🗑️ 删除快捷回复: {}", id);
+            let response: ApiResponse<()> = ApiResponse {
+                success: true,
+                message: "快捷回复已删除".to_string(),
+                data: None,
+            };
+            reply(response, StatusCode::OK)
+        }
+        Err(e) => storage_error("删除快捷回复", e),
+    })
+}
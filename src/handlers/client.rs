@@ -5,9 +5,11 @@ use tracing::{info, warn, error};
 use uuid::Uuid;
 use warp::Reply;
 
+use crate::config::AppConfig;
 use crate::types::api::{
     ApiResponse, IpLocationQuery, IpLocationResponse, 
-    ClientRegisterInfo, ClientRegisterResponse
+    ClientRegisterInfo, ClientRegisterResponse,
+    ClientConfigQuery, ClientConfigResponse, ClientUploadLimits
 };
 use crate::storage::LocalStorage;
 
@@ -109,6 +111,91 @@ pub async fn handle_client_register(
     Ok(warp::reply::json(&response))
 }
 
+/// 客户端配置下发处理器
+pub async fn handle_client_config(
+    query: ClientConfigQuery,
+) -> Result<impl Reply, warp::Rejection> {
+    info!("⚙️ 客户端拉取配置: type={:?}, version={:?}", query.client_type, query.version);
+
+    let response = ApiResponse {
+        success: true,
+        message: "获取客户端配置成功".to_string(),
+        data: Some(build_client_config(AppConfig::get(), &query)),
+    };
+
+    Ok(warp::reply::json(&response))
+}
+
+/// 从应用配置中挑出前端可见的字段，并套用该客户端类型满足版本要求的定制项
+pub(crate) fn build_client_config(config: &AppConfig, query: &ClientConfigQuery) -> ClientConfigResponse {
+    let frontend = &config.frontend;
+    let mut features: std::collections::BTreeMap<String, bool> =
+        serde_json::from_value(serde_json::to_value(&frontend.features).unwrap_or_default()).unwrap_or_default();
+    let mut response = ClientConfigResponse {
+        ws_url: frontend.ws_url.clone(),
+        api_url: frontend.api_url.clone(),
+        features: std::collections::BTreeMap::new(),
+        upload: ClientUploadLimits {
+            max_file_size: frontend.upload.max_file_size,
+            allowed_types: frontend.upload.allowed_types.clone(),
+            max_size_by_type: frontend.upload.max_size_by_type.clone(),
+        },
+        heartbeat_interval: config.websocket.heartbeat_interval,
+        reconnect_interval: config.websocket.reconnect_interval,
+        max_reconnect_attempts: config.websocket.max_reconnect_attempts,
+        max_message_size: config.websocket.max_message_size,
+    };
+
+    let profiles = query
+        .client_type
+        .as_deref()
+        .and_then(|client_type| frontend.client_profiles.get(client_type))
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for profile in profiles {
+        let applicable = match (&profile.min_version, &query.version) {
+            (None, _) => true,
+            (Some(min_version), Some(version)) => version_at_least(version, min_version),
+            (Some(_), None) => false,
+        };
+        if !applicable {
+            continue;
+        }
+        if let Some(ws_url) = &profile.ws_url {
+            response.ws_url = ws_url.clone();
+        }
+        if let Some(max_file_size) = profile.max_file_size {
+            response.upload.max_file_size = max_file_size;
+        }
+        // 只覆盖已有的开关，避免配置拼写错误时下发未知字段
+        for (name, enabled) in &profile.features {
+            if let Some(current) = features.get_mut(name) {
+                *current = *enabled;
+            }
+        }
+    }
+
+    response.features = features;
+    response
+}
+
+/// 按点分的数字段比较版本号，非数字段视为0
+fn version_at_least(version: &str, min_version: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (version, min_version) = (parse(version), parse(min_version));
+    let len = version.len().max(min_version.len());
+    let segment = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    (0..len)
+        .map(|i| segment(&version, i).cmp(&segment(&min_version, i)))
+        .find(|ordering| ordering.is_ne())
+        != Some(std::cmp::Ordering::Less)
+}
+
 /// 验证IP地址格式
 fn is_valid_ip(ip: &str) -> bool {
     // 简单的IP地址验证
@@ -117,6 +204,11 @@ fn is_valid_ip(ip: &str) -> bool {
 
 /// 获取IP地理位置信息 (企业级实现)
 async fn get_ip_location(ip: &str) -> IpLocationResponse {
+    lookup_ip_location(ip)
+}
+
+/// 同步查询IP地理位置，供WebSocket握手风控等非异步场景复用
+pub(crate) fn lookup_ip_location(ip: &str) -> IpLocationResponse {
     // 企业级IP地理位置查询实现
     // 这里可以集成第三方服务如 MaxMind, ipapi.co, ip-api.com 等
     
@@ -169,7 +261,7 @@ async fn get_ip_location(ip: &str) -> IpLocationResponse {
 }
 
 /// 检查是否为私有IP
-fn is_private_ip(ip: &str) -> bool {
+pub(crate) fn is_private_ip(ip: &str) -> bool {
     if let Ok(addr) = ip.parse::<std::net::IpAddr>() {
         match addr {
             std::net::IpAddr::V4(ipv4) => {
@@ -185,6 +277,24 @@ fn is_private_ip(ip: &str) -> bool {
 }
 
 /// 获取IP前缀用于匹配
+/// 只查内置位置库，内网地址或库中没有的IP返回 None，不回落到默认位置
+pub(crate) fn lookup_known_ip_location(ip: &str) -> Option<IpLocationResponse> {
+    if is_private_ip(ip) {
+        return None;
+    }
+    let location_info = get_location_database().remove(&get_ip_prefix(ip))?;
+    Some(IpLocationResponse {
+        ip: ip.to_string(),
+        country: location_info.country,
+        region: location_info.region,
+        city: location_info.city,
+        latitude: location_info.latitude,
+        longitude: location_info.longitude,
+        isp: location_info.isp,
+        timezone: location_info.timezone,
+    })
+}
+
 fn get_ip_prefix(ip: &str) -> String {
     // 取IP地址的前两段作为前缀
     let parts: Vec<&str> = ip.split('.').collect();
@@ -263,4 +373,69 @@ fn get_location_database() -> HashMap<String, LocationInfo> {
     });
     
     db
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientProfile;
+
+    fn shipped_config() -> AppConfig {
+        AppConfig::load_from_file("config/app-config.json").unwrap()
+    }
+
+    fn query(client_type: &str, version: Option<&str>) -> ClientConfigQuery {
+        ClientConfigQuery {
+            client_type: Some(client_type.to_string()),
+            version: version.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_client_config_has_ws_url_and_upload_limits_without_secrets() {
+        let config = shipped_config();
+        let client_config = build_client_config(&config, &ClientConfigQuery::default());
+        assert_eq!(client_config.ws_url, config.frontend.ws_url);
+        assert_eq!(client_config.upload.max_file_size, config.frontend.upload.max_file_size);
+        assert_eq!(client_config.features["imageUpload"], config.frontend.features.image_upload);
+
+        let json = serde_json::to_string(&client_config).unwrap().to_lowercase();
+        for sensitive in ["secret", "password", "token", "redis", &config.security.jwt_secret.to_lowercase()] {
+            assert!(!json.contains(sensitive), "客户端配置包含敏感字段: {}", sensitive);
+        }
+    }
+
+    #[test]
+    fn test_client_profiles_apply_by_type_and_version() {
+        let mut config = shipped_config();
+        config.frontend.client_profiles.insert(
+            "mobile".to_string(),
+            vec![
+                ClientProfile {
+                    max_file_size: Some(1024),
+                    features: [("virtualScrolling".to_string(), false), ("unknown".to_string(), true)].into(),
+                    ..Default::default()
+                },
+                ClientProfile {
+                    min_version: Some("2.0".to_string()),
+                    ws_url: Some("wss://m.example.com/ws".to_string()),
+                    ..Default::default()
+                },
+            ],
+        );
+
+        let old = build_client_config(&config, &query("mobile", Some("1.9.3")));
+        assert_eq!(old.upload.max_file_size, 1024);
+        assert!(!old.features["virtualScrolling"]);
+        assert!(!old.features.contains_key("unknown"));
+        assert_eq!(old.ws_url, config.frontend.ws_url);
+
+        let new = build_client_config(&config, &query("mobile", Some("v2.0.1")));
+        assert_eq!(new.ws_url, "wss://m.example.com/ws");
+        assert_eq!(new.upload.max_file_size, 1024);
+
+        let web = build_client_config(&config, &query("web", Some("3.0")));
+        assert_eq!(web.ws_url, config.frontend.ws_url);
+        assert_eq!(web.upload.max_file_size, config.frontend.upload.max_file_size);
+    }
+}
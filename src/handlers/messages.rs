@@ -1,16 +1,18 @@
 use std::sync::Arc;
 use warp::{Reply, Rejection};
 use serde::{Deserialize, Serialize};
-use crate::storage::LocalStorage;
-use crate::types::api::ApiResponse;
+use crate::auth::operator::Operator;
+use crate::handlers::system_extended::verify_admin_token;
+use crate::message::ChatMessage;
+use crate::storage::{ExportQuery, LocalStorage, MessageExport, MessageSearchQuery, MessageState, StatusUpdateResult};
+use crate::types::api::{ApiResponse, PageRequest, PageResponse};
+use crate::websocket::WebSocketManager;
 use chrono::{DateTime, Utc};
-use uuid::Uuid;
+use warp::http::StatusCode;
 
 // 请求和响应结构体
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageListQuery {
-    pub page: Option<u32>,
-    pub limit: Option<u32>,
     pub user_id: Option<String>,
     pub start_date: Option<DateTime<Utc>>,
     pub end_date: Option<DateTime<Utc>>,
@@ -24,10 +26,21 @@ pub struct MessageSearchRequest {
     pub content_type: Option<String>,
     pub start_date: Option<DateTime<Utc>>,
     pub end_date: Option<DateTime<Utc>>,
-    pub page: Option<u32>,
-    pub limit: Option<u32>,
+    #[serde(flatten)]
+    pub page: PageRequest,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InternalMessagesQuery {
+    /// 管理员查询时指定对话的另一位客服，客服查询时取自令牌
+    pub kefu_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// 内部消息历史默认和最多返回的条数
+const DEFAULT_INTERNAL_MESSAGE_LIMIT: usize = 50;
+const MAX_INTERNAL_MESSAGE_LIMIT: usize = 200;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageExportRequest {
     pub format: String, // json, csv, excel
@@ -39,12 +52,10 @@ pub struct MessageExportRequest {
 
 // 获取消息列表
 pub async fn handle_list_messages(
-    query: MessageListQuery,
+    _query: MessageListQuery,
+    page: PageRequest,
     _storage: Arc<LocalStorage>,
 ) -> Result<impl Reply, Rejection> {
-    let page = query.page.unwrap_or(1);
-    let limit = query.limit.unwrap_or(20);
-    
     // TODO: 从storage实际获取消息
     let messages = vec![
         serde_json::json!({
@@ -70,15 +81,7 @@ pub async fn handle_list_messages(
     let response = ApiResponse {
         success: true,
         message: "获取消息列表成功".to_string(),
-        data: Some(serde_json::json!({
-            "messages": messages,
-            "pagination": {
-                "page": page,
-                "limit": limit,
-                "total": 100,
-                "total_pages": 5
-            }
-        })),
+        data: Some(PageResponse::paginate(messages, &page)),
     };
 
     Ok(warp::reply::json(&response))
@@ -115,70 +118,461 @@ pub async fn handle_get_message(
     Ok(warp::reply::json(&response))
 }
 
-// 搜索消息
+// 查询消息投递状态（已入队/已发送/已送达/已读/失败），只有消息的收发双方或管理员可以查询
+pub async fn handle_get_message_status(
+    message_id: String,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let Some(caller) = MessageCaller::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(login_required_reply());
+    };
+    // 非收发双方查询时与状态不存在同样处理，不暴露消息是否存在
+    let record = ws_manager
+        .message_queue
+        .get_status(&message_id)
+        .await
+        .map(|record| record.filter(|record| caller.is_party(&record.sender, Some(&record.recipient))));
+    let response = match record {
+        Ok(Some(record)) => ApiResponse {
+            success: true,
+            message: "获取消息状态成功".to_string(),
+            data: Some(serde_json::to_value(&record).unwrap_or_default()),
+        },
+        Ok(None) => ApiResponse {
+            success: false,
+            message: format!("消息状态不存在或已过期: {}", message_id),
+            data: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            message: format!("获取消息状态失败: {}", e),
+            data: None,
+        },
+    };
+
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
+}
+
+// 按自动标签筛选消息：客服只能看到本人参与的会话，管理令牌不限
+pub async fn handle_list_messages_by_tag(
+    tag: String,
+    page: PageRequest,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
+) -> Result<impl Reply, Rejection> {
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized_reply());
+    };
+    Ok(messages_by_tag_reply(&operator, &tag, page, &storage))
+}
+
+fn messages_by_tag_reply(
+    operator: &Operator,
+    tag: &str,
+    page: PageRequest,
+    storage: &LocalStorage,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let response = match storage.get_messages_by_tag(tag, operator.kefu_id()) {
+        Ok(messages) => ApiResponse {
+            success: true,
+            message: format!("获取标签 {} 的消息成功", tag),
+            data: Some(PageResponse::paginate(messages, &page)),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            message: format!("获取标签消息失败: {}", e),
+            data: None,
+        },
+    };
+
+    warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+}
+
+fn unauthorized_reply() -> warp::reply::WithStatus<warp::reply::Json> {
+    let response: ApiResponse<()> = ApiResponse {
+        success: false,
+        message: "需要客服登录或管理令牌".to_string(),
+        data: None,
+    };
+    warp::reply::with_status(warp::reply::json(&response), StatusCode::UNAUTHORIZED)
+}
+
+// 搜索消息：每条结果附带关键词附近的摘要和摘要内的高亮位置（按字符计）。
+// 客服只能搜索本人参与的会话，管理令牌不限
 pub async fn handle_search_messages(
     request: MessageSearchRequest,
-    _storage: Arc<LocalStorage>,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
 ) -> Result<impl Reply, Rejection> {
-    let page = request.page.unwrap_or(1);
-    let limit = request.limit.unwrap_or(20);
-    
-    // TODO: 实现搜索逻辑
-    let results = vec![
-        serde_json::json!({
-            "id": "msg_003",
-            "from": "user_001",
-            "to": "user_002",
-            "content": format!("包含关键词 '{}' 的消息", request.keyword),
-            "content_type": "text",
-            "timestamp": "2025-07-16T10:00:00Z",
-            "highlight": format!("<mark>{}</mark>", request.keyword)
-        }),
-    ];
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized_reply());
+    };
+    Ok(search_messages_reply(&operator, request, storage).await)
+}
 
-    let response = ApiResponse {
-        success: true,
-        message: format!("搜索 '{}' 完成", request.keyword),
-        data: Some(serde_json::json!({
-            "results": results,
-            "pagination": {
-                "page": page,
-                "limit": limit,
-                "total": results.len(),
-                "keyword": request.keyword
-            }
-        })),
+async fn search_messages_reply(
+    operator: &Operator,
+    request: MessageSearchRequest,
+    storage: Arc<LocalStorage>,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let query = MessageSearchQuery {
+        keyword: request.keyword.clone(),
+        user_id: request.user_id.clone(),
+        kefu_id: operator.kefu_id().map(str::to_string),
+        content_type: request.content_type.clone(),
+        start_date: request.start_date,
+        end_date: request.end_date,
     };
 
-    Ok(warp::reply::json(&response))
+    let hits = tokio::task::spawn_blocking(move || storage.search_messages_with_snippets(&query))
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+    let response = match hits {
+        Ok(hits) => ApiResponse {
+            success: true,
+            message: format!("搜索 '{}' 完成", request.keyword),
+            data: Some(PageResponse::paginate(hits, &request.page)),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            message: format!("搜索消息失败: {}", e),
+            data: None,
+        },
+    };
+
+    warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
 }
 
-// 导出消息
+// 客服之间的内部消息历史：客服只能查看本人与 peer 的对话，管理令牌需用 kefu_id 指定另一方
+pub async fn handle_internal_messages(
+    peer: String,
+    query: InternalMessagesQuery,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
+) -> Result<impl Reply, Rejection> {
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized_reply());
+    };
+    Ok(internal_messages_reply(&operator, &peer, query, &storage))
+}
+
+fn internal_messages_reply(
+    operator: &Operator,
+    peer: &str,
+    query: InternalMessagesQuery,
+    storage: &LocalStorage,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let Some(kefu_id) = operator.kefu_id().or(query.kefu_id.as_deref()) else {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "管理员查询需指定 kefu_id".to_string(),
+            data: None,
+        };
+        return warp::reply::with_status(warp::reply::json(&response), StatusCode::BAD_REQUEST);
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_INTERNAL_MESSAGE_LIMIT)
+        .clamp(1, MAX_INTERNAL_MESSAGE_LIMIT);
+
+    match storage.get_internal_messages(kefu_id, peer, limit) {
+        Ok(messages) => {
+            let response = ApiResponse {
+                success: true,
+                message: format!("获取到{}条内部消息", messages.len()),
+                data: Some(messages),
+            };
+            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+        }
+        Err(e) => {
+            tracing::error!("❌ 读取内部消息失败: {} <-> {}, error: {:?}", kefu_id, peer, e);
+            let response: ApiResponse<()> = ApiResponse {
+                success: false,
+                message: "读取内部消息失败".to_string(),
+                data: None,
+            };
+            warp::reply::with_status(warp::reply::json(&response), StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 导出消息：导出人取自令牌，客服只能导出本人参与的会话；超过导出人当日限额时拒绝，每次导出都记审计
 pub async fn handle_export_messages(
     request: MessageExportRequest,
-    _storage: Arc<LocalStorage>,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
 ) -> Result<impl Reply, Rejection> {
-    // TODO: 实现导出逻辑
-    let export_id = Uuid::new_v4().to_string();
-    let file_name = format!("messages_export_{}_{}.{}", 
-        Utc::now().format("%Y%m%d_%H%M%S"),
-        export_id,
-        request.format
-    );
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(unauthorized_reply());
+    };
+    let query = ExportQuery {
+        user_id: request.user_id.clone(),
+        kefu_id: operator.kefu_id().map(str::to_string),
+        start_date: request.start_date,
+        end_date: request.end_date,
+        format: request.format.clone(),
+    };
 
-    let response = ApiResponse {
-        success: true,
-        message: "消息导出任务已创建".to_string(),
-        data: Some(serde_json::json!({
-            "export_id": export_id,
-            "file_name": file_name,
-            "format": request.format,
-            "status": "processing",
-            "download_url": format!("/api/exports/{}", export_id)
-        })),
+    let export = tokio::task::spawn_blocking(move || storage.export_messages(operator.name(), &query))
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+    let (response, status) = match export {
+        Ok(MessageExport::Completed { audit, messages }) => {
+            let file_name = format!("messages_export_{}_{}.{}",
+                audit.timestamp.format("%Y%m%d_%H%M%S"),
+                audit.id,
+                request.format
+            );
+            (
+                ApiResponse {
+                    success: true,
+                    message: format!("导出完成，共{}条消息", audit.message_count),
+                    data: Some(serde_json::json!({
+                        "export_id": audit.id,
+                        "file_name": file_name,
+                        "format": request.format,
+                        "status": "completed",
+                        "message_count": audit.message_count,
+                        "messages": messages,
+                    })),
+                },
+                StatusCode::OK,
+            )
+        }
+        Ok(MessageExport::Rejected(audit)) => (
+            ApiResponse {
+                success: false,
+                message: format!("导出被拒绝: {}", audit.reason.unwrap_or_default()),
+                data: None,
+            },
+            StatusCode::TOO_MANY_REQUESTS,
+        ),
+        Err(e) => (
+            ApiResponse {
+                success: false,
+                message: format!("导出消息失败: {}", e),
+                data: None,
+            },
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
     };
 
-    Ok(warp::reply::json(&response))
+    Ok(warp::reply::with_status(warp::reply::json(&response), status))
+}
+
+// 导出审计记录，最新的在前，仅管理端可查
+pub async fn handle_export_audit(
+    admin_token: Option<String>,
+    query: std::collections::HashMap<String, String>,
+    storage: Arc<LocalStorage>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::FORBIDDEN));
+    }
+
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse::<usize>().ok())
+        .unwrap_or(100)
+        .clamp(1, 1000);
+    let response = match storage.list_export_audit(limit) {
+        Ok(entries) => ApiResponse {
+            success: true,
+            message: format!("共 {} 条导出审计记录", entries.len()),
+            data: Some(entries),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            message: format!("获取导出审计失败: {}", e),
+            data: None,
+        },
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
+}
+
+/// 会话导出支持的格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConversationExportFormat {
+    Json,
+    Csv,
+}
+
+impl ConversationExportFormat {
+    fn parse(format: &str) -> Option<Self> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json; charset=utf-8",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+/// CSV 导出的表头，开头的 BOM 让 Excel 按 UTF-8 识别中文
+const CONVERSATION_CSV_HEADER: &str = "\u{feff}timestamp,from,to,content_type,content\n";
+
+/// 流式导出时后台读取线程最多领先发送的块数
+const CONVERSATION_EXPORT_BUFFER: usize = 64;
+
+// 按格式逐条序列化会话消息：JSON 为消息数组，CSV 每条消息一行；无法解码的消息记录日志后跳过
+fn conversation_export_chunks(
+    messages: impl Iterator<Item = anyhow::Result<ChatMessage>> + Send + 'static,
+    format: ConversationExportFormat,
+) -> Box<dyn Iterator<Item = anyhow::Result<String>> + Send> {
+    let messages = messages.filter_map(|message| match message {
+        Ok(message) => Some(message),
+        Err(e) => {
+            tracing::warn!("⚠️ 导出会话时跳过无法读取的消息: {}", e);
+            None
+        }
+    });
+    match format {
+        ConversationExportFormat::Json => {
+            let mut first = true;
+            let items = messages.map(move |message| {
+                let item = serde_json::to_string(&message)?;
+                let separator = if std::mem::take(&mut first) { "" } else { "," };
+                Ok::<String, anyhow::Error>(format!("{}{}", separator, item))
+            });
+            Box::new(
+                std::iter::once(Ok("[".to_string()))
+                    .chain(items)
+                    .chain(std::iter::once(Ok("]".to_string()))),
+            )
+        }
+        ConversationExportFormat::Csv => Box::new(
+            std::iter::once(Ok(CONVERSATION_CSV_HEADER.to_string()))
+                .chain(messages.map(|message| Ok(conversation_csv_row(&message)))),
+        ),
+    }
+}
+
+fn conversation_csv_row(message: &ChatMessage) -> String {
+    let content_type = message
+        .content_type
+        .as_ref()
+        .and_then(|content_type| serde_json::to_value(content_type).ok())
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let fields = [
+        message.timestamp.to_rfc3339(),
+        message.from.clone(),
+        message.to.clone().unwrap_or_default(),
+        content_type,
+        message.content.clone(),
+    ];
+    let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\n", row.join(","))
+}
+
+// 以 = + - @、制表符或回车开头的字段前加单引号，防止在表格软件中被当作公式执行；
+// 含逗号、引号或换行的字段加引号，内部引号双写
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+// 下载文件名只保留字母、数字、下划线和连字符，避免破坏响应头
+fn file_name_part(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+// 导出两个用户之间的完整会话（format=json|csv），边读取边返回，不在内存中缓存整个会话；仅管理端可用
+pub async fn handle_export_conversation(
+    user_a: String,
+    user_b: String,
+    admin_token: Option<String>,
+    query: std::collections::HashMap<String, String>,
+    storage: Arc<LocalStorage>,
+) -> Result<warp::reply::Response, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::FORBIDDEN).into_response());
+    }
+
+    let requested = query.get("format").map(String::as_str).unwrap_or("json");
+    let Some(format) = ConversationExportFormat::parse(requested) else {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: format!("不支持的导出格式: {}，支持 json、csv", requested),
+            data: None,
+        };
+        return Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::BAD_REQUEST).into_response());
+    };
+
+    let file_name = format!(
+        "conversation_{}_{}_{}.{}",
+        file_name_part(&user_a),
+        file_name_part(&user_b),
+        Utc::now().format("%Y%m%d_%H%M%S"),
+        format.extension()
+    );
+    tracing::info!("📤 导出会话: {} <-> {}, 格式={}", user_a, user_b, format.extension());
+
+    // sled 读取在阻塞线程中进行，经有界通道逐块交给响应体，客户端断开后读取随之停止
+    let (tx, rx) = tokio::sync::mpsc::channel::<anyhow::Result<String>>(CONVERSATION_EXPORT_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        for chunk in conversation_export_chunks(storage.iter_messages(&user_a, &user_b), format) {
+            // 响应头已发出，序列化失败时只能中断连接
+            if let Err(e) = &chunk {
+                tracing::error!("❌ 导出会话失败: {}", e);
+            }
+            let failed = chunk.is_err();
+            if tx.blocking_send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    });
+    let chunks = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    let mut response = warp::reply::Response::new(warp::hyper::Body::wrap_stream(chunks));
+    let headers = response.headers_mut();
+    headers.insert(
+        warp::http::header::CONTENT_TYPE,
+        warp::http::HeaderValue::from_static(format.content_type()),
+    );
+    if let Ok(disposition) = warp::http::HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name)) {
+        headers.insert(warp::http::header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(response)
 }
 
 // 删除消息
@@ -200,42 +594,370 @@ pub async fn handle_delete_message(
     Ok(warp::reply::json(&response))
 }
 
-// 批量删除消息
+/// 按消息操作的调用方：管理令牌不限，其他调用方须持有 JWT 且为消息的收发一方
+pub(crate) enum MessageCaller {
+    Admin,
+    Participant(String),
+}
+
+impl MessageCaller {
+    pub(crate) fn resolve(authorization: Option<&str>, admin_token: Option<&str>) -> Option<Self> {
+        if verify_admin_token(admin_token) {
+            return Some(MessageCaller::Admin);
+        }
+        crate::auth::operator::bearer_claims(authorization).map(|claims| MessageCaller::Participant(claims.sub))
+    }
+
+    pub(crate) fn can_access(&self, message: &ChatMessage) -> bool {
+        self.is_party(&message.from, message.to.as_deref())
+    }
+
+    fn is_party(&self, from: &str, to: Option<&str>) -> bool {
+        match self {
+            MessageCaller::Admin => true,
+            MessageCaller::Participant(user_id) => from == user_id || to == Some(user_id.as_str()),
+        }
+    }
+}
+
+fn login_required_reply() -> warp::reply::WithStatus<warp::reply::Json> {
+    let response: ApiResponse<()> = ApiResponse {
+        success: false,
+        message: "需要登录或管理令牌".to_string(),
+        data: None,
+    };
+    warp::reply::with_status(warp::reply::json(&response), StatusCode::UNAUTHORIZED)
+}
+
+// 批量删除消息（软删除），只能删除本人收发的消息
 pub async fn handle_bulk_delete_messages(
     message_ids: Vec<String>,
-    _storage: Arc<LocalStorage>,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
 ) -> Result<impl Reply, Rejection> {
-    let deleted_count = message_ids.len();
-    
-    let response = ApiResponse {
-        success: true,
-        message: format!("已删除 {} 条消息", deleted_count),
-        data: Some(serde_json::json!({
-            "deleted_count": deleted_count,
-            "deleted_ids": message_ids,
-            "deleted_at": Utc::now()
-        })),
+    let Some(caller) = MessageCaller::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(login_required_reply());
     };
-
-    Ok(warp::reply::json(&response))
+    let response = batch_status_response(&storage, &caller, &message_ids, MessageState::Deleted, "删除");
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
 }
 
-// 标记消息已读
+// 标记消息已读，只能标记本人收发的消息
 pub async fn handle_mark_messages_read(
     message_ids: Vec<String>,
-    _storage: Arc<LocalStorage>,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
 ) -> Result<impl Reply, Rejection> {
-    let marked_count = message_ids.len();
-    
-    let response = ApiResponse {
-        success: true,
-        message: format!("已标记 {} 条消息为已读", marked_count),
-        data: Some(serde_json::json!({
-            "marked_count": marked_count,
-            "message_ids": message_ids,
-            "marked_at": Utc::now()
-        })),
+    let Some(caller) = MessageCaller::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return Ok(login_required_reply());
     };
+    let response = batch_status_response(&storage, &caller, &message_ids, MessageState::Read, "标记已读");
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
+}
 
-    Ok(warp::reply::json(&response))
+// 批量状态更新的统一响应：整体成功与否看是否有失败项，data 中附逐条结果。
+// 调用方不是收发一方的消息不做更新，在逐条结果中记为失败
+fn batch_status_response(
+    storage: &LocalStorage,
+    caller: &MessageCaller,
+    message_ids: &[String],
+    status: MessageState,
+    action: &str,
+) -> ApiResponse<serde_json::Value> {
+    let denied: std::collections::HashSet<&str> = message_ids
+        .iter()
+        .filter(|message_id| {
+            matches!(storage.get_message(message_id), Ok(Some(message)) if !caller.can_access(&message))
+        })
+        .map(String::as_str)
+        .collect();
+    let allowed: Vec<String> = message_ids
+        .iter()
+        .filter(|message_id| !denied.contains(message_id.as_str()))
+        .cloned()
+        .collect();
+
+    match storage.batch_update_message_status(&allowed, status) {
+        Ok(updated) => {
+            let mut updated = updated.into_iter();
+            let results: Vec<StatusUpdateResult> = message_ids
+                .iter()
+                .filter_map(|message_id| {
+                    if denied.contains(message_id.as_str()) {
+                        Some(StatusUpdateResult {
+                            message_id: message_id.clone(),
+                            success: false,
+                            error: Some("无权操作该消息".to_string()),
+                        })
+                    } else {
+                        updated.next()
+                    }
+                })
+                .collect();
+            let updated_count = results.iter().filter(|r| r.success).count();
+            let failed_count = results.len() - updated_count;
+            let status_counts: serde_json::Map<String, serde_json::Value> =
+                [MessageState::Unread, MessageState::Read, MessageState::Deleted]
+                    .into_iter()
+                    .filter_map(|state| Some((state.as_str().to_string(), storage.get_status_count(state).ok()?.into())))
+                    .collect();
+            ApiResponse {
+                success: failed_count == 0,
+                message: format!("已{} {} 条消息，失败 {} 条", action, updated_count, failed_count),
+                data: Some(serde_json::json!({
+                    "updated_count": updated_count,
+                    "failed_count": failed_count,
+                    "results": results,
+                    "status_counts": status_counts,
+                    "updated_at": Utc::now()
+                })),
+            }
+        }
+        Err(e) => ApiResponse {
+            success: false,
+            message: format!("批量{}失败: {}", action, e),
+            data: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+    use crate::message::ContentType;
+
+    async fn reply_json(reply: impl Reply) -> serde_json::Value {
+        let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn page_request(page_size: u32) -> PageRequest {
+        PageRequest { page: None, page_size: Some(page_size), cursor: None }
+    }
+
+    #[tokio::test]
+    async fn test_list_endpoints_share_pagination_shape() {
+        let dir = std::env::temp_dir().join(format!("paging_test_{}", Uuid::new_v4()));
+        let storage = Arc::new(LocalStorage::new(dir.to_str().unwrap()).unwrap());
+        for i in 0..3 {
+            storage
+                .save_message(&ChatMessage {
+                    id: Some(format!("msg_{}", i)),
+                    from: "kehu_001".to_string(),
+                    to: Some("kefu_001".to_string()),
+                    content: format!("第{}次申请退款", i),
+                    content_type: None,
+                    filename: None,
+                    timestamp: Utc::now(),
+                    url: None,
+                    thumbnail_url: None,
+                    seq: None,
+                })
+                .unwrap();
+        }
+
+        let list_query = MessageListQuery {
+            user_id: None,
+            start_date: None,
+            end_date: None,
+            content_type: None,
+        };
+        let search_request: MessageSearchRequest =
+            serde_json::from_value(serde_json::json!({ "keyword": "退款", "limit": 1 })).unwrap();
+
+        let responses = vec![
+            reply_json(handle_list_messages(list_query, page_request(1), storage.clone()).await.unwrap()).await,
+            reply_json(search_messages_reply(&Operator::Admin, search_request, storage.clone()).await).await,
+            reply_json(messages_by_tag_reply(&Operator::Admin, "refund", page_request(1), &storage)).await,
+        ];
+
+        for response in &responses {
+            let data = &response["data"];
+            let mut keys: Vec<&String> = data.as_object().unwrap().keys().collect();
+            keys.sort();
+            assert_eq!(keys, ["has_more", "items", "next_cursor", "page", "page_size", "total"]);
+            assert_eq!(data["page"], 1);
+            assert_eq!(data["page_size"], 1);
+            assert!(data["items"].as_array().unwrap().len() <= 1);
+            assert_eq!(data["has_more"], data["total"].as_u64().unwrap() > 1);
+        }
+
+        // 按游标翻到标签消息的最后一页
+        let last = PageRequest { page: None, page_size: Some(1), cursor: Some("2".to_string()) };
+        let response = reply_json(messages_by_tag_reply(&Operator::Admin, "refund", last, &storage)).await;
+        assert_eq!(response["data"]["total"], 3);
+        assert_eq!(response["data"]["page"], 3);
+        assert_eq!(response["data"]["has_more"], false);
+        assert!(response["data"]["next_cursor"].is_null());
+
+        // 客服只能看到本人参与的会话中的标签消息
+        let own = Operator::Kefu("kefu_001".to_string());
+        let response = reply_json(messages_by_tag_reply(&own, "refund", page_request(10), &storage)).await;
+        assert_eq!(response["data"]["total"], 3);
+        let other = Operator::Kefu("kefu_002".to_string());
+        let response = reply_json(messages_by_tag_reply(&other, "refund", page_request(10), &storage)).await;
+        assert_eq!(response["data"]["total"], 0);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_conversation_export_streams_json_and_csv() {
+        let dir = std::env::temp_dir().join(format!("conversation_export_test_{}", Uuid::new_v4()));
+        let storage = Arc::new(LocalStorage::new(dir.to_str().unwrap()).unwrap());
+        let message = |id: &str, from: &str, to: &str, content: &str, seconds: i64| ChatMessage {
+            id: Some(id.to_string()),
+            from: from.to_string(),
+            to: Some(to.to_string()),
+            content: content.to_string(),
+            content_type: Some(ContentType::Text),
+            filename: None,
+            timestamp: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+            url: None,
+            thumbnail_url: None,
+            seq: None,
+        };
+        storage.save_message(&message("msg_1", "kehu_001", "kefu_001", "你好，订单\"123\"没到", 0)).unwrap();
+        storage.save_message(&message("msg_2", "kefu_001", "kehu_001", "稍等\n帮您查询", 1)).unwrap();
+        storage.save_message(&message("msg_3", "kehu_002", "kefu_001", "其他会话", 2)).unwrap();
+
+        let collect = |format| {
+            conversation_export_chunks(storage.iter_messages("kefu_001", "kehu_001"), format)
+                .collect::<anyhow::Result<String>>()
+                .unwrap()
+        };
+
+        let exported: Vec<ChatMessage> = serde_json::from_str(&collect(ConversationExportFormat::Json)).unwrap();
+        let ids: Vec<_> = exported.iter().filter_map(|message| message.id.as_deref()).collect();
+        assert_eq!(ids, ["msg_1", "msg_2"]);
+
+        let csv = collect(ConversationExportFormat::Csv);
+        assert!(csv.starts_with(CONVERSATION_CSV_HEADER));
+        assert!(csv.contains(",kehu_001,kefu_001,Text,\"你好，订单\"\"123\"\"没到\"\n"));
+        assert!(csv.contains(",kefu_001,kehu_001,Text,\"稍等\n帮您查询\"\n"));
+        assert!(!csv.contains("其他会话"));
+
+        // 公式开头的字段加单引号；无法读取的消息被跳过，不中断导出
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("-1+1"), "'-1+1");
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        let messages = vec![
+            Err(anyhow::anyhow!("损坏的消息")),
+            Ok(message("msg_4", "kehu_001", "kefu_001", "+86 138", 3)),
+        ];
+        let csv = conversation_export_chunks(messages.into_iter(), ConversationExportFormat::Csv)
+            .collect::<anyhow::Result<String>>()
+            .unwrap();
+        assert!(csv.ends_with(",kehu_001,kefu_001,Text,'+86 138\n"));
+
+        assert_eq!(ConversationExportFormat::parse("CSV"), Some(ConversationExportFormat::Csv));
+        assert_eq!(ConversationExportFormat::parse("pdf"), None);
+        assert_eq!(file_name_part("kehu/001\"x"), "kehu_001_x");
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_batch_status_only_updates_callers_messages() {
+        let dir = std::env::temp_dir().join(format!("batch_status_caller_test_{}", Uuid::new_v4()));
+        let storage = LocalStorage::new(dir.to_str().unwrap()).unwrap();
+        for (id, from) in [("own_1", "kehu_001"), ("own_2", "kefu_001"), ("other", "kehu_002")] {
+            storage
+                .save_message(&ChatMessage {
+                    id: Some(id.to_string()),
+                    from: from.to_string(),
+                    to: Some(if from == "kefu_001" { "kehu_001" } else { "kefu_001" }.to_string()),
+                    content: "你好".to_string(),
+                    content_type: None,
+                    filename: None,
+                    timestamp: Utc::now(),
+                    url: None,
+                    thumbnail_url: None,
+                    seq: None,
+                })
+                .unwrap();
+        }
+        let ids: Vec<String> = ["own_1", "other", "own_2"].iter().map(|id| id.to_string()).collect();
+
+        // 客户只能标记本人收发的消息，其他会话的消息逐条报错且状态不变
+        let caller = MessageCaller::Participant("kehu_001".to_string());
+        let response = batch_status_response(&storage, &caller, &ids, MessageState::Read, "标记已读");
+        let data = response.data.unwrap();
+        assert_eq!(data["updated_count"], 2);
+        assert_eq!(data["results"][1]["message_id"], "other");
+        assert_eq!(data["results"][1]["success"], false);
+        assert_eq!(data["results"][2]["message_id"], "own_2");
+        assert_eq!(storage.get_message_status("other").unwrap(), MessageState::Unread);
+        assert_eq!(storage.get_message_status("own_1").unwrap(), MessageState::Read);
+        // 投递状态同样只对收发双方可见
+        assert!(caller.is_party("kefu_001", Some("kehu_001")));
+        assert!(!caller.is_party("kehu_002", Some("kefu_001")));
+
+        // 管理令牌不限会话
+        let response = batch_status_response(&storage, &MessageCaller::Admin, &ids, MessageState::Deleted, "删除");
+        assert!(response.success);
+        assert_eq!(storage.get_message_status("other").unwrap(), MessageState::Deleted);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_internal_messages_scoped_to_operator() {
+        let dir = std::env::temp_dir().join(format!("internal_history_test_{}", Uuid::new_v4()));
+        let storage = LocalStorage::new(dir.to_str().unwrap()).unwrap();
+        let pairs = [("kefu_001", "kefu_002"), ("kefu_002", "kefu_001"), ("kefu_002", "kefu_003")];
+        for (i, (from, to)) in pairs.into_iter().enumerate() {
+            storage
+                .save_internal_message(&ChatMessage {
+                    id: Some(format!("internal_{}", i)),
+                    from: from.to_string(),
+                    to: Some(to.to_string()),
+                    content: "这位客户需要升级处理".to_string(),
+                    content_type: Some(ContentType::Text),
+                    filename: None,
+                    timestamp: DateTime::from_timestamp(1_700_000_000 + i as i64, 0).unwrap(),
+                    url: None,
+                    thumbnail_url: None,
+                    seq: None,
+                })
+                .unwrap();
+        }
+        let query = |kefu_id: Option<&str>, limit: Option<usize>| InternalMessagesQuery {
+            kefu_id: kefu_id.map(str::to_string),
+            limit,
+        };
+        let ids = |response: &serde_json::Value| -> Vec<String> {
+            response["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|message| message["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        // 客服只能看到本人参与的对话，query 中的 kefu_id 被忽略
+        let kefu = Operator::Kefu("kefu_001".to_string());
+        let response = reply_json(internal_messages_reply(&kefu, "kefu_002", query(Some("kefu_003"), None), &storage)).await;
+        assert_eq!(ids(&response), ["internal_0", "internal_1"]);
+        let response = reply_json(internal_messages_reply(&kefu, "kefu_002", query(None, Some(1)), &storage)).await;
+        assert_eq!(ids(&response), ["internal_1"]);
+
+        let admin = internal_messages_reply(&Operator::Admin, "kefu_002", query(None, None), &storage);
+        assert_eq!(admin.into_response().status(), StatusCode::BAD_REQUEST);
+        let response =
+            reply_json(internal_messages_reply(&Operator::Admin, "kefu_002", query(Some("kefu_003"), None), &storage)).await;
+        assert_eq!(ids(&response), ["internal_2"]);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
@@ -16,6 +16,7 @@
 /// - `messages`: 消息管理处理器
 /// - `sessions`: 会话管理处理器
 /// - `analytics`: 统计分析处理器
+/// - `canned_responses`: 客服快捷回复处理器
 /// 
 /// # 设计原则
 /// - 单一职责：每个处理器只负责特定的业务功能
@@ -36,6 +37,7 @@ pub mod users;
 pub mod messages;
 pub mod sessions;
 pub mod analytics;
+pub mod canned_responses;
 
 #[cfg(test)]
 mod tests {
@@ -1,16 +1,17 @@
 use std::sync::Arc;
 use warp::{Reply, Rejection};
 use serde::{Deserialize, Serialize};
-use crate::websocket::WebSocketManager;
+use crate::websocket::{SessionTransferOutcome, WebSocketManager};
 use crate::storage::LocalStorage;
-use crate::types::api::ApiResponse;
+use crate::types::api::{ApiResponse, PageRequest, PageResponse};
+use crate::message::{LeaveMessageTicket, TicketStatus};
+use crate::auth::operator::Operator;
 use chrono::{DateTime, Utc};
+use warp::http::StatusCode;
 
 // 请求和响应结构体
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionListQuery {
-    pub page: Option<u32>,
-    pub limit: Option<u32>,
     pub kefu_id: Option<String>,
     pub status: Option<String>, // active, completed, transferred
     pub start_date: Option<DateTime<Utc>>,
@@ -19,11 +20,26 @@ pub struct SessionListQuery {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionMessagesQuery {
-    pub page: Option<u32>,
-    pub limit: Option<u32>,
     pub include_system: Option<bool>,
 }
 
+/// 增量同步查询，session_id 为会话记录ID或 conversation_id（两个参与者按字典序以 ':' 连接）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessagesSinceQuery {
+    #[serde(default)]
+    pub since_seq: u64,
+    /// 长轮询等待秒数，没有新消息时最多等待这么久；为0时立即返回
+    #[serde(default)]
+    pub wait_seconds: u64,
+}
+
+/// 长轮询的最长等待时间
+const MAX_SYNC_WAIT_SECONDS: u64 = 30;
+/// 同时挂起的长轮询请求上限，超出时不再等待、立即返回
+const MAX_SYNC_WAITERS: usize = 1000;
+
+static SYNC_WAITERS: tokio::sync::Semaphore = tokio::sync::Semaphore::const_new(MAX_SYNC_WAITERS);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TransferSessionRequest {
     pub to_kefu_id: String,
@@ -31,6 +47,19 @@ pub struct TransferSessionRequest {
     pub note: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerTransferRequest {
+    pub from_kefu_id: String,
+    pub to_kefu_id: String,
+    pub customer_id: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketListQuery {
+    pub status: Option<TicketStatus>, // open, resolved；为空时返回全部
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionInfo {
     pub session_id: String,
@@ -47,12 +76,10 @@ pub struct SessionInfo {
 
 // 获取会话列表
 pub async fn handle_list_sessions(
-    query: SessionListQuery,
+    _query: SessionListQuery,
+    page: PageRequest,
     ws_manager: Arc<WebSocketManager>,
 ) -> Result<impl Reply, Rejection> {
-    let page = query.page.unwrap_or(1);
-    let limit = query.limit.unwrap_or(20);
-    
     // TODO: 从WebSocketManager获取实际会话
     let sessions = vec![
         SessionInfo {
@@ -84,15 +111,7 @@ pub async fn handle_list_sessions(
     let response = ApiResponse {
         success: true,
         message: "获取会话列表成功".to_string(),
-        data: Some(serde_json::json!({
-            "sessions": sessions,
-            "pagination": {
-                "page": page,
-                "limit": limit,
-                "total": 50,
-                "total_pages": 3
-            }
-        })),
+        data: Some(PageResponse::paginate(sessions, &page)),
     };
 
     Ok(warp::reply::json(&response))
@@ -151,11 +170,10 @@ pub async fn handle_get_session(
 pub async fn handle_get_session_messages(
     session_id: String,
     query: SessionMessagesQuery,
+    page: PageRequest,
     ws_manager: Arc<WebSocketManager>,
     storage: Arc<LocalStorage>,
 ) -> Result<impl Reply, Rejection> {
-    let page = query.page.unwrap_or(1);
-    let limit = query.limit.unwrap_or(50);
     let include_system = query.include_system.unwrap_or(false);
     
     // TODO: 从storage获取实际消息
@@ -180,22 +198,58 @@ pub async fn handle_get_session_messages(
 
     let response = ApiResponse {
         success: true,
-        message: "获取会话消息成功".to_string(),
-        data: Some(serde_json::json!({
-            "session_id": session_id,
-            "messages": messages,
-            "pagination": {
-                "page": page,
-                "limit": limit,
-                "total": 15,
-                "has_more": false
-            }
-        })),
+        message: format!("获取会话 {} 的消息成功", session_id),
+        data: Some(PageResponse::paginate(messages, &page)),
     };
 
     Ok(warp::reply::json(&response))
 }
 
+// 增量同步会话消息：只有会话参与者可以读取，返回序号大于 since_seq 的消息，可长轮询等待新消息
+pub async fn handle_get_messages_since(
+    session_id: String,
+    query: MessagesSinceQuery,
+    authorization: Option<String>,
+    storage: Arc<LocalStorage>,
+) -> Result<impl Reply, Rejection> {
+    let reply = |response: ApiResponse<serde_json::Value>,
+                 status: StatusCode|
+     -> Result<warp::reply::WithStatus<warp::reply::Json>, Rejection> {
+        Ok(warp::reply::with_status(warp::reply::json(&response), status))
+    };
+    let Some(claims) = crate::auth::operator::bearer_claims(authorization.as_deref()) else {
+        return reply(ApiResponse::error("需要登录后同步消息".to_string()), StatusCode::UNAUTHORIZED);
+    };
+    let is_participant = match storage.session_participants(&session_id) {
+        Ok(participants) => participants.is_some_and(|(user1, user2)| claims.sub == user1 || claims.sub == user2),
+        Err(e) => return reply(ApiResponse::error(format!("增量同步会话消息失败: {}", e)), StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    if !is_participant {
+        return reply(ApiResponse::error("无权访问该会话".to_string()), StatusCode::FORBIDDEN);
+    }
+
+    // 挂起的长轮询达到上限时退化为普通查询，不占用等待名额
+    let permit = (query.wait_seconds > 0).then(|| SYNC_WAITERS.try_acquire().ok()).flatten();
+    let wait = match permit {
+        Some(_) => std::time::Duration::from_secs(query.wait_seconds.min(MAX_SYNC_WAIT_SECONDS)),
+        None => std::time::Duration::ZERO,
+    };
+    let response = match storage.wait_for_messages_since(&session_id, query.since_seq, wait).await {
+        Ok(messages) => {
+            // 客户端下次同步从 latest_seq 继续
+            let latest_seq = messages.iter().filter_map(|message| message.seq).max().unwrap_or(query.since_seq);
+            ApiResponse::success(
+                format!("获取到 {} 条新消息", messages.len()),
+                serde_json::json!({ "messages": messages, "latest_seq": latest_seq }),
+            )
+        }
+        Err(e) => ApiResponse::error(format!("增量同步会话消息失败: {}", e)),
+    };
+    drop(permit);
+
+    reply(response, StatusCode::OK)
+}
+
 // 转接会话
 pub async fn handle_transfer_session(
     session_id: String,
@@ -220,6 +274,212 @@ pub async fn handle_transfer_session(
     Ok(warp::reply::json(&response))
 }
 
+// 将客户转接给另一位客服
+pub async fn handle_transfer_customer(
+    request: CustomerTransferRequest,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let reply = |success: bool, message: String, status: StatusCode| {
+        let response = ApiResponse {
+            success,
+            message,
+            data: Some(serde_json::json!({
+                "from_kefu_id": request.from_kefu_id,
+                "to_kefu_id": request.to_kefu_id,
+                "customer_id": request.customer_id,
+                "reason": request.reason,
+                "transfer_time": Utc::now()
+            })),
+        };
+        Ok(warp::reply::with_status(warp::reply::json(&response), status))
+    };
+
+    if request.from_kefu_id == request.to_kefu_id {
+        return reply(false, "不能转接给当前客服".to_string(), StatusCode::BAD_REQUEST);
+    }
+
+    match ws_manager
+        .transfer_session(&request.from_kefu_id, &request.to_kefu_id, &request.customer_id)
+        .await
+    {
+        Ok(SessionTransferOutcome::Transferred { history_count }) => reply(
+            true,
+            format!("会话已转接给客服 {}，同步历史消息{}条", request.to_kefu_id, history_count),
+            StatusCode::OK,
+        ),
+        Ok(SessionTransferOutcome::SessionNotFound) => reply(
+            false,
+            format!("客户 {} 当前不由客服 {} 接待", request.customer_id, request.from_kefu_id),
+            StatusCode::NOT_FOUND,
+        ),
+        Ok(SessionTransferOutcome::TargetKefuOffline) => reply(
+            false,
+            format!("目标客服 {} 不在线", request.to_kefu_id),
+            StatusCode::BAD_REQUEST,
+        ),
+        Ok(SessionTransferOutcome::TargetKefuBusy { active_sessions }) => reply(
+            false,
+            format!("目标客服 {} 已达接待上限（{}个会话）", request.to_kefu_id, active_sessions),
+            StatusCode::CONFLICT,
+        ),
+        Err(e) => {
+            tracing::error!("❌ 会话转接失败: {:?}", e);
+            reply(false, format!("会话转接失败: {}", e), StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn ticket_error_reply(message: String, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    let response: ApiResponse<()> = ApiResponse {
+        success: false,
+        message,
+        data: None,
+    };
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
+// 列表中的联系方式只保留首尾几位，完整联系方式需打开单个工单查看
+fn mask_contact(contact: &str) -> String {
+    let chars: Vec<char> = contact.chars().collect();
+    let (head, tail) = if chars.len() > 7 { (3, 4) } else { (1.min(chars.len()), 0) };
+    chars[..head]
+        .iter()
+        .chain(std::iter::repeat_n(&'*', chars.len() - head - tail))
+        .chain(&chars[chars.len() - tail..])
+        .collect()
+}
+
+// 留言工单列表：排队超时的客户留下的联系方式和问题；仅客服或管理员可查看，联系方式脱敏
+pub async fn handle_list_tickets(
+    query: TicketListQuery,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
+) -> Result<impl Reply, Rejection> {
+    let operator = Operator::resolve(authorization.as_deref(), admin_token.as_deref());
+    Ok(list_tickets_reply(operator.as_ref(), query, &storage))
+}
+
+fn list_tickets_reply(
+    operator: Option<&Operator>,
+    query: TicketListQuery,
+    storage: &LocalStorage,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    if operator.is_none() {
+        return ticket_error_reply("需要客服登录或管理令牌".to_string(), StatusCode::UNAUTHORIZED);
+    }
+    match storage.list_tickets(query.status) {
+        Ok(tickets) => {
+            let tickets: Vec<LeaveMessageTicket> = tickets
+                .into_iter()
+                .map(|ticket| LeaveMessageTicket {
+                    contact: mask_contact(&ticket.contact),
+                    ..ticket
+                })
+                .collect();
+            let response = ApiResponse {
+                success: true,
+                message: format!("获取留言工单成功，共{}条", tickets.len()),
+                data: Some(tickets),
+            };
+            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+        }
+        Err(e) => {
+            tracing::error!("❌ 获取留言工单失败: {:?}", e);
+            ticket_error_reply("获取留言工单失败".to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 查看单个工单的完整联系方式，记录查看人
+pub async fn handle_get_ticket(
+    ticket_id: String,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
+) -> Result<impl Reply, Rejection> {
+    let operator = Operator::resolve(authorization.as_deref(), admin_token.as_deref());
+    Ok(get_ticket_reply(operator.as_ref(), &ticket_id, &storage))
+}
+
+fn get_ticket_reply(
+    operator: Option<&Operator>,
+    ticket_id: &str,
+    storage: &LocalStorage,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let Some(operator) = operator else {
+        return ticket_error_reply("需要客服登录或管理令牌".to_string(), StatusCode::UNAUTHORIZED);
+    };
+    match storage.get_ticket(ticket_id) {
+        Ok(Some(ticket)) => {
+            tracing::info!("🔎 {} 查看工单{}的联系方式", operator.name(), ticket_id);
+            let response = ApiResponse {
+                success: true,
+                message: "获取工单成功".to_string(),
+                data: Some(ticket),
+            };
+            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+        }
+        Ok(None) => ticket_error_reply(format!("工单 {} 不存在", ticket_id), StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("❌ 获取工单失败: {:?}", e);
+            ticket_error_reply("获取工单失败".to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 客服处理完留言后关闭工单，处理人取自登录令牌
+pub async fn handle_resolve_ticket(
+    ticket_id: String,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    storage: Arc<LocalStorage>,
+) -> Result<impl Reply, Rejection> {
+    let operator = Operator::resolve(authorization.as_deref(), admin_token.as_deref());
+    Ok(resolve_ticket_reply(operator.as_ref(), &ticket_id, &storage))
+}
+
+fn resolve_ticket_reply(
+    operator: Option<&Operator>,
+    ticket_id: &str,
+    storage: &LocalStorage,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let Some(operator) = operator else {
+        return ticket_error_reply("需要客服登录或管理令牌".to_string(), StatusCode::UNAUTHORIZED);
+    };
+    let (response, status) = match storage.resolve_ticket(ticket_id, operator.name()) {
+        Ok(Some(ticket)) => (
+            ApiResponse {
+                success: true,
+                message: "工单已处理".to_string(),
+                data: Some(ticket),
+            },
+            StatusCode::OK,
+        ),
+        Ok(None) => (
+            ApiResponse {
+                success: false,
+                message: format!("工单 {} 不存在", ticket_id),
+                data: None,
+            },
+            StatusCode::NOT_FOUND,
+        ),
+        Err(e) => {
+            tracing::error!("❌ 处理工单失败: {:?}", e);
+            (
+                ApiResponse {
+                    success: false,
+                    message: "处理工单失败".to_string(),
+                    data: None,
+                },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
 // 结束会话
 pub async fn handle_end_session(
     session_id: String,
@@ -267,3 +527,51 @@ pub async fn handle_session_statistics(
 
     Ok(warp::reply::json(&response))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    async fn reply_parts(reply: impl Reply) -> (StatusCode, serde_json::Value) {
+        let response = reply.into_response();
+        let status = response.status();
+        let body = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_ticket_routes_require_operator_and_mask_contact() {
+        let dir = std::env::temp_dir().join(format!("tickets_test_{}", Uuid::new_v4()));
+        let storage = LocalStorage::new(dir.to_str().unwrap()).unwrap();
+        let ticket = storage.create_ticket("kehu_001", "13800000000", "订单一直没发货").unwrap();
+        let kefu = Operator::Kefu("kefu_001".to_string());
+
+        let (status, _) = reply_parts(list_tickets_reply(None, TicketListQuery { status: None }, &storage)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = reply_parts(get_ticket_reply(None, &ticket.id, &storage)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = reply_parts(resolve_ticket_reply(None, &ticket.id, &storage)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // 列表中联系方式脱敏，打开单个工单才能看到完整联系方式
+        let (status, body) = reply_parts(list_tickets_reply(Some(&kefu), TicketListQuery { status: None }, &storage)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"][0]["contact"], "138****0000");
+        let (_, body) = reply_parts(get_ticket_reply(Some(&kefu), &ticket.id, &storage)).await;
+        assert_eq!(body["data"]["contact"], "13800000000");
+
+        // 处理人取自令牌，不由请求体指定
+        let (status, body) = reply_parts(resolve_ticket_reply(Some(&kefu), &ticket.id, &storage)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["resolved_by"], "kefu_001");
+        let (status, _) = reply_parts(resolve_ticket_reply(Some(&Operator::Admin), "missing", &storage)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        assert_eq!(mask_contact("abc"), "a**");
+        assert_eq!(mask_contact(""), "");
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -3,6 +3,11 @@ use warp::{Reply, Rejection};
 use serde::{Deserialize, Serialize};
 use crate::websocket::WebSocketManager;
 use crate::types::api::ApiResponse;
+use crate::config::AppConfig;
+use crate::auth::jwt_auth::constant_time_eq;
+use crate::connection_events::{DEFAULT_EVENT_LIMIT, MAX_CONNECTION_EVENTS};
+use crate::server::logging::LogLevelController;
+use warp::http::StatusCode;
 use chrono::Utc;
 use uuid::Uuid;
 
@@ -40,6 +45,191 @@ pub struct RedisFlushRequest {
     pub confirm: bool,
 }
 
+// 日志级别调整请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevelRequest {
+    pub level: String, // error, warn, info, debug, trace；模块级别可用 reset 恢复为全局级别
+    pub module: Option<String>, // 为空时调整全局级别
+}
+
+// 持久化系统广播请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SystemBroadcastRequest {
+    pub content: String,
+    pub ttl_secs: Option<u64>, // 有效期（秒），默认一天，最长三十天
+}
+
+// 校验管理令牌，使用定长比较
+pub(crate) fn verify_admin_token(token: Option<&str>) -> bool {
+    match (&AppConfig::get().security.admin_token, token) {
+        (Some(expected), Some(token)) => {
+            !expected.is_empty() && constant_time_eq(expected.as_bytes(), token.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+fn log_level_reply<T: Serialize>(response: ApiResponse<T>, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
+// 获取当前日志级别
+pub async fn handle_get_log_level(
+    admin_token: Option<String>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(log_level_reply(response, StatusCode::FORBIDDEN));
+    }
+
+    let response = ApiResponse {
+        success: true,
+        message: "获取日志级别成功".to_string(),
+        data: LogLevelController::global().map(|controller| controller.current()),
+    };
+    Ok(log_level_reply(response, StatusCode::OK))
+}
+
+// 运行时调整日志级别
+pub async fn handle_set_log_level(
+    admin_token: Option<String>,
+    request: LogLevelRequest,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(log_level_reply(response, StatusCode::FORBIDDEN));
+    }
+
+    let result = match LogLevelController::global() {
+        Some(controller) => match request.module.as_deref() {
+            Some(module) if request.level == "reset" => controller.reset_module(module),
+            module => controller.set_level(module, &request.level),
+        },
+        None => Err(anyhow::anyhow!("日志控制器未初始化")),
+    };
+
+    match result {
+        Ok(state) => {
+            tracing::warn!("🔧 日志级别已调整: module={:?}, level={}", request.module, request.level);
+            let response = ApiResponse {
+                success: true,
+                message: "日志级别已更新".to_string(),
+                data: Some(state),
+            };
+            Ok(log_level_reply(response, StatusCode::OK))
+        }
+        Err(e) => {
+            let response: ApiResponse<()> = ApiResponse {
+                success: false,
+                message: format!("调整日志级别失败: {}", e),
+                data: None,
+            };
+            Ok(log_level_reply(response, StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+// 发布持久化系统广播：在线用户立即收到，离线用户在有效期内上线时补发
+pub async fn handle_publish_system_broadcast(
+    admin_token: Option<String>,
+    request: SystemBroadcastRequest,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(log_level_reply(response, StatusCode::FORBIDDEN));
+    }
+
+    if request.content.trim().is_empty() {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "广播内容不能为空".to_string(),
+            data: None,
+        };
+        return Ok(log_level_reply(response, StatusCode::BAD_REQUEST));
+    }
+
+    let ttl_secs = request
+        .ttl_secs
+        .unwrap_or(crate::system_broadcast::DEFAULT_BROADCAST_TTL_SECS);
+    match ws_manager.publish_system_broadcast(&request.content, ttl_secs).await {
+        Ok((broadcast, delivered)) => {
+            let response = ApiResponse {
+                success: true,
+                message: "系统广播已发布".to_string(),
+                data: Some(serde_json::json!({
+                    "broadcast": broadcast,
+                    "delivered_online": delivered,
+                })),
+            };
+            Ok(log_level_reply(response, StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("❌ 发布系统广播失败: {:?}", e);
+            let response: ApiResponse<()> = ApiResponse {
+                success: false,
+                message: format!("发布系统广播失败: {}", e),
+                data: None,
+            };
+            Ok(log_level_reply(response, StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// 用户最近的连接/断开事件，用于审计登录来源
+pub async fn handle_connection_events(
+    user_id: String,
+    admin_token: Option<String>,
+    query: std::collections::HashMap<String, String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(log_level_reply(response, StatusCode::FORBIDDEN));
+    }
+
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_EVENT_LIMIT)
+        .clamp(1, MAX_CONNECTION_EVENTS as usize);
+    match ws_manager.connection_events(&user_id, limit).await {
+        Ok(events) => {
+            let response = ApiResponse {
+                success: true,
+                message: format!("共 {} 条连接事件", events.len()),
+                data: Some(events),
+            };
+            Ok(log_level_reply(response, StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("❌ 查询连接事件失败: user_id={}, {:?}", user_id, e);
+            let response: ApiResponse<()> = ApiResponse {
+                success: false,
+                message: format!("查询连接事件失败: {}", e),
+                data: None,
+            };
+            Ok(log_level_reply(response, StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
 // 获取系统日志
 pub async fn handle_system_logs(
     query: SystemLogsQuery,
@@ -254,6 +444,35 @@ pub async fn handle_redis_keys(
     Ok(warp::reply::json(&response))
 }
 
+// 扩缩容建议：按持续的在线数与客服利用率给出目标实例数，仅管理端可查
+pub async fn handle_auto_scaling(
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::FORBIDDEN));
+    }
+
+    let response = match ws_manager.get_scaling_recommendation() {
+        Some(recommendation) => ApiResponse {
+            success: true,
+            message: recommendation.reason.clone(),
+            data: Some(recommendation),
+        },
+        None => ApiResponse {
+            success: false,
+            message: "尚未完成负载采样".to_string(),
+            data: None,
+        },
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
+}
+
 // 系统健康检查（增强版）
 pub async fn handle_system_health(
     ws_manager: Arc<WebSocketManager>,
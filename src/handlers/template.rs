@@ -17,10 +17,12 @@ use tracing::{error, info};
 use warp::{reject::Rejection, reply::Reply};
 
 use crate::{
+    handlers::system_extended::verify_admin_token,
     html_template_manager::{
         HtmlTemplateManager, HtmlTemplateCreateRequest, HtmlTemplateUpdateRequest,
         HtmlRenderRequest, HtmlTemplateListRequest,
     },
+    template_sanitizer::{escape_value, InterpolationContext},
     types::{
         api::{ApiResponse, TemplateListQuery},
         auth::AppUserInfo,
@@ -49,9 +51,18 @@ pub async fn handle_create_template(
     template_manager: Arc<HtmlTemplateManager>,
     create_request: HtmlTemplateCreateRequest,
     user_info: AppUserInfo,
+    admin_token: Option<String>,
 ) -> Result<impl Reply, Rejection> {
     info!("📝 用户 {} 创建HTML模板: {}", user_info.id, create_request.name);
 
+    if create_request.trusted && !verify_admin_token(admin_token.as_deref()) {
+        return Ok(warp::reply::json(&ApiResponse {
+            success: false,
+            message: "只有管理员可以创建受信任模板".to_string(),
+            data: None::<()>,
+        }));
+    }
+
     let mut request = create_request;
     request.created_by = user_info.id;
 
@@ -169,10 +180,20 @@ pub async fn handle_update_template(
     template_id: String,
     update_request: HtmlTemplateUpdateRequest,
     user_info: AppUserInfo,
+    admin_token: Option<String>,
 ) -> Result<impl Reply, Rejection> {
     info!("📝 用户 {} 更新HTML模板: {}", user_info.id, template_id);
 
-    match template_manager.update_template(&template_id, update_request).await {
+    let is_admin = verify_admin_token(admin_token.as_deref());
+    if update_request.trusted.is_some() && !is_admin {
+        return Ok(warp::reply::json(&ApiResponse {
+            success: false,
+            message: "只有管理员可以修改模板的受信任标记".to_string(),
+            data: None::<()>,
+        }));
+    }
+
+    match template_manager.update_template(&template_id, update_request, is_admin).await {
         Ok(_template) => {
             info!("📝 HTML模板更新成功: {}", template_id);
             Ok(warp::reply::json(&ApiResponse {
@@ -287,6 +308,7 @@ pub async fn handle_render_template(
                     "rendered_html": render_response.rendered_html,
                     "rendered_css": render_response.rendered_css,
                     "rendered_js": render_response.rendered_js,
+                    "content_security_policy": render_response.content_security_policy,
                 })),
             }))
         }
@@ -511,11 +533,11 @@ pub async fn handle_preview_template(
     info!("📝 预览HTML模板: {}", template_id);
 
     match template_manager.preview_template(&template_id, None).await {
-        Ok(html_content) => {
+        Ok(preview) => {
             let response = warp::reply::with_header(
-                html_content,
-                "content-type",
-                "text/html; charset=utf-8",
+                warp::reply::with_header(preview.html, "content-type", "text/html; charset=utf-8"),
+                "content-security-policy",
+                preview.content_security_policy,
             );
             Ok(Box::new(response))
         }
@@ -532,7 +554,7 @@ pub async fn handle_preview_template(
                 </body>
                 </html>
                 "#,
-                e
+                escape_value(&e.to_string(), InterpolationContext::Html)
             );
             let response = warp::reply::with_header(
                 error_html,
@@ -2,7 +2,10 @@ use std::sync::Arc;
 use warp::{Reply, Rejection};
 use serde::{Deserialize, Serialize};
 use crate::user_manager::{UserManager, User};
-use crate::types::api::ApiResponse;
+use crate::types::api::{ApiResponse, PageRequest, PageResponse};
+use crate::websocket::WebSocketManager;
+use crate::auth::operator::Operator;
+use warp::http::StatusCode;
 use chrono::Utc;
 use uuid::Uuid;
 
@@ -34,16 +37,23 @@ pub struct UpdateStatusRequest {
     pub status: String, // active, inactive, suspended
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OnlineStatusRequest {
+    pub user_ids: Vec<String>,
+}
+
+// 单次批量查询在线状态的用户数上限
+const MAX_ONLINE_STATUS_IDS: usize = 500;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserListQuery {
-    pub page: Option<u32>,
-    pub limit: Option<u32>,
     pub role: Option<String>,
     pub status: Option<String>,
 }
 
 // 获取用户列表
 pub async fn handle_list_users(
+    page: PageRequest,
     user_manager: Arc<UserManager>,
 ) -> Result<impl Reply, Rejection> {
     // 在实际实现中，应该从UserManager获取用户列表
@@ -74,12 +84,7 @@ pub async fn handle_list_users(
     let response = ApiResponse {
         success: true,
         message: "获取用户列表成功".to_string(),
-        data: Some(serde_json::json!({
-            "users": users,
-            "total": users.len(),
-            "page": 1,
-            "limit": 10
-        })),
+        data: Some(PageResponse::paginate(users, &page)),
     };
 
     Ok(warp::reply::json(&response))
@@ -217,6 +222,73 @@ pub async fn handle_update_permissions(
     Ok(warp::reply::json(&response))
 }
 
+// 校验调用方并整理待查询的用户ID：仅客服或管理员可查，去空去重后不超过上限
+fn online_status_user_ids(
+    operator: Option<&Operator>,
+    request: OnlineStatusRequest,
+) -> Result<Vec<String>, (StatusCode, String)> {
+    if operator.is_none() {
+        return Err((StatusCode::UNAUTHORIZED, "需要客服登录或管理令牌".to_string()));
+    }
+
+    let mut user_ids: Vec<String> = request
+        .user_ids
+        .into_iter()
+        .map(|user_id| user_id.trim().to_string())
+        .filter(|user_id| !user_id.is_empty())
+        .collect();
+    user_ids.sort();
+    user_ids.dedup();
+
+    if user_ids.len() > MAX_ONLINE_STATUS_IDS {
+        return Err((StatusCode::BAD_REQUEST, format!("一次最多查询 {} 个用户", MAX_ONLINE_STATUS_IDS)));
+    }
+    Ok(user_ids)
+}
+
+// 批量查询用户在线状态，返回 user_id -> 是否在线
+pub async fn handle_users_online_status(
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    request: OnlineStatusRequest,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let operator = Operator::resolve(authorization.as_deref(), admin_token.as_deref());
+    let user_ids = match online_status_user_ids(operator.as_ref(), request) {
+        Ok(user_ids) => user_ids,
+        Err((status, message)) => {
+            let response: ApiResponse<()> = ApiResponse {
+                success: false,
+                message,
+                data: None,
+            };
+            return Ok(warp::reply::with_status(warp::reply::json(&response), status));
+        }
+    };
+
+    let result = ws_manager.redis.read().await.check_users_online(&user_ids).await;
+    match result {
+        Ok(statuses) => {
+            let online = statuses.values().filter(|online| **online).count();
+            let response = ApiResponse {
+                success: true,
+                message: format!("查询 {} 个用户，在线 {} 个", statuses.len(), online),
+                data: Some(statuses),
+            };
+            Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("❌ 批量查询在线状态失败: {:?}", e);
+            let response: ApiResponse<()> = ApiResponse {
+                success: false,
+                message: format!("查询在线状态失败: {}", e),
+                data: None,
+            };
+            Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
 // 更新用户状态
 pub async fn handle_update_user_status(
     user_id: String,
@@ -237,3 +309,28 @@ pub async fn handle_update_user_status(
 
     Ok(warp::reply::json(&response))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(user_ids: &[&str]) -> OnlineStatusRequest {
+        OnlineStatusRequest {
+            user_ids: user_ids.iter().map(|user_id| user_id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_online_status_requires_operator() {
+        let (status, _) = online_status_user_ids(None, request(&["kehu_001"])).unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let kefu = Operator::Kefu("kefu_001".to_string());
+        let user_ids = online_status_user_ids(Some(&kefu), request(&["kehu_002", " kehu_001 ", "", "kehu_002"])).unwrap();
+        assert_eq!(user_ids, vec!["kehu_001".to_string(), "kehu_002".to_string()]);
+
+        let too_many: Vec<String> = (0..=MAX_ONLINE_STATUS_IDS).map(|i| format!("kehu_{}", i)).collect();
+        let (status, _) = online_status_user_ids(Some(&Operator::Admin), OnlineStatusRequest { user_ids: too_many }).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
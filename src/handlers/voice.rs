@@ -14,14 +14,16 @@ use anyhow::Result;
 use serde_json::json;
 use std::sync::Arc;
 use tracing::{error, info};
-use warp::{reject::Rejection, reply::Reply};
+use warp::{http::StatusCode, reject::Rejection, reply::Reply};
 
 use crate::{
+    auth::operator::Operator,
     types::{
         api::ApiResponse,
         auth::AppUserInfo,
     },
     voice_message::VoiceMessageManager,
+    websocket::{VoiceTranscriptionRequest, WebSocketManager},
 };
 
 /// 获取语音消息信息处理函数
@@ -402,4 +404,99 @@ pub async fn handle_voice_delete(
         "success": true,
         "message": "语音文件删除功能待实现"
     })))
-} 
\ No newline at end of file
+}
+
+/// 每位调用方每分钟最多提交的按需转写次数
+const TRANSCRIBE_REQUESTS_PER_MINUTE: i64 = 10;
+
+/// 按需转写语音
+///
+/// 未开启自动转写时，由客服对指定语音触发转写；转写完成后回填语音元数据并推送给会话双方。
+/// 需要客服令牌（只能转写本人参与的语音）或管理令牌，并按调用方限制提交频率
+pub async fn handle_transcribe_voice(
+    voice_id: String,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    let error_reply = |message: &str, status: StatusCode| -> Result<warp::reply::WithStatus<warp::reply::Json>, Rejection> {
+        let response: ApiResponse<serde_json::Value> = ApiResponse {
+            success: false,
+            message: message.to_string(),
+            data: None,
+        };
+        Ok(warp::reply::with_status(warp::reply::json(&response), status))
+    };
+    let Some(operator) = Operator::resolve(authorization.as_deref(), admin_token.as_deref()) else {
+        return error_reply("需要客服登录或管理令牌", StatusCode::UNAUTHORIZED);
+    };
+    info!("🎤 按需转写语音: voice_id={}, operator={}", voice_id, operator.name());
+
+    let rate_key = format!("rate:voice_transcribe:{}", operator.name());
+    match ws_manager.redis.read().await.count_in_window(&rate_key, 60).await {
+        Ok(count) if count > TRANSCRIBE_REQUESTS_PER_MINUTE => {
+            return error_reply("转写请求过于频繁，请稍后再试", StatusCode::TOO_MANY_REQUESTS);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("转写限流计数失败: operator={}, error: {}", operator.name(), e);
+            return error_reply("转写服务暂不可用", StatusCode::SERVICE_UNAVAILABLE);
+        }
+    }
+
+    let (response, status) = match ws_manager.request_voice_transcription(&voice_id, operator.kefu_id()).await {
+        Ok(VoiceTranscriptionRequest::Submitted { task_id }) => (
+            ApiResponse {
+                success: true,
+                message: "转写任务已提交".to_string(),
+                data: Some(json!({ "voice_id": voice_id, "task_id": task_id, "status": "submitted" })),
+            },
+            StatusCode::OK,
+        ),
+        Ok(VoiceTranscriptionRequest::AlreadyTranscribed(text)) => (
+            ApiResponse {
+                success: true,
+                message: "语音已有转写文本".to_string(),
+                data: Some(json!({ "voice_id": voice_id, "transcription": text, "status": "completed" })),
+            },
+            StatusCode::OK,
+        ),
+        Ok(VoiceTranscriptionRequest::Forbidden) => (
+            ApiResponse {
+                success: false,
+                message: "只能转写本人参与会话中的语音".to_string(),
+                data: None,
+            },
+            StatusCode::FORBIDDEN,
+        ),
+        Ok(VoiceTranscriptionRequest::VoiceNotFound) => (
+            ApiResponse {
+                success: false,
+                message: format!("语音消息 {} 不存在", voice_id),
+                data: None,
+            },
+            StatusCode::NOT_FOUND,
+        ),
+        Ok(VoiceTranscriptionRequest::Unavailable) => (
+            ApiResponse {
+                success: false,
+                message: "语音转写服务未启用".to_string(),
+                data: None,
+            },
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        Err(e) => {
+            error!("提交语音转写失败: voice_id={}, error: {}", voice_id, e);
+            (
+                ApiResponse {
+                    success: false,
+                    message: format!("提交语音转写失败: {}", e),
+                    data: None,
+                },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(warp::reply::with_status(warp::reply::json(&response), status))
+}
@@ -17,6 +17,7 @@ use tracing::info;
 use warp::{reject::Rejection, reply::Reply};
 
 use crate::{
+    close_code::CloseReason,
     types::{
         api::ApiResponse,
         auth::AppUserInfo,
@@ -83,7 +84,7 @@ pub async fn handle_disconnect_user(
     // 这里可以添加权限检查，确保只有管理员可以执行此操作
     
     // 调用WebSocketManager的disconnect_user方法
-    let disconnected = ws_manager.disconnect_user(&user_id).await;
+    let disconnected = ws_manager.disconnect_user(&user_id, CloseReason::Kicked).await;
 
     if disconnected {
         Ok(warp::reply::json(&ApiResponse {
@@ -1,9 +1,12 @@
-use crate::config::StorageConfig;
+use crate::cache::MemoryCache;
+use crate::config::{CacheConfig, StorageConfig};
+use crate::template_sanitizer::{content_security_policy, escape_value, sanitize_css, sanitize_html, InterpolationContext};
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use tracing::{error, info, warn};
 use utoipa::ToSchema;
@@ -17,6 +20,52 @@ pub struct HtmlTemplateManager {
     templates: std::sync::Arc<tokio::sync::RwLock<HashMap<String, HtmlTemplate>>>,
     #[allow(dead_code)]
     callbacks: std::sync::Arc<tokio::sync::RwLock<HashMap<String, Vec<HtmlCallback>>>>,
+    validation_regexes: ValidationRegexCache,
+    render_cache: Option<RenderCache>,
+}
+
+/// 模板渲染结果缓存，键见 render_cache_key
+struct RenderCache {
+    entries: MemoryCache<RenderedTemplate>,
+    ttl: std::time::Duration,
+}
+
+/// 模板预览页面，展示时须带上对应的内容安全策略
+pub struct TemplatePreview {
+    pub html: String,
+    pub content_security_policy: &'static str,
+}
+
+#[derive(Debug, Clone)]
+struct RenderedTemplate {
+    html: String,
+    css: Option<String>,
+    js: Option<String>,
+}
+
+/// 渲染缓存键：模板ID + 模板版本 + 变量摘要；变量按名称排序后再序列化，相同变量总是得到相同的键
+fn render_cache_key(template_id: &str, version: u32, variables: &HashMap<String, serde_json::Value>) -> String {
+    let sorted: BTreeMap<&String, &serde_json::Value> = variables.iter().collect();
+    let serialized = serde_json::to_vec(&sorted).unwrap_or_default();
+    format!("{}:{}:{:x}", template_id, version, Sha256::digest(&serialized))
+}
+
+/// 模板变量校验正则的编译缓存，同一规则只编译一次
+#[derive(Default)]
+struct ValidationRegexCache {
+    compiled: std::sync::Mutex<HashMap<String, Regex>>,
+}
+
+impl ValidationRegexCache {
+    fn get(&self, pattern: &str) -> Result<Regex> {
+        let mut compiled = self.compiled.lock().map_err(|_| anyhow!("校验规则缓存不可用"))?;
+        if let Some(regex) = compiled.get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = Regex::new(pattern)?;
+        compiled.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    }
 }
 
 /// HTML模板结构
@@ -38,6 +87,9 @@ pub struct HtmlTemplate {
     pub version: u32,
     pub tags: Vec<String>,
     pub usage_count: u64,
+    /// 管理员编写的受信任模板，渲染时不清洗、不转义变量；非受信任模板不输出脚本
+    #[serde(default)]
+    pub trusted: bool,
 }
 
 /// 模板变量定义
@@ -82,6 +134,8 @@ pub struct HtmlRenderResponse {
     pub rendered_html: String,
     pub rendered_css: Option<String>,
     pub rendered_js: Option<String>,
+    /// 展示渲染结果的页面应使用的内容安全策略
+    pub content_security_policy: String,
     pub success: bool,
     pub message: String,
 }
@@ -98,6 +152,9 @@ pub struct HtmlTemplateCreateRequest {
     pub javascript: Option<String>,
     pub created_by: String,
     pub tags: Vec<String>,
+    /// 仅管理员可以创建受信任模板
+    #[serde(default)]
+    pub trusted: bool,
 }
 
 /// HTML模板更新请求
@@ -112,6 +169,7 @@ pub struct HtmlTemplateUpdateRequest {
     pub javascript: Option<String>,
     pub is_active: Option<bool>,
     pub tags: Option<Vec<String>>,
+    pub trusted: Option<bool>,
 }
 
 /// HTML模板列表请求
@@ -193,6 +251,8 @@ impl HtmlTemplateManager {
             base_path,
             templates: std::sync::Arc::new(tokio::sync::RwLock::new(HashMap::new())),
             callbacks: std::sync::Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            validation_regexes: ValidationRegexCache::default(),
+            render_cache: None,
         };
 
         // 加载现有模板
@@ -201,6 +261,22 @@ impl HtmlTemplateManager {
         Ok(manager)
     }
 
+    /// 启用渲染结果缓存
+    pub fn with_render_cache(mut self, config: &CacheConfig) -> Self {
+        self.render_cache = config.enabled.then(|| RenderCache {
+            entries: MemoryCache::new(config.max_size.max(1)),
+            ttl: std::time::Duration::from_secs(config.ttl),
+        });
+        self
+    }
+
+    /// 模板更新或删除后丢弃它的所有渲染结果
+    async fn invalidate_render_cache(&self, template_id: &str) {
+        if let Some(cache) = &self.render_cache {
+            cache.entries.delete_prefix(&format!("{}:", template_id)).await;
+        }
+    }
+
     /// 创建HTML模板
     pub async fn create_template(
         &self,
@@ -239,6 +315,7 @@ impl HtmlTemplateManager {
             version: 1,
             tags: request.tags,
             usage_count: 0,
+            trusted: request.trusted,
         };
 
         // 保存模板
@@ -261,17 +338,30 @@ impl HtmlTemplateManager {
     }
 
     /// 更新HTML模板
+    ///
+    /// 受信任标记只有管理员可以修改；非管理员改动受信任模板的内容、样式或脚本时，
+    /// 模板降为不受信任，渲染时重新净化，避免借已受信任的模板注入脚本
     pub async fn update_template(
         &self,
         template_id: &str,
         request: HtmlTemplateUpdateRequest,
+        is_admin: bool,
     ) -> Result<HtmlTemplate> {
         info!("更新HTML模板: {}", template_id);
 
+        if request.trusted.is_some() && !is_admin {
+            return Err(anyhow!("只有管理员可以修改模板的受信任标记"));
+        }
+
         let mut template = self
             .get_template(template_id)
             .await?
             .ok_or_else(|| anyhow!("模板不存在: {}", template_id))?;
+        let edits_markup = request.content.is_some() || request.css.is_some() || request.javascript.is_some();
+        if template.trusted && edits_markup && !is_admin {
+            warn!("非管理员修改了受信任模板，取消受信任标记: {}", template_id);
+            template.trusted = false;
+        }
 
         // 更新字段
         if let Some(name) = request.name {
@@ -304,6 +394,9 @@ impl HtmlTemplateManager {
         if let Some(tags) = request.tags {
             template.tags = tags;
         }
+        if let Some(trusted) = request.trusted {
+            template.trusted = trusted;
+        }
 
         template.updated_at = Utc::now();
         template.version += 1;
@@ -316,6 +409,7 @@ impl HtmlTemplateManager {
             let mut templates = self.templates.write().await;
             templates.insert(template.id.clone(), template.clone());
         }
+        self.invalidate_render_cache(template_id).await;
 
         info!("HTML模板更新成功: {}", template.id);
         Ok(template)
@@ -332,6 +426,7 @@ impl HtmlTemplateManager {
                 return Ok(false);
             }
         }
+        self.invalidate_render_cache(template_id).await;
 
         // 删除文件
         let template_path = self.get_template_path(template_id);
@@ -462,20 +557,21 @@ impl HtmlTemplateManager {
             return Err(anyhow!("模板已禁用: {}", request.template_id));
         }
 
-        // 验证必需变量
-        self.validate_template_variables(&template.variables, &request.variables)?;
-
-        // 渲染HTML内容
-        let rendered_html = self.render_content(&template.content, &request.variables)?;
-        let rendered_css = if let Some(ref css) = template.css {
-            Some(self.render_content(css, &request.variables)?)
-        } else {
-            None
+        // 同一版本、相同变量的渲染结果直接复用（变量在首次渲染时已通过校验）
+        let cache_key = render_cache_key(&template.id, template.version, &request.variables);
+        let cached = match &self.render_cache {
+            Some(cache) => cache.entries.get(&cache_key).await,
+            None => None,
         };
-        let rendered_js = if let Some(ref js) = template.javascript {
-            Some(self.render_content(js, &request.variables)?)
-        } else {
-            None
+        let rendered = match cached {
+            Some(rendered) => rendered,
+            None => {
+                let rendered = self.render_fresh(&template, &request.variables)?;
+                if let Some(cache) = &self.render_cache {
+                    cache.entries.set(cache_key, rendered.clone(), Some(cache.ttl)).await;
+                }
+                rendered
+            }
         };
 
         // 生成消息ID
@@ -489,20 +585,59 @@ impl HtmlTemplateManager {
         Ok(HtmlRenderResponse {
             message_id,
             template_id: request.template_id,
-            rendered_html,
-            rendered_css,
-            rendered_js,
+            rendered_html: rendered.html,
+            rendered_css: rendered.css,
+            rendered_js: rendered.js,
+            content_security_policy: content_security_policy(template.trusted).to_string(),
             success: true,
             message: "模板渲染成功".to_string(),
         })
     }
 
+    fn render_fresh(
+        &self,
+        template: &HtmlTemplate,
+        variables: &HashMap<String, serde_json::Value>,
+    ) -> Result<RenderedTemplate> {
+        // 验证必需变量
+        self.validate_template_variables(&template.variables, variables)?;
+        self.render_sources(template, variables)
+    }
+
+    /// 插入变量：受信任模板原样插入；非受信任模板按位置转义变量后再按白名单清洗 HTML，
+    /// 样式转义 '<'，脚本不输出
+    fn render_sources(
+        &self,
+        template: &HtmlTemplate,
+        variables: &HashMap<String, serde_json::Value>,
+    ) -> Result<RenderedTemplate> {
+        let html = self.render_content(&template.content, variables, template.trusted, InterpolationContext::Html)?;
+        let css = template
+            .css
+            .as_deref()
+            .map(|css| self.render_content(css, variables, template.trusted, InterpolationContext::Css))
+            .transpose()?;
+        if !template.trusted {
+            return Ok(RenderedTemplate {
+                html: sanitize_html(&html),
+                css: css.as_deref().map(sanitize_css),
+                js: None,
+            });
+        }
+        let js = template
+            .javascript
+            .as_deref()
+            .map(|js| self.render_content(js, variables, true, InterpolationContext::Script))
+            .transpose()?;
+        Ok(RenderedTemplate { html, css, js })
+    }
+
     /// 预览HTML模板
     pub async fn preview_template(
         &self,
         template_id: &str,
         variables: Option<HashMap<String, serde_json::Value>>,
-    ) -> Result<String> {
+    ) -> Result<TemplatePreview> {
         let template = self
             .get_template(template_id)
             .await?
@@ -541,21 +676,20 @@ impl HtmlTemplateManager {
             default_vars
         };
 
-        // 渲染预览HTML
-        let mut preview_html = self.render_content(&template.content, &render_variables)?;
-
-        // 添加CSS和JavaScript
-        if let Some(ref css) = template.css {
-            let rendered_css = self.render_content(css, &render_variables)?;
-            preview_html = format!("<style>{}</style>\n{}", rendered_css, preview_html);
+        // 渲染预览HTML，添加CSS和JavaScript
+        let rendered = self.render_sources(&template, &render_variables)?;
+        let mut preview_html = rendered.html;
+        if let Some(css) = rendered.css {
+            preview_html = format!("<style>{}</style>\n{}", css, preview_html);
         }
-
-        if let Some(ref js) = template.javascript {
-            let rendered_js = self.render_content(js, &render_variables)?;
-            preview_html = format!("{}\n<script>{}</script>", preview_html, rendered_js);
+        if let Some(js) = rendered.js {
+            preview_html = format!("{}\n<script>{}</script>", preview_html, js);
         }
 
-        Ok(preview_html)
+        Ok(TemplatePreview {
+            html: preview_html,
+            content_security_policy: content_security_policy(template.trusted),
+        })
     }
 
     /// 处理HTML回调
@@ -726,68 +860,32 @@ impl HtmlTemplateManager {
         template_vars: &[TemplateVariable],
         provided_vars: &HashMap<String, serde_json::Value>,
     ) -> Result<()> {
-        for var in template_vars {
-            if var.required && !provided_vars.contains_key(&var.name) {
-                return Err(anyhow!("缺少必需变量: {}", var.name));
-            }
-
-            if let Some(value) = provided_vars.get(&var.name) {
-                // 验证变量类型
-                match var.var_type {
-                    VariableType::String => {
-                        if !value.is_string() && !value.is_null() {
-                            return Err(anyhow!("变量 {} 应为字符串类型", var.name));
-                        }
-                    }
-                    VariableType::Number => {
-                        if !value.is_number() && !value.is_null() {
-                            return Err(anyhow!("变量 {} 应为数字类型", var.name));
-                        }
-                    }
-                    VariableType::Boolean => {
-                        if !value.is_boolean() && !value.is_null() {
-                            return Err(anyhow!("变量 {} 应为布尔类型", var.name));
-                        }
-                    }
-                    VariableType::Array => {
-                        if !value.is_array() && !value.is_null() {
-                            return Err(anyhow!("变量 {} 应为数组类型", var.name));
-                        }
-                    }
-                    _ => {} // 其他类型暂不严格验证
-                }
-
-                // 正则验证
-                if let Some(ref pattern) = var.validation {
-                    if let Some(str_value) = value.as_str() {
-                        let regex = Regex::new(pattern)?;
-                        if !regex.is_match(str_value) {
-                            return Err(anyhow!("变量 {} 格式不正确", var.name));
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(())
+        validate_variables(template_vars, provided_vars, &self.validation_regexes)
     }
 
     fn render_content(
         &self,
         content: &str,
         variables: &HashMap<String, serde_json::Value>,
+        trusted: bool,
+        context: InterpolationContext,
     ) -> Result<String> {
         let var_regex = Regex::new(r"\{\{(\w+)\}\}").unwrap();
 
         let result = var_regex.replace_all(content, |caps: &regex::Captures| {
             let var_name = &caps[1];
             if let Some(value) = variables.get(var_name) {
-                match value {
+                let raw = match value {
                     serde_json::Value::String(s) => s.clone(),
                     serde_json::Value::Number(n) => n.to_string(),
                     serde_json::Value::Bool(b) => b.to_string(),
                     serde_json::Value::Null => "".to_string(),
                     _ => value.to_string(),
+                };
+                if trusted {
+                    raw
+                } else {
+                    escape_value(&raw, context)
                 }
             } else {
                 format!("{{{{{}}}}}", var_name) // 保留未找到的变量
@@ -811,3 +909,236 @@ impl HtmlTemplateManager {
         Ok(())
     }
 }
+
+/// 校验必需变量、变量类型和声明的正则规则，汇总所有不通过的变量后一并返回
+fn validate_variables(
+    template_vars: &[TemplateVariable],
+    provided_vars: &HashMap<String, serde_json::Value>,
+    regexes: &ValidationRegexCache,
+) -> Result<()> {
+    let mut failures = Vec::new();
+    for var in template_vars {
+        let Some(value) = provided_vars.get(&var.name) else {
+            if var.required {
+                failures.push(format!("{}: 缺少必需变量", var.name));
+            }
+            continue;
+        };
+
+        // 验证变量类型
+        let type_error = match var.var_type {
+            VariableType::String if !value.is_string() && !value.is_null() => Some("应为字符串类型"),
+            VariableType::Number if !value.is_number() && !value.is_null() => Some("应为数字类型"),
+            VariableType::Boolean if !value.is_boolean() && !value.is_null() => Some("应为布尔类型"),
+            VariableType::Array if !value.is_array() && !value.is_null() => Some("应为数组类型"),
+            _ => None, // 其他类型暂不严格验证
+        };
+        if let Some(type_error) = type_error {
+            failures.push(format!("{}: {}", var.name, type_error));
+            continue;
+        }
+
+        // 正则验证，只作用于字符串值
+        if let (Some(pattern), Some(str_value)) = (&var.validation, value.as_str()) {
+            match regexes.get(pattern) {
+                Ok(regex) if !regex.is_match(str_value) => {
+                    failures.push(format!("{}: 不符合格式 {}", var.name, pattern));
+                }
+                Ok(_) => {}
+                Err(e) => failures.push(format!("{}: 校验规则无效 ({})", var.name, e)),
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("模板变量校验失败: {}", failures.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL_PATTERN: &str = r"^[\w.+-]+@[\w-]+(\.[\w-]+)+$";
+    const URL_PATTERN: &str = r"^https?://[^\s/$.?#][^\s]*$";
+
+    fn variable(name: &str, var_type: VariableType, pattern: &str) -> TemplateVariable {
+        TemplateVariable {
+            name: name.to_string(),
+            var_type,
+            default_value: None,
+            required: true,
+            description: None,
+            validation: Some(pattern.to_string()),
+        }
+    }
+
+    fn values(email: &str, url: &str) -> HashMap<String, serde_json::Value> {
+        HashMap::from([
+            ("email".to_string(), serde_json::json!(email)),
+            ("link".to_string(), serde_json::json!(url)),
+        ])
+    }
+
+    #[test]
+    fn test_email_and_url_patterns() {
+        let vars = [
+            variable("email", VariableType::Email, EMAIL_PATTERN),
+            variable("link", VariableType::Url, URL_PATTERN),
+        ];
+        let cache = ValidationRegexCache::default();
+
+        assert!(validate_variables(&vars, &values("user.name+tag@example.com", "https://example.com/a?b=1"), &cache).is_ok());
+
+        // 两个变量都不通过时错误信息中全部列出
+        let error = validate_variables(&vars, &values("not-an-email", "javascript:alert(1)"), &cache)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("email"));
+        assert!(error.contains("link"));
+
+        let error = validate_variables(&vars, &values("user@example.com", "ftp://example.com"), &cache)
+            .unwrap_err()
+            .to_string();
+        assert!(!error.contains("email:"));
+        assert!(error.contains("link:"));
+
+        // 每个规则只编译一次
+        assert_eq!(cache.compiled.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_missing_and_invalid_rules_reported() {
+        let vars = [
+            variable("email", VariableType::Email, EMAIL_PATTERN),
+            variable("code", VariableType::String, "(unclosed"),
+        ];
+        let provided = HashMap::from([("code".to_string(), serde_json::json!("A1"))]);
+        let error = validate_variables(&vars, &provided, &ValidationRegexCache::default())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("email: 缺少必需变量"));
+        assert!(error.contains("code: 校验规则无效"));
+    }
+
+    fn storage_config(dir: &std::path::Path) -> StorageConfig {
+        StorageConfig {
+            data_dir: dir.to_string_lossy().to_string(),
+            blobs_dir: dir.join("blobs").to_string_lossy().to_string(),
+            snapshot_interval: 300,
+            max_snapshot_size: 1024,
+            export: Default::default(),
+            encryption: Default::default(),
+            backend: Default::default(),
+            s3: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_edit_revokes_trusted_template() {
+        let dir = std::env::temp_dir().join(format!("html_trusted_edit_{}", Uuid::new_v4()));
+        let manager = HtmlTemplateManager::new(storage_config(&dir)).await.unwrap();
+        let template = manager
+            .create_template(HtmlTemplateCreateRequest {
+                name: "活动页".to_string(),
+                description: None,
+                category: "test".to_string(),
+                content: "<p>活动</p>".to_string(),
+                variables: Vec::new(),
+                css: None,
+                javascript: Some("console.log(1)".to_string()),
+                created_by: "admin".to_string(),
+                tags: Vec::new(),
+                trusted: true,
+            })
+            .await
+            .unwrap();
+        let render = || HtmlRenderRequest {
+            template_id: template.id.clone(),
+            variables: HashMap::new(),
+            user_id: "kefu_1".to_string(),
+            callback_url: None,
+            callback_data: None,
+        };
+
+        // 非管理员不能直接改受信任标记
+        let set_trusted: HtmlTemplateUpdateRequest =
+            serde_json::from_value(serde_json::json!({ "trusted": true })).unwrap();
+        assert!(manager.update_template(&template.id, set_trusted, false).await.is_err());
+
+        // 非管理员改内容且不带 trusted 字段时模板降为不受信任，注入的脚本被净化
+        let edit: HtmlTemplateUpdateRequest = serde_json::from_value(serde_json::json!({
+            "content": "<p>活动</p><script>alert(document.cookie)</script>"
+        }))
+        .unwrap();
+        let updated = manager.update_template(&template.id, edit, false).await.unwrap();
+        assert!(!updated.trusted);
+        let rendered = manager.render_template(render()).await.unwrap();
+        assert!(!rendered.rendered_html.contains("<script"));
+        assert_eq!(rendered.content_security_policy, content_security_policy(false));
+
+        // 管理员的修改保留受信任标记
+        let restore: HtmlTemplateUpdateRequest =
+            serde_json::from_value(serde_json::json!({ "content": "<p>活动</p>", "trusted": true })).unwrap();
+        assert!(manager.update_template(&template.id, restore, true).await.unwrap().trusted);
+        let edit: HtmlTemplateUpdateRequest =
+            serde_json::from_value(serde_json::json!({ "css": "p { color: red; }" })).unwrap();
+        assert!(manager.update_template(&template.id, edit, true).await.unwrap().trusted);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_render_cache_hits_and_invalidation() {
+        let dir = std::env::temp_dir().join(format!("html_render_cache_{}", Uuid::new_v4()));
+        let cache_config = CacheConfig { enabled: true, max_size: 10, ttl: 60 };
+        let manager = HtmlTemplateManager::new(storage_config(&dir)).await.unwrap().with_render_cache(&cache_config);
+
+        let mut name = variable("name", VariableType::String, ".+");
+        name.validation = None;
+        let template = manager
+            .create_template(HtmlTemplateCreateRequest {
+                name: "问候".to_string(),
+                description: None,
+                category: "test".to_string(),
+                content: "<p>你好，{{name}}</p>".to_string(),
+                variables: vec![name],
+                css: None,
+                javascript: None,
+                created_by: "admin".to_string(),
+                tags: Vec::new(),
+                trusted: false,
+            })
+            .await
+            .unwrap();
+        let render = |value: &str| HtmlRenderRequest {
+            template_id: template.id.clone(),
+            variables: HashMap::from([("name".to_string(), serde_json::json!(value))]),
+            user_id: "kefu_1".to_string(),
+            callback_url: None,
+            callback_data: None,
+        };
+
+        let first = manager.render_template(render("张三")).await.unwrap();
+        let second = manager.render_template(render("张三")).await.unwrap();
+        assert_eq!(first.rendered_html, second.rendered_html);
+        assert!(!first.content_security_policy.contains("script-src"));
+        let cache = manager.render_cache.as_ref().unwrap();
+        assert_eq!(cache.entries.size().await, 1);
+        // 命中缓存时仍然计入使用次数
+        assert_eq!(manager.get_template(&template.id).await.unwrap().unwrap().usage_count, 2);
+
+        let update: HtmlTemplateUpdateRequest =
+            serde_json::from_value(serde_json::json!({ "content": "<p>您好，{{name}}</p>" })).unwrap();
+        manager.update_template(&template.id, update, false).await.unwrap();
+        assert_eq!(cache.entries.size().await, 0);
+        let updated = manager.render_template(render("张三")).await.unwrap();
+        assert_eq!(updated.rendered_html, "<p>您好，张三</p>");
+
+        manager.delete_template(&template.id).await.unwrap();
+        assert_eq!(cache.entries.size().await, 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
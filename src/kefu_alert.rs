@@ -0,0 +1,83 @@
+use crate::redis_client::MAX_KEFU_SESSIONS;
+use serde::Serialize;
+
+/// 从在线客服中挑选负载最低的 count 个接收新客户提醒，已满负载的客服不参与。
+/// loads 为 (客服ID, 当前接待数)，负载相同时按客服ID排序，保证结果稳定
+pub fn select_idlest_kefu(mut loads: Vec<(String, usize)>, count: usize) -> Vec<String> {
+    loads.retain(|(_, load)| *load < MAX_KEFU_SESSIONS);
+    loads.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    loads.into_iter().take(count.max(1)).map(|(kefu_id, _)| kefu_id).collect()
+}
+
+/// 在线客服整体负载：总接待容量与已用容量，供主管手动调配
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct WorkloadSummary {
+    pub online_kefu: usize,
+    /// 已满负载的客服数
+    pub busy_kefu: usize,
+    pub total_capacity: usize,
+    pub used_capacity: usize,
+    pub available_capacity: usize,
+    /// 已用容量占总容量的百分比
+    pub utilization_rate: f64,
+}
+
+/// 按各在线客服的当前接待数汇总整体负载
+pub fn summarize_workloads(active_sessions: &[usize]) -> WorkloadSummary {
+    let total_capacity = active_sessions.len() * MAX_KEFU_SESSIONS;
+    let used_capacity: usize = active_sessions.iter().sum();
+    // 超出上限的客服不会抵消其他客服的空闲容量
+    let available_capacity = active_sessions
+        .iter()
+        .map(|sessions| MAX_KEFU_SESSIONS.saturating_sub(*sessions))
+        .sum();
+    WorkloadSummary {
+        online_kefu: active_sessions.len(),
+        busy_kefu: active_sessions.iter().filter(|sessions| **sessions >= MAX_KEFU_SESSIONS).count(),
+        total_capacity,
+        used_capacity,
+        available_capacity,
+        utilization_rate: if total_capacity == 0 {
+            0.0
+        } else {
+            used_capacity as f64 / total_capacity as f64 * 100.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loads(entries: &[(&str, usize)]) -> Vec<(String, usize)> {
+        entries.iter().map(|(id, load)| (id.to_string(), *load)).collect()
+    }
+
+    #[test]
+    fn test_alert_only_goes_to_idlest_kefu() {
+        let online = loads(&[("kefu_a", 3), ("kefu_b", 0), ("kefu_c", 1), ("kefu_d", MAX_KEFU_SESSIONS), ("kefu_e", 0)]);
+
+        assert_eq!(select_idlest_kefu(online.clone(), 2), vec!["kefu_b", "kefu_e"]);
+        assert_eq!(select_idlest_kefu(online.clone(), 3), vec!["kefu_b", "kefu_e", "kefu_c"]);
+        // 满负载的客服即使数量不够也不提醒
+        assert_eq!(select_idlest_kefu(online.clone(), 10).len(), 4);
+        // N 配置为 0 时至少提醒一位客服
+        assert_eq!(select_idlest_kefu(online, 0), vec!["kefu_b"]);
+
+        assert!(select_idlest_kefu(loads(&[("kefu_a", MAX_KEFU_SESSIONS)]), 3).is_empty());
+    }
+
+    #[test]
+    fn test_fleet_workload_summary() {
+        let summary = summarize_workloads(&[0, 2, MAX_KEFU_SESSIONS, MAX_KEFU_SESSIONS + 1]);
+        assert_eq!(summary.online_kefu, 4);
+        assert_eq!(summary.busy_kefu, 2);
+        assert_eq!(summary.total_capacity, 4 * MAX_KEFU_SESSIONS);
+        assert_eq!(summary.used_capacity, 2 * MAX_KEFU_SESSIONS + 3);
+        assert_eq!(summary.available_capacity, 2 * MAX_KEFU_SESSIONS - 2);
+
+        let idle = summarize_workloads(&[]);
+        assert_eq!(idle.total_capacity, 0);
+        assert_eq!(idle.utilization_rate, 0.0);
+    }
+}
@@ -8,16 +8,33 @@
 #![allow(clippy::assertions_on_constants)]
 
 // 核心模块
+mod auto_tag;
+mod canned_response;
+mod close_code;
 mod compression;
 mod config;
+mod connection_events;
+mod content_filter;
+mod feature_flags;
 mod file_manager;
 mod file_manager_ext;  // 新增：文件管理器扩展
+mod greeting;
 mod html_template_manager;
+mod kefu_alert;
 mod message;
 mod message_queue;
+mod message_reorder;
+mod message_version;
+mod reaction;
 mod redis_client;
 mod redis_pool;
+mod satisfaction;
 mod storage;
+mod storage_backend;
+mod storage_crypto;
+mod storage_wal;
+mod system_broadcast;
+mod template_sanitizer;
 mod websocket;
 mod user_manager;
 mod voice_message;
@@ -46,12 +63,13 @@ mod cache;
 use anyhow::Result;
 use tracing::info;
 
+use server::logging::LogLevelController;
 use server::{initialize_system_components, start_background_tasks, start_server};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // 初始化日志
-    tracing_subscriber::fmt::init();
+    // 初始化日志：初始级别读取 RUST_LOG，默认 info，可通过 /admin/log-level 运行时调整
+    LogLevelController::init("info")?;
     info!("启动企业级客服系统...");
 
     // 初始化系统组件
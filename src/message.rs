@@ -16,6 +16,18 @@ pub enum Message {
         filename: Option<String>,
         timestamp: DateTime<Utc>,
         url: Option<String>,
+        /// 图片附件的缩略图地址
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thumbnail_url: Option<String>,
+        // 超出服务端重排序窗口后到达的迟到消息
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        out_of_order: Option<bool>,
+        /// 会话内消息序号，客户端据此增量同步
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// 客服引用的快捷回复ID，服务端展开为快捷回复内容后再存储和转发
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        canned_response_id: Option<String>,
     },
     // 系统消息
     #[serde(rename = "System")]
@@ -39,12 +51,33 @@ pub enum Message {
     },
     // 历史消息
     #[serde(rename = "History")]
-    History { messages: Vec<ChatMessage> },
+    History {
+        messages: Vec<ChatMessage>,
+        /// 是否还有更早的历史消息，客户端可用最早一条消息的时间继续请求
+        #[serde(default)]
+        has_more: bool,
+        /// 各消息的表情回应数量，键为消息ID，没有回应的消息不出现
+        #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+        reactions: std::collections::HashMap<String, std::collections::HashMap<String, usize>>,
+        /// 会话已分配的最大消息序号；重连后序号不大于它的实时消息已包含在历史中，可按 seq 去重
+        #[serde(default)]
+        latest_seq: u64,
+    },
     // 历史消息请求
     #[serde(rename = "HistoryRequest")]
     HistoryRequest {
+        /// 客服请求时指定客户；客户请求时忽略，按连接身份读取本人所在的会话
         customer_id: String,
         limit: Option<usize>,
+        /// 翻页游标：只返回该时间之前的消息，为空时返回最新一页
+        #[serde(default)]
+        before_timestamp: Option<DateTime<Utc>>,
+        /// 与 before_timestamp 一起组成游标，取本页最早一条消息的ID，同一毫秒内的消息不会遗漏或重复
+        #[serde(default)]
+        before_id: Option<String>,
+        /// 重连补齐：只返回序号大于该值的消息，设置后忽略 before_timestamp
+        #[serde(default)]
+        since_seq: Option<u64>,
         timestamp: DateTime<Utc>,
     },
     // 在线用户列表（可以是请求或响应）
@@ -85,6 +118,27 @@ pub enum Message {
         user_type: UserType,
         zhanghao: Option<String>,
         timestamp: DateTime<Utc>,
+        // 协商成功的帧压缩方式（如 "gzip"），之后超过阈值的消息以二进制帧发送
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        compression: Option<String>,
+        // 协商成功的会话加密方式（如 "aes-256-gcm"），之后双方只收发 Encrypted 帧
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        encryption: Option<String>,
+        // Base64 编码的服务端 X25519 临时公钥，客户端据此完成密钥协商，各代密钥按 key_id 派生
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        server_key: Option<String>,
+        // 服务端为未携带令牌的访客签发的令牌，重连时以 token 参数携带即可恢复同一客户ID
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+        /// 本连接协商的消息协议版本，低于 max_version 时服务端按该版本的格式升级收到的消息
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<u32>,
+        /// 服务端支持的最低协议版本
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_version: Option<u32>,
+        /// 服务端支持的最高（当前）协议版本
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_version: Option<u32>,
     },
     // 错误消息
     #[serde(rename = "Error")]
@@ -134,6 +188,115 @@ pub enum Message {
         transcription: Option<String>, // 语音转文字（可选）
         timestamp: DateTime<Utc>,
     },
+    // 已读回执（接收方 -> 服务器）
+    #[serde(rename = "ReadReceipt")]
+    ReadReceipt {
+        message_ids: Vec<String>,
+        from: String,
+        timestamp: DateTime<Utc>,
+    },
+    // 收到确认（接收方 -> 服务器），客户端收到聊天或语音消息后回复，用于统计发送到确认的耗时
+    #[serde(rename = "Ack")]
+    Ack {
+        message_ids: Vec<String>,
+        from: String,
+        timestamp: DateTime<Utc>,
+    },
+    // 消息投递状态变化（服务器 -> 发送方），用于渲染消息的送达/已读标记
+    #[serde(rename = "DeliveryStatus")]
+    DeliveryStatus {
+        message_id: String,
+        status: crate::message_queue::MessageStatus,
+        timestamp: DateTime<Utc>,
+    },
+    // 会话结束时客户提交的满意度评价（客户 -> 服务器），score 为1-5星
+    #[serde(rename = "Rating")]
+    Rating {
+        session_id: String,
+        score: u8,
+        comment: Option<String>,
+        timestamp: DateTime<Utc>,
+    },
+    // 排队超时后客户提交的留言（客户 -> 服务器），生成工单由客服稍后处理
+    #[serde(rename = "LeaveMessage")]
+    Leave {
+        contact: String,
+        content: String,
+        timestamp: DateTime<Utc>,
+    },
+    // 对消息添加或取消表情回应（双向），服务器转发给会话另一方
+    #[serde(rename = "Reaction")]
+    Reaction {
+        message_id: String,
+        emoji: String,
+        user_id: String,
+        action: crate::reaction::ReactionAction,
+        timestamp: DateTime<Utc>,
+    },
+    // 按快捷码前缀查询快捷回复（客服 -> 服务器）
+    #[serde(rename = "CannedResponsesRequest")]
+    CannedResponsesRequest {
+        prefix: String,
+        limit: Option<usize>,
+        timestamp: DateTime<Utc>,
+    },
+    // 匹配的快捷回复（服务器 -> 客服），个人快捷回复在前
+    #[serde(rename = "CannedResponses")]
+    CannedResponses {
+        prefix: String,
+        responses: Vec<crate::canned_response::CannedResponse>,
+    },
+    // 客服之间的内部消息（客服 -> 客服），只在客服之间转发，单独存储，不会出现在客户的会话历史中
+    #[serde(rename = "InternalChat")]
+    InternalChat {
+        id: Option<String>,
+        from: String,
+        to: String,
+        content: String,
+        timestamp: DateTime<Utc>,
+    },
+    // 分段语音转写的中间结果，partial_text 为目前为止的转写，客户端按 voice_id 更新对应语音消息
+    #[serde(rename = "VoiceTranscriptionProgress")]
+    VoiceTranscriptionProgress {
+        voice_id: String,
+        sequence: usize,
+        total: usize,
+        partial_text: String,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+impl Message {
+    /// 消息类型名，与序列化时的 type 字段一致，用于日志和指标
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Message::Chat { .. } => "Chat",
+            Message::Welcome { .. } => "Welcome",
+            Message::History { .. } => "History",
+            Message::HistoryRequest { .. } => "HistoryRequest",
+            Message::OnlineUsers { .. } => "OnlineUsers",
+            Message::Heartbeat { .. } => "Heartbeat",
+            Message::Typing { .. } => "Typing",
+            Message::System { .. } => "System",
+            Message::UserJoined { .. } => "UserJoined",
+            Message::UserLeft { .. } => "UserLeft",
+            Message::Status { .. } => "Status",
+            Message::Error { .. } => "Error",
+            Message::HtmlTemplate { .. } => "HtmlTemplate",
+            Message::HtmlCallback { .. } => "HtmlCallback",
+            Message::Voice { .. } => "VoiceMessage",
+            Message::ReadReceipt { .. } => "ReadReceipt",
+            Message::Ack { .. } => "Ack",
+            Message::DeliveryStatus { .. } => "DeliveryStatus",
+            Message::Rating { .. } => "Rating",
+            Message::Leave { .. } => "LeaveMessage",
+            Message::Reaction { .. } => "Reaction",
+            Message::CannedResponsesRequest { .. } => "CannedResponsesRequest",
+            Message::CannedResponses { .. } => "CannedResponses",
+            Message::InternalChat { .. } => "InternalChat",
+            Message::VoiceTranscriptionProgress { .. } => "VoiceTranscriptionProgress",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
@@ -156,7 +319,10 @@ pub enum ContentType {
 pub enum OnlineStatus {
     Online,
     Offline,
+    /// 客服暂离：保留已有会话，不再分配新客户
     Away,
+    /// 客服忙碌：保留已有会话，不再分配新客户
+    Busy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -169,6 +335,12 @@ pub struct ChatMessage {
     pub filename: Option<String>,
     pub timestamp: DateTime<Utc>,
     pub url: Option<String>,
+    /// 图片附件的缩略图地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    /// 会话内递增的序号，保存时由存储分配，从1开始
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -191,6 +363,38 @@ pub struct Session {
     pub last_activity: DateTime<Utc>,
     pub messages: Vec<ChatMessage>,
     pub kehu_zhanghao: Option<String>,
+    /// 会话结束后由AI生成的摘要
+    #[serde(default)]
+    pub summary: Option<SessionSummary>,
+}
+
+/// 会话摘要：一句话概括与关键要点
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct SessionSummary {
+    pub summary: String,
+    pub key_points: Vec<String>,
+    pub task_id: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TicketStatus {
+    Open,
+    Resolved,
+}
+
+// 留言工单：排队超时无人接入的客户留下的联系方式和问题
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LeaveMessageTicket {
+    pub id: String,
+    pub customer_id: String,
+    pub contact: String,
+    pub content: String,
+    pub status: TicketStatus,
+    pub created_at: DateTime<Utc>,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
 }
 
 // 🚀 企业级客户信息结构
@@ -224,4 +428,11 @@ pub struct UserConnection {
     pub connected_at: DateTime<Utc>,
     pub last_heartbeat: DateTime<Utc>,
     pub status: OnlineStatus,
+    /// 握手风控判定为可疑时的原因
+    pub suspicious_reason: Option<String>,
+    /// 握手时的客户端IP，用于按IP封禁时断开已有连接，也记入连接事件。
+    /// 由 middleware::client_ip 按可信代理配置解析，不直接采信 X-Forwarded-For
+    pub client_ip: Option<String>,
+    /// 握手时的 User-Agent，记入连接事件
+    pub user_agent: Option<String>,
 }
@@ -3,21 +3,177 @@ use chrono::{DateTime, Utc};
 use redis::{Commands, Connection};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::{Mutex, RwLock};
+use utoipa::ToSchema;
 use uuid::Uuid;
+use crate::config::MessageQueueConfig;
+use crate::redis_client::{AsyncConnection, RedisManager};
 
-// 消息状态
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// 投递状态在Redis中的保留时长（秒）
+pub const MESSAGE_STATUS_TTL: usize = 7 * 24 * 3600;
+
+// 消息投递状态：Queued -> Sent -> Delivered -> Read，Failed 可在送达前任意时刻出现
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum MessageStatus {
-    Pending,   // 待发送
-    Sent,      // 已发送
-    Delivered, // 已送达
-    Read,      // 已读
+    #[serde(alias = "Pending")]
+    Queued,    // 已入队，接收方不在线或尚未推送
+    Sent,      // 已推送到接收方连接
+    Delivered, // 已写入接收方WebSocket
+    Read,      // 接收方已读
     Failed,    // 发送失败
 }
 
+impl MessageStatus {
+    const ALL: [MessageStatus; 5] = [
+        MessageStatus::Queued,
+        MessageStatus::Sent,
+        MessageStatus::Delivered,
+        MessageStatus::Read,
+        MessageStatus::Failed,
+    ];
+
+    /// 序列化后的状态名，与存入Redis的记录一致
+    fn name(self) -> &'static str {
+        match self {
+            MessageStatus::Queued => "Queued",
+            MessageStatus::Sent => "Sent",
+            MessageStatus::Delivered => "Delivered",
+            MessageStatus::Read => "Read",
+            MessageStatus::Failed => "Failed",
+        }
+    }
+
+    fn rank(self) -> u8 {
+        match self {
+            MessageStatus::Queued => 0,
+            MessageStatus::Sent => 1,
+            MessageStatus::Delivered => 2,
+            MessageStatus::Read => 3,
+            MessageStatus::Failed => 0,
+        }
+    }
+
+    /// 状态只能前进（允许跳过中间状态）；失败只能发生在送达之前，失败的消息可重新入队
+    pub fn can_transition_to(self, next: MessageStatus) -> bool {
+        match (self, next) {
+            (MessageStatus::Failed, MessageStatus::Queued) => true,
+            (MessageStatus::Failed, _) => false,
+            (current, MessageStatus::Failed) => current.rank() < MessageStatus::Delivered.rank(),
+            (current, next) => next.rank() > current.rank(),
+        }
+    }
+}
+
+/// 单条消息当前的投递状态，sender/recipient 用于回执通知和已读权限校验
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct MessageStatusRecord {
+    pub message_id: String,
+    pub sender: String,
+    pub recipient: String,
+    pub status: MessageStatus,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn status_key(message_id: &str) -> String {
+    format!("msg_status:{}", message_id)
+}
+
+/// 状态转换表的 Lua 定义：allowed[当前状态][新状态] 为 true 时允许转换。
+/// 由 MessageStatus::can_transition_to 逐对生成，脚本不另写一份规则；旧数据中的 Pending 按 Queued 处理
+fn lua_transition_table() -> String {
+    let row = |from: MessageStatus| {
+        MessageStatus::ALL
+            .iter()
+            .filter(|next| from.can_transition_to(**next))
+            .map(|next| format!("{} = true", next.name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut rows: Vec<String> = MessageStatus::ALL
+        .iter()
+        .map(|status| format!("{} = {{{}}}", status.name(), row(*status)))
+        .collect();
+    rows.push(format!("Pending = {{{}}}", row(MessageStatus::Queued)));
+    format!("local allowed = {{{}}}", rows.join(", "))
+}
+
+/// 状态转换脚本：读取当前状态、按状态机校验、写入新状态在服务端一次完成，并发推进同一消息时不会互相覆盖。
+/// 已有记录时保留原 sender/recipient，非法转换返回 nil
+fn status_transition_script() -> &'static str {
+    static SCRIPT: OnceLock<String> = OnceLock::new();
+    SCRIPT.get_or_init(|| {
+        format!(
+            "{}\n{}",
+            lua_transition_table(),
+            r#"
+local next_status = ARGV[1]
+local sender, recipient = ARGV[2], ARGV[3]
+local current = redis.call('GET', KEYS[1])
+if current then
+    local record = cjson.decode(current)
+    local row = allowed[record.status]
+    if not (row and row[next_status]) then
+        return false
+    end
+    sender, recipient = record.sender, record.recipient
+end
+local updated = cjson.encode({
+    message_id = ARGV[6],
+    sender = sender,
+    recipient = recipient,
+    status = next_status,
+    updated_at = ARGV[4],
+})
+redis.call('SET', KEYS[1], updated, 'EX', ARGV[5])
+return updated
+"#
+        )
+    })
+}
+
+// 按状态机规则批量写入新状态，所有消息在一个 pipeline 中完成；
+// 结果与 updates 一一对应，非法转换（回退、重复）对应位置为 None
+async fn apply_status_transitions(
+    conn: &mut AsyncConnection,
+    updates: &[(&str, &str, &str)],
+    status: MessageStatus,
+) -> Result<Vec<Option<MessageStatusRecord>>> {
+    if updates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let updated_at = Utc::now().to_rfc3339();
+
+    let mut pipe = redis::pipe();
+    for (message_id, sender, recipient) in updates {
+        pipe.cmd("EVAL")
+            .arg(status_transition_script())
+            .arg(1)
+            .arg(status_key(message_id))
+            .arg(status.name())
+            .arg(*sender)
+            .arg(*recipient)
+            .arg(&updated_at)
+            .arg(MESSAGE_STATUS_TTL)
+            .arg(*message_id);
+    }
+    let results: Vec<Option<String>> = conn.query_pipeline(&pipe).await?;
+
+    Ok(updates
+        .iter()
+        .zip(results)
+        .map(|((message_id, _, _), json)| {
+            let record = json.and_then(|json| serde_json::from_str::<MessageStatusRecord>(&json).ok());
+            if record.is_none() {
+                tracing::debug!("Ignored status transition for {}: -> {:?}", message_id, status);
+            }
+            record
+        })
+        .collect())
+}
+
 // 企业级增强消息结构 - Redis消息队列系统
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)] // 企业级消息结构：所有字段用于完整的企业级消息队列功能
@@ -35,6 +191,9 @@ pub struct EnhancedMessage {
     pub checksum: String,
     pub priority: u8,
     pub metadata: HashMap<String, String>,
+    /// 入队时按配置的保留时长设置，旧数据没有该字段
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl EnhancedMessage {
@@ -52,13 +211,14 @@ impl EnhancedMessage {
             to_user,
             content,
             content_type,
-            status: MessageStatus::Pending,
+            status: MessageStatus::Queued,
             retry_count: 0,
             created_at,
             last_attempt: created_at,
             checksum,
             priority: 5, // 默认优先级
             metadata: HashMap::new(),
+            expires_at: None,
         }
     }
 
@@ -87,14 +247,68 @@ impl EnhancedMessage {
     // 企业级消息过期检查功能
     #[allow(dead_code)] // 企业级功能：用于消息生命周期管理
     pub fn is_expired(&self) -> bool {
-        let expiry_duration = chrono::Duration::hours(24); // 24小时过期
-        Utc::now() - self.created_at > expiry_duration
+        match self.expires_at {
+            Some(expires_at) => Utc::now() >= expires_at,
+            // 未记录过期时间的旧消息按24小时过期
+            None => Utc::now() - self.created_at > chrono::Duration::hours(24),
+        }
     }
 }
 
+/// 写入后队列长度为 len 时，按上限需要丢弃的消息数
+pub fn overflow_count(len: u64, max_length: usize) -> u64 {
+    if max_length == 0 {
+        0
+    } else {
+        len.saturating_sub(max_length as u64)
+    }
+}
+
+/// 离线队列中按 timestamp 字段判断消息是否已超过保留时长；没有可解析的时间戳时视为未过期
+pub fn is_payload_expired(payload: &str, ttl_seconds: u64, now: DateTime<Utc>) -> bool {
+    #[derive(Deserialize)]
+    struct Timestamped {
+        timestamp: Option<DateTime<Utc>>,
+    }
+
+    if ttl_seconds == 0 {
+        return false;
+    }
+    match serde_json::from_str::<Timestamped>(payload) {
+        Ok(Timestamped { timestamp: Some(timestamp) }) => now - timestamp > chrono::Duration::seconds(ttl_seconds as i64),
+        _ => false,
+    }
+}
+
+// 写入队列并按上限裁剪（LTRIM 保留表头的 max_length 条），同时刷新队列过期时间，返回因超出上限丢弃的条数
+fn push_capped(
+    conn: &mut Connection,
+    queue_key: &str,
+    message_json: &str,
+    front: bool,
+    config: &MessageQueueConfig,
+) -> Result<u64> {
+    let mut pipe = redis::pipe();
+    if front {
+        pipe.lpush(queue_key, message_json);
+    } else {
+        pipe.rpush(queue_key, message_json);
+    }
+    if config.max_length > 0 {
+        pipe.ltrim(queue_key, 0, config.max_length as isize - 1).ignore();
+    }
+    if config.message_ttl_seconds > 0 {
+        pipe.expire(queue_key, config.message_ttl_seconds as usize).ignore();
+    }
+    let (len,): (u64,) = pipe.query(conn)?;
+    Ok(overflow_count(len, config.max_length))
+}
+
 // 企业级消息队列管理器 - Redis增强功能
 pub struct MessageQueueManager {
     connection: Arc<Mutex<Connection>>,
+    /// 投递状态在每次发送时更新，走连接池的异步连接，不与队列操作争用同一把锁
+    redis: RedisManager,
     #[allow(dead_code)] // 企业级字段：pending_messages用于离线消息重发和可靠性保障
     pending_messages: Arc<RwLock<HashMap<String, EnhancedMessage>>>, // 待确认消息
     #[allow(dead_code)] // 企业级字段：sequence_counters用于消息顺序保证和去重
@@ -102,17 +316,44 @@ pub struct MessageQueueManager {
     dedup_cache: Arc<RwLock<HashMap<String, u64>>>, // 去重缓存
     #[allow(dead_code)] // 企业级字段：retry_queue用于消息重试机制和故障恢复
     retry_queue: Arc<RwLock<VecDeque<String>>>, // 重试队列
+    config: std::sync::RwLock<MessageQueueConfig>,
+    /// 因队列超出上限被丢弃的消息数，由 /metrics 导出
+    dropped: Arc<AtomicU64>,
 }
 
 #[allow(dead_code)] // 企业级消息队列方法：所有方法用于完整的Redis增强功能
 impl MessageQueueManager {
-    pub fn new(connection: Connection) -> Self {
+    pub fn new(connection: Connection, redis: RedisManager) -> Self {
         Self {
             connection: Arc::new(Mutex::new(connection)),
+            redis,
             pending_messages: Arc::new(RwLock::new(HashMap::new())),
             sequence_counters: Arc::new(RwLock::new(HashMap::new())),
             dedup_cache: Arc::new(RwLock::new(HashMap::new())),
             retry_queue: Arc::new(RwLock::new(VecDeque::new())),
+            config: std::sync::RwLock::new(MessageQueueConfig::default()),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 设置队列长度上限与消息保留时长，之后写入的消息生效
+    pub fn set_config(&self, config: MessageQueueConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    pub fn config(&self) -> MessageQueueConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+
+    /// 记录因超出上限丢弃的消息
+    pub fn record_dropped(&self, user_id: &str, count: u64) {
+        if count > 0 {
+            self.dropped.fetch_add(count, Ordering::Relaxed);
+            tracing::warn!("⚠️ 离线队列已满，丢弃最早的 {} 条消息: {}", count, user_id);
         }
     }
 
@@ -126,14 +367,19 @@ impl MessageQueueManager {
 
         // 分配序列号
         message.sequence_id = self.get_next_sequence(&message.from_user).await;
+        let config = self.config();
+        if config.message_ttl_seconds > 0 {
+            message.expires_at = Some(message.created_at + chrono::Duration::seconds(config.message_ttl_seconds as i64));
+        }
 
         // 存储到Redis
         let mut conn = self.connection.lock().await;
 
-        // 添加到消息队列
+        // 添加到消息队列，超出上限时丢弃最早的消息
         let queue_key = format!("msg_queue:{}", message.to_user);
         let message_json = serde_json::to_string(&message)?;
-        conn.lpush::<_, _, ()>(&queue_key, &message_json)?;
+        let dropped = push_capped(&mut conn, &queue_key, &message_json, true, &config)?;
+        self.record_dropped(&message.to_user, dropped);
 
         // 添加到待确认队列
         let pending_key = format!("pending:{}", message.id);
@@ -195,35 +441,74 @@ impl MessageQueueManager {
 
     // 确认消息送达
     pub async fn acknowledge_message(&self, message_id: &str) -> Result<()> {
-        let mut conn = self.connection.lock().await;
-
         // 从待确认队列中移除
         let pending_key = format!("pending:{}", message_id);
-        conn.del::<_, ()>(&pending_key)?;
+        self.connection.lock().await.del::<_, ()>(&pending_key)?;
 
         // 从内存中移除
-        {
-            let mut pending = self.pending_messages.write().await;
-            if let Some(mut message) = pending.remove(message_id) {
-                message.status = MessageStatus::Delivered;
+        let removed = self.pending_messages.write().await.remove(message_id);
+        if let Some(mut message) = removed {
+            message.status = MessageStatus::Delivered;
 
-                // 更新消息状态到持久化存储
-                let status_key = format!("msg_status:{}", message_id);
-                conn.set_ex::<_, _, ()>(&status_key, "delivered", 86400)?; // 24小时
-            }
+            // 更新消息状态到持久化存储
+            self.update_status(message_id, &message.from_user, &message.to_user, MessageStatus::Delivered)
+                .await?;
         }
 
         tracing::debug!("Message acknowledged: {}", message_id);
         Ok(())
     }
 
+    /// 推进消息的投递状态，返回更新后的记录；不合法的转换（如已读后回到已送达）被忽略并返回 None
+    pub async fn update_status(
+        &self,
+        message_id: &str,
+        sender: &str,
+        recipient: &str,
+        status: MessageStatus,
+    ) -> Result<Option<MessageStatusRecord>> {
+        Ok(self.update_statuses(&[(message_id, sender, recipient)], status).await?.pop().flatten())
+    }
+
+    /// 批量推进投递状态（如一次已读回执中的多条消息），一次往返完成，结果与 updates 一一对应
+    pub async fn update_statuses(
+        &self,
+        updates: &[(&str, &str, &str)],
+        status: MessageStatus,
+    ) -> Result<Vec<Option<MessageStatusRecord>>> {
+        let mut conn = self.redis.get_async_connection().await?;
+        apply_status_transitions(&mut conn, updates, status).await
+    }
+
+    /// 查询消息当前的投递状态，未跟踪或已过期时返回 None
+    pub async fn get_status(&self, message_id: &str) -> Result<Option<MessageStatusRecord>> {
+        Ok(self.get_statuses(&[message_id.to_string()]).await?.pop().flatten())
+    }
+
+    /// 批量查询投递状态，一次 MGET 完成，结果与 message_ids 一一对应
+    pub async fn get_statuses(&self, message_ids: &[String]) -> Result<Vec<Option<MessageStatusRecord>>> {
+        if message_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.redis.get_async_connection().await?;
+        let keys: Vec<String> = message_ids.iter().map(|message_id| status_key(message_id)).collect();
+        let mut pipe = redis::pipe();
+        pipe.cmd("MGET").arg(&keys);
+        let (values,): (Vec<Option<String>>,) = conn.query_pipeline(&pipe).await?;
+        Ok(values
+            .into_iter()
+            .map(|json| json.and_then(|json| serde_json::from_str(&json).ok()))
+            .collect())
+    }
+
     // 处理掉线重发
     pub async fn handle_failed_delivery(&self, user_id: &str) -> Result<()> {
         let mut conn = self.connection.lock().await;
 
         // 查找该用户的未确认消息
         let pending_pattern = "pending:*".to_string();
-        let keys: Vec<String> = conn.keys(&pending_pattern)?;
+        // SCAN 分批遍历，避免 KEYS 阻塞服务端
+        let keys: Vec<String> = conn.scan_match(&pending_pattern)?.collect();
 
         let mut retry_messages = Vec::new();
 
@@ -233,7 +518,7 @@ impl MessageQueueManager {
                 if let Ok(mut message) = serde_json::from_str::<EnhancedMessage>(&json) {
                     if message.to_user == user_id && message.retry_count < 3 {
                         message.retry_count += 1;
-                        message.status = MessageStatus::Pending;
+                        message.status = MessageStatus::Queued;
                         retry_messages.push(message);
                     }
                 }
@@ -241,16 +526,15 @@ impl MessageQueueManager {
         }
 
         // 重新入队
+        let config = self.config();
         for message in retry_messages {
             let queue_key = format!("msg_queue:{}", message.to_user);
             let message_json = serde_json::to_string(&message)?;
 
-            // 根据优先级和重试次数调整位置
-            if message.retry_count > 1 || message.priority > 200 {
-                conn.lpush::<_, _, ()>(&queue_key, &message_json)?; // 高优先级放前面
-            } else {
-                conn.rpush::<_, _, ()>(&queue_key, &message_json)?; // 低优先级放后面
-            }
+            // 根据优先级和重试次数调整位置：高优先级放前面，低优先级放后面（队列已满时最先被裁掉）
+            let front = message.retry_count > 1 || message.priority > 200;
+            let dropped = push_capped(&mut conn, &queue_key, &message_json, front, &config)?;
+            self.record_dropped(&message.to_user, dropped);
 
             tracing::info!(
                 "Message requeued for retry: {} (attempt {})",
@@ -364,7 +648,7 @@ impl MessageQueueManager {
 
         // 清理过期的待确认消息
         let pending_pattern = "pending:*";
-        let keys: Vec<String> = conn.keys(pending_pattern)?;
+        let keys: Vec<String> = conn.scan_match(pending_pattern)?.collect();
 
         for key in keys {
             let message_json: Option<String> = conn.get(&key)?;
@@ -501,4 +785,121 @@ mod tests {
     async fn test_message_ordering() {
         // 测试消息顺序保证
     }
+
+    #[test]
+    fn test_overflow_and_payload_expiry() {
+        assert_eq!(overflow_count(1000, 1000), 0);
+        assert_eq!(overflow_count(1001, 1000), 1);
+        assert_eq!(overflow_count(5000, 0), 0);
+
+        let now = Utc::now();
+        let recent = serde_json::json!({ "type": "Chat", "timestamp": now - chrono::Duration::seconds(30) }).to_string();
+        let stale = serde_json::json!({ "type": "Chat", "timestamp": now - chrono::Duration::seconds(7200) }).to_string();
+        assert!(!is_payload_expired(&recent, 3600, now));
+        assert!(is_payload_expired(&stale, 3600, now));
+        assert!(!is_payload_expired(&stale, 0, now));
+        assert!(!is_payload_expired(r#"{"type":"System"}"#, 3600, now));
+
+        let mut message = EnhancedMessage::new("kehu_1".into(), "kefu_1".into(), "你好".into(), "text".into());
+        assert!(!message.is_expired());
+        message.expires_at = Some(now - chrono::Duration::seconds(1));
+        assert!(message.is_expired());
+        // 旧数据中没有过期时间的字段，反序列化后按24小时规则判断
+        let mut legacy = serde_json::to_value(&message).unwrap();
+        legacy.as_object_mut().unwrap().remove("expires_at");
+        assert!(!serde_json::from_value::<EnhancedMessage>(legacy).unwrap().is_expired());
+    }
+
+    fn test_manager() -> MessageQueueManager {
+        let redis = RedisManager::new("redis://127.0.0.1:6379/").expect("Redis不可用");
+        let connection = redis.get_connection().expect("Redis不可用");
+        MessageQueueManager::new(connection, redis)
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_status_transitions_are_applied_atomically_in_redis() {
+        let manager = test_manager();
+        let ids: Vec<String> = (0..3).map(|_| format!("status_test_{}", Uuid::new_v4())).collect();
+        let updates: Vec<(&str, &str, &str)> = ids.iter().map(|id| (id.as_str(), "kehu_001", "kefu_001")).collect();
+
+        let queued = manager.update_statuses(&updates, MessageStatus::Queued).await.unwrap();
+        assert!(queued.iter().all(|record| record.as_ref().map(|r| r.status) == Some(MessageStatus::Queued)));
+
+        // 已读后不能回退到已送达，已有记录的收发方不被覆盖
+        assert!(manager.update_status(&ids[0], "kehu_001", "kefu_001", MessageStatus::Read).await.unwrap().is_some());
+        let results = manager
+            .update_statuses(
+                &[(ids[0].as_str(), "forged", "forged"), (ids[1].as_str(), "forged", "forged")],
+                MessageStatus::Delivered,
+            )
+            .await
+            .unwrap();
+        assert!(results[0].is_none());
+        assert_eq!(results[1].as_ref().unwrap().sender, "kehu_001");
+
+        let statuses = manager.get_statuses(&ids).await.unwrap();
+        assert_eq!(
+            statuses.iter().map(|record| record.as_ref().map(|r| r.status)).collect::<Vec<_>>(),
+            [Some(MessageStatus::Read), Some(MessageStatus::Delivered), Some(MessageStatus::Queued)]
+        );
+        assert!(manager.get_status("status_test_missing").await.unwrap().is_none());
+
+        // 并发推进同一消息，同一状态只会成功一次
+        let manager = Arc::new(manager);
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let manager = manager.clone();
+                let id = ids[2].clone();
+                tokio::spawn(async move {
+                    manager.update_status(&id, "kehu_001", "kefu_001", MessageStatus::Sent).await.unwrap()
+                })
+            })
+            .collect();
+        let mut applied = 0;
+        for task in tasks {
+            applied += task.await.unwrap().is_some() as usize;
+        }
+        assert_eq!(applied, 1);
+
+        let mut conn = manager.connection.lock().await;
+        for id in &ids {
+            let _: () = conn.del(status_key(id)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_lua_transition_table_matches_state_machine() {
+        let table = lua_transition_table();
+        assert!(table.starts_with("local allowed = {"));
+        assert!(table.contains("Queued = {Sent = true, Delivered = true, Read = true, Failed = true}"));
+        assert!(table.contains("Delivered = {Read = true}"));
+        assert!(table.contains("Read = {}"));
+        assert!(table.contains("Failed = {Queued = true}"));
+        // 旧数据中的 Pending 与 Queued 同一行
+        assert!(table.contains("Pending = {Sent = true, Delivered = true, Read = true, Failed = true}"));
+        assert!(status_transition_script().starts_with(&table));
+    }
+
+    #[test]
+    fn test_status_transitions_only_move_forward() {
+        use MessageStatus::*;
+        assert!(Queued.can_transition_to(Sent));
+        assert!(Sent.can_transition_to(Delivered));
+        assert!(Delivered.can_transition_to(Read));
+        // 推送和写入可能乱序完成，允许跳过中间状态
+        assert!(Queued.can_transition_to(Read));
+
+        assert!(!Read.can_transition_to(Delivered));
+        assert!(!Delivered.can_transition_to(Sent));
+        assert!(!Sent.can_transition_to(Sent));
+
+        assert!(Sent.can_transition_to(Failed));
+        assert!(!Delivered.can_transition_to(Failed));
+        assert!(Failed.can_transition_to(Queued));
+        assert!(!Failed.can_transition_to(Read));
+
+        // 兼容旧数据中的 Pending
+        assert_eq!(serde_json::from_str::<MessageStatus>("\"Pending\"").unwrap(), Queued);
+    }
 }
@@ -0,0 +1,302 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// 默认重排序窗口
+pub const DEFAULT_REORDER_WINDOW: Duration = Duration::from_millis(200);
+
+/// 缓冲区空闲超过该时长后可以回收，回收后才会丢失已释放的序号
+pub const REORDER_STATE_IDLE: Duration = Duration::from_secs(600);
+
+/// 客户端时间戳与服务端时间允许的最大偏差，超出部分按边界处理
+pub const MAX_TIMESTAMP_SKEW: Duration = Duration::from_secs(5);
+
+/// 将客户端时间戳限制在服务端时间前后 `skew` 以内，
+/// 避免一条时间戳远在未来的消息把同一方向的后续消息都判为乱序
+pub fn clamp_timestamp(timestamp: DateTime<Utc>, server_now: DateTime<Utc>, skew: Duration) -> DateTime<Utc> {
+    let skew = chrono::Duration::from_std(skew).unwrap_or(chrono::Duration::zero());
+    timestamp.clamp(server_now - skew, server_now + skew)
+}
+
+/// 暂存的消息：内容、到达时间和序号
+type PendingEntry<T> = (T, Instant, Option<u64>);
+
+/// 从缓冲区释放的消息
+#[derive(Debug)]
+pub struct Released<T> {
+    pub item: T,
+    /// 超出窗口后才到达、已无法按序投递的消息
+    pub out_of_order: bool,
+}
+
+/// 消息重排序缓冲区
+///
+/// 消息到达后在窗口内暂存，窗口到期时按序释放：带序号的消息按序号，保证投递的序号只增不减；
+/// 没有序号的消息按时间戳，时间戳早于已释放消息的迟到消息立即释放并标记为乱序。
+/// 带序号的消息按序号去重：序号不大于已释放的最大序号、或已在窗口内的重传直接丢弃。
+/// 缓冲区清空后仍保留已释放的位置，同一方向的后续消息继续据此判断。
+/// 每个缓冲区同一时刻只需要一个刷新任务，由 `schedule_flush` / `next_flush_deadline` 协调
+pub struct ReorderBuffer<T> {
+    window: Duration,
+    /// 键为 (序号, 时间戳, 到达次序)，没有序号的消息序号记为0
+    pending: BTreeMap<(u64, DateTime<Utc>, u64), PendingEntry<T>>,
+    pending_seqs: HashSet<u64>,
+    next_index: u64,
+    last_released: Option<DateTime<Utc>>,
+    last_released_seq: Option<u64>,
+    last_activity: Instant,
+    flush_scheduled: bool,
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: BTreeMap::new(),
+            pending_seqs: HashSet::new(),
+            next_index: 0,
+            last_released: None,
+            last_released_seq: None,
+            last_activity: Instant::now(),
+            flush_scheduled: false,
+        }
+    }
+
+    /// 放入一条消息，返回此刻可以投递的消息；重复的消息被丢弃
+    pub fn push(&mut self, timestamp: DateTime<Utc>, seq: Option<u64>, item: T, now: Instant) -> Vec<Released<T>> {
+        self.last_activity = now;
+        if let Some(seq) = seq {
+            if self.is_duplicate(seq) {
+                tracing::debug!("丢弃重复投递的消息: seq={}", seq);
+                return self.drain_ready(now);
+            }
+        }
+
+        if seq.is_none() && self.last_released.is_some_and(|last| timestamp < last) {
+            self.mark_released(seq);
+            let mut released = vec![Released { item, out_of_order: true }];
+            released.extend(self.drain_ready(now));
+            return released;
+        }
+
+        self.pending.insert((seq.unwrap_or(0), timestamp, self.next_index), (item, now, seq));
+        if let Some(seq) = seq {
+            self.pending_seqs.insert(seq);
+        }
+        self.next_index += 1;
+        self.drain_ready(now)
+    }
+
+    fn is_duplicate(&self, seq: u64) -> bool {
+        self.last_released_seq.is_some_and(|last| seq <= last) || self.pending_seqs.contains(&seq)
+    }
+
+    fn mark_released(&mut self, seq: Option<u64>) {
+        if let Some(seq) = seq {
+            self.pending_seqs.remove(&seq);
+            self.last_released_seq = Some(self.last_released_seq.map_or(seq, |last| last.max(seq)));
+        }
+    }
+
+    /// 释放窗口已到期的消息；排在它之前的消息随之一起按序释放
+    pub fn drain_ready(&mut self, now: Instant) -> Vec<Released<T>> {
+        let cutoff = self
+            .pending
+            .iter()
+            .filter(|(_, (_, arrived_at, _))| now.duration_since(*arrived_at) >= self.window)
+            .map(|(key, _)| *key)
+            .max();
+
+        let mut released = Vec::new();
+        if let Some(cutoff) = cutoff {
+            while let Some(entry) = self.pending.first_entry() {
+                if *entry.key() > cutoff {
+                    break;
+                }
+                let ((_, timestamp, _), (item, _, seq)) = entry.remove_entry();
+                if seq.is_none() {
+                    self.last_released = Some(timestamp);
+                }
+                self.mark_released(seq);
+                released.push(Released { item, out_of_order: false });
+            }
+        }
+        released
+    }
+
+    /// 有待投递的消息且尚无刷新任务时返回 true，调用方随后启动一个刷新任务
+    pub fn schedule_flush(&mut self) -> bool {
+        if self.pending.is_empty() || self.flush_scheduled {
+            return false;
+        }
+        self.flush_scheduled = true;
+        true
+    }
+
+    /// 刷新任务下一次需要唤醒的时间；缓冲区已清空时返回 None 并结束本轮刷新
+    pub fn next_flush_deadline(&mut self) -> Option<Instant> {
+        let deadline = self
+            .pending
+            .values()
+            .map(|(_, arrived_at, _)| *arrived_at + self.window)
+            .min();
+        if deadline.is_none() {
+            self.flush_scheduled = false;
+        }
+        deadline
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// 没有待投递的消息且空闲已久，可以回收
+    pub fn is_idle(&self, now: Instant, idle: Duration) -> bool {
+        self.pending.is_empty() && now.saturating_duration_since(self.last_activity) >= idle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(offset_ms: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(1_700_000_000_000 + offset_ms).unwrap()
+    }
+
+    fn items(released: Vec<Released<&'static str>>) -> Vec<(&'static str, bool)> {
+        released.into_iter().map(|r| (r.item, r.out_of_order)).collect()
+    }
+
+    #[test]
+    fn test_out_of_order_messages_reordered_within_window() {
+        let mut buffer = ReorderBuffer::new(DEFAULT_REORDER_WINDOW);
+        let start = Instant::now();
+
+        assert!(buffer.push(ts(30), None, "third", start).is_empty());
+        assert!(buffer.push(ts(10), None, "first", start + Duration::from_millis(20)).is_empty());
+        assert!(buffer.push(ts(20), None, "second", start + Duration::from_millis(50)).is_empty());
+
+        // 第一条到达的消息窗口到期，更早时间戳的消息一并按序释放
+        let released = buffer.drain_ready(start + DEFAULT_REORDER_WINDOW);
+        assert_eq!(items(released), vec![("first", false), ("second", false), ("third", false)]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_later_message_waits_for_its_own_window() {
+        let mut buffer = ReorderBuffer::new(DEFAULT_REORDER_WINDOW);
+        let start = Instant::now();
+
+        buffer.push(ts(10), None, "first", start);
+        buffer.push(ts(20), None, "second", start + Duration::from_millis(150));
+
+        let released = buffer.drain_ready(start + DEFAULT_REORDER_WINDOW);
+        assert_eq!(items(released), vec![("first", false)]);
+        assert!(!buffer.is_empty());
+
+        let released = buffer.drain_ready(start + Duration::from_millis(350));
+        assert_eq!(items(released), vec![("second", false)]);
+    }
+
+    #[test]
+    fn test_late_message_marked_out_of_order() {
+        let mut buffer = ReorderBuffer::new(DEFAULT_REORDER_WINDOW);
+        let start = Instant::now();
+
+        buffer.push(ts(20), None, "second", start);
+        buffer.drain_ready(start + DEFAULT_REORDER_WINDOW);
+
+        let released = buffer.push(ts(10), None, "first", start + Duration::from_millis(300));
+        assert_eq!(items(released), vec![("first", true)]);
+    }
+
+    #[test]
+    fn test_retransmits_dropped_after_buffer_flushes() {
+        let mut buffer = ReorderBuffer::new(DEFAULT_REORDER_WINDOW);
+        let start = Instant::now();
+
+        // 窗口内的重传只保留一份
+        buffer.push(ts(10), Some(1), "first", start);
+        assert!(buffer.push(ts(10), Some(1), "first again", start + Duration::from_millis(10)).is_empty());
+        let released = buffer.drain_ready(start + DEFAULT_REORDER_WINDOW);
+        assert_eq!(items(released), vec![("first", false)]);
+        assert!(buffer.is_empty());
+
+        // 缓冲区清空后仍记得已释放的序号和时间
+        let later = start + Duration::from_millis(500);
+        assert!(buffer.push(ts(10), Some(1), "first late", later).is_empty());
+        assert!(buffer.is_empty());
+        // 序号更大的消息即使时间戳更早也正常排队，不算乱序
+        assert!(buffer.push(ts(5), Some(2), "earlier", later).is_empty());
+        assert!(buffer.push(ts(5), Some(2), "earlier again", later).is_empty());
+        let released = buffer.drain_ready(later + DEFAULT_REORDER_WINDOW);
+        assert_eq!(items(released), vec![("earlier", false)]);
+        let later = later + DEFAULT_REORDER_WINDOW;
+
+        assert!(!buffer.is_idle(later, REORDER_STATE_IDLE));
+        assert!(buffer.is_idle(later + REORDER_STATE_IDLE, REORDER_STATE_IDLE));
+    }
+
+    #[test]
+    fn test_sequenced_messages_released_in_seq_order() {
+        let mut buffer = ReorderBuffer::new(DEFAULT_REORDER_WINDOW);
+        let start = Instant::now();
+
+        // 序号按保存顺序分配，与客户端时间戳不一致时以序号为准，投递的序号不回退
+        buffer.push(ts(10), Some(2), "second saved", start);
+        buffer.push(ts(30), Some(1), "first saved", start + Duration::from_millis(20));
+        buffer.push(ts(20), Some(3), "third saved", start + Duration::from_millis(40));
+
+        let released = buffer.drain_ready(start + DEFAULT_REORDER_WINDOW + Duration::from_millis(40));
+        assert_eq!(
+            items(released),
+            vec![("first saved", false), ("second saved", false), ("third saved", false)]
+        );
+    }
+
+    #[test]
+    fn test_future_timestamp_clamped_to_skew() {
+        let now = ts(0);
+        let skew = chrono::Duration::from_std(MAX_TIMESTAMP_SKEW).unwrap();
+        assert_eq!(clamp_timestamp(ts(3_600_000), now, MAX_TIMESTAMP_SKEW), now + skew);
+        assert_eq!(clamp_timestamp(ts(-3_600_000), now, MAX_TIMESTAMP_SKEW), now - skew);
+        assert_eq!(clamp_timestamp(ts(100), now, MAX_TIMESTAMP_SKEW), ts(100));
+
+        // 钳制后，时间戳远在未来的消息不再让后续消息被判为乱序
+        let mut buffer = ReorderBuffer::new(DEFAULT_REORDER_WINDOW);
+        let start = Instant::now();
+        buffer.push(clamp_timestamp(ts(3_600_000), now, MAX_TIMESTAMP_SKEW), None, "future", start);
+        buffer.drain_ready(start + DEFAULT_REORDER_WINDOW);
+        let later = ts(MAX_TIMESTAMP_SKEW.as_millis() as i64 + 1_000);
+        let mut released = buffer.push(clamp_timestamp(later, later, MAX_TIMESTAMP_SKEW), None, "next", start + DEFAULT_REORDER_WINDOW);
+        released.extend(buffer.drain_ready(start + DEFAULT_REORDER_WINDOW * 2));
+        assert_eq!(items(released), vec![("next", false)]);
+    }
+
+    #[test]
+    fn test_single_flush_scheduled_per_buffer() {
+        let mut buffer = ReorderBuffer::new(DEFAULT_REORDER_WINDOW);
+        let start = Instant::now();
+        assert!(!buffer.schedule_flush());
+
+        buffer.push(ts(10), None, "first", start);
+        buffer.push(ts(20), None, "second", start + Duration::from_millis(50));
+        assert!(buffer.schedule_flush());
+        assert!(!buffer.schedule_flush());
+        assert_eq!(buffer.next_flush_deadline(), Some(start + DEFAULT_REORDER_WINDOW));
+
+        buffer.drain_ready(start + DEFAULT_REORDER_WINDOW + Duration::from_millis(50));
+        assert_eq!(buffer.next_flush_deadline(), None);
+        buffer.push(ts(30), None, "third", start + Duration::from_millis(300));
+        assert!(buffer.schedule_flush());
+    }
+
+    #[test]
+    fn test_zero_window_releases_immediately() {
+        let mut buffer = ReorderBuffer::new(Duration::ZERO);
+        let released = buffer.push(ts(10), None, "only", Instant::now());
+        assert_eq!(items(released), vec![("only", false)]);
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+/// 当前的WebSocket消息协议版本，与 Message 枚举的格式一致
+pub const CURRENT_PROTOCOL_VERSION: u32 = 2;
+
+/// 仍兼容的最低协议版本。各版本的差异：
+/// - 1：早期前端格式，Chat 用 from_user_id / to_user_id 且 content_type 为小写（如 "text"），
+///   Typing 用 user_id / target_id 且不带 is_typing
+/// - 2：当前格式
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// 握手时协商协议版本（连接参数 version）。未声明时按最低版本处理，升级逻辑不会改动当前格式的消息；
+/// 高于服务端支持的版本时报错，由调用方拒绝连接
+pub fn negotiate_version(requested: Option<&str>) -> Result<u32> {
+    let Some(requested) = requested.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(MIN_PROTOCOL_VERSION);
+    };
+    let version = requested
+        .parse()
+        .map_err(|_| anyhow!("无效的消息协议版本: {}", requested))?;
+    check_version(version)?;
+    Ok(version)
+}
+
+fn check_version(version: u32) -> Result<()> {
+    if (MIN_PROTOCOL_VERSION..=CURRENT_PROTOCOL_VERSION).contains(&version) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "不支持的消息协议版本 {}，服务端支持 {}-{}",
+            version,
+            MIN_PROTOCOL_VERSION,
+            CURRENT_PROTOCOL_VERSION
+        ))
+    }
+}
+
+/// 把旧版本格式的消息升级为当前格式。消息自带的 version 字段优先于连接协商的版本
+pub fn upgrade_message(mut value: Value, connection_version: u32) -> Result<Value> {
+    let version = match value.get("version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow!("消息的 version 字段必须是正整数"))?,
+        None => connection_version,
+    };
+    check_version(version)?;
+
+    if let Some(fields) = value.as_object_mut() {
+        if version < 2 {
+            upgrade_v1(fields);
+        }
+    }
+    Ok(value)
+}
+
+fn upgrade_v1(fields: &mut Map<String, Value>) {
+    match fields.get("type").and_then(Value::as_str) {
+        Some("Chat") => {
+            rename_field(fields, "from_user_id", "from");
+            rename_field(fields, "to_user_id", "to");
+            // "text" -> "Text"
+            if let Some(Value::String(content_type)) = fields.get_mut("content_type") {
+                let mut chars = content_type.chars();
+                if let Some(first) = chars.next() {
+                    *content_type = first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect();
+                }
+            }
+        }
+        Some("Typing") => {
+            rename_field(fields, "user_id", "from");
+            rename_field(fields, "target_id", "to");
+            fields.entry("is_typing").or_insert(Value::Bool(true));
+        }
+        _ => {}
+    }
+}
+
+// 旧字段名改为新字段名，两者都有时以新字段为准
+fn rename_field(fields: &mut Map<String, Value>, old: &str, new: &str) {
+    if let Some(value) = fields.remove(old) {
+        fields.entry(new).or_insert(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ContentType, Message};
+
+    #[test]
+    fn test_v1_messages_upgraded_to_current_shape() {
+        let chat = serde_json::json!({
+            "type": "Chat",
+            "from_user_id": "kehu_1",
+            "to_user_id": "kefu_1",
+            "content": "你好",
+            "content_type": "text",
+            "timestamp": "2024-01-01T00:00:00Z"
+        });
+        let upgraded = upgrade_message(chat, 1).unwrap();
+        match serde_json::from_value::<Message>(upgraded).unwrap() {
+            Message::Chat { from, to, content_type, .. } => {
+                assert_eq!(from, "kehu_1");
+                assert_eq!(to.as_deref(), Some("kefu_1"));
+                assert_eq!(content_type, Some(ContentType::Text));
+            }
+            other => panic!("应解析为Chat: {:?}", other),
+        }
+
+        let typing = serde_json::json!({
+            "type": "Typing",
+            "user_id": "kehu_1",
+            "target_id": "kefu_1",
+            "timestamp": "2024-01-01T00:00:00Z"
+        });
+        let upgraded = upgrade_message(typing, 1).unwrap();
+        assert!(matches!(
+            serde_json::from_value::<Message>(upgraded).unwrap(),
+            Message::Typing { is_typing: true, .. }
+        ));
+
+        // 当前格式的消息经过升级不变
+        let current = serde_json::json!({
+            "type": "Typing",
+            "from": "kehu_1",
+            "to": "kefu_1",
+            "is_typing": false,
+            "timestamp": "2024-01-01T00:00:00Z"
+        });
+        assert_eq!(upgrade_message(current.clone(), 1).unwrap(), current);
+        assert_eq!(upgrade_message(current.clone(), CURRENT_PROTOCOL_VERSION).unwrap(), current);
+    }
+
+    #[test]
+    fn test_future_versions_rejected() {
+        assert_eq!(negotiate_version(None).unwrap(), MIN_PROTOCOL_VERSION);
+        assert_eq!(negotiate_version(Some("2")).unwrap(), 2);
+        let error = negotiate_version(Some("3")).unwrap_err().to_string();
+        assert!(error.contains("1-2"), "{}", error);
+        assert!(negotiate_version(Some("abc")).is_err());
+        assert!(negotiate_version(Some("0")).is_err());
+
+        // 消息自带的版本优先于连接版本
+        let future = serde_json::json!({ "type": "Heartbeat", "version": 9, "timestamp": "2024-01-01T00:00:00Z" });
+        assert!(upgrade_message(future, 2).is_err());
+        let legacy = serde_json::json!({ "type": "Typing", "version": 1, "user_id": "kehu_1", "timestamp": "2024-01-01T00:00:00Z" });
+        assert_eq!(upgrade_message(legacy, 2).unwrap()["from"], "kehu_1");
+    }
+}
@@ -0,0 +1,145 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use warp::Filter;
+use crate::config::AppConfig;
+use crate::middleware::request_id::remote_addr;
+
+/// 可信反向代理的地址或网段（如 10.0.0.0/8）。只有对端是可信代理时才读取 X-Forwarded-For，
+/// 否则该头可由客户端任意伪造
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// 解析配置中的地址或网段，无法解析的条目记录日志后跳过
+    pub fn parse(entries: &[String]) -> Self {
+        let ranges = entries
+            .iter()
+            .filter_map(|entry| {
+                let range = parse_range(entry.trim());
+                if range.is_none() {
+                    tracing::warn!("⚠️ 忽略无法解析的可信代理配置: {}", entry);
+                }
+                range
+            })
+            .collect();
+        Self { ranges }
+    }
+
+    pub fn from_config() -> Self {
+        Self::parse(&AppConfig::get().security.trusted_proxies)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.ranges.iter().any(|(network, prefix)| in_range(ip, *network, *prefix))
+    }
+
+    /// 客户端IP：对端不是可信代理时直接取对端地址；否则从右往左跳过可信代理，
+    /// 取 X-Forwarded-For 中最右侧的不可信地址，左侧由客户端自行填写的部分不予采信
+    pub fn client_ip(&self, forwarded_for: Option<&str>, remote: Option<SocketAddr>) -> Option<String> {
+        let mut client = remote?.ip().to_canonical();
+        if self.contains(client) {
+            for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+                let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                    break;
+                };
+                client = ip.to_canonical();
+                if !self.contains(client) {
+                    break;
+                }
+            }
+        }
+        Some(client.to_string())
+    }
+}
+
+fn parse_range(entry: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix) = match entry.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+        None => (entry, None),
+    };
+    let ip = address.parse::<IpAddr>().ok()?.to_canonical();
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max_prefix);
+    (prefix <= max_prefix).then_some((ip, prefix))
+}
+
+fn in_range(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// 按可信代理配置解析出的客户端IP，取不到对端地址时为 None；X-Forwarded-For 不是合法字符串时拒绝请求
+pub fn client_ip(
+    trusted_proxies: Arc<TrustedProxies>,
+) -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("x-forwarded-for")
+        .and(remote_addr())
+        .map(move |forwarded_for: Option<String>, remote: Option<SocketAddr>| {
+            trusted_proxies.client_ip(forwarded_for.as_deref(), remote)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(ip: &str) -> Option<SocketAddr> {
+        Some(SocketAddr::new(ip.parse().unwrap(), 443))
+    }
+
+    #[test]
+    fn test_client_ip_uses_rightmost_untrusted_hop() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8".to_string(), "192.168.1.10".to_string(), "bad".to_string()]);
+
+        // 未经可信代理时 X-Forwarded-For 由客户端伪造，直接取对端地址
+        assert_eq!(proxies.client_ip(Some("1.1.1.1"), remote("203.0.113.7")).as_deref(), Some("203.0.113.7"));
+        assert_eq!(TrustedProxies::default().client_ip(Some("1.1.1.1"), remote("10.0.0.1")).as_deref(), Some("10.0.0.1"));
+
+        // 经可信代理时跳过代理链，客户端在最左侧伪造的地址不被采信
+        assert_eq!(
+            proxies.client_ip(Some("1.1.1.1, 203.0.113.7, 10.0.0.2"), remote("192.168.1.10")).as_deref(),
+            Some("203.0.113.7")
+        );
+        assert_eq!(proxies.client_ip(Some("10.0.0.3"), remote("10.0.0.1")).as_deref(), Some("10.0.0.3"));
+        assert_eq!(proxies.client_ip(Some("garbage, 10.0.0.2"), remote("10.0.0.1")).as_deref(), Some("10.0.0.2"));
+        assert_eq!(proxies.client_ip(None, remote("::ffff:10.0.0.1")).as_deref(), Some("10.0.0.1"));
+        assert_eq!(proxies.client_ip(Some("1.1.1.1"), None), None);
+
+        assert!(proxies.contains("10.255.0.1".parse().unwrap()));
+        assert!(!proxies.contains("192.168.1.11".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_filter_ignores_forged_header_from_untrusted_peer() {
+        // 握手路由经该过滤器取得的IP会记入连接事件，直连客户端伪造的头不能改写审计IP
+        let filter = client_ip(Arc::new(TrustedProxies::parse(&["10.0.0.0/8".to_string()])));
+
+        let direct = warp::test::request()
+            .remote_addr("203.0.113.7:50000".parse().unwrap())
+            .header("x-forwarded-for", "1.1.1.1")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(direct.as_deref(), Some("203.0.113.7"));
+
+        let proxied = warp::test::request()
+            .remote_addr("10.0.0.1:50000".parse().unwrap())
+            .header("x-forwarded-for", "1.1.1.1, 198.51.100.9")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(proxied.as_deref(), Some("198.51.100.9"));
+    }
+}
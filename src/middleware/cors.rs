@@ -0,0 +1,146 @@
+use warp::cors::Builder;
+use warp::http::header::HeaderName;
+use warp::http::Method;
+use crate::config::CorsConfig;
+use crate::middleware::request_id::REQUEST_ID_HEADER;
+
+/// 前端和管理端点固定会携带的请求头，无论配置如何都允许
+const BUILTIN_HEADERS: [&str; 8] = [
+    "content-type",
+    "authorization",
+    "user-id",
+    "user-name",
+    "user-type",
+    "session-id",
+    "x-admin-token",
+    REQUEST_ID_HEADER,
+];
+
+/// 按运行环境的跨域配置构建CORS过滤器。只允许配置的来源，其他来源的跨域请求和预检被拒绝（403），
+/// 不会回显请求的 Origin；未启用跨域配置时不检查来源，也不允许携带凭据
+pub fn build_cors(config: &CorsConfig, environment: &str) -> Builder {
+    let config = config.for_environment(environment);
+
+    let mut headers: Vec<HeaderName> = BUILTIN_HEADERS
+        .iter()
+        .map(|header| HeaderName::from_static(header))
+        .collect();
+    for header in &config.headers {
+        match HeaderName::from_bytes(header.trim().as_bytes()) {
+            Ok(header) => headers.push(header),
+            Err(_) => tracing::warn!("⚠️ 忽略无效的跨域请求头配置: {}", header),
+        }
+    }
+    let methods: Vec<Method> = config
+        .methods
+        .iter()
+        .filter_map(|method| match Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()) {
+            Ok(method) => Some(method),
+            Err(_) => {
+                tracing::warn!("⚠️ 忽略无效的跨域方法配置: {}", method);
+                None
+            }
+        })
+        .collect();
+
+    let cors = warp::cors()
+        .allow_headers(headers)
+        .allow_methods(methods)
+        .expose_headers(vec![REQUEST_ID_HEADER]);
+
+    if !config.enabled {
+        return cors.allow_any_origin();
+    }
+    let origins = allowed_origins(&config.origins);
+    if origins.iter().any(|origin| origin == "*") {
+        // 任意来源时携带凭据等同于关闭同源保护，不允许
+        if config.credentials {
+            tracing::warn!("⚠️ 跨域来源配置为 *，已忽略 credentials 设置");
+        }
+        return cors.allow_any_origin();
+    }
+    tracing::info!("🌐 跨域来源（{}）: {:?}", environment, origins);
+    cors.allow_origins(origins.iter().map(String::as_str))
+        .allow_credentials(config.credentials)
+}
+
+/// 规范化配置的来源为 scheme://host[:port]，去掉末尾斜杠；带路径或无法解析的来源忽略
+pub fn allowed_origins(origins: &[String]) -> Vec<String> {
+    let mut allowed = Vec::new();
+    for origin in origins.iter().map(|origin| origin.trim()) {
+        if origin == "*" {
+            allowed.push(origin.to_string());
+            continue;
+        }
+        let normalized = url::Url::parse(origin)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some() && url.path() == "/")
+            .filter(|url| url.query().is_none() && url.fragment().is_none())
+            .map(|url| url.origin().ascii_serialization());
+        match normalized {
+            Some(origin) if !allowed.contains(&origin) => allowed.push(origin),
+            Some(_) => {}
+            None => tracing::warn!("⚠️ 忽略无效的跨域来源配置: {}", origin),
+        }
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    fn cors_config() -> CorsConfig {
+        serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "origins": ["https://b.ylqkf.com"],
+            "methods": ["GET", "POST"],
+            "headers": ["Content-Type"],
+            "credentials": true,
+            "environments": {
+                "development": { "origins": ["http://localhost:6007/", "http://localhost:6007", "not a url"] }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_only_configured_origins_allowed_per_environment() {
+        let config = cors_config();
+        assert_eq!(allowed_origins(&config.for_environment("development").origins), ["http://localhost:6007"]);
+
+        let routes = warp::any().map(warp::reply).with(build_cors(&config, "production"));
+        let allowed = warp::test::request()
+            .header("origin", "https://b.ylqkf.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(allowed.status(), 200);
+        assert_eq!(allowed.headers()["access-control-allow-origin"], "https://b.ylqkf.com");
+        assert_eq!(allowed.headers()["access-control-allow-credentials"], "true");
+
+        // 不在列表中的来源直接拒绝，不回显 Origin
+        let rejected = warp::test::request()
+            .header("origin", "https://evil.example.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(rejected.status(), 403);
+        assert!(rejected.headers().get("access-control-allow-origin").is_none());
+
+        // development 环境使用覆盖的来源
+        let routes = warp::any().map(warp::reply).with(build_cors(&config, "development"));
+        let preflight = warp::test::request()
+            .method("OPTIONS")
+            .header("origin", "http://localhost:6007")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "x-admin-token")
+            .reply(&routes)
+            .await;
+        assert_eq!(preflight.status(), 200);
+        let rejected = warp::test::request()
+            .header("origin", "https://b.ylqkf.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(rejected.status(), 403);
+    }
+}
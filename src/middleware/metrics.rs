@@ -1,8 +1,8 @@
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use warp::Filter;
+use crate::monitoring::metrics::Histograms;
 use crate::monitoring::MetricsRegistry;
-use tokio;
 
 /// 性能监控中间件
 pub fn with_metrics(
@@ -11,30 +11,46 @@ pub fn with_metrics(
     warp::any().map(move || metrics.clone())
 }
 
-/// 请求计时中间件
-pub fn request_timer<F>(
-    filter: F,
-    metrics: Arc<MetricsRegistry>,
-) -> impl Filter<Extract = F::Extract, Error = F::Error> + Clone
-where
-    F: Filter + Clone,
-{
-    warp::any()
-        .map(move || {
-            let start = Instant::now();
-            let metrics = metrics.clone();
-            (start, metrics)
-        })
-        .and(filter)
-        .map(move |(start, metrics): (Instant, Arc<MetricsRegistry>), response| {
-            let duration = start.elapsed().as_secs_f64() * 1000.0; // 转换为毫秒
-            
-            // 异步记录指标
-            tokio::spawn(async move {
-                metrics.increment_counter("http_requests_total", 1.0).await;
-                metrics.record_histogram("http_request_duration", duration).await;
-            });
-            
-            response
-        })
-}
\ No newline at end of file
+/// 请求计时：用于WebSocket消息处理、AI任务处理等不经过HTTP过滤器的路径
+pub fn request_timer(histograms: &Arc<Histograms>, name: &'static str) -> RequestTimer {
+    RequestTimer {
+        histograms: histograms.clone(),
+        name,
+        started: Instant::now(),
+    }
+}
+
+/// 处理耗时计时器，调用 finish 时把耗时记入直方图；中途放弃的处理不计入
+pub struct RequestTimer {
+    histograms: Arc<Histograms>,
+    name: &'static str,
+    started: Instant,
+}
+
+impl RequestTimer {
+    /// 结束计时并记录，返回本次耗时
+    pub fn finish(self) -> Duration {
+        let elapsed = self.started.elapsed();
+        self.histograms.record_duration(self.name, elapsed);
+        elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_timer_records_only_finished_work() {
+        let histograms = Arc::new(Histograms::default());
+
+        let elapsed = request_timer(&histograms, "handling_ms").finish();
+        let snapshot = histograms.get("handling_ms").unwrap();
+        assert_eq!(snapshot.count, 1);
+        assert_eq!(snapshot.sum, elapsed.as_secs_f64() * 1000.0);
+
+        // 放弃的计时不产生样本
+        drop(request_timer(&histograms, "abandoned_ms"));
+        assert!(histograms.get("abandoned_ms").is_none());
+    }
+}
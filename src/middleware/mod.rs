@@ -1,4 +1,7 @@
 /// 中间件模块
+pub mod client_ip;
+pub mod cors;
 pub mod metrics;
+pub mod request_id;
 
-pub use metrics::with_metrics;
\ No newline at end of file
+pub use metrics::with_metrics;
@@ -0,0 +1,152 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tracing::Instrument;
+use uuid::Uuid;
+use warp::http::{HeaderMap, HeaderValue, Request, Response};
+use warp::hyper::service::Service;
+use warp::hyper::Body;
+use warp::Filter;
+
+/// 请求ID头，上游（如增强后端代理）传入时沿用，否则由本服务生成
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 上游传入的请求ID长度上限，超长或含非法字符时重新生成
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 请求处理期间可取到的上下文
+#[derive(Debug, Clone)]
+struct RequestContext {
+    request_id: String,
+    remote_addr: Option<SocketAddr>,
+}
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+/// 当前请求的ID，不在HTTP请求处理流程中（如后台任务）时返回 None
+pub fn current_request_id() -> Option<String> {
+    REQUEST_CONTEXT.try_with(|context| context.request_id.clone()).ok()
+}
+
+/// 对端地址。经 with_request_id 接入时 warp::addr::remote 取不到连接地址，改从请求上下文读取
+pub fn remote_addr() -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone {
+    warp::addr::remote().map(|remote: Option<SocketAddr>| {
+        remote.or_else(|| REQUEST_CONTEXT.try_with(|context| context.remote_addr).ok().flatten())
+    })
+}
+
+/// 取请求头中的 X-Request-Id，没有或不合法时生成新的
+pub fn resolve_request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// 为一次HTTP请求挂上请求ID：处理期间的日志都在带 request_id 的 span 中，
+/// 错误响应体（见 handle_rejection 和 ApiResponse）和响应头带上同一个ID
+pub async fn with_request_id<S>(
+    mut service: S,
+    remote_addr: Option<SocketAddr>,
+    mut request: Request<Body>,
+) -> Result<Response<Body>, S::Error>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+{
+    let request_id = resolve_request_id(request.headers());
+    let header = HeaderValue::from_str(&request_id).ok();
+    if let Some(header) = header.clone() {
+        request.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        remote_addr = ?remote_addr,
+    );
+    let context = RequestContext { request_id, remote_addr };
+    let mut response = REQUEST_CONTEXT
+        .scope(context, async move { service.call(request).await }.instrument(span))
+        .await?;
+
+    if let Some(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::handle_rejection;
+    use crate::types::api::ApiResponse;
+    use warp::Filter;
+
+    async fn call(path: &str, request_id: Option<&str>) -> (String, serde_json::Value) {
+        let routes = warp::path!("api" / "fail")
+            .map(|| {
+                let response: ApiResponse<()> = ApiResponse::error("处理失败".to_string());
+                warp::reply::json(&response)
+            })
+            .or(warp::path!("api" / "addr").and(remote_addr()).map(|addr: Option<SocketAddr>| {
+                warp::reply::json(&serde_json::json!({ "remote_addr": addr }))
+            }))
+            .recover(handle_rejection);
+
+        let mut request = Request::builder().uri(path);
+        if let Some(id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let remote = SocketAddr::from(([203, 0, 113, 7], 50000));
+        let response = with_request_id(warp::service(routes), Some(remote), request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let header = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        let body = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (header, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_resolve_request_id_propagates_or_generates() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("proxy-123"));
+        assert_eq!(resolve_request_id(&headers), "proxy-123");
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("bad id<script>"));
+        let generated = resolve_request_id(&headers);
+        assert_ne!(generated, "bad id<script>");
+        assert!(Uuid::parse_str(&generated).is_ok());
+
+        assert!(Uuid::parse_str(&resolve_request_id(&HeaderMap::new())).is_ok());
+        assert_eq!(current_request_id(), None);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_in_error_responses() {
+        // 上游传入的ID原样透传到响应头和错误响应体
+        let (header, body) = call("/api/fail", Some("proxy-123")).await;
+        assert_eq!(header, "proxy-123");
+        assert_eq!(body["success"], false);
+        assert_eq!(body["request_id"], "proxy-123");
+
+        // 经 handle_rejection 的错误同样带上生成的ID
+        let (header, body) = call("/api/missing", None).await;
+        assert_eq!(body["code"], 404);
+        assert_eq!(body["request_id"], header.as_str());
+
+        // 路由中仍能取到对端地址
+        let (_, body) = call("/api/addr", None).await;
+        assert_eq!(body["remote_addr"], "203.0.113.7:50000");
+    }
+}
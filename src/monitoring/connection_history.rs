@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// 默认采样间隔
+pub const DEFAULT_SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 默认保留的采样点数量（按分钟采样保留一天）
+pub const DEFAULT_HISTORY_CAPACITY: usize = 24 * 60;
+
+/// 一次连接数采样
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ConnectionSample {
+    pub timestamp: DateTime<Utc>,
+    pub total_connections: usize,
+    pub kefu_connections: usize,
+    pub kehu_connections: usize,
+}
+
+/// 连接数时间序列，内存环形缓冲，超出容量时丢弃最早的采样
+pub struct ConnectionHistory {
+    samples: Mutex<VecDeque<ConnectionSample>>,
+    capacity: usize,
+}
+
+impl Default for ConnectionHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl ConnectionHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn record(&self, sample: ConnectionSample) {
+        if let Ok(mut samples) = self.samples.lock() {
+            if samples.len() >= self.capacity {
+                samples.pop_front();
+            }
+            samples.push_back(sample);
+        }
+    }
+
+    /// 返回 [from, to] 区间内的采样，按时间升序
+    pub fn range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<ConnectionSample> {
+        match self.samples.lock() {
+            Ok(samples) => samples
+                .iter()
+                .filter(|sample| sample.timestamp >= from && sample.timestamp <= to)
+                .cloned()
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn sample(timestamp: DateTime<Utc>, kefu: usize, kehu: usize) -> ConnectionSample {
+        ConnectionSample {
+            timestamp,
+            total_connections: kefu + kehu,
+            kefu_connections: kefu,
+            kehu_connections: kehu,
+        }
+    }
+
+    #[test]
+    fn test_query_recorded_samples() {
+        let history = ConnectionHistory::new(10);
+        let start = Utc::now();
+        for i in 0..5 {
+            history.record(sample(start + Duration::minutes(i), 1, i as usize));
+        }
+
+        let series = history.range(start + Duration::minutes(1), start + Duration::minutes(3));
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].kehu_connections, 1);
+        assert_eq!(series[2].total_connections, 4);
+        assert!(history.range(start - Duration::hours(2), start - Duration::hours(1)).is_empty());
+    }
+
+    #[test]
+    fn test_ring_buffer_drops_oldest() {
+        let history = ConnectionHistory::new(3);
+        let start = Utc::now();
+        for i in 0..5 {
+            history.record(sample(start + Duration::minutes(i), 0, i as usize));
+        }
+
+        let series = history.range(start, start + Duration::minutes(10));
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].kehu_connections, 2);
+    }
+}
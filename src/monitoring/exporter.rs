@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use warp::Filter;
+use crate::middleware::with_metrics;
 use crate::monitoring::metrics::{MetricsRegistry, MetricType};
 
 /// Prometheus格式导出器
@@ -14,52 +16,57 @@ impl PrometheusExporter {
     
     /// 创建metrics端点路由
     pub fn routes(&self) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-        let metrics = self.metrics.clone();
-        
         warp::path("metrics")
+            .and(warp::path::end())
             .and(warp::get())
-            .and_then(move || {
-                let metrics = metrics.clone();
-                async move {
-                    let output = Self::format_metrics(&metrics).await;
-                    Ok::<_, warp::Rejection>(warp::reply::with_header(
-                        output,
-                        "Content-Type",
-                        "text/plain; version=0.0.4"
-                    ))
-                }
+            .and(with_metrics(self.metrics.clone()))
+            .and_then(|metrics: Arc<MetricsRegistry>| async move {
+                let output = Self::format_metrics(&metrics).await;
+                Ok::<_, warp::Rejection>(warp::reply::with_header(
+                    output,
+                    "Content-Type",
+                    "text/plain; version=0.0.4"
+                ))
             })
     }
     
     /// 格式化为Prometheus格式
     async fn format_metrics(metrics: &Arc<MetricsRegistry>) -> String {
         let mut output = String::new();
+        let mut previous_name = String::new();
         
         for metric in metrics.get_all_metrics().await {
-            // 写入HELP信息
-            output.push_str(&format!("# HELP {} {}\n", metric.name, metric.help));
-            
-            // 写入TYPE信息
-            let type_str = match &metric.metric_type {
-                MetricType::Counter(_) => "counter",
-                MetricType::Gauge(_) => "gauge",
-                MetricType::Histogram(_) => "histogram",
-                MetricType::Summary { .. } => "summary",
-            };
-            output.push_str(&format!("# TYPE {} {}\n", metric.name, type_str));
+            // 同名的带标签指标只写一次HELP和TYPE
+            if metric.name != previous_name {
+                // 写入HELP信息
+                output.push_str(&format!("# HELP {} {}\n", metric.name, metric.help));
+
+                // 写入TYPE信息
+                let type_str = match &metric.metric_type {
+                    MetricType::Counter(_) => "counter",
+                    MetricType::Gauge(_) => "gauge",
+                    MetricType::Histogram(_) => "histogram",
+                    MetricType::Summary { .. } => "summary",
+                };
+                output.push_str(&format!("# TYPE {} {}\n", metric.name, type_str));
+                previous_name = metric.name.clone();
+            }
             
             // 写入指标值
             match &metric.metric_type {
                 MetricType::Counter(value) | MetricType::Gauge(value) => {
-                    output.push_str(&format!("{} {}\n", metric.name, value));
+                    output.push_str(&format!("{}{} {}\n", metric.name, format_labels(&metric.labels), value));
                 }
-                MetricType::Histogram(values) => {
-                    if !values.is_empty() {
-                        let count = values.len();
-                        let sum: f64 = values.iter().sum();
-                        output.push_str(&format!("{}_count {}\n", metric.name, count));
-                        output.push_str(&format!("{}_sum {}\n", metric.name, sum));
+                MetricType::Histogram(histogram) => {
+                    let mut labels = metric.labels.clone();
+                    for (bound, count) in &histogram.buckets {
+                        labels.insert("le".to_string(), bound.to_string());
+                        output.push_str(&format!("{}_bucket{} {}\n", metric.name, format_labels(&labels), count));
                     }
+                    labels.insert("le".to_string(), "+Inf".to_string());
+                    output.push_str(&format!("{}_bucket{} {}\n", metric.name, format_labels(&labels), histogram.count));
+                    output.push_str(&format!("{}_sum{} {}\n", metric.name, format_labels(&metric.labels), histogram.sum));
+                    output.push_str(&format!("{}_count{} {}\n", metric.name, format_labels(&metric.labels), histogram.count));
                 }
                 MetricType::Summary { count, sum, .. } => {
                     output.push_str(&format!("{}_count {}\n", metric.name, count));
@@ -70,4 +77,65 @@ impl PrometheusExporter {
         
         output
     }
+}
+
+// 标签按名称排序输出，如 {direction="inbound",type="Chat"}
+fn format_labels(labels: &HashMap<String, String>) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let mut pairs: Vec<_> = labels.iter().collect();
+    pairs.sort();
+    let pairs: Vec<String> = pairs
+        .into_iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitoring::metrics::{MessageTypeCounters, AI_TASK_PROCESSING_MS, INBOUND, OUTBOUND};
+
+    #[tokio::test]
+    async fn test_metrics_output_has_message_type_breakdown() {
+        let counters = Arc::new(MessageTypeCounters::default());
+        counters.record(INBOUND, "Chat");
+        counters.record(INBOUND, "Chat");
+        counters.record(OUTBOUND, "System");
+        let registry = Arc::new(MetricsRegistry::new().with_message_counters(counters));
+
+        let response = warp::test::request()
+            .path("/metrics")
+            .reply(&PrometheusExporter::new(registry).routes())
+            .await;
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+
+        assert!(body.contains("websocket_messages_total{direction=\"inbound\",type=\"Chat\"} 2\n"));
+        assert!(body.contains("websocket_messages_total{direction=\"outbound\",type=\"System\"} 1\n"));
+        assert_eq!(body.matches("# TYPE websocket_messages_total counter").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_histogram_exported_with_buckets() {
+        let registry = Arc::new(MetricsRegistry::new());
+        registry.record_histogram(AI_TASK_PROCESSING_MS, 30.0);
+        registry.record_histogram(AI_TASK_PROCESSING_MS, 700.0);
+
+        let response = warp::test::request()
+            .path("/metrics")
+            .reply(&PrometheusExporter::new(registry).routes())
+            .await;
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+
+        assert!(body.contains("# TYPE ai_task_processing_ms histogram\n"));
+        assert!(body.contains("ai_task_processing_ms_bucket{le=\"25\"} 0\n"));
+        assert!(body.contains("ai_task_processing_ms_bucket{le=\"50\"} 1\n"));
+        assert!(body.contains("ai_task_processing_ms_bucket{le=\"1000\"} 2\n"));
+        assert!(body.contains("ai_task_processing_ms_bucket{le=\"+Inf\"} 2\n"));
+        assert!(body.contains("ai_task_processing_ms_sum 730\n"));
+        assert!(body.contains("ai_task_processing_ms_count 2\n"));
+        assert!(body.contains("ai_task_processing_ms_quantile{quantile=\"0.99\"} 700\n"));
+    }
 }
\ No newline at end of file
@@ -1,15 +1,22 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use serde::{Serialize, Deserialize};
+use crate::cache::analytics::AnalyticsCacheCounters;
+
+/// 客户端发给服务器的消息
+pub const INBOUND: &str = "inbound";
+/// 服务器推送给客户端的消息
+pub const OUTBOUND: &str = "outbound";
 
 /// 指标类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MetricType {
     Counter(f64),
     Gauge(f64),
-    Histogram(Vec<f64>),
+    Histogram(HistogramSnapshot),
     Summary {
         count: u64,
         sum: f64,
@@ -27,17 +34,393 @@ pub struct Metric {
     pub timestamp: Instant,
 }
 
+/// 按方向和消息类型分组的WebSocket消息计数，收发路径上同步累加
+#[derive(Debug, Default)]
+pub struct MessageTypeCounters {
+    counts: std::sync::Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+}
+
+impl MessageTypeCounters {
+    pub fn record(&self, direction: &'static str, message_type: &'static str) {
+        *self.counts.lock().unwrap().entry((direction, message_type)).or_insert(0) += 1;
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, direction: &str, message_type: &str) -> u64 {
+        self.counts.lock().unwrap().get(&(direction, message_type)).copied().unwrap_or(0)
+    }
+
+    /// 按 (方向, 类型) 排序的计数快照
+    pub fn snapshot(&self) -> Vec<(&'static str, &'static str, u64)> {
+        self.counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&(direction, message_type), &count)| (direction, message_type, count))
+            .collect()
+    }
+}
+
+/// 单个连接累计的上下行字节数（以服务端为视角，in 为客户端上行）
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+pub struct ConnectionBandwidth {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl ConnectionBandwidth {
+    pub fn total(&self) -> u64 {
+        self.bytes_in + self.bytes_out
+    }
+}
+
+#[derive(Debug, Default)]
+struct BandwidthEntry {
+    usage: ConnectionBandwidth,
+    // 上次带宽检查时的累计字节数
+    checked: u64,
+}
+
+/// WebSocket收发字节统计：全局累计和按连接累计，连接断开后移除该连接的记录
+#[derive(Debug, Default)]
+pub struct BandwidthCounters {
+    total_in: AtomicU64,
+    total_out: AtomicU64,
+    connections: std::sync::Mutex<HashMap<String, BandwidthEntry>>,
+}
+
+impl BandwidthCounters {
+    /// 连接建立时开始按连接统计，重连时从零开始
+    pub fn open(&self, user_id: &str) {
+        self.connections.lock().unwrap().insert(user_id.to_string(), BandwidthEntry::default());
+    }
+
+    /// 累计一帧的字节数；连接已移除时只计入全局
+    pub fn record(&self, user_id: &str, direction: &str, bytes: usize) {
+        let bytes = bytes as u64;
+        let total = if direction == INBOUND { &self.total_in } else { &self.total_out };
+        total.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(entry) = self.connections.lock().unwrap().get_mut(user_id) {
+            if direction == INBOUND {
+                entry.usage.bytes_in += bytes;
+            } else {
+                entry.usage.bytes_out += bytes;
+            }
+        }
+    }
+
+    /// 全部连接（含已断开）的累计字节数
+    pub fn totals(&self) -> ConnectionBandwidth {
+        ConnectionBandwidth {
+            bytes_in: self.total_in.load(Ordering::Relaxed),
+            bytes_out: self.total_out.load(Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn connection(&self, user_id: &str) -> Option<ConnectionBandwidth> {
+        self.connections.lock().unwrap().get(user_id).map(|entry| entry.usage)
+    }
+
+    /// 当前在线连接的字节数
+    pub fn snapshot(&self) -> HashMap<String, ConnectionBandwidth> {
+        self.connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(user_id, entry)| (user_id.clone(), entry.usage))
+            .collect()
+    }
+
+    pub fn remove(&self, user_id: &str) {
+        self.connections.lock().unwrap().remove(user_id);
+    }
+
+    /// 自上次检查以来收发字节数超过 limit 的连接，按字节数从大到小排列；每次调用重新开始计数
+    pub fn take_heavy_connections(&self, limit: u64) -> Vec<(String, u64)> {
+        let mut heavy: Vec<(String, u64)> = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter_map(|(user_id, entry)| {
+                let total = entry.usage.total();
+                let delta = total - entry.checked;
+                entry.checked = total;
+                (delta > limit).then(|| (user_id.clone(), delta))
+            })
+            .collect();
+        heavy.sort_by_key(|(_, delta)| std::cmp::Reverse(*delta));
+        heavy
+    }
+}
+
+/// 计算确认耗时分位数时保留的最近样本数
+const ACK_LATENCY_SAMPLES: usize = 1000;
+
+/// 需确认消息的送达确认统计快照
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+pub struct DeliveryAckStats {
+    pub acked: u64,
+    /// 超时未确认或投递失败的消息数
+    pub unacked: u64,
+    /// 已推送、尚在等待确认的消息数
+    pub pending: u64,
+    pub avg_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    /// 未确认数占已有结果（确认+未确认）的比例，没有结果时为0
+    pub unacked_rate: f64,
+}
+
+/// 需确认消息的端到端确认统计：交给接收方连接的发送队列前开始计时，接收方回复 Ack 或已读回执时记录耗时，
+/// 超时仍未确认或投递失败的计为未确认
+#[derive(Debug, Default)]
+pub struct DeliveryAckTracker {
+    pending: std::sync::Mutex<HashMap<String, Instant>>,
+    acked: AtomicU64,
+    unacked: AtomicU64,
+    latency_ms_sum: AtomicU64,
+    recent_latencies: std::sync::Mutex<VecDeque<u64>>,
+    // 上次检查以来的 (确认数, 未确认数)
+    window: std::sync::Mutex<(u64, u64)>,
+}
+
+impl DeliveryAckTracker {
+    /// 消息推送到接收方连接，重复推送时沿用首次推送的时间
+    pub fn sent(&self, message_id: &str, at: Instant) {
+        self.pending.lock().unwrap().entry(message_id.to_string()).or_insert(at);
+    }
+
+    /// 接收方确认，返回发送到确认的耗时；没有等待中的记录（已确认或已超时）时返回 None
+    pub fn acked(&self, message_id: &str, at: Instant) -> Option<Duration> {
+        let sent_at = self.pending.lock().unwrap().remove(message_id)?;
+        let latency = at.saturating_duration_since(sent_at);
+        let latency_ms = latency.as_millis() as u64;
+        self.acked.fetch_add(1, Ordering::Relaxed);
+        self.latency_ms_sum.fetch_add(latency_ms, Ordering::Relaxed);
+        let mut recent = self.recent_latencies.lock().unwrap();
+        recent.push_back(latency_ms);
+        if recent.len() > ACK_LATENCY_SAMPLES {
+            recent.pop_front();
+        }
+        drop(recent);
+        self.window.lock().unwrap().0 += 1;
+        Some(latency)
+    }
+
+    /// 投递失败，等待中的消息直接计为未确认
+    pub fn failed(&self, message_id: &str) {
+        if self.pending.lock().unwrap().remove(message_id).is_some() {
+            self.record_unacked(1);
+        }
+    }
+
+    /// 推送后超过 timeout 仍未确认的消息计为未确认，返回本次新增的数量
+    pub fn expire(&self, timeout: Duration, now: Instant) -> u64 {
+        let mut pending = self.pending.lock().unwrap();
+        let before = pending.len();
+        pending.retain(|_, sent_at| now.saturating_duration_since(*sent_at) < timeout);
+        let expired = (before - pending.len()) as u64;
+        drop(pending);
+        self.record_unacked(expired);
+        expired
+    }
+
+    /// 自上次检查以来的 (确认数, 未确认数)，每次调用重新计数
+    pub fn take_window(&self) -> (u64, u64) {
+        std::mem::take(&mut *self.window.lock().unwrap())
+    }
+
+    pub fn stats(&self) -> DeliveryAckStats {
+        let acked = self.acked.load(Ordering::Relaxed);
+        let unacked = self.unacked.load(Ordering::Relaxed);
+        let mut recent: Vec<u64> = self.recent_latencies.lock().unwrap().iter().copied().collect();
+        recent.sort_unstable();
+        let p95_latency_ms = match recent.len() {
+            0 => 0.0,
+            len => recent[((len as f64 * 0.95).ceil() as usize).clamp(1, len) - 1] as f64,
+        };
+        DeliveryAckStats {
+            acked,
+            unacked,
+            pending: self.pending.lock().unwrap().len() as u64,
+            avg_latency_ms: if acked == 0 {
+                0.0
+            } else {
+                self.latency_ms_sum.load(Ordering::Relaxed) as f64 / acked as f64
+            },
+            p95_latency_ms,
+            unacked_rate: unacked_rate(acked, unacked),
+        }
+    }
+
+    fn record_unacked(&self, count: u64) {
+        if count > 0 {
+            self.unacked.fetch_add(count, Ordering::Relaxed);
+            self.window.lock().unwrap().1 += count;
+        }
+    }
+}
+
+/// 未确认数占已有结果的比例
+pub fn unacked_rate(acked: u64, unacked: u64) -> f64 {
+    match acked + unacked {
+        0 => 0.0,
+        total => unacked as f64 / total as f64,
+    }
+}
+
+/// WebSocket消息从收到到处理完成的耗时（毫秒）
+pub const WS_MESSAGE_HANDLING_MS: &str = "websocket_message_handling_ms";
+/// AI任务从开始处理到得到结果的耗时（毫秒）
+pub const AI_TASK_PROCESSING_MS: &str = "ai_task_processing_ms";
+
+/// 耗时直方图的分桶上界（毫秒）
+pub const DEFAULT_LATENCY_BUCKETS: [f64; 12] = [
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+/// 计算直方图分位数时保留的最近样本数
+const HISTOGRAM_SAMPLES: usize = 1000;
+
+/// 直方图快照，buckets 为 (上界, 不超过该上界的累计样本数)，与Prometheus的 le 分桶一致
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<(f64, u64)>,
+    pub count: u64,
+    pub sum: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+#[derive(Debug)]
+struct Histogram {
+    bounds: Vec<f64>,
+    // 各桶内（非累计）的样本数，最后一个为超过所有上界的样本
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+    recent: VecDeque<f64>,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0.0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, value: f64) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[index] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.recent.push_back(value);
+        if self.recent.len() > HISTOGRAM_SAMPLES {
+            self.recent.pop_front();
+        }
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .bounds
+            .iter()
+            .zip(&self.counts)
+            .map(|(bound, count)| {
+                cumulative += count;
+                (*bound, cumulative)
+            })
+            .collect();
+        let mut recent: Vec<f64> = self.recent.iter().copied().collect();
+        recent.sort_by(f64::total_cmp);
+        HistogramSnapshot {
+            buckets,
+            count: self.count,
+            sum: self.sum,
+            p50: percentile(&recent, 0.5),
+            p90: percentile(&recent, 0.9),
+            p99: percentile(&recent, 0.99),
+        }
+    }
+}
+
+/// 已排序样本的分位数（取不小于该比例的最近秩），没有样本时为0
+pub fn percentile(sorted: &[f64], quantile: f64) -> f64 {
+    match sorted.len() {
+        0 => 0.0,
+        len => sorted[((len as f64 * quantile).ceil() as usize).clamp(1, len) - 1],
+    }
+}
+
+/// 按名称区分的耗时直方图，WebSocket管理器和AI管理器记录，由 /metrics 导出
+#[derive(Debug, Default)]
+pub struct Histograms {
+    histograms: std::sync::Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Histograms {
+    /// 记录一个样本，首次出现的名称使用默认的耗时分桶
+    pub fn record(&self, name: &str, value: f64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        match histograms.get_mut(name) {
+            Some(histogram) => histogram.record(value),
+            None => {
+                let mut histogram = Histogram::new(&DEFAULT_LATENCY_BUCKETS);
+                histogram.record(value);
+                histograms.insert(name.to_string(), histogram);
+            }
+        }
+    }
+
+    /// 以毫秒记录耗时
+    pub fn record_duration(&self, name: &str, duration: Duration) {
+        self.record(name, duration.as_secs_f64() * 1000.0);
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, name: &str) -> Option<HistogramSnapshot> {
+        self.histograms.lock().unwrap().get(name).map(Histogram::snapshot)
+    }
+
+    /// 按名称排序的快照
+    pub fn snapshot(&self) -> Vec<(String, HistogramSnapshot)> {
+        self.histograms
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, histogram)| (name.clone(), histogram.snapshot()))
+            .collect()
+    }
+}
+
 /// 指标注册中心
 pub struct MetricsRegistry {
     metrics: Arc<RwLock<HashMap<String, Metric>>>,
     
     // 预定义的系统指标
     pub http_requests_total: Arc<RwLock<f64>>,
-    pub http_request_duration: Arc<RwLock<Vec<f64>>>,
     pub websocket_connections: Arc<RwLock<f64>>,
     pub message_processed_total: Arc<RwLock<f64>>,
     pub redis_operations_total: Arc<RwLock<f64>>,
-    pub redis_operation_duration: Arc<RwLock<Vec<f64>>>,
+    /// 请求、消息处理和AI任务等耗时分布
+    pub histograms: Arc<Histograms>,
+    pub websocket_messages: Arc<MessageTypeCounters>,
+    pub websocket_bandwidth: Arc<BandwidthCounters>,
+    pub websocket_acks: Arc<DeliveryAckTracker>,
+    /// 离线队列超出长度上限丢弃的消息数
+    pub message_queue_dropped: Arc<AtomicU64>,
+    /// 统计分析接口缓存的命中情况
+    pub analytics_cache: Arc<AnalyticsCacheCounters>,
 }
 
 impl MetricsRegistry {
@@ -45,13 +428,53 @@ impl MetricsRegistry {
         Self {
             metrics: Arc::new(RwLock::new(HashMap::new())),
             http_requests_total: Arc::new(RwLock::new(0.0)),
-            http_request_duration: Arc::new(RwLock::new(Vec::new())),
             websocket_connections: Arc::new(RwLock::new(0.0)),
             message_processed_total: Arc::new(RwLock::new(0.0)),
             redis_operations_total: Arc::new(RwLock::new(0.0)),
-            redis_operation_duration: Arc::new(RwLock::new(Vec::new())),
+            histograms: Arc::new(Histograms::default()),
+            websocket_messages: Arc::new(MessageTypeCounters::default()),
+            websocket_bandwidth: Arc::new(BandwidthCounters::default()),
+            websocket_acks: Arc::new(DeliveryAckTracker::default()),
+            message_queue_dropped: Arc::new(AtomicU64::new(0)),
+            analytics_cache: Arc::new(AnalyticsCacheCounters::default()),
         }
     }
+
+    /// 使用WebSocket管理器的消息计数，使 /metrics 导出实际收发的消息数
+    pub fn with_message_counters(mut self, counters: Arc<MessageTypeCounters>) -> Self {
+        self.websocket_messages = counters;
+        self
+    }
+
+    /// 使用WebSocket管理器的字节统计
+    pub fn with_bandwidth_counters(mut self, counters: Arc<BandwidthCounters>) -> Self {
+        self.websocket_bandwidth = counters;
+        self
+    }
+
+    /// 使用WebSocket管理器的消息确认统计
+    pub fn with_ack_tracker(mut self, tracker: Arc<DeliveryAckTracker>) -> Self {
+        self.websocket_acks = tracker;
+        self
+    }
+
+    /// 使用消息队列管理器的溢出丢弃计数
+    pub fn with_queue_dropped_counter(mut self, dropped: Arc<AtomicU64>) -> Self {
+        self.message_queue_dropped = dropped;
+        self
+    }
+
+    /// 使用WebSocket管理器的统计分析缓存计数
+    pub fn with_analytics_cache_counters(mut self, counters: Arc<AnalyticsCacheCounters>) -> Self {
+        self.analytics_cache = counters;
+        self
+    }
+
+    /// 使用WebSocket管理器和AI管理器共同记录的耗时直方图
+    pub fn with_histograms(mut self, histograms: Arc<Histograms>) -> Self {
+        self.histograms = histograms;
+        self
+    }
     
     /// 增加计数器
     pub async fn increment_counter(&self, name: &str, value: f64) {
@@ -80,26 +503,9 @@ impl MetricsRegistry {
         }
     }
     
-    /// 记录直方图值
-    pub async fn record_histogram(&self, name: &str, value: f64) {
-        match name {
-            "http_request_duration" => {
-                let mut hist = self.http_request_duration.write().await;
-                hist.push(value);
-                // 保持最近1000个样本
-                if hist.len() > 1000 {
-                    hist.remove(0);
-                }
-            }
-            "redis_operation_duration" => {
-                let mut hist = self.redis_operation_duration.write().await;
-                hist.push(value);
-                if hist.len() > 1000 {
-                    hist.remove(0);
-                }
-            }
-            _ => {}
-        }
+    /// 记录直方图值，按名称分桶并计算 p50/p90/p99
+    pub fn record_histogram(&self, name: &str, value: f64) {
+        self.histograms.record(name, value);
     }
     
     /// 获取所有指标
@@ -132,7 +538,258 @@ impl MetricsRegistry {
             labels: HashMap::new(),
             timestamp: Instant::now(),
         });
+
+        // 按类型细分的WebSocket消息数
+        for (direction, message_type, count) in self.websocket_messages.snapshot() {
+            metrics.push(Metric {
+                name: "websocket_messages_total".to_string(),
+                help: "Total number of WebSocket messages by direction and type".to_string(),
+                metric_type: MetricType::Counter(count as f64),
+                labels: HashMap::from([
+                    ("direction".to_string(), direction.to_string()),
+                    ("type".to_string(), message_type.to_string()),
+                ]),
+                timestamp: Instant::now(),
+            });
+        }
+
+        // WebSocket收发字节数
+        let totals = self.websocket_bandwidth.totals();
+        for (direction, bytes) in [(INBOUND, totals.bytes_in), (OUTBOUND, totals.bytes_out)] {
+            metrics.push(Metric {
+                name: "websocket_bytes_total".to_string(),
+                help: "Total number of WebSocket frame bytes by direction".to_string(),
+                metric_type: MetricType::Counter(bytes as f64),
+                labels: HashMap::from([("direction".to_string(), direction.to_string())]),
+                timestamp: Instant::now(),
+            });
+        }
+
+        // 需确认消息的确认结果与发送到确认的耗时
+        let acks = self.websocket_acks.stats();
+        for (result, count) in [("acked", acks.acked), ("unacked", acks.unacked)] {
+            metrics.push(Metric {
+                name: "websocket_message_acks_total".to_string(),
+                help: "Total number of acknowledgement-tracked WebSocket messages by result".to_string(),
+                metric_type: MetricType::Counter(count as f64),
+                labels: HashMap::from([("result".to_string(), result.to_string())]),
+                timestamp: Instant::now(),
+            });
+        }
+        metrics.push(Metric {
+            name: "websocket_message_ack_pending".to_string(),
+            help: "Number of pushed WebSocket messages awaiting acknowledgement".to_string(),
+            metric_type: MetricType::Gauge(acks.pending as f64),
+            labels: HashMap::new(),
+            timestamp: Instant::now(),
+        });
+        metrics.push(Metric {
+            name: "websocket_message_ack_latency_ms".to_string(),
+            help: "Latency from push to acknowledgement of WebSocket messages in milliseconds".to_string(),
+            metric_type: MetricType::Summary {
+                count: acks.acked,
+                sum: self.websocket_acks.latency_ms_sum.load(Ordering::Relaxed) as f64,
+                quantiles: HashMap::from([("0.95".to_string(), acks.p95_latency_ms)]),
+            },
+            labels: HashMap::new(),
+            timestamp: Instant::now(),
+        });
+
+        metrics.push(Metric {
+            name: "message_queue_dropped_total".to_string(),
+            help: "Total number of offline queue messages dropped because the queue exceeded its max length".to_string(),
+            metric_type: MetricType::Counter(self.message_queue_dropped.load(Ordering::Relaxed) as f64),
+            labels: HashMap::new(),
+            timestamp: Instant::now(),
+        });
+
+        for (result, count) in self.analytics_cache.snapshot() {
+            metrics.push(Metric {
+                name: "analytics_cache_requests_total".to_string(),
+                help: "Total number of analytics dashboard requests by cache result".to_string(),
+                metric_type: MetricType::Counter(count as f64),
+                labels: HashMap::from([("result".to_string(), result.to_string())]),
+                timestamp: Instant::now(),
+            });
+        }
+
+        // 耗时分布，另以仪表导出最近样本的分位数
+        for (name, histogram) in self.histograms.snapshot() {
+            let quantiles = [("0.5", histogram.p50), ("0.9", histogram.p90), ("0.99", histogram.p99)];
+            metrics.push(Metric {
+                name: name.clone(),
+                help: format!("Distribution of {}", name),
+                metric_type: MetricType::Histogram(histogram),
+                labels: HashMap::new(),
+                timestamp: Instant::now(),
+            });
+            for (quantile, value) in quantiles {
+                metrics.push(Metric {
+                    name: format!("{}_quantile", name),
+                    help: format!("Quantiles of the most recent {} samples of {}", HISTOGRAM_SAMPLES, name),
+                    metric_type: MetricType::Gauge(value),
+                    labels: HashMap::from([("quantile".to_string(), quantile.to_string())]),
+                    timestamp: Instant::now(),
+                });
+            }
+        }
         
         metrics
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_message_counts_by_type() {
+        let counters = Arc::new(MessageTypeCounters::default());
+        for _ in 0..3 {
+            counters.record(INBOUND, "Chat");
+        }
+        counters.record(INBOUND, "Typing");
+        counters.record(OUTBOUND, "Chat");
+        counters.record(OUTBOUND, "VoiceMessage");
+        counters.record(OUTBOUND, "VoiceMessage");
+
+        assert_eq!(counters.get(INBOUND, "Chat"), 3);
+        assert_eq!(counters.get(INBOUND, "Typing"), 1);
+        assert_eq!(counters.get(OUTBOUND, "Chat"), 1);
+        assert_eq!(counters.get(OUTBOUND, "VoiceMessage"), 2);
+        assert_eq!(counters.get(INBOUND, "System"), 0);
+
+        let registry = MetricsRegistry::new().with_message_counters(counters);
+        let by_type: Vec<(String, String, f64)> = registry
+            .get_all_metrics()
+            .await
+            .into_iter()
+            .filter(|metric| metric.name == "websocket_messages_total")
+            .map(|metric| match metric.metric_type {
+                MetricType::Counter(value) => (metric.labels["direction"].clone(), metric.labels["type"].clone(), value),
+                other => panic!("unexpected metric type: {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            by_type,
+            [
+                ("inbound".to_string(), "Chat".to_string(), 3.0),
+                ("inbound".to_string(), "Typing".to_string(), 1.0),
+                ("outbound".to_string(), "Chat".to_string(), 1.0),
+                ("outbound".to_string(), "VoiceMessage".to_string(), 2.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_counted_per_connection_and_globally() {
+        let bandwidth = Arc::new(BandwidthCounters::default());
+        bandwidth.open("kehu_1");
+        bandwidth.open("kefu_1");
+        bandwidth.record("kehu_1", INBOUND, 120);
+        bandwidth.record("kehu_1", OUTBOUND, 300);
+        bandwidth.record("kehu_1", OUTBOUND, 80);
+        bandwidth.record("kefu_1", INBOUND, 50);
+
+        assert_eq!(bandwidth.connection("kehu_1"), Some(ConnectionBandwidth { bytes_in: 120, bytes_out: 380 }));
+        assert_eq!(bandwidth.connection("kefu_1"), Some(ConnectionBandwidth { bytes_in: 50, bytes_out: 0 }));
+        assert_eq!(bandwidth.totals(), ConnectionBandwidth { bytes_in: 170, bytes_out: 380 });
+
+        // 断开后不再按连接统计，全局累计保留
+        bandwidth.remove("kefu_1");
+        bandwidth.record("kefu_1", OUTBOUND, 0);
+        assert_eq!(bandwidth.connection("kefu_1"), None);
+        assert_eq!(bandwidth.snapshot().len(), 1);
+        assert_eq!(bandwidth.totals().total(), 550);
+
+        // 只有两次检查之间超过阈值的连接才告警
+        assert_eq!(bandwidth.take_heavy_connections(400), vec![("kehu_1".to_string(), 500)]);
+        assert!(bandwidth.take_heavy_connections(400).is_empty());
+        bandwidth.record("kehu_1", OUTBOUND, 10);
+        assert!(bandwidth.take_heavy_connections(400).is_empty());
+
+        let registry = MetricsRegistry::new().with_bandwidth_counters(bandwidth);
+        let bytes: Vec<(String, f64)> = registry
+            .get_all_metrics()
+            .await
+            .into_iter()
+            .filter(|metric| metric.name == "websocket_bytes_total")
+            .map(|metric| match metric.metric_type {
+                MetricType::Counter(value) => (metric.labels["direction"].clone(), value),
+                other => panic!("unexpected metric type: {:?}", other),
+            })
+            .collect();
+        assert_eq!(bytes, [("inbound".to_string(), 170.0), ("outbound".to_string(), 390.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_ack_latency_and_unacked_rate() {
+        let tracker = Arc::new(DeliveryAckTracker::default());
+        let start = Instant::now();
+        for i in 0..4 {
+            tracker.sent(&format!("msg_{}", i), start);
+        }
+        // 重复推送不重置计时
+        tracker.sent("msg_0", start + Duration::from_millis(500));
+
+        assert_eq!(tracker.acked("msg_0", start + Duration::from_millis(100)), Some(Duration::from_millis(100)));
+        assert_eq!(tracker.acked("msg_1", start + Duration::from_millis(300)), Some(Duration::from_millis(300)));
+        // 已确认的消息再次确认不重复计数
+        assert_eq!(tracker.acked("msg_0", start + Duration::from_millis(900)), None);
+        tracker.failed("msg_2");
+
+        // msg_3 超时未确认
+        assert_eq!(tracker.expire(Duration::from_secs(30), start + Duration::from_secs(10)), 0);
+        assert_eq!(tracker.expire(Duration::from_secs(30), start + Duration::from_secs(30)), 1);
+        assert_eq!(tracker.acked("msg_3", start + Duration::from_secs(31)), None);
+
+        let stats = tracker.stats();
+        assert_eq!((stats.acked, stats.unacked, stats.pending), (2, 2, 0));
+        assert_eq!(stats.avg_latency_ms, 200.0);
+        assert_eq!(stats.p95_latency_ms, 300.0);
+        assert_eq!(stats.unacked_rate, 0.5);
+        assert_eq!(tracker.take_window(), (2, 2));
+        assert_eq!(tracker.take_window(), (0, 0));
+
+        let registry = MetricsRegistry::new().with_ack_tracker(tracker);
+        let metrics = registry.get_all_metrics().await;
+        let unacked = metrics
+            .iter()
+            .find(|metric| metric.name == "websocket_message_acks_total" && metric.labels["result"] == "unacked")
+            .unwrap();
+        assert!(matches!(unacked.metric_type, MetricType::Counter(value) if value == 2.0));
+        let latency = metrics.iter().find(|metric| metric.name == "websocket_message_ack_latency_ms").unwrap();
+        assert!(matches!(latency.metric_type, MetricType::Summary { count: 2, sum, .. } if sum == 400.0));
+    }
+
+    #[tokio::test]
+    async fn test_histogram_buckets_and_percentiles() {
+        let histograms = Arc::new(Histograms::default());
+        for value in 1..=100 {
+            histograms.record(WS_MESSAGE_HANDLING_MS, value as f64);
+        }
+        histograms.record_duration(WS_MESSAGE_HANDLING_MS, Duration::from_secs(20));
+        assert!(histograms.get(AI_TASK_PROCESSING_MS).is_none());
+
+        let snapshot = histograms.get(WS_MESSAGE_HANDLING_MS).unwrap();
+        assert_eq!(snapshot.count, 101);
+        assert_eq!(snapshot.sum, 5050.0 + 20000.0);
+        // 分桶为累计计数，超过最大上界的样本只计入总数
+        assert_eq!(snapshot.buckets[0], (1.0, 1));
+        assert_eq!(snapshot.buckets[3], (25.0, 25));
+        assert_eq!(snapshot.buckets[5], (100.0, 100));
+        assert_eq!(snapshot.buckets.last(), Some(&(10000.0, 100)));
+        assert_eq!((snapshot.p50, snapshot.p90, snapshot.p99), (51.0, 91.0, 100.0));
+
+        let registry = MetricsRegistry::new().with_histograms(histograms);
+        registry.record_histogram(AI_TASK_PROCESSING_MS, 300.0);
+        let metrics = registry.get_all_metrics().await;
+        let ai = metrics.iter().find(|metric| metric.name == AI_TASK_PROCESSING_MS).unwrap();
+        assert!(matches!(&ai.metric_type, MetricType::Histogram(snapshot) if snapshot.count == 1 && snapshot.p99 == 300.0));
+        let p90 = metrics
+            .iter()
+            .find(|metric| metric.name == "websocket_message_handling_ms_quantile" && metric.labels["quantile"] == "0.9")
+            .unwrap();
+        assert!(matches!(p90.metric_type, MetricType::Gauge(value) if value == 91.0));
+    }
 }
\ No newline at end of file
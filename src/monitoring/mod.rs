@@ -5,6 +5,9 @@
 pub mod metrics;
 pub mod collector;
 pub mod exporter;
+pub mod connection_history;
+pub mod sla;
+pub mod scaling;
 
 pub use metrics::{MetricsRegistry, MetricType};
 pub use collector::PerformanceCollector;
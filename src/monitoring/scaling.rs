@@ -0,0 +1,287 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// 扩缩容采样间隔
+pub const DEFAULT_SCALING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 扩缩容策略。扩容和缩容使用不同的阈值，并要求负载持续若干个采样点、两次调整之间有冷却期，避免来回抖动
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalingPolicy {
+    /// 单个实例可承载的在线连接数
+    pub connections_per_replica: usize,
+    /// 在线数超过当前容量的该比例时扩容
+    pub scale_up_ratio: f64,
+    /// 在线数低于缩容后容量的该比例时缩容
+    pub scale_down_ratio: f64,
+    /// 客服利用率（百分比）高于该值时扩容
+    pub scale_up_utilization: f64,
+    /// 客服利用率（百分比）低于该值时才允许缩容
+    pub scale_down_utilization: f64,
+    /// 负载需要持续的采样点数
+    pub sustained_samples: usize,
+    /// 两次调整之间的最短间隔
+    pub cooldown: Duration,
+    pub min_replicas: usize,
+    pub max_replicas: usize,
+}
+
+impl Default for ScalingPolicy {
+    fn default() -> Self {
+        Self {
+            connections_per_replica: 5000,
+            scale_up_ratio: 0.8,
+            scale_down_ratio: 0.5,
+            scale_up_utilization: 85.0,
+            scale_down_utilization: 40.0,
+            // 按30秒采样，负载持续3分钟
+            sustained_samples: 6,
+            cooldown: Duration::minutes(5),
+            min_replicas: 1,
+            max_replicas: 10,
+        }
+    }
+}
+
+/// 一次负载采样
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ScalingSample {
+    pub timestamp: DateTime<Utc>,
+    pub online_users: usize,
+    /// 在线客服整体利用率（百分比）
+    pub kefu_utilization: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalingAction {
+    ScaleUp,
+    ScaleDown,
+    Hold,
+}
+
+/// 扩缩容建议，target_replicas 为建议的实例数
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ScalingRecommendation {
+    pub action: ScalingAction,
+    pub current_replicas: usize,
+    pub target_replicas: usize,
+    pub reason: String,
+    /// 决策窗口内的最高在线数与平均客服利用率
+    pub peak_online_users: usize,
+    pub average_kefu_utilization: f64,
+    pub samples: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+struct ScalerState {
+    window: VecDeque<ScalingSample>,
+    /// 假定上一次建议已被执行后的实例数
+    replicas: usize,
+    last_change: Option<DateTime<Utc>>,
+    latest: Option<ScalingRecommendation>,
+}
+
+/// 根据持续的在线数与客服利用率给出扩缩容建议。只给出建议，由部署平台执行；
+/// 给出扩缩容建议后按建议的实例数继续判断
+pub struct AutoScaler {
+    policy: ScalingPolicy,
+    state: Mutex<ScalerState>,
+}
+
+impl Default for AutoScaler {
+    fn default() -> Self {
+        Self::new(ScalingPolicy::default())
+    }
+}
+
+impl AutoScaler {
+    pub fn new(policy: ScalingPolicy) -> Self {
+        let replicas = policy.min_replicas.max(1);
+        Self {
+            policy,
+            state: Mutex::new(ScalerState {
+                window: VecDeque::new(),
+                replicas,
+                last_change: None,
+                latest: None,
+            }),
+        }
+    }
+
+    /// 记录一次采样并返回最新的建议
+    pub fn observe(&self, sample: ScalingSample) -> ScalingRecommendation {
+        let mut state = self.state.lock().unwrap();
+        let now = sample.timestamp;
+        state.window.push_back(sample);
+        while state.window.len() > self.policy.sustained_samples.max(1) {
+            state.window.pop_front();
+        }
+
+        let recommendation = self.decide(&state, now);
+        if recommendation.action != ScalingAction::Hold {
+            state.replicas = recommendation.target_replicas;
+            state.last_change = Some(now);
+            // 调整后重新累计负载，新的实例数需要重新观察
+            state.window.clear();
+        }
+        state.latest = Some(recommendation.clone());
+        recommendation
+    }
+
+    /// 最近一次采样给出的建议，尚未采样时为 None
+    pub fn latest(&self) -> Option<ScalingRecommendation> {
+        self.state.lock().unwrap().latest.clone()
+    }
+
+    fn decide(&self, state: &ScalerState, now: DateTime<Utc>) -> ScalingRecommendation {
+        let policy = &self.policy;
+        let replicas = state.replicas;
+        let peak_online_users = state.window.iter().map(|sample| sample.online_users).max().unwrap_or(0);
+        let average_kefu_utilization = if state.window.is_empty() {
+            0.0
+        } else {
+            state.window.iter().map(|sample| sample.kefu_utilization).sum::<f64>() / state.window.len() as f64
+        };
+        let recommend = |action, target_replicas, reason: String| ScalingRecommendation {
+            action,
+            current_replicas: replicas,
+            target_replicas,
+            reason,
+            peak_online_users,
+            average_kefu_utilization,
+            samples: state.window.len(),
+            timestamp: now,
+        };
+
+        if state.window.len() < policy.sustained_samples.max(1) {
+            return recommend(ScalingAction::Hold, replicas, "采样点不足，继续观察".to_string());
+        }
+        if let Some(last_change) = state.last_change {
+            if now - last_change < policy.cooldown {
+                return recommend(ScalingAction::Hold, replicas, "上次调整后仍在冷却期".to_string());
+            }
+        }
+
+        let capacity = |replicas: usize| (replicas * policy.connections_per_replica) as f64;
+        let connections_high = state
+            .window
+            .iter()
+            .all(|sample| sample.online_users as f64 > capacity(replicas) * policy.scale_up_ratio);
+        let utilization_high = state
+            .window
+            .iter()
+            .all(|sample| sample.kefu_utilization >= policy.scale_up_utilization);
+        if (connections_high || utilization_high) && replicas < policy.max_replicas {
+            // 扩容到峰值在线数处于扩缩容阈值中间的实例数，至少加一个
+            let target_ratio = (policy.scale_up_ratio + policy.scale_down_ratio) / 2.0;
+            let needed = (peak_online_users as f64 / (policy.connections_per_replica as f64 * target_ratio)).ceil() as usize;
+            let target = needed.max(replicas + 1).min(policy.max_replicas);
+            let reason = if connections_high {
+                format!("在线数持续高于容量的{:.0}%", policy.scale_up_ratio * 100.0)
+            } else {
+                format!("客服利用率持续高于{:.0}%", policy.scale_up_utilization)
+            };
+            return recommend(ScalingAction::ScaleUp, target, reason);
+        }
+
+        // 缩容每次只减一个实例，且缩容后的负载仍要低于缩容阈值
+        let connections_low = state
+            .window
+            .iter()
+            .all(|sample| (sample.online_users as f64) < capacity(replicas - 1) * policy.scale_down_ratio);
+        let utilization_low = state
+            .window
+            .iter()
+            .all(|sample| sample.kefu_utilization <= policy.scale_down_utilization);
+        if connections_low && utilization_low && replicas > policy.min_replicas.max(1) {
+            return recommend(
+                ScalingAction::ScaleDown,
+                replicas - 1,
+                format!("在线数持续低于缩容后容量的{:.0}%", policy.scale_down_ratio * 100.0),
+            );
+        }
+
+        recommend(ScalingAction::Hold, replicas, "负载在阈值范围内".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ScalingPolicy {
+        ScalingPolicy {
+            connections_per_replica: 100,
+            sustained_samples: 3,
+            cooldown: Duration::minutes(5),
+            ..ScalingPolicy::default()
+        }
+    }
+
+    fn sample(start: DateTime<Utc>, seconds: i64, online_users: usize, kefu_utilization: f64) -> ScalingSample {
+        ScalingSample {
+            timestamp: start + Duration::seconds(seconds),
+            online_users,
+            kefu_utilization,
+        }
+    }
+
+    #[test]
+    fn test_sustained_load_scales_up_with_target() {
+        let scaler = AutoScaler::new(policy());
+        let start = Utc::now();
+
+        // 短暂的峰值不触发扩容
+        assert_eq!(scaler.observe(sample(start, 0, 150, 50.0)).action, ScalingAction::Hold);
+        assert_eq!(scaler.observe(sample(start, 30, 60, 50.0)).action, ScalingAction::Hold);
+        assert_eq!(scaler.observe(sample(start, 60, 150, 50.0)).action, ScalingAction::Hold);
+
+        scaler.observe(sample(start, 90, 150, 50.0));
+        let recommendation = scaler.observe(sample(start, 120, 170, 50.0));
+        assert_eq!(recommendation.action, ScalingAction::ScaleUp);
+        assert_eq!(recommendation.current_replicas, 1);
+        // 峰值170，按65%的目标负载需要3个实例
+        assert_eq!(recommendation.target_replicas, 3);
+        assert_eq!(scaler.latest(), Some(recommendation));
+
+        // 客服利用率持续过高时也扩容
+        let scaler = AutoScaler::new(policy());
+        for i in 0..3 {
+            scaler.observe(sample(start, i * 30, 10, 95.0));
+        }
+        let recommendation = scaler.latest().unwrap();
+        assert_eq!((recommendation.action, recommendation.target_replicas), (ScalingAction::ScaleUp, 2));
+    }
+
+    #[test]
+    fn test_hysteresis_and_cooldown_prevent_flapping() {
+        let scaler = AutoScaler::new(policy());
+        let start = Utc::now();
+        for i in 0..3 {
+            scaler.observe(sample(start, i * 30, 170, 50.0));
+        }
+        assert_eq!(scaler.latest().unwrap().target_replicas, 3);
+
+        // 负载骤降，冷却期内不缩容
+        for i in 3..10 {
+            assert_eq!(scaler.observe(sample(start, i * 30, 10, 10.0)).action, ScalingAction::Hold);
+        }
+        // 冷却期后客服利用率仍高时不缩容
+        assert_eq!(scaler.observe(sample(start, 360, 10, 60.0)).action, ScalingAction::Hold);
+        assert_eq!(scaler.observe(sample(start, 390, 10, 10.0)).action, ScalingAction::Hold);
+        assert_eq!(scaler.observe(sample(start, 420, 10, 10.0)).action, ScalingAction::Hold);
+        // 在线数和利用率都持续偏低后缩容，每次减一个实例
+        let recommendation = scaler.observe(sample(start, 450, 10, 10.0));
+        assert_eq!((recommendation.action, recommendation.target_replicas), (ScalingAction::ScaleDown, 2));
+
+        // 负载介于扩缩容阈值之间时保持不变
+        let scaler = AutoScaler::new(ScalingPolicy { min_replicas: 2, ..policy() });
+        for i in 0..5 {
+            // 2个实例容量200：高于缩容阈值（1个实例的50%）且低于扩容阈值（80%）
+            let recommendation = scaler.observe(sample(start, i * 30, 120, 60.0));
+            assert_eq!(recommendation.action, ScalingAction::Hold);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::config::SlaConfig;
+
+/// 按天聚合的 SLA 数据保留天数
+pub const SLA_RETENTION_DAYS: usize = 30;
+
+/// 一个进行中会话的首响与解决情况
+#[derive(Debug, Clone)]
+struct OpenSession {
+    started_at: DateTime<Utc>,
+    customer_first_message: Option<DateTime<Utc>>,
+    first_response_ms: Option<i64>,
+    /// 最后一条消息是否来自客服，即客户的问题是否都有回复
+    last_from_kefu: bool,
+}
+
+/// 单日原始数据，会话在结束时计入其开始当天
+#[derive(Debug, Default)]
+struct DayStats {
+    sessions: usize,
+    resolved: usize,
+    /// 客户发过消息的会话的首响毫秒数，None 表示直到会话结束都没有客服回复
+    first_responses: Vec<Option<i64>>,
+    queued: HashSet<String>,
+    queue_timeouts: usize,
+}
+
+/// 单日 SLA 报表；没有样本的比率为 None
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DailySlaReport {
+    pub date: NaiveDate,
+    pub sessions: usize,
+    pub first_response_p50_ms: Option<i64>,
+    pub first_response_p90_ms: Option<i64>,
+    pub first_response_p99_ms: Option<i64>,
+    /// 首响在时限内的会话占比
+    pub first_response_sla_rate: Option<f64>,
+    /// 首响达成率是否达到目标
+    pub first_response_sla_met: Option<bool>,
+    /// 客服有回复且客户最后一条消息已被回复的会话占比
+    pub resolution_rate: Option<f64>,
+    pub queued_customers: usize,
+    pub queue_timeouts: usize,
+    pub queue_timeout_rate: Option<f64>,
+}
+
+/// 首响 SLA 达成率：未获回复的会话算未达成
+pub fn first_response_sla_rate(first_responses: &[Option<i64>], limit_ms: i64) -> Option<f64> {
+    if first_responses.is_empty() {
+        return None;
+    }
+    let met = first_responses
+        .iter()
+        .filter(|response| response.is_some_and(|ms| ms <= limit_ms))
+        .count();
+    Some(met as f64 / first_responses.len() as f64)
+}
+
+/// 最近秩法分位数，sorted 须为升序
+pub fn percentile(sorted: &[i64], p: f64) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn ratio(part: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| part as f64 / total as f64)
+}
+
+/// 业务 SLA 统计：跟踪会话首响、解决和排队超时，按天聚合
+pub struct SlaTracker {
+    first_response_limit_ms: i64,
+    target_rate: f64,
+    open_sessions: Mutex<HashMap<String, OpenSession>>,
+    days: Mutex<BTreeMap<NaiveDate, DayStats>>,
+}
+
+impl Default for SlaTracker {
+    fn default() -> Self {
+        Self::new(&SlaConfig::default())
+    }
+}
+
+impl SlaTracker {
+    pub fn new(config: &SlaConfig) -> Self {
+        Self {
+            first_response_limit_ms: (config.first_response_seconds as i64).saturating_mul(1000),
+            target_rate: config.target_rate,
+            open_sessions: Mutex::new(HashMap::new()),
+            days: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 客户与客服建立会话；转接或重复分配时沿用原会话的计时
+    pub fn session_started(&self, customer_id: &str, at: DateTime<Utc>) {
+        if let Ok(mut sessions) = self.open_sessions.lock() {
+            sessions.entry(customer_id.to_string()).or_insert(OpenSession {
+                started_at: at,
+                customer_first_message: None,
+                first_response_ms: None,
+                last_from_kefu: false,
+            });
+        }
+    }
+
+    pub fn customer_message(&self, customer_id: &str, at: DateTime<Utc>) {
+        if let Ok(mut sessions) = self.open_sessions.lock() {
+            if let Some(session) = sessions.get_mut(customer_id) {
+                session.customer_first_message.get_or_insert(at);
+                session.last_from_kefu = false;
+            }
+        }
+    }
+
+    /// 客服给客户发消息；客户开口之前的问候不计入首响
+    pub fn kefu_message(&self, customer_id: &str, at: DateTime<Utc>) {
+        if let Ok(mut sessions) = self.open_sessions.lock() {
+            if let Some(session) = sessions.get_mut(customer_id) {
+                if let Some(first_message) = session.customer_first_message {
+                    session
+                        .first_response_ms
+                        .get_or_insert_with(|| (at - first_message).num_milliseconds().max(0));
+                    session.last_from_kefu = true;
+                }
+            }
+        }
+    }
+
+    /// 客户离开，会话计入开始当天的统计
+    pub fn session_ended(&self, customer_id: &str) {
+        let session = match self.open_sessions.lock() {
+            Ok(mut sessions) => sessions.remove(customer_id),
+            Err(_) => None,
+        };
+        let Some(session) = session else {
+            return;
+        };
+        self.with_day(session.started_at, |day| {
+            day.sessions += 1;
+            if session.customer_first_message.is_some() {
+                day.first_responses.push(session.first_response_ms);
+            }
+            if session.first_response_ms.is_some() && session.last_from_kefu {
+                day.resolved += 1;
+            }
+        });
+    }
+
+    /// 客户进入等待队列，同一天重复入队只计一次
+    pub fn customer_queued(&self, customer_id: &str, at: DateTime<Utc>) {
+        self.with_day(at, |day| {
+            day.queued.insert(customer_id.to_string());
+        });
+    }
+
+    pub fn queue_timed_out(&self, customer_id: &str, at: DateTime<Utc>) {
+        self.with_day(at, |day| {
+            day.queued.insert(customer_id.to_string());
+            day.queue_timeouts += 1;
+        });
+    }
+
+    fn with_day(&self, at: DateTime<Utc>, update: impl FnOnce(&mut DayStats)) {
+        if let Ok(mut days) = self.days.lock() {
+            update(days.entry(at.date_naive()).or_default());
+            while days.len() > SLA_RETENTION_DAYS {
+                days.pop_first();
+            }
+        }
+    }
+
+    /// 截至 today 最近 days 天的报表，按日期升序，没有数据的日期也会列出
+    pub fn daily_reports(&self, days: usize, today: NaiveDate) -> Vec<DailySlaReport> {
+        let Ok(stats) = self.days.lock() else {
+            return Vec::new();
+        };
+        let days = days.clamp(1, SLA_RETENTION_DAYS) as i64;
+        (0..days)
+            .rev()
+            .map(|offset| today - Duration::days(offset))
+            .map(|date| match stats.get(&date) {
+                Some(day) => self.report(date, day),
+                None => self.report(date, &DayStats::default()),
+            })
+            .collect()
+    }
+
+    fn report(&self, date: NaiveDate, day: &DayStats) -> DailySlaReport {
+        let mut answered: Vec<i64> = day.first_responses.iter().flatten().copied().collect();
+        answered.sort_unstable();
+        let sla_rate = first_response_sla_rate(&day.first_responses, self.first_response_limit_ms);
+        DailySlaReport {
+            date,
+            sessions: day.sessions,
+            first_response_p50_ms: percentile(&answered, 50.0),
+            first_response_p90_ms: percentile(&answered, 90.0),
+            first_response_p99_ms: percentile(&answered, 99.0),
+            first_response_sla_rate: sla_rate,
+            first_response_sla_met: sla_rate.map(|rate| rate >= self.target_rate),
+            resolution_rate: ratio(day.resolved, day.sessions),
+            queued_customers: day.queued.len(),
+            queue_timeouts: day.queue_timeouts,
+            queue_timeout_rate: ratio(day.queue_timeouts, day.queued.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_response_sla_rate_for_batch() {
+        // 时限30秒：10秒、30秒达成，31秒超时，未回复算未达成
+        let responses = [Some(10_000), Some(30_000), Some(31_000), None];
+        assert_eq!(first_response_sla_rate(&responses, 30_000), Some(0.5));
+        assert_eq!(first_response_sla_rate(&[], 30_000), None);
+
+        let sorted = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(percentile(&sorted, 50.0), Some(5));
+        assert_eq!(percentile(&sorted, 90.0), Some(9));
+        assert_eq!(percentile(&sorted, 99.0), Some(10));
+    }
+
+    #[test]
+    fn test_daily_report_from_session_events() {
+        let tracker = SlaTracker::new(&SlaConfig {
+            first_response_seconds: 30,
+            target_rate: 0.9,
+        });
+        let start = Utc::now();
+
+        // 20秒首响并回复了客户最后一条消息
+        tracker.session_started("kehu_1", start);
+        tracker.customer_message("kehu_1", start + Duration::seconds(5));
+        tracker.kefu_message("kehu_1", start + Duration::seconds(25));
+        tracker.session_ended("kehu_1");
+
+        // 客服先问候不计首响，客户开口后60秒才回复，之后客户的追问没有回复
+        tracker.session_started("kehu_2", start);
+        tracker.kefu_message("kehu_2", start + Duration::seconds(1));
+        tracker.customer_message("kehu_2", start + Duration::seconds(10));
+        tracker.kefu_message("kehu_2", start + Duration::seconds(70));
+        tracker.customer_message("kehu_2", start + Duration::seconds(80));
+        tracker.session_ended("kehu_2");
+
+        tracker.customer_queued("kehu_3", start);
+        tracker.customer_queued("kehu_3", start);
+        tracker.customer_queued("kehu_4", start);
+        tracker.queue_timed_out("kehu_4", start);
+
+        let reports = tracker.daily_reports(1, start.date_naive());
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.sessions, 2);
+        assert_eq!(report.first_response_p50_ms, Some(20_000));
+        assert_eq!(report.first_response_p99_ms, Some(60_000));
+        assert_eq!(report.first_response_sla_rate, Some(0.5));
+        assert_eq!(report.first_response_sla_met, Some(false));
+        assert_eq!(report.resolution_rate, Some(0.5));
+        assert_eq!(report.queued_customers, 2);
+        assert_eq!(report.queue_timeout_rate, Some(0.5));
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// 允许使用的表情回应，限制在固定集合内，防止任意字符串写入 Redis
+pub const ALLOWED_REACTIONS: &[&str] = &[
+    "👍", "👎", "❤️", "😂", "😮", "😢", "😡", "🙏", "🎉", "✅", "👀", "🔥",
+];
+
+/// 单条消息上的表情回应数量，键为表情
+pub type ReactionCounts = HashMap<String, usize>;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReactionAction {
+    Add,
+    Remove,
+}
+
+pub fn is_allowed_reaction(emoji: &str) -> bool {
+    ALLOWED_REACTIONS.contains(&emoji)
+}
+
+/// Redis 中一条回应的成员值，同一用户对同一消息的同一表情只计一次
+pub fn reaction_member(emoji: &str, user_id: &str) -> String {
+    format!("{}:{}", emoji, user_id)
+}
+
+/// 把一条消息的回应成员汇总为各表情的数量，不认识的成员跳过
+pub fn count_reactions<S: AsRef<str>>(members: &[S]) -> ReactionCounts {
+    let mut counts = ReactionCounts::new();
+    for member in members {
+        if let Some((emoji, _)) = member.as_ref().split_once(':') {
+            if is_allowed_reaction(emoji) {
+                *counts.entry(emoji.to_string()).or_default() += 1;
+            }
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reactions_validated_and_counted() {
+        assert!(is_allowed_reaction("👍"));
+        assert!(!is_allowed_reaction("<script>"));
+        assert!(!is_allowed_reaction(""));
+
+        let members = vec![
+            reaction_member("👍", "kehu_1"),
+            reaction_member("👍", "kefu_1"),
+            reaction_member("🎉", "kefu_1"),
+            "garbage".to_string(),
+            reaction_member("💩", "kehu_1"),
+        ];
+        let counts = count_reactions(&members);
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["👍"], 2);
+        assert_eq!(counts["🎉"], 1);
+    }
+}
@@ -1,18 +1,140 @@
+use crate::connection_events::{
+    connection_events_key, parse_events, ConnectionEvent, CONNECTION_EVENTS_TTL_SECS, MAX_CONNECTION_EVENTS,
+};
+use crate::config::MessageQueueConfig;
 use crate::message::UserInfo;
-use crate::redis_pool::{PoolMetrics, RedisPoolConfig, RedisPoolManager};
+use crate::redis_pool::{PoolError, PoolMetrics, RedisConnection, RedisPoolConfig, RedisPoolManager};
 use anyhow::Result;
-use chrono::Utc;
+use crate::canned_response::CannedResponse;
+use crate::reaction::{count_reactions, reaction_member, ReactionAction, ReactionCounts};
+use crate::satisfaction::{rolling_average, KefuSatisfaction, SessionRating, SATISFACTION_WINDOW};
+use crate::system_broadcast::{
+    SystemBroadcast, BROADCAST_LOG_KEY, MAX_BROADCAST_TTL_SECS, MAX_LOGGED_BROADCASTS,
+};
+use chrono::{DateTime, Utc};
 use redis::{AsyncCommands, Client, Connection, RedisResult};
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
 // use tracing::{info, warn, error}; // 暂时注释未使用的导入
 
+/// 单个客服同时接待的会话上限
+pub const MAX_KEFU_SESSIONS: usize = 5;
+
+/// 会话评价记录保留时长（30天），期间同一会话不能重复评价
+const SESSION_RATING_TTL_SECONDS: i64 = 30 * 24 * 3600;
+
+/// 用户最后在线时间保留时长，与系统广播最长有效期一致
+const LAST_SEEN_TTL_SECONDS: i64 = crate::system_broadcast::MAX_BROADCAST_TTL_SECS as i64;
+
+/// 会话最后活动时间的有序集合，成员为 "客户ID:客服ID"，分值为秒级时间戳
+const SESSION_ACTIVITY_KEY: &str = "session_activity";
+
+/// 会话信息保留时长，会话每有一条消息就重新计算
+const SESSION_TTL_SECONDS: i64 = 86400;
+
+/// 会话存在时更新 last_activity 并重设过期时间，返回1；会话已结束时返回0
+const TOUCH_SESSION_SCRIPT: &str = r#"
+local raw = redis.call('GET', KEYS[1])
+if not raw then
+    return 0
+end
+local info = cjson.decode(raw)
+info['last_activity'] = tonumber(ARGV[1])
+redis.call('SET', KEYS[1], cjson.encode(info), 'EX', ARGV[2])
+return 1
+"#;
+
+/// 客户不在等待队列中时入队（LPUSH）并返回1；已在队列中时保持原位置，返回0
+const ENQUEUE_WAITING_SCRIPT: &str = r#"
+for _, customer_id in ipairs(redis.call('LRANGE', KEYS[1], 0, -1)) do
+    if customer_id == ARGV[1] then
+        return 0
+    end
+end
+redis.call('LPUSH', KEYS[1], ARGV[1])
+return 1
+"#;
+
+/// 认领客户：认领标记不存在时设置并把客户移出等待队列，返回1；已被认领时返回0
+const CLAIM_CUSTOMER_SCRIPT: &str = r#"
+if not redis.call('SET', KEYS[1], '1', 'NX', 'EX', ARGV[2]) then
+    return 0
+end
+redis.call('LREM', KEYS[2], 0, ARGV[1])
+redis.call('DEL', KEYS[3])
+return 1
+"#;
+
+/// 认领标记的保留时长，覆盖认领到会话建立完成的时间
+const CUSTOMER_CLAIM_TTL_SECONDS: i64 = 10;
+
+/// 固定窗口计数：计数加一，窗口内首次计数时设置过期时间，返回当前计数
+const RATE_WINDOW_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+/// 快捷回复存储的 hash 键
+const CANNED_RESPONSES_KEY: &str = "canned_responses";
+
+/// 连接类错误的最大尝试次数（含首次）与重试间隔基数
+const REDIS_RETRY_ATTEMPTS: u32 = 3;
+const REDIS_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(50);
+
+// 连接被断开、拒绝、IO 错误和超时可以换连接重试；类型错误、命令错误等逻辑错误重试也不会成功
+fn is_connection_error(error: &redis::RedisError) -> bool {
+    error.is_connection_dropped() || error.is_connection_refusal() || error.is_io_error() || error.is_timeout()
+}
+
+pub(crate) fn is_retryable(error: &anyhow::Error) -> bool {
+    if let Some(error) = error.downcast_ref::<redis::RedisError>() {
+        return is_connection_error(error);
+    }
+    match error.downcast_ref::<PoolError>() {
+        Some(PoolError::Backend(error)) => is_connection_error(error),
+        Some(PoolError::Timeout(_)) => true,
+        _ => false,
+    }
+}
+
+// 每次尝试都通过 connect 取新连接执行 op，连接类错误按次数递增的间隔重试
+async fn retry_with_fresh_connection<C, T, Connect, ConnectFut, Op, OpFut>(
+    max_attempts: u32,
+    delay: std::time::Duration,
+    mut connect: Connect,
+    mut op: Op,
+) -> Result<T>
+where
+    Connect: FnMut() -> ConnectFut,
+    ConnectFut: Future<Output = Result<C>>,
+    Op: FnMut(C) -> OpFut,
+    OpFut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        let result = match connect().await {
+            Ok(conn) => op(conn).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e) if attempt < max_attempts && is_retryable(&e) => {
+                tracing::warn!("⚠️ Redis连接错误，第{}次重试: {}", attempt, e);
+                tokio::time::sleep(delay * attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RedisManager {
     // 保留原有的客户端用于向后兼容
     client: Client,
-    #[allow(dead_code)] // 企业级向后兼容保留
-    redis_url: String,
     // 新增连接池管理器
     pool_manager: Option<Arc<RedisPoolManager>>,
     use_pool: bool,
@@ -25,23 +147,21 @@ impl RedisManager {
         let client = Client::open(redis_url)?;
         Ok(RedisManager {
             client,
-            redis_url: redis_url.to_string(),
             pool_manager: None,
             use_pool: false,
         })
     }
 
-    // 新的构造函数（使用连接池）
+    // 新的构造函数（使用连接池），连接按部署方式由连接池建立，client 仅在关闭连接池时使用
     pub fn with_pool(config: RedisPoolConfig) -> Result<Self> {
         let client = Client::open(config.url.clone())?;
-        let pool_manager = RedisPoolManager::new(config.clone())?;
+        let pool_manager = RedisPoolManager::new(config)?;
 
         // 启动健康检查任务
         let _health_check_handle = pool_manager.start_health_check_task();
 
         Ok(RedisManager {
             client,
-            redis_url: config.url,
             pool_manager: Some(Arc::new(pool_manager)),
             use_pool: true,
         })
@@ -56,15 +176,25 @@ impl RedisManager {
         Self::with_pool(config)
     }
 
+    /// 订阅等需要独占连接的场景使用；哨兵模式每次调用都查询当前主节点，断线重连后随主从切换
+    pub async fn pubsub_client(&self) -> Result<Client> {
+        match self.active_pool() {
+            Some(pool_manager) => Ok(pool_manager.pubsub_client().await?),
+            None => Ok(self.client.clone()),
+        }
+    }
+
     // 获取新的同步连接（保持向后兼容）
     pub fn get_connection(&self) -> RedisResult<Connection> {
-        self.client.get_connection()
+        match self.active_pool() {
+            Some(pool_manager) => pool_manager.blocking_client()?.get_connection(),
+            None => self.client.get_connection(),
+        }
     }
 
     // 获取异步连接（升级版，使用连接池）
     pub async fn get_async_connection(&self) -> Result<AsyncConnection> {
-        if self.use_pool && self.pool_manager.is_some() {
-            let pool_manager = self.pool_manager.as_ref().unwrap();
+        if let Some(pool_manager) = self.active_pool() {
             let conn = pool_manager.get_connection().await?;
             Ok(AsyncConnection::Pooled(PooledConnection { conn }))
         } else {
@@ -73,11 +203,66 @@ impl RedisManager {
         }
     }
 
+    // 启用了连接池时返回连接池管理器，否则走直连客户端
+    fn active_pool(&self) -> Option<&Arc<RedisPoolManager>> {
+        self.pool_manager.as_ref().filter(|_| self.use_pool)
+    }
+
+    // 带重试的命令执行：连接类错误换新连接后有限次重试，逻辑错误直接返回。
+    // 命令可能在连接断开前已执行，op 中只应包含可重复执行的命令
+    pub async fn with_retry<T, Op, OpFut>(&self, op: Op) -> Result<T>
+    where
+        Op: FnMut(AsyncConnection) -> OpFut,
+        OpFut: Future<Output = Result<T>>,
+    {
+        retry_with_fresh_connection(
+            REDIS_RETRY_ATTEMPTS,
+            REDIS_RETRY_DELAY,
+            || self.get_async_connection(),
+            op,
+        )
+        .await
+    }
+
+    // PING：连接池与直连两种模式均可用
+    pub async fn ping(&self) -> Result<String> {
+        let mut conn = self.get_async_connection().await?;
+        conn.ping().await
+    }
+
+    // 事务封装：闭包中构建的命令以 MULTI/EXEC 原子执行，执行期间其他连接看不到中间状态。
+    // 集群模式下各键通常分属不同槽位（CROSSSLOT），改为逐条执行，不保证原子性
+    pub async fn transaction<T, F>(&self, build: F) -> Result<T>
+    where
+        T: redis::FromRedisValue,
+        F: FnOnce(&mut redis::Pipeline),
+    {
+        let mut pipe = redis::pipe();
+        build(&mut pipe);
+        let mut conn = self.get_async_connection().await?;
+
+        if !self.is_cluster() {
+            pipe.atomic();
+            return conn.query_pipeline(&pipe).await;
+        }
+        let mut results = Vec::new();
+        for cmd in pipe.cmd_iter() {
+            let mut single = redis::pipe();
+            single.add_command(cmd.clone());
+            let (value,): (redis::Value,) = conn.query_pipeline(&single).await?;
+            results.push(value);
+        }
+        Ok(T::from_redis_value(&redis::Value::Bulk(results))?)
+    }
+
+    fn is_cluster(&self) -> bool {
+        self.active_pool().is_some_and(|pool_manager| pool_manager.is_cluster())
+    }
+
     // 连接测试功能（增强版）
     #[allow(dead_code)]
     pub async fn test_connection(&self) -> Result<bool> {
-        if self.use_pool && self.pool_manager.is_some() {
-            let pool_manager = self.pool_manager.as_ref().unwrap();
+        if let Some(pool_manager) = self.active_pool() {
             pool_manager.health_check().await
         } else {
             match self.get_connection() {
@@ -92,23 +277,24 @@ impl RedisManager {
 
     // 设置用户在线状态（优化版）
     pub async fn set_user_online(&self, user_id: &str, user_info: &UserInfo) -> Result<()> {
-        let mut conn = self.get_async_connection().await?;
-
         let user_key = format!("user:{}", user_id);
         let user_json = serde_json::to_string(user_info)?;
-
-        // 使用管道批量操作
-        conn.set(&user_key, &user_json).await?;
-        conn.expire(&user_key, 300).await?; // 5分钟过期
-        conn.sadd("users:online", user_id).await?;
-        conn.set_ex(
-            format!("heartbeat:{}", user_id),
-            Utc::now().timestamp().to_string(),
-            60,
-        )
+        let (user_key, user_json) = (user_key.as_str(), user_json.as_str());
+
+        self.with_retry(|mut conn| async move {
+            conn.set(user_key, user_json).await?;
+            conn.expire(user_key, 300).await?; // 5分钟过期
+            conn.sadd("users:online", user_id).await?;
+            conn.set_ex(
+                format!("heartbeat:{}", user_id),
+                Utc::now().timestamp().to_string(),
+                60,
+            )
+            .await
+        })
         .await?;
 
-        // 广播用户状态变化
+        // 广播用户状态变化；广播不重试，避免订阅方收到重复通知
         let status_update = serde_json::json!({
             "type": "user_online",
             "user_id": user_id,
@@ -116,6 +302,7 @@ impl RedisManager {
             "timestamp": Utc::now().timestamp()
         });
 
+        let mut conn = self.get_async_connection().await?;
         conn.publish("user_status_updates", &status_update.to_string())
             .await?;
         Ok(())
@@ -123,14 +310,18 @@ impl RedisManager {
 
     // 设置用户离线状态（优化版）
     pub async fn set_user_offline(&self, user_id: &str) -> Result<()> {
-        let mut conn = self.get_async_connection().await?;
-
         let user_key = format!("user:{}", user_id);
-
-        // 使用批量操作
-        conn.del(&user_key).await?;
-        conn.del(&format!("heartbeat:{}", user_id)).await?;
-        conn.srem("users:online", user_id).await?;
+        let last_seen = Utc::now().timestamp_millis().to_string();
+        let (user_key, last_seen) = (user_key.as_str(), last_seen.as_str());
+
+        self.with_retry(|mut conn| async move {
+            conn.del(user_key).await?;
+            conn.del(&format!("heartbeat:{}", user_id)).await?;
+            conn.srem("users:online", user_id).await?;
+            conn.set_ex(format!("last_seen:{}", user_id), last_seen.to_string(), LAST_SEEN_TTL_SECONDS)
+                .await
+        })
+        .await?;
 
         // 广播用户离线
         let status_update = serde_json::json!({
@@ -139,6 +330,7 @@ impl RedisManager {
             "timestamp": Utc::now().timestamp()
         });
 
+        let mut conn = self.get_async_connection().await?;
         conn.publish("user_status_updates", &status_update.to_string())
             .await?;
         Ok(())
@@ -170,10 +362,10 @@ impl RedisManager {
 
     // 获取用户信息（优化版）
     pub async fn get_user_info(&self, user_id: &str) -> Result<UserInfo> {
-        let mut conn = self.get_async_connection().await?;
         let key = format!("user:{}", user_id);
+        let key = key.as_str();
 
-        let value: String = conn.get(&key).await?;
+        let value: String = self.with_retry(|mut conn| async move { conn.get(key).await }).await?;
         let user_info = serde_json::from_str::<UserInfo>(&value)?;
         Ok(user_info)
     }
@@ -212,10 +404,10 @@ impl RedisManager {
 
     // 获取聊天伙伴（优化版）
     pub async fn get_partner(&self, user_id: &str) -> Result<Option<String>> {
-        let mut conn = self.get_async_connection().await?;
         let key = format!("partner:{}", user_id);
+        let key = key.as_str();
 
-        match conn.get(&key).await {
+        match self.with_retry(|mut conn| async move { conn.get(key).await }).await {
             Ok(partner_id) => Ok(Some(partner_id)),
             Err(_) => Ok(None),
         }
@@ -247,8 +439,7 @@ impl RedisManager {
         Ok(())
     }
 
-    // 检查用户在线状态（优化版）
-    #[allow(dead_code)] // 企业级功能保留
+    // 检查用户在线状态（优化版）：任一实例上保持心跳即视为在线
     pub async fn is_user_online(&self, user_id: &str) -> Result<bool> {
         let mut conn = self.get_async_connection().await?;
         let key = format!("heartbeat:{}", user_id);
@@ -272,18 +463,20 @@ impl RedisManager {
         }
     }
 
-    // 批量检查用户在线状态（优化版）
+    // 批量检查用户在线状态（优化版），所有 EXISTS 通过一次管道发送
     pub async fn check_users_online(&self, user_ids: &[String]) -> Result<HashMap<String, bool>> {
-        let mut conn = self.get_async_connection().await?;
-        let mut results = HashMap::new();
+        if user_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
 
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
         for user_id in user_ids {
-            let key = format!("heartbeat:{}", user_id);
-            let exists: bool = conn.exists(&key).await?;
-            results.insert(user_id.clone(), exists);
+            pipe.exists(format!("heartbeat:{}", user_id));
         }
+        let exists: Vec<bool> = conn.query_pipeline(&pipe).await?;
 
-        Ok(results)
+        Ok(user_ids.iter().cloned().zip(exists).collect())
     }
 
     // 发布到频道（优化版）
@@ -389,6 +582,12 @@ impl RedisManager {
 
     // 🚀 企业级会话管理功能
 
+    // 客户与客服之间是否有进行中的会话
+    pub async fn session_exists(&self, kehu_id: &str, kefu_id: &str) -> Result<bool> {
+        let mut conn = self.get_async_connection().await?;
+        conn.exists(&format!("session:{}:{}", kehu_id, kefu_id)).await
+    }
+
     // 获取客服的活跃会话列表
     pub async fn get_kefu_active_sessions(&self, kefu_id: &str) -> Result<Vec<String>> {
         let mut conn = self.get_async_connection().await?;
@@ -422,106 +621,184 @@ impl RedisManager {
     }
 
     // 添加客户到等待队列
+    // 重连时重复加入不会产生重复条目，排队位置和起始时间保持不变
     pub async fn add_to_waiting_queue(&self, customer_id: &str) -> Result<()> {
         let mut conn = self.get_async_connection().await?;
 
         // 添加到全局等待队列
-        conn.lpush("waiting_queue", customer_id).await?;
-
-        // 设置等待状态和时间戳
+        let mut pipe = redis::pipe();
+        pipe.cmd("EVAL")
+            .arg(ENQUEUE_WAITING_SCRIPT)
+            .arg(1)
+            .arg("waiting_queue")
+            .arg(customer_id);
+        let (added,): (i64,) = conn.query_pipeline(&pipe).await?;
+
+        // 设置等待状态和时间戳，已在等待时保留原时间戳
         let waiting_info = serde_json::json!({
             "customer_id": customer_id,
             "waiting_since": Utc::now().timestamp(),
             "status": "waiting"
         });
-
-        conn.set_ex(
-            format!("waiting:{}", customer_id),
-            waiting_info.to_string(),
-            3600, // 1小时过期
-        )
-        .await?;
-
-        tracing::info!("📋 客户{}已加入等待队列", customer_id);
+        let mut pipe = redis::pipe();
+        pipe.cmd("SET")
+            .arg(format!("waiting:{}", customer_id))
+            .arg(waiting_info.to_string())
+            .arg("NX")
+            .arg("EX")
+            .arg(3600) // 1小时过期
+            .ignore();
+        conn.query_pipeline::<()>(&pipe).await?;
+
+        if added == 1 {
+            tracing::info!("📋 客户{}已加入等待队列", customer_id);
+        } else {
+            tracing::info!("📋 客户{}已在等待队列中", customer_id);
+        }
         Ok(())
     }
 
-    // 从等待队列移除客户
-    pub async fn remove_from_waiting_queue(&self, customer_id: &str) -> Result<()> {
+    // 限流计数：返回 window_seconds 秒窗口内（含本次）的调用次数
+    pub async fn count_in_window(&self, key: &str, window_seconds: i64) -> Result<i64> {
         let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.cmd("EVAL").arg(RATE_WINDOW_SCRIPT).arg(1).arg(key).arg(window_seconds);
+        let (count,): (i64,) = conn.query_pipeline(&pipe).await?;
+        Ok(count)
+    }
 
-        // 从全局等待队列移除
-        conn.lrem("waiting_queue", 0, customer_id).await?;
+    // 认领待分配的客户：原子地设置认领标记并移出等待队列，多个实例或客服同时认领时只有一方返回 true。
+    // 不在等待队列中的客户同样经此认领
+    pub async fn claim_waiting_customer(&self, customer_id: &str) -> Result<bool> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.cmd("EVAL")
+            .arg(CLAIM_CUSTOMER_SCRIPT)
+            .arg(3)
+            .arg(format!("claim:{}", customer_id))
+            .arg("waiting_queue")
+            .arg(format!("waiting:{}", customer_id))
+            .arg(customer_id)
+            .arg(CUSTOMER_CLAIM_TTL_SECONDS);
+        let (claimed,): (i64,) = conn.query_pipeline(&pipe).await?;
+        if claimed == 0 {
+            return Ok(false);
+        }
+        tracing::info!("✅ 客户{}已被认领，移出等待队列", customer_id);
+        Ok(true)
+    }
 
-        // 清除等待状态
-        conn.del(&format!("waiting:{}", customer_id)).await?;
+    // 从等待队列移除客户
+    pub async fn remove_from_waiting_queue(&self, customer_id: &str) -> Result<()> {
+        // 移出全局等待队列并清除等待状态
+        self.transaction::<(), _>(|pipe| {
+            pipe.lrem("waiting_queue", 0, customer_id)
+                .ignore()
+                .del(format!("waiting:{}", customer_id))
+                .ignore();
+        })
+        .await?;
 
         tracing::info!("✅ 客户{}已从等待队列移除", customer_id);
         Ok(())
     }
 
-    // 获取等待队列
+    // 客户进入等待队列的时间（秒级时间戳），不在队列或等待信息已过期时返回 None
+    pub async fn get_waiting_since(&self, customer_id: &str) -> Result<Option<i64>> {
+        let mut conn = self.get_async_connection().await?;
+        let key = format!("waiting:{}", customer_id);
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        let waiting_info: serde_json::Value = serde_json::from_str(&conn.get(&key).await?)?;
+        Ok(waiting_info["waiting_since"].as_i64())
+    }
+
+    // 获取等待队列，按排队先后返回（最早排队的在前）。入队用 LPUSH，队尾是最早排队的客户
     pub async fn get_waiting_queue(&self) -> Result<Vec<String>> {
         let mut conn = self.get_async_connection().await?;
-        let customers: Vec<String> = conn
+        let mut customers: Vec<String> = conn
             .lrange("waiting_queue", 0, -1)
             .await
             .unwrap_or_default();
+        customers.reverse();
         Ok(customers)
     }
 
     // 清除会话关系
     pub async fn clear_session(&self, user1_id: &str, user2_id: &str) -> Result<()> {
-        let mut conn = self.get_async_connection().await?;
-
-        // 清除配对关系
-        conn.del(&format!("partner:{}", user1_id)).await?;
-        conn.del(&format!("partner:{}", user2_id)).await?;
-
-        // 清除会话记录
-        let session_key = format!("session:{}:{}", user1_id, user2_id);
-        let session_key_alt = format!("session:{}:{}", user2_id, user1_id);
-        conn.del(&session_key).await?;
-        conn.del(&session_key_alt).await?;
-
-        // 从客服会话列表中移除
-        conn.srem(&format!("kefu_sessions:{}", user1_id), user2_id)
-            .await?;
-        conn.srem(&format!("kefu_sessions:{}", user2_id), user1_id)
-            .await?;
+        self.transaction::<(), _>(|pipe| {
+            // 清除配对关系
+            pipe.del(format!("partner:{}", user1_id))
+                .ignore()
+                .del(format!("partner:{}", user2_id))
+                .ignore();
+
+            // 清除会话记录
+            pipe.del(format!("session:{}:{}", user1_id, user2_id))
+                .ignore()
+                .del(format!("session:{}:{}", user2_id, user1_id))
+                .ignore();
+
+            // 从客服会话列表中移除
+            pipe.srem(format!("kefu_sessions:{}", user1_id), user2_id)
+                .ignore()
+                .srem(format!("kefu_sessions:{}", user2_id), user1_id)
+                .ignore();
+
+            // 不再参与空闲检查
+            pipe.zrem(SESSION_ACTIVITY_KEY, format!("{}:{}", user1_id, user2_id))
+                .ignore()
+                .zrem(SESSION_ACTIVITY_KEY, format!("{}:{}", user2_id, user1_id))
+                .ignore();
+        })
+        .await?;
 
         tracing::info!("🧹 已清除会话关系: {} <-> {}", user1_id, user2_id);
         Ok(())
     }
 
     // 建立会话（增强版，支持多会话）
-    pub async fn establish_session_enhanced(&self, kehu_id: &str, kefu_id: &str) -> Result<()> {
-        let mut conn = self.get_async_connection().await?;
-
+    // 返回是否新建了会话：此前不存在该客户与客服之间进行中的会话
+    pub async fn establish_session_enhanced(&self, kehu_id: &str, kefu_id: &str) -> Result<bool> {
         let session_id = format!("{}:{}", kehu_id, kefu_id);
         let session_key = format!("session:{}", session_id);
+        let now = Utc::now().timestamp();
 
         let session_info = serde_json::json!({
             "kehu_id": kehu_id,
             "kefu_id": kefu_id,
             "session_id": session_id,
-            "established_at": Utc::now().timestamp(),
-            "last_activity": Utc::now().timestamp(),
+            "established_at": now,
+            "last_activity": now,
             "status": "active",
             "priority": "normal"
-        });
+        })
+        .to_string();
 
-        // 建立双向配对关系
-        conn.set(&format!("partner:{}", kehu_id), kefu_id).await?;
-        conn.set(&format!("partner:{}", kefu_id), kehu_id).await?;
+        let (session_key, session_info) = (session_key.as_str(), session_info.as_str());
+        let existed = self.with_retry(|mut conn| async move {
+            let existed = conn.exists(session_key).await?;
 
-        // 设置会话信息
-        conn.set_ex(session_key.clone(), session_info.to_string(), 86400)
-            .await?; // 24小时
+            // 建立双向配对关系
+            conn.set(&format!("partner:{}", kehu_id), kefu_id).await?;
+            conn.set(&format!("partner:{}", kefu_id), kehu_id).await?;
 
-        // 添加到客服的会话列表
-        conn.sadd(&format!("kefu_sessions:{}", kefu_id), kehu_id)
-            .await?;
+            // 设置会话信息
+            conn.set_ex(session_key.to_string(), session_info.to_string(), SESSION_TTL_SECONDS)
+                .await?;
+
+            // 添加到客服的会话列表
+            conn.sadd(&format!("kefu_sessions:{}", kefu_id), kehu_id).await?;
+            Ok(existed)
+        })
+        .await?;
+
+        // 建立会话即视为一次活动，开始空闲计时
+        self.transaction::<(), _>(|pipe| {
+            pipe.zadd(SESSION_ACTIVITY_KEY, &session_id, now).ignore();
+        })
+        .await?;
 
         // 从等待队列移除客户
         let _ = self.remove_from_waiting_queue(kehu_id).await;
@@ -535,11 +812,351 @@ impl RedisManager {
             "timestamp": Utc::now().timestamp()
         });
 
+        let mut conn = self.get_async_connection().await?;
+
         conn.publish("session_updates", &session_update.to_string())
             .await?;
 
         tracing::info!("🎯 企业级会话已建立: {} <-> {}", kehu_id, kefu_id);
-        Ok(())
+        Ok(!existed)
+    }
+
+    // 会话有新消息时更新最后活动时间并延长会话信息的保留时长；会话已结束时忽略。
+    // 会话信息在脚本内读改写，不会与并发的结束会话交错而把已清除的会话重新写回
+    pub async fn touch_session(&self, kehu_id: &str, kefu_id: &str, at: DateTime<Utc>) -> Result<()> {
+        let session_id = format!("{}:{}", kehu_id, kefu_id);
+        let session_key = format!("session:{}", session_id);
+        let mut conn = self.get_async_connection().await?;
+
+        let mut pipe = redis::pipe();
+        pipe.cmd("EVAL")
+            .arg(TOUCH_SESSION_SCRIPT)
+            .arg(1)
+            .arg(&session_key)
+            .arg(at.timestamp())
+            .arg(SESSION_TTL_SECONDS);
+        let (touched,): (i64,) = conn.query_pipeline(&pipe).await?;
+        if touched == 0 {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.zadd(SESSION_ACTIVITY_KEY, &session_id, at.timestamp()).ignore();
+        conn.query_pipeline::<()>(&pipe).await
+    }
+
+    // 最后活动时间不晚于 idle_since（秒级时间戳）的会话，返回 (客户ID, 客服ID)
+    pub async fn get_idle_sessions(&self, idle_since: i64) -> Result<Vec<(String, String)>> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.zrangebyscore(SESSION_ACTIVITY_KEY, "-inf", idle_since);
+        let (members,): (Vec<String>,) = conn.query_pipeline(&pipe).await?;
+        Ok(members
+            .iter()
+            .filter_map(|member| member.split_once(':'))
+            .map(|(kehu_id, kefu_id)| (kehu_id.to_string(), kefu_id.to_string()))
+            .collect())
+    }
+
+    // 客服当前接待的会话数
+    pub async fn count_kefu_sessions(&self, kefu_id: &str) -> Result<usize> {
+        let mut conn = self.get_async_connection().await?;
+        conn.scard(&format!("kefu_sessions:{}", kefu_id)).await
+    }
+
+    // 客户当前是否由该客服接待
+    pub async fn is_kefu_serving(&self, kefu_id: &str, customer_id: &str) -> Result<bool> {
+        if self.get_partner(customer_id).await?.as_deref() == Some(kefu_id) {
+            return Ok(true);
+        }
+        let mut conn = self.get_async_connection().await?;
+        let customers = conn.smembers(&format!("kefu_sessions:{}", kefu_id)).await?;
+        Ok(customers.iter().any(|id| id == customer_id))
+    }
+
+    // 标记会话已因客户负面情绪升级给主管，与会话信息同样保留24小时
+    pub async fn mark_session_escalated(&self, session_id: &str, details: &str) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        conn.set_ex(format!("session:{}:escalated", session_id), details.to_string(), 86400)
+            .await
+    }
+
+    // 会话是否已升级给主管
+    #[allow(dead_code)] // 预留给主管工作台查询
+    pub async fn is_session_escalated(&self, session_id: &str) -> Result<bool> {
+        let mut conn = self.get_async_connection().await?;
+        conn.exists(&format!("session:{}:escalated", session_id)).await
+    }
+
+    // 离线消息队列：接收方不在线时暂存待推送的消息（LPUSH，最新在前）。
+    // 超出长度上限时裁掉最早的消息，返回丢弃的条数；每次写入刷新队列过期时间
+    pub async fn push_offline_message(&self, user_id: &str, payload: &str, config: &MessageQueueConfig) -> Result<u64> {
+        let mut conn = self.get_async_connection().await?;
+        let key = format!("offline:{}", user_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic().lpush(&key, payload);
+        if config.max_length > 0 {
+            pipe.ltrim(&key, 0, config.max_length as isize - 1).ignore();
+        }
+        if config.message_ttl_seconds > 0 {
+            pipe.expire(&key, config.message_ttl_seconds as usize).ignore();
+        }
+        let (len,): (u64,) = conn.query_pipeline(&pipe).await?;
+        Ok(crate::message_queue::overflow_count(len, config.max_length))
+    }
+
+    // 取出并清空离线消息，按入队顺序返回。LRANGE 与 DEL 在同一事务中执行，
+    // 取出之后新入队的消息留在队列中等待下次投递
+    pub async fn take_offline_messages(&self, user_id: &str) -> Result<Vec<String>> {
+        // 事务已执行但响应丢失时重试会丢消息，这里不重试
+        let mut conn = self.get_async_connection().await?;
+        let key = format!("offline:{}", user_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic().lrange(&key, 0, -1).del(&key).ignore();
+        let (mut messages,): (Vec<String>,) = conn.query_pipeline(&pipe).await?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    // 把未送达的离线消息放回队列末端（最早的位置），排在取出之后新入队的消息之前投递
+    pub async fn requeue_offline_messages(&self, user_id: &str, payloads: &[String], ttl_seconds: u64) -> Result<()> {
+        if payloads.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.get_async_connection().await?;
+        let key = format!("offline:{}", user_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic().rpush(&key, payloads.iter().rev().collect::<Vec<_>>()).ignore();
+        if ttl_seconds > 0 {
+            pipe.expire(&key, ttl_seconds as usize).ignore();
+        }
+        conn.query_pipeline(&pipe).await
+    }
+
+    // 持久化系统广播：只保留最近的若干条。单条广播的有效期由读取方按 expires_at 过滤，
+    // 列表本身按最长有效期过期，避免短期广播缩短长期广播的保留时间
+    pub async fn push_system_broadcast(&self, broadcast: &SystemBroadcast) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .lpush(BROADCAST_LOG_KEY, serde_json::to_string(broadcast)?)
+            .ignore()
+            .ltrim(BROADCAST_LOG_KEY, 0, MAX_LOGGED_BROADCASTS - 1)
+            .ignore()
+            .expire(BROADCAST_LOG_KEY, MAX_BROADCAST_TTL_SECS as usize)
+            .ignore();
+        conn.query_pipeline::<()>(&pipe).await
+    }
+
+    // 读取已持久化的系统广播，跳过无法解析的记录
+    pub async fn get_system_broadcasts(&self) -> Result<Vec<SystemBroadcast>> {
+        let mut conn = self.get_async_connection().await?;
+        let entries = conn.lrange(BROADCAST_LOG_KEY, 0, -1).await?;
+        Ok(entries
+            .iter()
+            .filter_map(|entry| serde_json::from_str(entry).ok())
+            .collect())
+    }
+
+    // 记录一次连接/断开事件：按毫秒时间戳写入有序集合，只保留最近的若干条
+    pub async fn record_connection_event(&self, event: &ConnectionEvent) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let key = connection_events_key(&event.user_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .zadd(&key, serde_json::to_string(event)?, event.timestamp.timestamp_millis())
+            .ignore()
+            .zremrangebyrank(&key, 0, -(MAX_CONNECTION_EVENTS + 1))
+            .ignore()
+            .expire(&key, CONNECTION_EVENTS_TTL_SECS)
+            .ignore();
+        conn.query_pipeline::<()>(&pipe).await
+    }
+
+    // 用户最近的连接事件，最新的在前
+    pub async fn get_connection_events(&self, user_id: &str, limit: usize) -> Result<Vec<ConnectionEvent>> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.zrevrange(connection_events_key(user_id), 0, limit.max(1) as isize - 1);
+        let (entries,): (Vec<String>,) = conn.query_pipeline(&pipe).await?;
+        Ok(parse_events(&entries))
+    }
+
+    // 用户最后在线时间，用于判断需要补发哪些系统广播
+    pub async fn get_user_last_seen(&self, user_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let mut conn = self.get_async_connection().await?;
+        let key = format!("last_seen:{}", user_id);
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        let millis: i64 = conn.get(&key).await?.parse()?;
+        Ok(DateTime::from_timestamp_millis(millis))
+    }
+
+    // 保存会话满意度评价并重新计算客服最近评价的滚动平均；会话已评价过时返回 None
+    pub async fn record_session_rating(
+        &self,
+        rating: &SessionRating,
+    ) -> Result<Option<KefuSatisfaction>> {
+        let mut conn = self.get_async_connection().await?;
+        // SET NX 一步完成判重和写入，同一会话的并发评价只有一条生效
+        let mut pipe = redis::pipe();
+        pipe.cmd("SET")
+            .arg(format!("rating:{}", rating.session_id))
+            .arg(serde_json::to_string(rating)?)
+            .arg("NX")
+            .arg("EX")
+            .arg(SESSION_RATING_TTL_SECONDS);
+        let (stored,): (Option<String>,) = conn.query_pipeline(&pipe).await?;
+        if stored.is_none() {
+            return Ok(None);
+        }
+
+        let scores_key = format!("ratings:{}", rating.kefu_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .lpush(&scores_key, rating.score)
+            .ignore()
+            .ltrim(&scores_key, 0, SATISFACTION_WINDOW as isize - 1)
+            .ignore();
+        conn.query_pipeline::<()>(&pipe).await?;
+        // 与评分列表不在同一槽位，集群模式下不能放进同一事务
+        conn.sadd("kefu:rated", &rating.kefu_id).await?;
+
+        let scores: Vec<u8> = conn
+            .lrange(&scores_key, 0, -1)
+            .await?
+            .iter()
+            .filter_map(|score| score.parse().ok())
+            .collect();
+        let Some(average) = rolling_average(&scores) else {
+            return Ok(None);
+        };
+        conn.set(&format!("satisfaction:{}", rating.kefu_id), &average.to_string())
+            .await?;
+
+        Ok(Some(KefuSatisfaction {
+            kefu_id: rating.kefu_id.clone(),
+            satisfaction_score: average,
+            rating_count: scores.len(),
+        }))
+    }
+
+    // 客服满意度滚动平均，尚无评价时返回 None
+    pub async fn get_kefu_satisfaction(&self, kefu_id: &str) -> Result<Option<KefuSatisfaction>> {
+        let mut conn = self.get_async_connection().await?;
+        let key = format!("satisfaction:{}", kefu_id);
+        if !conn.exists(&key).await? {
+            return Ok(None);
+        }
+        let satisfaction_score: f64 = conn.get(&key).await?.parse()?;
+        let rating_count = conn.llen(&format!("ratings:{}", kefu_id)).await?;
+        Ok(Some(KefuSatisfaction {
+            kefu_id: kefu_id.to_string(),
+            satisfaction_score,
+            rating_count,
+        }))
+    }
+
+    // 所有收到过评价的客服的满意度汇总
+    pub async fn get_all_kefu_satisfaction(&self) -> Result<Vec<KefuSatisfaction>> {
+        let mut conn = self.get_async_connection().await?;
+        let mut kefu_ids = conn.smembers("kefu:rated").await?;
+        kefu_ids.sort();
+        let mut scores = Vec::with_capacity(kefu_ids.len());
+        for kefu_id in &kefu_ids {
+            if let Some(satisfaction) = self.get_kefu_satisfaction(kefu_id).await? {
+                scores.push(satisfaction);
+            }
+        }
+        Ok(scores)
+    }
+
+    // 快捷回复（全局与客服个人）统一存放在一个 hash 中，字段为快捷回复ID
+    pub async fn save_canned_response(&self, response: &CannedResponse) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hset(CANNED_RESPONSES_KEY, &response.id, serde_json::to_string(response)?)
+            .ignore();
+        conn.query_pipeline::<()>(&pipe).await
+    }
+
+    pub async fn get_canned_response(&self, id: &str) -> Result<Option<CannedResponse>> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hget(CANNED_RESPONSES_KEY, id);
+        let (value,): (Option<String>,) = conn.query_pipeline(&pipe).await?;
+        Ok(match value {
+            Some(value) => Some(serde_json::from_str(&value)?),
+            None => None,
+        })
+    }
+
+    // 删除快捷回复，返回是否存在
+    pub async fn delete_canned_response(&self, id: &str) -> Result<bool> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hdel(CANNED_RESPONSES_KEY, id);
+        let (removed,): (usize,) = conn.query_pipeline(&pipe).await?;
+        Ok(removed > 0)
+    }
+
+    // 所有快捷回复，解析失败的条目跳过
+    pub async fn list_canned_responses(&self) -> Result<Vec<CannedResponse>> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hvals(CANNED_RESPONSES_KEY);
+        let (values,): (Vec<String>,) = conn.query_pipeline(&pipe).await?;
+        Ok(values
+            .iter()
+            .filter_map(|value| serde_json::from_str(value).ok())
+            .collect())
+    }
+
+    // 添加或取消一条表情回应，重复添加或取消不存在的回应不影响计数
+    pub async fn update_reaction(
+        &self,
+        message_id: &str,
+        emoji: &str,
+        user_id: &str,
+        action: ReactionAction,
+    ) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let key = format!("reactions:{}", message_id);
+        let member = reaction_member(emoji, user_id);
+        match action {
+            ReactionAction::Add => conn.sadd(&key, &member).await,
+            ReactionAction::Remove => conn.srem(&key, &member).await,
+        }
+    }
+
+    // 批量读取消息的表情回应数量，没有回应的消息不出现在结果中
+    pub async fn get_reaction_counts(&self, message_ids: &[String]) -> Result<HashMap<String, ReactionCounts>> {
+        if message_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut pipe = redis::pipe();
+        for message_id in message_ids {
+            pipe.smembers(format!("reactions:{}", message_id));
+        }
+        let mut conn = self.get_async_connection().await?;
+        let members: Vec<Vec<String>> = conn.query_pipeline(&pipe).await?;
+        Ok(message_ids
+            .iter()
+            .zip(members)
+            .map(|(message_id, members)| (message_id.clone(), count_reactions(&members)))
+            .filter(|(_, counts)| !counts.is_empty())
+            .collect())
+    }
+
+    pub async fn set_user_last_seen(&self, user_id: &str, at: DateTime<Utc>) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        conn.set_ex(
+            format!("last_seen:{}", user_id),
+            at.timestamp_millis().to_string(),
+            LAST_SEEN_TTL_SECONDS,
+        )
+        .await
     }
 
     // 获取客服工作负载统计
@@ -547,7 +1164,9 @@ impl RedisManager {
     pub async fn get_cache<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
         let mut conn = self.get_async_connection().await?;
         
-        match conn.get::<_, Option<String>>(key).await? {
+        // 单条命令的管道，键不存在时得到 None 而不是类型转换错误
+        let (value,): (Option<String>,) = conn.query_pipeline(redis::pipe().get(key)).await?;
+        match value {
             Some(value) => {
                 let deserialized = serde_json::from_str(&value)?;
                 Ok(Some(deserialized))
@@ -558,7 +1177,7 @@ impl RedisManager {
     
     pub async fn set_cache(&self, key: &str, value: &str, ttl: i64) -> Result<()> {
         let mut conn = self.get_async_connection().await?;
-        conn.set_ex(key, value, ttl as usize).await?;
+        conn.set_ex(key.to_string(), value.to_string(), ttl).await?;
         Ok(())
     }
     
@@ -569,15 +1188,31 @@ impl RedisManager {
     }
     
     pub async fn delete_pattern(&self, pattern: &str) -> Result<()> {
+        let keys = self.scan_keys(pattern).await?;
+        // 逐个删除，集群模式下多键 DEL 会跨槽位
         let mut conn = self.get_async_connection().await?;
-        let keys: Vec<String> = conn.keys(pattern).await?;
-        
-        if !keys.is_empty() {
-            conn.del(keys).await?;
+        for key in keys {
+            conn.del(&key).await?;
         }
-        
         Ok(())
     }
+
+    /// 按模式查找键：用 SCAN 分批遍历避免 KEYS 阻塞服务端，集群模式逐个扫描每个主节点
+    pub async fn scan_keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let clients = match self.active_pool() {
+            Some(pool_manager) => pool_manager.master_clients().await?,
+            None => vec![self.client.clone()],
+        };
+        let mut keys = Vec::new();
+        for client in clients {
+            let mut conn = client.get_async_connection().await?;
+            let mut iter = conn.scan_match::<_, String>(pattern).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
     
     pub async fn get_kefu_workload(&self, kefu_id: &str) -> Result<serde_json::Value> {
         let mut conn = self.get_async_connection().await?;
@@ -586,12 +1221,17 @@ impl RedisManager {
         let session_count = active_sessions.len();
 
         // 计算平均响应时间等指标
+        let satisfaction_score = self
+            .get_kefu_satisfaction(kefu_id)
+            .await?
+            .map(|satisfaction| satisfaction.satisfaction_score);
         let workload_info = serde_json::json!({
             "kefu_id": kefu_id,
             "active_sessions": session_count,
-            "max_sessions": 5,
-            "utilization_rate": (session_count as f64 / 5.0) * 100.0,
-            "status": if session_count >= 5 { "busy" } else { "available" },
+            "satisfaction_score": satisfaction_score,
+            "max_sessions": MAX_KEFU_SESSIONS,
+            "utilization_rate": (session_count as f64 / MAX_KEFU_SESSIONS as f64) * 100.0,
+            "status": if session_count >= MAX_KEFU_SESSIONS { "busy" } else { "available" },
             "last_updated": Utc::now().timestamp()
         });
 
@@ -766,6 +1406,7 @@ impl AsyncConnection {
         }
     }
 
+    #[allow(dead_code)]
     pub async fn lrem(&mut self, key: &str, count: i64, value: &str) -> Result<()> {
         match self {
             AsyncConnection::Pooled(conn) => conn.lrem(key, count, value).await,
@@ -773,18 +1414,31 @@ impl AsyncConnection {
         }
     }
 
-    #[allow(dead_code)]
     pub async fn llen(&mut self, key: &str) -> Result<usize> {
         match self {
             AsyncConnection::Pooled(conn) => conn.llen(key).await,
             AsyncConnection::Direct(conn) => conn.llen(key).await,
         }
     }
+
+    pub async fn query_pipeline<T: redis::FromRedisValue>(&mut self, pipe: &redis::Pipeline) -> Result<T> {
+        match self {
+            AsyncConnection::Pooled(conn) => conn.query_pipeline(pipe).await,
+            AsyncConnection::Direct(conn) => conn.query_pipeline(pipe).await,
+        }
+    }
+
+    pub async fn ping(&mut self) -> Result<String> {
+        match self {
+            AsyncConnection::Pooled(conn) => conn.ping().await,
+            AsyncConnection::Direct(conn) => conn.ping().await,
+        }
+    }
 }
 
 // 连接池连接包装器
 pub struct PooledConnection {
-    conn: deadpool_redis::Connection,
+    conn: RedisConnection,
 }
 
 impl PooledConnection {
@@ -861,10 +1515,17 @@ impl PooledConnection {
             .map_err(Into::into)
     }
 
-    #[allow(dead_code)]
     pub async fn llen(&mut self, key: &str) -> Result<usize> {
         self.conn.llen(key).await.map_err(Into::into)
     }
+
+    pub async fn query_pipeline<T: redis::FromRedisValue>(&mut self, pipe: &redis::Pipeline) -> Result<T> {
+        pipe.query_async(&mut self.conn).await.map_err(Into::into)
+    }
+
+    pub async fn ping(&mut self) -> Result<String> {
+        redis::cmd("PING").query_async(&mut self.conn).await.map_err(Into::into)
+    }
 }
 
 // 直接连接包装器
@@ -946,8 +1607,282 @@ impl DirectConnection {
             .map_err(Into::into)
     }
 
-    #[allow(dead_code)]
     pub async fn llen(&mut self, key: &str) -> Result<usize> {
         self.conn.llen(key).await.map_err(Into::into)
     }
+
+    pub async fn query_pipeline<T: redis::FromRedisValue>(&mut self, pipe: &redis::Pipeline) -> Result<T> {
+        pipe.query_async(&mut self.conn).await.map_err(Into::into)
+    }
+
+    pub async fn ping(&mut self) -> Result<String> {
+        redis::cmd("PING").query_async(&mut self.conn).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 注意：以下测试需要本地Redis服务器运行
+    async fn test_manager() -> Option<RedisManager> {
+        let manager = RedisManager::new("redis://127.0.0.1:6379").ok()?;
+        match manager.get_async_connection().await {
+            Ok(_) => Some(manager),
+            Err(_) => None,
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_transaction_hides_intermediate_state() {
+        let manager = test_manager().await.expect("Redis不可用");
+        let (key_a, key_b) = ("test:tx:hidden:a", "test:tx:hidden:b");
+
+        let writer = {
+            let manager = manager.clone();
+            tokio::spawn(async move {
+                for i in 0..200 {
+                    manager
+                        .transaction::<(), _>(|pipe| {
+                            pipe.set(key_a, i).ignore().set(key_b, i).ignore();
+                        })
+                        .await
+                        .unwrap();
+                }
+            })
+        };
+
+        // 另一条连接读取时两个键总是一致
+        let mut reader = manager.client.get_async_connection().await.unwrap();
+        while !writer.is_finished() {
+            let (a, b): (Option<i64>, Option<i64>) = redis::cmd("MGET")
+                .arg(key_a)
+                .arg(key_b)
+                .query_async(&mut reader)
+                .await
+                .unwrap();
+            assert_eq!(a, b);
+        }
+        writer.await.unwrap();
+
+        let mut conn = manager.get_async_connection().await.unwrap();
+        conn.del(key_a).await.unwrap();
+        conn.del(key_b).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_transaction_all_or_nothing() {
+        let manager = test_manager().await.expect("Redis不可用");
+        let key = "test:tx:abort";
+        let mut conn = manager.get_async_connection().await.unwrap();
+        conn.set(key, "before").await.unwrap();
+
+        // 事务中有命令入队失败时整体放弃
+        let result = manager
+            .transaction::<(), _>(|pipe| {
+                pipe.set(key, "after").ignore();
+                pipe.cmd("NOT_A_COMMAND").ignore();
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(conn.get(key).await.unwrap(), "before");
+
+        manager
+            .transaction::<(), _>(|pipe| {
+                pipe.set(key, "after").ignore();
+            })
+            .await
+            .unwrap();
+        assert_eq!(conn.get(key).await.unwrap(), "after");
+        conn.del(key).await.unwrap();
+    }
+
+    fn io_error(kind: std::io::ErrorKind) -> anyhow::Error {
+        redis::RedisError::from(std::io::Error::new(kind, "mock")).into()
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_connection_error_uses_fresh_connection() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        // 第0号连接执行命令时被断开，重试时换用第1号连接成功
+        let connects = AtomicU32::new(0);
+        let result = retry_with_fresh_connection(
+            REDIS_RETRY_ATTEMPTS,
+            std::time::Duration::ZERO,
+            || {
+                let id = connects.fetch_add(1, Ordering::SeqCst);
+                async move { Ok(id) }
+            },
+            |conn: u32| async move {
+                if conn == 0 {
+                    Err(io_error(std::io::ErrorKind::ConnectionReset))
+                } else {
+                    Ok(format!("conn{}", conn))
+                }
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, "conn1");
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+        // 获取连接被拒绝同样重试
+        let connects = AtomicU32::new(0);
+        let result = retry_with_fresh_connection(
+            REDIS_RETRY_ATTEMPTS,
+            std::time::Duration::ZERO,
+            || {
+                let id = connects.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if id == 0 {
+                        Err(io_error(std::io::ErrorKind::ConnectionRefused))
+                    } else {
+                        Ok(id)
+                    }
+                }
+            },
+            |conn: u32| async move { Ok(conn) },
+        )
+        .await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_logical_errors_not_retried() {
+        let mut calls = 0;
+        let result: Result<()> = retry_with_fresh_connection(
+            REDIS_RETRY_ATTEMPTS,
+            std::time::Duration::ZERO,
+            || async { Ok(()) },
+            |_conn: ()| {
+                calls += 1;
+                async { Err(redis::RedisError::from((redis::ErrorKind::TypeError, "WRONGTYPE")).into()) }
+            },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        // 持续的连接错误在达到次数上限后放弃
+        let mut calls = 0;
+        let result: Result<()> = retry_with_fresh_connection(
+            REDIS_RETRY_ATTEMPTS,
+            std::time::Duration::ZERO,
+            || async { Ok(()) },
+            |_conn: ()| {
+                calls += 1;
+                async { Err(io_error(std::io::ErrorKind::BrokenPipe)) }
+            },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, REDIS_RETRY_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn test_ping_without_pool() {
+        // 非连接池模式下服务不可达时返回错误而不是 panic
+        let unreachable = RedisManager::new("redis://127.0.0.1:1").unwrap();
+        assert!(!unreachable.is_using_pool());
+        assert!(unreachable.ping().await.is_err());
+
+        if let Some(manager) = test_manager().await {
+            assert!(!manager.is_using_pool());
+            assert_eq!(manager.ping().await.unwrap(), "PONG");
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_mark_session_escalated() {
+        let manager = test_manager().await.expect("Redis不可用");
+        let session_id = "test_kehu:test_kefu";
+        assert!(!manager.is_session_escalated(session_id).await.unwrap());
+
+        manager.mark_session_escalated(session_id, "{}").await.unwrap();
+        assert!(manager.is_session_escalated(session_id).await.unwrap());
+
+        let mut conn = manager.get_async_connection().await.unwrap();
+        conn.del(&format!("session:{}:escalated", session_id)).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_concurrent_ratings_recorded_once() {
+        let manager = test_manager().await.expect("Redis不可用");
+        let kefu_id = format!("test_kefu_{}", uuid::Uuid::new_v4());
+        let rating = SessionRating {
+            session_id: format!("test_kehu:{}", kefu_id),
+            customer_id: "test_kehu".to_string(),
+            kefu_id: kefu_id.clone(),
+            score: 5,
+            comment: None,
+            timestamp: Utc::now(),
+        };
+
+        let (first, second) = tokio::join!(
+            manager.record_session_rating(&rating),
+            manager.record_session_rating(&rating)
+        );
+        let recorded: Vec<_> = [first.unwrap(), second.unwrap()].into_iter().flatten().collect();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].rating_count, 1);
+
+        let mut conn = manager.get_async_connection().await.unwrap();
+        for key in [
+            format!("rating:{}", rating.session_id),
+            format!("ratings:{}", kefu_id),
+            format!("satisfaction:{}", kefu_id),
+        ] {
+            conn.del(&key).await.unwrap();
+        }
+        conn.srem("kefu:rated", &kefu_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_customer_claimed_once_whether_queued_or_not() {
+        let manager = test_manager().await.expect("Redis不可用");
+        let queued = format!("test_kehu_{}", uuid::Uuid::new_v4());
+        let unqueued = format!("test_kehu_{}", uuid::Uuid::new_v4());
+        manager.add_to_waiting_queue(&queued).await.unwrap();
+
+        for customer_id in [&queued, &unqueued] {
+            let (first, second) = tokio::join!(
+                manager.claim_waiting_customer(customer_id),
+                manager.claim_waiting_customer(customer_id)
+            );
+            assert_eq!([first.unwrap(), second.unwrap()].iter().filter(|claimed| **claimed).count(), 1);
+        }
+        assert!(!manager.get_waiting_queue().await.unwrap().contains(&queued));
+
+        let mut conn = manager.get_async_connection().await.unwrap();
+        for customer_id in [&queued, &unqueued] {
+            conn.del(&format!("claim:{}", customer_id)).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_offline_messages_enqueued_during_delivery_are_kept() {
+        let manager = test_manager().await.expect("Redis不可用");
+        let user_id = format!("test_kehu_{}", uuid::Uuid::new_v4());
+        let config = MessageQueueConfig::default();
+        for payload in ["a", "b"] {
+            manager.push_offline_message(&user_id, payload, &config).await.unwrap();
+        }
+
+        let taken = manager.take_offline_messages(&user_id).await.unwrap();
+        assert_eq!(taken, vec!["a".to_string(), "b".to_string()]);
+        // 投递期间新入队的消息不受影响；未送达的消息放回后排在新消息之前
+        manager.push_offline_message(&user_id, "c", &config).await.unwrap();
+        manager.requeue_offline_messages(&user_id, &taken[1..], 60).await.unwrap();
+
+        let remaining = manager.take_offline_messages(&user_id).await.unwrap();
+        assert_eq!(remaining, vec!["b".to_string(), "c".to_string()]);
+        assert!(manager.take_offline_messages(&user_id).await.unwrap().is_empty());
+    }
 }
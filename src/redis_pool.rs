@@ -1,15 +1,81 @@
 use anyhow::Result;
-use deadpool_redis::{Config, Pool, Runtime};
+use deadpool::managed::{self, Metrics, Object, RecycleError, RecycleResult};
+use deadpool::Runtime;
+use redis::aio::ConnectionLike;
+use redis::sentinel::{Sentinel, SentinelNodeConnectionInfo};
+use redis::{ConnectionAddr, ConnectionInfo, IntoConnectionInfo, RedisError, RedisResult};
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::{error, info, warn};
 
+/// 连接池利用率（百分比）超过该值视为压力过高
+pub const POOL_UTILIZATION_WARN_THRESHOLD: f64 = 80.0;
+
+/// 连接池取连接失败时的错误
+pub type PoolError = managed::PoolError<RedisError>;
+
+type Pool = managed::Pool<TopologyManager, RedisConnection>;
+
+/// Redis部署方式
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum RedisTopology {
+    /// 单节点，连接 `url`
+    #[default]
+    Single,
+    /// 哨兵模式：每次建立连接时向哨兵查询当前主节点，主从切换后新连接自动指向新主节点
+    Sentinel {
+        addresses: Vec<String>,
+        master_name: String,
+    },
+    /// 集群模式：按槽位路由命令。键分属不同槽位的事务会逐条执行（见 RedisManager::transaction），
+    /// 不再保证原子性
+    Cluster { nodes: Vec<String> },
+}
+
+impl RedisTopology {
+    fn name(&self) -> &'static str {
+        match self {
+            RedisTopology::Single => "single",
+            RedisTopology::Sentinel { .. } => "sentinel",
+            RedisTopology::Cluster { .. } => "cluster",
+        }
+    }
+}
+
+// 节点地址允许省略协议，如 10.0.0.1:26379
+fn node_url(address: &str) -> String {
+    if address.contains("://") {
+        address.to_string()
+    } else {
+        format!("redis://{}", address)
+    }
+}
+
+fn parse_nodes(kind: &str, addresses: &[String]) -> Result<Vec<redis::ConnectionInfo>> {
+    if addresses.is_empty() {
+        return Err(anyhow::anyhow!("{}地址列表不能为空", kind));
+    }
+    addresses
+        .iter()
+        .map(|address| {
+            node_url(address.trim())
+                .into_connection_info()
+                .map_err(|e| anyhow::anyhow!("{}地址无效 {}: {}", kind, address, e))
+        })
+        .collect()
+}
+
 // 连接池配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RedisPoolConfig {
+    /// 单节点地址；哨兵模式下其中的密码和库号用于连接主节点
     pub url: String,
+    #[serde(default)]
+    pub topology: RedisTopology,
     pub max_size: usize,
     pub min_idle: Option<usize>,
     pub max_lifetime: Option<Duration>,
@@ -28,10 +94,272 @@ impl Default for RedisPoolConfig {
             idle_timeout: Some(Duration::from_secs(600)),  // 空闲超时：10分钟
             connection_timeout: Duration::from_secs(5),    // 连接超时：5秒
             recycle_timeout: Duration::from_secs(2),       // 回收超时：2秒
+            topology: RedisTopology::Single,
+        }
+    }
+}
+
+impl RedisPoolConfig {
+    /// 校验各部署方式所需的地址和主节点名称
+    pub fn validate(&self) -> Result<()> {
+        self.url
+            .as_str()
+            .into_connection_info()
+            .map_err(|e| anyhow::anyhow!("Redis地址无效 {}: {}", self.url, e))?;
+        match &self.topology {
+            RedisTopology::Single => {}
+            RedisTopology::Sentinel { addresses, master_name } => {
+                parse_nodes("哨兵", addresses)?;
+                if master_name.trim().is_empty() {
+                    return Err(anyhow::anyhow!("哨兵模式需要配置主节点名称"));
+                }
+            }
+            RedisTopology::Cluster { nodes } => {
+                parse_nodes("集群节点", nodes)?;
+            }
+        }
+        if self.max_size == 0 {
+            return Err(anyhow::anyhow!("连接池最大连接数必须大于0"));
+        }
+        Ok(())
+    }
+}
+
+// 按部署方式创建连接的来源
+enum ConnectionSource {
+    Single(redis::Client),
+    Sentinel {
+        // 查询主节点需要可变借用
+        sentinel: tokio::sync::Mutex<Sentinel>,
+        addresses: Vec<ConnectionInfo>,
+        master_name: String,
+        node_info: SentinelNodeConnectionInfo,
+    },
+    Cluster {
+        client: redis::cluster::ClusterClient,
+        // 首个节点的连接信息，直连各主节点时沿用其中的密码
+        seed: ConnectionInfo,
+    },
+}
+
+// CLUSTER NODES 输出中未处于故障状态的主节点地址
+fn cluster_masters(nodes: &str) -> Vec<(String, u16)> {
+    nodes
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let address = fields.nth(1)?;
+            let flags = fields.next()?;
+            if !flags.split(',').any(|flag| flag == "master") || flags.contains("fail") {
+                return None;
+            }
+            // 形如 10.0.0.1:7000@17000,hostname
+            let address = address.split(['@', ',']).next()?;
+            let (host, port) = address.rsplit_once(':')?;
+            Some((host.to_string(), port.parse().ok()?))
+        })
+        .collect()
+}
+
+/// 连接池中的底层连接
+pub enum TopologyConnection {
+    Single(redis::aio::Connection),
+    Sentinel(redis::aio::MultiplexedConnection),
+    Cluster(redis::cluster_async::ClusterConnection),
+}
+
+impl ConnectionLike for TopologyConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a redis::Cmd) -> redis::RedisFuture<'a, redis::Value> {
+        match self {
+            TopologyConnection::Single(conn) => conn.req_packed_command(cmd),
+            TopologyConnection::Sentinel(conn) => conn.req_packed_command(cmd),
+            TopologyConnection::Cluster(conn) => conn.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a redis::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
+        match self {
+            TopologyConnection::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            TopologyConnection::Sentinel(conn) => conn.req_packed_commands(cmd, offset, count),
+            TopologyConnection::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            TopologyConnection::Single(conn) => conn.get_db(),
+            TopologyConnection::Sentinel(conn) => conn.get_db(),
+            TopologyConnection::Cluster(conn) => conn.get_db(),
+        }
+    }
+}
+
+/// 按部署方式创建、回收连接的连接池管理器
+pub struct TopologyManager {
+    source: ConnectionSource,
+    ping_number: AtomicUsize,
+}
+
+impl TopologyManager {
+    pub fn new(config: &RedisPoolConfig) -> Result<Self> {
+        config.validate()?;
+        let source = match &config.topology {
+            RedisTopology::Single => ConnectionSource::Single(redis::Client::open(config.url.as_str())?),
+            RedisTopology::Sentinel { addresses, master_name } => {
+                let addresses = parse_nodes("哨兵", addresses)?;
+                ConnectionSource::Sentinel {
+                    sentinel: tokio::sync::Mutex::new(Sentinel::build(addresses.clone())?),
+                    addresses,
+                    master_name: master_name.trim().to_string(),
+                    node_info: SentinelNodeConnectionInfo {
+                        tls_mode: None,
+                        redis_connection_info: Some(config.url.as_str().into_connection_info()?.redis),
+                    },
+                }
+            }
+            RedisTopology::Cluster { nodes } => {
+                let nodes = parse_nodes("集群节点", nodes)?;
+                ConnectionSource::Cluster {
+                    seed: nodes[0].clone(),
+                    client: redis::cluster::ClusterClient::new(nodes)?,
+                }
+            }
+        };
+        Ok(Self {
+            source,
+            ping_number: AtomicUsize::new(0),
+        })
+    }
+
+    // 哨兵模式下每次调用都重新查询，主从切换后返回新主节点
+    async fn sentinel_master(
+        sentinel: &tokio::sync::Mutex<Sentinel>,
+        master_name: &str,
+        node_info: &SentinelNodeConnectionInfo,
+    ) -> RedisResult<redis::Client> {
+        sentinel.lock().await.async_master_for(master_name, Some(node_info)).await
+    }
+
+    /// 当前所有主节点的直连客户端，SCAN 等只作用于单个节点的命令须逐个执行
+    pub async fn master_clients(&self) -> RedisResult<Vec<redis::Client>> {
+        match &self.source {
+            ConnectionSource::Single(client) => Ok(vec![client.clone()]),
+            ConnectionSource::Sentinel { sentinel, master_name, node_info, .. } => {
+                Ok(vec![Self::sentinel_master(sentinel, master_name, node_info).await?])
+            }
+            ConnectionSource::Cluster { client, seed } => {
+                let mut conn = client.get_async_connection().await?;
+                let nodes: String = redis::cmd("CLUSTER").arg("NODES").query_async(&mut conn).await?;
+                cluster_masters(&nodes)
+                    .into_iter()
+                    .map(|(host, port)| {
+                        redis::Client::open(ConnectionInfo {
+                            addr: ConnectionAddr::Tcp(host, port),
+                            redis: seed.redis.clone(),
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// 同步直连客户端，供仍使用阻塞连接的旧模块使用；哨兵模式在调用时查询当前主节点
+    pub fn blocking_client(&self) -> RedisResult<redis::Client> {
+        match &self.source {
+            ConnectionSource::Single(client) => Ok(client.clone()),
+            ConnectionSource::Sentinel { addresses, master_name, node_info, .. } => {
+                // 另建哨兵客户端查询，避免在同步代码中占用异步锁
+                Sentinel::build(addresses.clone())?.master_for(master_name, Some(node_info))
+            }
+            ConnectionSource::Cluster { seed, .. } => redis::Client::open(seed.clone()),
         }
     }
 }
 
+#[async_trait::async_trait]
+impl managed::Manager for TopologyManager {
+    type Type = TopologyConnection;
+    type Error = RedisError;
+
+    async fn create(&self) -> Result<TopologyConnection, RedisError> {
+        Ok(match &self.source {
+            ConnectionSource::Single(client) => TopologyConnection::Single(client.get_async_connection().await?),
+            ConnectionSource::Sentinel { sentinel, master_name, node_info, .. } => {
+                let master = Self::sentinel_master(sentinel, master_name, node_info).await?;
+                TopologyConnection::Sentinel(master.get_multiplexed_async_connection().await?)
+            }
+            ConnectionSource::Cluster { client, .. } => {
+                TopologyConnection::Cluster(client.get_async_connection().await?)
+            }
+        })
+    }
+
+    async fn recycle(&self, conn: &mut TopologyConnection, _: &Metrics) -> RecycleResult<RedisError> {
+        let ping_number = self.ping_number.fetch_add(1, Ordering::Relaxed).to_string();
+        let pong: String = redis::cmd("PING").arg(&ping_number).query_async(&mut *conn).await?;
+        if pong != ping_number {
+            return Err(RecycleError::StaticMessage("PING响应无效"));
+        }
+        // 主从切换后旧主节点降为从节点，连接须丢弃后重新向哨兵查询
+        if matches!(conn, TopologyConnection::Sentinel(_)) {
+            let role: Vec<redis::Value> = redis::cmd("ROLE").query_async(&mut *conn).await?;
+            if !matches!(role.first(), Some(redis::Value::Data(role)) if role.as_slice() == b"master") {
+                return Err(RecycleError::StaticMessage("哨兵主节点已切换"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 从连接池取出的连接，归还由 Drop 完成
+pub struct RedisConnection {
+    conn: Object<TopologyManager>,
+}
+
+impl From<Object<TopologyManager>> for RedisConnection {
+    fn from(conn: Object<TopologyManager>) -> Self {
+        Self { conn }
+    }
+}
+
+impl Deref for RedisConnection {
+    type Target = TopologyConnection;
+
+    fn deref(&self) -> &TopologyConnection {
+        &self.conn
+    }
+}
+
+impl DerefMut for RedisConnection {
+    fn deref_mut(&mut self) -> &mut TopologyConnection {
+        &mut self.conn
+    }
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a redis::Cmd) -> redis::RedisFuture<'a, redis::Value> {
+        self.conn.req_packed_command(cmd)
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a redis::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
+        self.conn.req_packed_commands(cmd, offset, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.conn.get_db()
+    }
+}
+
 // 连接池性能指标
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PoolMetrics {
@@ -125,33 +453,21 @@ impl std::fmt::Debug for RedisPoolManager {
 
 impl RedisPoolManager {
     pub fn new(config: RedisPoolConfig) -> Result<Self> {
-        // 创建连接池配置
-        let mut pool_config = Config::from_url(&config.url);
-
-        // 设置连接池参数 - 使用正确的API结构
-        if let Some(pool_opts) = &mut pool_config.pool {
-            pool_opts.max_size = config.max_size;
-            if let Some(timeout) = config.idle_timeout {
-                pool_opts.timeouts.wait = Some(timeout);
-            }
-            if let Some(timeout) = config.max_lifetime {
-                pool_opts.timeouts.recycle = Some(timeout);
-            }
-        } else {
-            // 如果pool字段为None，创建默认配置
-            let mut pool_opts = deadpool_redis::PoolConfig::new(config.max_size);
-            pool_opts.timeouts.wait = config.idle_timeout;
-            pool_opts.timeouts.create = Some(config.connection_timeout);
-            pool_opts.timeouts.recycle = config.max_lifetime;
-            pool_config.pool = Some(pool_opts);
-        }
-
-        // 创建连接池
-        let pool = pool_config.create_pool(Some(Runtime::Tokio1))?;
+        // 创建连接池，按部署方式选择连接来源
+        let pool = Pool::builder(TopologyManager::new(&config)?)
+            .max_size(config.max_size)
+            .wait_timeout(config.idle_timeout)
+            .create_timeout(Some(config.connection_timeout))
+            .recycle_timeout(config.max_lifetime)
+            .runtime(Runtime::Tokio1)
+            .build()?;
 
         info!(
-            "Redis连接池初始化成功 - URL: {}, 最大连接数: {}, 最小空闲: {:?}",
-            config.url, config.max_size, config.min_idle
+            "Redis连接池初始化成功 - 模式: {}, URL: {}, 最大连接数: {}, 最小空闲: {:?}",
+            config.topology.name(),
+            config.url,
+            config.max_size,
+            config.min_idle
         );
 
         Ok(Self {
@@ -163,7 +479,7 @@ impl RedisPoolManager {
     }
 
     // 获取连接（带监控）
-    pub async fn get_connection(&self) -> Result<deadpool_redis::Connection> {
+    pub async fn get_connection(&self) -> Result<RedisConnection> {
         let start_time = Instant::now();
 
         match tokio::time::timeout(self.config.connection_timeout, self.pool.get()).await {
@@ -197,7 +513,7 @@ impl RedisPoolManager {
     #[allow(dead_code)] // 企业级功能保留
     pub async fn execute<F, R>(&self, operation: F) -> Result<R>
     where
-        F: FnOnce(&mut deadpool_redis::Connection) -> Result<R> + Send,
+        F: FnOnce(&mut RedisConnection) -> Result<R> + Send,
         R: Send,
     {
         let mut conn = self.get_connection().await?;
@@ -210,8 +526,8 @@ impl RedisPoolManager {
     #[allow(dead_code)] // 企业级功能保留
     pub async fn execute_async<F, Fut, R>(&self, operation: F) -> Result<R>
     where
-        F: FnOnce(deadpool_redis::Connection) -> Fut + Send,
-        Fut: std::future::Future<Output = Result<(deadpool_redis::Connection, R)>> + Send,
+        F: FnOnce(RedisConnection) -> Fut + Send,
+        Fut: std::future::Future<Output = Result<(RedisConnection, R)>> + Send,
         R: Send,
     {
         let conn = self.get_connection().await?;
@@ -226,7 +542,7 @@ impl RedisPoolManager {
 
     // 获取连接池状态
     #[allow(dead_code)] // 企业级功能保留
-    pub fn get_pool_status(&self) -> deadpool_redis::Status {
+    pub fn get_pool_status(&self) -> managed::Status {
         self.pool.status()
     }
 
@@ -320,7 +636,7 @@ impl RedisPoolManager {
 
                 // 检查连接池性能警告
                 let metrics = pool_manager.get_metrics();
-                if metrics.pool_utilization > 80.0 {
+                if metrics.pool_utilization > POOL_UTILIZATION_WARN_THRESHOLD {
                     warn!(
                         "Redis连接池使用率过高: {:.1}%, 考虑增加连接数",
                         metrics.pool_utilization
@@ -356,6 +672,27 @@ impl RedisPoolManager {
     pub fn get_config(&self) -> &RedisPoolConfig {
         &self.config
     }
+
+    /// 是否为集群模式：跨槽位的多键命令和事务不可用
+    pub fn is_cluster(&self) -> bool {
+        matches!(self.config.topology, RedisTopology::Cluster { .. })
+    }
+
+    // 以下按部署方式取独占连接的客户端，不经过连接池
+    pub async fn master_clients(&self) -> RedisResult<Vec<redis::Client>> {
+        self.pool.manager().master_clients().await
+    }
+
+    /// 订阅专用连接的客户端：哨兵模式指向当前主节点，断线重连时随主从切换；集群中 PUBLISH 会广播到所有节点，订阅任一主节点即可
+    pub async fn pubsub_client(&self) -> RedisResult<redis::Client> {
+        self.master_clients().await?.into_iter().next().ok_or_else(|| {
+            RedisError::from((redis::ErrorKind::ClusterDown, "集群中没有可用的主节点"))
+        })
+    }
+
+    pub fn blocking_client(&self) -> RedisResult<redis::Client> {
+        self.pool.manager().blocking_client()
+    }
 }
 
 // 实现Clone以支持共享
@@ -425,6 +762,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cluster_masters_parsed_from_nodes() {
+        let nodes = "\
+07c37dfeb235213a872192d90877d0cd55635b91 10.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-5460
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 10.0.0.2:7000@17000,redis-b.local master - 0 1426238316232 2 connected 5461-10922
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 10.0.0.3:7000@17000 master,fail - 1426238316232 0 3 connected
+6ec23923021cf3ffec47632106199cb7f496ce01 10.0.0.4:7000@17000 slave 67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 0 1426238316232 5 connected
+";
+        assert_eq!(
+            cluster_masters(nodes),
+            vec![("10.0.0.1".to_string(), 7000), ("10.0.0.2".to_string(), 7000)]
+        );
+    }
+
+    #[test]
+    fn test_topology_validation() {
+        let sentinel = RedisPoolConfig {
+            topology: RedisTopology::Sentinel {
+                addresses: vec!["10.0.0.1:26379".to_string(), "redis://10.0.0.2:26379".to_string()],
+                master_name: "mymaster".to_string(),
+            },
+            ..Default::default()
+        };
+        assert!(sentinel.validate().is_ok());
+        assert!(TopologyManager::new(&sentinel).is_ok());
+
+        let no_master = RedisPoolConfig {
+            topology: RedisTopology::Sentinel {
+                addresses: vec!["10.0.0.1:26379".to_string()],
+                master_name: " ".to_string(),
+            },
+            ..Default::default()
+        };
+        assert!(no_master.validate().is_err());
+
+        let cluster = RedisPoolConfig {
+            topology: RedisTopology::Cluster {
+                nodes: vec!["10.0.0.1:7000".to_string(), "10.0.0.2:7000".to_string()],
+            },
+            ..Default::default()
+        };
+        assert!(RedisPoolManager::new(cluster).is_ok());
+
+        let empty_cluster = RedisPoolConfig {
+            topology: RedisTopology::Cluster { nodes: Vec::new() },
+            ..Default::default()
+        };
+        assert!(RedisPoolManager::new(empty_cluster).is_err());
+
+        let bad_node = RedisPoolConfig {
+            topology: RedisTopology::Cluster {
+                nodes: vec!["http://10.0.0.1:7000".to_string()],
+            },
+            ..Default::default()
+        };
+        assert!(bad_node.validate().is_err());
+    }
+
     #[tokio::test]
     async fn test_metrics_collection() {
         let config = RedisPoolConfig::default();
@@ -19,6 +19,7 @@ pub fn build_extended_api_routes(
     // === 用户管理 API ===
     let users_list = warp::path!("api" / "users" / "list")
         .and(warp::get())
+        .and(warp::query())
         .and(with_user_manager(user_manager.clone()))
         .and_then(crate::handlers::users::handle_list_users);
 
@@ -50,6 +51,21 @@ pub fn build_extended_api_routes(
         .and(with_user_manager(user_manager.clone()))
         .and_then(crate::handlers::users::handle_update_permissions);
 
+    let users_connection_events = warp::path!("api" / "v1" / "users" / String / "connection-events")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::query::<std::collections::HashMap<String, String>>())
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(handle_connection_events);
+
+    let users_online_status = warp::path!("api" / "v1" / "users" / "online-status")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::body::json())
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::users::handle_users_online_status);
+
     let users_status = warp::path!("api" / "users" / String / "status")
         .and(warp::put())
         .and(warp::body::json())
@@ -60,6 +76,7 @@ pub fn build_extended_api_routes(
     let messages_list = warp::path!("api" / "messages")
         .and(warp::get())
         .and(warp::query())
+        .and(warp::query())
         .and(with_storage(storage.clone()))
         .and_then(crate::handlers::messages::handle_list_messages);
 
@@ -71,15 +88,72 @@ pub fn build_extended_api_routes(
     let messages_search = warp::path!("api" / "messages" / "search")
         .and(warp::post())
         .and(warp::body::json())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
         .and(with_storage(storage.clone()))
         .and_then(crate::handlers::messages::handle_search_messages);
 
+    let messages_internal = warp::path!("api" / "messages" / "internal" / String)
+        .and(warp::get())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::messages::handle_internal_messages);
+
     let messages_export = warp::path!("api" / "messages" / "export")
         .and(warp::post())
         .and(warp::body::json())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
         .and(with_storage(storage.clone()))
         .and_then(crate::handlers::messages::handle_export_messages);
 
+    let messages_export_audit = warp::path!("api" / "messages" / "export" / "audit")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::query::<std::collections::HashMap<String, String>>())
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::messages::handle_export_audit);
+
+    let conversation_export = warp::path!("api" / "v1" / "conversations" / String / String / "export")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::query::<std::collections::HashMap<String, String>>())
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::messages::handle_export_conversation);
+
+    let messages_by_tag = warp::path!("api" / "messages" / "tags" / String)
+        .and(warp::get())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::messages::handle_list_messages_by_tag);
+
+    let messages_mark_read = warp::path!("api" / "messages" / "read")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::messages::handle_mark_messages_read);
+
+    let messages_bulk_delete = warp::path!("api" / "messages" / "bulk-delete")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::messages::handle_bulk_delete_messages);
+
+    let messages_status = warp::path!("api" / "messages" / String / "status")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::messages::handle_get_message_status);
+
     let messages_delete = warp::path!("api" / "messages" / String)
         .and(warp::delete())
         .and(with_storage(storage.clone()))
@@ -89,6 +163,7 @@ pub fn build_extended_api_routes(
     let sessions_list = warp::path!("api" / "sessions" / "list")
         .and(warp::get())
         .and(warp::query())
+        .and(warp::query())
         .and(with_ws_manager(ws_manager.clone()))
         .and_then(crate::handlers::sessions::handle_list_sessions);
 
@@ -100,16 +175,92 @@ pub fn build_extended_api_routes(
     let sessions_messages = warp::path!("api" / "sessions" / String / "messages")
         .and(warp::get())
         .and(warp::query())
+        .and(warp::query())
         .and(with_ws_manager(ws_manager.clone()))
         .and(with_storage(storage.clone()))
         .and_then(crate::handlers::sessions::handle_get_session_messages);
 
+    let sessions_messages_since = warp::path!("api" / "sessions" / String / "messages" / "since")
+        .and(warp::get())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::sessions::handle_get_messages_since);
+
     let sessions_transfer = warp::path!("api" / "sessions" / String / "transfer")
         .and(warp::post())
         .and(warp::body::json())
         .and(with_ws_manager(ws_manager.clone()))
         .and_then(crate::handlers::sessions::handle_transfer_session);
 
+    let sessions_transfer_customer = warp::path!("api" / "v1" / "sessions" / "transfer")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::sessions::handle_transfer_customer);
+
+    let tickets_list = warp::path!("api" / "v1" / "tickets")
+        .and(warp::get())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::sessions::handle_list_tickets);
+
+    let tickets_get = warp::path!("api" / "v1" / "tickets" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::sessions::handle_get_ticket);
+
+    let tickets_resolve = warp::path!("api" / "v1" / "tickets" / String / "resolve")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_storage(storage.clone()))
+        .and_then(crate::handlers::sessions::handle_resolve_ticket);
+
+    // === 快捷回复 API ===
+    let canned_responses_list = warp::path!("api" / "v1" / "canned-responses")
+        .and(warp::get())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::canned_responses::handle_list_canned_responses);
+
+    let canned_responses_create = warp::path!("api" / "v1" / "canned-responses")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::canned_responses::handle_create_canned_response);
+
+    let canned_responses_update = warp::path!("api" / "v1" / "canned-responses" / String)
+        .and(warp::put())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::canned_responses::handle_update_canned_response);
+
+    let canned_responses_delete = warp::path!("api" / "v1" / "canned-responses" / String)
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::canned_responses::handle_delete_canned_response);
+
+    // === 语音 API ===
+    let voice_transcribe = warp::path!("api" / "v1" / "voice" / String / "transcribe")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::voice::handle_transcribe_voice);
+
     // === 统计分析 API ===
     let analytics_overview = warp::path!("api" / "analytics" / "overview")
         .and(warp::get())
@@ -130,11 +281,36 @@ pub fn build_extended_api_routes(
         .and(with_user_manager(user_manager.clone()))
         .and_then(crate::handlers::analytics::handle_analytics_users);
 
+    let analytics_connections = warp::path!("api" / "analytics" / "connections")
+        .and(warp::get())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("cache-control"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::analytics::handle_analytics_connections);
+
     let analytics_performance = warp::path!("api" / "analytics" / "performance")
         .and(warp::get())
         .and(with_ws_manager(ws_manager.clone()))
         .and_then(crate::handlers::analytics::handle_analytics_performance);
 
+    let analytics_satisfaction = warp::path!("api" / "analytics" / "satisfaction")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("cache-control"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::analytics::handle_analytics_satisfaction);
+
+    let kefu_workload = warp::path!("api" / "v1" / "kefu" / "workload")
+        .and(warp::get())
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::analytics::handle_kefu_workload);
+
+    let analytics_sla = warp::path!("api" / "analytics" / "sla")
+        .and(warp::get())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("cache-control"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(crate::handlers::analytics::handle_analytics_sla);
+
     // === 系统管理 API ===
     let system_logs = warp::path!("api" / "system" / "logs")
         .and(warp::get())
@@ -152,12 +328,36 @@ pub fn build_extended_api_routes(
         .and(warp::body::json())
         .and_then(handle_system_maintenance);
 
+    let system_auto_scaling = warp::path!("api" / "v1" / "system" / "auto-scaling")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(handle_auto_scaling);
+
     let system_health = warp::path!("api" / "system" / "health")
         .and(warp::get())
         .and(with_ws_manager(ws_manager.clone()))
         .and(with_storage(storage.clone()))
         .and_then(handle_system_health);
 
+    let log_level_get = warp::path!("admin" / "log-level")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and_then(handle_get_log_level);
+
+    let log_level_set = warp::path!("admin" / "log-level")
+        .and(warp::put())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::body::json())
+        .and_then(handle_set_log_level);
+
+    let system_broadcast_publish = warp::path!("admin" / "broadcasts")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::body::json())
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(handle_publish_system_broadcast);
+
     // === Redis管理 API ===
     let redis_status = warp::path!("api" / "redis" / "status")
         .and(warp::get())
@@ -187,23 +387,50 @@ pub fn build_extended_api_routes(
         .or(users_delete)
         .or(users_permissions)
         .or(users_status)
+        .or(users_connection_events)
+        .or(users_online_status)
         .or(messages_list)
         .or(messages_get)
         .or(messages_search)
+        .or(messages_internal)
         .or(messages_export)
+        .or(messages_export_audit)
+        .or(conversation_export)
+        .or(messages_by_tag)
+        .or(messages_mark_read)
+        .or(messages_bulk_delete)
+        .or(messages_status)
         .or(messages_delete)
         .or(sessions_list)
         .or(sessions_get)
         .or(sessions_messages)
+        .or(sessions_messages_since)
         .or(sessions_transfer)
+        .or(sessions_transfer_customer)
+        .or(tickets_list)
+        .or(tickets_get)
+        .or(tickets_resolve)
+        .or(canned_responses_list)
+        .or(canned_responses_create)
+        .or(canned_responses_update)
+        .or(canned_responses_delete)
+        .or(voice_transcribe)
         .or(analytics_overview)
         .or(analytics_messages)
         .or(analytics_users)
+        .or(analytics_connections)
         .or(analytics_performance)
+        .or(analytics_satisfaction)
+        .or(analytics_sla)
+        .or(kefu_workload)
         .or(system_logs)
         .or(system_backup)
         .or(system_maintenance)
         .or(system_health)
+        .or(system_auto_scaling)
+        .or(log_level_get)
+        .or(log_level_set)
+        .or(system_broadcast_publish)
         .or(redis_status)
         .or(redis_flush)
         .or(redis_keys)
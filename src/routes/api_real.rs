@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use warp::Filter;
 use crate::file_manager::{FileManager, FileListRequest};
-use crate::types::api::ApiResponse;
+use crate::types::api::{ApiResponse, PageRequest, PageResponse};
 
 /// 构建真实的文件管理API路由
 pub fn build_real_file_api_routes(
@@ -12,6 +12,7 @@ pub fn build_real_file_api_routes(
     let file_list_route = warp::path!("api" / "file" / "list")
         .and(warp::get())
         .and(warp::query())
+        .and(warp::query())
         .and(with_file_manager(file_manager.clone()))
         .and_then(handle_real_file_list);
 
@@ -28,6 +29,12 @@ pub fn build_real_file_api_routes(
         .and(with_file_manager(file_manager.clone()))
         .and_then(handle_real_file_download);
 
+    // 图片缩略图路由
+    let file_thumbnail_route = warp::path!("api" / "file" / "thumbnail" / String)
+        .and(warp::get())
+        .and(with_file_manager(file_manager.clone()))
+        .and_then(handle_file_thumbnail);
+
     // 文件删除路由（真实实现）
     let file_delete_route = warp::path!("api" / "file" / String)
         .and(warp::delete())
@@ -58,6 +65,7 @@ pub fn build_real_file_api_routes(
     file_list_route
         .or(file_upload_route)
         .or(file_download_route)
+        .or(file_thumbnail_route)
         .or(file_delete_route)
         .or(file_info_route)
         .or(file_bulk_delete_route)
@@ -78,6 +86,8 @@ use futures_util::TryStreamExt;
 use bytes::BufMut;
 use crate::file_manager_ext::FileManagerExt;
 use crate::message::ContentType;
+use crate::config::AppConfig;
+use crate::errors::UploadRejected;
 
 // 使用 types 模块中的 FileListQuery，不要重复定义
 use crate::types::api::FileListQuery;
@@ -98,11 +108,9 @@ pub struct BulkDeleteRequest {
 // 获取文件列表（真实实现）
 async fn handle_real_file_list(
     query: FileListQuery,
+    page: PageRequest,
     file_manager: Arc<FileManager>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let page = query.page.unwrap_or(1);
-    let limit = query.limit.unwrap_or(20);
-    
     // 构建 FileListRequest
     let request = FileListRequest {
         category: query.category.as_ref().and_then(|c| {
@@ -115,8 +123,8 @@ async fn handle_real_file_list(
             }
         }),
         uploaded_by: None,
-        page,
-        limit,
+        page: page.page(),
+        limit: page.page_size(),
         sort_by: "uploaded_at".to_string(),
         sort_order: "desc".to_string(),
     };
@@ -127,14 +135,7 @@ async fn handle_real_file_list(
             let api_response = ApiResponse {
                 success: true,
                 message: "文件列表获取成功".to_string(),
-                data: Some(serde_json::json!({
-                    "files": response.files,
-                    "total": response.total,
-                    "page": response.page,
-                    "limit": response.limit,
-                    "has_more": response.has_more,
-                    "total_pages": (response.total as f32 / response.limit as f32).ceil() as u32
-                })),
+                data: Some(PageResponse::from_page(response.files, response.total as usize, &page)),
             };
             Ok(warp::reply::json(&api_response))
         }
@@ -161,6 +162,7 @@ async fn handle_real_file_upload(
 
     let mut file_data = None;
     let mut file_name = None;
+    let mut declared_mime = None;
     let mut category = "default".to_string();
     let mut user_id = "anonymous".to_string();
 
@@ -169,6 +171,7 @@ async fn handle_real_file_upload(
         match part.name() {
             "file" => {
                 file_name = part.filename().map(|s| s.to_string());
+                declared_mime = part.content_type().map(|s| s.to_string());
                 let data = part.stream().try_fold(Vec::new(), |mut vec, data| {
                     vec.put(data);
                     async move { Ok(vec) }
@@ -215,6 +218,16 @@ async fn handle_real_file_upload(
         }
     };
 
+    // 校验文件类型与大小，未声明类型时按文件名推断
+    let declared_mime = declared_mime
+        .filter(|mime| mime != "application/octet-stream")
+        .unwrap_or_else(|| mime_guess::from_path(&name).first_or_octet_stream().to_string());
+    let max_size = AppConfig::get().frontend.upload.max_size_for(&declared_mime);
+    if let Err(e) = FileManager::validate_upload(&data, &declared_mime, max_size) {
+        tracing::warn!("❌ 拒绝上传文件 {}: {}", name, e);
+        return Err(warp::reject::custom(UploadRejected { message: e.to_string() }));
+    }
+
     // 保存文件
     match file_manager.save_file(&name, &data, &category, &user_id).await {
         Ok(file_info) => {
@@ -256,6 +269,20 @@ async fn handle_real_file_download(
     }
 }
 
+// 图片缩略图，统一为PNG
+async fn handle_file_thumbnail(
+    file_id: String,
+    file_manager: Arc<FileManager>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match file_manager.read_thumbnail(&file_id).await {
+        Ok(data) => Ok(warp::reply::with_header(data, "Content-Type", "image/png").into_response()),
+        Err(e) => {
+            tracing::warn!("缩略图读取失败: {}", e);
+            Err(warp::reject::not_found())
+        }
+    }
+}
+
 // 文件删除（真实实现）
 async fn handle_real_file_delete(
     file_id: String,
@@ -5,7 +5,7 @@ use crate::file_manager::FileManager;
 use crate::html_template_manager::HtmlTemplateManager;
 use crate::voice_message::VoiceMessageManager;
 use crate::storage::LocalStorage;
-use crate::types::api::{ApiResponse, IpLocationQuery, ClientRegisterInfo};
+use crate::types::api::{ApiResponse, IpLocationQuery, ClientRegisterInfo, ClientConfigQuery};
 use crate::handlers::system::*;
 use crate::handlers::client::*;
 
@@ -25,6 +25,18 @@ pub fn build_api_routes(
             handle_get_config().await
         });
 
+    // 配置项schema路由
+    let config_schema_route = warp::path!("api" / "config" / "schema")
+        .and(warp::get())
+        .and_then(|| async {
+            let response = ApiResponse {
+                success: true,
+                message: "获取配置项说明成功".to_string(),
+                data: Some(crate::config::export_config_schema()),
+            };
+            Result::<_, warp::Rejection>::Ok(warp::reply::json(&response))
+        });
+
     // 在线用户列表路由（无需认证）
     let ws_manager_users = ws_manager.clone();
     let users_route = warp::path!("api" / "users")
@@ -323,6 +335,16 @@ pub fn build_api_routes(
             }
         });
 
+    // 客户端配置下发路由，可按 client_type / version 定制
+    let client_config_route = warp::path!("api" / "v1" / "client-config")
+        .and(warp::get())
+        .and(warp::query::<ClientConfigQuery>())
+        .and_then(move |query: ClientConfigQuery| {
+            async move {
+                handle_client_config(query).await
+            }
+        });
+
     // 客户端信息查询路由 (额外功能)
     let storage_query = storage.clone();
     let client_info_route = warp::path!("api" / "client" / "info" / String)
@@ -356,6 +378,7 @@ pub fn build_api_routes(
 
     // 组合所有路由
     config_route
+        .or(config_schema_route)
         .or(users_route)
         .or(public_users_route)
         .or(realtime_users_route)
@@ -373,4 +396,5 @@ pub fn build_api_routes(
         .or(ip_location_route)
         .or(client_register_route)
         .or(client_info_route)
+        .or(client_config_route)
 }
\ No newline at end of file
@@ -1,11 +1,20 @@
 use std::sync::Arc;
+use serde::Deserialize;
 use warp::Filter;
+use crate::auth::jwt_auth::RefreshTokenManager;
 use crate::user_manager::{UserManager, LoginRequest};
 use crate::types::api::{ApiResponse, SuccessResponse};
 
+/// 刷新令牌请求
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
 /// 构建简化的认证路由
 pub fn build_auth_routes(
     _user_manager: Arc<UserManager>,
+    refresh_tokens: Arc<RefreshTokenManager>,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     
     // 登录路由
@@ -67,6 +76,13 @@ pub fn build_auth_routes(
             Result::<_, warp::Rejection>::Ok(warp::reply::json(&response))
         });
 
+    // 刷新令牌路由：作废旧刷新令牌并签发新的令牌对
+    let refresh_route = warp::path!("auth" / "refresh")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::any().map(move || refresh_tokens.clone()))
+        .and_then(handle_refresh_token);
+
     // 会话验证路由
     let validate_route = warp::path!("auth" / "validate")
         .and(warp::get())
@@ -146,9 +162,30 @@ pub fn build_auth_routes(
     login_route
         .or(force_login_route)
         .or(logout_route)
+        .or(refresh_route)
         .or(validate_route)
         .or(heartbeat_route)
         .or(sessions_route)
         .or(realtime_check_route)
         .or(user_online_info_route)
-} 
\ No newline at end of file
+}
+
+/// 用刷新令牌换取新的令牌对；令牌已吊销、已被使用或账号被禁用时返回 401
+async fn handle_refresh_token(
+    request: RefreshTokenRequest,
+    refresh_tokens: Arc<RefreshTokenManager>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(match refresh_tokens.refresh(request.refresh_token.trim()).await {
+        Ok(pair) => warp::reply::with_status(
+            warp::reply::json(&ApiResponse::success("令牌已刷新".to_string(), pair)),
+            warp::http::StatusCode::OK,
+        ),
+        Err(e) => {
+            tracing::warn!("🔑 刷新令牌失败: {}", e);
+            warp::reply::with_status(
+                warp::reply::json(&ApiResponse::<()>::error(e.to_string())),
+                warp::http::StatusCode::UNAUTHORIZED,
+            )
+        }
+    })
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+use serde::Deserialize;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use crate::auth::customer_manager::{BanKind, CustomerManager};
+use crate::close_code::CloseReason;
+use crate::handlers::system_extended::verify_admin_token;
+use crate::types::api::ApiResponse;
+use crate::websocket::WebSocketManager;
+
+/// 封禁请求
+#[derive(Debug, Deserialize)]
+pub struct BanRequest {
+    pub kind: BanKind,
+    pub value: String,
+    pub reason: Option<String>,
+    /// 封禁时长（秒），为空时永久封禁
+    pub duration_secs: Option<u64>,
+}
+
+/// 审计日志中的操作人：封禁接口只接受管理令牌，操作人即管理员，不由请求指定
+const BAN_OPERATOR: &str = "admin";
+
+/// 客户黑名单管理路由，需携带 X-Admin-Token
+pub struct CustomerApiRoutes {
+    customer_manager: Arc<CustomerManager>,
+    ws_manager: Arc<WebSocketManager>,
+}
+
+impl CustomerApiRoutes {
+    pub fn new(customer_manager: Arc<CustomerManager>, ws_manager: Arc<WebSocketManager>) -> Self {
+        Self {
+            customer_manager,
+            ws_manager,
+        }
+    }
+
+    pub fn routes(&self) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + use<> {
+        let customer_manager = self.customer_manager.clone();
+        let ws_manager = self.ws_manager.clone();
+
+        let list_bans = warp::path!("api" / "customers" / "bans")
+            .and(warp::get())
+            .and(warp::header::optional::<String>("x-admin-token"))
+            .and(with_customer_manager(customer_manager.clone()))
+            .and_then(handle_list_bans);
+
+        let add_ban = warp::path!("api" / "customers" / "bans")
+            .and(warp::post())
+            .and(warp::header::optional::<String>("x-admin-token"))
+            .and(warp::body::json())
+            .and(with_customer_manager(customer_manager.clone()))
+            .and(warp::any().map(move || ws_manager.clone()))
+            .and_then(handle_add_ban);
+
+        let remove_ban = warp::path!("api" / "customers" / "bans" / String / String)
+            .and(warp::delete())
+            .and(warp::header::optional::<String>("x-admin-token"))
+            .and(with_customer_manager(customer_manager.clone()))
+            .and_then(handle_remove_ban);
+
+        let audit_log = warp::path!("api" / "customers" / "bans" / "audit")
+            .and(warp::get())
+            .and(warp::header::optional::<String>("x-admin-token"))
+            .and(warp::query::<std::collections::HashMap<String, String>>())
+            .and(with_customer_manager(customer_manager))
+            .and_then(handle_ban_audit_log);
+
+        list_bans.or(add_ban).or(audit_log).or(remove_ban)
+    }
+}
+
+fn with_customer_manager(
+    customer_manager: Arc<CustomerManager>,
+) -> impl Filter<Extract = (Arc<CustomerManager>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || customer_manager.clone())
+}
+
+fn reply<T: serde::Serialize>(response: ApiResponse<T>, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
+fn forbidden() -> warp::reply::WithStatus<warp::reply::Json> {
+    reply::<()>(
+        ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        },
+        StatusCode::FORBIDDEN,
+    )
+}
+
+fn failure(message: String, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    reply::<()>(
+        ApiResponse {
+            success: false,
+            message,
+            data: None,
+        },
+        status,
+    )
+}
+
+// 当前生效的封禁列表
+async fn handle_list_bans(
+    admin_token: Option<String>,
+    customer_manager: Arc<CustomerManager>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    Ok(match customer_manager.list_bans().await {
+        Ok(bans) => reply(
+            ApiResponse {
+                success: true,
+                message: format!("共 {} 条封禁", bans.len()),
+                data: Some(bans),
+            },
+            StatusCode::OK,
+        ),
+        Err(e) => failure(format!("获取封禁列表失败: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    })
+}
+
+// 封禁客户ID或IP，并断开已有连接
+async fn handle_add_ban(
+    admin_token: Option<String>,
+    request: BanRequest,
+    customer_manager: Arc<CustomerManager>,
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    let ban = match customer_manager
+        .ban(request.kind, &request.value, request.reason, BAN_OPERATOR, request.duration_secs)
+        .await
+    {
+        Ok(ban) => ban,
+        Err(e) => return Ok(failure(format!("封禁失败: {}", e), StatusCode::BAD_REQUEST)),
+    };
+
+    let disconnected = match ban.kind {
+        BanKind::Customer => {
+            if ws_manager.disconnect_user(&ban.value, CloseReason::Banned).await {
+                vec![ban.value.clone()]
+            } else {
+                Vec::new()
+            }
+        }
+        BanKind::Ip => ws_manager.disconnect_ip(&ban.value, CloseReason::Banned).await,
+    };
+
+    Ok(reply(
+        ApiResponse {
+            success: true,
+            message: format!("封禁成功，断开 {} 个连接", disconnected.len()),
+            data: Some(serde_json::json!({
+                "ban": ban,
+                "disconnected": disconnected,
+            })),
+        },
+        StatusCode::OK,
+    ))
+}
+
+// 解除封禁
+async fn handle_remove_ban(
+    kind: String,
+    value: String,
+    admin_token: Option<String>,
+    customer_manager: Arc<CustomerManager>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    let kind: BanKind = match kind.parse() {
+        Ok(kind) => kind,
+        Err(e) => return Ok(failure(e.to_string(), StatusCode::BAD_REQUEST)),
+    };
+    Ok(match customer_manager.unban(kind, &value, BAN_OPERATOR).await {
+        Ok(true) => reply::<()>(
+            ApiResponse {
+                success: true,
+                message: format!("已解除封禁: {}", value),
+                data: None,
+            },
+            StatusCode::OK,
+        ),
+        Ok(false) => failure(format!("未找到封禁记录: {}", value), StatusCode::NOT_FOUND),
+        Err(e) => failure(format!("解除封禁失败: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    })
+}
+
+// 封禁审计日志，最新的在前
+async fn handle_ban_audit_log(
+    admin_token: Option<String>,
+    query: std::collections::HashMap<String, String>,
+    customer_manager: Arc<CustomerManager>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse::<usize>().ok())
+        .unwrap_or(100)
+        .clamp(1, 1000);
+    Ok(match customer_manager.audit_log(limit).await {
+        Ok(entries) => reply(
+            ApiResponse {
+                success: true,
+                message: format!("共 {} 条审计记录", entries.len()),
+                data: Some(entries),
+            },
+            StatusCode::OK,
+        ),
+        Err(e) => failure(format!("获取审计日志失败: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    })
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use crate::feature_flags::{FeatureFlag, FeatureFlags, FlagContext};
+use crate::handlers::system_extended::verify_admin_token;
+use crate::types::api::ApiResponse;
+
+/// 功能开关管理路由，需携带 X-Admin-Token；修改写入Redis，其他实例定期同步
+pub fn build_feature_flag_routes(
+    feature_flags: Arc<FeatureFlags>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let list_flags = warp::path!("api" / "feature-flags")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_feature_flags(feature_flags.clone()))
+        .and_then(handle_list_flags);
+
+    let check_flag = warp::path!("api" / "feature-flags" / String / "check")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::query::<FlagContext>())
+        .and(with_feature_flags(feature_flags.clone()))
+        .and_then(handle_check_flag);
+
+    let set_flag = warp::path!("api" / "feature-flags" / String)
+        .and(warp::put())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::body::json())
+        .and(with_feature_flags(feature_flags.clone()))
+        .and_then(handle_set_flag);
+
+    let remove_flag = warp::path!("api" / "feature-flags" / String)
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_feature_flags(feature_flags))
+        .and_then(handle_remove_flag);
+
+    list_flags.or(check_flag).or(set_flag).or(remove_flag)
+}
+
+fn with_feature_flags(
+    feature_flags: Arc<FeatureFlags>,
+) -> impl Filter<Extract = (Arc<FeatureFlags>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || feature_flags.clone())
+}
+
+fn reply<T: serde::Serialize>(response: ApiResponse<T>, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
+fn forbidden() -> warp::reply::WithStatus<warp::reply::Json> {
+    failure("无权访问管理端点".to_string(), StatusCode::FORBIDDEN)
+}
+
+fn failure(message: String, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    reply::<()>(
+        ApiResponse {
+            success: false,
+            message,
+            data: None,
+        },
+        status,
+    )
+}
+
+// 全部功能开关
+async fn handle_list_flags(
+    admin_token: Option<String>,
+    feature_flags: Arc<FeatureFlags>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    let flags = feature_flags.list();
+    Ok(reply(
+        ApiResponse {
+            success: true,
+            message: format!("共 {} 个功能开关", flags.len()),
+            data: Some(flags),
+        },
+        StatusCode::OK,
+    ))
+}
+
+// 查看开关对某个用户/租户是否开启，便于排查灰度
+async fn handle_check_flag(
+    name: String,
+    admin_token: Option<String>,
+    ctx: FlagContext,
+    feature_flags: Arc<FeatureFlags>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    let enabled = feature_flags.is_enabled(&name, &ctx);
+    Ok(reply(
+        ApiResponse {
+            success: true,
+            message: format!("功能开关 {} {}", name, if enabled { "已开启" } else { "未开启" }),
+            data: Some(serde_json::json!({
+                "name": name,
+                "enabled": enabled,
+                "defined": feature_flags.get(&name).is_some(),
+                "user_id": ctx.user_id,
+                "tenant_id": ctx.tenant_id,
+            })),
+        },
+        StatusCode::OK,
+    ))
+}
+
+// 新增或修改功能开关，立即生效
+async fn handle_set_flag(
+    name: String,
+    admin_token: Option<String>,
+    mut flag: FeatureFlag,
+    feature_flags: Arc<FeatureFlags>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    flag.name = name;
+    Ok(match feature_flags.set_flag(flag).await {
+        Ok(flag) => reply(
+            ApiResponse {
+                success: true,
+                message: format!("功能开关已更新: {}", flag.name),
+                data: Some(flag),
+            },
+            StatusCode::OK,
+        ),
+        Err(e) => failure(format!("更新功能开关失败: {}", e), StatusCode::BAD_REQUEST),
+    })
+}
+
+// 删除功能开关，删除后视为关闭
+async fn handle_remove_flag(
+    name: String,
+    admin_token: Option<String>,
+    feature_flags: Arc<FeatureFlags>,
+) -> Result<impl Reply, Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        return Ok(forbidden());
+    }
+
+    Ok(match feature_flags.remove_flag(&name).await {
+        Ok(true) => reply::<()>(
+            ApiResponse {
+                success: true,
+                message: format!("功能开关已删除: {}", name),
+                data: None,
+            },
+            StatusCode::OK,
+        ),
+        Ok(false) => failure(format!("未找到功能开关: {}", name), StatusCode::NOT_FOUND),
+        Err(e) => failure(format!("删除功能开关失败: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    })
+}
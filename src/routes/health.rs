@@ -0,0 +1,271 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use warp::http::StatusCode;
+use warp::Filter;
+use crate::handlers::system_extended::verify_admin_token;
+use crate::redis_pool::{PoolMetrics, POOL_UTILIZATION_WARN_THRESHOLD};
+use crate::types::api::ApiResponse;
+use crate::websocket::WebSocketManager;
+
+/// 构建健康检查子路由
+pub fn build_health_routes(
+    ws_manager: Arc<WebSocketManager>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // Redis连接池健康状态
+    let redis = warp::path!("health" / "redis")
+        .and(warp::get())
+        .and(with_ws_manager(ws_manager.clone()))
+        .and_then(handle_redis_health);
+
+    // 依赖服务汇总健康状态：会向已启用的外部AI服务发请求，仅管理端可查
+    let probe_client = reqwest::Client::builder()
+        .timeout(SERVICE_PROBE_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let services = warp::path!("health" / "services")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(with_ws_manager(ws_manager))
+        .and(warp::any().map(move || probe_client.clone()))
+        .and_then(handle_services_health);
+
+    redis.or(services)
+}
+
+fn with_ws_manager(
+    ws_manager: Arc<WebSocketManager>,
+) -> impl Filter<Extract = (Arc<WebSocketManager>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || ws_manager.clone())
+}
+
+/// 单个依赖服务的探测超时
+const SERVICE_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 单个依赖服务的探测结果
+#[derive(Debug, Serialize)]
+struct ServiceHealth {
+    name: String,
+    /// up / down
+    status: &'static str,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 在超时内执行一次探测并计时
+async fn probe_service(
+    name: impl Into<String>,
+    timeout: Duration,
+    probe: impl Future<Output = anyhow::Result<()>>,
+) -> ServiceHealth {
+    let started = Instant::now();
+    let error = match tokio::time::timeout(timeout, probe).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("探测超时（{}ms）", timeout.as_millis())),
+    };
+    ServiceHealth {
+        name: name.into(),
+        status: if error.is_none() { "up" } else { "down" },
+        latency_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+/// 外部服务能返回响应即视为可用，只有 5xx 视为故障（很多接口不接受无参数的 GET）。
+/// 原始错误只写日志，返回给调用方的错误不含地址和底层细节
+async fn probe_http(client: &reqwest::Client, name: &str, url: &str) -> anyhow::Result<()> {
+    let status = match client.get(url).send().await {
+        Ok(response) => response.status(),
+        Err(e) => {
+            let summary = probe_error_summary(&e);
+            tracing::warn!("🩺 依赖服务 {} 探测失败: {}", name, e.without_url());
+            anyhow::bail!(summary);
+        }
+    };
+    if status.is_server_error() {
+        anyhow::bail!("HTTP {}", status.as_u16());
+    }
+    Ok(())
+}
+
+fn probe_error_summary(error: &reqwest::Error) -> &'static str {
+    if error.is_timeout() {
+        "请求超时"
+    } else if error.is_connect() {
+        "连接失败"
+    } else {
+        "请求失败"
+    }
+}
+
+/// 全部依赖可用时为 healthy，否则为 degraded
+fn overall_status(services: &[ServiceHealth]) -> &'static str {
+    if services.iter().all(|service| service.status == "up") {
+        "healthy"
+    } else {
+        "degraded"
+    }
+}
+
+// 并发探测 Redis 和已启用的外部AI服务，汇总各自的状态与耗时
+async fn handle_services_health(
+    admin_token: Option<String>,
+    ws_manager: Arc<WebSocketManager>,
+    client: reqwest::Client,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    if !verify_admin_token(admin_token.as_deref()) {
+        let response: ApiResponse<()> = ApiResponse {
+            success: false,
+            message: "无权访问管理端点".to_string(),
+            data: None,
+        };
+        return Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::FORBIDDEN));
+    }
+
+    let redis = ws_manager.redis.clone();
+    let mut probes = vec![Box::pin(probe_service("redis", SERVICE_PROBE_TIMEOUT, async move {
+        redis.read().await.ping().await.map(|_| ()).map_err(|e| {
+            tracing::warn!("🩺 依赖服务 redis 探测失败: {}", e);
+            anyhow::anyhow!("Redis不可用")
+        })
+    })) as std::pin::Pin<Box<dyn Future<Output = ServiceHealth> + Send>>];
+
+    if let Some(ai_manager) = &ws_manager.ai_manager {
+        let config = ai_manager.get_config().await;
+        let endpoints = [
+            ("ai-intent-recognition", config.intent_recognition.enabled, config.intent_recognition.api_endpoint),
+            ("ai-translation", config.translation.enabled, config.translation.api_endpoint),
+            ("ai-speech-recognition", config.speech_recognition.enabled, config.speech_recognition.api_endpoint),
+        ];
+        for (name, enabled, endpoint) in endpoints {
+            if !enabled || endpoint.is_empty() {
+                continue;
+            }
+            let client = client.clone();
+            probes.push(Box::pin(probe_service(name, SERVICE_PROBE_TIMEOUT, async move {
+                probe_http(&client, name, &endpoint).await
+            })));
+        }
+    }
+
+    let services = futures_util::future::join_all(probes).await;
+    let status = overall_status(&services);
+    if status != "healthy" {
+        let down: Vec<&str> = services
+            .iter()
+            .filter(|service| service.status != "up")
+            .map(|service| service.name.as_str())
+            .collect();
+        tracing::warn!("⚠️ 依赖服务不可用: {:?}", down);
+    }
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({
+            "status": status,
+            "checked_at": chrono::Utc::now(),
+            "services": services,
+        })),
+        StatusCode::OK,
+    ))
+}
+
+async fn handle_redis_health(
+    ws_manager: Arc<WebSocketManager>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let metrics = ws_manager.redis.read().await.get_pool_metrics();
+    let report = redis_pool_health(metrics.as_ref(), POOL_UTILIZATION_WARN_THRESHOLD);
+    if report["status"] == "degraded" {
+        tracing::warn!("⚠️ Redis连接池利用率过高: {}%", report["pool_utilization"]);
+    }
+    Ok(warp::reply::json(&report))
+}
+
+/// 根据连接池指标生成健康报告，利用率超过阈值时状态为 degraded
+fn redis_pool_health(metrics: Option<&PoolMetrics>, threshold: f64) -> serde_json::Value {
+    match metrics {
+        Some(metrics) => serde_json::json!({
+            "status": if metrics.pool_utilization > threshold { "degraded" } else { "ok" },
+            "pool_enabled": true,
+            "pool_utilization": metrics.pool_utilization,
+            "utilization_threshold": threshold,
+            "active_connections": metrics.active_connections,
+            "idle_connections": metrics.idle_connections,
+            "total_connections": metrics.total_connections,
+            "avg_acquire_time_ms": metrics.avg_acquire_time_ms,
+            "acquire_timeouts": metrics.acquire_timeouts,
+        }),
+        // 直连模式没有连接池指标
+        None => serde_json::json!({
+            "status": "ok",
+            "pool_enabled": false,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(active: usize, idle: usize, utilization: f64) -> PoolMetrics {
+        PoolMetrics {
+            total_connections: active + idle,
+            idle_connections: idle,
+            active_connections: active,
+            total_acquired: 0,
+            total_released: 0,
+            acquire_timeouts: 0,
+            connection_errors: 0,
+            avg_acquire_time_ms: 1.5,
+            max_acquire_time_ms: 3,
+            pool_utilization: utilization,
+        }
+    }
+
+    #[test]
+    fn test_redis_pool_health_status() {
+        let report = redis_pool_health(Some(&metrics(4, 4, 12.5)), 80.0);
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["active_connections"], 4);
+        assert_eq!(report["total_connections"], 8);
+        assert_eq!(report["avg_acquire_time_ms"], 1.5);
+
+        let report = redis_pool_health(Some(&metrics(30, 2, 93.75)), 80.0);
+        assert_eq!(report["status"], "degraded");
+
+        let report = redis_pool_health(None, 80.0);
+        assert_eq!(report["pool_enabled"], false);
+    }
+
+    #[tokio::test]
+    async fn test_service_probes_time_out_and_degrade() {
+        let timeout = Duration::from_millis(20);
+        let up = probe_service("redis", timeout, async { Ok(()) }).await;
+        assert_eq!(up.status, "up");
+        assert!(up.error.is_none());
+
+        let failed = probe_service("ai-translation", timeout, async { Err(anyhow::anyhow!("HTTP 503")) }).await;
+        assert_eq!(failed.error.as_deref(), Some("HTTP 503"));
+
+        let hung = probe_service("ai-intent-recognition", timeout, std::future::pending()).await;
+        assert_eq!(hung.status, "down");
+        assert!(hung.latency_ms >= 20);
+
+        assert_eq!(overall_status(&[up]), "healthy");
+        assert_eq!(overall_status(&[failed, hung]), "degraded");
+    }
+
+    #[tokio::test]
+    async fn test_probe_errors_do_not_expose_endpoint() {
+        // 取一个空闲端口后关闭监听，连接必然被拒绝
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let url = format!("http://127.0.0.1:{}/v1/chat?api_key=secret", port);
+
+        let client = reqwest::Client::new();
+        let probe = probe_http(&client, "ai-translation", &url);
+        let health = probe_service("ai-translation", SERVICE_PROBE_TIMEOUT, probe).await;
+        assert_eq!(health.status, "down");
+        assert_eq!(health.error.as_deref(), Some("连接失败"));
+    }
+}
@@ -2,6 +2,9 @@ use std::sync::Arc;
 use warp::Filter;
 use serde::{Deserialize, Serialize};
 use crate::auth::kefu_auth::KefuAuthManager;
+use crate::auth::jwt_auth::RefreshTokenManager;
+use crate::auth::operator::Operator;
+use crate::message::UserType;
 
 /// 客服登录请求
 #[derive(Debug, Deserialize)]
@@ -19,6 +22,12 @@ pub struct KefuLoginResponse {
     pub real_name: Option<String>,
     pub max_customers: Option<u32>,
     pub session_token: Option<String>,
+    /// WebSocket连接使用的JWT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    /// 用于 /auth/refresh 换取新的访问令牌
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
 }
 
 /// 客服状态响应
@@ -35,6 +44,7 @@ pub struct KefuStatusResponse {
 /// 构建客服认证路由
 pub fn build_kefu_auth_routes(
     kefu_auth_manager: Arc<KefuAuthManager>,
+    refresh_tokens: Arc<RefreshTokenManager>,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let login_route = warp::path("api")
         .and(warp::path("kefu"))
@@ -42,6 +52,7 @@ pub fn build_kefu_auth_routes(
         .and(warp::post())
         .and(warp::body::json())
         .and(with_kefu_auth_manager(kefu_auth_manager.clone()))
+        .and(with_refresh_tokens(refresh_tokens.clone()))
         .and_then(handle_kefu_login);
 
     let logout_route = warp::path("api")
@@ -49,7 +60,10 @@ pub fn build_kefu_auth_routes(
         .and(warp::path("logout"))
         .and(warp::post())
         .and(warp::query::<std::collections::HashMap<String, String>>())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-admin-token"))
         .and(with_kefu_auth_manager(kefu_auth_manager.clone()))
+        .and(with_refresh_tokens(refresh_tokens))
         .and_then(handle_kefu_logout);
 
     let status_route = warp::path("api")
@@ -80,10 +94,17 @@ fn with_kefu_auth_manager(
     warp::any().map(move || kefu_auth_manager.clone())
 }
 
+fn with_refresh_tokens(
+    refresh_tokens: Arc<RefreshTokenManager>,
+) -> impl Filter<Extract = (Arc<RefreshTokenManager>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || refresh_tokens.clone())
+}
+
 /// 处理客服登录
 async fn handle_kefu_login(
     request: KefuLoginRequest,
     kefu_auth_manager: Arc<KefuAuthManager>,
+    refresh_tokens: Arc<RefreshTokenManager>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     tracing::info!("🔐 客服登录请求: {}", request.username);
 
@@ -99,6 +120,8 @@ async fn handle_kefu_login(
                         real_name: None,
                         max_customers: None,
                         session_token: None,
+                        access_token: None,
+                        refresh_token: None,
                     };
                     Ok(warp::reply::json(&response))
                 }
@@ -109,6 +132,11 @@ async fn handle_kefu_login(
                     // 客服上线
                     match kefu_auth_manager.kefu_login(&kefu_auth, &session_token).await {
                         Ok(true) => {
+                            let tokens = refresh_tokens
+                                .issue_pair(&kefu_auth.kefu_id, Some(&kefu_auth.real_name), UserType::Kefu)
+                                .await
+                                .map_err(|e| tracing::error!("签发客服令牌失败: {}", e))
+                                .ok();
                             let response = KefuLoginResponse {
                                 success: true,
                                 message: "登录成功".to_string(),
@@ -116,6 +144,8 @@ async fn handle_kefu_login(
                                 real_name: Some(kefu_auth.real_name.clone()),
                                 max_customers: Some(kefu_auth.max_customers),
                                 session_token: Some(session_token),
+                                access_token: tokens.as_ref().map(|pair| pair.access_token.clone()),
+                                refresh_token: tokens.map(|pair| pair.refresh_token),
                             };
                             Ok(warp::reply::json(&response))
                         }
@@ -127,6 +157,8 @@ async fn handle_kefu_login(
                                 real_name: None,
                                 max_customers: None,
                                 session_token: None,
+                                access_token: None,
+                                refresh_token: None,
                             };
                             Ok(warp::reply::json(&response))
                         }
@@ -139,6 +171,8 @@ async fn handle_kefu_login(
                                 real_name: None,
                                 max_customers: None,
                                 session_token: None,
+                                access_token: None,
+                                refresh_token: None,
                             };
                             Ok(warp::reply::json(&response))
                         }
@@ -153,6 +187,8 @@ async fn handle_kefu_login(
                         real_name: None,
                         max_customers: None,
                         session_token: None,
+                        access_token: None,
+                        refresh_token: None,
                     };
                     Ok(warp::reply::json(&response))
                 }
@@ -166,6 +202,8 @@ async fn handle_kefu_login(
                 real_name: None,
                 max_customers: None,
                 session_token: None,
+                access_token: None,
+                refresh_token: None,
             };
             Ok(warp::reply::json(&response))
         }
@@ -178,16 +216,21 @@ async fn handle_kefu_login(
                 real_name: None,
                 max_customers: None,
                 session_token: None,
+                access_token: None,
+                refresh_token: None,
             };
             Ok(warp::reply::json(&response))
         }
     }
 }
 
-/// 处理客服下线
+/// 处理客服下线：客服只能下线本人，管理员可下线任一客服；下线同时吊销该客服的刷新令牌
 async fn handle_kefu_logout(
     query: std::collections::HashMap<String, String>,
+    authorization: Option<String>,
+    admin_token: Option<String>,
     kefu_auth_manager: Arc<KefuAuthManager>,
+    refresh_tokens: Arc<RefreshTokenManager>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let kefu_id = query.get("kefu_id").unwrap_or(&"".to_string()).clone();
     
@@ -198,6 +241,26 @@ async fn handle_kefu_logout(
         })));
     }
 
+    let authorized = match Operator::resolve(authorization.as_deref(), admin_token.as_deref()) {
+        Some(Operator::Admin) => true,
+        Some(Operator::Kefu(operator_id)) => operator_id == kefu_id,
+        None => false,
+    };
+    if !authorized {
+        return Ok(warp::reply::json(&serde_json::json!({
+            "success": false,
+            "message": "只能下线本人账号"
+        })));
+    }
+
+    if let Err(e) = refresh_tokens.revoke_user(&kefu_id).await {
+        tracing::error!("吊销客服刷新令牌失败: {}", e);
+        return Ok(warp::reply::json(&serde_json::json!({
+            "success": false,
+            "message": "下线失败"
+        })));
+    }
+
     match kefu_auth_manager.kefu_logout(&kefu_id).await {
         Ok(()) => {
             Ok(warp::reply::json(&serde_json::json!({
@@ -16,6 +16,18 @@ pub mod api_real;
 // 客服认证路由模块
 pub mod kefu_auth;
 
+// 客户黑名单路由模块
+pub mod customer;
+
+// 健康检查路由模块
+pub mod health;
+
+// 单点登录路由模块
+pub mod sso;
+
+// 功能开关管理路由模块
+pub mod feature_flags;
+
 use std::sync::Arc;
 use warp::Filter;
 use crate::websocket::WebSocketManager;
@@ -27,6 +39,13 @@ use crate::storage::LocalStorage;
 use crate::ai::AIManager;
 use crate::handlers::ai::AIHandler;
 use crate::auth::kefu_auth::KefuAuthManager;
+use crate::auth::customer_manager::CustomerManager;
+use crate::auth::sso::SsoManager;
+use crate::auth::jwt_auth::RefreshTokenManager;
+use crate::config::AppConfig;
+use crate::errors::handle_rejection;
+use crate::feature_flags::FeatureFlags;
+use crate::monitoring::{MetricsRegistry, PrometheusExporter};
 // Temporarily disabled enterprise modules for compilation
 // use crate::load_balancer::LoadBalancer;
 // use crate::websocket_pool::WebSocketConnectionPool;
@@ -47,6 +66,11 @@ pub fn build_all_routes(
     storage: Arc<LocalStorage>,
     ai_manager: Arc<AIManager>,
     kefu_auth_manager: Arc<KefuAuthManager>,
+    customer_manager: Arc<CustomerManager>,
+    sso_manager: Arc<SsoManager>,
+    refresh_tokens: Arc<RefreshTokenManager>,
+    metrics_registry: Arc<MetricsRegistry>,
+    feature_flags: Arc<FeatureFlags>,
     _load_balancer: Option<()>, // placeholder
     _websocket_pool: Option<()>, // placeholder
     _api_routes: Option<()>, // placeholder
@@ -58,7 +82,7 @@ pub fn build_all_routes(
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     
     // 构建各个路由模块（使用简化版本）
-    let auth_routes = auth_simple::build_auth_routes(user_manager.clone());
+    let auth_routes = auth_simple::build_auth_routes(user_manager.clone(), refresh_tokens.clone());
     let simple_api_routes = api_simple::build_api_routes(ws_manager.clone(), file_manager.clone(), html_manager.clone(), voice_manager.clone(), storage.clone());
     
     // 扩展的API路由
@@ -74,7 +98,11 @@ pub fn build_all_routes(
         file_manager.clone(),
     );
     
-    let websocket_routes = websocket::build_websocket_routes(ws_manager.clone(), kefu_auth_manager.clone());
+    let websocket_routes = websocket::build_websocket_routes(
+        ws_manager.clone(),
+        kefu_auth_manager.clone(),
+        customer_manager.clone(),
+    );
     let frontend_routes = frontend::build_frontend_routes();
     
     // Swagger路由应该在最前面，避免被其他路由拦截
@@ -85,7 +113,16 @@ pub fn build_all_routes(
     let ai_routes = ai_handler.routes();
     
     // 客服认证路由
-    let kefu_auth_routes = kefu_auth::build_kefu_auth_routes(kefu_auth_manager.clone());
+    let kefu_auth_routes = kefu_auth::build_kefu_auth_routes(kefu_auth_manager.clone(), refresh_tokens);
+
+    // 客户黑名单路由
+    let customer_routes = customer::CustomerApiRoutes::new(customer_manager.clone(), ws_manager.clone()).routes();
+
+    // 单点登录路由
+    let sso_routes = sso::SsoRoutes::new(sso_manager).routes();
+
+    // 功能开关管理路由
+    let feature_flag_routes = feature_flags::build_feature_flag_routes(feature_flags);
     
     // 企业级路由 - 暂时禁用
     // let enterprise_routes = None;
@@ -94,11 +131,17 @@ pub fn build_all_routes(
     // let failover_routes = None;
     
     // 简单的健康检查路由
-    let health_route = warp::path("health").and(warp::get()).map(|| {
+    let health_route = warp::path("health").and(warp::path::end()).and(warp::get()).map(|| {
         tracing::info!("✅ 健康检查路由被访问");
         warp::reply::json(&serde_json::json!({"status": "ok"}))
     });
 
+    // 组件健康检查路由（/health/redis、/health/services）
+    let health_detail_routes = health::build_health_routes(ws_manager.clone());
+
+    // Prometheus指标路由（/metrics）
+    let metrics_routes = PrometheusExporter::new(metrics_registry).routes();
+
     // favicon.ico 路由 - 避免404错误
     let favicon_route = warp::path("favicon.ico").and(warp::get()).map(|| {
         tracing::info!("🎯 Favicon请求");
@@ -112,8 +155,14 @@ pub fn build_all_routes(
         )
     });
 
+    // 跨域按当前运行环境的配置限制来源；错误响应在CORS层内生成，同样带跨域响应头
+    let config = AppConfig::get();
+    let cors = crate::middleware::cors::build_cors(&config.server.cors, &config.app.environment);
+
     // 组合所有路由 - 注意顺序很重要！
     health_route
+        .or(health_detail_routes)
+        .or(metrics_routes)
         .or(favicon_route)
         // 2. Swagger路由应该在API路由之前
         .or(swagger_routes)
@@ -121,6 +170,9 @@ pub fn build_all_routes(
         .or(auth_routes)
         // 4. 客服认证路由
         .or(kefu_auth_routes)
+        .or(sso_routes)
+        .or(customer_routes)
+        .or(feature_flag_routes)
         // 5. AI路由
         .or(ai_routes)
         // 6. API路由
@@ -131,4 +183,6 @@ pub fn build_all_routes(
         .or(websocket_routes)
         // 8. 前端路由（静态文件）放在最后
         .or(frontend_routes)
+        .recover(handle_rejection)
+        .with(cors)
 }
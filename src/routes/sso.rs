@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use crate::auth::sso::{SsoManager, SSO_STATE_COOKIE};
+use crate::types::api::ApiResponse;
+
+/// 单点登录路由：列出身份提供方、跳转IdP授权、处理IdP回调
+pub struct SsoRoutes {
+    sso_manager: Arc<SsoManager>,
+}
+
+impl SsoRoutes {
+    pub fn new(sso_manager: Arc<SsoManager>) -> Self {
+        Self { sso_manager }
+    }
+
+    pub fn routes(&self) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + use<> {
+        let sso_manager = self.sso_manager.clone();
+
+        let providers = warp::path!("api" / "auth" / "sso" / "providers")
+            .and(warp::get())
+            .and(with_sso_manager(sso_manager.clone()))
+            .and_then(handle_sso_providers);
+
+        let login = warp::path!("api" / "auth" / "sso" / String / "login")
+            .and(warp::get())
+            .and(with_sso_manager(sso_manager.clone()))
+            .and_then(handle_sso_login);
+
+        let callback = warp::path!("api" / "auth" / "sso" / String / "callback")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::cookie::optional::<String>(SSO_STATE_COOKIE))
+            .and(with_sso_manager(sso_manager))
+            .and_then(handle_sso_callback);
+
+        providers.or(login).or(callback)
+    }
+}
+
+fn with_sso_manager(
+    sso_manager: Arc<SsoManager>,
+) -> impl Filter<Extract = (Arc<SsoManager>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || sso_manager.clone())
+}
+
+fn reply<T: serde::Serialize>(response: ApiResponse<T>, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
+fn failure(message: String, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    reply::<()>(ApiResponse::error(message), status)
+}
+
+// 登录页可选的身份提供方
+async fn handle_sso_providers(sso_manager: Arc<SsoManager>) -> Result<impl Reply, Rejection> {
+    let providers = sso_manager.providers();
+    Ok(reply(
+        ApiResponse::success(format!("共 {} 个身份提供方", providers.len()), providers),
+        StatusCode::OK,
+    ))
+}
+
+/// state Cookie 只在单点登录路径下发送；IdP回调是顶层跳转，SameSite=Lax 时浏览器会带上
+fn state_cookie(value: &str, max_age: i64) -> String {
+    format!(
+        "{}={}; Path=/api/auth/sso; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        SSO_STATE_COOKIE, value, max_age
+    )
+}
+
+// 重定向到IdP授权页，同时把 state 写入浏览器 Cookie
+async fn handle_sso_login(provider_id: String, sso_manager: Arc<SsoManager>) -> Result<warp::reply::Response, Rejection> {
+    let authorization = match sso_manager.authorize_url(&provider_id) {
+        Ok(authorization) => authorization,
+        Err(e) => return Ok(failure(e.to_string(), StatusCode::NOT_FOUND).into_response()),
+    };
+    let location = match authorization.url.parse::<warp::http::Uri>() {
+        Ok(location) => location,
+        Err(e) => return Ok(failure(e.to_string(), StatusCode::NOT_FOUND).into_response()),
+    };
+    tracing::info!("🔑 单点登录跳转: provider={}", provider_id);
+    let cookie = state_cookie(&authorization.state, sso_manager.state_ttl_seconds());
+    Ok(warp::reply::with_header(warp::redirect::temporary(location), "set-cookie", cookie).into_response())
+}
+
+// IdP回调：授权码换取本系统JWT，无论成败都清除 state Cookie
+async fn handle_sso_callback(
+    provider_id: String,
+    query: HashMap<String, String>,
+    cookie_state: Option<String>,
+    sso_manager: Arc<SsoManager>,
+) -> Result<impl Reply, Rejection> {
+    let response = sso_callback_reply(&provider_id, &query, cookie_state.as_deref(), &sso_manager).await;
+    Ok(warp::reply::with_header(response, "set-cookie", state_cookie("", 0)))
+}
+
+async fn sso_callback_reply(
+    provider_id: &str,
+    query: &HashMap<String, String>,
+    cookie_state: Option<&str>,
+    sso_manager: &SsoManager,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    if let Some(error) = query.get("error") {
+        let description = query.get("error_description").map(String::as_str).unwrap_or_default();
+        return failure(format!("身份提供方拒绝登录: {} {}", error, description), StatusCode::UNAUTHORIZED);
+    }
+    let (Some(code), Some(state)) = (query.get("code"), query.get("state")) else {
+        return failure("缺少 code 或 state 参数".to_string(), StatusCode::BAD_REQUEST);
+    };
+
+    match sso_manager.handle_callback(provider_id, code, state, cookie_state).await {
+        Ok(result) => reply(ApiResponse::success("登录成功".to_string(), result), StatusCode::OK),
+        Err(e) => {
+            tracing::warn!("🔑 单点登录失败: provider={}, 错误={}", provider_id, e);
+            failure(format!("单点登录失败: {}", e), StatusCode::UNAUTHORIZED)
+        }
+    }
+}
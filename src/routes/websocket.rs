@@ -1,65 +1,175 @@
 use std::sync::Arc;
+use futures_util::SinkExt;
 use warp::Filter;
-use crate::websocket::WebSocketManager;
+use crate::websocket::{ConnectionOptions, WebSocketManager};
 use crate::types::websocket::WebSocketParams;
-use crate::auth::websocket::{parse_websocket_connection, validate_kefu_websocket_auth};
+use crate::auth::websocket::{
+    authenticate_websocket_token, extract_websocket_token, generate_guest_customer_id, parse_websocket_connection,
+    redacted_params,
+};
 use crate::auth::kefu_auth::KefuAuthManager;
-use crate::errors::InvalidParams;
+use crate::auth::jwt_auth::JwtAuth;
+use crate::auth::geo_risk::GeoRiskAction;
+use crate::auth::customer_manager::CustomerManager;
+use crate::close_code::CloseReason;
+use crate::errors::{InvalidParams, Unauthorized};
+use crate::message::UserType;
+use crate::message_version::negotiate_version;
+use crate::middleware::client_ip::{client_ip, TrustedProxies};
+use warp::Reply;
 
 /// 构建WebSocket路由
 pub fn build_websocket_routes(
     ws_manager: Arc<WebSocketManager>,
     kefu_auth_manager: Arc<KefuAuthManager>,
+    customer_manager: Arc<CustomerManager>,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     
     // WebSocket路由 - 重新实现客户识别
     let ws_manager_clone = ws_manager.clone();
     let kefu_auth_manager_clone = kefu_auth_manager.clone();
+    let jwt_auth = Arc::new(JwtAuth::from_config());
+    let trusted_proxies = Arc::new(TrustedProxies::from_config());
     warp::path("ws")
         .and(warp::ws())
         .and(warp::query::<WebSocketParams>())
-        .and_then(move |ws: warp::ws::Ws, query: WebSocketParams| {
+        .and(warp::header::optional::<String>("sec-websocket-protocol"))
+        .and(client_ip(trusted_proxies))
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(move |ws: warp::ws::Ws, query: WebSocketParams, protocol: Option<String>, client_ip: Option<String>, user_agent: Option<String>| {
             let ws_manager = ws_manager_clone.clone();
             let kefu_auth_manager = kefu_auth_manager_clone.clone();
-            async move { handle_websocket(ws, query, ws_manager, kefu_auth_manager).await }
+            let customer_manager = customer_manager.clone();
+            let jwt_auth = jwt_auth.clone();
+            async move {
+                handle_websocket(ws, query, protocol, client_ip, user_agent, ws_manager, kefu_auth_manager, customer_manager, jwt_auth).await
+            }
         })
 }
 
+/// 通过子协议传递令牌时必须回显所选子协议，否则浏览器会关闭连接
+fn with_protocol(reply: impl Reply, accepted_protocol: Option<String>) -> warp::reply::Response {
+    match accepted_protocol {
+        Some(protocol) => warp::reply::with_header(reply, "sec-websocket-protocol", protocol).into_response(),
+        None => reply.into_response(),
+    }
+}
+
+/// 升级后立即以指定原因关闭：浏览器拿不到握手失败时的HTTP状态码，只能通过关闭帧区分认证失败、封禁等原因
+fn close_after_upgrade(ws: warp::ws::Ws, reason: CloseReason, accepted_protocol: Option<String>) -> warp::reply::Response {
+    let reply = ws.on_upgrade(move |mut socket| async move {
+        let _ = socket.send(reason.frame()).await;
+        let _ = socket.close().await;
+    });
+    with_protocol(reply, accepted_protocol)
+}
+
 /// 处理WebSocket连接
+#[allow(clippy::too_many_arguments)]
 async fn handle_websocket(
     ws: warp::ws::Ws,
     query: WebSocketParams,
+    protocol: Option<String>,
+    client_ip: Option<String>,
+    user_agent: Option<String>,
     ws_manager: Arc<WebSocketManager>,
     kefu_auth_manager: Arc<KefuAuthManager>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    tracing::info!("WebSocket连接请求: {:?}", query);
+    customer_manager: Arc<CustomerManager>,
+    jwt_auth: Arc<JwtAuth>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    // 查询参数中可能带有JWT，打码后再记录
+    tracing::info!("WebSocket连接请求: {:?}", redacted_params(&query));
 
-    // 验证和解析连接参数
-    let connection_info = parse_websocket_connection(&query)
-        .map_err(|_| warp::reject::custom(InvalidParams { 
-            message: "Invalid WebSocket connection parameters".to_string() 
-        }))?;
+    // 客户端通过 version 声明消息协议版本，不在服务端支持范围内时拒绝升级
+    let protocol_version = negotiate_version(query.get("version").map(String::as_str)).map_err(|e| {
+        tracing::warn!("WebSocket协议版本协商失败: {}", e);
+        warp::reject::custom(InvalidParams { message: e.to_string() })
+    })?;
 
-    // 验证客服认证
-    match validate_kefu_websocket_auth(&connection_info, &kefu_auth_manager).await {
-        Ok(true) => {
-            tracing::info!("WebSocket认证通过");
+    // 携带JWT时以令牌中的身份为准；未携带令牌只允许客户以访客身份接入
+    let mut guest_token = None;
+    let (connection_info, accepted_protocol) = match extract_websocket_token(&query, protocol.as_deref()) {
+        Some((token, accepted_protocol)) => {
+            match authenticate_websocket_token(&token, &query, &jwt_auth, &kefu_auth_manager).await {
+                Ok(connection_info) => (connection_info, accepted_protocol),
+                Err(e) => {
+                    tracing::warn!("WebSocket认证失败: {}", e);
+                    return Err(warp::reject::custom(Unauthorized { message: e.to_string() }));
+                }
+            }
         }
-        Ok(false) => {
-            tracing::warn!("WebSocket认证失败: 认证不通过");
-            return Err(warp::reject::custom(InvalidParams { 
-                message: "Authentication failed".to_string() 
-            }));
+        None => {
+            let mut connection_info = parse_websocket_connection(&query)
+                .map_err(|_| warp::reject::custom(InvalidParams { 
+                    message: "Invalid WebSocket connection parameters".to_string() 
+                }))?;
+            if connection_info.user_type == UserType::Kefu {
+                tracing::warn!("WebSocket认证失败: 客服连接缺少令牌 {}", connection_info.user_id);
+                return Err(warp::reject::custom(Unauthorized {
+                    message: "客服连接缺少令牌".to_string(),
+                }));
+            }
+            // 客户端自报的ID无法证明归属，一律分配新的访客ID，否则可借此读取他人的离线消息和历史；
+            // 同时签发访客令牌，客户端重连时携带该令牌恢复同一ID
+            let guest_id = generate_guest_customer_id();
+            tracing::info!("访客未携带令牌，分配新ID: 请求ID={}, 新ID={}", connection_info.user_id, guest_id);
+            connection_info = connection_info.with_guest_id(guest_id);
+            guest_token = match jwt_auth.issue(&connection_info.user_id, None, UserType::Kehu) {
+                Ok(token) => Some(token),
+                Err(e) => {
+                    tracing::warn!("⚠️ 签发访客令牌失败: {}", e);
+                    None
+                }
+            };
+            (connection_info, None)
         }
-        Err(e) => {
-            tracing::error!("WebSocket认证失败: {}", e);
-            return Err(warp::reject::custom(InvalidParams { 
-                message: format!("Authentication error: {}", e) 
-            }));
+    };
+    tracing::info!("WebSocket认证通过: {} ({:?})", connection_info.user_id, connection_info.user_type);
+
+    // 黑名单：被封禁的客户ID或IP升级后立即关闭
+    if connection_info.user_type == UserType::Kehu {
+        match customer_manager.find_active_ban(&connection_info.user_id, client_ip.as_deref()).await {
+            Ok(Some(ban)) => {
+                tracing::warn!("🚫 拒绝被封禁的客户连接: {} ({:?}) {:?}", connection_info.user_id, client_ip, ban.kind);
+                return Ok(close_after_upgrade(ws, CloseReason::Banned, accepted_protocol));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("⚠️ 查询封禁状态失败，放行连接: {:?}", e),
         }
     }
 
-    Ok(ws.on_upgrade(move |socket| async move {
+    // 异地登录风控：位置异常的连接标记为可疑，开启二次验证时要求重新登录
+    let geo_risk = match &client_ip {
+        Some(ip) => ws_manager.check_geo_risk(&connection_info.user_id, ip, connection_info.issued_at).await,
+        None => None,
+    };
+    let suspicious_reason = match &geo_risk {
+        Some(assessment) if assessment.action == GeoRiskAction::ReverificationRequired => {
+            tracing::warn!("🌍 异地登录需要二次验证: {}", connection_info.user_id);
+            return Ok(close_after_upgrade(ws, CloseReason::ReverificationRequired, accepted_protocol));
+        }
+        Some(assessment) if assessment.suspicious => Some(assessment.reason()),
+        _ => None,
+    };
+
+    // 客户端通过 compression=gzip 声明可以解压二进制帧，旧客户端不带该参数继续使用文本帧
+    let compression_supported = query
+        .get("compression")
+        .is_some_and(|value| value == crate::websocket::FRAME_COMPRESSION);
+    // 客户端通过 encryption=aes-256-gcm 和 client_key 请求会话加密，协商成功后只收发 Encrypted 帧
+    let client_key = query
+        .get("encryption")
+        .filter(|value| *value == crate::auth::session_crypto::SESSION_ENCRYPTION)
+        .and_then(|_| query.get("client_key"))
+        .and_then(|value| match crate::auth::session_crypto::parse_public_key(value) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                tracing::warn!("会话加密公钥无效，连接不加密: {}", e);
+                None
+            }
+        });
+
+    let reply = ws.on_upgrade(move |socket| async move {
         tracing::info!(
             "WebSocket连接建立: 用户ID={}, 用户名={}, 类型={:?}",
             connection_info.user_id, connection_info.user_name, connection_info.user_type
@@ -72,12 +182,22 @@ async fn handle_websocket(
                 connection_info.user_name,
                 connection_info.user_type,
                 connection_info.zhanghao,
-                None,
+                ConnectionOptions {
+                    compression_supported,
+                    client_key,
+                    protocol_version,
+                    suspicious_reason,
+                    client_ip,
+                    user_agent,
+                    guest_token,
+                },
             )
             .await;
 
         if let Err(e) = result {
             tracing::error!("WebSocket连接处理失败: {:?}", e);
         }
-    }))
+    });
+
+    Ok(with_protocol(reply, accepted_protocol))
 } 
\ No newline at end of file
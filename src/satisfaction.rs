@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// 计算客服满意度滚动平均时使用的最近评价条数
+pub const SATISFACTION_WINDOW: usize = 100;
+
+/// 评分范围（1-5星）
+pub const MIN_RATING_SCORE: u8 = 1;
+pub const MAX_RATING_SCORE: u8 = 5;
+
+/// 客户在会话结束时提交的一条满意度评价
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionRating {
+    pub session_id: String,
+    pub customer_id: String,
+    pub kefu_id: String,
+    pub score: u8,
+    pub comment: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// 单个客服的满意度汇总
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KefuSatisfaction {
+    pub kefu_id: String,
+    /// 最近 SATISFACTION_WINDOW 条评价的平均分
+    pub satisfaction_score: f64,
+    /// 参与平均的评价条数
+    pub rating_count: usize,
+}
+
+/// 会话ID形如 "客户ID:客服ID"，只有会话中的客户本人可以评价，返回被评价的客服ID
+pub fn rated_kefu<'a>(session_id: &'a str, customer_id: &str) -> Option<&'a str> {
+    let (customer, kefu) = session_id.split_once(':')?;
+    (customer == customer_id && !kefu.is_empty()).then_some(kefu)
+}
+
+pub fn is_valid_score(score: u8) -> bool {
+    (MIN_RATING_SCORE..=MAX_RATING_SCORE).contains(&score)
+}
+
+/// 评分的算术平均，没有评价时返回 None
+pub fn rolling_average(scores: &[u8]) -> Option<f64> {
+    if scores.is_empty() {
+        return None;
+    }
+    let total: u32 = scores.iter().map(|&score| score as u32).sum();
+    Some(total as f64 / scores.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_the_sessions_customer_can_rate() {
+        assert_eq!(rated_kefu("kehu_1:kefu_1", "kehu_1"), Some("kefu_1"));
+        assert_eq!(rated_kefu("kehu_1:kefu_1", "kehu_2"), None);
+        assert_eq!(rated_kefu("kehu_1:", "kehu_1"), None);
+        assert_eq!(rated_kefu("kehu_1", "kehu_1"), None);
+    }
+
+    #[test]
+    fn test_score_range_and_average() {
+        assert!(is_valid_score(1) && is_valid_score(5));
+        assert!(!is_valid_score(0) && !is_valid_score(6));
+
+        assert_eq!(rolling_average(&[]), None);
+        assert_eq!(rolling_average(&[5, 4, 3]), Some(4.0));
+    }
+}
@@ -1,17 +1,28 @@
 use std::sync::Arc;
 use anyhow::Result;
 use tracing::{info, error};
-use crate::config::{init_config, AppConfig};
+use crate::config::{init_config, AppConfig, StorageBackend};
 use crate::file_manager::FileManager;
 use crate::html_template_manager::HtmlTemplateManager;
 use crate::redis_client::RedisManager;
+use crate::redis_pool::{RedisPoolConfig, RedisTopology};
 use crate::storage::LocalStorage;
+use crate::storage_backend::{S3Storage, Storage};
+use crate::storage_crypto::MessageCipher;
+use crate::auto_tag::AutoTagger;
+use crate::content_filter::ContentFilter;
+use crate::feature_flags::{FeatureFlags, RedisFeatureFlagStore};
 use crate::user_manager::UserManager;
 use crate::voice_message::VoiceMessageManager;
 use crate::websocket::WebSocketManager;
 use crate::ai::AIManager;
+use crate::ai::dedup::RedisResultStore;
 use crate::auth::kefu_auth::KefuAuthManager;
-use crate::monitoring::{MetricsRegistry, PerformanceCollector, PrometheusExporter};
+use crate::auth::customer_manager::{CustomerManager, RedisBanStore};
+use crate::auth::jwt_auth::{JwtAuth, RedisRefreshTokenStore, RefreshTokenManager};
+use crate::auth::sso::{RedisSsoUserStore, SsoManager};
+use crate::monitoring::metrics::Histograms;
+use crate::monitoring::{MetricsRegistry, PerformanceCollector};
 // Temporarily disabled enterprise modules for compilation
 // use crate::load_balancer::{LoadBalancer, LoadBalancerConfig, LoadBalancingStrategy};
 // use crate::websocket_pool::{WebSocketConnectionPool, WebSocketPoolConfig};
@@ -37,6 +48,14 @@ pub struct SystemComponents {
     pub ws_manager: Arc<WebSocketManager>,
     pub ai_manager: Arc<AIManager>,
     pub kefu_auth_manager: Arc<KefuAuthManager>,
+    pub customer_manager: Arc<CustomerManager>,
+    pub sso_manager: Arc<SsoManager>,
+    /// 访问令牌与刷新令牌的签发和轮换
+    pub refresh_tokens: Arc<RefreshTokenManager>,
+    /// Prometheus指标，由 /metrics 导出
+    pub metrics_registry: Arc<MetricsRegistry>,
+    /// 功能开关，状态存Redis，各实例定期同步
+    pub feature_flags: Arc<FeatureFlags>,
     // 企业级组件 - 暂时禁用以修复编译
     // pub load_balancer: Arc<LoadBalancer>,
     // pub websocket_pool: Arc<WebSocketConnectionPool>,
@@ -55,9 +74,20 @@ pub async fn initialize_system_components() -> Result<SystemComponents> {
     let config = AppConfig::get();
     info!("配置加载成功: {} v{}", config.app.name, config.app.version);
 
+    // 客服身份只凭JWT认定，生产环境不能使用弱密钥
+    JwtAuth::check_secret(&config.security.jwt_secret, &config.app.environment)?;
+
     // 初始化Redis连接池
     let redis_url = format!("redis://{}:{}", config.redis.host, config.redis.port);
-    let redis_manager = match RedisManager::with_default_pool(&redis_url) {
+    let redis_manager = match config.redis.topology() {
+        RedisTopology::Single => RedisManager::with_default_pool(&redis_url),
+        topology => RedisManager::with_pool(RedisPoolConfig {
+            url: redis_url.clone(),
+            topology,
+            ..Default::default()
+        }),
+    };
+    let redis_manager = match redis_manager {
         Ok(manager) => {
             info!("Redis连接池初始化成功: {}", redis_url);
             if let Some(metrics) = manager.get_pool_metrics() {
@@ -78,7 +108,25 @@ pub async fn initialize_system_components() -> Result<SystemComponents> {
     let storage = match LocalStorage::new(&config.storage.data_dir) {
         Ok(storage) => {
             info!("本地存储初始化成功: {}", config.storage.data_dir);
-            storage
+            let storage = storage.with_export_limits(config.storage.export);
+            let storage = match MessageCipher::from_config(&config.storage.encryption) {
+                Ok(Some(cipher)) => {
+                    info!("🔒 消息静态加密已启用");
+                    storage.with_message_cipher(cipher)
+                }
+                Ok(None) => storage,
+                Err(e) => {
+                    error!("🔒 消息加密密钥无效: {:?}", e);
+                    return Err(e);
+                }
+            };
+            match AutoTagger::from_file("config/auto_tag_rules.json") {
+                Ok(tagger) => storage.with_auto_tagger(tagger),
+                Err(e) => {
+                    error!("🏷️ 自动打标规则加载失败，使用默认规则: {:?}", e);
+                    storage
+                }
+            }
         }
         Err(e) => {
             error!("本地存储初始化失败: {:?}", e);
@@ -86,6 +134,21 @@ pub async fn initialize_system_components() -> Result<SystemComponents> {
         }
     };
 
+    // 聊天消息的存储后端：s3 时消息在写入本地索引的同时写入S3桶
+    let message_store: Arc<dyn Storage> = match config.storage.backend {
+        StorageBackend::Local => Arc::new(storage.clone()),
+        StorageBackend::S3 => match S3Storage::new(&config.storage.s3, Arc::new(storage.clone())) {
+            Ok(store) => {
+                info!("☁️ S3存储已启用: bucket={}", config.storage.s3.bucket);
+                Arc::new(store)
+            }
+            Err(e) => {
+                error!("☁️ S3存储初始化失败: {:?}", e);
+                return Err(e);
+            }
+        },
+    };
+
     // 初始化文件管理器
     let file_manager = match FileManager::new(config.storage.clone()) {
         Ok(manager) => {
@@ -102,7 +165,7 @@ pub async fn initialize_system_components() -> Result<SystemComponents> {
     let html_manager = match HtmlTemplateManager::new(config.storage.clone()).await {
         Ok(manager) => {
             info!("HTML模板管理器初始化成功");
-            Arc::new(manager)
+            Arc::new(manager.with_render_cache(&config.performance.template_render_cache))
         }
         Err(e) => {
             error!("HTML模板管理器初始化失败: {:?}", e);
@@ -134,13 +197,74 @@ pub async fn initialize_system_components() -> Result<SystemComponents> {
         }
     };
 
-    // 创建WebSocket管理器
-    let ws_manager = Arc::new(WebSocketManager::new(redis_manager.clone(), storage.clone()));
+    // 消息处理和AI任务的耗时直方图，由 /metrics 导出
+    let latency_histograms = Arc::new(Histograms::default());
+
+    // 初始化功能开关，Redis暂不可用时以空开关启动，后台同步时再加载
+    let feature_flags = Arc::new(FeatureFlags::new(Arc::new(RedisFeatureFlagStore::new(redis_manager.clone()))));
+    match feature_flags.refresh().await {
+        Ok(count) => info!("🚩 功能开关加载成功: {} 个", count),
+        Err(e) => error!("🚩 功能开关加载失败: {:?}", e),
+    }
 
     // 初始化AI管理器
-    let ai_manager = Arc::new(AIManager::new());
+    let ai_manager = Arc::new(
+        AIManager::new()
+            .with_result_store(Arc::new(RedisResultStore::new(redis_manager.clone())))
+            .with_feature_flags(feature_flags.clone())
+            .with_latency_histograms(latency_histograms.clone()),
+    );
     info!("🤖 AI管理器初始化成功");
 
+    // 聊天内容过滤，词表加载失败时仍按长度限制过滤
+    let content_filter = ContentFilter::from_config(&config.security.content_filter).unwrap_or_else(|e| {
+        error!("🧹 敏感词表加载失败，暂不替换敏感词: {:?}", e);
+        ContentFilter::new(config.security.content_filter.clone(), Vec::new())
+    });
+
+    // 创建WebSocket管理器
+    let ws_manager = Arc::new(
+        WebSocketManager::new(redis_manager.clone(), storage.clone())
+            .with_message_store(message_store)
+            .with_reorder_window(std::time::Duration::from_millis(config.websocket.reorder_window))
+            .with_ping_interval(std::time::Duration::from_millis(config.websocket.heartbeat_interval))
+            .with_compression(
+                config.performance.compression.enabled,
+                config.performance.compression.threshold,
+                config.websocket.max_message_size,
+            )
+            .with_voice_transcription(ai_manager.clone(), voice_manager.clone())
+            .with_geo_risk(config.security.geo_risk.clone())
+            .with_geoip_database(config.websocket.geoip_database_path.as_deref())
+            .with_content_filter(content_filter)
+            .with_assignment(config.websocket.assignment_mode, config.websocket.new_customer_alert_count)
+            .with_duplicate_connection_policy(config.websocket.duplicate_connection_policy)
+            .with_connection_limits(config.websocket.max_connections, config.websocket.send_queue_size)
+            .with_queue_timeout(std::time::Duration::from_secs(config.websocket.queue_timeout_seconds))
+            .with_session_idle_timeout(std::time::Duration::from_secs(config.websocket.session_idle_timeout_seconds))
+            .with_bandwidth_alert(config.websocket.bandwidth_alert_bytes_per_sec)
+            .with_ack_alert(
+                std::time::Duration::from_secs(config.websocket.ack_timeout_seconds),
+                config.websocket.unacked_alert_rate,
+            )
+            .with_sla(config.performance.sla.clone())
+            .with_analytics_cache(&config.performance.analytics_cache)
+            .with_message_queue_config(config.websocket.message_queue.clone())
+            .with_greeting(config.websocket.greeting.clone())
+            .with_latency_histograms(latency_histograms.clone()),
+    );
+
+    // 指标注册中心与WebSocket管理器共用消息类型计数、收发字节、消息确认统计、统计缓存命中和耗时直方图
+    let metrics_registry = Arc::new(
+        MetricsRegistry::new()
+            .with_message_counters(ws_manager.message_counters.clone())
+            .with_bandwidth_counters(ws_manager.bandwidth.clone())
+            .with_ack_tracker(ws_manager.acks.clone())
+            .with_queue_dropped_counter(ws_manager.message_queue.dropped_counter())
+            .with_analytics_cache_counters(ws_manager.analytics_cache.counters())
+            .with_histograms(latency_histograms),
+    );
+
     // 初始化客服认证管理器
     let kefu_auth_manager = if let Some(pool_manager) = redis_manager.get_pool_manager() {
         let manager = KefuAuthManager::new(pool_manager);
@@ -159,6 +283,29 @@ pub async fn initialize_system_components() -> Result<SystemComponents> {
         return Err(anyhow::anyhow!("Redis连接池未启用"));
     };
 
+    // 初始化客户黑名单管理器
+    let customer_manager = Arc::new(CustomerManager::new(Arc::new(RedisBanStore::new(redis_manager.clone()))));
+    info!("🚫 客户黑名单管理器初始化成功");
+
+    // 初始化刷新令牌管理器
+    let refresh_tokens = Arc::new(RefreshTokenManager::new(
+        JwtAuth::from_config(),
+        Arc::new(RedisRefreshTokenStore::new(redis_manager.clone())),
+        config.security.refresh_token_expiry,
+    )
+    .with_account_status(kefu_auth_manager.clone()));
+
+    // 初始化单点登录管理器
+    let sso_manager = Arc::new(
+        SsoManager::new(
+            &config.security.sso,
+            Arc::new(RedisSsoUserStore::new(redis_manager.clone())),
+            JwtAuth::from_config(),
+        )
+        .with_refresh_tokens(refresh_tokens.clone()),
+    );
+    info!("🔑 单点登录管理器初始化成功，身份提供方 {} 个", config.security.sso.providers.len());
+
     // 企业级组件初始化 - 暂时禁用以修复编译
     // info!("🏢 开始初始化企业级组件...");
     info!("🏢 企业级组件暂时禁用，正在修复编译错误...");
@@ -173,6 +320,11 @@ pub async fn initialize_system_components() -> Result<SystemComponents> {
         ws_manager,
         ai_manager,
         kefu_auth_manager,
+        customer_manager,
+        sso_manager,
+        refresh_tokens,
+        metrics_registry,
+        feature_flags,
         // 企业级组件 - 暂时禁用
         // load_balancer,
         // websocket_pool,
@@ -191,6 +343,44 @@ pub async fn start_background_tasks(components: &SystemComponents) {
     components.ws_manager.start_heartbeat_checker().await;
     info!("✅ 基于会话的在线状态检测已启用 - 基于活动时间判断");
 
+    // 启动连接数采样
+    components
+        .ws_manager
+        .start_connection_sampler(crate::monitoring::connection_history::DEFAULT_SAMPLE_INTERVAL)
+        .await;
+    info!("📈 连接数历史采样已启动，每分钟采样一次");
+
+    // 启动扩缩容负载采样
+    components
+        .ws_manager
+        .start_scaling_sampler(crate::monitoring::scaling::DEFAULT_SCALING_INTERVAL)
+        .await;
+    info!("📐 扩缩容负载采样已启动");
+
+    // 启动共享的Redis频道订阅，事件转发给本机在线用户
+    components.ws_manager.start_redis_subscriber().await;
+    info!("📡 Redis频道订阅已启动");
+
+    // 启动性能指标采集
+    PerformanceCollector::new(components.metrics_registry.clone(), components.ws_manager.clone())
+        .start_collection()
+        .await;
+    info!("📊 性能指标采集已启动，/metrics 可查看");
+
+    // 启动排队超时检查，超时客户转为留言
+    components.ws_manager.start_queue_timeout_checker().await;
+    info!("✅ 排队超时转留言检查已启动");
+
+    // 启动空闲会话检查，长时间没有消息的会话自动结束
+    components.ws_manager.start_idle_session_checker().await;
+    info!("✅ 空闲会话自动结束检查已启动");
+
+    // 启动连接带宽检查，异常高带宽的连接告警
+    components.ws_manager.start_bandwidth_monitor().await;
+
+    // 启动消息确认检查，未确认率过高时告警
+    components.ws_manager.start_ack_monitor().await;
+
     // 启动AI处理器
     match components.ai_manager.start_processing().await { Err(e) => {
         error!("🤖 AI处理器启动失败: {}", e);
@@ -212,6 +402,77 @@ pub async fn start_background_tasks(components: &SystemComponents) {
         info!("✅ 会话清理任务已启动，每小时清理一次过期会话");
     }
 
+    // 启动功能开关同步，其他实例的修改定期生效
+    {
+        let feature_flags = components.feature_flags.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(crate::feature_flags::DEFAULT_REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = feature_flags.refresh().await {
+                    error!("🚩 功能开关同步失败: {:?}", e);
+                }
+            }
+        });
+        info!("🚩 功能开关同步已启动");
+    }
+
+    // 启动自动打标规则热更新检查
+    {
+        let auto_tagger = components.storage.auto_tagger().clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                if let Err(e) = auto_tagger.reload_if_changed() {
+                    error!("🏷️ 自动打标规则重新加载失败: {:?}", e);
+                }
+            }
+        });
+        info!("✅ 自动打标规则热更新已启用，每30秒检查一次");
+    }
+
+    // 启动敏感词表热更新检查
+    {
+        let content_filter = components.ws_manager.content_filter.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                if let Err(e) = content_filter.reload_if_changed() {
+                    error!("🧹 敏感词表重新加载失败: {:?}", e);
+                }
+            }
+        });
+        info!("✅ 敏感词表热更新已启用，每30秒检查一次");
+    }
+
+    // 启动WAL定期检查点：sled落盘后截断预写日志
+    {
+        let storage = components.storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(crate::storage_wal::WAL_CHECKPOINT_INTERVAL);
+            loop {
+                interval.tick().await;
+                // 落盘和截断会阻塞，放到阻塞线程池执行
+                let storage = storage.clone();
+                match tokio::task::spawn_blocking(move || storage.checkpoint_wal()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => error!("💾 WAL检查点失败: {:?}", e),
+                    Err(e) => error!("💾 WAL检查点任务异常: {:?}", e),
+                }
+            }
+        });
+        info!("✅ 消息WAL检查点已启用，每30秒截断一次已落盘的日志");
+    }
+
+    // 启动AI配置文件热更新
+    {
+        let ai_manager = components.ai_manager.clone();
+        tokio::spawn(ai_manager.watch_config_file(std::path::PathBuf::from(crate::ai::AI_CONFIG_PATH)));
+        info!("✅ AI配置热更新已启用: {}", crate::ai::AI_CONFIG_PATH);
+    }
+
     // 企业级组件启动 - 暂时禁用
     // info!("🏢 启动企业级后台任务...");
     // info!("✅ 企业级后台任务启动完成");
@@ -0,0 +1,217 @@
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+static LOG_CONTROLLER: OnceLock<LogLevelController> = OnceLock::new();
+
+/// 运行时日志级别控制器，基于 reload layer 动态替换过滤规则
+pub struct LogLevelController {
+    handle: reload::Handle<Targets, Registry>,
+    state: Mutex<LogLevelState>,
+}
+
+#[derive(Debug, Clone)]
+struct LogLevelState {
+    default_level: LevelFilter,
+    module_levels: BTreeMap<String, LevelFilter>,
+}
+
+impl LogLevelState {
+    /// 按 `RUST_LOG` 的指令语法解析（如 `info,kefu_system::websocket=debug`），未指定全局级别时使用 `fallback`
+    fn from_directives(directives: &str, fallback: LevelFilter) -> Result<Self> {
+        let targets = Targets::from_str(directives).map_err(|e| anyhow!("无效的日志指令 {}: {}", directives, e))?;
+        Ok(Self {
+            default_level: targets.default_level().unwrap_or(fallback),
+            module_levels: targets.into_iter().collect(),
+        })
+    }
+
+    fn targets(&self) -> Targets {
+        Targets::new()
+            .with_default(self.default_level)
+            .with_targets(self.module_levels.clone())
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let modules: BTreeMap<&String, String> = self
+            .module_levels
+            .iter()
+            .map(|(module, level)| (module, level.to_string()))
+            .collect();
+        serde_json::json!({
+            "default": self.default_level.to_string(),
+            "modules": modules,
+        })
+    }
+}
+
+impl LogLevelController {
+    /// 创建可重载的过滤层及其控制器
+    pub fn new(default_level: &str) -> Result<(reload::Layer<Targets, Registry>, Self)> {
+        Ok(Self::with_state(LogLevelState {
+            default_level: parse_level(default_level)?,
+            module_levels: BTreeMap::new(),
+        }))
+    }
+
+    fn with_state(state: LogLevelState) -> (reload::Layer<Targets, Registry>, Self) {
+        let (layer, handle) = reload::Layer::new(state.targets());
+        (
+            layer,
+            Self {
+                handle,
+                state: Mutex::new(state),
+            },
+        )
+    }
+
+    /// 初始化全局日志订阅器，初始级别取自 `RUST_LOG`，未设置或无法解析时使用 `default_level`
+    pub fn init(default_level: &str) -> Result<()> {
+        let fallback = parse_level(default_level)?;
+        let env_directives = std::env::var("RUST_LOG").ok().filter(|value| !value.trim().is_empty());
+        let mut env_error = None;
+        let (filter, controller) = match env_directives.map(|value| LogLevelState::from_directives(&value, fallback)) {
+            Some(Ok(state)) => Self::with_state(state),
+            Some(Err(e)) => {
+                env_error = Some(e);
+                Self::new(default_level)?
+            }
+            None => Self::new(default_level)?,
+        };
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer())
+            .try_init()?;
+        if let Some(e) = env_error {
+            tracing::warn!("⚠️ RUST_LOG 解析失败，使用默认日志级别 {}: {}", default_level, e);
+        }
+        LOG_CONTROLLER
+            .set(controller)
+            .map_err(|_| anyhow!("日志控制器已初始化"))
+    }
+
+    pub fn global() -> Option<&'static LogLevelController> {
+        LOG_CONTROLLER.get()
+    }
+
+    /// 调整日志级别；指定module时只调整该模块（如 kefu_system::websocket）
+    pub fn set_level(&self, module: Option<&str>, level: &str) -> Result<serde_json::Value> {
+        let level = parse_level(level)?;
+        let mut state = self.state.lock().map_err(|_| anyhow!("日志级别状态锁异常"))?;
+        let mut next = state.clone();
+        match module {
+            Some(module) if !module.is_empty() => {
+                next.module_levels.insert(module.to_string(), level);
+            }
+            _ => next.default_level = level,
+        }
+        self.handle.reload(next.targets())?;
+        *state = next;
+        Ok(state.to_json())
+    }
+
+    /// 移除模块级别的覆盖，恢复使用全局级别
+    pub fn reset_module(&self, module: &str) -> Result<serde_json::Value> {
+        let mut state = self.state.lock().map_err(|_| anyhow!("日志级别状态锁异常"))?;
+        let mut next = state.clone();
+        next.module_levels.remove(module);
+        self.handle.reload(next.targets())?;
+        *state = next;
+        Ok(state.to_json())
+    }
+
+    pub fn current(&self) -> serde_json::Value {
+        match self.state.lock() {
+            Ok(state) => state.to_json(),
+            Err(_) => serde_json::Value::Null,
+        }
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    LevelFilter::from_str(level).map_err(|_| anyhow!("无效的日志级别: {}", level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct CaptureWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CaptureWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CaptureWriter {
+        fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_reload_log_level() {
+        let writer = CaptureWriter::default();
+        let (filter, controller) = LogLevelController::new("info").unwrap();
+        let make_writer = writer.clone();
+        let subscriber = tracing_subscriber::registry().with(filter).with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(move || make_writer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("debug-before");
+            tracing::info!("info-before");
+            let output = writer.take();
+            assert!(!output.contains("debug-before"));
+            assert!(output.contains("info-before"));
+
+            controller.set_level(None, "debug").unwrap();
+            tracing::debug!("debug-enabled");
+            assert!(writer.take().contains("debug-enabled"));
+
+            controller.set_level(None, "info").unwrap();
+            tracing::debug!("debug-disabled");
+            assert!(!writer.take().contains("debug-disabled"));
+        });
+    }
+
+    #[test]
+    fn test_module_level_override() {
+        let (_filter, controller) = LogLevelController::new("info").unwrap();
+        let state = controller.set_level(Some("kefu_system::websocket"), "debug").unwrap();
+        assert_eq!(state["default"], "info");
+        assert_eq!(state["modules"]["kefu_system::websocket"], "debug");
+
+        let state = controller.reset_module("kefu_system::websocket").unwrap();
+        assert!(state["modules"].as_object().unwrap().is_empty());
+        assert!(controller.set_level(None, "verbose").is_err());
+    }
+
+    #[test]
+    fn test_state_seeded_from_rust_log_directives() {
+        let state = LogLevelState::from_directives("warn,kefu_system::websocket=debug", LevelFilter::INFO).unwrap();
+        assert_eq!(state.default_level, LevelFilter::WARN);
+        assert_eq!(state.module_levels["kefu_system::websocket"], LevelFilter::DEBUG);
+
+        let state = LogLevelState::from_directives("kefu_system=trace", LevelFilter::INFO).unwrap();
+        assert_eq!(state.default_level, LevelFilter::INFO);
+        assert_eq!(state.module_levels["kefu_system"], LevelFilter::TRACE);
+
+        assert!(LogLevelState::from_directives("kefu_system=verbose", LevelFilter::INFO).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub mod startup;
 pub mod components;
+pub mod logging;
 
 pub use startup::*;
 pub use components::*; 
\ No newline at end of file
@@ -1,9 +1,14 @@
 use anyhow::Result;
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::info;
+use warp::hyper::server::conn::AddrStream;
+use warp::hyper::service::{make_service_fn, service_fn};
 use warp::Filter;
 use crate::config::AppConfig;
 use crate::errors::handle_rejection;
+use crate::middleware::request_id::with_request_id;
 use crate::routes::build_all_routes;
 use crate::server::components::SystemComponents;
 
@@ -21,6 +26,11 @@ pub async fn start_server(components: SystemComponents) -> Result<()> {
         Arc::new(components.storage.clone()),
         components.ai_manager.clone(),
         components.kefu_auth_manager.clone(),
+        components.customer_manager.clone(),
+        components.sso_manager.clone(),
+        components.refresh_tokens.clone(),
+        components.metrics_registry.clone(),
+        components.feature_flags.clone(),
         None, // components.load_balancer.clone(),
         None, // components.websocket_pool.clone(),
         None, // components.api_routes.clone(),
@@ -31,27 +41,21 @@ pub async fn start_server(components: SystemComponents) -> Result<()> {
         None, // components.failover_manager.clone(),
     );
 
-    // 配置CORS - 简化实现
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec!["content-type", "authorization", "user-id", "user-name", "user-type", "session-id"])
-        .allow_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"]);
-
+    // 路由内已处理业务错误，这里只剩跨域来源被拒绝
     let final_routes = routes
         .recover(handle_rejection)
         .with(warp::log::custom(|info| {
+            // 来源地址和请求ID记录在外层 request span 中
             info!(
-                "🔍 路由调试: {} {} -> 状态: {} | 耗时: {:?} | 来源: {:?}",
+                "🔍 路由调试: {} {} -> 状态: {} | 耗时: {:?}",
                 info.method(),
                 info.path(),
                 info.status().as_u16(),
-                info.elapsed(),
-                info.remote_addr()
+                info.elapsed()
             );
-        }))
-        .with(cors);
+        }));
 
-    let addr = ([0, 0, 0, 0], config.server.port);
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.server.port));
     
     // 打印启动信息
     print_startup_info(config);
@@ -60,7 +64,18 @@ pub async fn start_server(components: SystemComponents) -> Result<()> {
     let url = format!("http://localhost:{}", config.server.port);
     open_browser(&url);
 
-    warp::serve(final_routes).run(addr).await;
+    // 每个请求先分配请求ID再进入路由，处理期间的日志和错误响应都能按ID关联
+    let service = warp::service(final_routes);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let service = service.clone();
+        let remote_addr = conn.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                with_request_id(service.clone(), Some(remote_addr), request)
+            }))
+        }
+    });
+    warp::hyper::Server::bind(&addr).serve(make_service).await?;
 
     Ok(())
 }
@@ -1,8 +1,266 @@
-use crate::message::{ChatMessage, Session};
+use crate::auto_tag::AutoTagger;
+use crate::config::ExportLimitConfig;
+use crate::message::{ChatMessage, ContentType, LeaveMessageTicket, Session, SessionSummary, TicketStatus};
+use crate::storage_crypto::{MessageCipher, SealedMessage};
+use crate::storage_wal::WriteAheadLog;
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sled::transaction::{ConflictableTransactionError, Transactional};
 use sled::{Db, Tree};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// 按会话分区的消息迁移完成标记，存放在默认树中
+const SESSION_PARTITION_MIGRATED_KEY: &str = "migration:session_messages";
+
+/// 每个会话保留的消息条数，超出后清理最早的消息
+const MAX_MESSAGES_PER_CONVERSATION: usize = 1000;
+
+/// 会话分区前缀：两个参与者按字典序排列，双方读取同一分区
+fn session_partition(user1: &str, user2: &str) -> String {
+    let (first, second) = if user1 <= user2 { (user1, user2) } else { (user2, user1) };
+    format!("{}:{}/", first, second)
+}
+
+/// 分区内的消息键：分区前缀 + 补零毫秒时间戳 + 消息ID，前缀扫描即按时间排序
+fn session_message_key(partition: &str, message: &ChatMessage, message_id: &str) -> String {
+    session_key_at(partition, message.timestamp, message_id)
+}
+
+fn session_key_at(partition: &str, timestamp: DateTime<Utc>, message_id: &str) -> String {
+    format!("{}{:020}:{}", partition, timestamp.timestamp_millis().max(0), message_id)
+}
+
+/// 会话的消息ID：两个参与者按字典序排列，用 ':' 连接，与会话分区前缀对应
+pub fn conversation_id(user1: &str, user2: &str) -> String {
+    session_partition(user1, user2).trim_end_matches('/').to_string()
+}
+
+/// 序号索引键：分区前缀 + 补零序号，范围扫描即按序号排序
+fn session_seq_key(partition: &str, seq: u64) -> String {
+    format!("{}{:020}", partition, seq)
+}
+
+/// 客户端消息ID的去重键，按发送者隔离
+fn client_message_key(from: &str, client_id: &str) -> String {
+    format!("{}/{}", from, client_id)
+}
+
+fn decode_seq(bytes: &[u8]) -> u64 {
+    <[u8; 8]>::try_from(bytes).map(u64::from_be_bytes).unwrap_or(0)
+}
+
+/// 保存消息的结果：同一发送者重试时按客户端消息ID去重，返回已存储的原消息
+#[derive(Debug, Clone)]
+pub enum SavedMessage {
+    Created(ChatMessage),
+    Duplicate(ChatMessage),
+}
+
+impl SavedMessage {
+    pub fn is_duplicate(&self) -> bool {
+        matches!(self, SavedMessage::Duplicate(_))
+    }
+
+    pub fn into_message(self) -> ChatMessage {
+        match self {
+            SavedMessage::Created(message) | SavedMessage::Duplicate(message) => message,
+        }
+    }
+}
+
+/// 消息的阅读/删除状态，未记录状态的消息视为未读
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageState {
+    Unread,
+    Read,
+    Deleted,
+}
+
+impl MessageState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageState::Unread => "unread",
+            MessageState::Read => "read",
+            MessageState::Deleted => "deleted",
+        }
+    }
+}
+
+/// 批量更新中单条消息的结果
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StatusUpdateResult {
+    pub message_id: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 消息导出条件，各项为空表示不限
+#[derive(Debug, Clone, Default)]
+pub struct ExportQuery {
+    /// 只导出该用户收发的消息
+    pub user_id: Option<String>,
+    /// 客服导出时只包含本人参与的会话，管理员导出时为空
+    pub kefu_id: Option<String>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+    pub format: String,
+}
+
+/// 一次消息导出的审计记录：谁导出了什么范围、多少条，被拒绝的导出也会记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportAuditEntry {
+    pub id: String,
+    pub operator: String,
+    pub user_id: Option<String>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+    pub format: String,
+    /// 导出的消息条数；被拒绝时为拒绝前已读到的条数，扫描前即被拒绝时为0
+    pub message_count: usize,
+    pub allowed: bool,
+    /// 被拒绝的原因
+    pub reason: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// 导出结果，两种情况都已写入审计
+#[derive(Debug, Clone)]
+pub enum MessageExport {
+    Completed { audit: ExportAuditEntry, messages: Vec<ChatMessage> },
+    Rejected(ExportAuditEntry),
+}
+
+/// 导出人当天已用的导出额度
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct ExportUsage {
+    exports: u32,
+    messages: u64,
+}
+
+// 超出当日限额时返回拒绝原因
+fn export_rejection(limits: &ExportLimitConfig, usage: ExportUsage, count: usize) -> Option<String> {
+    if limits.max_exports_per_day > 0 && usage.exports >= limits.max_exports_per_day {
+        return Some(format!("今日导出次数已达上限{}次", limits.max_exports_per_day));
+    }
+    if limits.max_messages_per_day > 0 && usage.messages + count as u64 > limits.max_messages_per_day {
+        return Some(format!(
+            "今日导出条数超过上限{}条（已导出{}条，本次{}条）",
+            limits.max_messages_per_day, usage.messages, count
+        ));
+    }
+    None
+}
+
+/// 搜索结果摘要中关键词前后各保留的字符数
+pub const SNIPPET_CONTEXT_CHARS: usize = 30;
+/// 摘要被截断处的省略号
+const SNIPPET_ELLIPSIS: char = '…';
+/// 单次搜索最多检查的消息条数，超出的部分不再扫描
+pub const SEARCH_SCAN_LIMIT: usize = 20_000;
+/// 单次搜索最多返回的命中条数
+pub const SEARCH_MAX_HITS: usize = 500;
+
+/// 消息搜索条件，除关键词外各项为空表示不限
+#[derive(Debug, Clone, Default)]
+pub struct MessageSearchQuery {
+    pub keyword: String,
+    /// 只搜索该用户收发的消息
+    pub user_id: Option<String>,
+    /// 客服搜索时只包含本人参与的会话，管理员搜索时为空
+    pub kefu_id: Option<String>,
+    /// 消息类型（如 "text"，不区分大小写），未设置类型的消息按文本处理
+    pub content_type: Option<String>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+/// 摘要中一处关键词的位置，按字符（而非字节）计数，左闭右开
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 一条搜索命中：消息本身、关键词附近的摘要以及摘要中需要高亮的位置
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub message: ChatMessage,
+    pub snippet: String,
+    pub match_ranges: Vec<MatchRange>,
+}
+
+// 按字符不区分大小写地查找关键词所有不重叠的出现位置（字符下标）
+fn find_matches(content: &[char], keyword: &[char]) -> Vec<MatchRange> {
+    let mut matches = Vec::new();
+    if keyword.is_empty() || keyword.len() > content.len() {
+        return matches;
+    }
+    let same = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+    let mut start = 0;
+    while start + keyword.len() <= content.len() {
+        if content[start..start + keyword.len()].iter().zip(keyword).all(|(&a, &b)| same(a, b)) {
+            matches.push(MatchRange { start, end: start + keyword.len() });
+            start += keyword.len();
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+/// 截取第一处关键词前后 context 个字符作为摘要，返回摘要和摘要内各处关键词的字符位置；
+/// 内容不含关键词时返回 None
+pub fn build_snippet(content: &str, keyword: &str, context: usize) -> Option<(String, Vec<MatchRange>)> {
+    let chars: Vec<char> = content.chars().collect();
+    let keyword: Vec<char> = keyword.trim().chars().collect();
+    let matches = find_matches(&chars, &keyword);
+    let first = matches.first()?;
+
+    let from = first.start.saturating_sub(context);
+    let to = (first.end + context).min(chars.len());
+    let mut snippet = String::new();
+    let mut offset = 0;
+    if from > 0 {
+        snippet.push(SNIPPET_ELLIPSIS);
+        offset = 1;
+    }
+    snippet.extend(&chars[from..to]);
+    if to < chars.len() {
+        snippet.push(SNIPPET_ELLIPSIS);
+    }
+
+    let ranges = matches
+        .iter()
+        .filter(|range| range.start >= from && range.end <= to)
+        .map(|range| MatchRange {
+            start: range.start - from + offset,
+            end: range.end - from + offset,
+        })
+        .collect();
+    Some((snippet, ranges))
+}
+
+/// 一页历史消息，按时间升序；has_more 表示更早的消息还有剩余。
+/// latest_seq 为读取时会话已分配的最大序号，客户端据此与实时消息去重合并
+#[derive(Debug, Clone, Default)]
+pub struct HistoryPage {
+    pub messages: Vec<ChatMessage>,
+    pub has_more: bool,
+    pub latest_seq: u64,
+}
+
+/// 向前翻页的游标：本页最早一条消息的时间和ID。
+/// 与分区内的键顺序一致，同一毫秒内的多条消息按ID区分，翻页时不会遗漏或重复；
+/// message_id 为空时返回该毫秒之前的全部消息
+#[derive(Debug, Clone)]
+pub struct HistoryCursor {
+    pub timestamp: DateTime<Utc>,
+    pub message_id: String,
+}
 
 #[derive(Clone)]
 pub struct LocalStorage {
@@ -10,6 +268,33 @@ pub struct LocalStorage {
     messages_tree: Tree,
     sessions_tree: Tree,
     user_messages_tree: Tree,
+    /// 按会话分区的消息副本，读取会话历史时只扫描该会话的前缀
+    session_messages_tree: Tree,
+    /// 每个会话分区当前的最大序号
+    session_seq_tree: Tree,
+    /// 会话序号到消息ID的索引，用于增量同步
+    seq_index_tree: Tree,
+    message_tags_tree: Tree,
+    tag_index_tree: Tree,
+    message_status_tree: Tree,
+    status_counts_tree: Tree,
+    tickets_tree: Tree,
+    /// 导出人每天的已用导出额度，键为 导出人:日期
+    export_quota_tree: Tree,
+    /// 导出审计，键为递增序号，按写入顺序排列
+    export_audit_tree: Tree,
+    /// 二进制对象，键由调用方指定
+    blobs_tree: Tree,
+    /// 客服之间的内部消息，按会话分区存放，与客户会话的消息和索引完全分开
+    internal_messages_tree: Tree,
+    /// 客户端消息ID与其他发送者冲突时，(发送者, 客户端ID) 到实际存储ID的映射
+    client_message_ids_tree: Tree,
+    export_limits: ExportLimitConfig,
+    auto_tagger: AutoTagger,
+    /// 消息写入的预写日志，防止sled缓冲中未落盘的消息在崩溃时丢失
+    wal: Arc<WriteAheadLog>,
+    /// 消息静态加密，未开启时为 None
+    message_cipher: Option<MessageCipher>,
 }
 
 impl LocalStorage {
@@ -41,13 +326,215 @@ impl LocalStorage {
         let messages_tree = db.open_tree("messages")?;
         let sessions_tree = db.open_tree("sessions")?;
         let user_messages_tree = db.open_tree("user_messages")?;
-
-        Ok(Self {
+        let session_messages_tree = db.open_tree("session_messages")?;
+        let session_seq_tree = db.open_tree("session_seq")?;
+        let seq_index_tree = db.open_tree("session_seq_index")?;
+        let message_tags_tree = db.open_tree("message_tags")?;
+        let tag_index_tree = db.open_tree("tag_index")?;
+        let message_status_tree = db.open_tree("message_status")?;
+        let status_counts_tree = db.open_tree("message_status_counts")?;
+        let tickets_tree = db.open_tree("tickets")?;
+        let export_quota_tree = db.open_tree("export_quota")?;
+        let export_audit_tree = db.open_tree("export_audit")?;
+        let blobs_tree = db.open_tree("blobs")?;
+        let internal_messages_tree = db.open_tree("internal_messages")?;
+        let client_message_ids_tree = db.open_tree("client_message_ids")?;
+        let wal = WriteAheadLog::open(&base_path.join("wal").join("messages.wal"))?;
+
+        let storage = Self {
             db,
             messages_tree,
             sessions_tree,
             user_messages_tree,
-        })
+            session_messages_tree,
+            session_seq_tree,
+            seq_index_tree,
+            message_tags_tree,
+            tag_index_tree,
+            message_status_tree,
+            status_counts_tree,
+            tickets_tree,
+            export_quota_tree,
+            export_audit_tree,
+            blobs_tree,
+            internal_messages_tree,
+            client_message_ids_tree,
+            export_limits: ExportLimitConfig::default(),
+            auto_tagger: AutoTagger::default(),
+            wal: Arc::new(wal),
+            message_cipher: None,
+        };
+        storage.migrate_to_session_partitions()?;
+        storage.recover_from_wal()?;
+        Ok(storage)
+    }
+
+    /// 把旧版本只有用户索引的消息写入会话分区，只在首次启动新版本时执行
+    fn migrate_to_session_partitions(&self) -> Result<usize> {
+        if self.db.contains_key(SESSION_PARTITION_MIGRATED_KEY)? {
+            return Ok(0);
+        }
+
+        let mut migrated = 0;
+        for entry in self.user_messages_tree.iter() {
+            let (key, value) = entry?;
+            let key = String::from_utf8_lossy(&key).to_string();
+            // 每对用户有两个方向的索引，内容相同，只处理一个方向
+            let Some((user1, user2)) = key.split_once(':') else {
+                continue;
+            };
+            if user1 > user2 {
+                continue;
+            }
+            let partition = session_partition(user1, user2);
+            let message_ids: Vec<String> = serde_json::from_slice(&value)?;
+            for message_id in message_ids {
+                if let Some(data) = self.messages_tree.get(message_id.as_bytes())? {
+                    if let Ok(message) = serde_json::from_slice::<ChatMessage>(&data) {
+                        let partition_key = session_message_key(&partition, &message, &message_id);
+                        self.session_messages_tree.insert(partition_key.as_bytes(), data)?;
+                        migrated += 1;
+                    }
+                }
+            }
+        }
+
+        self.db.insert(SESSION_PARTITION_MIGRATED_KEY, &[])?;
+        if migrated > 0 {
+            tracing::info!("📦 会话分区迁移完成: 共迁移{}条消息", migrated);
+        }
+        Ok(migrated)
+    }
+
+    /// 重放WAL中残留的消息：上次退出前可能尚未落盘，已存在的消息按ID去重跳过。
+    /// 开启加密时WAL中的记录已是密文，重放时原样写入
+    fn recover_from_wal(&self) -> Result<usize> {
+        let entries = self.wal.read_entries()?;
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let mut recovered = 0;
+        let mut deferred = 0;
+        for sealed in &entries {
+            // 启动时还没有配置密钥，加密记录留在WAL中，开启加密后再重放
+            if sealed.encrypted.is_some() && self.message_cipher.is_none() {
+                deferred += 1;
+                continue;
+            }
+            let message = self.open_message(sealed.clone())?;
+            if !self.apply_message(&message)?.is_duplicate() {
+                recovered += 1;
+            }
+        }
+        if deferred > 0 {
+            tracing::warn!("🔒 WAL中有{}条加密记录，待开启消息加密后重放", deferred);
+            return Ok(recovered);
+        }
+        self.checkpoint_wal()?;
+        tracing::info!("🩹 WAL重放完成: 共{}条记录，恢复{}条未落盘消息", entries.len(), recovered);
+        Ok(recovered)
+    }
+
+    /// 检查点：sled 落盘后截断WAL，返回前的写入都已持久化到正式存储
+    pub fn checkpoint_wal(&self) -> Result<()> {
+        let mut wal = self.wal.lock();
+        self.db.flush()?;
+        wal.truncate()
+    }
+
+    /// 使用指定的消息导出限额
+    pub fn with_export_limits(mut self, export_limits: ExportLimitConfig) -> Self {
+        self.export_limits = export_limits;
+        self
+    }
+
+    /// 使用指定的自动打标器（规则可热更新）
+    pub fn with_auto_tagger(mut self, auto_tagger: AutoTagger) -> Self {
+        self.auto_tagger = auto_tagger;
+        self
+    }
+
+    pub fn auto_tagger(&self) -> &AutoTagger {
+        &self.auto_tagger
+    }
+
+    /// 开启消息静态加密：之后写入的消息 content 和 filename 加密落盘，读取时透明解密
+    pub fn with_message_cipher(mut self, cipher: MessageCipher) -> Self {
+        self.message_cipher = Some(cipher);
+        if let Err(e) = self.recover_from_wal() {
+            tracing::error!("🔒 加密WAL记录重放失败: {:?}", e);
+        }
+        self
+    }
+
+    // 落盘形式的消息：开启加密时加密敏感字段
+    fn seal_message(&self, message: &ChatMessage) -> Result<SealedMessage> {
+        match &self.message_cipher {
+            Some(cipher) => cipher.seal(message),
+            None => Ok(SealedMessage::plain(message.clone())),
+        }
+    }
+
+    // 解开落盘的消息，密文消息在未配置密钥时报错
+    fn open_message(&self, sealed: SealedMessage) -> Result<ChatMessage> {
+        match &self.message_cipher {
+            Some(cipher) => cipher.open(sealed),
+            None if sealed.encrypted.is_none() => Ok(sealed.message),
+            None => Err(anyhow::anyhow!("消息已加密，但未开启消息加密")),
+        }
+    }
+
+    // 按ID读取消息，不存在或已删除时返回 None
+    pub fn get_message(&self, message_id: &str) -> Result<Option<ChatMessage>> {
+        if self.get_message_status(message_id)? == MessageState::Deleted {
+            return Ok(None);
+        }
+        match self.messages_tree.get(message_id.as_bytes())? {
+            Some(data) => Ok(Some(self.decode_message(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn encode_message(&self, message: &ChatMessage) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self.seal_message(message)?)?)
+    }
+
+    pub(crate) fn decode_message(&self, data: &[u8]) -> Result<ChatMessage> {
+        self.open_message(serde_json::from_slice(data)?)
+    }
+
+    /// 保存客服内部消息，不写入客户会话的分区、序号和用户消息索引，客户历史中不会出现
+    pub fn save_internal_message(&self, message: &ChatMessage) -> Result<()> {
+        let to_user = message.to.as_deref().unwrap_or_default();
+        let message_id = message.id.clone().unwrap_or_default();
+        let key = session_message_key(&session_partition(&message.from, to_user), message, &message_id);
+        self.internal_messages_tree.insert(key.as_bytes(), self.encode_message(message)?)?;
+        Ok(())
+    }
+
+    /// 两个客服之间最近的 limit 条内部消息，按时间升序
+    pub fn get_internal_messages(&self, user1: &str, user2: &str, limit: usize) -> Result<Vec<ChatMessage>> {
+        let partition = session_partition(user1, user2);
+        let mut messages = self
+            .internal_messages_tree
+            .scan_prefix(partition.as_bytes())
+            .rev()
+            .take(limit)
+            .map(|entry| self.decode_message(&entry?.1))
+            .collect::<Result<Vec<_>>>()?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// 保存二进制对象，相同键覆盖
+    pub fn save_blob(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.blobs_tree.insert(key.as_bytes(), data)?;
+        Ok(())
+    }
+
+    pub fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.blobs_tree.get(key.as_bytes())?.map(|data| data.to_vec()))
     }
 
     /// 通用键值存储 - 设置值
@@ -92,35 +579,247 @@ impl LocalStorage {
     //     Ok(keys)
     // }
 
-    // 保存聊天消息
-    pub fn save_message(&self, message: &ChatMessage) -> Result<()> {
-        // 生成唯一的消息ID
-        let message_id = message.id.clone().unwrap_or_else(|| {
-            format!(
+    // 保存聊天消息，(发送者, 客户端提供的 id) 作为幂等键：同一发送者重发同一 id 时不重复写入
+    pub fn save_message(&self, message: &ChatMessage) -> Result<SavedMessage> {
+        // 先顺序写入WAL并fsync，再写正式存储；持锁期间检查点不会截断这条记录
+        let mut wal = self.wal.lock();
+        if let Some(client_id) = message.id.as_deref() {
+            if let Some(existing) = self.find_client_message(message, client_id)? {
+                tracing::info!("♻️ 消息已存在，跳过重复写入: {}", client_id);
+                return Ok(SavedMessage::Duplicate(existing));
+            }
+        }
+
+        // 客户端ID已被其他会话的消息占用时改用服务端生成的ID，不能覆盖或返回别人的消息
+        let message_id = match message.id.as_deref() {
+            Some(client_id) if !self.messages_tree.contains_key(client_id.as_bytes())? => client_id.to_string(),
+            _ => format!(
                 "msg_{}_{}",
                 Utc::now().timestamp_millis(),
                 &uuid::Uuid::new_v4().to_string()[0..8]
-            )
-        });
+            ),
+        };
 
         // 创建带有ID的消息副本
         let mut message_with_id = message.clone();
         message_with_id.id = Some(message_id.clone());
 
-        // 保存单个消息
-        let message_data = serde_json::to_vec(&message_with_id)?;
-        self.messages_tree
-            .insert(message_id.as_bytes(), message_data)?;
+        wal.append(&self.seal_message(&message_with_id)?)?;
+        let saved = self.apply_message(&message_with_id)?;
+        if let Some(client_id) = message.id.as_deref().filter(|client_id| *client_id != message_id) {
+            tracing::warn!("⚠️ 客户端消息ID冲突，改用服务端ID: {} -> {}", client_id, message_id);
+            self.client_message_ids_tree
+                .insert(client_message_key(&message.from, client_id).as_bytes(), message_id.as_bytes())?;
+        }
+        Ok(saved)
+    }
 
-        // 更新用户消息索引
-        if let Some(to_user) = &message.to {
-            self.update_user_message_index(&message.from, to_user, &message_id)?;
-            self.update_user_message_index(to_user, &message.from, &message_id)?;
+    // 同一发送者此前以该客户端ID发给同一接收者的消息；ID相同但收发双方不同的消息不算重复
+    fn find_client_message(&self, message: &ChatMessage, client_id: &str) -> Result<Option<ChatMessage>> {
+        let remapped = self
+            .client_message_ids_tree
+            .get(client_message_key(&message.from, client_id).as_bytes())?
+            .map(|id| String::from_utf8_lossy(&id).to_string());
+        for stored_id in remapped.iter().map(String::as_str).chain(std::iter::once(client_id)) {
+            if let Some(data) = self.messages_tree.get(stored_id.as_bytes())? {
+                let existing = self.decode_message(&data)?;
+                if existing.from == message.from && existing.to == message.to {
+                    return Ok(Some(existing));
+                }
+            }
         }
+        Ok(None)
+    }
 
+    // 将已分配ID的消息写入正式存储，同一ID已存在时返回原消息
+    fn apply_message(&self, message: &ChatMessage) -> Result<SavedMessage> {
+        let message_id = message.id.clone().unwrap_or_default();
+
+        // 仅在ID不存在时写入，并发重试也只会保存一次
+        let message_data = self.encode_message(message)?;
+        if let Err(existing) = self.messages_tree.compare_and_swap(
+            message_id.as_bytes(),
+            None as Option<&[u8]>,
+            Some(message_data),
+        )? {
+            if let Some(data) = existing.current {
+                tracing::info!("♻️ 消息已存在，跳过重复写入: {}", message_id);
+                return Ok(SavedMessage::Duplicate(self.decode_message(&data)?));
+            }
+        }
+
+        // 分配会话序号，写入会话分区并更新用户消息索引
+        let mut message = message.clone();
+        if let Some(to_user) = message.to.clone() {
+            let partition = session_partition(&message.from, &to_user);
+            let seq = self.next_session_seq(&partition)?;
+            message.seq = Some(seq);
+            let message_data = self.encode_message(&message)?;
+            self.messages_tree.insert(message_id.as_bytes(), message_data.clone())?;
+            let partition_key = session_message_key(&partition, &message, &message_id);
+            self.session_messages_tree.insert(partition_key.as_bytes(), message_data)?;
+            self.seq_index_tree
+                .insert(session_seq_key(&partition, seq).as_bytes(), message_id.as_bytes())?;
+            self.update_user_message_index(&message.from, &to_user, &message_id)?;
+            self.update_user_message_index(&to_user, &message.from, &message_id)?;
+        }
+
+        // 关键词自动打标，标签单独存储，不修改消息内容
+        let auto_tags = self.auto_tagger.tag(&message.content);
+        if !auto_tags.is_empty() {
+            self.save_message_tags(&message_id, &auto_tags)?;
+        }
+
+        Ok(SavedMessage::Created(message))
+    }
+
+    // 原子递增会话分区的序号
+    fn next_session_seq(&self, partition: &str) -> Result<u64> {
+        let updated = self.session_seq_tree.update_and_fetch(partition.as_bytes(), |current| {
+            let next = current.map(decode_seq).unwrap_or(0) + 1;
+            Some(next.to_be_bytes().to_vec())
+        })?;
+        Ok(updated.map(|bytes| decode_seq(&bytes)).unwrap_or(1))
+    }
+
+    /// 会话当前已分配的最大序号，还没有消息时为0
+    pub fn latest_session_seq(&self, user1: &str, user2: &str) -> Result<u64> {
+        let partition = session_partition(user1, user2);
+        Ok(self
+            .session_seq_tree
+            .get(partition.as_bytes())?
+            .map(|bytes| decode_seq(&bytes))
+            .unwrap_or(0))
+    }
+
+    /// 会话ID对应的两个参与者：已保存的会话记录取客服和客户，否则按 conversation_id 格式解析
+    pub fn session_participants(&self, session_id: &str) -> Result<Option<(String, String)>> {
+        if let Some(session) = self.get_session(session_id)? {
+            return Ok(Some((session.kefu_id, session.kehu_id)));
+        }
+        Ok(session_id
+            .split_once(':')
+            .filter(|(user1, user2)| !user1.is_empty() && !user2.is_empty())
+            .map(|(user1, user2)| (user1.to_string(), user2.to_string())))
+    }
+
+    // 会话ID对应的分区
+    fn partition_for_session(&self, session_id: &str) -> Result<Option<String>> {
+        Ok(self
+            .session_participants(session_id)?
+            .map(|(user1, user2)| session_partition(&user1, &user2)))
+    }
+
+    /// 增量同步：返回会话中序号大于 since_seq 的消息，按序号升序；since_seq 为0时返回全部带序号的消息
+    pub fn get_messages_since(&self, session_id: &str, since_seq: u64) -> Result<Vec<ChatMessage>> {
+        self.get_messages_since_limited(session_id, since_seq, usize::MAX)
+    }
+
+    // 同 get_messages_since，最多读取 max 条，翻页时不必载入整段增量
+    fn get_messages_since_limited(&self, session_id: &str, since_seq: u64, max: usize) -> Result<Vec<ChatMessage>> {
+        let Some(partition) = self.partition_for_session(session_id)? else {
+            return Ok(Vec::new());
+        };
+
+        let start = session_seq_key(&partition, since_seq.saturating_add(1));
+        let mut messages = Vec::new();
+        for entry in self.seq_index_tree.range(start.as_bytes()..) {
+            let (key, message_id) = entry?;
+            if !key.starts_with(partition.as_bytes()) || messages.len() >= max {
+                break;
+            }
+            let message_id = String::from_utf8_lossy(&message_id).to_string();
+            if self.get_message_status(&message_id)? == MessageState::Deleted {
+                continue;
+            }
+            // 超出保留条数被清理的消息跳过
+            if let Some(data) = self.messages_tree.get(message_id.as_bytes())? {
+                messages.push(self.decode_message(&data)?);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// 长轮询版本的增量同步：暂无新消息时等待新消息写入，最多等待 timeout
+    pub async fn wait_for_messages_since(
+        &self,
+        session_id: &str,
+        since_seq: u64,
+        timeout: std::time::Duration,
+    ) -> Result<Vec<ChatMessage>> {
+        let Some(partition) = self.partition_for_session(session_id)? else {
+            return Ok(Vec::new());
+        };
+
+        // 先订阅再查询，避免查询与订阅之间写入的消息被漏掉
+        let subscriber = self.seq_index_tree.watch_prefix(partition.as_bytes());
+        let messages = self.get_messages_since(session_id, since_seq)?;
+        if !messages.is_empty() || timeout.is_zero() {
+            return Ok(messages);
+        }
+        let _ = tokio::time::timeout(timeout, subscriber).await;
+        self.get_messages_since(session_id, since_seq)
+    }
+
+    // 保存消息标签并更新标签索引
+    fn save_message_tags(&self, message_id: &str, tags: &[String]) -> Result<()> {
+        self.message_tags_tree
+            .insert(message_id.as_bytes(), serde_json::to_vec(tags)?)?;
+        for tag in tags {
+            let index_key = format!("{}:{}", tag, message_id);
+            self.tag_index_tree.insert(index_key.as_bytes(), &[])?;
+        }
         Ok(())
     }
 
+    // 删除消息标签及其索引
+    fn remove_message_tags(&self, message_id: &str) -> Result<()> {
+        for tag in self.get_message_tags(message_id)? {
+            let index_key = format!("{}:{}", tag, message_id);
+            self.tag_index_tree.remove(index_key.as_bytes())?;
+        }
+        self.message_tags_tree.remove(message_id.as_bytes())?;
+        Ok(())
+    }
+
+    /// 获取消息的自动标签
+    pub fn get_message_tags(&self, message_id: &str) -> Result<Vec<String>> {
+        match self.message_tags_tree.get(message_id.as_bytes())? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// 按标签筛选消息，按时间倒序返回；指定 kefu_id 时只返回该客服参与的消息
+    pub fn get_messages_by_tag(&self, tag: &str, kefu_id: Option<&str>) -> Result<Vec<ChatMessage>> {
+        let mut messages = Vec::new();
+        for message_id in self.get_message_ids_by_tag(tag)? {
+            if let Some(data) = self.messages_tree.get(message_id.as_bytes())? {
+                if let Ok(message) = self.decode_message(&data) {
+                    let involved = kefu_id
+                        .is_none_or(|kefu_id| message.from == kefu_id || message.to.as_deref() == Some(kefu_id));
+                    if involved {
+                        messages.push(message);
+                    }
+                }
+            }
+        }
+        messages.sort_by_key(|m| std::cmp::Reverse(m.timestamp));
+        Ok(messages)
+    }
+
+    /// 按标签筛选消息ID
+    pub fn get_message_ids_by_tag(&self, tag: &str) -> Result<Vec<String>> {
+        let prefix = format!("{}:", tag);
+        let mut message_ids = Vec::new();
+        for entry in self.tag_index_tree.scan_prefix(prefix.as_bytes()) {
+            let (key, _) = entry?;
+            let key = String::from_utf8(key.to_vec())?;
+            message_ids.push(key[prefix.len()..].to_string());
+        }
+        Ok(message_ids)
+    }
+
     // 更新用户消息索引
     fn update_user_message_index(&self, user1: &str, user2: &str, message_id: &str) -> Result<()> {
         let key = format!("{}:{}", user1, user2);
@@ -135,12 +834,20 @@ impl LocalStorage {
 
         message_ids.push(message_id.to_string());
 
-        // 保持最多1000条消息ID
-        if message_ids.len() > 1000 {
-            // 删除旧消息
-            let old_ids = message_ids.split_off(1000);
-            for old_id in old_ids {
-                let _ = self.messages_tree.remove(old_id.as_bytes());
+        // 保持最多1000条消息ID，超出时从最早的消息开始清理
+        if message_ids.len() > MAX_MESSAGES_PER_CONVERSATION {
+            let excess = message_ids.len() - MAX_MESSAGES_PER_CONVERSATION;
+            let partition = session_partition(user1, user2);
+            for old_id in message_ids.drain(..excess) {
+                if let Ok(Some(data)) = self.messages_tree.remove(old_id.as_bytes()) {
+                    if let Ok(message) = serde_json::from_slice::<ChatMessage>(&data) {
+                        let partition_key = session_message_key(&partition, &message, &old_id);
+                        let _ = self.session_messages_tree.remove(partition_key.as_bytes());
+                        if let Some(seq) = message.seq {
+                            let _ = self.seq_index_tree.remove(session_seq_key(&partition, seq).as_bytes());
+                        }
+                    }
+                }
             }
         }
 
@@ -150,34 +857,121 @@ impl LocalStorage {
         Ok(())
     }
 
-    // 获取两个用户之间的消息历史
+    // 获取两个用户之间的消息历史：只扫描该会话的分区，结果已按时间排序
     pub fn get_messages(&self, user1: &str, user2: &str) -> Result<Vec<ChatMessage>> {
-        // 尝试两种键的组合
-        let key1 = format!("{}:{}", user1, user2);
-        let key2 = format!("{}:{}", user2, user1);
+        self.iter_messages(user1, user2).collect()
+    }
 
-        let message_ids: Vec<String> =
-            match self.user_messages_tree.get(key1.as_bytes())? { Some(data) => {
-                serde_json::from_slice(&data)?
-            } _ => { match self.user_messages_tree.get(key2.as_bytes())? { Some(data) => {
-                serde_json::from_slice(&data)?
-            } _ => {
-                return Ok(Vec::new());
-            }}}};
+    /// 逐条读取两个用户之间的消息历史，顺序与 get_messages 相同，导出大会话时不必整体载入内存
+    pub fn iter_messages(&self, user1: &str, user2: &str) -> impl Iterator<Item = Result<ChatMessage>> + Send + 'static {
+        let storage = self.clone();
+        self.session_messages_tree
+            .scan_prefix(session_partition(user1, user2).as_bytes())
+            .filter_map(move |entry| storage.visible_message(entry).transpose())
+    }
 
-        let mut messages = Vec::new();
+    // 解码会话分区中的一条消息，已删除的消息不再出现在会话记录中
+    fn visible_message(&self, entry: sled::Result<(sled::IVec, sled::IVec)>) -> Result<Option<ChatMessage>> {
+        let (_, data) = entry?;
+        let message = self.decode_message(&data)?;
+        let message_id = message.id.as_deref().unwrap_or_default();
+        if self.get_message_status(message_id)? == MessageState::Deleted {
+            return Ok(None);
+        }
+        Ok(Some(message))
+    }
+
+    /// 获取消息状态
+    pub fn get_message_status(&self, message_id: &str) -> Result<MessageState> {
+        match self.message_status_tree.get(message_id.as_bytes())? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(MessageState::Unread),
+        }
+    }
+
+    /// 处于某状态的消息数，未读数由消息总数推算
+    pub fn get_status_count(&self, state: MessageState) -> Result<u64> {
+        let read_count = |tree: &Tree, state: MessageState| -> Result<u64> {
+            Ok(match tree.get(state.as_str())? {
+                Some(data) => u64::from_be_bytes(data.as_ref().try_into()?),
+                None => 0,
+            })
+        };
+        match state {
+            MessageState::Unread => {
+                let marked = read_count(&self.status_counts_tree, MessageState::Read)?
+                    + read_count(&self.status_counts_tree, MessageState::Deleted)?;
+                Ok((self.messages_tree.len() as u64).saturating_sub(marked))
+            }
+            state => read_count(&self.status_counts_tree, state),
+        }
+    }
+
+    /// 批量更新消息状态：逐条校验后在一个事务中写入状态并维护计数，
+    /// 不存在或已删除的消息不会中断整批，按输入顺序返回逐条结果
+    pub fn batch_update_message_status(
+        &self,
+        message_ids: &[String],
+        status: MessageState,
+    ) -> Result<Vec<StatusUpdateResult>> {
+        let mut results = Vec::with_capacity(message_ids.len());
+        let mut changes: HashMap<&str, MessageState> = HashMap::new();
         for message_id in message_ids {
-            if let Some(data) = self.messages_tree.get(message_id.as_bytes())? {
-                if let Ok(message) = serde_json::from_slice::<ChatMessage>(&data) {
-                    messages.push(message);
+            let error = if !self.messages_tree.contains_key(message_id.as_bytes())? {
+                Some("消息不存在".to_string())
+            } else if changes.contains_key(message_id.as_str()) {
+                None
+            } else {
+                let previous = self.get_message_status(message_id)?;
+                if previous == MessageState::Deleted && status != MessageState::Deleted {
+                    Some("消息已删除".to_string())
+                } else {
+                    changes.insert(message_id.as_str(), previous);
+                    None
                 }
-            }
+            };
+            results.push(StatusUpdateResult {
+                message_id: message_id.clone(),
+                success: error.is_none(),
+                error,
+            });
         }
 
-        // 按时间戳排序
-        messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        let status_data = serde_json::to_vec(&status)?;
+        let mut batch = sled::Batch::default();
+        let mut deltas: HashMap<MessageState, i64> = HashMap::new();
+        for (message_id, previous) in &changes {
+            batch.insert(message_id.as_bytes(), status_data.clone());
+            if *previous != status {
+                *deltas.entry(*previous).or_insert(0) -= 1;
+                *deltas.entry(status).or_insert(0) += 1;
+            }
+        }
+        // 未读数由总数推算，只持久化已读/已删计数
+        deltas.remove(&MessageState::Unread);
+
+        (&self.message_status_tree, &self.status_counts_tree)
+            .transaction(|(status_tree, counts_tree)| {
+                status_tree.apply_batch(&batch)?;
+                for (state, delta) in &deltas {
+                    let current = match counts_tree.get(state.as_str())? {
+                        Some(data) => u64::from_be_bytes(data.as_ref().try_into().unwrap_or([0; 8])),
+                        None => 0,
+                    };
+                    let updated = (current as i64 + delta).max(0) as u64;
+                    counts_tree.insert(state.as_str(), &updated.to_be_bytes())?;
+                }
+                Ok::<_, ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| anyhow::anyhow!("批量更新消息状态失败: {:?}", e))?;
 
-        Ok(messages)
+        tracing::info!(
+            "📝 批量更新消息状态为 {:?}: 成功 {} 条, 失败 {} 条",
+            status,
+            results.iter().filter(|r| r.success).count(),
+            results.iter().filter(|r| !r.success).count()
+        );
+        Ok(results)
     }
 
     // 保存会话信息
@@ -200,8 +994,243 @@ impl LocalStorage {
         }}
     }
 
+    // 创建留言工单
+    pub fn create_ticket(&self, customer_id: &str, contact: &str, content: &str) -> Result<LeaveMessageTicket> {
+        let ticket = LeaveMessageTicket {
+            id: uuid::Uuid::new_v4().to_string(),
+            customer_id: customer_id.to_string(),
+            contact: contact.to_string(),
+            content: content.to_string(),
+            status: TicketStatus::Open,
+            created_at: Utc::now(),
+            resolved_by: None,
+            resolved_at: None,
+        };
+        self.tickets_tree.insert(ticket.id.as_bytes(), serde_json::to_vec(&ticket)?)?;
+        Ok(ticket)
+    }
+
+    // 按创建时间列出工单，可按状态过滤
+    pub fn list_tickets(&self, status: Option<TicketStatus>) -> Result<Vec<LeaveMessageTicket>> {
+        let mut tickets = Vec::new();
+        for result in self.tickets_tree.iter() {
+            let (_, value) = result?;
+            let ticket: LeaveMessageTicket = serde_json::from_slice(&value)?;
+            if status.is_none_or(|status| ticket.status == status) {
+                tickets.push(ticket);
+            }
+        }
+        tickets.sort_by_key(|ticket| ticket.created_at);
+        Ok(tickets)
+    }
+
+    // 按ID读取工单，不存在时返回 None
+    pub fn get_ticket(&self, ticket_id: &str) -> Result<Option<LeaveMessageTicket>> {
+        match self.tickets_tree.get(ticket_id.as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    // 客服处理完留言后关闭工单，工单不存在时返回 None
+    pub fn resolve_ticket(&self, ticket_id: &str, kefu_id: &str) -> Result<Option<LeaveMessageTicket>> {
+        let Some(data) = self.tickets_tree.get(ticket_id.as_bytes())? else {
+            return Ok(None);
+        };
+        let mut ticket: LeaveMessageTicket = serde_json::from_slice(&data)?;
+        ticket.status = TicketStatus::Resolved;
+        ticket.resolved_by = Some(kefu_id.to_string());
+        ticket.resolved_at = Some(Utc::now());
+        self.tickets_tree.insert(ticket_id.as_bytes(), serde_json::to_vec(&ticket)?)?;
+        Ok(Some(ticket))
+    }
+
+    // 全文搜索未删除的消息，按时间倒序返回命中的消息及高亮摘要。
+    // 最多检查 SEARCH_SCAN_LIMIT 条消息、返回 SEARCH_MAX_HITS 条命中
+    pub fn search_messages_with_snippets(&self, query: &MessageSearchQuery) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        if query.keyword.trim().is_empty() {
+            return Ok(hits);
+        }
+        let scope = query.kefu_id.as_deref().or(query.user_id.as_deref());
+        for message in self.messages_involving(scope).take(SEARCH_SCAN_LIMIT) {
+            let message = message?;
+            let involved = [query.kefu_id.as_deref(), query.user_id.as_deref()]
+                .into_iter()
+                .flatten()
+                .all(|user_id| message.from == user_id || message.to.as_deref() == Some(user_id));
+            let in_range = query.start_date.is_none_or(|start| message.timestamp >= start)
+                && query.end_date.is_none_or(|end| message.timestamp <= end);
+            let content_type = message.content_type.clone().unwrap_or(ContentType::Text);
+            let type_matches = query
+                .content_type
+                .as_deref()
+                .is_none_or(|expected| format!("{:?}", content_type).eq_ignore_ascii_case(expected));
+            if !(involved && in_range && type_matches) {
+                continue;
+            }
+            let Some((snippet, match_ranges)) = build_snippet(&message.content, &query.keyword, SNIPPET_CONTEXT_CHARS)
+            else {
+                continue;
+            };
+            hits.push(SearchHit { message, snippet, match_ranges });
+            if hits.len() >= SEARCH_MAX_HITS {
+                break;
+            }
+        }
+        hits.sort_by_key(|hit| std::cmp::Reverse(hit.message.timestamp));
+        Ok(hits)
+    }
+
+    /// 未删除的消息：指定 user_id 时只读该用户参与的会话分区，否则遍历全部消息。无法解码的消息跳过
+    fn messages_involving(&self, user_id: Option<&str>) -> Box<dyn Iterator<Item = Result<ChatMessage>> + Send> {
+        let skip_undecodable = |result: Result<ChatMessage>| match result {
+            Err(e) if e.downcast_ref::<sled::Error>().is_none() => {
+                tracing::warn!("⚠️ 跳过无法解码的消息: {}", e);
+                None
+            }
+            result => Some(result),
+        };
+        let Some(user_id) = user_id else {
+            let storage = self.clone();
+            return Box::new(
+                self.messages_tree
+                    .iter()
+                    .filter_map(move |entry| storage.visible_message(entry).transpose())
+                    .filter_map(skip_undecodable),
+            );
+        };
+
+        // 用户消息索引的键为 用户:对方，前缀扫描得到该用户的所有会话对方
+        let prefix = format!("{}:", user_id);
+        let storage = self.clone();
+        let user_id = user_id.to_string();
+        Box::new(
+            self.user_messages_tree
+                .scan_prefix(prefix.as_bytes())
+                .keys()
+                .flat_map(move |key| -> Box<dyn Iterator<Item = Result<ChatMessage>> + Send> {
+                    match key {
+                        Ok(key) => {
+                            let partner = String::from_utf8_lossy(&key[prefix.len()..]).to_string();
+                            Box::new(storage.iter_messages(&user_id, &partner))
+                        }
+                        Err(e) => Box::new(std::iter::once(Err(e.into()))),
+                    }
+                })
+                .filter_map(skip_undecodable),
+        )
+    }
+
+    // 当日已用的导出额度
+    fn export_usage(&self, quota_key: &str) -> Result<ExportUsage> {
+        Ok(self
+            .export_quota_tree
+            .get(quota_key.as_bytes())?
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default())
+    }
+
+    // 导出消息：扫描前先检查当日额度，扫描到超出剩余条数即停止；占用额度时再原子检查一次。
+    // 无论结果如何都写入审计
+    pub fn export_messages(&self, operator: &str, query: &ExportQuery) -> Result<MessageExport> {
+        let now = Utc::now();
+        let quota_key = format!("{}:{}", operator, now.format("%Y-%m-%d"));
+        let usage = self.export_usage(&quota_key)?;
+        if let Some(reason) = export_rejection(&self.export_limits, usage, 0) {
+            return self.audit_export(operator, query, Vec::new(), Some(reason), now);
+        }
+        // 多读一条用于判断超限，超限的导出不必读完全部消息
+        let remaining = match self.export_limits.max_messages_per_day {
+            0 => usize::MAX,
+            max => max.saturating_sub(usage.messages) as usize,
+        };
+
+        // 客服只能导出本人参与的会话，再按 user_id 过滤
+        let scope = query.kefu_id.as_deref().or(query.user_id.as_deref());
+        let mut messages = Vec::new();
+        for message in self.messages_involving(scope) {
+            let message = message?;
+            let involved = [query.kefu_id.as_deref(), query.user_id.as_deref()]
+                .into_iter()
+                .flatten()
+                .all(|user_id| message.from == user_id || message.to.as_deref() == Some(user_id));
+            let in_range = query.start_date.is_none_or(|start| message.timestamp >= start)
+                && query.end_date.is_none_or(|end| message.timestamp <= end);
+            if involved && in_range {
+                messages.push(message);
+                if messages.len() > remaining {
+                    break;
+                }
+            }
+        }
+        messages.sort_by_key(|message| message.timestamp);
+
+        let mut rejection = None;
+        self.export_quota_tree.fetch_and_update(quota_key.as_bytes(), |old| {
+            let usage: ExportUsage = old.and_then(|data| serde_json::from_slice(data).ok()).unwrap_or_default();
+            rejection = export_rejection(&self.export_limits, usage, messages.len());
+            if rejection.is_some() {
+                return old.map(<[u8]>::to_vec);
+            }
+            serde_json::to_vec(&ExportUsage {
+                exports: usage.exports + 1,
+                messages: usage.messages + messages.len() as u64,
+            })
+            .ok()
+        })?;
+        self.audit_export(operator, query, messages, rejection, now)
+    }
+
+    // 写入导出审计：有拒绝原因时返回 Rejected，被拒绝的条数为已读到的条数
+    fn audit_export(
+        &self,
+        operator: &str,
+        query: &ExportQuery,
+        messages: Vec<ChatMessage>,
+        rejection: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Result<MessageExport> {
+        let audit = ExportAuditEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            operator: operator.to_string(),
+            user_id: query.user_id.clone(),
+            start_date: query.start_date,
+            end_date: query.end_date,
+            format: query.format.clone(),
+            message_count: messages.len(),
+            allowed: rejection.is_none(),
+            reason: rejection,
+            timestamp: now,
+        };
+        self.export_audit_tree
+            .insert(self.db.generate_id()?.to_be_bytes(), serde_json::to_vec(&audit)?)?;
+
+        if let Some(reason) = &audit.reason {
+            tracing::warn!("📤 消息导出被拒绝: 导出人={}, 原因={}", operator, reason);
+            return Ok(MessageExport::Rejected(audit));
+        }
+        tracing::info!(
+            "📤 消息导出: 导出人={}, 用户={:?}, 条数={}, 格式={}",
+            operator,
+            audit.user_id,
+            audit.message_count,
+            audit.format
+        );
+        Ok(MessageExport::Completed { audit, messages })
+    }
+
+    // 导出审计记录，最新的在前
+    pub fn list_export_audit(&self, limit: usize) -> Result<Vec<ExportAuditEntry>> {
+        let mut entries = Vec::new();
+        for result in self.export_audit_tree.iter().rev().take(limit) {
+            let (_, value) = result?;
+            entries.push(serde_json::from_slice(&value)?);
+        }
+        Ok(entries)
+    }
+
     // 企业级会话创建功能
-    #[allow(dead_code)] // 企业级功能：用于复杂的会话管理和客户关系追踪
     pub fn create_session(&self, kefu_id: &str, kehu_id: &str) -> Result<Session> {
         let session_id = format!("session_{}_{}", kefu_id, Utc::now().timestamp_millis());
 
@@ -220,12 +1249,28 @@ impl LocalStorage {
             last_activity: Utc::now(),
             messages: Vec::new(),
             kehu_zhanghao,
+            summary: None,
         };
 
         self.save_session(&session)?;
         Ok(session)
     }
 
+    // 会话结束后存档摘要，会话记录不重复保存消息正文
+    pub fn archive_session_summary(
+        &self,
+        kefu_id: &str,
+        kehu_id: &str,
+        created_at: DateTime<Utc>,
+        summary: SessionSummary,
+    ) -> Result<Session> {
+        let mut session = self.create_session(kefu_id, kehu_id)?;
+        session.created_at = created_at;
+        session.summary = Some(summary);
+        self.save_session(&session)?;
+        Ok(session)
+    }
+
     // 更新会话活动时间
     #[allow(dead_code)]
     pub fn update_session_activity(&self, session_id: &str) -> Result<()> {
@@ -237,7 +1282,6 @@ impl LocalStorage {
     }
 
     // 获取用户的所有会话
-    #[allow(dead_code)]
     pub fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>> {
         let mut sessions = Vec::new();
 
@@ -298,6 +1342,56 @@ impl LocalStorage {
         Ok(messages)
     }
 
+    /// 向前翻页：返回游标之前（不含）的最后 limit 条消息，before 为空时从最新消息开始
+    pub fn get_messages_before(
+        &self,
+        user_a: &str,
+        user_b: &str,
+        before: Option<&HistoryCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage> {
+        // 先读序号再读消息，读取期间写入的消息序号大于 latest_seq，客户端仍会从实时消息收到
+        let latest_seq = self.latest_session_seq(user_a, user_b)?;
+        let partition = session_partition(user_a, user_b);
+        let entries = match before {
+            Some(cursor) => {
+                let upper = session_key_at(&partition, cursor.timestamp, &cursor.message_id);
+                self.session_messages_tree.range(partition.as_bytes()..upper.as_bytes())
+            }
+            None => self.session_messages_tree.scan_prefix(partition.as_bytes()),
+        };
+
+        // 从游标处倒序读取，多读一条用于判断 has_more
+        let mut messages = entries
+            .rev()
+            .filter_map(|entry| self.visible_message(entry).transpose())
+            .take(limit.saturating_add(1))
+            .collect::<Result<Vec<_>>>()?;
+        let has_more = messages.len() > limit;
+        messages.truncate(limit);
+        messages.reverse();
+
+        Ok(HistoryPage { messages, has_more, latest_seq })
+    }
+
+    /// 重连补齐：返回序号大于 since_seq 的前 limit 条消息，按序号升序；
+    /// 此时 has_more 表示更新的消息还有剩余，客户端用本页最后一条的序号继续请求
+    pub fn get_history_since(
+        &self,
+        user_a: &str,
+        user_b: &str,
+        since_seq: u64,
+        limit: usize,
+    ) -> Result<HistoryPage> {
+        let latest_seq = self.latest_session_seq(user_a, user_b)?;
+        // 多读一条用于判断 has_more
+        let mut messages =
+            self.get_messages_since_limited(&conversation_id(user_a, user_b), since_seq, limit.saturating_add(1))?;
+        let has_more = messages.len() > limit;
+        messages.truncate(limit);
+        Ok(HistoryPage { messages, has_more, latest_seq })
+    }
+
     // 企业级账号查找功能
     #[allow(dead_code)] // 企业级功能：用于客户账号关联和历史查询
     pub fn get_session_by_zhanghao(&self, zhanghao: &str) -> Result<Option<Session>> {
@@ -342,6 +1436,7 @@ impl LocalStorage {
 
         for key in keys_to_delete {
             self.messages_tree.remove(&key)?;
+            self.remove_message_tags(&String::from_utf8_lossy(&key))?;
             deleted_count += 1;
         }
 
@@ -407,3 +1502,615 @@ pub struct OptimizationResult {
     pub initial_size_bytes: u64,
     pub final_size_bytes: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_storage() -> (LocalStorage, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("kefu_storage_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        (LocalStorage::new(dir.to_str().unwrap()).unwrap(), dir)
+    }
+
+    fn chat_message(id: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            id: Some(id.to_string()),
+            from: "kehu_001".to_string(),
+            to: Some("kefu_001".to_string()),
+            content: content.to_string(),
+            content_type: None,
+            filename: None,
+            timestamp: Utc::now(),
+            url: None,
+            thumbnail_url: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn test_refund_message_auto_tagged() {
+        let (storage, dir) = temp_storage();
+
+        storage.save_message(&chat_message("msg_refund", "这个商品我要退款")).unwrap();
+        storage.save_message(&chat_message("msg_hello", "你好")).unwrap();
+
+        assert_eq!(storage.get_message_tags("msg_refund").unwrap(), vec!["refund"]);
+        assert!(storage.get_message_tags("msg_hello").unwrap().is_empty());
+        assert_eq!(storage.get_message_ids_by_tag("refund").unwrap(), vec!["msg_refund"]);
+        let tagged = storage.get_messages_by_tag("refund", None).unwrap();
+        assert_eq!(tagged.len(), 1);
+        // 客服只能看到本人参与的会话中的标签消息
+        assert_eq!(storage.get_messages_by_tag("refund", Some("kefu_001")).unwrap().len(), 1);
+        assert!(storage.get_messages_by_tag("refund", Some("kefu_002")).unwrap().is_empty());
+
+        // 打标不影响消息内容
+        let messages = storage.get_messages("kehu_001", "kefu_001").unwrap();
+        assert!(messages.iter().any(|m| m.content == "这个商品我要退款"));
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_double_submit_is_deduplicated() {
+        let (storage, dir) = temp_storage();
+
+        let first = storage.save_message(&chat_message("msg_retry", "请问发货了吗")).unwrap();
+        assert!(!first.is_duplicate());
+
+        // 客户端网络抖动后用同一ID重发，内容或时间戳可能不同
+        let mut retry = chat_message("msg_retry", "请问发货了吗？");
+        retry.timestamp = Utc::now() + chrono::Duration::seconds(5);
+        let second = storage.save_message(&retry).unwrap();
+        assert!(second.is_duplicate());
+
+        let original = second.into_message();
+        assert_eq!(original.content, "请问发货了吗");
+        assert_eq!(original.timestamp, first.into_message().timestamp);
+
+        let messages = storage.get_messages("kehu_001", "kefu_001").unwrap();
+        assert_eq!(messages.iter().filter(|m| m.id.as_deref() == Some("msg_retry")).count(), 1);
+
+        // 其他发送者碰巧（或故意）使用同一ID：不能拿到原消息，改用服务端ID另存
+        let mut other = chat_message("msg_retry", "别人的消息");
+        other.from = "kehu_002".to_string();
+        let saved = storage.save_message(&other).unwrap();
+        assert!(!saved.is_duplicate());
+        let stored = saved.into_message();
+        assert_eq!(stored.content, "别人的消息");
+        assert_ne!(stored.id.as_deref(), Some("msg_retry"));
+        assert_eq!(storage.get_messages("kehu_002", "kefu_001").unwrap().len(), 1);
+
+        // 该发送者重试时命中自己的消息
+        let retried = storage.save_message(&other).unwrap();
+        assert!(retried.is_duplicate());
+        assert_eq!(retried.into_message().id, stored.id);
+        let original = storage.save_message(&chat_message("msg_retry", "请问发货了吗")).unwrap();
+        assert_eq!(original.into_message().content, "请问发货了吗");
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_batch_mark_read_keeps_counts() {
+        let (storage, dir) = temp_storage();
+        let mut ids: Vec<String> = (0..100).map(|i| format!("msg_{}", i)).collect();
+        for id in &ids {
+            storage.save_message(&chat_message(id, "你好")).unwrap();
+        }
+        ids.push("msg_missing".to_string());
+
+        let results = storage.batch_update_message_status(&ids, MessageState::Read).unwrap();
+        assert_eq!(results.len(), 101);
+        assert_eq!(results.iter().filter(|r| r.success).count(), 100);
+        let missing = results.last().unwrap();
+        assert!(!missing.success);
+        assert_eq!(missing.error.as_deref(), Some("消息不存在"));
+        assert_eq!(storage.get_status_count(MessageState::Read).unwrap(), 100);
+        assert_eq!(storage.get_status_count(MessageState::Unread).unwrap(), 0);
+
+        // 重复标记不重复计数
+        storage.batch_update_message_status(&ids[..10], MessageState::Read).unwrap();
+        assert_eq!(storage.get_status_count(MessageState::Read).unwrap(), 100);
+
+        let deleted = storage.batch_update_message_status(&ids[..10], MessageState::Deleted).unwrap();
+        assert!(deleted.iter().all(|r| r.success));
+        assert_eq!(storage.get_status_count(MessageState::Read).unwrap(), 90);
+        assert_eq!(storage.get_status_count(MessageState::Deleted).unwrap(), 10);
+        assert_eq!(storage.get_messages("kehu_001", "kefu_001").unwrap().len(), 90);
+
+        // 已删除的消息不能再标记为已读
+        let results = storage
+            .batch_update_message_status(&["msg_0".to_string(), "msg_50".to_string()], MessageState::Read)
+            .unwrap();
+        assert_eq!(results[0].error.as_deref(), Some("消息已删除"));
+        assert!(results[1].success);
+        assert_eq!(storage.get_message_status("msg_0").unwrap(), MessageState::Deleted);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_history_pages_backwards_by_timestamp() {
+        let (storage, dir) = temp_storage();
+        let start = Utc::now() - chrono::Duration::hours(1);
+        for i in 0..5 {
+            let mut message = chat_message(&format!("msg_{}", i), &format!("第{}条", i));
+            message.timestamp = start + chrono::Duration::minutes(i);
+            storage.save_message(&message).unwrap();
+        }
+
+        let latest = storage.get_messages_before("kefu_001", "kehu_001", None, 2).unwrap();
+        assert_eq!(latest.messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["第3条", "第4条"]);
+        assert!(latest.has_more);
+
+        // 以本页最早一条的时间作为游标继续向前翻
+        let cursor = HistoryCursor { timestamp: latest.messages[0].timestamp, message_id: String::new() };
+        let older = storage.get_messages_before("kefu_001", "kehu_001", Some(&cursor), 2).unwrap();
+        assert_eq!(older.messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["第1条", "第2条"]);
+        assert!(older.has_more);
+
+        let cursor = HistoryCursor { timestamp: older.messages[0].timestamp, message_id: String::new() };
+        let oldest = storage.get_messages_before("kefu_001", "kehu_001", Some(&cursor), 2).unwrap();
+        assert_eq!(oldest.messages.len(), 1);
+        assert!(!oldest.has_more);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_history_cursor_splits_messages_in_same_millisecond() {
+        let (storage, dir) = temp_storage();
+        let timestamp = Utc::now() - chrono::Duration::minutes(5);
+        for i in 0..5 {
+            let mut message = chat_message(&format!("msg_{}", i), &format!("第{}条", i));
+            message.timestamp = timestamp;
+            storage.save_message(&message).unwrap();
+        }
+
+        // 同一毫秒的消息逐页翻完，既不遗漏也不重复
+        let mut seen = Vec::new();
+        let mut cursor: Option<HistoryCursor> = None;
+        loop {
+            let page = storage.get_messages_before("kefu_001", "kehu_001", cursor.as_ref(), 2).unwrap();
+            let first = page.messages.first().cloned();
+            seen.splice(0..0, page.messages.into_iter().map(|m| m.content));
+            if !page.has_more {
+                break;
+            }
+            let first = first.unwrap();
+            cursor = Some(HistoryCursor { timestamp: first.timestamp, message_id: first.id.unwrap() });
+        }
+        assert_eq!(seen, ["第0条", "第1条", "第2条", "第3条", "第4条"]);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_unflushed_messages_recovered_from_wal_after_crash() {
+        let (storage, dir) = temp_storage();
+        storage.save_message(&chat_message("msg_flushed", "已落盘")).unwrap();
+        storage.checkpoint_wal().unwrap();
+        storage.save_message(&chat_message("msg_saved", "已写入")).unwrap();
+
+        // 模拟崩溃：消息已写入WAL，但进程在写正式存储前退出，最后一条记录只写了一半
+        {
+            let mut wal = storage.wal.lock();
+            wal.append(&SealedMessage::plain(chat_message("msg_lost", "崩溃前未落盘"))).unwrap();
+        }
+        let wal_path = dir.canonicalize().unwrap().join("wal").join("messages.wal");
+        let mut file = std::fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+        std::io::Write::write_all(&mut file, br#"{"id":"msg_torn","from":"#).unwrap();
+        drop(file);
+        assert!(storage.get_messages("kehu_001", "kefu_001").unwrap().iter().all(|m| m.content != "崩溃前未落盘"));
+        drop(storage);
+
+        let recovered = LocalStorage::new(dir.to_str().unwrap()).unwrap();
+        let messages = recovered.get_messages("kehu_001", "kefu_001").unwrap();
+        let mut ids: Vec<_> = messages.iter().filter_map(|m| m.id.as_deref()).collect();
+        ids.sort();
+        assert_eq!(ids, ["msg_flushed", "msg_lost", "msg_saved"]);
+        assert!(recovered.wal.read_entries().unwrap().is_empty());
+
+        drop(recovered);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_leave_message_recorded_as_open_ticket() {
+        let (storage, dir) = temp_storage();
+
+        let ticket = storage.create_ticket("kehu_001", "13800000000", "订单一直没发货").unwrap();
+        assert_eq!(ticket.status, TicketStatus::Open);
+        let open = storage.list_tickets(Some(TicketStatus::Open)).unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].contact, "13800000000");
+        assert_eq!(open[0].content, "订单一直没发货");
+
+        let resolved = storage.resolve_ticket(&ticket.id, "kefu_001").unwrap().unwrap();
+        assert_eq!(resolved.resolved_by.as_deref(), Some("kefu_001"));
+        assert!(storage.list_tickets(Some(TicketStatus::Open)).unwrap().is_empty());
+        assert_eq!(storage.list_tickets(None).unwrap().len(), 1);
+        assert!(storage.resolve_ticket("missing", "kefu_001").unwrap().is_none());
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_session_read_only_touches_its_partition() {
+        let (storage, dir) = temp_storage();
+        storage.save_message(&chat_message("msg_a1", "会话A第一条")).unwrap();
+        let mut other = chat_message("msg_b1", "会话B");
+        other.from = "kehu_002".to_string();
+        storage.save_message(&other).unwrap();
+        let mut reply = chat_message("msg_a2", "会话A回复");
+        reply.from = "kefu_001".to_string();
+        reply.to = Some("kehu_001".to_string());
+        reply.timestamp += chrono::Duration::seconds(1);
+        storage.save_message(&reply).unwrap();
+
+        // 损坏会话B的分区数据：读取会话A不受影响，说明没有扫描其他会话
+        storage
+            .session_messages_tree
+            .insert(format!("{}garbage", session_partition("kehu_002", "kefu_001")).as_bytes(), &b"not json"[..])
+            .unwrap();
+        let messages = storage.get_messages("kefu_001", "kehu_001").unwrap();
+        assert_eq!(messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["会话A第一条", "会话A回复"]);
+        assert!(storage.get_messages("kehu_002", "kefu_001").is_err());
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_conversation_keeps_newest_messages_beyond_limit() {
+        let (storage, dir) = temp_storage();
+        let total = MAX_MESSAGES_PER_CONVERSATION + 5;
+        let start = Utc::now();
+        for i in 1..=total {
+            let mut message = chat_message(&format!("msg_{}", i), &format!("第{}条", i));
+            message.timestamp = start + chrono::Duration::milliseconds(i as i64);
+            storage.save_message(&message).unwrap();
+        }
+
+        // 超出保留条数时清理最早的消息，最新的消息仍可读取
+        let messages = storage.get_messages("kehu_001", "kefu_001").unwrap();
+        assert_eq!(messages.len(), MAX_MESSAGES_PER_CONVERSATION);
+        assert_eq!(messages.first().unwrap().id.as_deref(), Some("msg_6"));
+        assert_eq!(messages.last().unwrap().id.as_deref(), Some(format!("msg_{}", total).as_str()));
+        assert!(storage.get_message("msg_1").unwrap().is_none());
+        assert!(storage.get_message(&format!("msg_{}", total)).unwrap().is_some());
+
+        // 增量同步能取到最新消息，被清理的消息不再占用序号索引
+        let session_id = conversation_id("kehu_001", "kefu_001");
+        let since = storage.get_messages_since(&session_id, total as u64 - 1).unwrap();
+        assert_eq!(since.iter().map(|m| m.seq).collect::<Vec<_>>(), [Some(total as u64)]);
+        assert_eq!(storage.get_messages_since(&session_id, 0).unwrap().len(), MAX_MESSAGES_PER_CONVERSATION);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_messages_since_returns_only_later_seq() {
+        let (storage, dir) = temp_storage();
+        for i in 1..=4 {
+            let mut message = chat_message(&format!("msg_{}", i), &format!("第{}条", i));
+            if i % 2 == 0 {
+                message.from = "kefu_001".to_string();
+                message.to = Some("kehu_001".to_string());
+            }
+            let saved = storage.save_message(&message).unwrap().into_message();
+            assert_eq!(saved.seq, Some(i));
+        }
+        // 其他会话的序号独立计数，不会出现在本会话的增量中
+        let mut other = chat_message("msg_other", "其他会话");
+        other.from = "kehu_002".to_string();
+        assert_eq!(storage.save_message(&other).unwrap().into_message().seq, Some(1));
+        // 重复提交不占用新序号
+        assert_eq!(storage.save_message(&chat_message("msg_3", "重试")).unwrap().into_message().seq, Some(3));
+
+        let session_id = conversation_id("kehu_001", "kefu_001");
+        assert_eq!(session_id, "kefu_001:kehu_001");
+        let since_two = storage.get_messages_since(&session_id, 2).unwrap();
+        assert_eq!(since_two.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["第3条", "第4条"]);
+        assert_eq!(since_two.iter().map(|m| m.seq).collect::<Vec<_>>(), [Some(3), Some(4)]);
+        assert_eq!(storage.get_messages_since(&session_id, 0).unwrap().len(), 4);
+        assert!(storage.get_messages_since(&session_id, 4).unwrap().is_empty());
+        assert!(storage.get_messages_since("unknown", 0).unwrap().is_empty());
+        assert_eq!(
+            storage.session_participants(&session_id).unwrap(),
+            Some(("kefu_001".to_string(), "kehu_001".to_string()))
+        );
+        assert!(storage.session_participants("unknown").unwrap().is_none());
+
+        // 已保存的会话记录也可以作为会话ID
+        let session = storage.create_session("kefu_001", "kehu_001").unwrap();
+        assert_eq!(storage.get_messages_since(&session.session_id, 3).unwrap().len(), 1);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_internal_messages_kept_out_of_customer_history() {
+        let (storage, dir) = temp_storage();
+        storage.save_message(&chat_message("msg_1", "客户消息")).unwrap();
+        for i in 0..3 {
+            let mut internal = chat_message(&format!("internal_{}", i), &format!("内部{}", i));
+            internal.from = "kefu_002".to_string();
+            internal.timestamp = Utc::now() + chrono::Duration::seconds(i);
+            storage.save_internal_message(&internal).unwrap();
+        }
+
+        let internal = storage.get_internal_messages("kefu_001", "kefu_002", 2).unwrap();
+        assert_eq!(internal.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["内部1", "内部2"]);
+        assert!(storage.get_internal_messages("kefu_001", "kehu_001", 10).unwrap().is_empty());
+
+        // 内部消息不进入客服与任何人的会话历史，也不占用会话序号
+        assert!(storage.get_messages("kefu_001", "kefu_002").unwrap().is_empty());
+        assert!(storage.get_message("internal_0").unwrap().is_none());
+        assert_eq!(storage.get_messages_before("kefu_001", "kehu_001", None, 10).unwrap().messages.len(), 1);
+        assert_eq!(storage.latest_session_seq("kefu_001", "kefu_002").unwrap(), 0);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_history_reports_latest_seq_for_reconnect() {
+        let (storage, dir) = temp_storage();
+        assert_eq!(storage.latest_session_seq("kefu_001", "kehu_001").unwrap(), 0);
+        for i in 1..=5 {
+            storage.save_message(&chat_message(&format!("msg_{}", i), &format!("第{}条", i))).unwrap();
+        }
+
+        let page = storage.get_messages_before("kefu_001", "kehu_001", None, 2).unwrap();
+        assert_eq!(page.latest_seq, 5);
+        assert_eq!(storage.latest_session_seq("kehu_001", "kefu_001").unwrap(), 5);
+
+        // 客户端已有到序号2的消息，重连后只补齐之后的部分
+        let missing = storage.get_history_since("kefu_001", "kehu_001", 2, 2).unwrap();
+        assert_eq!(missing.messages.iter().map(|m| m.seq).collect::<Vec<_>>(), [Some(3), Some(4)]);
+        assert!(missing.has_more);
+        assert_eq!(missing.latest_seq, 5);
+        let rest = storage.get_history_since("kefu_001", "kehu_001", 4, 2).unwrap();
+        assert_eq!(rest.messages.len(), 1);
+        assert!(!rest.has_more);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_long_poll_returns_new_message() {
+        let (storage, dir) = temp_storage();
+        let storage = Arc::new(storage);
+        storage.save_message(&chat_message("msg_1", "第一条")).unwrap();
+        let session_id = conversation_id("kehu_001", "kefu_001");
+
+        let waiting = {
+            let storage = storage.clone();
+            let session_id = session_id.clone();
+            tokio::spawn(async move {
+                storage
+                    .wait_for_messages_since(&session_id, 1, std::time::Duration::from_secs(5))
+                    .await
+                    .unwrap()
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        storage.save_message(&chat_message("msg_2", "第二条")).unwrap();
+
+        let messages = waiting.await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "第二条");
+
+        // 没有新消息时超时返回空
+        let empty = storage
+            .wait_for_messages_since(&session_id, 2, std::time::Duration::from_millis(20))
+            .await
+            .unwrap();
+        assert!(empty.is_empty());
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_legacy_messages_migrated_into_partitions() {
+        let (storage, dir) = temp_storage();
+        // 模拟旧版本数据：只有消息表和用户索引
+        let legacy = chat_message("msg_legacy", "旧版本消息");
+        storage.messages_tree.insert("msg_legacy", serde_json::to_vec(&legacy).unwrap()).unwrap();
+        storage.update_user_message_index("kehu_001", "kefu_001", "msg_legacy").unwrap();
+        storage.update_user_message_index("kefu_001", "kehu_001", "msg_legacy").unwrap();
+        storage.db.remove(SESSION_PARTITION_MIGRATED_KEY).unwrap();
+        assert!(storage.get_messages("kehu_001", "kefu_001").unwrap().is_empty());
+
+        assert_eq!(storage.migrate_to_session_partitions().unwrap(), 1);
+        let messages = storage.get_messages("kefu_001", "kehu_001").unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "旧版本消息");
+        // 迁移只执行一次
+        assert_eq!(storage.migrate_to_session_partitions().unwrap(), 0);
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_export_over_daily_limit_rejected_and_audited() {
+        let (storage, dir) = temp_storage();
+        let storage = storage.with_export_limits(ExportLimitConfig { max_exports_per_day: 2, max_messages_per_day: 3 });
+        storage.save_message(&chat_message("msg_1", "你好")).unwrap();
+        storage.save_message(&chat_message("msg_2", "我的订单号是123")).unwrap();
+        let query = ExportQuery {
+            user_id: Some("kehu_001".to_string()),
+            format: "json".to_string(),
+            ..ExportQuery::default()
+        };
+
+        let MessageExport::Completed { audit, messages } = storage.export_messages("kefu_001", &query).unwrap() else {
+            panic!("首次导出应在限额内");
+        };
+        assert_eq!(messages.len(), 2);
+        assert_eq!(audit.message_count, 2);
+
+        // 再导出2条会超过每日3条的上限
+        let MessageExport::Rejected(rejected) = storage.export_messages("kefu_001", &query).unwrap() else {
+            panic!("超过每日条数上限应被拒绝");
+        };
+        assert!(!rejected.allowed);
+        assert!(rejected.reason.unwrap().contains("3条"));
+
+        // 次数上限：第2次成功后第3次被拒
+        let other_user = ExportQuery { user_id: Some("kehu_002".to_string()), ..query.clone() };
+        assert!(matches!(storage.export_messages("kefu_001", &other_user).unwrap(), MessageExport::Completed { .. }));
+        assert!(matches!(storage.export_messages("kefu_001", &other_user).unwrap(), MessageExport::Rejected(_)));
+        // 额度按导出人独立计算
+        assert!(matches!(storage.export_messages("kefu_002", &query).unwrap(), MessageExport::Completed { .. }));
+
+        // 每次导出都有审计，最新的在前
+        let audit = storage.list_export_audit(10).unwrap();
+        assert_eq!(audit.len(), 5);
+        assert_eq!(audit[0].operator, "kefu_002");
+        assert_eq!(audit[0].user_id.as_deref(), Some("kehu_001"));
+        assert_eq!(audit[0].message_count, 2);
+        assert_eq!(audit.iter().filter(|entry| !entry.allowed).count(), 2);
+        assert_eq!(audit.iter().filter(|entry| entry.operator == "kefu_001").count(), 4);
+
+        // 次数已用完时扫描前即拒绝
+        let MessageExport::Rejected(rejected) = storage.export_messages("kefu_001", &query).unwrap() else {
+            panic!("次数用完应被拒绝");
+        };
+        assert_eq!(rejected.message_count, 0);
+
+        // 客服只能导出本人参与的会话
+        let mut other = chat_message("msg_3", "别的客服的会话");
+        other.to = Some("kefu_009".to_string());
+        storage.save_message(&other).unwrap();
+        let scoped = ExportQuery { kefu_id: Some("kefu_003".to_string()), ..query.clone() };
+        let MessageExport::Completed { messages, .. } = storage.export_messages("kefu_003", &scoped).unwrap() else {
+            panic!("限额内的导出应完成");
+        };
+        assert!(messages.is_empty());
+        let scoped = ExportQuery { kefu_id: Some("kefu_009".to_string()), user_id: None, ..query.clone() };
+        let MessageExport::Completed { messages, .. } = storage.export_messages("kefu_009", &scoped).unwrap() else {
+            panic!("限额内的导出应完成");
+        };
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id.as_deref(), Some("msg_3"));
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_encrypted_at_rest_and_transparently_decrypted() {
+        let (storage, dir) = temp_storage();
+        // 开启加密前写入的明文消息
+        storage.save_message(&chat_message("msg_plain", "开启加密前的消息")).unwrap();
+
+        let storage = storage.with_message_cipher(MessageCipher::new(&[3u8; 32]));
+        let mut message = chat_message("msg_secret", "病历号 A12345");
+        message.filename = Some("体检报告.pdf".to_string());
+        let saved = storage.save_message(&message).unwrap().into_message();
+        assert_eq!(saved.content, "病历号 A12345");
+
+        // 落盘的 content 和 filename 为密文，ID 和时间戳保持明文
+        let raw = storage.messages_tree.get("msg_secret").unwrap().unwrap();
+        let raw: SealedMessage = serde_json::from_slice(&raw).unwrap();
+        assert!(raw.encrypted.is_some());
+        let raw = raw.message;
+        assert!(!raw.content.contains("A12345"));
+        assert!(!raw.filename.as_deref().unwrap().contains("体检报告"));
+        assert_eq!(raw.id.as_deref(), Some("msg_secret"));
+        assert_eq!(raw.timestamp, message.timestamp);
+        let partition = session_partition("kehu_001", "kefu_001");
+        assert!(storage
+            .session_messages_tree
+            .scan_prefix(partition.as_bytes())
+            .values()
+            .all(|data| !String::from_utf8_lossy(&data.unwrap()).contains("A12345")));
+
+        // 读取时透明解密，旧的明文消息照常读取
+        let recent = storage.get_recent_messages("kehu_001", "kefu_001", 10).unwrap();
+        let contents: Vec<&str> = recent.iter().map(|m| m.content.as_str()).collect();
+        assert!(contents.contains(&"开启加密前的消息"));
+        assert!(contents.contains(&"病历号 A12345"));
+        let secret = recent.iter().find(|m| m.id.as_deref() == Some("msg_secret")).unwrap();
+        assert_eq!(secret.filename.as_deref(), Some("体检报告.pdf"));
+
+        // 重复提交返回解密后的原消息
+        let duplicate = storage.save_message(&message).unwrap();
+        assert!(duplicate.is_duplicate());
+        assert_eq!(duplicate.into_message().content, "病历号 A12345");
+
+        // 崩溃前只写入WAL的加密消息：启动时尚未配置密钥，开启加密后重放
+        let cipher = MessageCipher::new(&[3u8; 32]);
+        storage.wal.lock().append(&cipher.seal(&chat_message("msg_wal", "卡号 6222")).unwrap()).unwrap();
+        drop(storage);
+        let reopened = LocalStorage::new(dir.to_str().unwrap()).unwrap();
+        assert!(reopened.get_message("msg_wal").unwrap().is_none());
+        let reopened = reopened.with_message_cipher(cipher);
+        assert_eq!(reopened.get_message("msg_wal").unwrap().unwrap().content, "卡号 6222");
+        assert!(reopened.wal.read_entries().unwrap().is_empty());
+
+        drop(reopened);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_search_snippets_use_char_offsets() {
+        // 中文字符每个占3字节，位置按字符计算
+        let (snippet, ranges) = build_snippet("您好，我想申请退款，退款多久到账？", "退款", 3).unwrap();
+        assert_eq!(snippet, "…想申请退款，退款…");
+        let chars: Vec<char> = snippet.chars().collect();
+        for range in &ranges {
+            assert_eq!(chars[range.start..range.end].iter().collect::<String>(), "退款");
+        }
+        assert_eq!(ranges, vec![MatchRange { start: 4, end: 6 }, MatchRange { start: 7, end: 9 }]);
+
+        // 不区分大小写，关键词靠近开头时不加前省略号
+        let (snippet, ranges) = build_snippet("VIP会员Refund政策", "refund", 10).unwrap();
+        assert_eq!(snippet, "VIP会员Refund政策");
+        assert_eq!(ranges, vec![MatchRange { start: 5, end: 11 }]);
+        assert!(build_snippet("你好", "退款", 10).is_none());
+
+        let (storage, dir) = temp_storage();
+        storage.save_message(&chat_message("msg_1", "我要退款")).unwrap();
+        storage.save_message(&chat_message("msg_2", "你好")).unwrap();
+        let mut later = chat_message("msg_3", "退款到账了吗");
+        later.timestamp = Utc::now() + chrono::Duration::seconds(5);
+        storage.save_message(&later).unwrap();
+        storage.save_message(&chat_message("msg_4", "退款申请已提交")).unwrap();
+        storage
+            .batch_update_message_status(&["msg_1".to_string()], MessageState::Deleted)
+            .unwrap();
+
+        let query = MessageSearchQuery { keyword: "退款".to_string(), ..Default::default() };
+        let hits = storage.search_messages_with_snippets(&query).unwrap();
+        let ids: Vec<&str> = hits.iter().filter_map(|hit| hit.message.id.as_deref()).collect();
+        // 已删除的消息不出现，最新的在前
+        assert_eq!(ids, vec!["msg_3", "msg_4"]);
+        assert_eq!(hits[0].match_ranges, vec![MatchRange { start: 0, end: 2 }]);
+
+        let other_user = MessageSearchQuery { user_id: Some("kehu_999".to_string()), ..query.clone() };
+        assert!(storage.search_messages_with_snippets(&other_user).unwrap().is_empty());
+
+        // 客服只能搜到本人参与的会话
+        let own = MessageSearchQuery { kefu_id: Some("kefu_001".to_string()), ..query.clone() };
+        assert_eq!(storage.search_messages_with_snippets(&own).unwrap().len(), 2);
+        let other_kefu = MessageSearchQuery { kefu_id: Some("kefu_002".to_string()), ..query };
+        assert!(storage.search_messages_with_snippets(&other_kefu).unwrap().is_empty());
+
+        drop(storage);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
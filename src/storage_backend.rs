@@ -0,0 +1,241 @@
+use crate::config::S3StorageConfig;
+use crate::message::ChatMessage;
+use crate::storage::{conversation_id, LocalStorage, SavedMessage};
+use anyhow::Result;
+use s3::bucket::Bucket;
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::region::Region;
+use std::sync::Arc;
+use tracing::warn;
+
+/// 消息和二进制对象的存储后端，由 storage.backend 选择
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    /// 保存消息；同一消息ID重复提交时返回已存储的原消息
+    async fn save_message(&self, message: &ChatMessage) -> Result<SavedMessage>;
+    /// 两个用户之间最近的 limit 条消息，按时间升序
+    async fn get_recent_messages(&self, user1: &str, user2: &str, limit: usize) -> Result<Vec<ChatMessage>>;
+    #[allow(dead_code)] // 二进制对象接口：附件等需要跨实例共享的数据迁移到对象存储时使用
+    async fn save_blob(&self, key: &str, data: &[u8]) -> Result<()>;
+    #[allow(dead_code)]
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// sled 读写和持有WAL锁的 fsync 都会阻塞，放到阻塞线程池执行，不占用异步运行时的工作线程
+async fn run_blocking<T: Send + 'static>(
+    local: &LocalStorage,
+    f: impl FnOnce(&LocalStorage) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let local = local.clone();
+    tokio::task::spawn_blocking(move || f(&local)).await?
+}
+
+#[async_trait::async_trait]
+impl Storage for LocalStorage {
+    async fn save_message(&self, message: &ChatMessage) -> Result<SavedMessage> {
+        let message = message.clone();
+        run_blocking(self, move |local| local.save_message(&message)).await
+    }
+
+    async fn get_recent_messages(&self, user1: &str, user2: &str, limit: usize) -> Result<Vec<ChatMessage>> {
+        let (user1, user2) = (user1.to_string(), user2.to_string());
+        run_blocking(self, move |local| local.get_recent_messages(&user1, &user2, limit)).await
+    }
+
+    async fn save_blob(&self, key: &str, data: &[u8]) -> Result<()> {
+        let (key, data) = (key.to_string(), data.to_vec());
+        run_blocking(self, move |local| local.save_blob(&key, &data)).await
+    }
+
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let key = key.to_string();
+        run_blocking(self, move |local| local.get_blob(&key)).await
+    }
+}
+
+/// 对象键前缀，非空时以 '/' 结尾
+fn object_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", prefix)
+    }
+}
+
+/// 会话消息在桶中的目录，两个参与者按字典序排列
+fn conversation_prefix(prefix: &str, user1: &str, user2: &str) -> String {
+    format!("{}messages/{}/", prefix, conversation_id(user1, user2))
+}
+
+/// 消息的对象键：会话目录 + 补零的倒序毫秒时间戳 + 消息ID。桶按键升序列出，
+/// 倒序时间戳使最新的消息排在最前，取最近消息时只需列出一页；没有接收方的消息不写入
+fn message_object_key(prefix: &str, message: &ChatMessage) -> Option<String> {
+    let to = message.to.as_deref()?;
+    Some(format!(
+        "{}{:020}_{}",
+        conversation_prefix(prefix, &message.from, to),
+        u64::MAX - message.timestamp.timestamp_millis().max(0) as u64,
+        message.id.as_deref().unwrap_or_default()
+    ))
+}
+
+/// S3 兼容对象存储。消息先写入本地存储分配ID和会话序号，再以对象写入桶中，
+/// 历史翻页、增量同步等仍读本地索引；最近消息和二进制对象从桶中读取，多个实例共用同一个桶时互相可见。
+///
+/// 会话序号由各实例的本地索引分别分配，实例之间不协调：同一会话的消息经不同实例写入时序号可能重复，
+/// 按序号增量同步只在客户端固定连接同一实例时可靠
+pub struct S3Storage {
+    bucket: Bucket,
+    prefix: String,
+    local: Arc<LocalStorage>,
+}
+
+impl S3Storage {
+    pub fn new(config: &S3StorageConfig, local: Arc<LocalStorage>) -> Result<Self> {
+        if config.bucket.is_empty() {
+            anyhow::bail!("S3存储缺少 bucket 配置");
+        }
+        let region = match &config.endpoint {
+            Some(endpoint) => Region::Custom {
+                region: config.region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => config.region.parse()?,
+        };
+        let credentials = Credentials::new(
+            config.access_key_id.as_deref(),
+            config.secret_access_key.as_deref(),
+            None,
+            None,
+            None,
+        )?;
+        let bucket = Bucket::new(&config.bucket, region, credentials)?;
+        let bucket = if config.path_style { bucket.with_path_style() } else { bucket };
+
+        Ok(Self {
+            bucket,
+            prefix: object_prefix(&config.prefix),
+            local,
+        })
+    }
+
+    // 开启 fail-on-err 时 404 以 S3Error::Http 返回，关闭时只能从响应状态码判断
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.bucket.get_object(key).await {
+            Ok(response) => match response.status_code() {
+                200..=299 => Ok(Some(response.bytes().to_vec())),
+                404 => Ok(None),
+                status => Err(anyhow::anyhow!("读取S3对象失败: {} - HTTP {}", key, status)),
+            },
+            Err(S3Error::Http(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for S3Storage {
+    async fn save_message(&self, message: &ChatMessage) -> Result<SavedMessage> {
+        let saved = Storage::save_message(self.local.as_ref(), message).await?;
+        // 本地已提交，写桶失败只记录日志，不让发送方误以为消息未保存而重发；
+        // 重复提交时再写一次，补上之前写桶失败的消息。写入内容与本地一致，开启加密时同样是密文
+        let stored = match &saved {
+            SavedMessage::Created(stored) | SavedMessage::Duplicate(stored) => stored,
+        };
+        if let Some(key) = message_object_key(&self.prefix, stored) {
+            let data = self.local.encode_message(stored)?;
+            if let Err(e) = self.bucket.put_object(&key, &data).await {
+                warn!("⚠️ 消息写入S3失败，仅保存在本地: {} - {}", key, e);
+            }
+        }
+        Ok(saved)
+    }
+
+    async fn get_recent_messages(&self, user1: &str, user2: &str, limit: usize) -> Result<Vec<ChatMessage>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        // 键按时间倒序排列，第一页即最近的 limit 条
+        let prefix = conversation_prefix(&self.prefix, user1, user2);
+        let (page, _) = self.bucket.list_page(prefix, None, None, None, Some(limit)).await?;
+
+        let mut messages = Vec::new();
+        for object in page.contents.iter().rev() {
+            // 列出后被删除的对象跳过
+            if let Some(data) = self.get_object(&object.key).await? {
+                messages.push(self.local.decode_message(&data)?);
+            }
+        }
+        Ok(messages)
+    }
+
+    async fn save_blob(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.bucket.put_object(format!("{}blobs/{}", self.prefix, key), data).await?;
+        Ok(())
+    }
+
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.get_object(&format!("{}blobs/{}", self.prefix, key)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn chat_message(id: &str, from: &str, to: Option<&str>) -> ChatMessage {
+        ChatMessage {
+            id: Some(id.to_string()),
+            from: from.to_string(),
+            to: to.map(str::to_string),
+            content: "你好".to_string(),
+            content_type: None,
+            filename: None,
+            timestamp: Utc.timestamp_millis_opt(1_700_000_000_000).unwrap(),
+            url: None,
+            thumbnail_url: None,
+            seq: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_backend_through_trait() {
+        let dir = std::env::temp_dir().join(format!("kefu_storage_backend_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store: Arc<dyn Storage> = Arc::new(LocalStorage::new(dir.to_str().unwrap()).unwrap());
+
+        let message = chat_message("msg_1", "kehu_001", Some("kefu_001"));
+        assert!(!store.save_message(&message).await.unwrap().is_duplicate());
+        assert!(store.save_message(&message).await.unwrap().is_duplicate());
+        let recent = store.get_recent_messages("kefu_001", "kehu_001", 10).await.unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].seq, Some(1));
+
+        store.save_blob("avatar/kehu_001", b"png").await.unwrap();
+        assert_eq!(store.get_blob("avatar/kehu_001").await.unwrap().as_deref(), Some(&b"png"[..]));
+        assert!(store.get_blob("missing").await.unwrap().is_none());
+
+        drop(store);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_object_keys_sorted_by_conversation_and_time() {
+        assert_eq!(object_prefix(""), "");
+        assert_eq!(object_prefix("/prod/"), "prod/");
+
+        let earlier = chat_message("msg_1", "kehu_001", Some("kefu_001"));
+        let key = message_object_key("prod/", &earlier).unwrap();
+        assert_eq!(key, "prod/messages/kefu_001:kehu_001/18446742373709551615_msg_1");
+        assert!(key.starts_with(&conversation_prefix("prod/", "kehu_001", "kefu_001")));
+
+        // 新消息的键排在前面
+        let mut later = chat_message("msg_0", "kefu_001", Some("kehu_001"));
+        later.timestamp = Utc.timestamp_millis_opt(1_700_000_000_001).unwrap();
+        assert!(message_object_key("prod/", &later).unwrap() < key);
+        assert!(message_object_key("", &chat_message("msg_2", "kehu_001", None)).is_none());
+    }
+}
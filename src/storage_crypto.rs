@@ -0,0 +1,186 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use crate::config::StorageEncryptionConfig;
+use crate::message::ChatMessage;
+
+/// 当前的密文格式版本，为以后更换算法留出余地
+const ENVELOPE_VERSION: u8 = 1;
+
+const NONCE_LEN: usize = 12;
+
+/// 落盘的消息信封：encrypted 为密文格式版本，明文消息（未开启加密或开启前写入）没有该字段。
+/// 是否加密只看信封标记，不根据字段内容猜测，用户发送任何内容都会被加密
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedMessage {
+    #[serde(flatten)]
+    pub message: ChatMessage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<u8>,
+}
+
+impl SealedMessage {
+    pub fn plain(message: ChatMessage) -> Self {
+        Self { message, encrypted: None }
+    }
+}
+
+// 附加认证数据：消息ID + 字段名，密文被挪到其他消息或其他字段时解密失败
+fn field_aad(message_id: &str, field: &str) -> String {
+    format!("{}/{}", message_id, field)
+}
+
+/// 消息静态加密：只加密 content 和 filename，ID、收发方和时间戳保持明文用于索引
+#[derive(Clone)]
+pub struct MessageCipher {
+    cipher: Aes256Gcm,
+}
+
+impl MessageCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    /// 按配置创建，未开启时返回 None；开启但缺少密钥或密钥不是 Base64 编码的 32 字节时报错
+    pub fn from_config(config: &StorageEncryptionConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let encoded = config
+            .key
+            .as_deref()
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| anyhow!("已开启消息加密但未配置密钥（storage.encryption.key 或 STORAGE_ENCRYPTION_KEY）"))?;
+        let key: [u8; 32] = STANDARD
+            .decode(encoded)?
+            .try_into()
+            .map_err(|key: Vec<u8>| anyhow!("消息加密密钥长度应为32字节，实际为{}字节", key.len()))?;
+        Ok(Some(Self::new(&key)))
+    }
+
+    /// 加密单个字段，消息ID和字段名作为附加认证数据
+    fn encrypt_field(&self, aad: &str, plaintext: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: plaintext.as_bytes(), aad: aad.as_bytes() })
+            .map_err(|_| anyhow!("消息加密失败"))?;
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        Ok(STANDARD.encode(data))
+    }
+
+    fn decrypt_field(&self, aad: &str, value: &str) -> Result<String> {
+        let data = STANDARD.decode(value)?;
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("消息密文长度错误"));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+            .map_err(|_| anyhow!("消息解密失败，密钥不匹配或数据已损坏"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    /// 加密消息的 content 和 filename，消息必须已分配ID
+    pub fn seal(&self, message: &ChatMessage) -> Result<SealedMessage> {
+        let message_id = message.id.as_deref().ok_or_else(|| anyhow!("消息缺少ID，无法加密"))?;
+        let mut encrypted = message.clone();
+        encrypted.content = self.encrypt_field(&field_aad(message_id, "content"), &message.content)?;
+        encrypted.filename = message
+            .filename
+            .as_deref()
+            .map(|name| self.encrypt_field(&field_aad(message_id, "filename"), name))
+            .transpose()?;
+        Ok(SealedMessage { message: encrypted, encrypted: Some(ENVELOPE_VERSION) })
+    }
+
+    /// 解开信封，明文信封原样返回
+    pub fn open(&self, sealed: SealedMessage) -> Result<ChatMessage> {
+        let mut message = sealed.message;
+        match sealed.encrypted {
+            None => return Ok(message),
+            Some(ENVELOPE_VERSION) => {}
+            Some(version) => return Err(anyhow!("不支持的消息密文版本: {}", version)),
+        }
+        let message_id = message.id.clone().ok_or_else(|| anyhow!("加密消息缺少ID"))?;
+        message.content = self.decrypt_field(&field_aad(&message_id, "content"), &message.content)?;
+        message.filename = message
+            .filename
+            .as_deref()
+            .map(|name| self.decrypt_field(&field_aad(&message_id, "filename"), name))
+            .transpose()?;
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn message(id: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            id: Some(id.to_string()),
+            from: "kehu_001".to_string(),
+            to: Some("kefu_001".to_string()),
+            content: content.to_string(),
+            content_type: None,
+            filename: Some("银行卡.jpg".to_string()),
+            timestamp: Utc::now(),
+            url: None,
+            thumbnail_url: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn test_fields_encrypted_and_bound_to_message_id() {
+        let cipher = MessageCipher::new(&[7u8; 32]);
+        let sealed = cipher.seal(&message("msg_1", "我的银行卡号 6222")).unwrap();
+        assert_eq!(sealed.encrypted, Some(ENVELOPE_VERSION));
+        assert!(!sealed.message.content.contains("6222"));
+        assert!(!sealed.message.filename.as_deref().unwrap().contains("银行卡"));
+        // 每次使用新的随机数，相同明文的密文不同
+        assert_ne!(sealed.message.content, cipher.seal(&message("msg_1", "我的银行卡号 6222")).unwrap().message.content);
+        assert_eq!(cipher.open(sealed.clone()).unwrap().content, "我的银行卡号 6222");
+
+        // 形似旧版密文前缀的内容同样加密，读回原文
+        let lookalike = cipher.seal(&message("msg_2", "enc:v1:AAAA")).unwrap();
+        assert_ne!(lookalike.message.content, "enc:v1:AAAA");
+        assert_eq!(cipher.open(lookalike).unwrap().content, "enc:v1:AAAA");
+
+        // 密文挪到其他消息或其他字段、密钥不匹配时解密失败而不是返回乱码
+        let mut moved = sealed.clone();
+        moved.message.id = Some("msg_other".to_string());
+        assert!(cipher.open(moved).is_err());
+        let mut swapped = sealed.clone();
+        swapped.message.filename = Some(sealed.message.content.clone());
+        assert!(cipher.open(swapped).is_err());
+        assert!(MessageCipher::new(&[8u8; 32]).open(sealed).is_err());
+
+        // 开启加密前写入的明文信封原样读取
+        let plain = SealedMessage::plain(message("msg_3", "开启加密前的消息"));
+        assert_eq!(cipher.open(plain).unwrap().content, "开启加密前的消息");
+    }
+
+    #[test]
+    fn test_from_config_requires_valid_key() {
+        let disabled = StorageEncryptionConfig::default();
+        assert!(MessageCipher::from_config(&disabled).unwrap().is_none());
+
+        let missing = StorageEncryptionConfig { enabled: true, key: None };
+        assert!(MessageCipher::from_config(&missing).is_err());
+        let short = StorageEncryptionConfig { enabled: true, key: Some(STANDARD.encode([1u8; 16])) };
+        assert!(MessageCipher::from_config(&short).is_err());
+        let valid = StorageEncryptionConfig { enabled: true, key: Some(STANDARD.encode([1u8; 32])) };
+        assert!(MessageCipher::from_config(&valid).unwrap().is_some());
+    }
+}
@@ -0,0 +1,77 @@
+use crate::storage_crypto::SealedMessage;
+use anyhow::Result;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// 定期检查点间隔：sled 落盘后截断 WAL
+pub const WAL_CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 消息写入的预写日志：每条消息以一行JSON顺序追加并 fsync，sled 落盘后由检查点截断。
+/// 重启时日志中残留的消息即为可能未落盘的写入，按消息ID幂等重放
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl WriteAheadLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// 获取日志锁。持锁期间完成追加与正式存储的写入，检查点不会截断尚未写入存储的记录
+    pub fn lock(&self) -> WalGuard<'_> {
+        WalGuard {
+            file: self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
+    }
+
+    /// 读取日志中的全部消息；崩溃时写了一半的末尾记录无法解析，直接跳过
+    pub fn read_entries(&self) -> Result<Vec<SealedMessage>> {
+        let _guard = self.lock();
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<SealedMessage>(&line) {
+                Ok(message) => entries.push(message),
+                Err(e) => tracing::warn!("⚠️ 跳过无法解析的WAL记录: {}", e),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+pub struct WalGuard<'a> {
+    file: MutexGuard<'a, File>,
+}
+
+impl WalGuard<'_> {
+    /// 追加一条消息（开启加密时为密文信封）并 fsync，返回后即使进程崩溃消息也不会丢失
+    pub fn append(&mut self, message: &SealedMessage) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// 正式存储已落盘后清空日志
+    pub fn truncate(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+}
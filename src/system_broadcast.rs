@@ -0,0 +1,96 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// 持久化系统广播的 Redis 列表（LPUSH，最新在前）
+pub const BROADCAST_LOG_KEY: &str = "system:broadcasts:log";
+
+/// 广播日志最多保留的条数
+pub const MAX_LOGGED_BROADCASTS: isize = 200;
+
+/// 广播有效期默认一天，最长三十天；用户最后在线时间保留同样长度
+pub const DEFAULT_BROADCAST_TTL_SECS: u64 = 24 * 3600;
+pub const MAX_BROADCAST_TTL_SECS: u64 = 30 * 24 * 3600;
+
+/// 需要让离线用户上线后补看的系统广播，如维护窗口通知
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SystemBroadcast {
+    pub id: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SystemBroadcast {
+    /// 有效期超过上限时按上限截断
+    pub fn new(content: String, ttl_secs: u64) -> Self {
+        let created_at = Utc::now();
+        let ttl_secs = ttl_secs.clamp(1, MAX_BROADCAST_TTL_SECS);
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            content,
+            created_at,
+            expires_at: created_at + Duration::seconds(ttl_secs as i64),
+        }
+    }
+
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at > now
+    }
+}
+
+/// 用户上次在线之后发布且仍在有效期内的广播，按发布时间升序；从未记录在线时间的用户补看全部有效广播
+pub fn missed_broadcasts(
+    broadcasts: Vec<SystemBroadcast>,
+    last_seen: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Vec<SystemBroadcast> {
+    let mut missed: Vec<SystemBroadcast> = broadcasts
+        .into_iter()
+        .filter(|broadcast| broadcast.is_active(now))
+        .filter(|broadcast| last_seen.is_none_or(|last_seen| broadcast.created_at > last_seen))
+        .collect();
+    missed.sort_by_key(|broadcast| broadcast.created_at);
+    missed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn broadcast(content: &str, created_at: DateTime<Utc>, ttl: Duration) -> SystemBroadcast {
+        SystemBroadcast {
+            id: content.to_string(),
+            content: content.to_string(),
+            created_at,
+            expires_at: created_at + ttl,
+        }
+    }
+
+    #[test]
+    fn test_only_unexpired_broadcasts_after_last_seen_are_replayed() {
+        let now = Utc::now();
+        let last_seen = now - Duration::hours(2);
+        let log = vec![
+            broadcast("维护通知", now - Duration::hours(1), Duration::hours(6)),
+            broadcast("已过期", now - Duration::hours(1), Duration::minutes(30)),
+            broadcast("上线前已看过", now - Duration::hours(3), Duration::hours(6)),
+            broadcast("更早的通知", now - Duration::minutes(90), Duration::hours(6)),
+        ];
+
+        let missed = missed_broadcasts(log.clone(), Some(last_seen), now);
+        let contents: Vec<&str> = missed.iter().map(|b| b.content.as_str()).collect();
+        assert_eq!(contents, ["更早的通知", "维护通知"]);
+
+        // 没有在线记录的用户补看全部有效广播
+        assert_eq!(missed_broadcasts(log, None, now).len(), 3);
+    }
+
+    #[test]
+    fn test_ttl_is_capped() {
+        let broadcast = SystemBroadcast::new("通知".to_string(), u64::MAX);
+        assert_eq!(
+            (broadcast.expires_at - broadcast.created_at).num_seconds(),
+            MAX_BROADCAST_TTL_SECS as i64
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+/// 非受信任模板允许保留的标签和属性：ammonia 默认白名单（不含 script、style、事件属性、
+/// javascript: 等非白名单协议链接），另放行按钮和 data-* 属性，回调通过 data-callback 声明、由前端统一绑定
+static UNTRUSTED_HTML: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(["button"])
+        .add_tag_attributes("button", ["type"])
+        .generic_attribute_prefixes(HashSet::from(["data-"]));
+    builder
+});
+
+/// 受信任模板预览页的内容安全策略：允许模板自带的内联样式和脚本，禁止加载外部脚本和提交表单
+pub const TRUSTED_TEMPLATE_CSP: &str =
+    "default-src 'self'; script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'; img-src 'self' https: data:; form-action 'none'; frame-ancestors 'self'";
+
+/// 非受信任模板预览页的内容安全策略：不执行任何脚本
+pub const UNTRUSTED_TEMPLATE_CSP: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; img-src 'self' https: data:; form-action 'none'; frame-ancestors 'self'";
+
+/// 渲染结果对应的内容安全策略
+pub fn content_security_policy(trusted: bool) -> &'static str {
+    if trusted {
+        TRUSTED_TEMPLATE_CSP
+    } else {
+        UNTRUSTED_TEMPLATE_CSP
+    }
+}
+
+/// 模板变量插入的位置，决定转义方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InterpolationContext {
+    Html,
+    Css,
+    Script,
+}
+
+/// 按白名单清洗非受信任模板渲染后的 HTML，白名单外的标签、属性和链接协议一律移除
+pub fn sanitize_html(html: &str) -> String {
+    UNTRUSTED_HTML.clean(html).to_string()
+}
+
+/// 非受信任模板的样式放入 <style> 时，转义 '<' 防止提前闭合标签
+pub fn sanitize_css(css: &str) -> String {
+    css.replace('<', "\\3c ")
+}
+
+/// 按插入位置转义变量值，防止变量内容改变模板结构
+pub fn escape_value(value: &str, context: InterpolationContext) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match context {
+            InterpolationContext::Html => match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                _ => escaped.push(c),
+            },
+            // 假定变量位于字符串字面量中
+            InterpolationContext::Script => match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '"' | '\'' | '`' | '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                    escaped.push_str(&format!("\\u{:04x}", c as u32))
+                }
+                _ => escaped.push(c),
+            },
+            InterpolationContext::Css => {
+                if c.is_alphanumeric() || matches!(c, ' ' | '#' | '.' | '%' | '-' | '_' | ',') {
+                    escaped.push(c);
+                } else {
+                    escaped.push_str(&format!("\\{:x} ", c as u32));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_sanitized_by_allowlist() {
+        let html = r#"<button data-callback="confirm" onclick="htmlCallback('ok')">确定</button><img/onerror=alert(1) src=x><script src="https://evil.com/a.js"></script><script>eval(x)</script><a href=javascript:alert(1)>链接</a><a href="https://example.com/help">帮助</a><svg><style>*{}</style></svg>"#;
+        let sanitized = sanitize_html(html);
+        assert!(sanitized.contains(r#"data-callback="confirm""#));
+        assert!(!sanitized.contains("onclick"));
+        assert!(!sanitized.contains("onerror"));
+        assert!(!sanitized.contains("<script"));
+        assert!(!sanitized.contains("evil.com"));
+        assert!(!sanitized.contains("eval"));
+        assert!(!sanitized.contains("javascript:"));
+        assert!(!sanitized.contains("<svg"));
+        assert!(sanitized.contains(r#"href="https://example.com/help""#));
+        assert!(sanitized.contains("确定"));
+
+        assert_eq!(sanitize_css("a{color:red}</style><script>"), "a{color:red}\\3c /style>\\3c script>");
+        assert!(!content_security_policy(false).contains("script-src"));
+        assert!(content_security_policy(false).starts_with("default-src 'none'"));
+    }
+
+    #[test]
+    fn test_interpolation_escaped_by_context() {
+        let payload = "</div><script>alert('x')</script>";
+        assert_eq!(
+            escape_value(payload, InterpolationContext::Html),
+            "&lt;/div&gt;&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;"
+        );
+        let script = escape_value("\"; alert(1); //", InterpolationContext::Script);
+        assert!(!script.contains('"'));
+        assert_eq!(escape_value("red;}body{", InterpolationContext::Css), "red\\3b \\7d body\\7b ");
+    }
+}
@@ -1,6 +1,9 @@
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use utoipa::ToSchema;
 
+use crate::middleware::request_id::current_request_id;
+
 /// 通用API错误响应
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct ApiError {
@@ -15,7 +18,9 @@ pub struct ApiError {
 }
 
 /// 通用API响应
-#[derive(Serialize, Deserialize, Debug, ToSchema)]
+///
+/// 失败响应在HTTP请求处理流程中序列化时附带 request_id，便于和服务端日志对应
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct ApiResponse<T> {
     /// 操作是否成功
     pub success: bool,
@@ -25,6 +30,55 @@ pub struct ApiResponse<T> {
     pub data: Option<T>,
 }
 
+impl<T: Serialize> Serialize for ApiResponse<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let request_id = (!self.success).then(current_request_id).flatten();
+        let mut state = serializer.serialize_struct("ApiResponse", 3 + usize::from(request_id.is_some()))?;
+        state.serialize_field("success", &self.success)?;
+        state.serialize_field("message", &self.message)?;
+        state.serialize_field("data", &self.data)?;
+        if let Some(request_id) = request_id {
+            state.serialize_field("request_id", &request_id)?;
+        }
+        state.end()
+    }
+}
+
+/// 默认每页条目数
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+
+/// 每页条目数上限
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// 统一分页请求参数，列表接口通过查询参数（或请求体）传入
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct PageRequest {
+    /// 页码，从1开始
+    pub page: Option<u32>,
+    /// 每页条目数，兼容旧参数名 limit
+    #[serde(alias = "limit")]
+    pub page_size: Option<u32>,
+    /// 上一页响应中的 next_cursor，传入时忽略 page
+    pub cursor: Option<String>,
+}
+
+/// 统一分页响应
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PageResponse<T> {
+    /// 当前页数据
+    pub items: Vec<T>,
+    /// 总条目数
+    pub total: usize,
+    /// 当前页码
+    pub page: u32,
+    /// 每页条目数
+    pub page_size: u32,
+    /// 是否还有下一页
+    pub has_more: bool,
+    /// 获取下一页使用的游标
+    pub next_cursor: Option<String>,
+}
+
 /// 通用成功响应
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct SuccessResponse {
@@ -101,12 +155,6 @@ pub struct OnlineUserInfo {
 /// 文件列表查询参数
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct FileListQuery {
-    /// 页码，从1开始
-    #[allow(dead_code)] // 将在文件列表API中使用
-    pub page: Option<u32>,
-    /// 每页条目数
-    #[allow(dead_code)] // 将在文件列表API中使用
-    pub limit: Option<u32>,
     /// 文件分类过滤
     #[allow(dead_code)] // 将在文件列表API中使用
     pub category: Option<String>,
@@ -193,6 +241,45 @@ pub struct ClientRegisterResponse {
     pub location: Option<IpLocationResponse>,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct ClientConfigQuery {
+    /// 客户端类型 (web, mobile, desktop)
+    pub client_type: Option<String>,
+    /// 客户端版本
+    pub version: Option<String>,
+}
+
+/// 下发给前端的运行时配置，只包含前端可见的字段
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClientConfigResponse {
+    /// WebSocket连接地址
+    pub ws_url: String,
+    /// API地址
+    pub api_url: String,
+    /// 功能开关
+    pub features: std::collections::BTreeMap<String, bool>,
+    /// 上传限制
+    pub upload: ClientUploadLimits,
+    /// 心跳间隔（毫秒）
+    pub heartbeat_interval: u64,
+    /// 重连间隔（毫秒）
+    pub reconnect_interval: u64,
+    /// 最大重连次数
+    pub max_reconnect_attempts: u32,
+    /// 单条消息大小上限（字节）
+    pub max_message_size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClientUploadLimits {
+    /// 上传文件大小上限（字节）
+    pub max_file_size: u64,
+    /// 允许上传的MIME类型
+    pub allowed_types: Vec<String>,
+    /// 按MIME类型或主类型的大小上限（字节）
+    pub max_size_by_type: std::collections::HashMap<String, u64>,
+}
+
 impl ApiError {
     /// 创建新的API错误
     #[allow(dead_code)] // 工具方法，将在错误处理中使用
@@ -239,6 +326,53 @@ impl<T> ApiResponse<T> {
     }
 }
 
+impl PageRequest {
+    /// 每页条目数，限制在 1..=MAX_PAGE_SIZE
+    pub fn page_size(&self) -> u32 {
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    /// 本页第一条的偏移量；游标无效时按页码计算
+    pub fn offset(&self) -> usize {
+        if let Some(offset) = self.cursor.as_deref().and_then(|cursor| cursor.parse::<usize>().ok()) {
+            return offset;
+        }
+        (self.page.unwrap_or(1).max(1) as usize - 1) * self.page_size() as usize
+    }
+
+    /// 当前页码（由偏移量换算，游标分页时同样有效）
+    pub fn page(&self) -> u32 {
+        (self.offset() / self.page_size() as usize) as u32 + 1
+    }
+}
+
+impl<T> PageResponse<T> {
+    /// 对完整列表做内存分页
+    pub fn paginate(items: Vec<T>, request: &PageRequest) -> Self {
+        let total = items.len();
+        let items = items
+            .into_iter()
+            .skip(request.offset())
+            .take(request.page_size() as usize)
+            .collect();
+        Self::from_page(items, total, request)
+    }
+
+    /// 数据源已按请求分页时，补全分页信息
+    pub fn from_page(items: Vec<T>, total: usize, request: &PageRequest) -> Self {
+        let next_offset = request.offset() + items.len();
+        let has_more = next_offset < total;
+        Self {
+            items,
+            total,
+            page: request.page(),
+            page_size: request.page_size(),
+            has_more,
+            next_cursor: has_more.then(|| next_offset.to_string()),
+        }
+    }
+}
+
 impl SuccessResponse {
     /// 创建成功响应
     #[allow(dead_code)] // 工具方法，将在API响应中使用
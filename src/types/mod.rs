@@ -48,6 +48,35 @@ mod tests {
         assert!(deserialized.is_ok(), "ApiResponse应该可以正常反序列化");
     }
     
+    #[test]
+    fn test_page_request_offset_and_cursor() {
+        use super::api::{PageRequest, PageResponse, MAX_PAGE_SIZE};
+
+        let first = PageRequest { page: None, page_size: Some(2), cursor: None };
+        let page: PageResponse<u32> = PageResponse::paginate((1..=5).collect(), &first);
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!((page.total, page.page, page.page_size), (5, 1, 2));
+        assert!(page.has_more);
+        assert_eq!(page.next_cursor.as_deref(), Some("2"));
+
+        // 游标优先于页码
+        let next = PageRequest { page: Some(1), page_size: Some(2), cursor: page.next_cursor };
+        let page = PageResponse::paginate((1..=5).collect::<Vec<u32>>(), &next);
+        assert_eq!(page.items, vec![3, 4]);
+        assert_eq!(page.page, 2);
+
+        let last = PageRequest { page: Some(3), page_size: Some(2), cursor: None };
+        let page = PageResponse::paginate((1..=5).collect::<Vec<u32>>(), &last);
+        assert_eq!(page.items, vec![5]);
+        assert!(!page.has_more);
+        assert!(page.next_cursor.is_none());
+
+        // 兼容旧参数 limit，且不超过上限
+        let legacy: PageRequest = serde_json::from_str(r#"{"page": 0, "limit": 1000}"#).unwrap();
+        assert_eq!(legacy.page_size(), MAX_PAGE_SIZE);
+        assert_eq!(legacy.offset(), 0);
+    }
+
     #[test]
     fn test_success_response_creation() {
         let response = SuccessResponse {
@@ -25,6 +25,9 @@ pub struct VoiceMessage {
     pub transcription: Option<String>, // 语音转文字（可选）
     pub is_read: bool,
     pub checksum: String,
+    /// 会话中对应的聊天消息ID，推送转写结果时客户端据此更新原消息
+    #[serde(default)]
+    pub message_id: Option<String>,
 }
 
 /// 语音上传请求
@@ -258,6 +261,7 @@ impl VoiceMessageManager {
             transcription: None, // 未来可以集成语音识别服务
             is_read: false,
             checksum,
+            message_id: None,
         };
 
         // 保存语音消息元数据
@@ -307,6 +311,34 @@ impl VoiceMessageManager {
         }
     }
 
+    /// 回填语音转写文本，语音不存在时返回 None
+    pub async fn set_transcription(&self, voice_id: &str, text: &str) -> Result<Option<VoiceMessage>> {
+        let Some(mut voice_message) = self.get_voice_message(voice_id).await? else {
+            return Ok(None);
+        };
+        voice_message.transcription = Some(text.to_string());
+        self.save_voice_metadata(&voice_message).await?;
+        info!("🎤 语音转写已回填: voice_id={}, 长度={}", voice_id, text.chars().count());
+        Ok(Some(voice_message))
+    }
+
+    /// 记录语音对应的聊天消息ID
+    pub async fn set_message_id(&self, voice_id: &str, message_id: &str) -> Result<()> {
+        if let Some(mut voice_message) = self.get_voice_message(voice_id).await? {
+            voice_message.message_id = Some(message_id.to_string());
+            self.save_voice_metadata(&voice_message).await?;
+        }
+        Ok(())
+    }
+
+    /// 获取语音文件的本地存储路径
+    pub async fn get_voice_file_path(&self, voice_id: &str) -> Result<Option<PathBuf>> {
+        Ok(self.get_voice_message(voice_id).await?.map(|msg| {
+            let filename = format!("{}_{}.{}", msg.id, msg.upload_time.timestamp(), msg.format);
+            self.storage_path.join(filename)
+        }))
+    }
+
     /// 下载语音文件
     #[allow(dead_code)] // 将在语音下载API中使用
     pub async fn download_voice_file(&self, file_id: &str) -> Result<(Vec<u8>, String)> {
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::mpsc::error::SendTimeoutError;
 use tokio::sync::{mpsc, RwLock};
 
 use futures_util::{SinkExt, StreamExt};
@@ -10,21 +11,128 @@ use chrono::Utc;
 use uuid::Uuid;
 use tracing::info;
 
-use crate::compression::{AdaptiveCompressor, CompressionConfig};
+use crate::ai::escalation::{score_sentiment, SentimentEscalationTracker};
+use crate::ai::interim::InterimResult;
+use crate::ai::summarization::{summary_input, SummaryResult};
+use crate::ai::{AIManager, AITask, AITaskType};
+use crate::auth::session_crypto::{EncryptedFrame, KeyExchange, KeyRotationPolicy, SessionCipher, SESSION_ENCRYPTION};
+use crate::auth::geo_risk::{
+    BuiltinGeoLocator, GeoLocator, GeoRiskAction, GeoRiskAssessment, GeoRiskTracker, MaxMindGeoLocator,
+};
+use crate::canned_response::{self, CannedResponse, DEFAULT_MATCH_LIMIT};
+use crate::close_code::{CloseReason, CloseSignal};
+use crate::compression::{AdaptiveCompressor, CompressionConfig, MessageCompressor};
+use crate::config::{AssignmentMode, DuplicateConnectionPolicy, GreetingConfig};
+use crate::connection_events::ConnectionEvent;
+use crate::content_filter::{ContentFilter, FilterDecision};
+use crate::errors::WebSocketError;
+use crate::file_manager::is_thumbnail_url;
+use crate::greeting::render_greeting;
+use crate::kefu_alert::select_idlest_kefu;
 use crate::message::{
-    ChatMessage, ContentType, CustomerInfo, Message as AppMessage, OnlineStatus, UserConnection,
-    UserInfo, UserType,
+    ChatMessage, ContentType, CustomerInfo, Message as AppMessage, OnlineStatus, SessionSummary,
+    UserConnection, UserInfo, UserType,
+};
+use crate::message_queue::{is_payload_expired, MessageQueueManager, MessageStatus, MessageStatusRecord, MessageStatusSyncer};
+use crate::message_reorder::{clamp_timestamp, ReorderBuffer, DEFAULT_REORDER_WINDOW, MAX_TIMESTAMP_SKEW, REORDER_STATE_IDLE};
+use crate::message_version::{upgrade_message, CURRENT_PROTOCOL_VERSION, MIN_PROTOCOL_VERSION};
+use crate::middleware::metrics::request_timer;
+use crate::cache::analytics::AnalyticsCache;
+use crate::monitoring::connection_history::{ConnectionHistory, ConnectionSample};
+use crate::monitoring::scaling::{AutoScaler, ScalingAction, ScalingRecommendation, ScalingSample};
+use crate::monitoring::metrics::{
+    BandwidthCounters, ConnectionBandwidth, DeliveryAckStats, DeliveryAckTracker, Histograms, MessageTypeCounters, INBOUND,
+    OUTBOUND, WS_MESSAGE_HANDLING_MS,
 };
-use crate::message_queue::{MessageQueueManager, MessageStatusSyncer};
-use crate::redis_client::RedisManager;
-use crate::storage::LocalStorage;
+use crate::monitoring::sla::{DailySlaReport, SlaTracker};
+use crate::reaction::{is_allowed_reaction, ReactionAction};
+use crate::redis_client::{RedisManager, MAX_KEFU_SESSIONS};
+use crate::satisfaction::{is_valid_score, rated_kefu, KefuSatisfaction, SessionRating};
+use crate::storage::{HistoryCursor, HistoryPage, LocalStorage};
+use crate::storage_backend::Storage;
+use crate::system_broadcast::{missed_broadcasts, SystemBroadcast};
+use crate::types::api::MAX_PAGE_SIZE;
+use crate::voice_message::{VoiceMessage, VoiceMessageManager};
 
 // 🚀 添加Redis事件处理支持
 // use redis::AsyncCommands; // 已在函数内部导入
 use serde_json::json;
 
 pub type UserConnections = Arc<RwLock<HashMap<String, UserConnection>>>;
-pub type UserSenders = Arc<RwLock<HashMap<String, mpsc::UnboundedSender<AppMessage>>>>;
+/// 发送通道中传递的消息，广播时所有接收者共享同一份数据
+pub type SharedMessage = Arc<AppMessage>;
+pub type UserSenders = Arc<RwLock<HashMap<String, mpsc::Sender<SharedMessage>>>>;
+/// 打字指示器自动清除定时器：from -> (接收方, 定时任务)
+pub type TypingTimers = Arc<RwLock<HashMap<String, (String, tokio::task::JoinHandle<()>)>>>;
+
+/// 每个用户当前连接的关闭信号，用于关闭被新连接替换或被强制断开的连接
+pub type CloseSignals = Arc<RwLock<HashMap<String, Arc<CloseSignal>>>>;
+
+/// 按会话方向（from->to）缓存待重排序的聊天消息
+pub type ReorderBuffers = Arc<RwLock<HashMap<String, ReorderBuffer<AppMessage>>>>;
+
+/// 未收到刷新时自动清除"正在输入"状态的时长
+const TYPING_INDICATOR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// 协议层 ping 的默认发送间隔
+const DEFAULT_PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 连续多少个 ping 间隔收不到 pong 即判定连接已断开
+const PONG_TIMEOUT_INTERVALS: u32 = 2;
+
+/// 默认同时在线连接数上限
+const DEFAULT_MAX_CONNECTIONS: usize = 10000;
+
+/// 默认每个连接的待发送消息队列长度
+const DEFAULT_SEND_QUEUE_SIZE: usize = 256;
+
+/// 发送队列已满时等待腾出空位的最长时间，超时后消息转入离线队列
+const SEND_QUEUE_FULL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// 默认排队超时时长，超时后客户转为留言
+const DEFAULT_QUEUE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// 连接带宽检查间隔
+const BANDWIDTH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 消息确认检查间隔
+const ACK_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 默认消息确认超时，超时仍未确认的消息计为未确认
+const DEFAULT_ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 一个检查周期内有结果的消息少于该数量时不判断未确认率，避免少量消息误报
+const ACK_ALERT_MIN_SAMPLES: u64 = 20;
+
+/// 排队超时检查间隔
+const QUEUE_TIMEOUT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 默认会话空闲超时，双方都没有消息超过该时长的会话自动结束
+const DEFAULT_SESSION_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1800);
+
+/// 空闲会话检查间隔
+const IDLE_SESSION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 客服请求客户历史消息时的默认每页条数
+const HISTORY_PAGE_SIZE: usize = 50;
+
+/// 二进制帧使用的压缩方式，在欢迎消息中告知客户端
+pub const FRAME_COMPRESSION: &str = "gzip";
+
+/// 达到该大小的出站帧放到阻塞线程池压缩，避免大帧的gzip占用异步工作线程
+const BLOCKING_COMPRESSION_SIZE: usize = 64 * 1024;
+
+/// 等待服务端语音转写完成的最长时间
+const VOICE_TRANSCRIPTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 等待会话摘要结果的最长时间
+const SESSION_SUMMARY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// 留言联系方式的最大字符数
+const MAX_TICKET_CONTACT_CHARS: usize = 64;
+
+/// 留言内容的最大字符数
+const MAX_TICKET_CONTENT_CHARS: usize = 2000;
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ConnectionStats {
@@ -33,6 +141,22 @@ pub struct ConnectionStats {
     pub kehu_connections: usize,
     pub average_connection_duration: i64,
     pub longest_connection_duration: i64,
+    /// 启动以来全部连接的收发字节数
+    pub bandwidth: ConnectionBandwidth,
+    /// 在线连接各自的收发字节数
+    pub connection_bandwidth: HashMap<String, ConnectionBandwidth>,
+    /// 需确认消息的确认耗时与未确认率
+    pub acks: DeliveryAckStats,
+}
+
+/// 会话转接结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionTransferOutcome {
+    Transferred { history_count: usize },
+    /// 客户当前不由发起转接的客服接待
+    SessionNotFound,
+    TargetKefuOffline,
+    TargetKefuBusy { active_sessions: usize },
 }
 
 #[allow(dead_code)] // 企业级WebSocket管理器：message_queue和status_syncer用于Redis增强功能
@@ -42,9 +166,58 @@ pub struct WebSocketManager {
     pub senders: UserSenders,
     pub redis: Arc<RwLock<RedisManager>>,
     pub storage: Arc<LocalStorage>,
-    pub compressor: Arc<RwLock<AdaptiveCompressor>>,
+    /// 聊天消息写入和最近消息读取走配置的存储后端，默认与 storage 相同
+    pub message_store: Arc<dyn Storage>,
+    /// 帧压缩配置，每个连接按此创建自己的压缩器，互不加锁
+    pub compression: CompressionConfig,
     pub message_queue: Arc<MessageQueueManager>, // 企业级消息队列功能
     pub status_syncer: Arc<MessageStatusSyncer>, // 企业级状态同步功能
+    pub typing_timers: TypingTimers,
+    pub close_signals: CloseSignals,
+    /// 同一用户重复连接时替换旧连接还是拒绝新连接
+    pub duplicate_connection_policy: DuplicateConnectionPolicy,
+    pub reorder_buffers: ReorderBuffers,
+    pub reorder_window: std::time::Duration,
+    pub ping_interval: std::time::Duration,
+    pub ai_manager: Option<Arc<AIManager>>,
+    pub voice_manager: Option<Arc<VoiceMessageManager>>,
+    pub connection_history: Arc<ConnectionHistory>,
+    /// 按持续负载给出扩缩容建议
+    pub auto_scaler: Arc<AutoScaler>,
+    pub sla_tracker: Arc<SlaTracker>,
+    pub sentiment_tracker: Arc<SentimentEscalationTracker>,
+    pub geo_risk: Arc<GeoRiskTracker>,
+    /// 为连接事件补充地理位置的GeoIP库，未配置或数据库缺失时不补充
+    pub geoip: Option<Arc<dyn GeoLocator>>,
+    pub assignment_mode: AssignmentMode,
+    pub new_customer_alert_count: usize,
+    /// 同时在线连接数上限，0 表示不限制
+    pub max_connections: usize,
+    pub send_queue_size: usize,
+    /// 排队超时时长，超时仍无客服接入的客户转为留言；为0时不超时
+    pub queue_timeout: std::time::Duration,
+    /// 会话空闲超时时长，超时的会话自动结束；为0时不自动结束
+    pub session_idle_timeout: std::time::Duration,
+    /// 按消息类型的收发计数，由 /metrics 导出
+    pub message_counters: Arc<MessageTypeCounters>,
+    /// 仪表盘统计接口的短时结果缓存
+    pub analytics_cache: Arc<AnalyticsCache>,
+    /// 会话建立后自动发送的问候语
+    pub greeting: Arc<GreetingConfig>,
+    /// 文本聊天内容的长度限制与敏感词过滤
+    pub content_filter: ContentFilter,
+    /// 按连接和全局累计的收发字节数，由 /metrics 和连接统计导出
+    pub bandwidth: Arc<BandwidthCounters>,
+    /// 单个连接收发速率超过该值（字节/秒）时告警，0 表示不检查
+    pub bandwidth_alert_bytes_per_sec: u64,
+    /// 需确认消息的发送到确认耗时与未确认统计，由 /metrics 和连接统计导出
+    pub acks: Arc<DeliveryAckTracker>,
+    /// 推送后超过该时长仍未确认的消息计为未确认
+    pub ack_timeout: std::time::Duration,
+    /// 检查周期内未确认率超过该值时告警，0 表示不检查
+    pub unacked_alert_rate: f64,
+    /// 消息处理耗时分布，由 /metrics 导出
+    pub latency_histograms: Arc<Histograms>,
 }
 
 // 聊天消息参数结构体
@@ -60,6 +233,38 @@ struct ChatMessageParams {
     url: Option<String>,
 }
 
+/// 握手时协商出的连接选项，旧客户端不声明的项取默认值
+#[derive(Debug, Clone)]
+pub struct ConnectionOptions {
+    /// 客户端可以解压 gzip 二进制帧
+    pub compression_supported: bool,
+    /// 客户端请求会话加密时提供的 X25519 公钥
+    pub client_key: Option<[u8; 32]>,
+    /// 协商后的消息协议版本
+    pub protocol_version: u32,
+    /// 异地登录风控判定的可疑原因
+    pub suspicious_reason: Option<String>,
+    /// 经可信代理解析出的客户端IP
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// 未携带令牌的访客由服务端签发的令牌，随欢迎消息下发
+    pub guest_token: Option<String>,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            compression_supported: false,
+            client_key: None,
+            protocol_version: CURRENT_PROTOCOL_VERSION,
+            suspicious_reason: None,
+            client_ip: None,
+            user_agent: None,
+            guest_token: None,
+        }
+    }
+}
+
 /// 企业级语音消息参数结构体
 struct VoiceMessageParams {
     id: Option<String>,
@@ -76,29 +281,209 @@ struct VoiceMessageParams {
     timestamp: chrono::DateTime<Utc>,
 }
 
+/// 按需转写请求的处理结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceTranscriptionRequest {
+    /// 已有转写文本，不再重复提交
+    AlreadyTranscribed(String),
+    Submitted { task_id: String },
+    VoiceNotFound,
+    /// 客服不是该语音的发送方或接收方
+    Forbidden,
+    /// 未接入AI服务或语音存储
+    Unavailable,
+}
+
 impl WebSocketManager {
     pub fn new(redis: RedisManager, storage: LocalStorage) -> Self {
-        let compression_config = CompressionConfig::default();
-        let compressor = AdaptiveCompressor::new(compression_config);
-
         // 创建消息队列管理器
         let redis_conn = redis
             .get_connection()
             .expect("Failed to get Redis connection");
-        let message_queue = Arc::new(MessageQueueManager::new(redis_conn));
+        let message_queue = Arc::new(MessageQueueManager::new(redis_conn, redis.clone()));
         let status_syncer = Arc::new(MessageStatusSyncer::new(message_queue.clone()));
 
+        let storage = Arc::new(storage);
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
             senders: Arc::new(RwLock::new(HashMap::new())),
             redis: Arc::new(RwLock::new(redis)),
-            storage: Arc::new(storage),
-            compressor: Arc::new(RwLock::new(compressor)),
+            message_store: storage.clone(),
+            storage,
+            compression: CompressionConfig::default(),
             message_queue,
             status_syncer,
+            typing_timers: Arc::new(RwLock::new(HashMap::new())),
+            close_signals: Arc::new(RwLock::new(HashMap::new())),
+            duplicate_connection_policy: DuplicateConnectionPolicy::default(),
+            reorder_buffers: Arc::new(RwLock::new(HashMap::new())),
+            reorder_window: DEFAULT_REORDER_WINDOW,
+            ping_interval: DEFAULT_PING_INTERVAL,
+            ai_manager: None,
+            voice_manager: None,
+            connection_history: Arc::new(ConnectionHistory::default()),
+            auto_scaler: Arc::new(AutoScaler::default()),
+            sla_tracker: Arc::new(SlaTracker::default()),
+            sentiment_tracker: Arc::new(SentimentEscalationTracker::new()),
+            geo_risk: Arc::new(GeoRiskTracker::new(
+                Arc::new(BuiltinGeoLocator),
+                crate::config::GeoRiskConfig::default(),
+            )),
+            geoip: None,
+            assignment_mode: AssignmentMode::Auto,
+            new_customer_alert_count: 3,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            send_queue_size: DEFAULT_SEND_QUEUE_SIZE,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+            session_idle_timeout: DEFAULT_SESSION_IDLE_TIMEOUT,
+            message_counters: Arc::new(MessageTypeCounters::default()),
+            analytics_cache: Arc::new(AnalyticsCache::default()),
+            greeting: Arc::new(GreetingConfig::default()),
+            content_filter: ContentFilter::default(),
+            bandwidth: Arc::new(BandwidthCounters::default()),
+            bandwidth_alert_bytes_per_sec: 0,
+            acks: Arc::new(DeliveryAckTracker::default()),
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            unacked_alert_rate: 0.0,
+            latency_histograms: Arc::new(Histograms::default()),
         }
     }
 
+    /// 设置客户分配方式；manual 模式下新客户提醒只推送给负载最低的 alert_count 个客服
+    pub fn with_assignment(mut self, mode: AssignmentMode, alert_count: usize) -> Self {
+        self.assignment_mode = mode;
+        self.new_customer_alert_count = alert_count;
+        self
+    }
+
+    /// 设置连接数上限（0 表示不限制）与每个连接的待发送队列长度
+    pub fn with_connection_limits(mut self, max_connections: usize, send_queue_size: usize) -> Self {
+        self.max_connections = max_connections;
+        self.send_queue_size = send_queue_size.max(1);
+        self
+    }
+
+    /// 设置同一用户重复连接时的处理方式
+    pub fn with_duplicate_connection_policy(mut self, policy: DuplicateConnectionPolicy) -> Self {
+        self.duplicate_connection_policy = policy;
+        self
+    }
+
+    /// 设置排队超时时长（为0时一直排队）
+    pub fn with_queue_timeout(mut self, queue_timeout: std::time::Duration) -> Self {
+        self.queue_timeout = queue_timeout;
+        self
+    }
+
+    /// 设置会话空闲超时时长（为0时不自动结束会话）
+    pub fn with_session_idle_timeout(mut self, session_idle_timeout: std::time::Duration) -> Self {
+        self.session_idle_timeout = session_idle_timeout;
+        self
+    }
+
+    /// 聊天消息改用指定的存储后端，如 S3Storage
+    pub fn with_message_store(mut self, message_store: Arc<dyn Storage>) -> Self {
+        self.message_store = message_store;
+        self
+    }
+
+    /// 客户分配到客服后以客服身份自动发送问候语
+    pub fn with_greeting(mut self, config: GreetingConfig) -> Self {
+        self.greeting = Arc::new(config);
+        self
+    }
+
+    /// 缓存仪表盘统计接口的结果，有效期内相同查询直接返回
+    pub fn with_analytics_cache(mut self, config: &crate::config::CacheConfig) -> Self {
+        self.analytics_cache = Arc::new(AnalyticsCache::new(config));
+        self
+    }
+
+    /// 使用配置中的首响时限与达成率目标计算 SLA
+    pub fn with_sla(mut self, config: crate::config::SlaConfig) -> Self {
+        self.sla_tracker = Arc::new(SlaTracker::new(&config));
+        self
+    }
+
+    /// 使用配置中的异地登录风控策略
+    pub fn with_geo_risk(mut self, config: crate::config::GeoRiskConfig) -> Self {
+        self.geo_risk = Arc::new(GeoRiskTracker::new(Arc::new(BuiltinGeoLocator), config));
+        self
+    }
+
+    /// 加载 MaxMind GeoIP 数据库，为连接事件补充地理位置；未配置路径或文件不存在时跳过
+    pub fn with_geoip_database(mut self, path: Option<&str>) -> Self {
+        self.geoip = path
+            .and_then(MaxMindGeoLocator::open)
+            .map(|locator| Arc::new(locator) as Arc<dyn GeoLocator>);
+        self
+    }
+
+    /// 使用配置中的内容过滤策略和敏感词表
+    pub fn with_content_filter(mut self, filter: ContentFilter) -> Self {
+        self.content_filter = filter;
+        self
+    }
+
+    /// 设置单连接带宽告警阈值（字节/秒，0 表示不检查）
+    pub fn with_bandwidth_alert(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth_alert_bytes_per_sec = bytes_per_sec;
+        self
+    }
+
+    /// 设置消息确认超时与未确认率告警阈值（0 表示不告警）
+    pub fn with_ack_alert(mut self, ack_timeout: std::time::Duration, unacked_alert_rate: f64) -> Self {
+        self.ack_timeout = ack_timeout;
+        self.unacked_alert_rate = unacked_alert_rate;
+        self
+    }
+
+    /// 设置离线消息队列的长度上限与保留时长
+    pub fn with_message_queue_config(self, config: crate::config::MessageQueueConfig) -> Self {
+        self.message_queue.set_config(config);
+        self
+    }
+
+    /// 与AI管理器、指标注册中心共用耗时直方图
+    pub fn with_latency_histograms(mut self, histograms: Arc<Histograms>) -> Self {
+        self.latency_histograms = histograms;
+        self
+    }
+
+    /// 设置聊天消息重排序窗口，Duration::ZERO 表示收到即投递
+    pub fn with_reorder_window(mut self, window: std::time::Duration) -> Self {
+        self.reorder_window = window;
+        self
+    }
+
+    /// 设置协议层 ping 间隔，超过两个间隔收不到 pong 时清理连接；Duration::ZERO 表示关闭
+    pub fn with_ping_interval(mut self, interval: std::time::Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// 接入AI管理器与语音文件管理器，用于语音消息的服务端转写
+    pub fn with_voice_transcription(
+        mut self,
+        ai_manager: Arc<AIManager>,
+        voice_manager: Arc<VoiceMessageManager>,
+    ) -> Self {
+        self.ai_manager = Some(ai_manager);
+        self.voice_manager = Some(voice_manager);
+        self
+    }
+
+    /// 使用配置中的压缩开关与阈值，客户端压缩帧解压后不得超过单条消息大小上限
+    pub fn with_compression(mut self, enabled: bool, threshold: usize, max_message_size: usize) -> Self {
+        self.compression = CompressionConfig {
+            enabled,
+            min_size: threshold,
+            max_decompressed_size: max_message_size,
+            ..CompressionConfig::default()
+        };
+        self
+    }
+
     // 处理新的WebSocket连接
     pub async fn handle_connection(
         &self,
@@ -107,8 +492,17 @@ impl WebSocketManager {
         user_name: String,
         user_type: UserType,
         zhanghao: Option<String>,
-        _target_id: Option<String>,
+        options: ConnectionOptions,
     ) -> Result<()> {
+        let ConnectionOptions {
+            compression_supported,
+            client_key,
+            protocol_version,
+            suspicious_reason,
+            client_ip,
+            user_agent,
+            guest_token,
+        } = options;
         tracing::info!(
             "🔗 开始建立WebSocket连接: user_id={}, user_name={}, user_type={:?}",
             user_id,
@@ -117,7 +511,7 @@ impl WebSocketManager {
         );
 
         let (mut ws_sender, mut ws_receiver) = websocket.split();
-        let (tx, mut rx) = mpsc::unbounded_channel::<AppMessage>();
+        let (tx, mut rx) = mpsc::channel::<SharedMessage>(self.send_queue_size);
 
         // 创建用户连接信息
         let user_connection = UserConnection {
@@ -128,24 +522,163 @@ impl WebSocketManager {
             connected_at: Utc::now(),
             last_heartbeat: Utc::now(),
             status: OnlineStatus::Online,
+            suspicious_reason,
+            client_ip,
+            user_agent,
         };
 
         tracing::info!("📝 添加用户连接信息: {}", user_id);
 
-        // 添加到连接管理器
-        {
-            let mut connections = self.connections.write().await;
-            connections.insert(user_id.clone(), user_connection.clone());
-        }
-
-        // 添加到发送器管理器
-        {
-            let mut senders = self.senders.write().await;
-            senders.insert(user_id.clone(), tx.clone());
+        // 添加到连接管理器；达到连接上限时拒绝新用户，已在线用户重连不受限制
+        let close_signal = Arc::new(CloseSignal::default());
+        let replaced = match self.register_connection(&user_connection, &tx, &close_signal).await {
+            Ok(replaced) => replaced,
+            Err(reason) => {
+                let _ = ws_sender.send(reason.frame()).await;
+                let _ = ws_sender.close().await;
+                return Ok(());
+            }
+        };
+        // 旧连接被替换：记为断开，但不做下线清理和广播，用户仍在线
+        if let Some(previous) = replaced {
+            tracing::info!("🔁 用户{}建立了新连接，关闭旧连接", user_id);
+            self.record_connection_event(ConnectionEvent::disconnect(&previous, Utc::now())).await;
         }
+        self.bandwidth.open(&user_id);
+        self.record_connection_event(ConnectionEvent::connect(&user_connection)).await;
 
         tracing::info!("📡 用户连接信息已保存: {}", user_id);
 
+        // 启动发送任务：欢迎、历史和离线消息都经有界队列发送，需先开始消费
+        let compressor = Arc::new(std::sync::Mutex::new(AdaptiveCompressor::new(self.compression.clone())));
+        // 客户端提供公钥时做 X25519 密钥协商，收发双方共用同一个按策略轮换的密钥序列
+        let key_exchange = client_key.and_then(|client_key| {
+            let exchange = KeyExchange::new();
+            let server_key = exchange.public_key();
+            match exchange.complete(client_key, KeyRotationPolicy::default()) {
+                Ok(cipher) => Some((Arc::new(std::sync::Mutex::new(cipher)), server_key)),
+                Err(e) => {
+                    tracing::warn!("会话密钥协商失败，连接不加密: user_id={}, error={}", user_id, e);
+                    None
+                }
+            }
+        });
+        let (session_cipher, server_key) = key_exchange.unzip();
+        let session_cipher_send = session_cipher.clone();
+        let user_id_send = user_id.clone();
+        let status_manager = self.clone();
+        // 协议层心跳：代理会断开长时间无数据的TCP连接，应用层Heartbeat无法覆盖
+        let last_pong = Arc::new(std::sync::Mutex::new(std::time::Instant::now()));
+        let last_pong_send = last_pong.clone();
+        let close_signal_send = close_signal.clone();
+        let ping_interval = self.ping_interval;
+        let send_task = tokio::spawn(async move {
+            let ping_enabled = !ping_interval.is_zero();
+            let ping_period = ping_interval.max(std::time::Duration::from_millis(1));
+            let mut ping_timer = tokio::time::interval_at(tokio::time::Instant::now() + ping_period, ping_period);
+
+            loop {
+                let message = tokio::select! {
+                    // 优先处理关闭信号：强制断开时发送通道也会随之关闭，需先发出关闭帧
+                    biased;
+                    reason = close_signal_send.closed() => {
+                        let _ = ws_sender.send(reason.frame()).await;
+                        let _ = ws_sender.close().await;
+                        tracing::info!("🔌 连接已关闭: {} ({})", user_id_send, reason.reason());
+                        return true;
+                    }
+                    message = rx.recv() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                    _ = ping_timer.tick(), if ping_enabled => {
+                        let since_pong = last_pong_send.lock().map(|t| t.elapsed()).unwrap_or_default();
+                        if since_pong > ping_interval * PONG_TIMEOUT_INTERVALS {
+                            tracing::warn!("💔 {} 已{:?}未响应pong，判定连接已断开", user_id_send, since_pong);
+                            status_manager.cleanup_connection_if_current(&user_id_send, &close_signal_send).await;
+                            let _ = ws_sender.send(CloseReason::HeartbeatTimeout.frame()).await;
+                            let _ = ws_sender.close().await;
+                            return true;
+                        }
+                        if let Err(e) = ws_sender.send(WsMessage::ping(Vec::new())).await {
+                            tracing::error!("❌ 发送ping失败给 {}: error={:?}", user_id_send, e);
+                            break;
+                        }
+                        continue;
+                    }
+                };
+
+                let message_type = message.type_name();
+
+                tracing::info!("📤 准备发送消息给 {}: 类型={}", user_id_send, message_type);
+
+                if let Ok(json) = serde_json::to_string(message.as_ref()) {
+                    let is_welcome = matches!(*message, AppMessage::Welcome { .. });
+                    // 加密会话中除携带密钥材料的欢迎消息外都以 Encrypted 帧发送，密文不再压缩
+                    let json = match &session_cipher_send {
+                        Some(cipher) if !is_welcome => match encrypt_outbound_frame(cipher, &json) {
+                            Ok(encrypted) => encrypted,
+                            Err(e) => {
+                                tracing::error!("❌ 消息加密失败，不发送 {}: 类型={}, error={:?}", user_id_send, message_type, e);
+                                continue;
+                            }
+                        },
+                        _ => json,
+                    };
+                    // 客户端声明支持时，超过阈值的消息压缩后以二进制帧发送；欢迎消息始终为文本
+                    let compressed = if compression_supported && session_cipher_send.is_none() && !is_welcome {
+                        match compress_outbound_frame(&compressor, &json).await {
+                            Ok(compressed) => compressed,
+                            Err(e) => {
+                                tracing::warn!("⚠️ 消息压缩失败，按文本发送 {}: {:?}", user_id_send, e);
+                                None
+                            }
+                        }
+                    } else {
+                        None
+                    };
+                    let final_message = match compressed {
+                        Some(bytes) => WsMessage::binary(bytes),
+                        None => WsMessage::text(json),
+                    };
+                    let tracked = tracked_delivery(&message, &user_id_send);
+                    let frame_bytes = final_message.as_bytes().len();
+
+                    match ws_sender.send(final_message).await { Err(e) => {
+                        tracing::error!(
+                            "❌ 发送消息失败给 {}: 类型={}, error={:?}",
+                            user_id_send,
+                            message_type,
+                            e
+                        );
+                        if let Some((message_id, sender)) = tracked {
+                            status_manager.acks.failed(&message_id);
+                            status_manager
+                                .track_delivery_status(&message_id, &sender, &user_id_send, MessageStatus::Failed)
+                                .await;
+                        }
+                        break;
+                    } _ => {
+                        tracing::info!("✅ 成功发送消息给 {}: 类型={}", user_id_send, message_type);
+                        status_manager.bandwidth.record(&user_id_send, OUTBOUND, frame_bytes);
+                        if let Some((message_id, sender)) = tracked {
+                            status_manager
+                                .track_delivery_status(&message_id, &sender, &user_id_send, MessageStatus::Delivered)
+                                .await;
+                        }
+                    }}
+                } else {
+                    tracing::error!(
+                        "❌ 序列化消息失败给 {}: 类型={}",
+                        user_id_send,
+                        message_type
+                    );
+                }
+            }
+            tracing::info!("📤 发送任务结束: {}", user_id_send);
+            false
+        });
+
         // 更新Redis中的在线状态
         tracing::info!("🔄 更新Redis在线状态: {}", user_id);
         {
@@ -166,22 +699,6 @@ impl WebSocketManager {
             }}
         }
 
-        // 🚀 启动Redis频道订阅 - 企业级实时事件驱动功能
-        tracing::info!("📡 启动Redis频道订阅: {}", user_id);
-        let ws_manager_clone = Arc::new(self.clone());
-        let user_id_clone = user_id.clone();
-        let channels = vec![
-            format!("user:{}:messages", user_id),        // 用户私有消息频道
-            format!("user:{}:notifications", user_id),   // 用户通知频道
-            "system:broadcasts".to_string(),             // 系统广播频道
-            format!("session:{}:events", user_id),       // 会话事件频道
-        ];
-        
-        // 在后台启动Redis订阅
-        tokio::spawn(async move {
-            subscribe_redis_channels_for_user(user_id_clone, channels, ws_manager_clone).await;
-        });
-
         // 发送欢迎消息
         tracing::info!("🎉 发送欢迎消息: {}", user_id);
         let welcome_msg = AppMessage::Welcome {
@@ -190,8 +707,15 @@ impl WebSocketManager {
             user_type: user_type.clone(),
             zhanghao: zhanghao.clone(),
             timestamp: Utc::now(),
+            compression: compression_supported.then(|| FRAME_COMPRESSION.to_string()),
+            encryption: session_cipher.as_ref().map(|_| SESSION_ENCRYPTION.to_string()),
+            server_key,
+            token: guest_token,
+            version: Some(protocol_version),
+            min_version: Some(MIN_PROTOCOL_VERSION),
+            max_version: Some(CURRENT_PROTOCOL_VERSION),
         };
-        if let Err(e) = tx.send(welcome_msg) {
+        if let Err(e) = tx.try_send(Arc::new(welcome_msg)) {
             tracing::error!("❌ 发送欢迎消息失败: {}, error: {:?}", user_id, e);
             return Err(anyhow::anyhow!("Failed to send welcome message"));
         }
@@ -208,6 +732,16 @@ impl WebSocketManager {
             tracing::warn!("⚠️ 发送历史消息失败: {}, error: {:?}", user_id, e);
         }
 
+        // 推送离线期间积压的消息
+        if let Err(e) = self.deliver_offline_messages(&user_id, &tx).await {
+            tracing::warn!("⚠️ 推送离线消息失败: {}, error: {:?}", user_id, e);
+        }
+
+        // 补发离线期间的系统广播
+        if let Err(e) = self.deliver_missed_broadcasts(&user_id, &tx).await {
+            tracing::warn!("⚠️ 补发系统广播失败: {}, error: {:?}", user_id, e);
+        }
+
         // 广播用户加入通知
         tracing::info!("📢 广播用户加入通知: {}", user_id);
         if let Err(e) = self
@@ -229,6 +763,15 @@ impl WebSocketManager {
 
         // 根据用户类型建立会话
         match user_type {
+            UserType::Kehu if self.assignment_mode == AssignmentMode::Manual => {
+                // 手动分配：客户进入等待队列，只提醒最空闲的几位客服
+                if let Err(e) = self.redis.read().await.add_to_waiting_queue(&user_id).await {
+                    tracing::warn!("⚠️ 客户加入等待队列失败: {}, error: {:?}", user_id, e);
+                }
+                self.sla_tracker.customer_queued(&user_id, Utc::now());
+                self.alert_idle_kefu(&user_id, &user_name).await;
+                self.broadcast_customer_list().await?;
+            }
             UserType::Kehu => {
                 // 客户连接：立即寻找并分配客服
                 tracing::info!("🔍 客户{}请求分配客服", user_id);
@@ -260,12 +803,19 @@ impl WebSocketManager {
                         }
                     }}
                 } else {
-                    tracing::warn!("⚠️ 没有可用客服，客户 {} 暂时无法分配", user_id);
+                    // 进入等待队列，客服上线时按排队先后接入
+                    tracing::warn!("⚠️ 没有可用客服，客户 {} 进入等待队列", user_id);
+                    if let Err(e) = self.redis.read().await.add_to_waiting_queue(&user_id).await {
+                        tracing::warn!("⚠️ 客户加入等待队列失败: {}, error: {:?}", user_id, e);
+                    }
+                    self.sla_tracker.customer_queued(&user_id, Utc::now());
                 }
             }
             UserType::Kefu => {
-                // 客服连接：检查是否有等待的客户
-                if let Ok(waiting_kehu) = self.find_waiting_customer().await {
+                // 客服连接：检查是否有等待的客户（手动分配模式下由客服自行接入）
+                if self.assignment_mode == AssignmentMode::Manual {
+                    tracing::info!("🙋 手动分配模式，客服{}自行接入等待客户", user_id);
+                } else if let Ok(waiting_kehu) = self.find_waiting_customer().await {
                     tracing::info!("🤝 为等待客户分配客服: {} <-> {}", waiting_kehu, user_id);
                     if let Err(e) = self.establish_session(&waiting_kehu, &user_id, &None).await {
                         tracing::warn!("⚠️ 建立会话失败: {}, error: {:?}", waiting_kehu, e);
@@ -283,71 +833,49 @@ impl WebSocketManager {
         let senders_clone = self.senders.clone();
         let redis_clone = self.redis.clone();
         let storage_clone = self.storage.clone();
-        let _compressor_clone_send = self.compressor.clone();
-        let compressor_clone_recv = self.compressor.clone();
         let user_id_clone = user_id.clone();
 
-        // 启动发送任务
-        let user_id_send = user_id.clone();
-        let send_task = tokio::spawn(async move {
-            while let Some(message) = rx.recv().await {
-                // 添加消息发送日志
-                let message_type = match &message {
-                    AppMessage::Chat { .. } => "Chat",
-                    AppMessage::Welcome { .. } => "Welcome",
-                    AppMessage::History { .. } => "History",
-                    AppMessage::HistoryRequest { .. } => "HistoryRequest",
-                    AppMessage::OnlineUsers { .. } => "OnlineUsers",
-                    AppMessage::Heartbeat { .. } => "Heartbeat",
-                    AppMessage::Typing { .. } => "Typing",
-                    AppMessage::System { .. } => "System",
-                    AppMessage::UserJoined { .. } => "UserJoined",
-                    AppMessage::UserLeft { .. } => "UserLeft",
-                    AppMessage::Status { .. } => "Status",
-                    AppMessage::Error { .. } => "Error",
-                    AppMessage::HtmlTemplate { .. } => "HtmlTemplate",
-                    AppMessage::HtmlCallback { .. } => "HtmlCallback",
-                    AppMessage::Voice { .. } => "VoiceMessage",
-                };
-
-                tracing::info!("📤 准备发送消息给 {}: 类型={}", user_id_send, message_type);
-
-                if let Ok(json) = serde_json::to_string(&message) {
-                    // 暂时禁用压缩，直接发送JSON消息
-                    let final_message = json;
-
-                    match ws_sender.send(WsMessage::text(final_message)).await { Err(e) => {
-                        tracing::error!(
-                            "❌ 发送消息失败给 {}: 类型={}, error={:?}",
-                            user_id_send,
-                            message_type,
-                            e
-                        );
-                        break;
-                    } _ => {
-                        tracing::info!("✅ 成功发送消息给 {}: 类型={}", user_id_send, message_type);
-                    }}
-                } else {
-                    tracing::error!(
-                        "❌ 序列化消息失败给 {}: 类型={}",
-                        user_id_send,
-                        message_type
-                    );
-                }
-            }
-            tracing::info!("📤 发送任务结束: {}", user_id_send);
-        });
-
         // 启动接收任务
         let self_clone = Arc::new(WebSocketManager {
             connections: connections_clone,
             senders: senders_clone,
             redis: redis_clone,
             storage: storage_clone,
-            compressor: compressor_clone_recv,
+            message_store: self.message_store.clone(),
+            compression: self.compression.clone(),
             // 复用现有的message_queue和status_syncer
             message_queue: self.message_queue.clone(),
             status_syncer: self.status_syncer.clone(),
+            typing_timers: self.typing_timers.clone(),
+            close_signals: self.close_signals.clone(),
+            duplicate_connection_policy: self.duplicate_connection_policy,
+            reorder_buffers: self.reorder_buffers.clone(),
+            reorder_window: self.reorder_window,
+            ping_interval: self.ping_interval,
+            ai_manager: self.ai_manager.clone(),
+            voice_manager: self.voice_manager.clone(),
+            connection_history: self.connection_history.clone(),
+            auto_scaler: self.auto_scaler.clone(),
+            sla_tracker: self.sla_tracker.clone(),
+            sentiment_tracker: self.sentiment_tracker.clone(),
+            geo_risk: self.geo_risk.clone(),
+            geoip: self.geoip.clone(),
+            assignment_mode: self.assignment_mode,
+            new_customer_alert_count: self.new_customer_alert_count,
+            max_connections: self.max_connections,
+            send_queue_size: self.send_queue_size,
+            queue_timeout: self.queue_timeout,
+            session_idle_timeout: self.session_idle_timeout,
+            message_counters: self.message_counters.clone(),
+            analytics_cache: self.analytics_cache.clone(),
+            greeting: self.greeting.clone(),
+            content_filter: self.content_filter.clone(),
+            bandwidth: self.bandwidth.clone(),
+            bandwidth_alert_bytes_per_sec: self.bandwidth_alert_bytes_per_sec,
+            acks: self.acks.clone(),
+            ack_timeout: self.ack_timeout,
+            unacked_alert_rate: self.unacked_alert_rate,
+            latency_histograms: self.latency_histograms.clone(),
         });
 
         let receive_task = tokio::spawn(async move {
@@ -355,7 +883,13 @@ impl WebSocketManager {
 
             while let Some(result) = ws_receiver.next().await {
                 match result {
+                    Ok(msg) if msg.is_pong() => {
+                        if let Ok(mut last_pong) = last_pong.lock() {
+                            *last_pong = std::time::Instant::now();
+                        }
+                    }
                     Ok(msg) => {
+                        self_clone.bandwidth.record(&user_id_clone, INBOUND, msg.as_bytes().len());
                         tracing::info!(
                             "📥 收到WebSocket消息从 {}: 长度={}",
                             user_id_clone,
@@ -366,9 +900,14 @@ impl WebSocketManager {
                             }
                         );
 
-                        if let Err(e) = self_clone.handle_message(msg, &user_id_clone).await {
-                            tracing::error!("❌ 处理消息失败从 {}: error={:?}", user_id_clone, e);
+                        let timer = request_timer(&self_clone.latency_histograms, WS_MESSAGE_HANDLING_MS);
+                        if let Err(e) = self_clone
+                            .handle_message(msg, &user_id_clone, protocol_version, session_cipher.as_deref())
+                            .await
+                        {
+                            log_message_error(&user_id_clone, &e);
                         }
+                        timer.finish();
                     }
                     Err(e) => {
                         tracing::error!("❌ WebSocket错误从 {}: {:?}", user_id_clone, e);
@@ -379,12 +918,18 @@ impl WebSocketManager {
 
             tracing::info!("📥 接收任务结束: {}", user_id_clone);
             // 清理连接
-            self_clone.cleanup_connection(&user_id_clone).await;
+            self_clone.cleanup_connection_if_current(&user_id_clone, &close_signal).await;
         });
 
         // 等待任务完成
+        let receive_abort = receive_task.abort_handle();
         tokio::select! {
-            _ = send_task => {},
+            timed_out = send_task => {
+                // pong 超时、被新连接替换或被强制断开时连接已关闭，接收任务可能仍阻塞在读取上，直接终止
+                if matches!(timed_out, Ok(true)) {
+                    receive_abort.abort();
+                }
+            },
             _ = receive_task => {},
         }
 
@@ -392,28 +937,59 @@ impl WebSocketManager {
     }
 
     // 处理WebSocket消息 - 生产级优化
-    async fn handle_message(&self, message: WsMessage, user_id: &str) -> Result<()> {
-        if message.is_text() {
-            let text = message
-                .to_str()
-                .map_err(|_| anyhow::anyhow!("Invalid UTF-8"))?;
+    async fn handle_message(
+        &self,
+        message: WsMessage,
+        user_id: &str,
+        protocol_version: u32,
+        session_cipher: Option<&std::sync::Mutex<SessionCipher>>,
+    ) -> Result<()> {
+        if message.is_text() || message.is_binary() {
+            // 二进制帧为gzip压缩的JSON，先解压
+            let decompressed_text = if message.is_binary() {
+                MessageCompressor::new(self.compression.clone()).decompress_binary(message.as_bytes())?
+            } else {
+                message
+                    .to_str()
+                    .map_err(|_| anyhow::anyhow!("Invalid UTF-8"))?
+                    .to_string()
+            };
+            // 加密会话只接受 Encrypted 帧
+            let decompressed_text = match session_cipher {
+                Some(cipher) => decrypt_inbound_frame(cipher, &decompressed_text)?,
+                None => decompressed_text,
+            };
 
             // 更新心跳时间
             self.update_heartbeat(user_id).await;
 
-            // 暂时禁用解压缩，直接使用原始文本
-            let decompressed_text = text.to_string();
-
             tracing::debug!("📨 收到原始消息: {} -> '{}'", user_id, decompressed_text);
 
-            // 生产级消息解析：优先尝试JSON解析
-            match serde_json::from_str::<AppMessage>(&decompressed_text) {
+            // 生产级消息解析：优先尝试JSON解析，旧版本格式先升级为当前格式
+            let parsed = match serde_json::from_str::<serde_json::Value>(&decompressed_text) {
+                Ok(value) => match upgrade_message(value, protocol_version) {
+                    Ok(value) => serde_json::from_value::<AppMessage>(value),
+                    Err(e) => {
+                        tracing::warn!("⚠️ 消息协议版本不受支持: {} {}", user_id, e);
+                        let error = AppMessage::Error {
+                            message: e.to_string(),
+                            code: 400,
+                            timestamp: Utc::now(),
+                        };
+                        return self.send_to_user(user_id, error).await.map_err(Into::into);
+                    }
+                },
+                Err(e) => Err(e),
+            };
+            match parsed {
                 Ok(app_message) => {
                     tracing::info!("✅ 成功解析为AppMessage: {:?}", app_message);
+                    self.message_counters.record(INBOUND, app_message.type_name());
                     self.process_app_message(app_message, user_id).await?;
                 }
                 Err(parse_error) => {
                     tracing::warn!("⚠️ JSON解析失败: {}, 当作文本消息处理", parse_error);
+                    self.message_counters.record(INBOUND, "Chat");
                     // 如果不是标准消息格式，当作文本聊天消息处理
                     self.handle_text_message(&decompressed_text, user_id)
                         .await?;
@@ -435,7 +1011,28 @@ impl WebSocketManager {
                 filename,
                 timestamp,
                 url,
+                thumbnail_url,
+                canned_response_id,
+                ..
             } => {
+                // 引用快捷回复时以快捷回复内容为准，存储和统计的都是实际发出的文本
+                let content = match canned_response_id {
+                    Some(canned_response_id) => {
+                        match self.expand_canned_response(user_id, &canned_response_id).await? {
+                            Some(expanded) => expanded,
+                            None => {
+                                tracing::warn!("⚠️ 用户{}引用了不可用的快捷回复: {}", user_id, canned_response_id);
+                                let error = AppMessage::Error {
+                                    message: "快捷回复不存在或无权使用".to_string(),
+                                    code: 404,
+                                    timestamp: Utc::now(),
+                                };
+                                return self.send_to_user(user_id, error).await.map_err(Into::into);
+                            }
+                        }
+                    }
+                    None => content,
+                };
                 self.handle_chat_message(
                     id,
                     from,
@@ -445,6 +1042,7 @@ impl WebSocketManager {
                     filename,
                     timestamp,
                     url,
+                    thumbnail_url,
                     user_id,
                 )
                 .await?;
@@ -455,7 +1053,11 @@ impl WebSocketManager {
                 is_typing,
                 timestamp,
             } => {
-                self.handle_typing_message(from, to, is_typing, timestamp)
+                // 打字状态以连接的认证身份为准，不信任客户端填写的 from
+                if from != user_id {
+                    tracing::warn!("⚠️ 打字消息的from与连接身份不符，以连接为准: {} != {}", from, user_id);
+                }
+                self.handle_typing_message(user_id.to_string(), to, is_typing, timestamp)
                     .await?;
             }
             AppMessage::Heartbeat {
@@ -467,35 +1069,61 @@ impl WebSocketManager {
             }
             AppMessage::HistoryRequest {
                 customer_id,
-                limit: _limit,
+                limit,
+                before_timestamp,
+                before_id,
+                since_seq,
                 timestamp: _timestamp,
             } => {
-                tracing::info!("📚 客服{}请求客户{}的历史消息", user_id, customer_id);
-                
-                // 验证是客服用户
-                let user_connection = {
+                tracing::info!("📚 {}请求客户{}的历史消息", user_id, customer_id);
+
+                let user_type = {
                     let connections = self.connections.read().await;
-                    connections.get(user_id).cloned()
+                    connections.get(user_id).map(|connection| connection.user_type.clone())
                 };
-                
-                if let Some(connection) = user_connection {
-                    if connection.user_type == UserType::Kefu {
-                        if let Some(sender) = self.get_user_sender(user_id).await {
-                            self.send_customer_history_messages(user_id, &customer_id, &sender).await?;
-                        }
-                    } else {
-                        tracing::warn!("⚠️ 非客服用户尝试请求历史消息: {}", user_id);
+
+                // 客服可读取任一客户的会话；客户只能读取自己所在的会话，customer_id 以连接身份为准
+                let conversation = match user_type {
+                    Some(UserType::Kefu) => Some((user_id.to_string(), customer_id)),
+                    Some(UserType::Kehu) => self
+                        .customer_conversation_kefu(user_id)
+                        .await
+                        .map(|kefu_id| (kefu_id, user_id.to_string())),
+                    None => {
+                        tracing::warn!("⚠️ 用户连接不存在: {}", user_id);
+                        None
+                    }
+                };
+
+                if let Some((kefu_id, customer_id)) = conversation {
+                    if let Some(sender) = self.get_user_sender(user_id).await {
+                        let limit = limit.unwrap_or(HISTORY_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE as usize);
+                        let before = before_timestamp.map(|timestamp| HistoryCursor {
+                            timestamp,
+                            message_id: before_id.unwrap_or_default(),
+                        });
+                        self.send_customer_history_messages(
+                            &kefu_id,
+                            &customer_id,
+                            before.as_ref(),
+                            since_seq,
+                            limit,
+                            &sender,
+                        )
+                        .await?;
                     }
-                } else {
-                    tracing::warn!("⚠️ 用户连接不存在: {}", user_id);
                 }
             }
             AppMessage::Status {
-                user_id,
+                user_id: claimed_user_id,
                 status,
                 timestamp: _timestamp, // 使用下划线前缀表示未使用
             } => {
-                self.handle_status_message(user_id, status).await?;
+                // 只能修改自己的状态，消息中的 user_id 以连接身份为准
+                if claimed_user_id != user_id {
+                    tracing::warn!("⚠️ 用户{}尝试修改{}的在线状态，已按本人处理", user_id, claimed_user_id);
+                }
+                self.handle_status_message(user_id.to_string(), status).await?;
             }
             AppMessage::OnlineUsers { users } => {
                 // 处理在线用户列表消息
@@ -629,6 +1257,54 @@ impl WebSocketManager {
                 };
                 self.handle_voice_message(voice_params, user_id).await?;
             }
+            AppMessage::ReadReceipt { message_ids, .. } => {
+                self.handle_read_receipt(message_ids, user_id).await;
+            }
+            AppMessage::Ack { message_ids, .. } => {
+                self.handle_delivery_ack(message_ids, user_id).await;
+            }
+            AppMessage::Rating {
+                session_id,
+                score,
+                comment,
+                timestamp,
+            } => {
+                self.handle_rating(session_id, score, comment, timestamp, user_id)
+                    .await?;
+            }
+            AppMessage::Leave { contact, content, .. } => {
+                self.handle_leave_message(contact, content, user_id).await?;
+            }
+            AppMessage::Reaction {
+                message_id,
+                emoji,
+                user_id: claimed_user_id,
+                action,
+                ..
+            } => {
+                // 回应人以连接身份为准
+                if claimed_user_id != user_id {
+                    tracing::warn!("⚠️ 用户{}以{}的身份发送表情回应，已按本人处理", user_id, claimed_user_id);
+                }
+                self.handle_reaction(message_id, emoji, action, user_id).await?;
+            }
+            AppMessage::CannedResponsesRequest { prefix, limit, .. } => {
+                let is_kefu = matches!(
+                    self.connections.read().await.get(user_id),
+                    Some(conn) if conn.user_type == UserType::Kefu
+                );
+                if !is_kefu {
+                    tracing::warn!("⚠️ 非客服用户尝试查询快捷回复: {}", user_id);
+                    return Ok(());
+                }
+                let limit = limit.unwrap_or(DEFAULT_MATCH_LIMIT).clamp(1, DEFAULT_MATCH_LIMIT * 5);
+                let responses = self.find_canned_responses(user_id, &prefix, limit).await?;
+                self.send_to_user(user_id, AppMessage::CannedResponses { prefix, responses })
+                    .await?;
+            }
+            AppMessage::InternalChat { id, to, content, .. } => {
+                self.handle_internal_chat(id, to, content, user_id).await?;
+            }
             _ => {
                 tracing::warn!("Unhandled message type from user {}", user_id);
             }
@@ -653,6 +1329,9 @@ impl WebSocketManager {
             if let Some(to) = partner_id {
                 tracing::info!("💬 找到聊天伙伴: {} -> {}", user_id, to);
 
+                let Some(text) = self.filter_chat_content(user_id, text).await? else {
+                    return Ok(());
+                };
                 let message_id = Uuid::new_v4().to_string();
                 let url = format!("#{}", chrono::Utc::now().timestamp_millis());
                 let timestamp = Utc::now();
@@ -661,32 +1340,40 @@ impl WebSocketManager {
                     id: Some(message_id.clone()),
                     from: user_id.to_string(),
                     to: Some(to.clone()),
-                    content: text.to_string(),
+                    content: text.clone(),
                     content_type: Some(ContentType::Text),
                     filename: None,
                     timestamp,
                     url: Some(url.clone()),
+                    thumbnail_url: None,
+                    seq: None,
                 };
 
                 // 保存到本地存储
-                self.storage.save_message(&chat_message)?;
+                let seq = self.message_store.save_message(&chat_message).await?.into_message().seq;
                 tracing::info!("💾 消息已保存到本地存储");
+                self.record_sla_message(&user_conn.user_type, user_id, &to, timestamp);
+                self.touch_session(&user_conn.user_type, user_id, &to, timestamp).await;
 
                 // 创建应用消息
                 let app_message = AppMessage::Chat {
                     id: Some(message_id),
                     from: user_id.to_string(),
                     to: Some(to.clone()),
-                    content: text.to_string(),
+                    content: text,
                     content_type: Some(ContentType::Text),
                     filename: None,
                     timestamp,
                     url: Some(url),
+                    thumbnail_url: None,
+                    out_of_order: None,
+                    seq,
+                    canned_response_id: None,
                 };
 
-                // 发送给接收者
+                // 发送给接收者，与聊天消息一样经重排序窗口投递
                 tracing::info!("📤 转发消息给接收者: {}", to);
-                self.send_to_user(&to, app_message.clone()).await?;
+                self.deliver_in_order(user_id, &to, app_message.clone(), timestamp).await?;
 
                 // 回显给发送者
                 tracing::info!("📤 回显消息给发送者: {}", user_id);
@@ -713,8 +1400,9 @@ impl WebSocketManager {
         filename: Option<String>,
         timestamp: chrono::DateTime<Utc>,
         url: Option<String>,
+        thumbnail_url: Option<String>,
         current_user_id: &str,
-    ) -> Result<()> {
+    ) -> Result<(), WebSocketError> {
         // 生产级用户ID处理：确保发送者ID与当前连接用户ID一致
         let verified_from = current_user_id.to_string();
 
@@ -727,6 +1415,20 @@ impl WebSocketManager {
 
         let message_id = id.unwrap_or_else(|| Uuid::new_v4().to_string());
         let message_url = url.unwrap_or_else(|| format!("#{}", timestamp.timestamp_millis()));
+        // 只转发本服务生成的附件缩略图地址
+        let thumbnail_url = thumbnail_url.filter(|url| {
+            matches!(content_type, Some(ContentType::File) | Some(ContentType::Image))
+                && is_thumbnail_url(url)
+        });
+        // 文本消息在存储和转发前过滤，被拦截时只通知发送方
+        let content = if matches!(content_type, None | Some(ContentType::Text)) {
+            match self.filter_chat_content(current_user_id, &content).await? {
+                Some(content) => content,
+                None => return Ok(()),
+            }
+        } else {
+            content
+        };
 
         let chat_message = ChatMessage {
             id: Some(message_id.clone()),
@@ -737,12 +1439,53 @@ impl WebSocketManager {
             filename: filename.clone(),
             timestamp,
             url: Some(message_url.clone()),
+            thumbnail_url: thumbnail_url.clone(),
+            seq: None,
         };
 
-        // 保存到本地存储
-        self.storage.save_message(&chat_message)?;
+        // 保存到本地存储；同一消息ID重复提交（客户端重试）时只回显原消息，不再转发
+        let saved = self
+            .message_store
+            .save_message(&chat_message)
+            .await
+            .map_err(WebSocketError::Storage)?;
+        if saved.is_duplicate() {
+            let original = saved.into_message();
+            tracing::info!("♻️ 重复提交的聊天消息，回显原消息: {}", message_id);
+            let echo = AppMessage::Chat {
+                id: original.id,
+                from: original.from,
+                to: original.to,
+                content: original.content,
+                content_type: original.content_type,
+                filename: original.filename,
+                timestamp: original.timestamp,
+                url: original.url,
+                thumbnail_url: original.thumbnail_url,
+                out_of_order: None,
+                seq: original.seq,
+                canned_response_id: None,
+            };
+            return self.send_to_user(current_user_id, echo).await;
+        }
+        // 客户端ID与其他会话冲突时存储层会改用服务端ID，转发以存储的ID为准
+        let stored = saved.into_message();
+        let seq = stored.seq;
+        let message_id = stored.id.unwrap_or(message_id);
         tracing::info!("💾 聊天消息已保存到本地存储");
 
+        // 客户文本消息参与情绪升级判断
+        let sender_type = {
+            let connections = self.connections.read().await;
+            connections.get(current_user_id).map(|conn| conn.user_type.clone())
+        };
+        if sender_type == Some(UserType::Kehu)
+            && matches!(content_type, None | Some(ContentType::Text))
+        {
+            self.check_sentiment_escalation(current_user_id, to.clone(), &content)
+                .await;
+        }
+
         // 创建应用消息
         let app_message = AppMessage::Chat {
             id: Some(message_id),
@@ -753,12 +1496,23 @@ impl WebSocketManager {
             filename,
             timestamp,
             url: Some(message_url),
+            thumbnail_url,
+            out_of_order: None,
+            seq,
+            canned_response_id: None,
         };
 
         // 转发给接收者
+        let mut recipient = None;
         if let Some(to_user) = &to {
             tracing::info!("📤 转发聊天消息给接收者: {}", to_user);
-            self.send_to_user(to_user, app_message.clone()).await?;
+            if let Some(sender_type) = &sender_type {
+                self.record_sla_message(sender_type, current_user_id, to_user, timestamp);
+                self.touch_session(sender_type, current_user_id, to_user, timestamp).await;
+            }
+            self.deliver_in_order(&verified_from, to_user, app_message.clone(), timestamp)
+                .await?;
+            recipient = Some(to_user.clone());
         } else {
             // 如果没有明确的接收者，尝试找到聊天伙伴
             tracing::info!("🔍 没有明确接收者，查找聊天伙伴...");
@@ -777,12 +1531,18 @@ impl WebSocketManager {
                         current_user_id,
                         partner_id
                     );
+                    if let Some(sender_type) = &sender_type {
+                        self.record_sla_message(sender_type, current_user_id, &partner_id, timestamp);
+                        self.touch_session(sender_type, current_user_id, &partner_id, timestamp).await;
+                    }
                     let mut forwarded_message = app_message.clone();
                     // 更新to字段
                     if let AppMessage::Chat { ref mut to, .. } = forwarded_message {
                         *to = Some(partner_id.clone());
                     }
-                    self.send_to_user(&partner_id, forwarded_message).await?;
+                    self.deliver_in_order(&verified_from, &partner_id, forwarded_message, timestamp)
+                        .await?;
+                    recipient = Some(partner_id);
                 } _ => {
                     tracing::warn!("⚠️ 没有找到聊天伙伴，消息无法转发");
                 }}
@@ -793,16 +1553,241 @@ impl WebSocketManager {
         tracing::info!("📤 回显聊天消息给发送者: {}", current_user_id);
         self.send_to_user(current_user_id, app_message).await?;
 
-        Ok(())
+        // 消息已保存并回显，接收方缺失或离线时再报告，便于调用方区分处理
+        match recipient {
+            None => Err(WebSocketError::NoRecipient(current_user_id.to_string())),
+            Some(recipient) if !self.senders.read().await.contains_key(&recipient) => {
+                Err(WebSocketError::RecipientOffline(recipient))
+            }
+            Some(_) => Ok(()),
+        }
     }
 
-    // 处理打字指示器
-    async fn handle_typing_message(
-        &self,
-        from: String,
-        to: Option<String>,
-        is_typing: bool,
-        timestamp: chrono::DateTime<Utc>,
+    /// 过滤文本聊天内容，返回过滤后的内容；被拦截时向发送方返回错误并返回 None
+    async fn filter_chat_content(&self, user_id: &str, content: &str) -> Result<Option<String>> {
+        let outcome = self.content_filter.check(content);
+        tracing::info!(
+            "🧹 内容过滤: user={}, 结果={:?}, 原因={}",
+            user_id,
+            outcome.decision,
+            outcome.reason.as_deref().unwrap_or("-")
+        );
+        if outcome.decision != FilterDecision::Blocked {
+            return Ok(Some(outcome.content));
+        }
+
+        let error = AppMessage::Error {
+            message: format!("消息未发送: {}", outcome.reason.unwrap_or_default()),
+            code: 400,
+            timestamp: Utc::now(),
+        };
+        self.send_to_user(user_id, error).await?;
+        Ok(None)
+    }
+
+    /// 客户连续多条消息情绪低于阈值时，标记会话已升级并向主管发送系统告警
+    async fn check_sentiment_escalation(&self, customer_id: &str, kefu_id: Option<String>, content: &str) {
+        let ai_manager = match &self.ai_manager {
+            Some(ai_manager) => ai_manager,
+            None => return,
+        };
+        let config = ai_manager.get_config().await.sentiment_analysis;
+        if !config.enabled {
+            return;
+        }
+
+        let score = score_sentiment(content, &config.custom_keywords);
+        if !self.sentiment_tracker.observe(
+            customer_id,
+            score,
+            config.escalation_threshold,
+            config.escalation_consecutive,
+        ) {
+            return;
+        }
+
+        let kefu_id = match kefu_id {
+            Some(kefu_id) => Some(kefu_id),
+            None => self.redis.read().await.get_partner(customer_id).await.unwrap_or(None),
+        };
+        let session_id = match &kefu_id {
+            Some(kefu_id) => format!("{}:{}", customer_id, kefu_id),
+            None => customer_id.to_string(),
+        };
+        tracing::warn!(
+            "🚨 客户连续{}条负面消息，升级给主管: session={}, score={:.2}",
+            config.escalation_consecutive,
+            session_id,
+            score
+        );
+
+        let details = json!({
+            "customer_id": customer_id,
+            "kefu_id": kefu_id,
+            "score": score,
+            "consecutive": config.escalation_consecutive,
+            "escalated_at": Utc::now().timestamp(),
+        });
+        if let Err(e) = self
+            .redis
+            .read()
+            .await
+            .mark_session_escalated(&session_id, &details.to_string())
+            .await
+        {
+            tracing::warn!("⚠️ 标记会话升级失败: session={}, error: {:?}", session_id, e);
+        }
+
+        let supervisors = if config.supervisor_ids.is_empty() {
+            let connections = self.connections.read().await;
+            connections
+                .values()
+                .filter(|conn| conn.user_type == UserType::Kefu)
+                .map(|conn| conn.user_id.clone())
+                .collect()
+        } else {
+            config.supervisor_ids
+        };
+        let alert = AppMessage::System {
+            content: format!(
+                "⚠️ 客户 {} 连续{}条消息情绪负面，请主管介入（会话: {}）",
+                customer_id, config.escalation_consecutive, session_id
+            ),
+            timestamp: Utc::now(),
+        };
+        for supervisor_id in supervisors {
+            if let Err(e) = self.send_to_user(&supervisor_id, alert.clone()).await {
+                tracing::warn!("⚠️ 主管告警发送失败: {}, error: {:?}", supervisor_id, e);
+            }
+        }
+    }
+
+    /// 握手时评估登录位置，位置异常时记录日志并通知在线客服
+    pub async fn check_geo_risk(&self, user_id: &str, ip: &str, issued_at: Option<i64>) -> Option<GeoRiskAssessment> {
+        let assessment = self.geo_risk.evaluate(user_id, ip, issued_at)?;
+        if !assessment.suspicious {
+            return Some(assessment);
+        }
+
+        let reason = assessment.reason();
+        tracing::warn!("🚩 可疑连接: user_id={}, {}, action={:?}", user_id, reason, assessment.action);
+        let outcome = match assessment.action {
+            GeoRiskAction::Flagged => "连接已标记为可疑",
+            GeoRiskAction::ReverificationRequired => "已拒绝连接并要求二次验证",
+            GeoRiskAction::Trusted => "用户已完成二次验证",
+        };
+        let alert = AppMessage::System {
+            content: format!("🚩 用户 {} {}，{}", user_id, reason, outcome),
+            timestamp: Utc::now(),
+        };
+        let admins: Vec<String> = {
+            let connections = self.connections.read().await;
+            connections
+                .values()
+                .filter(|conn| conn.user_type == UserType::Kefu && conn.user_id != user_id)
+                .map(|conn| conn.user_id.clone())
+                .collect()
+        };
+        for admin_id in admins {
+            if let Err(e) = self.send_to_user(&admin_id, alert.clone()).await {
+                tracing::warn!("⚠️ 风控告警发送失败: {}, error: {:?}", admin_id, e);
+            }
+        }
+        Some(assessment)
+    }
+
+    // 经重排序窗口投递聊天消息，窗口内乱序到达的消息按会话序号整理后再发送，投递的序号不回退；
+    // 客户端时间戳先钳制到服务端时间附近，每个方向只保留一个刷新任务
+    async fn deliver_in_order(
+        &self,
+        from: &str,
+        to: &str,
+        message: AppMessage,
+        timestamp: chrono::DateTime<Utc>,
+    ) -> Result<(), WebSocketError> {
+        if self.reorder_window.is_zero() {
+            return self.send_to_user(to, message).await;
+        }
+
+        let key = format!("{}->{}", from, to);
+        let seq = match &message {
+            AppMessage::Chat { seq, .. } => *seq,
+            _ => None,
+        };
+        let timestamp = clamp_timestamp(timestamp, Utc::now(), MAX_TIMESTAMP_SKEW);
+        let (released, start_flush) = {
+            let mut buffers = self.reorder_buffers.write().await;
+            let buffer = buffers
+                .entry(key.clone())
+                .or_insert_with(|| ReorderBuffer::new(self.reorder_window));
+            let released = buffer.push(timestamp, seq, message, std::time::Instant::now());
+            (released, buffer.schedule_flush())
+        };
+        self.send_released(to, released).await;
+
+        if start_flush {
+            let manager = self.clone();
+            let to_user = to.to_string();
+            tokio::spawn(async move { manager.flush_reorder_buffer(&key, &to_user).await });
+        }
+
+        Ok(())
+    }
+
+    // 刷新任务：按最早到期的消息休眠，释放到期消息，缓冲区清空后退出
+    async fn flush_reorder_buffer(&self, key: &str, to: &str) {
+        loop {
+            let deadline = {
+                let mut buffers = self.reorder_buffers.write().await;
+                match buffers.get_mut(key).and_then(|buffer| buffer.next_flush_deadline()) {
+                    Some(deadline) => deadline,
+                    None => {
+                        // 清空的缓冲区保留已释放的位置用于去重，长时间无消息的方向再回收
+                        let now = std::time::Instant::now();
+                        buffers.retain(|_, buffer| !buffer.is_idle(now, REORDER_STATE_IDLE));
+                        return;
+                    }
+                }
+            };
+            tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await;
+
+            let released = {
+                let mut buffers = self.reorder_buffers.write().await;
+                match buffers.get_mut(key) {
+                    Some(buffer) => buffer.drain_ready(std::time::Instant::now()),
+                    None => return,
+                }
+            };
+            self.send_released(to, released).await;
+        }
+    }
+
+    async fn send_released(
+        &self,
+        to: &str,
+        released: Vec<crate::message_reorder::Released<AppMessage>>,
+    ) {
+        for entry in released {
+            let mut message = entry.item;
+            if entry.out_of_order {
+                if let AppMessage::Chat { ref id, ref mut out_of_order, .. } = message {
+                    tracing::warn!("⚠️ 消息超出重排序窗口后到达: {:?} -> {}", id, to);
+                    *out_of_order = Some(true);
+                }
+            }
+            if let Err(e) = self.send_to_user(to, message).await {
+                tracing::warn!("⚠️ 重排序消息投递失败: {}, error: {:?}", to, e);
+            }
+        }
+    }
+
+    // 处理打字指示器，from 为连接的认证用户ID
+    async fn handle_typing_message(
+        &self,
+        from: String,
+        to: Option<String>,
+        is_typing: bool,
+        timestamp: chrono::DateTime<Utc>,
     ) -> Result<()> {
         let typing_message = AppMessage::Typing {
             from: from.clone(),
@@ -811,26 +1796,57 @@ impl WebSocketManager {
             timestamp,
         };
 
-        // 发送给特定用户或广播
-        if let Some(to_user) = &to {
-            self.send_to_user(to_user, typing_message).await?;
+        // 发送给特定用户，否则发送给聊天对象
+        let target = if let Some(to_user) = to {
+            Some(to_user)
         } else {
-            // 获取聊天对象
             let user_connection = {
                 let connections = self.connections.read().await;
                 connections.get(&from).cloned()
             };
 
-            if let Some(user_conn) = user_connection {
-                if let Ok(Some(partner_id)) =
-                    self.get_chat_partner(&from, &user_conn.user_type).await
-                {
-                    self.send_to_user(&partner_id, typing_message).await?;
-                }
+            match user_connection {
+                Some(user_conn) => self
+                    .get_chat_partner(&from, &user_conn.user_type)
+                    .await
+                    .ok()
+                    .flatten(),
+                None => None,
             }
+        };
+
+        let target = match target {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+
+        if is_typing {
+            self.schedule_typing_clear(&from, &target).await;
+        } else {
+            self.cancel_typing_clear(&from).await;
         }
 
-        Ok(())
+        self.send_to_user(&target, typing_message).await.map_err(Into::into)
+    }
+
+    // 安排打字状态自动清除，重复收到 is_typing:true 时重新计时
+    async fn schedule_typing_clear(&self, from: &str, target: &str) {
+        let manager = self.clone();
+        let (from_user, target_user) = (from.to_string(), target.to_string());
+        arm_typing_timer(&self.typing_timers, from, target, async move {
+            tracing::debug!("⌨️ 打字状态超时自动清除: {} -> {}", from_user, target_user);
+            let _ = manager.send_typing_cleared(&from_user, &target_user).await;
+        })
+        .await;
+    }
+
+    // 取消打字状态定时器，返回尚未触发的定时器对应的接收方
+    async fn cancel_typing_clear(&self, from: &str) -> Option<String> {
+        disarm_typing_timer(&self.typing_timers, from).await
+    }
+
+    async fn send_typing_cleared(&self, from: &str, target: &str) -> Result<()> {
+        self.send_to_user(target, typing_cleared(from, target)).await.map_err(Into::into)
     }
 
     // 处理心跳消息 - 生产级实现
@@ -865,7 +1881,7 @@ impl WebSocketManager {
         Ok(())
     }
 
-    // 处理状态消息 - 简化版本，移除未使用的timestamp参数
+    // 处理状态消息：客服设为暂离/忙碌后不再分配新客户，已有会话不受影响
     async fn handle_status_message(&self, user_id: String, status: OnlineStatus) -> Result<()> {
         // 更新连接状态
         {
@@ -874,13 +1890,20 @@ impl WebSocketManager {
                 connection.status = status.clone();
             }
         }
+        tracing::info!("🔄 用户{}状态更新为: {:?}", user_id, status);
 
         // 更新Redis中的状态
-        if let Ok(redis) = self.redis.try_write() {
-            if let Ok(mut user_info) = redis.get_user_info(&user_id).await {
-                user_info.status = status.clone();
-                user_info.last_seen = Utc::now();
-                let _ = redis.set_user_online(&user_id, &user_info).await;
+        {
+            let redis = self.redis.read().await;
+            match redis.get_user_info(&user_id).await {
+                Ok(mut user_info) => {
+                    user_info.status = status.clone();
+                    user_info.last_seen = Utc::now();
+                    if let Err(e) = redis.set_user_online(&user_id, &user_info).await {
+                        tracing::warn!("⚠️ 保存用户{}状态失败: {}", user_id, e);
+                    }
+                }
+                Err(e) => tracing::warn!("⚠️ 读取用户{}信息失败，状态未写入Redis: {}", user_id, e),
             }
         }
 
@@ -898,61 +1921,850 @@ impl WebSocketManager {
     }
 
     // 发送消息给特定用户 - 生产级实现
-    async fn send_to_user(&self, user_id: &str, message: AppMessage) -> Result<()> {
-        let message_type = match &message {
-            AppMessage::Chat { .. } => "Chat",
-            AppMessage::Welcome { .. } => "Welcome",
-            AppMessage::History { .. } => "History",
-            AppMessage::HistoryRequest { .. } => "HistoryRequest",
-            AppMessage::OnlineUsers { .. } => "OnlineUsers",
-            AppMessage::Heartbeat { .. } => "Heartbeat",
-            AppMessage::Typing { .. } => "Typing",
-            AppMessage::System { .. } => "System",
-            AppMessage::UserJoined { .. } => "UserJoined",
-            AppMessage::UserLeft { .. } => "UserLeft",
-            AppMessage::Status { .. } => "Status",
-            AppMessage::Error { .. } => "Error",
-            AppMessage::HtmlTemplate { .. } => "HtmlTemplate",
-            AppMessage::HtmlCallback { .. } => "HtmlCallback",
-            AppMessage::Voice { .. } => "VoiceMessage",
-        };
+    async fn send_to_user(&self, user_id: &str, message: AppMessage) -> Result<(), WebSocketError> {
+        let message_type = message.type_name();
+        self.message_counters.record(OUTBOUND, message_type);
 
-        let senders = self.senders.read().await;
+        let tracked = tracked_delivery(&message, user_id);
+        // 复制发送端后立即释放读锁，等待队列空位时不阻塞连接的注册和清理
+        let sender = self.senders.read().await.get(user_id).cloned();
 
         tracing::info!("📤 尝试发送{}消息给: {}", message_type, user_id);
 
-        if let Some(sender) = senders.get(user_id) {
-            match sender.send(message) {
+        if let Some(sender) = sender {
+            // 入队前开始计时，避免发送任务写出后客户端的确认先于计时到达
+            if let Some((message_id, _)) = &tracked {
+                self.acks.sent(message_id, std::time::Instant::now());
+            }
+            // 队列满时短暂等待发送任务腾出空位，客户端持续消费过慢才转入离线队列
+            match sender.send_timeout(Arc::new(message), SEND_QUEUE_FULL_TIMEOUT).await {
                 Ok(_) => {
                     tracing::info!("✅ 成功发送{}消息给: {}", message_type, user_id);
+                    if let Some((message_id, from)) = tracked {
+                        self.track_delivery_status(&message_id, &from, user_id, MessageStatus::Sent)
+                            .await;
+                    }
                 }
-                Err(_) => {
+                Err(SendTimeoutError::Timeout(message)) => {
+                    // 客户端消费过慢：不再堆积在内存中，转入离线队列
+                    tracing::warn!(
+                        "🐢 {}的发送队列已满且{:?}内未腾出空位，{}消息转入离线队列",
+                        user_id,
+                        SEND_QUEUE_FULL_TIMEOUT,
+                        message_type
+                    );
+                    self.enqueue_offline_message(user_id, &message).await;
+                    if let Some((message_id, from)) = tracked {
+                        self.acks.failed(&message_id);
+                        self.track_delivery_status(&message_id, &from, user_id, MessageStatus::Queued)
+                            .await;
+                    }
+                }
+                Err(SendTimeoutError::Closed(message)) => {
                     tracing::error!("❌ 发送{}消息失败给: {} (通道关闭)", message_type, user_id);
-                    // 生产级错误处理：移除无效的发送器
-                    drop(senders);
-                    let mut senders_write = self.senders.write().await;
-                    senders_write.remove(user_id);
+                    // 生产级错误处理：移除无效的发送器，连接已被新连接替换时保留新的发送器
+                    {
+                        let mut senders_write = self.senders.write().await;
+                        if senders_write.get(user_id).is_some_and(|current| current.same_channel(&sender)) {
+                            senders_write.remove(user_id);
+                        }
+                    }
                     tracing::warn!("🧹 已移除失效的发送器: {}", user_id);
+                    self.enqueue_offline_message(user_id, &message).await;
+                    if let Some((message_id, from)) = tracked {
+                        self.acks.failed(&message_id);
+                        self.track_delivery_status(&message_id, &from, user_id, MessageStatus::Queued)
+                            .await;
+                    }
                 }
             }
         } else {
-            let available_users: Vec<String> = senders.keys().cloned().collect();
+            let available_users: Vec<String> = self.senders.read().await.keys().cloned().collect();
             tracing::warn!(
-                "⚠️ 用户{}不存在发送器列表中，无法发送{}消息",
+                "⚠️ 用户{}不存在发送器列表中，无法实时发送{}消息",
                 user_id,
                 message_type
             );
             tracing::debug!("📋 当前可用用户: {:?}", available_users);
+            self.enqueue_offline_message(user_id, &message).await;
+            if let Some((message_id, from)) = tracked {
+                self.track_delivery_status(&message_id, &from, user_id, MessageStatus::Queued)
+                    .await;
+            }
         }
         Ok(())
     }
 
+    /// 推进消息投递状态并实时通知发送方；状态跟踪失败不影响消息本身的投递
+    async fn track_delivery_status(&self, message_id: &str, sender: &str, recipient: &str, status: MessageStatus) {
+        let record = match self
+            .message_queue
+            .update_status(message_id, sender, recipient, status)
+            .await
+        {
+            Ok(Some(record)) => record,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("⚠️ 更新消息投递状态失败: {} -> {:?}, error: {:?}", message_id, status, e);
+                return;
+            }
+        };
+        self.notify_delivery_status(record).await;
+    }
+
+    // 发送方离线时不补发状态，上线后可通过接口查询
+    async fn notify_delivery_status(&self, record: MessageStatusRecord) {
+        if let Some(sender_tx) = self.get_user_sender(&record.sender).await {
+            let _ = sender_tx.try_send(Arc::new(AppMessage::DeliveryStatus {
+                message_id: record.message_id,
+                status: record.status,
+                timestamp: record.updated_at,
+            }));
+        }
+    }
+
+    // 处理客户端的收到确认：只统计消息接收方的确认，投递状态已在写出连接时推进
+    async fn handle_delivery_ack(&self, message_ids: Vec<String>, user_id: &str) {
+        tracing::debug!("📬 收到消息确认: {} 共{}条", user_id, message_ids.len());
+        for (message_id, record) in self.recipient_statuses(&message_ids, user_id, "消息确认").await {
+            if record.is_some() {
+                self.record_client_ack(message_id);
+            }
+        }
+    }
+
+    // 一次批量查询投递状态，只保留 user_id 为接收方的消息；非接收方提交的回执记录日志后忽略
+    async fn recipient_statuses<'a>(
+        &self,
+        message_ids: &'a [String],
+        user_id: &str,
+        action: &str,
+    ) -> Vec<(&'a str, Option<MessageStatusRecord>)> {
+        let records = match self.message_queue.get_statuses(message_ids).await {
+            Ok(records) => records,
+            Err(e) => {
+                tracing::warn!("⚠️ 查询消息投递状态失败: {} 共{}条, error: {:?}", user_id, message_ids.len(), e);
+                return Vec::new();
+            }
+        };
+        message_ids
+            .iter()
+            .zip(records)
+            .map(|(message_id, record)| {
+                let record = match record {
+                    Some(record) if record.recipient == user_id => Some(record),
+                    Some(_) => {
+                        tracing::warn!("⚠️ 非接收方提交{}，忽略: {} -> {}", action, user_id, message_id);
+                        None
+                    }
+                    None => {
+                        tracing::debug!("未跟踪投递状态的消息，忽略{}: {}", action, message_id);
+                        None
+                    }
+                };
+                (message_id.as_str(), record)
+            })
+            .collect()
+    }
+
+    // 接收方确认（Ack 或已读回执）才结束计时，重复确认不重复计数
+    fn record_client_ack(&self, message_id: &str) {
+        if let Some(latency) = self.acks.acked(message_id, std::time::Instant::now()) {
+            tracing::debug!("📬 消息{}确认耗时: {}ms", message_id, latency.as_millis());
+        }
+    }
+
+    // 处理已读回执：只有消息的接收方可以标记已读，已读同时视为收到确认
+    async fn handle_read_receipt(&self, message_ids: Vec<String>, user_id: &str) {
+        tracing::info!("👀 收到已读回执: {} 共{}条", user_id, message_ids.len());
+        let readable: Vec<MessageStatusRecord> = self
+            .recipient_statuses(&message_ids, user_id, "已读回执")
+            .await
+            .into_iter()
+            .filter_map(|(_, record)| record)
+            .collect();
+        for record in &readable {
+            self.record_client_ack(&record.message_id);
+        }
+
+        // 所有消息的状态转换在一个 pipeline 中完成
+        let updates: Vec<(&str, &str, &str)> = readable
+            .iter()
+            .map(|record| (record.message_id.as_str(), record.sender.as_str(), user_id))
+            .collect();
+        match self.message_queue.update_statuses(&updates, MessageStatus::Read).await {
+            Ok(records) => {
+                for record in records.into_iter().flatten() {
+                    self.notify_delivery_status(record).await;
+                }
+            }
+            Err(e) => {
+                tracing::warn!("⚠️ 更新消息投递状态失败: {} 共{}条 -> Read, error: {:?}", user_id, updates.len(), e);
+            }
+        }
+    }
+
+    // 处理客户的会话满意度评价：记录到被评价客服名下并更新其滚动平均满意度
+    async fn handle_rating(
+        &self,
+        session_id: String,
+        score: u8,
+        comment: Option<String>,
+        timestamp: chrono::DateTime<Utc>,
+        user_id: &str,
+    ) -> Result<()> {
+        let is_customer = matches!(
+            self.connections.read().await.get(user_id),
+            Some(conn) if conn.user_type == UserType::Kehu
+        );
+        let kefu_id = match rated_kefu(&session_id, user_id) {
+            Some(kefu_id) if is_customer && is_valid_score(score) && self.had_session(user_id, kefu_id).await => {
+                kefu_id.to_string()
+            }
+            _ => {
+                tracing::warn!("⚠️ 无效的满意度评价: {} session={} score={}", user_id, session_id, score);
+                return self
+                    .send_to_user(
+                        user_id,
+                        AppMessage::Error {
+                            message: "无效的满意度评价".to_string(),
+                            code: 400,
+                            timestamp: Utc::now(),
+                        },
+                    )
+                    .await
+                    .map_err(Into::into);
+            }
+        };
+
+        let rating = SessionRating {
+            session_id,
+            customer_id: user_id.to_string(),
+            kefu_id,
+            score,
+            comment,
+            timestamp,
+        };
+        let recorded = self.redis.read().await.record_session_rating(&rating).await?;
+        let content = match recorded {
+            Some(satisfaction) => {
+                tracing::info!(
+                    "⭐ 收到满意度评价: {} -> {} {}星, 当前平均{:.2}",
+                    user_id,
+                    rating.kefu_id,
+                    score,
+                    satisfaction.satisfaction_score
+                );
+                "感谢您的评价".to_string()
+            }
+            None => "该会话已评价过".to_string(),
+        };
+        self.send_to_user(
+            user_id,
+            AppMessage::System {
+                content,
+                timestamp: Utc::now(),
+            },
+        )
+        .await
+        .map_err(Into::into)
+    }
+
+    // 客户与客服之间有进行中的会话，或会话结束前互相发过消息；查询失败时按没有会话处理
+    async fn had_session(&self, kehu_id: &str, kefu_id: &str) -> bool {
+        match self.redis.read().await.session_exists(kehu_id, kefu_id).await {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(e) => tracing::warn!("⚠️ 查询会话失败: {} <-> {}, error: {:?}", kehu_id, kefu_id, e),
+        }
+        matches!(
+            self.message_store.get_recent_messages(kehu_id, kefu_id, 1).await,
+            Ok(messages) if !messages.is_empty()
+        )
+    }
+
+    // 处理客户留言：生成工单供客服稍后处理，并提醒在线客服
+    async fn handle_leave_message(&self, contact: String, content: String, user_id: &str) -> Result<()> {
+        let is_customer = matches!(
+            self.connections.read().await.get(user_id),
+            Some(conn) if conn.user_type == UserType::Kehu
+        );
+        let invalid = if is_customer {
+            leave_message_error(&contact, &content)
+        } else {
+            Some("仅客户可以留言".to_string())
+        };
+        if let Some(message) = invalid {
+            tracing::warn!("⚠️ 无效的留言: {} - {}", user_id, message);
+            return self
+                .send_to_user(
+                    user_id,
+                    AppMessage::Error {
+                        message,
+                        code: 400,
+                        timestamp: Utc::now(),
+                    },
+                )
+                .await
+                .map_err(Into::into);
+        }
+
+        let ticket = self.storage.create_ticket(user_id, contact.trim(), content.trim())?;
+        tracing::info!("📝 客户{}留言已生成工单: {}", user_id, ticket.id);
+
+        let notice = Arc::new(AppMessage::System {
+            content: format!("新留言工单: {}({})", ticket.id, user_id),
+            timestamp: Utc::now(),
+        });
+        let kefu_ids: Vec<String> = self
+            .connections
+            .read()
+            .await
+            .iter()
+            .filter(|(_, connection)| connection.user_type == UserType::Kefu)
+            .map(|(kefu_id, _)| kefu_id.clone())
+            .collect();
+        let senders = self.senders.read().await;
+        for sender in kefu_ids.iter().filter_map(|kefu_id| senders.get(kefu_id)) {
+            let _ = sender.try_send(Arc::clone(&notice));
+        }
+        drop(senders);
+
+        self.send_to_user(
+            user_id,
+            AppMessage::System {
+                content: "留言已收到，客服会尽快通过您留下的联系方式与您联系".to_string(),
+                timestamp: Utc::now(),
+            },
+        )
+        .await
+        .map_err(Into::into)
+    }
+
+    // 处理表情回应：只有消息的收发双方可以回应，回应保存后转发给会话另一方并回显
+    async fn handle_reaction(
+        &self,
+        message_id: String,
+        emoji: String,
+        action: ReactionAction,
+        user_id: &str,
+    ) -> Result<()> {
+        let partner = self.storage.get_message(&message_id)?.and_then(|message| {
+            if message.from == user_id {
+                message.to
+            } else if message.to.as_deref() == Some(user_id) {
+                Some(message.from)
+            } else {
+                None
+            }
+        });
+        let Some(partner) = partner.filter(|_| is_allowed_reaction(&emoji)) else {
+            tracing::warn!("⚠️ 无效的表情回应: {} message={} emoji={}", user_id, message_id, emoji);
+            return self
+                .send_to_user(
+                    user_id,
+                    AppMessage::Error {
+                        message: "无效的表情回应".to_string(),
+                        code: 400,
+                        timestamp: Utc::now(),
+                    },
+                )
+                .await
+                .map_err(Into::into);
+        };
+
+        self.redis
+            .read()
+            .await
+            .update_reaction(&message_id, &emoji, user_id, action)
+            .await?;
+        let reaction = AppMessage::Reaction {
+            message_id,
+            emoji,
+            user_id: user_id.to_string(),
+            action,
+            timestamp: Utc::now(),
+        };
+        self.send_to_user(&partner, reaction.clone()).await?;
+        self.send_to_user(user_id, reaction).await?;
+        Ok(())
+    }
+
+    // 处理客服内部消息：收发双方都必须是在线客服，消息存入内部命名空间后转发给对方并回显
+    async fn handle_internal_chat(
+        &self,
+        id: Option<String>,
+        to: String,
+        content: String,
+        user_id: &str,
+    ) -> Result<()> {
+        let sender_is_kefu =
+            matches!(self.connections.read().await.get(user_id), Some(conn) if conn.user_type == UserType::Kefu);
+        if !sender_is_kefu {
+            tracing::warn!("⚠️ 非客服用户尝试发送内部消息: {}", user_id);
+            return self
+                .send_to_user(
+                    user_id,
+                    AppMessage::Error {
+                        message: "仅客服可以发送内部消息".to_string(),
+                        code: 403,
+                        timestamp: Utc::now(),
+                    },
+                )
+                .await
+                .map_err(Into::into);
+        }
+        if to == user_id || content.trim().is_empty() || !self.is_online_kefu(&to).await {
+            tracing::warn!("⚠️ 无效的内部消息: {} -> {}", user_id, to);
+            return self
+                .send_to_user(
+                    user_id,
+                    AppMessage::Error {
+                        message: "内部消息只能发送给其他在线客服".to_string(),
+                        code: 400,
+                        timestamp: Utc::now(),
+                    },
+                )
+                .await
+                .map_err(Into::into);
+        }
+
+        let message = ChatMessage {
+            id: Some(id.unwrap_or_else(|| Uuid::new_v4().to_string())),
+            from: user_id.to_string(),
+            to: Some(to.clone()),
+            content,
+            content_type: Some(ContentType::Text),
+            filename: None,
+            timestamp: Utc::now(),
+            url: None,
+            thumbnail_url: None,
+            seq: None,
+        };
+        self.storage.save_internal_message(&message)?;
+        tracing::info!("🔒 客服内部消息: {} -> {}", user_id, to);
+
+        let internal = AppMessage::InternalChat {
+            id: message.id,
+            from: message.from,
+            to: to.clone(),
+            content: message.content,
+            timestamp: message.timestamp,
+        };
+        // 接收方不在本机时进入Redis离线队列，重连时投递；历史可通过内部消息接口查询
+        self.send_to_user(&to, internal.clone()).await?;
+        self.send_to_user(user_id, internal).await?;
+        Ok(())
+    }
+
+    // 接收方可能连接在其他实例上：本机没有连接时按Redis中的在线用户信息判断
+    async fn is_online_kefu(&self, user_id: &str) -> bool {
+        if let Some(conn) = self.connections.read().await.get(user_id) {
+            return conn.user_type == UserType::Kefu;
+        }
+        matches!(
+            self.redis.read().await.get_user_info(user_id).await,
+            Ok(info) if info.user_type == UserType::Kefu
+        )
+    }
+
+    // 组装历史消息，附带各消息的表情回应数量；读取回应失败时不带回应
+    async fn history_message(&self, page: HistoryPage) -> AppMessage {
+        let message_ids: Vec<String> = page.messages.iter().filter_map(|message| message.id.clone()).collect();
+        let reactions = match self.redis.read().await.get_reaction_counts(&message_ids).await {
+            Ok(reactions) => reactions,
+            Err(e) => {
+                tracing::warn!("⚠️ 读取表情回应失败: {:?}", e);
+                HashMap::new()
+            }
+        };
+        AppMessage::History {
+            messages: page.messages,
+            has_more: page.has_more,
+            reactions,
+            latest_seq: page.latest_seq,
+        }
+    }
+
+    /// 客服可用的快捷回复中快捷码匹配前缀的条目
+    pub async fn find_canned_responses(&self, kefu_id: &str, prefix: &str, limit: usize) -> Result<Vec<CannedResponse>> {
+        let responses = self.redis.read().await.list_canned_responses().await?;
+        Ok(canned_response::matching(&responses, kefu_id, prefix, limit)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    // 展开客服消息引用的快捷回复；发送方不是客服或快捷回复对其不可用时返回 None
+    async fn expand_canned_response(&self, user_id: &str, canned_response_id: &str) -> Result<Option<String>> {
+        let is_kefu = matches!(
+            self.connections.read().await.get(user_id),
+            Some(conn) if conn.user_type == UserType::Kefu
+        );
+        if !is_kefu {
+            return Ok(None);
+        }
+        let response = self.redis.read().await.get_canned_response(canned_response_id).await?;
+        Ok(response
+            .filter(|response| response.is_visible_to(user_id))
+            .map(|response| response.content))
+    }
+
+    /// 启动共享的Redis频道订阅：整个进程只用一条订阅连接，按频道把事件转发给本机在线用户
+    pub async fn start_redis_subscriber(&self) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            // 连续失败次数，订阅成功后清零
+            let mut attempt = 0u32;
+            loop {
+                match manager.subscribe_redis_channels().await {
+                    Ok(pubsub) => {
+                        attempt = 0;
+                        manager.forward_redis_events(pubsub).await;
+                        tracing::warn!("⚠️ Redis频道订阅连接已断开");
+                    }
+                    Err(e) => tracing::error!("❌ Redis频道订阅失败: {}", e),
+                }
+                attempt += 1;
+                let delay = redis_resubscribe_delay(attempt);
+                tracing::info!("🔄 {}ms后第{}次重新订阅Redis频道", delay.as_millis(), attempt);
+                tokio::time::sleep(delay).await;
+            }
+        });
+    }
+
+    // 建立订阅连接并重新订阅全部频道；每次都按部署方式重新取客户端，哨兵主从切换后订阅新主节点
+    async fn subscribe_redis_channels(&self) -> Result<redis::aio::PubSub> {
+        let client = self.redis.read().await.pubsub_client().await?;
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        for pattern in REDIS_EVENT_PATTERNS {
+            pubsub.psubscribe(*pattern).await?;
+        }
+        pubsub.subscribe(REDIS_BROADCAST_CHANNEL).await?;
+        tracing::info!("📡 Redis频道订阅已启动: {}", client.get_connection_info().addr);
+        Ok(pubsub)
+    }
+
+    // 转发订阅收到的事件，直到连接断开
+    async fn forward_redis_events(&self, mut pubsub: redis::aio::PubSub) {
+        let mut stream = pubsub.on_message();
+        while let Some(msg) = stream.next().await {
+            let channel = msg.get_channel_name().to_string();
+            let Ok(payload) = msg.get_payload::<String>() else {
+                continue;
+            };
+            let delivered = self.forward_redis_event(&channel, &payload).await;
+            tracing::debug!("📨 Redis事件 {} 已转发给{}个本机用户", channel, delivered);
+        }
+    }
+
+    // 只投递给本机在线的用户，不在本机的用户由其所在实例转发
+    async fn forward_redis_event(&self, channel: &str, payload: &str) -> usize {
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(payload) else {
+            return 0;
+        };
+        let message = AppMessage::System {
+            content: json!({
+                "type": "redis_event",
+                "channel": channel,
+                "event": event,
+                "timestamp": Utc::now().timestamp()
+            })
+            .to_string(),
+            timestamp: Utc::now(),
+        };
+
+        let senders = self.senders.read().await;
+        if channel == REDIS_BROADCAST_CHANNEL {
+            return fan_out(senders.values(), message);
+        }
+        match redis_event_user(channel).and_then(|user_id| senders.get(user_id)) {
+            Some(sender) => fan_out([sender], message),
+            None => 0,
+        }
+    }
+
+    /// 启动排队超时检查：等待超过时限仍无客服接入的客户移出队列并转为留言
+    pub async fn start_queue_timeout_checker(&self) {
+        if self.queue_timeout.is_zero() {
+            return;
+        }
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(QUEUE_TIMEOUT_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let expired = manager.expire_waiting_customers().await;
+                if expired > 0 {
+                    tracing::info!("⏰ {}位客户排队超时，已转为留言", expired);
+                }
+            }
+        });
+    }
+
+    // 检查等待队列，把排队超时的客户转为留言，返回处理的人数
+    async fn expire_waiting_customers(&self) -> usize {
+        let now = Utc::now().timestamp();
+        let waiting = match self.redis.read().await.get_waiting_queue().await {
+            Ok(waiting) => waiting,
+            Err(e) => {
+                tracing::warn!("⚠️ 读取等待队列失败: {:?}", e);
+                return 0;
+            }
+        };
+
+        let mut expired = 0;
+        for customer_id in waiting {
+            let redis = self.redis.read().await;
+            let waiting_since = match redis.get_waiting_since(&customer_id).await {
+                Ok(Some(waiting_since)) => waiting_since,
+                _ => continue,
+            };
+            if !queue_wait_timed_out(waiting_since, now, self.queue_timeout)
+                || !matches!(redis.get_partner(&customer_id).await, Ok(None))
+            {
+                continue;
+            }
+            if let Err(e) = redis.remove_from_waiting_queue(&customer_id).await {
+                tracing::warn!("⚠️ 移出等待队列失败: {}, error: {:?}", customer_id, e);
+                continue;
+            }
+            drop(redis);
+
+            expired += 1;
+            self.sla_tracker.queue_timed_out(&customer_id, Utc::now());
+            let prompt = AppMessage::System {
+                content: format!(
+                    "当前客服繁忙，您已等待超过{}分钟。请留下联系方式和问题，客服会尽快与您联系",
+                    self.queue_timeout.as_secs().div_ceil(60)
+                ),
+                timestamp: Utc::now(),
+            };
+            if let Some(sender) = self.get_user_sender(&customer_id).await {
+                let _ = sender.try_send(Arc::new(prompt));
+            }
+        }
+        expired
+    }
+
+    // 会话中有新消息，刷新会话的最后活动时间
+    async fn touch_session(
+        &self,
+        sender_type: &UserType,
+        sender: &str,
+        recipient: &str,
+        at: chrono::DateTime<Utc>,
+    ) {
+        let (kehu_id, kefu_id) = match sender_type {
+            UserType::Kehu => (sender, recipient),
+            UserType::Kefu => (recipient, sender),
+        };
+        if let Err(e) = self.redis.read().await.touch_session(kehu_id, kefu_id, at).await {
+            tracing::warn!("⚠️ 更新会话活动时间失败: {}:{}, error: {:?}", kehu_id, kefu_id, e);
+        }
+    }
+
+    /// 启动空闲会话检查：双方长时间没有消息的会话自动结束
+    pub async fn start_idle_session_checker(&self) {
+        if self.session_idle_timeout.is_zero() {
+            return;
+        }
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_SESSION_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let closed = manager.close_idle_sessions().await;
+                if closed > 0 {
+                    tracing::info!("⏰ {}个会话空闲超时，已自动结束", closed);
+                }
+            }
+        });
+    }
+
+    // 结束空闲超时的会话并通知双方，返回结束的会话数
+    async fn close_idle_sessions(&self) -> usize {
+        let idle_since = Utc::now().timestamp() - self.session_idle_timeout.as_secs() as i64;
+        let idle_sessions = match self.redis.read().await.get_idle_sessions(idle_since).await {
+            Ok(idle_sessions) => idle_sessions,
+            Err(e) => {
+                tracing::warn!("⚠️ 读取空闲会话失败: {:?}", e);
+                return 0;
+            }
+        };
+
+        let mut closed = 0;
+        for (kehu_id, kefu_id) in idle_sessions {
+            if let Err(e) = self.redis.read().await.clear_session(&kehu_id, &kefu_id).await {
+                tracing::warn!("⚠️ 结束空闲会话失败: {}:{}, error: {:?}", kehu_id, kefu_id, e);
+                continue;
+            }
+            closed += 1;
+
+            let notice = Arc::new(AppMessage::System {
+                content: format!(
+                    "会话已超过{}分钟没有新消息，已自动结束",
+                    self.session_idle_timeout.as_secs().div_ceil(60)
+                ),
+                timestamp: Utc::now(),
+            });
+            for user_id in [&kehu_id, &kefu_id] {
+                if let Some(sender) = self.get_user_sender(user_id).await {
+                    let _ = sender.try_send(Arc::clone(&notice));
+                }
+            }
+        }
+        closed
+    }
+
+    // 客户消息开始首响计时，客服回复客户时记录首响
+    fn record_sla_message(
+        &self,
+        sender_type: &UserType,
+        sender: &str,
+        recipient: &str,
+        at: chrono::DateTime<Utc>,
+    ) {
+        match sender_type {
+            UserType::Kehu => self.sla_tracker.customer_message(sender, at),
+            UserType::Kefu => self.sla_tracker.kefu_message(recipient, at),
+        }
+    }
+
+    /// 最近 days 天的每日 SLA 报表
+    pub fn get_sla_reports(&self, days: usize) -> Vec<DailySlaReport> {
+        self.sla_tracker.daily_reports(days, Utc::now().date_naive())
+    }
+
+    /// 各客服的满意度汇总，供主管查看
+    pub async fn get_kefu_satisfaction_scores(&self) -> Result<Vec<KefuSatisfaction>> {
+        self.redis.read().await.get_all_kefu_satisfaction().await
+    }
+
+    /// 所有在线客服的实时工作负载，按客服ID排序；读取失败的客服跳过
+    pub async fn get_kefu_workloads(&self) -> Vec<serde_json::Value> {
+        let mut kefu_ids: Vec<String> = self
+            .connections
+            .read()
+            .await
+            .values()
+            .filter(|connection| connection.user_type == UserType::Kefu)
+            .map(|connection| connection.user_id.clone())
+            .collect();
+        kefu_ids.sort();
+
+        let redis = self.redis.read().await;
+        let mut workloads = Vec::with_capacity(kefu_ids.len());
+        for kefu_id in kefu_ids {
+            match redis.get_kefu_workload(&kefu_id).await {
+                Ok(workload) => workloads.push(workload),
+                Err(e) => tracing::warn!("⚠️ 获取客服{}工作负载失败: {}", kefu_id, e),
+            }
+        }
+        workloads
+    }
+
+    // 接收方不在线时，将需要送达的消息写入Redis离线队列
+    async fn enqueue_offline_message(&self, user_id: &str, message: &AppMessage) {
+        // 输入状态、在线列表等瞬时消息过期即无意义，不做离线保存
+        let deliverable = matches!(
+            message,
+            AppMessage::Chat { .. }
+                | AppMessage::Voice { .. }
+                | AppMessage::HtmlTemplate { .. }
+                | AppMessage::HtmlCallback { .. }
+                | AppMessage::InternalChat { .. }
+        );
+        if !deliverable {
+            return;
+        }
+
+        let payload = match serde_json::to_string(message) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("❌ 离线消息序列化失败: {}, error: {:?}", user_id, e);
+                return;
+            }
+        };
+
+        let redis = self.redis.read().await;
+        match redis.push_offline_message(user_id, &payload, &self.message_queue.config()).await {
+            Ok(dropped) => {
+                tracing::info!("📥 消息已加入离线队列: {}", user_id);
+                self.message_queue.record_dropped(user_id, dropped);
+            }
+            Err(e) => tracing::error!("❌ 写入离线队列失败: {}, error: {:?}", user_id, e),
+        }
+    }
+
+    // 用户上线后按顺序推送离线队列中的消息，全部投递后清空队列
+    async fn deliver_offline_messages(
+        &self,
+        user_id: &str,
+        sender: &mpsc::Sender<SharedMessage>,
+    ) -> Result<()> {
+        // 原子地取出队列，投递期间新入队的消息不会被一并删除；等待发送队列时不持有Redis锁
+        let queued = self.redis.read().await.take_offline_messages(user_id).await?;
+        if queued.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!("📬 推送离线消息: {} 共{}条", user_id, queued.len());
+        let ttl_seconds = self.message_queue.config().message_ttl_seconds;
+        let now = Utc::now();
+        for (index, payload) in queued.iter().enumerate() {
+            if is_payload_expired(payload, ttl_seconds, now) {
+                tracing::debug!("离线消息已超过保留时长，不再投递: {}", user_id);
+                continue;
+            }
+            match serde_json::from_str::<AppMessage>(payload) {
+                Ok(message) => {
+                    if sender.send(Arc::new(message)).await.is_err() {
+                        // 连接已断开，未送达的消息放回队列等待下次上线
+                        self.redis
+                            .read()
+                            .await
+                            .requeue_offline_messages(user_id, &queued[index..], ttl_seconds)
+                            .await?;
+                        return Err(anyhow::anyhow!("连接已关闭，离线消息未全部送达"));
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("⚠️ 丢弃无法解析的离线消息: {}, error: {:?}", user_id, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // 补发用户上次在线之后发布、仍在有效期内的系统广播，随后把最后在线时间推进到本次上线
+    async fn deliver_missed_broadcasts(
+        &self,
+        user_id: &str,
+        sender: &mpsc::Sender<SharedMessage>,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let redis = self.redis.read().await;
+        let last_seen = redis.get_user_last_seen(user_id).await?;
+        let missed = missed_broadcasts(redis.get_system_broadcasts().await?, last_seen, now);
+
+        if !missed.is_empty() {
+            tracing::info!("📢 补发系统广播: {} 共{}条", user_id, missed.len());
+        }
+        for broadcast in missed {
+            if sender.send(Arc::new(system_broadcast_message(&broadcast))).await.is_err() {
+                return Err(anyhow::anyhow!("连接已关闭，系统广播未全部送达"));
+            }
+        }
+
+        redis.set_user_last_seen(user_id, now).await
+    }
+
     // 广播消息给所有用户
     async fn broadcast_message(&self, message: AppMessage) -> Result<()> {
         let senders = self.senders.read().await;
-        for (_, sender) in senders.iter() {
-            let _ = sender.send(message.clone());
-        }
+        fan_out(senders.values(), message);
         Ok(())
     }
 
@@ -1051,7 +2863,7 @@ impl WebSocketManager {
     }
 
     // 获取用户发送器
-    async fn get_user_sender(&self, user_id: &str) -> Option<mpsc::UnboundedSender<AppMessage>> {
+    async fn get_user_sender(&self, user_id: &str) -> Option<mpsc::Sender<SharedMessage>> {
         let senders = self.senders.read().await;
         senders.get(user_id).cloned()
     }
@@ -1133,6 +2945,7 @@ impl WebSocketManager {
                 // 3. 进入等待队列
                 tracing::info!("⏳ 客户{}进入等待队列", user_id);
                 let _ = redis.add_to_waiting_queue(user_id).await;
+                self.sla_tracker.customer_queued(user_id, Utc::now());
                 Ok(None)
             }
         }
@@ -1145,9 +2958,9 @@ impl WebSocketManager {
 
         let mut kefu_candidates = Vec::new();
 
-        // 收集所有在线客服及其企业级工作负载数据
+        // 收集所有可接待（状态为 Online）的客服及其企业级工作负载数据
         for (kefu_id, connection) in connections.iter() {
-            if connection.user_type == UserType::Kefu {
+            if accepts_new_customers(connection) {
                 // 🚀 使用企业级工作负载分析
                 let workload_data = match redis.get_kefu_workload(kefu_id).await { Ok(workload) => {
                     workload
@@ -1205,28 +3018,82 @@ impl WebSocketManager {
         Ok(selected_kefu.0.clone())
     }
 
-    // 🔍 为特定客服寻找等待中的客户
+    // 🔍 为特定客服寻找等待中的客户，最早排队的优先
     async fn find_waiting_customer_for_kefu(&self, kefu_id: &str) -> Result<Option<String>> {
+        let waiting_customers = self.redis.read().await.get_waiting_queue().await.unwrap_or_default();
+        let customer = self.take_waiting_customer(waiting_customers).await;
+        if let Some(customer_id) = &customer {
+            tracing::info!("🎯 为客服{}找到等待客户: {}", kefu_id, customer_id);
+        }
+        Ok(customer)
+    }
+
+    // 按排队先后取第一个仍在线且未分配客服的客户，并移出等待队列
+    async fn take_waiting_customer(&self, waiting_customers: Vec<String>) -> Option<String> {
         let redis = self.redis.read().await;
-        let connections = self.connections.read().await;
 
-        // 获取等待队列中的客户
-        if let Ok(waiting_customers) = redis.get_waiting_queue().await {
-            for customer_id in waiting_customers {
-                // 验证客户是否仍在线
-                if connections.contains_key(&customer_id) {
-                    // 检查客户是否未被分配
-                    if let Ok(None) = redis.get_partner(&customer_id).await {
-                        tracing::info!("🎯 为客服{}找到等待客户: {}", kefu_id, customer_id);
-                        // 从等待队列移除
-                        let _ = redis.remove_from_waiting_queue(&customer_id).await;
-                        return Ok(Some(customer_id));
-                    }
+        for customer_id in waiting_customers {
+            let connected = self.connections.read().await.contains_key(&customer_id);
+            if !connected {
+                // 所有实例上都已下线的客户移出队列，不再占用队首
+                if let Ok(false) = redis.is_user_online(&customer_id).await {
+                    tracing::info!("🧹 等待中的客户{}已下线，移出等待队列", customer_id);
+                    let _ = redis.remove_from_waiting_queue(&customer_id).await;
+                }
+                continue;
+            }
+            if !matches!(redis.get_partner(&customer_id).await, Ok(None)) {
+                continue;
+            }
+            // 认领失败说明已被其他客服或实例接走
+            match redis.claim_waiting_customer(&customer_id).await {
+                Ok(true) => return Some(customer_id),
+                Ok(false) => continue,
+                Err(e) => tracing::warn!("⚠️ 认领等待客户失败: {}, error: {:?}", customer_id, e),
+            }
+        }
+        None
+    }
+
+    // 新客户提醒只推送给当前负载最低的几位客服，避免所有客服同时抢单或都不理会
+    async fn alert_idle_kefu(&self, customer_id: &str, customer_name: &str) {
+        let kefu_ids: Vec<String> = {
+            let connections = self.connections.read().await;
+            connections
+                .iter()
+                .filter(|(_, connection)| accepts_new_customers(connection))
+                .map(|(kefu_id, _)| kefu_id.clone())
+                .collect()
+        };
+
+        let mut loads = Vec::with_capacity(kefu_ids.len());
+        {
+            let redis = self.redis.read().await;
+            for kefu_id in kefu_ids {
+                match redis.count_kefu_sessions(&kefu_id).await {
+                    Ok(load) => loads.push((kefu_id, load)),
+                    Err(e) => tracing::warn!("⚠️ 获取客服负载失败: {}, error: {:?}", kefu_id, e),
                 }
             }
         }
 
-        Ok(None)
+        let targets = select_idlest_kefu(loads, self.new_customer_alert_count);
+        if targets.is_empty() {
+            tracing::warn!("⚠️ 没有可接待的客服，新客户{}暂无人提醒", customer_id);
+            return;
+        }
+
+        tracing::info!("🔔 新客户{}等待接入，提醒客服: {:?}", customer_id, targets);
+        let alert = AppMessage::System {
+            content: format!("新客户等待接入: {}({})", customer_name, customer_id),
+            timestamp: Utc::now(),
+        };
+        let alert = Arc::new(alert);
+        for kefu_id in targets {
+            if let Some(sender) = self.get_user_sender(&kefu_id).await {
+                let _ = sender.try_send(Arc::clone(&alert));
+            }
+        }
     }
 
     // 寻找可用客服
@@ -1249,19 +3116,37 @@ impl WebSocketManager {
         Err(anyhow::anyhow!("No available kefu found"))
     }
 
-    // 寻找等待的客户
+    // 寻找等待的客户：按等待队列先到先得，队列中没有可认领的客户时才查找在线但未分配客服的客户
     async fn find_waiting_customer(&self) -> Result<String> {
-        let connections = self.connections.read().await;
+        let waiting_customers = self.redis.read().await.get_waiting_queue().await?;
+        // 队列中的客户都已被其他客服或实例接走时不回退，避免绕过排队顺序
+        if !waiting_customers.is_empty() {
+            return self
+                .take_waiting_customer(waiting_customers)
+                .await
+                .ok_or_else(|| anyhow::anyhow!("No waiting customer found"));
+        }
 
-        // 查找在线但没有分配客服的客户
-        for (user_id, connection) in connections.iter() {
-            if connection.user_type == UserType::Kehu {
-                // 检查这个客户是否已经有客服
-                let redis = self.redis.read().await;
-                if let Ok(None) = redis.get_partner(user_id).await {
-                    // 没有伙伴关系，说明客户在等待
-                    return Ok(user_id.clone());
-                }
+        // 队列为空时回退到本实例的在线客户，按连接时间先后查找，同样经原子认领避免重复分配
+        let mut customers: Vec<(chrono::DateTime<Utc>, String)> = self
+            .connections
+            .read()
+            .await
+            .iter()
+            .filter(|(_, connection)| connection.user_type == UserType::Kehu)
+            .map(|(user_id, connection)| (connection.connected_at, user_id.clone()))
+            .collect();
+        customers.sort();
+
+        let redis = self.redis.read().await;
+        for (_, user_id) in customers {
+            if !matches!(redis.get_partner(&user_id).await, Ok(None)) {
+                continue;
+            }
+            match redis.claim_waiting_customer(&user_id).await {
+                Ok(true) => return Ok(user_id),
+                Ok(false) => continue,
+                Err(e) => tracing::warn!("⚠️ 认领客户失败: {}, error: {:?}", user_id, e),
             }
         }
 
@@ -1274,67 +3159,172 @@ impl WebSocketManager {
         kehu_id: &str,
         kefu_id: &str,
         _zhanghao: &Option<String>,
-    ) -> Result<()> {
+    ) -> Result<(), WebSocketError> {
         let redis = self.redis.write().await;
 
         // 使用企业级增强会话建立功能
-        redis.establish_session_enhanced(kehu_id, kefu_id).await?;
+        let created = redis
+            .establish_session_enhanced(kehu_id, kefu_id)
+            .await
+            .map_err(WebSocketError::Redis)?;
+        self.sla_tracker.session_started(kehu_id, Utc::now());
+        drop(redis);
 
         tracing::info!(
             "🎯 企业级会话已建立: {} <-> {} (增强模式)",
             kehu_id,
             kefu_id
         );
+        // 重连、重复分配到同一客服时会话已存在，不重复问候
+        if created || self.conversation_is_empty(kehu_id, kefu_id).await {
+            self.send_greeting(kehu_id, kefu_id).await;
+        }
         Ok(())
     }
 
+    // 两人之间还没有任何消息；读取失败时按非空处理，宁可少发问候语
+    async fn conversation_is_empty(&self, kehu_id: &str, kefu_id: &str) -> bool {
+        match self.message_store.get_recent_messages(kehu_id, kefu_id, 1).await {
+            Ok(messages) => messages.is_empty(),
+            Err(e) => {
+                tracing::warn!("⚠️ 读取会话消息失败，跳过问候语: {} <-> {}, error: {:?}", kehu_id, kefu_id, e);
+                false
+            }
+        }
+    }
+
+    // 会话建立后以客服身份发送问候语，与普通聊天消息一样保存并分配序号；不计入客服首次响应SLA，失败只记日志
+    async fn send_greeting(&self, kehu_id: &str, kefu_id: &str) {
+        let (customer_name, kefu_name) = {
+            let connections = self.connections.read().await;
+            let name = |user_id: &str| {
+                connections
+                    .get(user_id)
+                    .map(|connection| connection.user_name.clone())
+                    .unwrap_or_default()
+            };
+            (name(kehu_id), name(kefu_id))
+        };
+        let Some(content) = render_greeting(&self.greeting, kefu_id, &kefu_name, &customer_name) else {
+            return;
+        };
+
+        let message = ChatMessage {
+            id: Some(Uuid::new_v4().to_string()),
+            from: kefu_id.to_string(),
+            to: Some(kehu_id.to_string()),
+            content,
+            content_type: Some(ContentType::Text),
+            filename: None,
+            timestamp: Utc::now(),
+            url: None,
+            thumbnail_url: None,
+            seq: None,
+        };
+        let saved = match self.message_store.save_message(&message).await {
+            Ok(saved) => saved.into_message(),
+            Err(e) => {
+                tracing::warn!("⚠️ 保存问候语失败: {} -> {}, error: {:?}", kefu_id, kehu_id, e);
+                return;
+            }
+        };
+
+        let greeting = AppMessage::Chat {
+            id: saved.id,
+            from: saved.from,
+            to: saved.to,
+            content: saved.content,
+            content_type: saved.content_type,
+            filename: None,
+            timestamp: saved.timestamp,
+            url: None,
+            thumbnail_url: None,
+            out_of_order: None,
+            seq: saved.seq,
+            canned_response_id: None,
+        };
+        // 回显给客服，客服端的会话记录与客户一致
+        for user_id in [kehu_id, kefu_id] {
+            if let Err(e) = self.send_to_user(user_id, greeting.clone()).await {
+                tracing::warn!("⚠️ 发送问候语失败: {}, error: {:?}", user_id, e);
+            }
+        }
+        tracing::info!("👋 已发送问候语: {} -> {}", kefu_id, kehu_id);
+    }
+
     // 发送历史消息
     async fn send_history_messages(
         &self,
         user_id: &str,
         user_type: &UserType,
-        sender: &mpsc::UnboundedSender<AppMessage>,
+        sender: &mpsc::Sender<SharedMessage>,
     ) -> Result<()> {
         // 从本地存储获取历史消息
-        let messages = match user_type {
+        let page = match user_type {
             UserType::Kefu => {
                 // 客服只获取空的历史消息，会话历史将通过客户切换时单独请求
-                Ok(Vec::new())
-            }
-            UserType::Kehu => {
-                // 客户获取与所有用户的消息
-                self.storage.get_recent_messages(user_id, "all", 20)
+                Ok(HistoryPage::default())
             }
+            // 序号按会话分配，须按客户实际所在的会话读取，latest_seq 才能用于重连补齐
+            UserType::Kehu => match self.customer_conversation_kefu(user_id).await {
+                Some(kefu_id) => self.storage.get_messages_before(user_id, &kefu_id, None, 20),
+                None => Ok(HistoryPage::default()),
+            },
         };
 
-        if let Ok(chat_messages) = messages {
+        if let Ok(page) = page {
             // 批量发送历史消息
-            let history_message = AppMessage::History {
-                messages: chat_messages,
-            };
-            let _ = sender.send(history_message);
+            let history_message = self.history_message(page).await;
+            let _ = sender.try_send(Arc::new(history_message));
         }
 
         Ok(())
     }
 
-    // 新增：发送特定客户的历史消息
+    // 客户当前所在会话的客服：优先取配对关系，未配对时取最近活动的历史会话
+    async fn customer_conversation_kefu(&self, kehu_id: &str) -> Option<String> {
+        if let Ok(Some(kefu_id)) = self.redis.read().await.get_partner(kehu_id).await {
+            return Some(kefu_id);
+        }
+        match self.storage.get_user_sessions(kehu_id) {
+            Ok(sessions) => sessions
+                .into_iter()
+                .find(|session| session.kehu_id == kehu_id)
+                .map(|session| session.kefu_id),
+            Err(e) => {
+                tracing::warn!("⚠️ 读取客户会话失败: {}, error: {:?}", kehu_id, e);
+                None
+            }
+        }
+    }
+
+    // 新增：发送特定客户的历史消息，before 为翻页游标；since_seq 用于重连后只补齐缺失的消息
     async fn send_customer_history_messages(
         &self,
         kefu_id: &str,
         customer_id: &str,
-        sender: &mpsc::UnboundedSender<AppMessage>,
+        before: Option<&HistoryCursor>,
+        since_seq: Option<u64>,
+        limit: usize,
+        sender: &mpsc::Sender<SharedMessage>,
     ) -> Result<()> {
         // 获取客服与特定客户的历史消息
-        let messages = self.storage.get_recent_messages(kefu_id, customer_id, 50);
+        let page = match since_seq {
+            Some(since_seq) => self.storage.get_history_since(kefu_id, customer_id, since_seq, limit),
+            None => self.storage.get_messages_before(kefu_id, customer_id, before, limit),
+        };
 
-        if let Ok(chat_messages) = messages {
-            tracing::info!("📚 发送客服{}与客户{}的历史消息: {}条", kefu_id, customer_id, chat_messages.len());
-            
-            let history_message = AppMessage::History {
-                messages: chat_messages,
-            };
-            let _ = sender.send(history_message);
+        if let Ok(page) = page {
+            tracing::info!(
+                "📚 发送客服{}与客户{}的历史消息: {}条, has_more={}",
+                kefu_id,
+                customer_id,
+                page.messages.len(),
+                page.has_more
+            );
+
+            let history_message = self.history_message(page).await;
+            let _ = sender.try_send(Arc::new(history_message));
         } else {
             tracing::warn!("⚠️ 获取历史消息失败: {} <-> {}", kefu_id, customer_id);
         }
@@ -1343,7 +3333,7 @@ impl WebSocketManager {
     }
 
     // 发送在线用户列表
-    async fn send_online_users(&self, sender: &mpsc::UnboundedSender<AppMessage>) -> Result<()> {
+    async fn send_online_users(&self, sender: &mpsc::Sender<SharedMessage>) -> Result<()> {
         let connections = self.connections.read().await;
         let mut users = Vec::new();
 
@@ -1368,31 +3358,93 @@ impl WebSocketManager {
 
         let user_count = users.len();
         let online_users_message = AppMessage::OnlineUsers { users: Some(users) };
-        let _ = sender.send(online_users_message);
+        let _ = sender.try_send(Arc::new(online_users_message));
 
         tracing::info!("📋 发送在线客户列表: 共{}个客户", user_count);
 
         Ok(())
     }
 
+    // 登记连接：按重复连接策略和连接上限决定接受还是拒绝，接受时返回被替换的旧连接。
+    // 连接记录、发送器和关闭信号都在关闭信号表的写锁内更新，与清理互斥；
+    // 其他代码不会在持有连接表或发送器表时再获取该锁，不会形成锁环
+    async fn register_connection(
+        &self,
+        user_connection: &UserConnection,
+        tx: &mpsc::Sender<SharedMessage>,
+        close_signal: &Arc<CloseSignal>,
+    ) -> std::result::Result<Option<UserConnection>, CloseReason> {
+        let user_id = &user_connection.user_id;
+        let mut close_signals = self.close_signals.write().await;
+        let replaced = {
+            let mut connections = self.connections.write().await;
+            let existing = connections.contains_key(user_id);
+            if existing && self.duplicate_connection_policy == DuplicateConnectionPolicy::Reject {
+                tracing::warn!("🚫 用户{}已有连接，按配置拒绝新连接", user_id);
+                return Err(CloseReason::DuplicateConnection);
+            }
+            if !existing && connection_limit_reached(connections.len(), self.max_connections) {
+                tracing::warn!("🚦 连接数已达上限{}，拒绝新连接: {}", self.max_connections, user_id);
+                return Err(CloseReason::ServerBusy);
+            }
+            connections.insert(user_id.clone(), user_connection.clone())
+        };
+        self.senders.write().await.insert(user_id.clone(), tx.clone());
+        if let Some(signal) = close_signals.insert(user_id.clone(), close_signal.clone()) {
+            signal.close(CloseReason::Replaced);
+        }
+        Ok(replaced)
+    }
+
     // 清理连接
+    // 连接结束时只清理仍是当前连接的记录；已被同一用户的新连接替换时跳过，避免把新连接当作下线。
+    // 判断与移除在同一次关闭信号表写锁内完成，新连接无法在两者之间登记
+    async fn cleanup_connection_if_current(&self, user_id: &str, close_signal: &Arc<CloseSignal>) {
+        let user_info = {
+            let mut close_signals = self.close_signals.write().await;
+            let is_current = close_signals
+                .get(user_id)
+                .is_some_and(|current| Arc::ptr_eq(current, close_signal));
+            if !is_current {
+                tracing::info!("🔁 {}的旧连接已结束，当前连接不受影响", user_id);
+                return;
+            }
+            close_signals.remove(user_id);
+            self.remove_connection_entries(user_id).await
+        };
+        self.finish_cleanup(user_id, user_info).await;
+    }
+
     pub async fn cleanup_connection(&self, user_id: &str) {
-        // 🚀 在移除连接前获取用户信息用于实时通知
         let user_info = {
-            let connections = self.connections.read().await;
-            connections.get(user_id).cloned()
+            let mut close_signals = self.close_signals.write().await;
+            close_signals.remove(user_id);
+            self.remove_connection_entries(user_id).await
         };
+        self.finish_cleanup(user_id, user_info).await;
+    }
 
-        // 从连接管理器移除
-        {
-            let mut connections = self.connections.write().await;
-            connections.remove(user_id);
+    // 移除连接记录和发送器，返回移除前的连接信息用于实时通知；调用方需持有关闭信号表的写锁
+    async fn remove_connection_entries(&self, user_id: &str) -> Option<UserConnection> {
+        let user_info = self.connections.write().await.remove(user_id);
+        self.senders.write().await.remove(user_id);
+        user_info
+    }
+
+    async fn finish_cleanup(&self, user_id: &str, user_info: Option<UserConnection>) {
+        self.sentiment_tracker.reset(user_id);
+        self.bandwidth.remove(user_id);
+        if let Some(conn) = &user_info {
+            self.record_connection_event(ConnectionEvent::disconnect(conn, Utc::now())).await;
+        }
+        if let Some(conn) = user_info.as_ref().filter(|conn| conn.user_type == UserType::Kehu) {
+            self.sla_tracker.session_ended(user_id);
+            self.spawn_session_summary(user_id, conn.connected_at).await;
         }
 
-        // 从发送器管理器移除
-        {
-            let mut senders = self.senders.write().await;
-            senders.remove(user_id);
+        // 输入中途断开时立即清除对方看到的打字状态
+        if let Some(target) = self.cancel_typing_clear(user_id).await {
+            let _ = self.send_typing_cleared(user_id, &target).await;
         }
 
         // 更新Redis中的离线状态
@@ -1490,9 +3542,138 @@ impl WebSocketManager {
             kehu_connections,
             average_connection_duration: average_duration,
             longest_connection_duration: longest_duration,
+            bandwidth: self.bandwidth.totals(),
+            connection_bandwidth: self.bandwidth.snapshot(),
+            acks: self.acks.stats(),
         }
     }
 
+    /// 启动消息确认检查：超时未确认的消息计为未确认，检查周期内未确认率过高时告警（可能对端批量掉线）
+    pub async fn start_ack_monitor(&self) {
+        let acks = self.acks.clone();
+        let timeout = self.ack_timeout;
+        let alert_rate = self.unacked_alert_rate;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ACK_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let expired = acks.expire(timeout, std::time::Instant::now());
+                let (acked, unacked) = acks.take_window();
+                if unacked_rate_alert(acked, unacked, alert_rate) {
+                    tracing::warn!(
+                        "🚨 消息未确认率过高: 最近{}秒确认{}条、未确认{}条（其中超时{}条），未确认率{:.1}%，可能有接收方批量掉线",
+                        ACK_CHECK_INTERVAL.as_secs(),
+                        acked,
+                        unacked,
+                        expired,
+                        crate::monitoring::metrics::unacked_rate(acked, unacked) * 100.0
+                    );
+                }
+            }
+        });
+    }
+
+    /// 启动带宽检查：每个检查周期内收发字节数超过阈值的连接记录告警日志
+    pub async fn start_bandwidth_monitor(&self) {
+        if self.bandwidth_alert_bytes_per_sec == 0 {
+            return;
+        }
+        let bandwidth = self.bandwidth.clone();
+        let limit = self.bandwidth_alert_bytes_per_sec * BANDWIDTH_CHECK_INTERVAL.as_secs();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(BANDWIDTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                for (user_id, bytes) in bandwidth.take_heavy_connections(limit) {
+                    tracing::warn!(
+                        "🚨 连接带宽异常: user={}, 最近{}秒收发{}字节（约{}字节/秒）",
+                        user_id,
+                        BANDWIDTH_CHECK_INTERVAL.as_secs(),
+                        bytes,
+                        bytes / BANDWIDTH_CHECK_INTERVAL.as_secs()
+                    );
+                }
+            }
+        });
+    }
+
+    // 定期采样连接数与类型分布，写入连接历史
+    pub async fn start_connection_sampler(&self, interval: std::time::Duration) {
+        let connections = self.connections.clone();
+        let history = self.connection_history.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+
+            loop {
+                interval.tick().await;
+
+                let sample = {
+                    let connections = connections.read().await;
+                    let kefu_connections = connections
+                        .values()
+                        .filter(|connection| connection.user_type == UserType::Kefu)
+                        .count();
+                    ConnectionSample {
+                        timestamp: Utc::now(),
+                        total_connections: connections.len(),
+                        kefu_connections,
+                        kehu_connections: connections.len() - kefu_connections,
+                    }
+                };
+                history.record(sample);
+            }
+        });
+    }
+
+    // 定期采样在线数与客服利用率，交给扩缩容判断，建议变化时记录日志
+    pub async fn start_scaling_sampler(&self, interval: std::time::Duration) {
+        let manager = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+
+            loop {
+                interval.tick().await;
+
+                let active_sessions: Vec<usize> = manager
+                    .get_kefu_workloads()
+                    .await
+                    .iter()
+                    .map(|workload| workload["active_sessions"].as_u64().unwrap_or(0) as usize)
+                    .collect();
+                let recommendation = manager.auto_scaler.observe(ScalingSample {
+                    timestamp: Utc::now(),
+                    online_users: manager.get_realtime_online_count().await,
+                    kefu_utilization: crate::kefu_alert::summarize_workloads(&active_sessions).utilization_rate,
+                });
+                if recommendation.action != ScalingAction::Hold {
+                    tracing::warn!(
+                        "📐 扩缩容建议: {:?} {} -> {} 个实例（{}）",
+                        recommendation.action,
+                        recommendation.current_replicas,
+                        recommendation.target_replicas,
+                        recommendation.reason
+                    );
+                }
+            }
+        });
+    }
+
+    // 最近一次扩缩容建议，尚未采样时为 None
+    pub fn get_scaling_recommendation(&self) -> Option<ScalingRecommendation> {
+        self.auto_scaler.latest()
+    }
+
+    // 查询时间区间内的连接数曲线
+    pub fn get_connection_history(
+        &self,
+        from: chrono::DateTime<Utc>,
+        to: chrono::DateTime<Utc>,
+    ) -> Vec<ConnectionSample> {
+        self.connection_history.range(from, to)
+    }
+
     // 启动心跳检查器
     pub async fn start_heartbeat_checker(&self) {
         let redis = self.redis.clone();
@@ -1557,6 +3738,7 @@ impl WebSocketManager {
             match session_result {
                 Ok(_) => {
                     tracing::info!("✅ 客服{}成功切换到客户: {}", kefu_id, real_customer_id);
+                    self.sla_tracker.session_started(&real_customer_id, Utc::now());
 
                     // 发送切换成功通知给客服
                     let switch_notification = AppMessage::System {
@@ -1567,11 +3749,11 @@ impl WebSocketManager {
 
                     // 发送历史消息
                     if let Some(sender) = self.get_user_sender(kefu_id).await {
-                        let messages =
-                            self.storage
-                                .get_recent_messages(kefu_id, &real_customer_id, 20)?;
-                        let history_message = AppMessage::History { messages };
-                        let _ = sender.send(history_message);
+                        let page = self
+                            .storage
+                            .get_messages_before(kefu_id, &real_customer_id, None, 20)?;
+                        let history_message = self.history_message(page).await;
+                        let _ = sender.try_send(Arc::new(history_message));
                     }
 
                     Ok(true)
@@ -1587,6 +3769,82 @@ impl WebSocketManager {
         }
     }
 
+    // 🔀 会话转接：客服离开时把正在接待的客户交给另一位在线客服
+    pub async fn transfer_session(
+        &self,
+        from_kefu: &str,
+        to_kefu: &str,
+        customer_id: &str,
+    ) -> Result<SessionTransferOutcome> {
+        tracing::info!("🔀 客服{}请求将客户{}转接给客服{}", from_kefu, customer_id, to_kefu);
+
+        let target_online = {
+            let connections = self.connections.read().await;
+            connections
+                .get(to_kefu)
+                .is_some_and(|connection| connection.user_type == UserType::Kefu)
+        };
+        if !target_online {
+            tracing::warn!("⚠️ 转接目标客服{}不在线", to_kefu);
+            return Ok(SessionTransferOutcome::TargetKefuOffline);
+        }
+
+        {
+            let redis = self.redis.read().await;
+            if !redis.is_kefu_serving(from_kefu, customer_id).await? {
+                tracing::warn!("⚠️ 客户{}当前不由客服{}接待", customer_id, from_kefu);
+                return Ok(SessionTransferOutcome::SessionNotFound);
+            }
+
+            let active_sessions = redis.count_kefu_sessions(to_kefu).await?;
+            if active_sessions >= MAX_KEFU_SESSIONS {
+                tracing::warn!("⚠️ 客服{}已达接待上限: {}/{}", to_kefu, active_sessions, MAX_KEFU_SESSIONS);
+                return Ok(SessionTransferOutcome::TargetKefuBusy { active_sessions });
+            }
+
+            redis.clear_session(customer_id, from_kefu).await?;
+            redis.establish_session_enhanced(customer_id, to_kefu).await?;
+        }
+
+        // 新客服接收转接通知和此前的会话记录
+        let page = self.storage.get_messages_before(customer_id, from_kefu, None, HISTORY_PAGE_SIZE)?;
+        let history_count = page.messages.len();
+        self.send_to_user(
+            to_kefu,
+            AppMessage::System {
+                content: format!("🔀 客服{}将客户{}转接给您", from_kefu, customer_id),
+                timestamp: Utc::now(),
+            },
+        )
+        .await?;
+        let history_message = self.history_message(page).await;
+        self.send_to_user(to_kefu, history_message).await?;
+
+        self.send_to_user(
+            customer_id,
+            AppMessage::System {
+                content: format!("您的会话已转接给客服{}，请稍候", to_kefu),
+                timestamp: Utc::now(),
+            },
+        )
+        .await?;
+        self.send_to_user(
+            from_kefu,
+            AppMessage::System {
+                content: format!("✅ 客户{}已转接给客服{}", customer_id, to_kefu),
+                timestamp: Utc::now(),
+            },
+        )
+        .await?;
+
+        if let Err(e) = self.broadcast_customer_list().await {
+            tracing::warn!("⚠️ 转接后刷新客户列表失败: {:?}", e);
+        }
+
+        tracing::info!("✅ 会话转接完成: {} {} -> {}", customer_id, from_kefu, to_kefu);
+        Ok(SessionTransferOutcome::Transferred { history_count })
+    }
+
     // 🔍 智能用户ID匹配算法 - 解决ID不一致问题
     #[allow(dead_code)]
     async fn find_actual_customer_id(&self, partial_id: &str) -> Result<Option<String>> {
@@ -1660,8 +3918,9 @@ impl WebSocketManager {
                     if customer_conn.user_type == UserType::Kehu {
                         // 获取最后一条消息
                         let last_message = self
-                            .storage
+                            .message_store
                             .get_recent_messages(kefu_id, &customer_id, 1)
+                            .await
                             .unwrap_or_default()
                             .first()
                             .map(|msg| msg.content.clone())
@@ -1731,64 +3990,320 @@ impl WebSocketManager {
             filename: Some(params.original_filename.clone()),
             timestamp: params.timestamp,
             url: Some(params.access_url.clone()),
+            thumbnail_url: None,
+            seq: None,
+        };
+
+        // 保存到本地存储，并在语音元数据中记下消息ID，之后推送转写结果时沿用
+        match self.message_store.save_message(&chat_message).await {
+            Ok(saved) => {
+                if let (Some(voice_manager), Some(message_id)) = (&self.voice_manager, saved.into_message().id) {
+                    if let Err(e) = voice_manager.set_message_id(&params.voice_id, &message_id).await {
+                        tracing::warn!("⚠️ 记录语音消息ID失败: voice_id={}, error: {:?}", params.voice_id, e);
+                    }
+                }
+            }
+            Err(e) => tracing::error!("💾 保存语音消息到本地存储失败: {:?}", e),
+        }
+
+        // 语音消息暂时不需要特殊的Redis保存逻辑，因为ChatMessage已经通过常规方式保存了
+        tracing::debug!("🎤 语音消息元数据已保存: voice_id={}", params.voice_id);
+
+        // 处理消息转发逻辑
+        let mut recipient = params.to.clone();
+        if let Some(to_user) = &params.to {
+            tracing::info!("📤 转发语音消息给接收者: {}", to_user);
+            self.send_to_user(to_user, voice_message.clone()).await?;
+        } else {
+            // 没有指定接收者，根据用户类型智能路由
+            let user_connection = {
+                let connections = self.connections.read().await;
+                connections.get(current_user_id).cloned()
+            };
+
+            if let Some(connection) = user_connection {
+                let chat_partner = self.get_chat_partner(current_user_id, &connection.user_type).await?;
+                
+                if let Some(partner_id) = chat_partner {
+                    tracing::info!("📤 智能路由语音消息给对话伙伴: {}", partner_id);
+                    
+                    // 更新消息中的接收者
+                    let routed_message = AppMessage::Voice {
+                        id: params.id.clone(),
+                        from: params.from.clone(),
+                        to: Some(partner_id.clone()),
+                        voice_id: params.voice_id.clone(),
+                        file_id: params.file_id.clone(),
+                        original_filename: params.original_filename.clone(),
+                        file_size: params.file_size,
+                        duration: params.duration,
+                        format: params.format.clone(),
+                        access_url: params.access_url.clone(),
+                        transcription: params.transcription.clone(),
+                        timestamp: params.timestamp,
+                    };
+                    
+                    self.send_to_user(&partner_id, routed_message).await?;
+                    recipient = Some(partner_id);
+                } else {
+                    tracing::warn!("⚠️ 语音消息无法找到对话伙伴: {}", current_user_id);
+                }
+            } else {
+                tracing::warn!("⚠️ 用户连接不存在: {}", current_user_id);
+            }
+        }
+
+        // 语音消息同样计入会话活动
+        if let Some(recipient) = &recipient {
+            let sender_type = {
+                let connections = self.connections.read().await;
+                connections.get(current_user_id).map(|conn| conn.user_type.clone())
+            };
+            if let Some(sender_type) = &sender_type {
+                self.touch_session(sender_type, current_user_id, recipient, Utc::now()).await;
+            }
+        }
+
+        // 回显给发送者（确认消息已处理）
+        tracing::info!("📤 回显语音消息给发送者: {}", current_user_id);
+        self.send_to_user(current_user_id, voice_message).await?;
+
+        if params.transcription.is_none() {
+            self.spawn_voice_transcription(&params, recipient).await;
+        }
+
+        tracing::info!("✅ 语音消息处理完成: voice_id={}", params.voice_id);
+        Ok(())
+    }
+
+    /// 语音消息未附带转写文本时提交服务端语音识别，完成后向双方推送带转写的语音消息。
+    /// 超过分段时长的语音按段识别，每完成一段向双方推送一条转写进度的系统消息
+    async fn spawn_voice_transcription(&self, params: &VoiceMessageParams, recipient: Option<String>) {
+        let (ai_manager, voice_manager) = match (&self.ai_manager, &self.voice_manager) {
+            (Some(ai_manager), Some(voice_manager)) => (ai_manager.clone(), voice_manager.clone()),
+            _ => return,
+        };
+
+        let speech_config = ai_manager.get_config().await.speech_recognition;
+        if !speech_config.auto_transcribe_voice {
+            return;
+        }
+        let streaming = use_streaming_transcription(params.duration, speech_config.streaming_chunk_seconds);
+
+        let audio_file_path = match voice_manager.get_voice_file_path(&params.voice_id).await {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                tracing::warn!("⚠️ 语音文件不存在，跳过转写: voice_id={}", params.voice_id);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("⚠️ 查找语音文件失败: voice_id={}, error: {:?}", params.voice_id, e);
+                return;
+            }
+        };
+
+        let mut task = voice_transcription_task(
+            &params.from,
+            params.id.clone().unwrap_or_else(|| params.voice_id.clone()),
+            &audio_file_path,
+            &params.access_url,
+            &params.voice_id,
+        );
+        // 提交前订阅，不会漏掉第一段的中间结果
+        let mut interim = None;
+        if streaming {
+            task.task_type = AITaskType::StreamingSpeechRecognition;
+            interim = Some(ai_manager.subscribe_interim());
+        }
+        let task_id = match ai_manager.submit_task(task).await {
+            Ok(task_id) => task_id,
+            Err(e) => {
+                tracing::warn!("⚠️ 提交语音转写任务失败: voice_id={}, error: {:?}", params.voice_id, e);
+                return;
+            }
+        };
+        tracing::info!("🎤 已提交语音转写任务: voice_id={}, task_id={}", params.voice_id, task_id);
+
+        let mut updated_message = AppMessage::Voice {
+            id: params.id.clone(),
+            from: params.from.clone(),
+            to: recipient.clone(),
+            voice_id: params.voice_id.clone(),
+            file_id: params.file_id.clone(),
+            original_filename: params.original_filename.clone(),
+            file_size: params.file_size,
+            duration: params.duration,
+            format: params.format.clone(),
+            access_url: params.access_url.clone(),
+            transcription: None,
+            timestamp: params.timestamp,
+        };
+        let sender = params.from.clone();
+        let voice_id = params.voice_id.clone();
+        let manager = self.clone();
+
+        tokio::spawn(async move {
+            let backfill = backfill_voice_transcription(&ai_manager, &voice_manager, &task_id, &voice_id);
+            tokio::pin!(backfill);
+            let text = match interim.as_mut() {
+                None => backfill.await,
+                Some(receiver) => loop {
+                    tokio::select! {
+                        text = &mut backfill => break text,
+                        received = receiver.recv() => match received {
+                            Ok(result) if result.task_id == task_id => {
+                                let message = interim_transcription_message(&voice_id, &result);
+                                if let Some(recipient) = &recipient {
+                                    let _ = manager.send_to_user(recipient, message.clone()).await;
+                                }
+                                let _ = manager.send_to_user(&sender, message).await;
+                            }
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break (&mut backfill).await,
+                            // 其他任务的中间结果，或积压被丢弃的中间结果，最终结果不受影响
+                            _ => {}
+                        },
+                    }
+                },
+            };
+            let Some(text) = text else {
+                return;
+            };
+
+            if let AppMessage::Voice { ref mut transcription, .. } = updated_message {
+                *transcription = Some(text);
+            }
+            if let Some(recipient) = &recipient {
+                let _ = manager.send_to_user(recipient, updated_message.clone()).await;
+            }
+            let _ = manager.send_to_user(&sender, updated_message).await;
+            tracing::info!("🎤 语音转写结果已推送: task_id={}", task_id);
+        });
+    }
+
+    /// 客服按需转写指定语音：提交语音识别任务，完成后回填语音元数据并向双方推送带转写的语音消息
+    // kefu_id 为 None 表示管理员，不限会话
+    pub async fn request_voice_transcription(
+        &self,
+        voice_id: &str,
+        kefu_id: Option<&str>,
+    ) -> Result<VoiceTranscriptionRequest> {
+        let (Some(ai_manager), Some(voice_manager)) = (self.ai_manager.clone(), self.voice_manager.clone()) else {
+            return Ok(VoiceTranscriptionRequest::Unavailable);
+        };
+        let Some(voice) = voice_manager.get_voice_message(voice_id).await? else {
+            return Ok(VoiceTranscriptionRequest::VoiceNotFound);
+        };
+        if let Some(kefu_id) = kefu_id {
+            if voice.from != kefu_id && voice.to.as_deref() != Some(kefu_id) {
+                return Ok(VoiceTranscriptionRequest::Forbidden);
+            }
+        }
+        if let Some(text) = voice.transcription.clone().filter(|text| !text.is_empty()) {
+            return Ok(VoiceTranscriptionRequest::AlreadyTranscribed(text));
+        }
+        let audio_file_path = voice_manager
+            .get_voice_file_path(voice_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("语音文件不存在: {}", voice_id))?;
+
+        let task = voice_transcription_task(&voice.from, voice.id.clone(), &audio_file_path, &voice.access_url, &voice.id);
+        let task_id = ai_manager.submit_task(task).await?;
+        tracing::info!("🎤 已按需提交语音转写任务: voice_id={}, task_id={}", voice_id, task_id);
+
+        let manager = self.clone();
+        let voice_id = voice_id.to_string();
+        let spawned_task_id = task_id.clone();
+        tokio::spawn(async move {
+            let task_id = spawned_task_id;
+            if backfill_voice_transcription(&ai_manager, &voice_manager, &task_id, &voice_id).await.is_none() {
+                return;
+            }
+            let Ok(Some(voice)) = voice_manager.get_voice_message(&voice_id).await else {
+                return;
+            };
+            let message = voice_app_message(&voice);
+            for user_id in std::iter::once(&voice.from).chain(voice.to.as_ref()) {
+                let _ = manager.send_to_user(user_id, message.clone()).await;
+            }
+            tracing::info!("🎤 按需转写结果已推送: voice_id={}, task_id={}", voice_id, task_id);
+        });
+
+        Ok(VoiceTranscriptionRequest::Submitted { task_id })
+    }
+
+    /// 客户离开时为本次会话提交摘要任务，生成后存入会话记录
+    async fn spawn_session_summary(&self, customer_id: &str, since: chrono::DateTime<Utc>) {
+        let Some(ai_manager) = self.ai_manager.clone() else {
+            return;
         };
+        let config = ai_manager.get_config().await.summarization;
+        if !config.auto_summarize {
+            return;
+        }
 
-        // 保存到本地存储
-        if let Err(e) = self.storage.save_message(&chat_message) {
-            tracing::error!("💾 保存语音消息到本地存储失败: {:?}", e);
+        let kefu_id = match self.redis.read().await.get_partner(customer_id).await {
+            Ok(Some(kefu_id)) => kefu_id,
+            _ => return,
+        };
+        let messages: Vec<ChatMessage> = match self.storage.get_messages(customer_id, &kefu_id) {
+            Ok(messages) => messages.into_iter().filter(|m| m.timestamp >= since).collect(),
+            Err(e) => {
+                tracing::warn!("⚠️ 读取会话消息失败，跳过摘要: {}, error: {:?}", customer_id, e);
+                return;
+            }
+        };
+        if messages.len() < config.min_messages {
+            return;
         }
 
-        // 语音消息暂时不需要特殊的Redis保存逻辑，因为ChatMessage已经通过常规方式保存了
-        tracing::debug!("🎤 语音消息元数据已保存: voice_id={}", params.voice_id);
+        let task = AITask::new(
+            AITaskType::Summarization,
+            customer_id.to_string(),
+            format!("{}:{}", customer_id, kefu_id),
+            summary_input(&messages, customer_id),
+            3,
+        );
+        let task_id = match ai_manager.submit_task(task).await {
+            Ok(task_id) => task_id,
+            Err(e) => {
+                tracing::warn!("⚠️ 提交会话摘要任务失败: {}, error: {:?}", customer_id, e);
+                return;
+            }
+        };
+        tracing::info!("📝 已提交会话摘要任务: {} <-> {}, task_id={}", customer_id, kefu_id, task_id);
 
-        // 处理消息转发逻辑
-        if let Some(to_user) = &params.to {
-            tracing::info!("📤 转发语音消息给接收者: {}", to_user);
-            self.send_to_user(to_user, voice_message.clone()).await?;
-        } else {
-            // 没有指定接收者，根据用户类型智能路由
-            let user_connection = {
-                let connections = self.connections.read().await;
-                connections.get(current_user_id).cloned()
+        let storage = self.storage.clone();
+        let customer_id = customer_id.to_string();
+        tokio::spawn(async move {
+            let output = match ai_manager.wait_for_result(&task_id, SESSION_SUMMARY_TIMEOUT).await {
+                Ok(Some(result)) => serde_json::from_value::<SummaryResult>(result.result),
+                Ok(None) => {
+                    tracing::warn!("⚠️ 会话摘要生成失败: task_id={}", task_id);
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!("⚠️ 等待会话摘要结果失败: {:?}", e);
+                    return;
+                }
             };
-
-            if let Some(connection) = user_connection {
-                let chat_partner = self.get_chat_partner(current_user_id, &connection.user_type).await?;
-                
-                if let Some(partner_id) = chat_partner {
-                    tracing::info!("📤 智能路由语音消息给对话伙伴: {}", partner_id);
-                    
-                    // 更新消息中的接收者
-                    let routed_message = AppMessage::Voice {
-                        id: params.id.clone(),
-                        from: params.from.clone(),
-                        to: Some(partner_id.clone()),
-                        voice_id: params.voice_id.clone(),
-                        file_id: params.file_id.clone(),
-                        original_filename: params.original_filename.clone(),
-                        file_size: params.file_size,
-                        duration: params.duration,
-                        format: params.format.clone(),
-                        access_url: params.access_url.clone(),
-                        transcription: params.transcription.clone(),
-                        timestamp: params.timestamp,
-                    };
-                    
-                    self.send_to_user(&partner_id, routed_message).await?;
-                } else {
-                    tracing::warn!("⚠️ 语音消息无法找到对话伙伴: {}", current_user_id);
+            let summary = match output {
+                Ok(output) => SessionSummary {
+                    summary: output.summary,
+                    key_points: output.key_points,
+                    task_id: task_id.clone(),
+                    generated_at: Utc::now(),
+                },
+                Err(e) => {
+                    tracing::warn!("⚠️ 会话摘要结果格式错误: task_id={}, error: {:?}", task_id, e);
+                    return;
                 }
-            } else {
-                tracing::warn!("⚠️ 用户连接不存在: {}", current_user_id);
+            };
+            let started_at = messages.first().map_or(since, |m| m.timestamp);
+            match storage.archive_session_summary(&kefu_id, &customer_id, started_at, summary) {
+                Ok(session) => tracing::info!("📝 会话摘要已存档: {}", session.session_id),
+                Err(e) => tracing::warn!("⚠️ 会话摘要存档失败: {}, error: {:?}", customer_id, e),
             }
-        }
-
-        // 回显给发送者（确认消息已处理）
-        tracing::info!("📤 回显语音消息给发送者: {}", current_user_id);
-        self.send_to_user(current_user_id, voice_message).await?;
-
-        tracing::info!("✅ 语音消息处理完成: voice_id={}", params.voice_id);
-        Ok(())
+        });
     }
 
     /// 实时广播在线用户状态变化 - 企业级功能
@@ -1808,9 +4323,9 @@ impl WebSocketManager {
             });
         }
 
-        let status_message = AppMessage::OnlineUsers {
+        let status_message = Arc::new(AppMessage::OnlineUsers {
             users: Some(user_infos.clone()),
-        };
+        });
 
         // 广播给所有连接的客服
         let senders = self.senders.read().await;
@@ -1818,7 +4333,7 @@ impl WebSocketManager {
             let connections_guard = self.connections.read().await;
             if let Some(connection) = connections_guard.get(user_id) {
                 if connection.user_type == UserType::Kefu {
-                    if let Err(e) = sender.send(status_message.clone()) {
+                    if let Err(e) = sender.try_send(Arc::clone(&status_message)) {
                         tracing::warn!("发送实时状态消息失败 to {}: {:?}", user_id, e);
                     }
                 }
@@ -1831,10 +4346,10 @@ impl WebSocketManager {
 
     /// 用户上线时的实时通知
     pub async fn notify_user_online(&self, user_id: &str, user_name: &str, user_type: &UserType) -> Result<()> {
-        let notification = AppMessage::System {
+        let notification = Arc::new(AppMessage::System {
             content: format!("🟢 {}({}) 已上线", user_name, user_id),
             timestamp: Utc::now(),
-        };
+        });
 
         // 广播给所有客服
         let senders = self.senders.read().await;
@@ -1842,7 +4357,7 @@ impl WebSocketManager {
             let connections_guard = self.connections.read().await;
             if let Some(connection) = connections_guard.get(target_id) {
                 if connection.user_type == UserType::Kefu {
-                    if let Err(e) = sender.send(notification.clone()) {
+                    if let Err(e) = sender.try_send(Arc::clone(&notification)) {
                         tracing::warn!("发送上线通知失败 to {}: {:?}", target_id, e);
                     }
                 }
@@ -1871,10 +4386,10 @@ impl WebSocketManager {
 
     /// 用户下线时的实时通知
     pub async fn notify_user_offline(&self, user_id: &str, user_name: &str, _user_type: &UserType) -> Result<()> {
-        let notification = AppMessage::System {
+        let notification = Arc::new(AppMessage::System {
             content: format!("🔴 {}({}) 已下线", user_name, user_id),
             timestamp: Utc::now(),
-        };
+        });
 
         // 广播给所有客服
         let senders = self.senders.read().await;
@@ -1882,7 +4397,7 @@ impl WebSocketManager {
             let connections_guard = self.connections.read().await;
             if let Some(connection) = connections_guard.get(target_id) {
                 if connection.user_type == UserType::Kefu {
-                    if let Err(e) = sender.send(notification.clone()) {
+                    if let Err(e) = sender.try_send(Arc::clone(&notification)) {
                         tracing::warn!("发送下线通知失败 to {}: {:?}", target_id, e);
                     }
                 }
@@ -1928,17 +4443,18 @@ impl WebSocketManager {
                 "last_seen": Utc::now(),
                 "connection_id": format!("conn_{}_{}", user_id, connection.connected_at.timestamp()),
                 "detection_method": "实时WebSocket连接",
-                "confidence": 1.0
+                "confidence": 1.0,
+                "suspicious_reason": connection.suspicious_reason
             }));
         }
         
         users
     }
 
-    /// 强制断开指定用户的连接
+    /// 强制断开指定用户的连接，客户端收到 reason 对应的关闭帧
     /// 管理员功能，用于处理违规用户
-    pub async fn disconnect_user(&self, user_id: &str) -> bool {
-        info!("🔌 管理员强制断开用户连接: {}", user_id);
+    pub async fn disconnect_user(&self, user_id: &str, reason: CloseReason) -> bool {
+        info!("🔌 管理员强制断开用户连接: {} ({})", user_id, reason.reason());
         
         // 获取用户连接信息
         let connection_exists = {
@@ -1947,6 +4463,11 @@ impl WebSocketManager {
         };
         
         if connection_exists {
+            // 先通知发送任务发出关闭帧，清理连接后关闭信号即被移除
+            if let Some(signal) = self.close_signals.read().await.get(user_id) {
+                signal.close(reason);
+            }
+
             // 清理连接
             self.cleanup_connection(user_id).await;
             
@@ -1967,6 +4488,22 @@ impl WebSocketManager {
         }
     }
 
+    /// 断开来自某个IP的所有连接，返回断开的用户ID
+    pub async fn disconnect_ip(&self, ip: &str, reason: CloseReason) -> Vec<String> {
+        let user_ids: Vec<String> = {
+            let connections = self.connections.read().await;
+            connections
+                .values()
+                .filter(|connection| connection.client_ip.as_deref() == Some(ip))
+                .map(|connection| connection.user_id.clone())
+                .collect()
+        };
+        for user_id in &user_ids {
+            self.disconnect_user(user_id, reason).await;
+        }
+        user_ids
+    }
+
     /// 向所有在线用户广播消息
     /// 管理员功能，用于系统通知
     pub async fn broadcast_to_all(&self, message: &str) -> usize {
@@ -1991,6 +4528,40 @@ impl WebSocketManager {
         success_count
     }
 
+    /// 发布持久化系统广播：先写入广播日志，再推送给当前在线用户；
+    /// 离线用户在有效期内上线时补发
+    pub async fn publish_system_broadcast(
+        &self,
+        content: &str,
+        ttl_secs: u64,
+    ) -> Result<(SystemBroadcast, usize)> {
+        let broadcast = SystemBroadcast::new(content.to_string(), ttl_secs);
+        self.redis.read().await.push_system_broadcast(&broadcast).await?;
+
+        let senders = self.senders.read().await;
+        let delivered = fan_out(senders.values(), system_broadcast_message(&broadcast));
+        info!(
+            "📢 系统广播已发布: {} 在线送达{}人, 有效期至 {}",
+            broadcast.id, delivered, broadcast.expires_at
+        );
+        Ok((broadcast, delivered))
+    }
+
+    // 连接事件只用于审计，写入失败不影响连接本身
+    async fn record_connection_event(&self, mut event: ConnectionEvent) {
+        if let (Some(geoip), Some(ip)) = (&self.geoip, event.ip.as_deref()) {
+            event.location = geoip.locate(ip);
+        }
+        if let Err(e) = self.redis.read().await.record_connection_event(&event).await {
+            tracing::warn!("⚠️ 记录连接事件失败: user_id={}, {:?}: {:?}", event.user_id, event.kind, e);
+        }
+    }
+
+    /// 用户最近的连接/断开事件（时间、IP、User-Agent），最新的在前
+    pub async fn connection_events(&self, user_id: &str, limit: usize) -> Result<Vec<ConnectionEvent>> {
+        self.redis.read().await.get_connection_events(user_id, limit).await
+    }
+
     /// 获取用户最后活跃时间
     /// 用于用户状态监控
     pub async fn get_user_last_seen(&self, user_id: &str) -> Option<chrono::DateTime<Utc>> {
@@ -2007,77 +4578,740 @@ impl WebSocketManager {
     }
 }
 
-/**
- * 🔥 为客户端订阅Redis频道 - 事件驱动核心
- */
-// 🚀 Redis事件驱动架构 - 为用户订阅Redis频道
-async fn subscribe_redis_channels_for_user(
-    user_id: String,
-    channels: Vec<String>,
-    ws_manager: Arc<WebSocketManager>
-) {
-    tracing::info!("📡 为用户{}启动Redis频道订阅: {:?}", user_id, channels);
-    
-    // 获取Redis连接用于订阅
-    let client = match redis::Client::open("redis://127.0.0.1:6379/") {
-        Ok(client) => client,
-        Err(e) => {
-            tracing::error!("❌ Redis客户端创建失败: {}", e);
-            return;
+/// 连接数是否已达上限，max_connections 为 0 表示不限制
+/// 压缩出站帧；大帧在阻塞线程池上压缩。压缩器归单个连接的发送任务所有，锁不会被争用
+async fn compress_outbound_frame(compressor: &Arc<std::sync::Mutex<AdaptiveCompressor>>, json: &str) -> Result<Option<Vec<u8>>> {
+    if json.len() < BLOCKING_COMPRESSION_SIZE {
+        return compressor.lock().unwrap_or_else(|e| e.into_inner()).compress_frame(json);
+    }
+    let compressor = compressor.clone();
+    let json = json.to_string();
+    tokio::task::spawn_blocking(move || compressor.lock().unwrap_or_else(|e| e.into_inner()).compress_frame(&json)).await?
+}
+
+/// 加密出站消息，返回 Encrypted 帧的JSON；达到轮换阈值时先换用下一代密钥
+fn encrypt_outbound_frame(cipher: &std::sync::Mutex<SessionCipher>, json: &str) -> Result<String> {
+    let frame = cipher.lock().unwrap_or_else(|e| e.into_inner()).encrypt(json)?;
+    Ok(serde_json::to_string(&frame)?)
+}
+
+/// 解开客户端发来的 Encrypted 帧，加密会话中的明文帧直接拒绝
+fn decrypt_inbound_frame(cipher: &std::sync::Mutex<SessionCipher>, text: &str) -> Result<String> {
+    let frame: EncryptedFrame =
+        serde_json::from_str(text).map_err(|_| anyhow::anyhow!("加密会话只接受 Encrypted 帧"))?;
+    cipher.lock().unwrap_or_else(|e| e.into_inner()).decrypt(&frame)
+}
+
+/// 启动打字状态定时器：超时后执行 on_timeout；同一发送方已有定时器时先取消，重新计时
+async fn arm_typing_timer<F>(timers: &TypingTimers, from: &str, target: &str, on_timeout: F)
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let handle = tokio::spawn(async move {
+        tokio::time::sleep(TYPING_INDICATOR_TIMEOUT).await;
+        on_timeout.await;
+    });
+    if let Some((_, previous)) = timers.write().await.insert(from.to_string(), (target.to_string(), handle)) {
+        previous.abort();
+    }
+}
+
+/// 取消发送方的打字状态定时器，返回尚未触发的定时器对应的接收方
+async fn disarm_typing_timer(timers: &TypingTimers, from: &str) -> Option<String> {
+    let (target, handle) = timers.write().await.remove(from)?;
+    let pending = !handle.is_finished();
+    handle.abort();
+    pending.then_some(target)
+}
+
+/// 打字状态结束的通知，超时或发送方断开时发给接收方
+fn typing_cleared(from: &str, target: &str) -> AppMessage {
+    AppMessage::Typing {
+        from: from.to_string(),
+        to: Some(target.to_string()),
+        is_typing: false,
+        timestamp: Utc::now(),
+    }
+}
+
+fn connection_limit_reached(current: usize, max_connections: usize) -> bool {
+    max_connections > 0 && current >= max_connections
+}
+
+/// 把同一条消息发给多个接收者：只分配一次，各通道共享引用，返回成功投递的数量。
+/// 队列已满的接收者跳过本条广播
+fn fan_out<'a>(
+    senders: impl IntoIterator<Item = &'a mpsc::Sender<SharedMessage>>,
+    message: AppMessage,
+) -> usize {
+    let message = Arc::new(message);
+    senders
+        .into_iter()
+        .filter(|sender| sender.try_send(Arc::clone(&message)).is_ok())
+        .count()
+}
+
+// 留言的联系方式和内容不能为空，也不能超过长度上限；合法时返回 None
+fn leave_message_error(contact: &str, content: &str) -> Option<String> {
+    let (contact, content) = (contact.trim(), content.trim());
+    if contact.is_empty() || content.is_empty() {
+        return Some("请填写联系方式和问题".to_string());
+    }
+    if contact.chars().count() > MAX_TICKET_CONTACT_CHARS {
+        return Some(format!("联系方式不能超过{}个字符", MAX_TICKET_CONTACT_CHARS));
+    }
+    if content.chars().count() > MAX_TICKET_CONTENT_CHARS {
+        return Some(format!("留言内容不能超过{}个字符", MAX_TICKET_CONTENT_CHARS));
+    }
+    None
+}
+
+// 检查周期内有足够样本且未确认率超过阈值时告警；阈值为0不告警
+fn unacked_rate_alert(acked: u64, unacked: u64, alert_rate: f64) -> bool {
+    alert_rate > 0.0
+        && acked + unacked >= ACK_ALERT_MIN_SAMPLES
+        && crate::monitoring::metrics::unacked_rate(acked, unacked) > alert_rate
+}
+
+// 只有状态为 Online 的客服接待新客户，暂离/忙碌的客服继续服务已有会话
+fn accepts_new_customers(connection: &UserConnection) -> bool {
+    connection.user_type == UserType::Kefu && connection.status == OnlineStatus::Online
+}
+
+// 按错误类型记录消息处理失败：临时故障记警告，其余记错误
+fn log_message_error(user_id: &str, error: &anyhow::Error) {
+    match error.downcast_ref::<WebSocketError>() {
+        Some(e) if e.is_transient() => tracing::warn!("⚠️ 处理消息暂时失败从 {}: {}", user_id, e),
+        _ => tracing::error!("❌ 处理消息失败从 {}: error={:?}", user_id, error),
+    }
+}
+
+// 客户自 waiting_since 起排队，到 now 时是否已超过排队时限（秒级时间戳）；时限为0表示不超时
+fn queue_wait_timed_out(waiting_since: i64, now: i64, timeout: std::time::Duration) -> bool {
+    !timeout.is_zero() && now.saturating_sub(waiting_since) >= timeout.as_secs() as i64
+}
+
+// 语音识别任务，message_id 为语音所在聊天消息的ID
+fn voice_transcription_task(
+    user_id: &str,
+    message_id: String,
+    audio_file_path: &std::path::Path,
+    access_url: &str,
+    voice_id: &str,
+) -> AITask {
+    AITask::new(
+        AITaskType::SpeechRecognition,
+        user_id.to_string(),
+        message_id,
+        json!({
+            "audio_file_path": audio_file_path.to_string_lossy(),
+            "access_url": access_url,
+            "voice_id": voice_id,
+        }),
+        5,
+    )
+}
+
+// 语音时长超过一个分段时按段识别；客户端未上报时长时整段识别
+fn use_streaming_transcription(duration: Option<u32>, chunk_seconds: u64) -> bool {
+    duration.is_some_and(|duration| duration as u64 > chunk_seconds)
+}
+
+// 分段转写的中间结果带上语音ID推送，内容为目前为止的转写
+fn interim_transcription_message(voice_id: &str, result: &InterimResult) -> AppMessage {
+    AppMessage::VoiceTranscriptionProgress {
+        voice_id: voice_id.to_string(),
+        sequence: result.sequence,
+        total: result.total,
+        partial_text: result.output["partial_text"].as_str().unwrap_or_default().to_string(),
+        timestamp: result.created_at,
+    }
+}
+
+// 等待语音识别结果并回填到语音元数据，返回转写文本；失败或结果为空时返回 None
+async fn backfill_voice_transcription(
+    ai_manager: &AIManager,
+    voice_manager: &VoiceMessageManager,
+    task_id: &str,
+    voice_id: &str,
+) -> Option<String> {
+    let text = match ai_manager.wait_for_result(task_id, VOICE_TRANSCRIPTION_TIMEOUT).await {
+        Ok(Some(result)) => result.result["text"].as_str().unwrap_or_default().to_string(),
+        Ok(None) => {
+            tracing::warn!("⚠️ 语音转写失败: task_id={}", task_id);
+            return None;
         }
-    };
-    
-    let mut pubsub = match client.get_async_connection().await {
-        Ok(conn) => conn.into_pubsub(),
         Err(e) => {
-            tracing::error!("❌ Redis连接失败: {}", e);
-            return;
+            tracing::warn!("⚠️ 等待语音转写结果失败: {:?}", e);
+            return None;
         }
     };
-    
-    // 订阅所有指定频道
-    for channel in &channels {
-        if let Err(e) = pubsub.subscribe(channel).await {
-            tracing::error!("❌ 订阅Redis频道失败 {}: {}", channel, e);
-            return;
+    if text.is_empty() {
+        return None;
+    }
+    if let Err(e) = voice_manager.set_transcription(voice_id, &text).await {
+        tracing::warn!("⚠️ 回填语音转写失败: voice_id={}, error: {:?}", voice_id, e);
+    }
+    Some(text)
+}
+
+// 语音元数据转为推送给客户端的语音消息
+fn voice_app_message(voice: &VoiceMessage) -> AppMessage {
+    AppMessage::Voice {
+        id: voice.message_id.clone(),
+        from: voice.from.clone(),
+        to: voice.to.clone(),
+        voice_id: voice.id.clone(),
+        file_id: voice.file_id.clone(),
+        original_filename: voice.original_filename.clone(),
+        file_size: voice.file_size,
+        duration: voice.duration,
+        format: voice.format.clone(),
+        access_url: voice.access_url.clone(),
+        transcription: voice.transcription.clone(),
+        timestamp: voice.upload_time,
+    }
+}
+
+// 系统广播以系统消息下发，时间戳取发布时间，补发时客户端能按原时间展示
+fn system_broadcast_message(broadcast: &SystemBroadcast) -> AppMessage {
+    AppMessage::System {
+        content: format!("系统广播: {}", broadcast.content),
+        timestamp: broadcast.created_at,
+    }
+}
+
+// 需要跟踪投递状态的消息：带ID的聊天/语音消息，且不是回显给发送者自己的副本。返回 (消息ID, 发送者)
+fn tracked_delivery(message: &AppMessage, recipient: &str) -> Option<(String, String)> {
+    match message {
+        AppMessage::Chat { id: Some(id), from, .. } | AppMessage::Voice { id: Some(id), from, .. }
+            if from != recipient =>
+        {
+            Some((id.clone(), from.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// 用户私有消息、通知和会话事件频道，频道名中间段为用户ID
+const REDIS_EVENT_PATTERNS: &[&str] = &["user:*:messages", "user:*:notifications", "session:*:events"];
+
+/// 系统广播频道，转发给本机所有在线用户
+const REDIS_BROADCAST_CHANNEL: &str = "system:broadcasts";
+
+/// 订阅断开后首次重连的退避间隔，之后每次翻倍
+const REDIS_RESUBSCRIBE_INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+const REDIS_RESUBSCRIBE_MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 第 attempt 次重连前的等待时间：指数退避，并在后一半区间内随机抖动，
+/// 避免Redis短暂中断后所有实例同时重连
+fn redis_resubscribe_delay(attempt: u32) -> std::time::Duration {
+    let backoff = REDIS_RESUBSCRIBE_INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(REDIS_RESUBSCRIBE_MAX_BACKOFF);
+    let half = backoff / 2;
+    half + half.mul_f64(rand::random::<f64>())
+}
+
+/// Redis事件频道对应的用户ID，不是用户事件频道时返回 None
+fn redis_event_user(channel: &str) -> Option<&str> {
+    let (prefix, rest) = channel.split_once(':')?;
+    let (user_id, suffix) = rest.rsplit_once(':')?;
+    match (prefix, suffix) {
+        ("user", "messages") | ("user", "notifications") | ("session", "events") if !user_id.is_empty() => {
+            Some(user_id)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leave_message_fields_are_capped() {
+        assert_eq!(leave_message_error("13800000000", "订单一直没发货"), None);
+        assert!(leave_message_error("  ", "订单一直没发货").is_some());
+        assert!(leave_message_error("13800000000", "").is_some());
+        assert!(leave_message_error(&"1".repeat(MAX_TICKET_CONTACT_CHARS + 1), "订单一直没发货").is_some());
+        assert_eq!(leave_message_error("13800000000", &"货".repeat(MAX_TICKET_CONTENT_CHARS)), None);
+        assert!(leave_message_error("13800000000", &"货".repeat(MAX_TICKET_CONTENT_CHARS + 1)).is_some());
+    }
+
+    #[test]
+    fn test_fan_out_shares_one_copy_of_large_message() {
+        const RECEIVERS: usize = 200;
+        let content = "x".repeat(1024 * 1024);
+
+        let (senders, mut receivers): (Vec<_>, Vec<_>) =
+            (0..RECEIVERS).map(|_| mpsc::channel::<SharedMessage>(1)).unzip();
+        let delivered = fan_out(&senders, AppMessage::System { content, timestamp: Utc::now() });
+        assert_eq!(delivered, RECEIVERS);
+
+        let received: Vec<SharedMessage> = receivers.iter_mut().map(|rx| rx.try_recv().unwrap()).collect();
+        // 逐个 clone 时需要 RECEIVERS 份 1MB 内容，共享后所有接收者指向同一块内存
+        assert_eq!(Arc::strong_count(&received[0]), RECEIVERS);
+        let content_ptr = |message: &AppMessage| match message {
+            AppMessage::System { content, .. } => content.as_ptr(),
+            _ => unreachable!(),
+        };
+        let first = content_ptr(&received[0]);
+        assert!(received.iter().all(|message| Arc::ptr_eq(message, &received[0])));
+        assert!(received.iter().all(|message| content_ptr(message) == first));
+    }
+
+    /// 当前进程的常驻内存（KB），读取 /proc/self/status 的 VmRSS
+    fn resident_kb() -> u64 {
+        std::fs::read_to_string("/proc/self/status")
+            .expect("需要 /proc 文件系统")
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .expect("读取 VmRSS 失败")
+    }
+
+    #[test]
+    #[ignore = "内存测量，单独运行: cargo test fan_out_memory -- --ignored --nocapture"]
+    fn test_fan_out_memory_clone_vs_arc() {
+        const RECEIVERS: usize = 100;
+        let large_message = || AppMessage::System { content: "x".repeat(1024 * 1024), timestamp: Utc::now() };
+
+        // 共享引用：所有接收队列指向同一条消息
+        let (senders, mut receivers): (Vec<_>, Vec<_>) =
+            (0..RECEIVERS).map(|_| mpsc::channel::<SharedMessage>(1)).unzip();
+        let before = resident_kb();
+        assert_eq!(fan_out(&senders, large_message()), RECEIVERS);
+        let shared_kb = resident_kb().saturating_sub(before);
+        let received: Vec<SharedMessage> = receivers.iter_mut().map(|rx| rx.try_recv().unwrap()).collect();
+        drop(received);
+
+        // 逐个 clone：每个接收队列各持有一份
+        let (senders, mut receivers): (Vec<_>, Vec<_>) =
+            (0..RECEIVERS).map(|_| mpsc::channel::<AppMessage>(1)).unzip();
+        let before = resident_kb();
+        let message = large_message();
+        for sender in &senders {
+            sender.try_send(message.clone()).unwrap();
+        }
+        let cloned_kb = resident_kb().saturating_sub(before);
+        let received: Vec<AppMessage> = receivers.iter_mut().map(|rx| rx.try_recv().unwrap()).collect();
+        assert_eq!(received.len(), RECEIVERS);
+
+        println!("1MB消息广播给{}个接收者: clone 增加 {} KB, Arc 增加 {} KB", RECEIVERS, cloned_kb, shared_kb);
+        assert!(cloned_kb >= (RECEIVERS as u64 - 1) * 1024);
+        assert!(shared_kb * 10 < cloned_kb);
+    }
+
+    #[test]
+    fn test_redis_event_channel_routing() {
+        assert_eq!(redis_event_user("user:kehu_1:messages"), Some("kehu_1"));
+        assert_eq!(redis_event_user("user:kefu:a:notifications"), Some("kefu:a"));
+        assert_eq!(redis_event_user("session:kehu_1:events"), Some("kehu_1"));
+        assert_eq!(redis_event_user(REDIS_BROADCAST_CHANNEL), None);
+        assert_eq!(redis_event_user("user::messages"), None);
+        assert_eq!(redis_event_user("order:1:events"), None);
+    }
+
+    #[test]
+    fn test_redis_resubscribe_backoff_with_jitter() {
+        let initial = REDIS_RESUBSCRIBE_INITIAL_BACKOFF;
+        for _ in 0..100 {
+            let first = redis_resubscribe_delay(1);
+            assert!(first >= initial / 2 && first <= initial);
+            let third = redis_resubscribe_delay(3);
+            assert!(third >= initial * 2 && third <= initial * 4);
+            let capped = redis_resubscribe_delay(30);
+            assert!(capped >= REDIS_RESUBSCRIBE_MAX_BACKOFF / 2 && capped <= REDIS_RESUBSCRIBE_MAX_BACKOFF);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outbound_frames_compressed_inline_and_off_thread() {
+        let compressor = Arc::new(std::sync::Mutex::new(AdaptiveCompressor::new(CompressionConfig::default())));
+        let decompressor = MessageCompressor::new(CompressionConfig::default());
+
+        // 阈值以上的小帧在当前任务中压缩，大帧交给阻塞线程池，结果一致可解压
+        for size in [4 * 1024, BLOCKING_COMPRESSION_SIZE * 2] {
+            let json = format!(r#"{{"type":"History","messages":"{}"}}"#, "a".repeat(size));
+            let frame = compress_outbound_frame(&compressor, &json).await.unwrap().expect("超过阈值应压缩");
+            assert_eq!(decompressor.decompress_binary(&frame).unwrap(), json);
         }
-        tracing::info!("✅ 用户{}已订阅Redis频道: {}", user_id, channel);
+        assert!(compress_outbound_frame(&compressor, r#"{"type":"Heartbeat"}"#).await.unwrap().is_none());
+        assert_eq!(compressor.lock().unwrap().get_stats().compressed_messages, 2);
+    }
+
+    #[test]
+    fn test_encrypted_session_frames_survive_key_rotation() {
+        use crate::auth::session_crypto::parse_public_key;
+
+        let policy = KeyRotationPolicy { max_messages: 1, ..KeyRotationPolicy::default() };
+        // 客户端用欢迎消息中的服务端公钥完成协商，建立同一密钥序列
+        let client_exchange = KeyExchange::new();
+        let server_exchange = KeyExchange::new();
+        let client_key = parse_public_key(&client_exchange.public_key()).unwrap();
+        let server_key = parse_public_key(&server_exchange.public_key()).unwrap();
+        let server = std::sync::Mutex::new(server_exchange.complete(client_key, policy.clone()).unwrap());
+        let mut client = client_exchange.complete(server_key, policy).unwrap();
+
+        let before_rotation = encrypt_outbound_frame(&server, r#"{"type":"System","content":"a"}"#).unwrap();
+        let after_rotation = encrypt_outbound_frame(&server, r#"{"type":"System","content":"b"}"#).unwrap();
+        let before: EncryptedFrame = serde_json::from_str(&before_rotation).unwrap();
+        let after: EncryptedFrame = serde_json::from_str(&after_rotation).unwrap();
+        assert_eq!((before.key_id, after.key_id), (0, 1));
+        // 轮换后才到达的旧帧仍可解密
+        assert!(client.decrypt(&after).unwrap().contains(r#""b""#));
+        assert!(client.decrypt(&before).unwrap().contains(r#""a""#));
+
+        let inbound = serde_json::to_string(&client.encrypt(r#"{"type":"Heartbeat"}"#).unwrap()).unwrap();
+        assert_eq!(decrypt_inbound_frame(&server, &inbound).unwrap(), r#"{"type":"Heartbeat"}"#);
+        assert!(decrypt_inbound_frame(&server, r#"{"type":"Heartbeat"}"#).is_err());
     }
-    
-    // 监听Redis消息并转发给WebSocket客户端
-    use futures_util::StreamExt;
-    let mut stream = pubsub.on_message();
-    
-    while let Some(msg) = stream.next().await {
-        let channel: String = msg.get_channel_name().to_string();
-        let payload: String = match msg.get_payload() {
-            Ok(p) => p,
-            Err(_) => continue,
+
+    #[tokio::test(start_paused = true)]
+    async fn test_typing_timer_rearms_on_refresh_and_cancels_on_disconnect() {
+        let timers: TypingTimers = Arc::new(RwLock::new(HashMap::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel::<AppMessage>();
+        let arm = |tx: mpsc::UnboundedSender<AppMessage>| {
+            let timers = timers.clone();
+            async move {
+                arm_typing_timer(&timers, "kehu_1", "kefu_1", async move {
+                    let _ = tx.send(typing_cleared("kehu_1", "kefu_1"));
+                })
+                .await;
+            }
         };
-        
-        // 解析Redis消息
-        if let Ok(redis_event) = serde_json::from_str::<serde_json::Value>(&payload) {
-            // 构造WebSocket消息
-            let ws_message = json!({
-                "type": "redis_event",
-                "channel": channel,
-                "event": redis_event,
-                "timestamp": chrono::Utc::now().timestamp()
-            });
-            
-                         // 发送给特定用户
-             let app_message = AppMessage::System {
-                 content: ws_message.to_string(),
-                 timestamp: chrono::Utc::now(),
-             };
-             if (ws_manager.send_to_user(&user_id, app_message).await).is_err() {
-                tracing::warn!("⚠️ 用户{}已断开，停止Redis事件转发", user_id);
-                break;
+
+        // 超时前再次收到 is_typing:true，从第二次开始重新计时
+        arm(tx.clone()).await;
+        tokio::time::sleep(TYPING_INDICATOR_TIMEOUT - std::time::Duration::from_secs(1)).await;
+        arm(tx.clone()).await;
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        assert!(rx.try_recv().is_err());
+        tokio::time::sleep(TYPING_INDICATOR_TIMEOUT).await;
+        match rx.try_recv() {
+            Ok(AppMessage::Typing { from, to, is_typing, .. }) => {
+                assert_eq!((from.as_str(), to.as_deref(), is_typing), ("kehu_1", Some("kefu_1"), false));
             }
-            
-            tracing::debug!("📨 Redis事件已转发到用户{}: {}", user_id, channel);
+            other => panic!("超时后应发送 is_typing:false，实际 {:?}", other),
+        }
+        assert!(rx.try_recv().is_err());
+
+        // 已触发的定时器不再重复通知；断开时取消未触发的定时器，由断开流程发送 is_typing:false
+        assert_eq!(disarm_typing_timer(&timers, "kehu_1").await, None);
+        arm(tx.clone()).await;
+        assert_eq!(disarm_typing_timer(&timers, "kehu_1").await.as_deref(), Some("kefu_1"));
+        tokio::time::sleep(TYPING_INDICATOR_TIMEOUT * 2).await;
+        assert!(rx.try_recv().is_err());
+        assert!(timers.read().await.is_empty());
+    }
+
+    #[test]
+    fn test_fan_out_skips_closed_channels() {
+        let (open_tx, mut open_rx) = mpsc::channel::<SharedMessage>(1);
+        let (closed_tx, closed_rx) = mpsc::channel::<SharedMessage>(1);
+        drop(closed_rx);
+
+        let delivered = fan_out([&open_tx, &closed_tx], AppMessage::System {
+            content: "维护通知".to_string(),
+            timestamp: Utc::now(),
+        });
+
+        assert_eq!(delivered, 1);
+        assert!(open_rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_fan_out_skips_full_queues() {
+        let (slow_tx, mut slow_rx) = mpsc::channel::<SharedMessage>(1);
+        let system = |content: &str| AppMessage::System { content: content.to_string(), timestamp: Utc::now() };
+
+        assert_eq!(fan_out([&slow_tx], system("第一条")), 1);
+        // 消费者未读取，队列已满时后续广播被丢弃而不是继续堆积
+        assert_eq!(fan_out([&slow_tx], system("第二条")), 0);
+
+        assert!(slow_rx.try_recv().is_ok());
+        assert!(slow_rx.try_recv().is_err());
+        assert_eq!(fan_out([&slow_tx], system("第三条")), 1);
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        assert!(!connection_limit_reached(9, 10));
+        assert!(connection_limit_reached(10, 10));
+        assert!(connection_limit_reached(11, 10));
+        // 0 表示不限制
+        assert!(!connection_limit_reached(1_000_000, 0));
+    }
+
+    #[test]
+    fn test_busy_kefu_skipped_during_assignment() {
+        let connection = |user_id: &str, user_type: UserType, status: OnlineStatus| UserConnection {
+            user_id: user_id.to_string(),
+            user_name: user_id.to_string(),
+            user_type,
+            zhanghao: None,
+            connected_at: Utc::now(),
+            last_heartbeat: Utc::now(),
+            status,
+            suspicious_reason: None,
+            client_ip: None,
+            user_agent: None,
+        };
+        let connections = [
+            connection("kefu_busy", UserType::Kefu, OnlineStatus::Busy),
+            connection("kefu_away", UserType::Kefu, OnlineStatus::Away),
+            connection("kefu_online", UserType::Kefu, OnlineStatus::Online),
+            connection("kehu_1", UserType::Kehu, OnlineStatus::Online),
+        ];
+
+        let assignable: Vec<&str> = connections
+            .iter()
+            .filter(|connection| accepts_new_customers(connection))
+            .map(|connection| connection.user_id.as_str())
+            .collect();
+        assert_eq!(assignable, ["kefu_online"]);
+    }
+
+    #[test]
+    fn test_unacked_rate_alert_threshold() {
+        assert!(unacked_rate_alert(10, 15, 0.5));
+        assert!(!unacked_rate_alert(15, 10, 0.5));
+        // 样本太少或未开启时不告警
+        assert!(!unacked_rate_alert(0, 5, 0.5));
+        assert!(!unacked_rate_alert(0, 100, 0.0));
+    }
+
+    #[test]
+    fn test_waiting_customer_times_out_to_leave_message() {
+        let timeout = std::time::Duration::from_secs(300);
+        let since = 1_000;
+        assert!(!queue_wait_timed_out(since, since + 299, timeout));
+        assert!(queue_wait_timed_out(since, since + 300, timeout));
+        // 时限为0时一直排队
+        assert!(!queue_wait_timed_out(since, since + 86_400, std::time::Duration::ZERO));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_chat_reports_missing_or_offline_recipient_after_echo() {
+        let redis = RedisManager::new("redis://127.0.0.1:6379").expect("Redis不可用");
+        let dir = std::env::temp_dir().join(format!("chat_recipient_{}", Uuid::new_v4()));
+        let manager = WebSocketManager::new(redis, LocalStorage::new(dir.to_str().unwrap()).unwrap());
+
+        let customer = format!("kehu_{}", Uuid::new_v4());
+        let (tx, mut rx) = mpsc::channel::<SharedMessage>(8);
+        manager.senders.write().await.insert(customer.clone(), tx);
+        manager.connections.write().await.insert(
+            customer.clone(),
+            UserConnection {
+                user_id: customer.clone(),
+                user_name: customer.clone(),
+                user_type: UserType::Kehu,
+                zhanghao: None,
+                connected_at: Utc::now(),
+                last_heartbeat: Utc::now(),
+                status: OnlineStatus::Online,
+                suspicious_reason: None,
+                client_ip: None,
+                user_agent: None,
+            },
+        );
+        async fn chat(manager: &WebSocketManager, customer: &str, to: Option<String>) -> Result<(), WebSocketError> {
+            manager
+                .handle_chat_message(
+                    Some(format!("msg_{}", Uuid::new_v4())),
+                    customer.to_string(),
+                    to,
+                    "我的订单还没发货".to_string(),
+                    None,
+                    None,
+                    Utc::now(),
+                    None,
+                    None,
+                    customer,
+                )
+                .await
+        }
+
+        // 没有接收方也没有会话伙伴：消息照常回显给发送方，随后返回 NoRecipient
+        let result = chat(&manager, &customer, None).await;
+        assert!(matches!(result, Err(WebSocketError::NoRecipient(ref user)) if *user == customer));
+        assert!(matches!(rx.try_recv().unwrap().as_ref(), AppMessage::Chat { .. }));
+
+        // 接收方离线：消息转入离线队列并回显，随后返回 RecipientOffline
+        let kefu = format!("kefu_{}", Uuid::new_v4());
+        let result = chat(&manager, &customer, Some(kefu.clone())).await;
+        assert!(matches!(result, Err(WebSocketError::RecipientOffline(ref user)) if *user == kefu));
+        assert!(matches!(rx.try_recv().unwrap().as_ref(), AppMessage::Chat { .. }));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_duplicate_connection_replaced_or_rejected() {
+        let redis = RedisManager::new("redis://127.0.0.1:6379").expect("Redis不可用");
+        let dir = std::env::temp_dir().join(format!("duplicate_connection_{}", Uuid::new_v4()));
+        let manager = WebSocketManager::new(redis, LocalStorage::new(dir.to_str().unwrap()).unwrap());
+        let kefu = format!("kefu_{}", Uuid::new_v4());
+        let connection = UserConnection {
+            user_id: kefu.clone(),
+            user_name: kefu.clone(),
+            user_type: UserType::Kefu,
+            zhanghao: None,
+            connected_at: Utc::now(),
+            last_heartbeat: Utc::now(),
+            status: OnlineStatus::Online,
+            suspicious_reason: None,
+            client_ip: None,
+            user_agent: None,
+        };
+        let is_registered = |tx: &mpsc::Sender<SharedMessage>| {
+            let manager = manager.clone();
+            let kefu = kefu.clone();
+            let tx = tx.clone();
+            async move {
+                manager.connections.read().await.contains_key(&kefu)
+                    && manager.senders.read().await.get(&kefu).is_some_and(|current| current.same_channel(&tx))
+            }
+        };
+
+        // 默认替换：新连接接管，旧连接收到 Replaced 关闭信号
+        let (old_tx, _old_rx) = mpsc::channel::<SharedMessage>(1);
+        let old_signal = Arc::new(CloseSignal::default());
+        assert!(matches!(manager.register_connection(&connection, &old_tx, &old_signal).await, Ok(None)));
+        let (new_tx, _new_rx) = mpsc::channel::<SharedMessage>(1);
+        let new_signal = Arc::new(CloseSignal::default());
+        assert!(matches!(manager.register_connection(&connection, &new_tx, &new_signal).await, Ok(Some(_))));
+        let reason = tokio::time::timeout(std::time::Duration::from_secs(1), old_signal.closed()).await;
+        assert_eq!(reason.ok(), Some(CloseReason::Replaced));
+
+        // 旧连接随后结束时不能清掉新连接
+        manager.cleanup_connection_if_current(&kefu, &old_signal).await;
+        assert!(is_registered(&new_tx).await);
+        manager.cleanup_connection_if_current(&kefu, &new_signal).await;
+        assert!(!manager.connections.read().await.contains_key(&kefu));
+        assert!(manager.close_signals.read().await.get(&kefu).is_none());
+
+        // 拒绝策略：保留已有连接，新连接以 DuplicateConnection 关闭
+        let manager = manager.with_duplicate_connection_policy(DuplicateConnectionPolicy::Reject);
+        assert!(manager.register_connection(&connection, &old_tx, &old_signal).await.is_ok());
+        let rejected = manager.register_connection(&connection, &new_tx, &new_signal).await;
+        assert!(matches!(rejected, Err(CloseReason::DuplicateConnection)));
+        assert!(manager.senders.read().await.get(&kefu).is_some_and(|current| current.same_channel(&old_tx)));
+        manager.cleanup_connection(&kefu).await;
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn test_bandwidth_counts_frames_of_live_connection() {
+        use warp::Filter;
+
+        let redis = RedisManager::new("redis://127.0.0.1:6379").expect("Redis不可用");
+        let dir = std::env::temp_dir().join(format!("bandwidth_{}", Uuid::new_v4()));
+        let manager = WebSocketManager::new(redis, LocalStorage::new(dir.to_str().unwrap()).unwrap());
+        let kefu = format!("kefu_{}", Uuid::new_v4());
+        let route = {
+            let manager = manager.clone();
+            let kefu = kefu.clone();
+            warp::path("ws").and(warp::ws()).map(move |ws: warp::ws::Ws| {
+                let manager = manager.clone();
+                let kefu = kefu.clone();
+                ws.on_upgrade(move |socket| async move {
+                    let _ = manager
+                        .handle_connection(socket, kefu.clone(), kefu, UserType::Kefu, None, ConnectionOptions::default())
+                        .await;
+                })
+            })
+        };
+        let mut client = warp::test::ws().path("/ws").handshake(route).await.expect("握手失败");
+
+        // 客户端上行一帧，服务端下行一条系统消息，两个方向都应按真实帧大小计入该连接
+        let heartbeat = serde_json::json!({ "type": "Heartbeat", "user_id": kefu, "timestamp": Utc::now() }).to_string();
+        client.send_text(heartbeat.clone()).await;
+        let system = Arc::new(AppMessage::System { content: "带宽统计".to_string(), timestamp: Utc::now() });
+        if let Some(sender) = manager.senders.read().await.get(&kefu) {
+            let _ = sender.try_send(system);
+        }
+        let counted = async {
+            loop {
+                let usage = manager.bandwidth.connection(&kefu).unwrap_or_default();
+                if usage.bytes_in >= heartbeat.len() as u64 && usage.bytes_out > 0 {
+                    break usage;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        };
+        let usage = tokio::time::timeout(std::time::Duration::from_secs(2), counted)
+            .await
+            .expect("未统计到连接的收发字节");
+        assert!(manager.bandwidth.totals().total() >= usage.total());
+        assert!(manager.bandwidth.take_heavy_connections(0).iter().any(|(user, _)| *user == kefu));
+
+        // 断开后移除该连接的统计，全局累计保留
+        drop(client);
+        let removed = async {
+            while manager.bandwidth.connection(&kefu).is_some() {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(2), removed)
+            .await
+            .expect("断开后应移除连接统计");
+        assert!(manager.bandwidth.totals().bytes_in >= heartbeat.len() as u64);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_on_demand_transcription_backfills_voice_message() {
+        use crate::ai::dedup::{task_fingerprint, MemoryResultStore, ResultStore};
+        use crate::voice_message::VoiceUploadRequest;
+
+        let dir = std::env::temp_dir().join(format!("voice_transcribe_{}", Uuid::new_v4()));
+        let voice_manager = VoiceMessageManager::new(dir.clone()).unwrap();
+        let voice = voice_manager
+            .upload_voice_message(VoiceUploadRequest {
+                from: "kehu_1".to_string(),
+                to: Some("kefu_1".to_string()),
+                audio_data: vec![0u8; 64],
+                filename: "voice.mp3".to_string(),
+                format: "mp3".to_string(),
+                duration: Some(3),
+                sample_rate: None,
+                bit_rate: None,
+            })
+            .await
+            .unwrap()
+            .voice_message;
+        assert!(voice.transcription.is_none());
+
+        // 预置识别结果，任务命中指纹缓存，不调用外部语音服务
+        let audio_file_path = voice_manager.get_voice_file_path(&voice.id).await.unwrap().unwrap();
+        let task = voice_transcription_task(&voice.from, voice.id.clone(), &audio_file_path, &voice.access_url, &voice.id);
+        let store = Arc::new(MemoryResultStore::new());
+        let fingerprint = task_fingerprint(&task, &crate::ai::config::AIConfig::default());
+        store.put(&fingerprint, &json!({ "text": "我的订单还没发货" }), 60).await.unwrap();
+        let ai_manager = AIManager::new().with_result_store(store);
+        ai_manager.start_processing().await.unwrap();
+
+        let task_id = ai_manager.submit_task(task).await.unwrap();
+        let text = backfill_voice_transcription(&ai_manager, &voice_manager, &task_id, &voice.id).await;
+        assert_eq!(text.as_deref(), Some("我的订单还没发货"));
+
+        let updated = voice_manager.get_voice_message(&voice.id).await.unwrap().unwrap();
+        assert_eq!(updated.transcription.as_deref(), Some("我的订单还没发货"));
+        match voice_app_message(&updated) {
+            AppMessage::Voice { voice_id, transcription, .. } => {
+                assert_eq!(voice_id, voice.id);
+                assert_eq!(transcription.as_deref(), Some("我的订单还没发货"));
+            }
+            _ => unreachable!(),
         }
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-    
-    tracing::info!("🔚 用户{}的Redis频道订阅任务结束", user_id);
 }